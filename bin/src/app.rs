@@ -1,11 +1,12 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{path::Path, sync::Arc, time::Instant};
 
-use log::{debug, error, info};
+use anyhow::Result;
+use log::{debug, error, info, warn};
 use vulkano::{
     Version,
     command_buffer::allocator::StandardCommandBufferAllocator,
     descriptor_set::allocator::StandardDescriptorSetAllocator,
-    device::{DeviceExtensions, DeviceFeatures},
+    device::{DeviceExtensions, physical::PhysicalDevice},
     image::ImageUsage,
     instance::{
         InstanceCreateInfo, InstanceExtensions,
@@ -23,15 +24,48 @@ use vulkano_util::{
 use winit::{
     application::ApplicationHandler,
     dpi::LogicalSize,
-    event::{ElementState, KeyEvent, WindowEvent},
-    keyboard::{Key, NamedKey},
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::ControlFlow,
+    keyboard::{Key, ModifiersState, NamedKey},
     raw_window_handle::HasDisplayHandle,
+    window::{Fullscreen, Window},
 };
 
-use raytracer::{Scene, Vk};
-use scene_file::SceneFile;
+use raytracer::{
+    CAMERA_BOOKMARK_COUNT, CancellationToken, Capabilities, OverlayElement, RenderHooks, Scene, Vk,
+    create_staging_allocator,
+};
+use scene_file::{EditedSceneFile, SceneFile, TextureFilter, load_recovery};
+
+use crate::{
+    config::{Config, FullscreenMode},
+    fallback_scene::create_fallback_scene,
+    settings_panel::SettingsPanel,
+    surface_format::{choose_surface_format, get_surface_formats},
+    watcher::{FileWatcher, POLL_INTERVAL},
+};
+
+/// Name of the Khronos validation layer.
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Distance the camera moves per key press relative to the distance to its look at point.
+const CAMERA_MOVE_STEP: f32 = 0.05;
+
+/// Angle in degrees the camera rolls per key press.
+const CAMERA_ROLL_STEP: f32 = 2.0;
 
-const INITIAL_WINDOW_SIZE: [f32; 2] = [1024.0, 576.0];
+/// Angle in degrees the camera orbits per pixel the cursor is dragged.
+const CAMERA_ORBIT_STEP: f32 = 0.25;
+
+/// Distance the camera pans per pixel the cursor is dragged relative to the distance to its look
+/// at point.
+const CAMERA_PAN_STEP: f32 = 0.002;
+
+/// Factor the distance to the look at point is scaled by per line scrolled with the mouse wheel.
+const CAMERA_DOLLY_STEP: f32 = 0.9;
+
+/// Pixels scrolled with a touchpad that count as a line scrolled with a mouse wheel.
+const PIXELS_PER_SCROLL_LINE: f64 = 50.0;
 
 /// Winit application.
 pub struct App {
@@ -47,26 +81,81 @@ pub struct App {
     /// The scene to render.
     scene: Option<Scene>,
 
-    /// The current scene file being rendered.
+    /// The current scene file being rendered. This is empty for built-in scenes and demos.
     current_file_path: String,
 
+    /// The scene file being rendered with any edits made to it, e.g. changed render settings.
+    edited_scene_file: Option<EditedSceneFile>,
+
     /// This will be used to track egui File > Open will result in a new scene being loaded.
     new_file_path: Option<String>,
+
+    /// Watches the current scene file so it is reloaded when it changes on disk. This is `None`
+    /// for built-in scenes and demos.
+    scene_file_watcher: Option<FileWatcher>,
+
+    /// Overlay for inspecting and tweaking the render. Created with the window.
+    settings_panel: Option<SettingsPanel>,
+
+    /// Current state of the keyboard modifiers.
+    modifiers: ModifiersState,
+
+    /// Whether the window is fullscreen.
+    is_fullscreen: bool,
+
+    /// Whether the window has keyboard focus.
+    is_focused: bool,
+
+    /// Whether the window is hidden by other windows or otherwise not visible.
+    is_occluded: bool,
+
+    /// Mesh instance under the cursor when it was last logged.
+    hovered_instance: Option<u32>,
+
+    /// Position of the cursor in physical pixels when it last moved, or `None` if it left the
+    /// window.
+    cursor_position: Option<[f64; 2]>,
+
+    /// Mouse button held down to drag the camera. The left button orbits and the others pan.
+    camera_drag: Option<MouseButton>,
+
+    /// Application settings.
+    config: Config,
 }
 
 impl App {
-    pub fn new(
-        event_loop: &impl HasDisplayHandle,
-        enable_debug_logging: bool,
-        initial_file_path: &str,
-    ) -> Self {
+    pub fn new(event_loop: &impl HasDisplayHandle, config: Config) -> Self {
         // Use extension supporting the winit event loop.
         let required_extensions = Surface::required_extensions(event_loop)
             .expect("Failed to get required extensions to create a surface");
 
+        let enabled_layers = if config.validation {
+            vec![VALIDATION_LAYER.to_string()]
+        } else {
+            vec![]
+        };
+
+        // Pick the device up front so optional extensions and features are only enabled if the
+        // device supports them.
+        let capabilities = Capabilities::query(config.device.as_deref())
+            .expect("Failed to find a supported device");
+        capabilities.warn_missing();
+        if !capabilities.hdr_surface {
+            warn!(
+                "Extended swapchain colour spaces are not supported. Only sRGB can be presented."
+            );
+        }
+
+        let device_extensions = capabilities.get_device_extensions();
+        let device_features = capabilities.get_device_features();
+        let hdr_surface = capabilities.hdr_surface;
+        let device_filter_fn = Arc::new(move |p: &PhysicalDevice| {
+            capabilities.is_device(p) && p.supported_extensions().khr_swapchain
+        });
+
         // Vulkano context
         let context = VulkanoContext::new(VulkanoConfig {
-            debug_create_info: setup_debug_callback(enable_debug_logging),
+            debug_create_info: setup_debug_callback(config.validation),
             instance_create_info: InstanceCreateInfo {
                 #[cfg(target_vendor = "apple")]
                 flags: vulkano::instance::InstanceCreateFlags::ENUMERATE_PORTABILITY,
@@ -74,31 +163,18 @@ impl App {
                 enabled_extensions: InstanceExtensions {
                     ext_debug_utils: true,
                     ext_debug_report: true,
-                    ext_swapchain_colorspace: true,
+                    ext_swapchain_colorspace: hdr_surface,
                     ..required_extensions
                 },
+                enabled_layers,
                 ..Default::default()
             },
             device_extensions: DeviceExtensions {
-                khr_acceleration_structure: true,
-                khr_deferred_host_operations: true,
-                khr_ray_tracing_pipeline: true,
-                khr_ray_tracing_maintenance1: true,
                 khr_swapchain: true,
-                khr_synchronization2: true,
-                ..DeviceExtensions::empty()
-            },
-            device_features: DeviceFeatures {
-                acceleration_structure: true,
-                buffer_device_address: true,
-                descriptor_binding_variable_descriptor_count: true,
-                ray_tracing_pipeline: true,
-                runtime_descriptor_array: true,
-                scalar_block_layout: true,
-                shader_int64: true,
-                synchronization2: true,
-                ..Default::default()
+                ..device_extensions
             },
+            device_features,
+            device_filter_fn,
             print_device_name: true,
             ..Default::default()
         });
@@ -124,6 +200,7 @@ impl App {
             memory_allocator: context.memory_allocator().clone(),
             command_buffer_allocator,
             descriptor_set_allocator,
+            staging_allocator: create_staging_allocator(context.memory_allocator().clone()),
         });
 
         // Create the app with a default asset file loaded.
@@ -132,28 +209,176 @@ impl App {
             windows,
             scene: None,
             vk,
-            current_file_path: initial_file_path.to_string(),
+            current_file_path: config.scene.clone(),
+            edited_scene_file: None,
             new_file_path: None,
+            scene_file_watcher: None,
+            settings_panel: None,
+            modifiers: ModifiersState::default(),
+            is_fullscreen: config.fullscreen,
+            is_focused: true,
+            is_occluded: false,
+            hovered_instance: None,
+            cursor_position: None,
+            camera_drag: None,
+            config,
         }
     }
-}
 
-fn adjust_window_size(mut window_size: [f32; 2], aspect_ratio: f32) -> [f32; 2] {
-    if window_size[0] > window_size[1] {
-        window_size[0] = aspect_ratio * window_size[1];
-    } else {
-        window_size[1] = window_size[0] / aspect_ratio;
+    /// Loads the scene file shown at startup. If the scene wasn't given on the command line and
+    /// the configured one doesn't exist, e.g. on the first run from another directory, a file
+    /// dialog asks for one. The built-in scene is shown if the dialog is cancelled.
+    ///
+    /// Built-in scenes and demos have no file, so the current file path is cleared for them.
+    fn load_startup_scene_file(&mut self) -> Result<SceneFile> {
+        if let Some(demo) = self.config.demo {
+            info!("Showing the {} demo", demo.get_name());
+            self.current_file_path.clear();
+            return self.config.load_demo(demo);
+        }
+
+        if self.config.is_scene_from_cli || Path::new(&self.current_file_path).exists() {
+            return self.config.load_scene_file(&self.current_file_path);
+        }
+
+        warn!("Scene file {} not found", self.current_file_path);
+        let picked = rfd::FileDialog::new()
+            .set_title("Open a scene file")
+            .add_filter("Scene (.json, .scenepkg)", &["json", "scenepkg"])
+            .pick_file();
+
+        match picked {
+            Some(path) => {
+                let path = path.display().to_string();
+                let scene_file = self.config.load_scene_file(&path)?;
+                self.current_file_path = path;
+                Ok(scene_file)
+            }
+            None => {
+                info!("No scene file selected. Showing the built-in scene.");
+                self.current_file_path.clear();
+                create_fallback_scene()
+            }
+        }
+    }
+
+    /// Starts tracking edits of the startup scene. If a recovery copy of it was left behind, e.g.
+    /// by a crash, the user is asked whether to restore the edits it holds.
+    fn start_editing(&mut self, scene_file: SceneFile) {
+        let name = self.get_recovery_name();
+        let mut edited_scene_file =
+            EditedSceneFile::new(scene_file, &name, self.config.get_autosave_policy());
+
+        match load_recovery(&name) {
+            Ok(Some(recovered)) => {
+                let answer = rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Warning)
+                    .set_title("Restore unsaved changes")
+                    .set_description(format!(
+                        "The application didn't exit cleanly while {name} had unsaved changes. \
+                        Restore them?"
+                    ))
+                    .set_buttons(rfd::MessageButtons::YesNo)
+                    .show();
+                let is_restored = matches!(answer, rfd::MessageDialogResult::Yes);
+
+                if is_restored {
+                    info!("Restoring unsaved changes to {name}");
+                    edited_scene_file.edit(|scene_file| *scene_file = recovered);
+                } else if let Err(e) = edited_scene_file.discard_recovery() {
+                    error!("Unable to discard unsaved changes. {e:?}");
+                }
+            }
+            Ok(None) => (),
+            Err(e) => warn!("Ignoring unsaved changes. {e:?}"),
+        }
+
+        self.edited_scene_file = Some(edited_scene_file);
+    }
+
+    /// Starts watching the current scene file for changes, if there is one.
+    fn watch_scene_file(&mut self) {
+        self.scene_file_watcher =
+            (!self.current_file_path.is_empty()).then(|| FileWatcher::new(&self.current_file_path));
+    }
+
+    /// Reloads the scene after its file changed on disk. Edits made in the application are
+    /// discarded. If the file can't be loaded, e.g. while it's being edited and isn't valid, the
+    /// error is logged and the current scene stays.
+    fn reload_scene_file(&mut self) {
+        let path = self.current_file_path.clone();
+        info!("Reloading {path} after it changed");
+
+        let scene_file = match self.config.load_scene_file(&path) {
+            Ok(scene_file) => scene_file,
+            Err(e) => {
+                error!("Unable to reload {path}. {e:?}");
+                return;
+            }
+        };
+
+        let renderer = self.windows.get_primary_renderer().unwrap();
+        let mut scene = match Scene::new(
+            self.vk.clone(),
+            &scene_file,
+            &renderer.window_size(),
+            renderer.window().scale_factor(),
+            renderer.swapchain_format(),
+            CancellationToken::new(),
+            create_render_hooks(),
+        ) {
+            Ok(scene) => scene,
+            Err(e) => {
+                error!("Unable to reload {path}. {e:?}");
+                return;
+            }
+        };
+        scene.set_camera_transition_duration(self.config.get_bookmark_transition_duration());
+        self.scene = Some(scene);
+
+        if let Some(Err(e)) = self
+            .edited_scene_file
+            .as_ref()
+            .map(|edited_scene_file| edited_scene_file.discard_recovery())
+        {
+            error!("Unable to discard unsaved changes. {e:?}");
+        }
+        self.edited_scene_file = Some(EditedSceneFile::new(
+            scene_file,
+            &path,
+            self.config.get_autosave_policy(),
+        ));
+    }
+
+    /// Returns the name that identifies the current scene's recovery copy across runs.
+    fn get_recovery_name(&self) -> String {
+        if !self.current_file_path.is_empty() {
+            self.current_file_path.clone()
+        } else if let Some(demo) = self.config.demo {
+            format!("demo-{}", demo.get_name())
+        } else {
+            "built-in".to_string()
+        }
     }
-    window_size
 }
 
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         // Load scene file.
-        let scene_file = SceneFile::load_json(&self.current_file_path).unwrap();
+        let scene_file = self.load_startup_scene_file().unwrap();
+        self.start_editing(scene_file);
+        let scene_file = self.edited_scene_file.as_ref().unwrap().get_scene_file();
+
+        let mut window_size = self.config.get_window_size(scene_file.render.aspect_ratio);
 
-        let mut window_size =
-            adjust_window_size(INITIAL_WINDOW_SIZE, scene_file.render.aspect_ratio);
+        // Pick the swapchain format since vulkano util would use whichever the driver lists first.
+        let surface_format = match get_surface_formats(event_loop, &self.context) {
+            Ok(formats) => choose_surface_format(&formats, self.config.surface_format),
+            Err(e) => {
+                warn!("Unable to query the surface formats. {e:?}");
+                None
+            }
+        };
 
         // Create a new window and renderer.
         self.windows.create_window(
@@ -168,6 +393,10 @@ impl ApplicationHandler for App {
             |ci| {
                 ci.image_usage = ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST; // ImageUsage::STORAGE;
                 ci.min_image_count = ci.min_image_count.max(2);
+                if let Some((image_format, image_color_space)) = surface_format {
+                    ci.image_format = image_format;
+                    ci.image_color_space = image_color_space;
+                }
             },
         );
 
@@ -179,13 +408,43 @@ impl ApplicationHandler for App {
         let swapchain_format = renderer.swapchain_format();
         info!("Swapchain image format: {swapchain_format:?}");
 
+        for (index, monitor) in renderer.window().available_monitors().enumerate() {
+            info!(
+                "Monitor {index}: {}",
+                monitor.name().unwrap_or_else(|| "unknown".to_string())
+            );
+        }
+
+        // Switching to fullscreen after the window is created allows picking the monitor.
+        if self.is_fullscreen {
+            renderer.window().set_fullscreen(get_fullscreen(
+                renderer.window(),
+                self.config.fullscreen_mode,
+                self.config.monitor,
+            ));
+            renderer.resize();
+        }
+
         // Refetch window size from renderer because window creation will account for fractional scaling.
         window_size = renderer.window_size();
 
         // Create scene.
-        let scene =
-            Scene::new(self.vk.clone(), &scene_file, &window_size, swapchain_format).unwrap();
+        let mut scene = Scene::new(
+            self.vk.clone(),
+            scene_file,
+            &window_size,
+            renderer.window().scale_factor(),
+            swapchain_format,
+            CancellationToken::new(),
+            create_render_hooks(),
+        )
+        .unwrap();
+        scene.set_camera_transition_duration(self.config.get_bookmark_transition_duration());
         self.scene = Some(scene);
+
+        self.settings_panel = Some(SettingsPanel::new(event_loop, renderer));
+
+        self.watch_scene_file();
     }
 
     fn window_event(
@@ -199,11 +458,11 @@ impl ApplicationHandler for App {
 
         // Handle loading a new scene before processing events.
         if let Some(new_scene_path) = &self.new_file_path {
-            match SceneFile::load_json(new_scene_path) {
+            match self.config.load_scene_file(new_scene_path) {
                 Ok(scene_file) => {
                     // Resize the window based on initial dimensions and scene aspect ratio.
                     let mut window_size =
-                        adjust_window_size(INITIAL_WINDOW_SIZE, scene_file.render.aspect_ratio);
+                        self.config.get_window_size(scene_file.render.aspect_ratio);
                     let _ = renderer
                         .window()
                         .request_inner_size(LogicalSize::new(window_size[0], window_size[1]));
@@ -215,12 +474,34 @@ impl ApplicationHandler for App {
                         self.vk.clone(),
                         &scene_file,
                         &window_size,
+                        renderer.window().scale_factor(),
                         renderer.swapchain_format(),
+                        CancellationToken::new(),
+                        create_render_hooks(),
                     ) {
-                        Ok(new_scene) => {
+                        Ok(mut new_scene) => {
+                            new_scene.set_camera_transition_duration(
+                                self.config.get_bookmark_transition_duration(),
+                            );
                             *scene = new_scene;
+
+                            // Edits of the previous scene are discarded with it.
+                            if let Some(Err(e)) = self
+                                .edited_scene_file
+                                .as_ref()
+                                .map(|edited_scene_file| edited_scene_file.discard_recovery())
+                            {
+                                error!("Unable to discard unsaved changes. {e:?}");
+                            }
+                            self.edited_scene_file = Some(EditedSceneFile::new(
+                                scene_file,
+                                new_scene_path,
+                                self.config.get_autosave_policy(),
+                            ));
+
                             self.current_file_path = new_scene_path.clone();
                             self.new_file_path = None;
+                            self.watch_scene_file();
                         }
                         Err(e) => {
                             error!("Unable to load file {}. {:?}", new_scene_path, e);
@@ -235,15 +516,95 @@ impl ApplicationHandler for App {
             }
         }
 
+        // The settings panel gets events first so clicks and drags on its controls don't also
+        // move the camera.
+        if let Some(settings_panel) = &mut self.settings_panel
+            && settings_panel.update(&event)
+        {
+            renderer.window().request_redraw();
+            return;
+        }
+
         match event {
             WindowEvent::Resized(window_size) => {
-                scene.update_window_size([window_size.width as f32, window_size.height as f32]);
+                // Minimized windows have no area and there is nothing to recreate the swapchain
+                // or render images at. Restoring the window resizes it again.
+                if window_size.width == 0 || window_size.height == 0 {
+                    return;
+                }
+                scene.update_window_size(
+                    [window_size.width as f32, window_size.height as f32],
+                    renderer.window().scale_factor(),
+                );
                 renderer.resize();
             }
-            WindowEvent::ScaleFactorChanged { .. } => {
-                scene.update_window_size(renderer.window_size());
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                if is_minimized(renderer.window()) {
+                    return;
+                }
+                scene.update_window_size(renderer.window_size(), scale_factor);
                 renderer.resize();
             }
+            WindowEvent::Focused(is_focused) => {
+                self.is_focused = is_focused;
+
+                // The button may be released while another window has focus.
+                if !is_focused {
+                    self.camera_drag = None;
+                }
+            }
+            WindowEvent::Occluded(is_occluded) => {
+                self.is_occluded = is_occluded;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                scene.set_cursor_position(Some([position.x as f32, position.y as f32]));
+
+                if let (Some(button), Some([x, y])) = (self.camera_drag, self.cursor_position) {
+                    let dx = (position.x - x) as f32;
+                    let dy = (position.y - y) as f32;
+                    match button {
+                        MouseButton::Left => {
+                            scene.orbit_camera(-dx * CAMERA_ORBIT_STEP, -dy * CAMERA_ORBIT_STEP)
+                        }
+                        // Image space y points down in Vulkan so camera up appears as down on
+                        // screen. Panning moves the scene along with the cursor.
+                        _ => scene.move_camera([-dx * CAMERA_PAN_STEP, -dy * CAMERA_PAN_STEP, 0.0]),
+                    }
+                }
+                self.cursor_position = Some([position.x, position.y]);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                scene.set_cursor_position(None);
+                self.cursor_position = None;
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed
+                    if matches!(
+                        button,
+                        MouseButton::Left | MouseButton::Right | MouseButton::Middle
+                    ) =>
+                {
+                    self.camera_drag = Some(button);
+                }
+                ElementState::Released if self.camera_drag == Some(button) => {
+                    self.camera_drag = None;
+                }
+                _ => (),
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        (position.y / PIXELS_PER_SCROLL_LINE) as f32
+                    }
+                };
+
+                // Scrolling up moves towards the look at point.
+                scene.dolly_camera(CAMERA_DOLLY_STEP.powf(lines));
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
@@ -260,18 +621,247 @@ impl ApplicationHandler for App {
                     info!("Escape key was pressed; stopping.");
                     event_loop.exit();
                 }
+                // Toggle fullscreen with F11 or Alt + Enter.
+                Key::Named(named @ (NamedKey::F11 | NamedKey::Enter))
+                    if named == NamedKey::F11 || self.modifiers.alt_key() =>
+                {
+                    // The window is resized so the swapchain and render images are recreated
+                    // when the resize event arrives.
+                    self.is_fullscreen = !self.is_fullscreen;
+                    let fullscreen = if self.is_fullscreen {
+                        get_fullscreen(
+                            renderer.window(),
+                            self.config.fullscreen_mode,
+                            self.config.monitor,
+                        )
+                    } else {
+                        None
+                    };
+                    renderer.window().set_fullscreen(fullscreen);
+                    renderer.resize();
+                }
+                Key::Named(NamedKey::F1) => {
+                    if let Some(settings_panel) = &mut self.settings_panel {
+                        let is_visible = !settings_panel.is_visible();
+                        settings_panel.set_visible(is_visible);
+                        info!("Settings panel: {is_visible}");
+                        renderer.window().request_redraw();
+                    }
+                }
+                Key::Character("w") => scene.move_camera([0.0, 0.0, CAMERA_MOVE_STEP]),
+                Key::Character("s") => scene.move_camera([0.0, 0.0, -CAMERA_MOVE_STEP]),
+                Key::Character("a") => scene.move_camera([-CAMERA_MOVE_STEP, 0.0, 0.0]),
+                Key::Character("d") => scene.move_camera([CAMERA_MOVE_STEP, 0.0, 0.0]),
+                // Image space y points down in Vulkan so camera up appears as down on screen.
+                Key::Character("e") => scene.move_camera([0.0, -CAMERA_MOVE_STEP, 0.0]),
+                Key::Character("q") => scene.move_camera([0.0, CAMERA_MOVE_STEP, 0.0]),
+                Key::Character("z") => scene.roll_camera(-CAMERA_ROLL_STEP),
+                // Ctrl + C stops loading textures so large scenes can be reviewed without waiting.
+                Key::Character("c") if self.modifiers.control_key() => {
+                    info!("Cancelling texture loading");
+                    scene.cancel();
+                }
+                Key::Character("c") => scene.roll_camera(CAMERA_ROLL_STEP),
+                Key::Character("i") => {
+                    let sampling_pattern = scene.get_navigation_sampling_pattern().next();
+                    info!("Navigation sampling pattern: {sampling_pattern:?}");
+                    scene.set_navigation_sampling_pattern(sampling_pattern);
+                }
+                Key::Character("t") => {
+                    let temporal_reprojection = !scene.get_temporal_reprojection();
+                    info!("Temporal reprojection: {temporal_reprojection}");
+                    scene.set_temporal_reprojection(temporal_reprojection);
+                }
+                Key::Character("f") => {
+                    let is_autofocus = !scene.is_autofocus();
+                    info!("Autofocus: {is_autofocus}");
+                    scene.set_autofocus(is_autofocus);
+                }
+                Key::Character("v") => match scene.get_probe_statistics() {
+                    Some(statistics) => info!(
+                        "Visible: {:.1}% of the image covered, depth {:.3} to {:.3} (mean {:.3}), \
+                        {} instances of {} meshes",
+                        statistics.coverage * 100.0,
+                        statistics.min_depth,
+                        statistics.max_depth,
+                        statistics.mean_depth,
+                        statistics.visible_instances,
+                        statistics.visible_meshes,
+                    ),
+                    None => info!("Scene statistics are not available yet"),
+                },
+                Key::Character(c @ ("g" | "x" | "l" | "k")) => {
+                    let element = match c {
+                        "g" => OverlayElement::Grid,
+                        "x" => OverlayElement::Axes,
+                        "l" => OverlayElement::Lights,
+                        _ => OverlayElement::Cameras,
+                    };
+                    let is_visible = !scene.is_overlay_visible(element);
+                    info!("{element:?} overlay: {is_visible}");
+                    scene.set_overlay_visible(element, is_visible);
+                }
+                Key::Character("h") => {
+                    let analysis_mode = scene.get_analysis_mode().next();
+                    info!("Analysis mode: {analysis_mode:?}");
+                    scene.set_analysis_mode(analysis_mode);
+                }
+                Key::Character("u") => {
+                    let is_profiling = !scene.is_profiling();
+                    info!("Material profiling: {is_profiling}");
+                    scene.set_profiling(is_profiling);
+                    if !is_profiling {
+                        log_material_profile(scene);
+                    }
+                }
+                Key::Character("m") => {
+                    let is_magnifier_visible = !scene.is_magnifier_visible();
+                    info!("Magnifier: {is_magnifier_visible}");
+                    scene.set_magnifier_visible(is_magnifier_visible);
+                }
+                Key::Character("p") => {
+                    let is_paused = !scene.is_paused();
+                    info!("Paused: {is_paused}");
+                    scene.set_paused(is_paused);
+                }
+                Key::Character("n") => {
+                    let is_progressive = !scene.is_progressive();
+                    info!("Progressive accumulation: {is_progressive}");
+                    scene.set_progressive(is_progressive);
+                }
+                Key::Character("y") => {
+                    let is_tracing_caustics = !scene.is_tracing_caustics();
+                    match scene.set_tracing_caustics(is_tracing_caustics) {
+                        Ok(()) => {
+                            info!("Caustics pass: {is_tracing_caustics}");
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| {
+                                    scene_file.render.caustics = is_tracing_caustics
+                                });
+                            }
+                        }
+                        Err(e) => error!("Unable to set caustics pass. {e:?}"),
+                    }
+                }
+                Key::Character("b") => {
+                    let is_transparent_shadows = !scene.is_transparent_shadows();
+                    match scene.set_transparent_shadows(is_transparent_shadows) {
+                        Ok(()) => {
+                            info!("Transparent shadows: {is_transparent_shadows}");
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| {
+                                    scene_file.render.transparent_shadows = is_transparent_shadows
+                                });
+                            }
+                        }
+                        Err(e) => error!("Unable to set transparent shadows. {e:?}"),
+                    }
+                }
+                // Cycle through the scene file's cameras.
+                Key::Character("r") => {
+                    let names = scene.get_camera_names();
+                    let index = names
+                        .iter()
+                        .position(|&name| name == scene.get_active_camera())
+                        .map_or(0, |index| (index + 1) % names.len());
+                    let name = names[index].to_string();
+                    match scene.set_active_camera(&name) {
+                        Ok(()) => {
+                            info!("Camera: {name}");
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file
+                                    .edit(|scene_file| scene_file.render.camera = name.clone());
+                            }
+                        }
+                        Err(e) => error!("Unable to switch to camera {name}. {e:?}"),
+                    }
+                }
+                // Cycle the filter of every image texture between each texture's own filter,
+                // nearest for crisp texels and linear.
+                Key::Character("j") => {
+                    let texture_filter = match scene.get_texture_filter() {
+                        None => Some(TextureFilter::Nearest),
+                        Some(TextureFilter::Nearest) => Some(TextureFilter::Linear),
+                        Some(TextureFilter::Linear) => None,
+                    };
+                    match scene.set_texture_filter(texture_filter) {
+                        Ok(()) => {
+                            match texture_filter {
+                                Some(filter) => info!("Texture filter: {filter:?}"),
+                                None => info!("Texture filter: per texture"),
+                            }
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| {
+                                    scene_file.render.texture_filter = texture_filter
+                                });
+                            }
+                        }
+                        Err(e) => error!("Unable to set the texture filter. {e:?}"),
+                    }
+                }
+                // Trade quality for responsiveness. Accumulation restarts with the new setting.
+                Key::Character(c @ ("[" | "]")) => {
+                    let current = scene.get_samples_per_pixel();
+                    let samples_per_pixel = if c == "]" { current * 2 } else { current / 2 };
+                    match scene.set_samples_per_pixel(samples_per_pixel) {
+                        Ok(()) => {
+                            let samples_per_pixel = scene.get_samples_per_pixel();
+                            info!("Samples per pixel: {samples_per_pixel}");
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| {
+                                    scene_file.render.samples_per_pixel = samples_per_pixel
+                                });
+                            }
+                        }
+                        Err(e) => error!("Unable to set samples per pixel. {e:?}"),
+                    }
+                }
+                Key::Character(c @ ("-" | "=")) => {
+                    let current = scene.get_sample_batches();
+                    let sample_batches = if c == "=" { current * 2 } else { current / 2 };
+                    match scene.set_sample_batches(sample_batches) {
+                        Ok(()) => {
+                            let sample_batches = scene.get_sample_batches();
+                            info!("Sample batches: {sample_batches}");
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| {
+                                    scene_file.render.sample_batches = sample_batches
+                                });
+                            }
+                        }
+                        Err(e) => error!("Unable to set sample batches. {e:?}"),
+                    }
+                }
+                Key::Character(c @ ("," | ".")) => {
+                    let current = scene.get_max_ray_depth();
+                    let max_ray_depth = if c == "." {
+                        current + 1
+                    } else {
+                        current.saturating_sub(1)
+                    };
+                    match scene.set_max_ray_depth(max_ray_depth) {
+                        Ok(()) => {
+                            let max_ray_depth = scene.get_max_ray_depth();
+                            info!("Max ray depth: {max_ray_depth}");
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| {
+                                    scene_file.render.max_ray_depth = max_ray_depth
+                                });
+                            }
+                        }
+                        Err(e) => error!("Unable to set max ray depth. {e:?}"),
+                    }
+                }
                 Key::Character("o") => {
-                    // Handle File > Open.
-                    let current_file_path_buf = PathBuf::from(&self.current_file_path);
-                    let current_dir_path = current_file_path_buf
+                    // Handle File > Open. Start in the current scene's directory if it exists,
+                    // which it doesn't for the built-in scene.
+                    let mut fd = rfd::FileDialog::new().add_filter("JSON (.json)", &["json"]);
+                    if let Some(dir) = Path::new(&self.current_file_path)
                         .parent()
-                        .expect("Unable to get current directory.");
-                    let absolute_path = std::fs::canonicalize(current_dir_path)
-                        .expect("Unable to get absolute path of current directory.");
-
-                    let fd = rfd::FileDialog::new()
-                        .set_directory(absolute_path)
-                        .add_filter("JSON (.json)", &["json"]);
+                        .and_then(|dir| std::fs::canonicalize(dir).ok())
+                    {
+                        fd = fd.set_directory(dir);
+                    }
 
                     if let Some(path) = fd.pick_file() {
                         let selected_path = path.display().to_string();
@@ -281,9 +871,46 @@ impl ApplicationHandler for App {
                         }
                     }
                 }
+                Key::Character(c) if get_camera_bookmark_index(c).is_some() => {
+                    let index = get_camera_bookmark_index(c).unwrap();
+                    if self.modifiers.control_key() {
+                        // Store the bookmark and persist it to the scene file if there is one.
+                        match scene.store_camera_bookmark(index).and_then(|camera| {
+                            let is_saved = !self.current_file_path.is_empty();
+                            if is_saved {
+                                SceneFile::save_camera(&self.current_file_path, &camera)?;
+
+                                // Saving the bookmark isn't a change that needs a reload.
+                                if let Some(watcher) = &mut self.scene_file_watcher {
+                                    watcher.acknowledge();
+                                }
+                            }
+
+                            // Only record the edit once the file on disk has it.
+                            if let Some(edited_scene_file) = &mut self.edited_scene_file {
+                                edited_scene_file.edit(|scene_file| scene_file.set_camera(&camera));
+                            }
+                            Ok(is_saved)
+                        }) {
+                            Ok(true) => info!("Stored camera bookmark {c}"),
+                            Ok(false) => {
+                                info!("Stored camera bookmark {c} until the scene changes")
+                            }
+                            Err(e) => error!("Unable to store camera bookmark {c}. {e:?}"),
+                        }
+                    } else if !scene.go_to_camera_bookmark(index) {
+                        info!("Camera bookmark {c} is not set");
+                    }
+                }
                 _ => (),
             },
             WindowEvent::RedrawRequested => {
+                // The platform may still ask minimized windows to redraw, but a swapchain can't be
+                // recreated without an area. Rendering resumes once the window is restored.
+                if is_minimized(renderer.window()) {
+                    return;
+                }
+
                 // Acquire swapchain future and render the scene overlayed with the GUI.
                 match renderer.acquire(None, |_| {}) {
                     Ok(future) => {
@@ -291,8 +918,27 @@ impl ApplicationHandler for App {
                         let after_scene_render =
                             scene.render(future, renderer.swapchain_image_view());
 
+                        // Render GUI
+                        let after_gui_render = match &mut self.settings_panel {
+                            Some(settings_panel) => settings_panel.draw(
+                                scene,
+                                self.edited_scene_file.as_mut(),
+                                after_scene_render,
+                                renderer.swapchain_image_view(),
+                            ),
+                            None => after_scene_render,
+                        };
+
                         // Present swapchain
-                        renderer.present(after_scene_render, true);
+                        renderer.present(after_gui_render, true);
+
+                        let hovered = scene.get_hovered_instance();
+                        if hovered.map(|(instance, _)| instance) != self.hovered_instance {
+                            self.hovered_instance = hovered.map(|(instance, _)| instance);
+                            if let Some((instance, mesh_name)) = hovered {
+                                debug!("Hovering instance {instance} of mesh {mesh_name}");
+                            }
+                        }
                     }
                     Err(vulkano::VulkanError::OutOfDate) => {
                         renderer.resize();
@@ -307,12 +953,157 @@ impl ApplicationHandler for App {
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        // Save a recovery copy of the edits regularly in case the application crashes.
+        if let Some(Err(e)) = self
+            .edited_scene_file
+            .as_mut()
+            .map(|edited_scene_file| edited_scene_file.autosave_if_due())
+        {
+            error!("Unable to save a recovery copy. {e:?}");
+        }
+
+        // Reload the scene when its file changes. The event loop wakes up regularly to check even
+        // when nothing is being traced.
+        if let Some(watcher) = &mut self.scene_file_watcher {
+            if watcher.poll() {
+                self.reload_scene_file();
+            }
+            event_loop.set_control_flow(ControlFlow::WaitUntil(Instant::now() + POLL_INTERVAL));
+        }
+
         let renderer = self.windows.get_primary_renderer().unwrap();
-        renderer.window().request_redraw();
+        let window = renderer.window();
+
+        // Redraw continuously only while there is something to trace and someone to see it.
+        // Otherwise the event loop waits for the next event and the last image stays presented.
+        let is_paused = self.scene.as_ref().is_some_and(|scene| scene.is_paused());
+        let is_background = !self.is_focused || self.is_occluded;
+
+        // The settings panel keeps responding to the cursor while paused.
+        let is_panel_visible = self
+            .settings_panel
+            .as_ref()
+            .is_some_and(|settings_panel| settings_panel.is_visible());
+
+        if (is_paused && !is_panel_visible)
+            || is_minimized(window)
+            || (is_background && !self.config.render_in_background)
+        {
+            return;
+        }
+
+        window.request_redraw();
+    }
+
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        // Exiting normally discards unsaved edits, so only a crash leaves a recovery copy behind.
+        if let Some(Err(e)) = self
+            .edited_scene_file
+            .as_ref()
+            .map(|edited_scene_file| edited_scene_file.discard_recovery())
+        {
+            error!("Unable to discard unsaved changes. {e:?}");
+        }
+    }
+}
+
+/// Returns true if the window is minimized or otherwise has no area to render to.
+fn is_minimized(window: &Window) -> bool {
+    let window_size = window.inner_size();
+    window.is_minimized().unwrap_or(false) || window_size.width == 0 || window_size.height == 0
+}
+
+/// Returns the fullscreen state for the window on the given monitor index. If there is no such
+/// monitor, the monitor the window is on is used. Exclusive fullscreen uses the highest resolution
+/// video mode and falls back to borderless if the monitor has none.
+fn get_fullscreen(
+    window: &Window,
+    mode: FullscreenMode,
+    monitor: Option<usize>,
+) -> Option<Fullscreen> {
+    let monitor = match monitor {
+        Some(index) => window.available_monitors().nth(index).or_else(|| {
+            warn!("Monitor {index} not found. Using the current monitor.");
+            window.current_monitor()
+        }),
+        None => window.current_monitor(),
+    };
+
+    match mode {
+        FullscreenMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        FullscreenMode::Exclusive => {
+            let video_mode = monitor.as_ref().and_then(|monitor| {
+                monitor.video_modes().max_by_key(|video_mode| {
+                    let size = video_mode.size();
+                    (
+                        size.width as u64 * size.height as u64,
+                        video_mode.refresh_rate_millihertz(),
+                    )
+                })
+            });
+
+            match video_mode {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    warn!("No exclusive fullscreen video modes found. Using borderless.");
+                    Some(Fullscreen::Borderless(monitor))
+                }
+            }
+        }
     }
 }
 
+/// Logs the share of rays that hit each material type since profiling started, most hit first.
+fn log_material_profile(scene: &Scene) {
+    let Some(profile) = scene.get_material_profile() else {
+        return;
+    };
+
+    let total_hits = profile.get_total_hits();
+    if total_hits == 0 {
+        info!("No rays were counted while profiling");
+        return;
+    }
+
+    info!("Rays counted while profiling: {total_hits}");
+    for (name, count) in profile.get_entries() {
+        info!(
+            "  {name}: {:.1}% of rays ({} hits, {:.1}% scattered)",
+            count.hits as f64 * 100.0 / total_hits as f64,
+            count.hits,
+            count.scattered as f64 * 100.0 / count.hits as f64,
+        );
+    }
+}
+
+/// Returns hooks logging when the scene is built and when its render is complete.
+fn create_render_hooks() -> RenderHooks {
+    RenderHooks::default()
+        .on_scene_built(|stats| {
+            info!(
+                "Built scene with {} meshes and {} instances in {:.2?}",
+                stats.mesh_count, stats.instance_count, stats.build_time
+            );
+        })
+        .on_batch_complete(|progress| {
+            if progress.is_complete {
+                info!(
+                    "Render complete with {} samples per pixel",
+                    progress.accumulated_samples_per_pixel
+                );
+            }
+        })
+}
+
+/// Returns the camera bookmark index for keys 1-9.
+fn get_camera_bookmark_index(key: &str) -> Option<usize> {
+    key.parse::<usize>()
+        .ok()
+        .filter(|n| (1..=CAMERA_BOOKMARK_COUNT).contains(n))
+        .map(|n| n - 1)
+}
+
 /// Setup callback for logging debug information the GPU.
 fn setup_debug_callback(enable_debug_logging: bool) -> Option<DebugUtilsMessengerCreateInfo> {
     let debug_callback = if enable_debug_logging {
@@ -0,0 +1,402 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use scene_file::{AssetResolver, AutosavePolicy, Quality, RenderOverrides, SceneFile};
+use serde::Deserialize;
+
+use crate::demo::Demo;
+
+/// Configuration file that is loaded from the working directory if `--config` isn't given.
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Command line arguments. Each of these can also be set with an environment variable. Anything
+/// not set here falls back to the configuration file and then to the built-in defaults.
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Path to the configuration file.
+    #[arg(long, env = "RAYTRACER_CONFIG")]
+    config: Option<String>,
+
+    /// Path to the scene file.
+    #[arg(short, long, env = "RAYTRACER_SCENE")]
+    path: Option<String>,
+
+    /// Show a demo scene compiled into the binary instead of a scene file.
+    #[arg(long, env = "RAYTRACER_DEMO")]
+    demo: Option<Demo>,
+
+    /// Use the first GPU whose name contains this text (case insensitive).
+    #[arg(long, env = "RAYTRACER_DEVICE")]
+    device: Option<String>,
+
+    /// Directory to search for assets that aren't found relative to the scene file. This can be
+    /// given more than once. Directories in `RAYTRACER_ASSET_DIRS` are searched afterwards.
+    #[arg(long = "asset-dir")]
+    asset_dirs: Vec<String>,
+
+    /// Download assets that scene files reference by URL.
+    #[arg(long, env = "RAYTRACER_ALLOW_DOWNLOADS", num_args = 0..=1, default_missing_value = "true")]
+    allow_downloads: Option<bool>,
+
+    /// Enable Vulkan validation layers and log their messages.
+    #[arg(long, env = "RAYTRACER_VALIDATION", num_args = 0..=1, default_missing_value = "true")]
+    validation: Option<bool>,
+
+    /// Initial window width.
+    #[arg(long, env = "RAYTRACER_WINDOW_WIDTH")]
+    window_width: Option<f32>,
+
+    /// Initial window height.
+    #[arg(long, env = "RAYTRACER_WINDOW_HEIGHT")]
+    window_height: Option<f32>,
+
+    /// Start in fullscreen.
+    #[arg(long, env = "RAYTRACER_FULLSCREEN", num_args = 0..=1, default_missing_value = "true")]
+    fullscreen: Option<bool>,
+
+    /// How fullscreen is done.
+    #[arg(long, env = "RAYTRACER_FULLSCREEN_MODE")]
+    fullscreen_mode: Option<FullscreenMode>,
+
+    /// Index of the monitor used for fullscreen. The monitors are listed in the log at startup.
+    #[arg(long, env = "RAYTRACER_MONITOR")]
+    monitor: Option<usize>,
+
+    /// Kind of swapchain format preferred for the window.
+    #[arg(long, env = "RAYTRACER_SURFACE_FORMAT")]
+    surface_format: Option<SurfaceFormatPreference>,
+
+    /// Keep rendering while the window is unfocused or hidden.
+    #[arg(long, env = "RAYTRACER_RENDER_IN_BACKGROUND", num_args = 0..=1, default_missing_value = "true")]
+    render_in_background: Option<bool>,
+
+    /// Time in seconds taken to move the camera to a bookmark.
+    #[arg(long, env = "RAYTRACER_BOOKMARK_TRANSITION")]
+    bookmark_transition: Option<f32>,
+
+    /// Minutes after the first unsaved edit of a scene when a recovery copy is saved.
+    #[arg(long, env = "RAYTRACER_AUTOSAVE_MINUTES")]
+    autosave_minutes: Option<f32>,
+
+    /// Number of unsaved edits of a scene after which a recovery copy is saved.
+    #[arg(long, env = "RAYTRACER_AUTOSAVE_EDITS")]
+    autosave_edits: Option<u32>,
+
+    /// Overrides the samples per pixel of every scene.
+    #[arg(long, env = "RAYTRACER_SAMPLES_PER_PIXEL")]
+    samples_per_pixel: Option<u32>,
+
+    /// Overrides the sample batches of every scene.
+    #[arg(long, env = "RAYTRACER_SAMPLE_BATCHES")]
+    sample_batches: Option<u32>,
+
+    /// Overrides the maximum ray depth of every scene.
+    #[arg(long, env = "RAYTRACER_MAX_RAY_DEPTH")]
+    max_ray_depth: Option<u32>,
+
+    /// Fail to load scenes with image textures that can't be loaded instead of showing a
+    /// placeholder.
+    #[arg(long, env = "RAYTRACER_STRICT_TEXTURES", num_args = 0..=1, default_missing_value = "true")]
+    strict_textures: Option<bool>,
+
+    /// Level of detail scenes are rendered at: `preview` for coarser spheres, smaller textures and
+    /// fewer samples, or `final`.
+    #[arg(long, env = "RAYTRACER_QUALITY")]
+    quality: Option<Quality>,
+
+    /// Print the Vulkan capabilities of each device and exit.
+    #[arg(long)]
+    print_caps: bool,
+
+    /// Render the scene without opening a window, save it to `--output` and exit.
+    #[arg(long, requires = "output")]
+    headless: bool,
+
+    /// Path to the PNG saved by `--headless`.
+    #[arg(long, requires = "headless")]
+    output: Option<String>,
+}
+
+/// How the window covers the monitor in fullscreen.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum FullscreenMode {
+    /// A borderless window covering the monitor at its current video mode.
+    #[default]
+    Borderless,
+
+    /// Exclusive fullscreen using the monitor's highest resolution video mode.
+    Exclusive,
+}
+
+/// Kind of swapchain format preferred for the window. Both kinds display the same colours, since
+/// the rendered image is encoded to sRGB when it is written to UNORM formats. An 8-bit format with
+/// BGRA channels is preferred within each kind, and the other kind is used if neither is supported.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SurfaceFormatPreference {
+    /// Formats that encode colours to sRGB when they are written, such as `B8G8R8A8_SRGB`.
+    #[default]
+    Srgb,
+
+    /// Formats that store colours as they are, such as `B8G8R8A8_UNORM`.
+    Unorm,
+}
+
+/// Application settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Path to the scene file loaded at startup.
+    pub scene: String,
+
+    /// Demo scene compiled into the binary shown at startup instead of the scene file.
+    pub demo: Option<Demo>,
+
+    /// Directories to search for assets that aren't found relative to the scene file.
+    pub asset_dirs: Vec<String>,
+
+    /// Download assets that scene files reference by URL. Off by default so opening a scene file
+    /// doesn't reach out to the network.
+    pub allow_downloads: bool,
+
+    /// Use the first GPU whose name contains this text (case insensitive). If no GPU matches,
+    /// discrete GPUs are preferred.
+    pub device: Option<String>,
+
+    /// Enable Vulkan validation layers and log their messages.
+    pub validation: bool,
+
+    /// Initial window size. This is adjusted to match the aspect ratio of the scene.
+    pub window_size: [f32; 2],
+
+    /// Start in fullscreen.
+    pub fullscreen: bool,
+
+    /// How fullscreen is done.
+    pub fullscreen_mode: FullscreenMode,
+
+    /// Index of the monitor used for fullscreen. The monitor the window is on is used if this is
+    /// not set.
+    pub monitor: Option<usize>,
+
+    /// Kind of swapchain format preferred for the window.
+    pub surface_format: SurfaceFormatPreference,
+
+    /// Keep rendering while the window is unfocused or hidden. Otherwise rendering idles until the
+    /// window is in the foreground again.
+    pub render_in_background: bool,
+
+    /// Time in seconds taken to move the camera to a bookmark.
+    pub bookmark_transition: f32,
+
+    /// Minutes after the first unsaved edit of a scene when a recovery copy is saved.
+    pub autosave_minutes: f32,
+
+    /// Number of unsaved edits of a scene after which a recovery copy is saved.
+    pub autosave_edits: u32,
+
+    /// Replacements for the render settings of every scene that is loaded.
+    pub render: RenderOverrides,
+
+    /// Print the Vulkan capabilities of each device and exit. This can only be set on the command
+    /// line.
+    #[serde(skip)]
+    pub print_caps: bool,
+
+    /// Path to save a render of the scene to without opening a window, before exiting. This can
+    /// only be set on the command line.
+    #[serde(skip)]
+    pub headless_output: Option<String>,
+
+    /// Whether the scene was given on the command line. If not and the scene file doesn't exist,
+    /// the user is asked to pick one instead of failing.
+    #[serde(skip)]
+    pub is_scene_from_cli: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scene: "assets/final-one-weekend.json".to_string(),
+            demo: None,
+            asset_dirs: vec![],
+            allow_downloads: false,
+            device: None,
+            validation: false,
+            window_size: [1024.0, 576.0],
+            fullscreen: false,
+            fullscreen_mode: FullscreenMode::default(),
+            monitor: None,
+            surface_format: SurfaceFormatPreference::default(),
+            render_in_background: false,
+            bookmark_transition: 1.0,
+            autosave_minutes: 2.0,
+            autosave_edits: 10,
+            render: RenderOverrides::default(),
+            print_caps: false,
+            headless_output: None,
+            is_scene_from_cli: false,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the built-in defaults, then the configuration file, then
+    /// environment variables and finally command line arguments, with later ones taking priority.
+    pub fn load() -> Result<Self> {
+        let cli = Cli::parse();
+
+        let mut config = match &cli.config {
+            Some(path) => Self::load_toml(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::load_toml(DEFAULT_CONFIG_PATH)?
+            }
+            None => Self::default(),
+        };
+
+        // Clap has already given command line arguments priority over environment variables.
+        if let Some(scene) = cli.path {
+            // A scene on the command line replaces a demo set in the configuration file.
+            config.scene = scene;
+            config.demo = None;
+            config.is_scene_from_cli = true;
+        }
+        if let Some(demo) = cli.demo {
+            config.demo = Some(demo);
+        }
+        if !cli.asset_dirs.is_empty() {
+            config.asset_dirs = cli.asset_dirs;
+        }
+        if let Some(allow_downloads) = cli.allow_downloads {
+            config.allow_downloads = allow_downloads;
+        }
+        if let Some(device) = cli.device {
+            config.device = Some(device);
+        }
+        if let Some(validation) = cli.validation {
+            config.validation = validation;
+        }
+        if let Some(width) = cli.window_width {
+            config.window_size[0] = width;
+        }
+        if let Some(height) = cli.window_height {
+            config.window_size[1] = height;
+        }
+        if let Some(fullscreen) = cli.fullscreen {
+            config.fullscreen = fullscreen;
+        }
+        if let Some(fullscreen_mode) = cli.fullscreen_mode {
+            config.fullscreen_mode = fullscreen_mode;
+        }
+        if let Some(monitor) = cli.monitor {
+            config.monitor = Some(monitor);
+        }
+        if let Some(surface_format) = cli.surface_format {
+            config.surface_format = surface_format;
+        }
+        if let Some(render_in_background) = cli.render_in_background {
+            config.render_in_background = render_in_background;
+        }
+        if let Some(bookmark_transition) = cli.bookmark_transition {
+            config.bookmark_transition = bookmark_transition;
+        }
+        if let Some(autosave_minutes) = cli.autosave_minutes {
+            config.autosave_minutes = autosave_minutes;
+        }
+        if let Some(autosave_edits) = cli.autosave_edits {
+            config.autosave_edits = autosave_edits;
+        }
+        if let Some(samples_per_pixel) = cli.samples_per_pixel {
+            config.render.samples_per_pixel = Some(samples_per_pixel);
+        }
+        if let Some(sample_batches) = cli.sample_batches {
+            config.render.sample_batches = Some(sample_batches);
+        }
+        if let Some(max_ray_depth) = cli.max_ray_depth {
+            config.render.max_ray_depth = Some(max_ray_depth);
+        }
+        if let Some(strict_textures) = cli.strict_textures {
+            config.render.strict_textures = Some(strict_textures);
+        }
+        if let Some(quality) = cli.quality {
+            config.render.quality = Some(quality);
+        }
+        config.print_caps = cli.print_caps;
+        config.headless_output = cli.output.filter(|_| cli.headless);
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads a scene file, finding its assets in the asset directories, and applies the render
+    /// setting overrides.
+    pub fn load_scene_file(&self, path: &str) -> Result<SceneFile> {
+        let mut resolver = AssetResolver::new(self.asset_dirs.iter().map(PathBuf::from).collect());
+        resolver.allow_downloads = self.allow_downloads;
+        let mut scene_file = SceneFile::load_json_with_resolver(path, &resolver)?;
+        scene_file.apply_render_overrides(&self.render);
+        Ok(scene_file)
+    }
+
+    /// Loads a demo scene and applies the render setting overrides.
+    pub fn load_demo(&self, demo: Demo) -> Result<SceneFile> {
+        let mut scene_file = demo.load()?;
+        scene_file.apply_render_overrides(&self.render);
+        Ok(scene_file)
+    }
+
+    /// Returns the window size adjusted to the scene's aspect ratio. The longer side of the
+    /// configured window size is the one that changes.
+    pub fn get_window_size(&self, aspect_ratio: f32) -> [f32; 2] {
+        let mut window_size = self.window_size;
+        if window_size[0] > window_size[1] {
+            window_size[0] = aspect_ratio * window_size[1];
+        } else {
+            window_size[1] = window_size[0] / aspect_ratio;
+        }
+        window_size
+    }
+
+    /// Returns the time taken to move the camera to a bookmark.
+    pub fn get_bookmark_transition_duration(&self) -> Duration {
+        Duration::from_secs_f32(self.bookmark_transition)
+    }
+
+    /// Returns how often recovery copies of edited scenes are saved.
+    pub fn get_autosave_policy(&self) -> AutosavePolicy {
+        AutosavePolicy {
+            interval: Duration::from_secs_f32(self.autosave_minutes * 60.0),
+            edits: self.autosave_edits,
+        }
+    }
+
+    /// Returns an error naming the first setting that is out of range.
+    fn validate(&self) -> Result<()> {
+        Duration::try_from_secs_f32(self.bookmark_transition).with_context(|| {
+            format!(
+                "bookmark_transition must be a non-negative number of seconds, not {}",
+                self.bookmark_transition
+            )
+        })?;
+        Duration::try_from_secs_f32(self.autosave_minutes * 60.0).with_context(|| {
+            format!(
+                "autosave_minutes must be a non-negative number of minutes, not {}",
+                self.autosave_minutes
+            )
+        })?;
+        Ok(())
+    }
+
+    fn load_toml(path: &str) -> Result<Self> {
+        let serialized = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read configuration file '{path}'"))?;
+        toml::from_str(&serialized)
+            .with_context(|| format!("Unable to parse configuration file '{path}'"))
+    }
+}
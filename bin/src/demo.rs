@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use scene_file::SceneFile;
+use serde::Deserialize;
+
+/// Demo scenes compiled into the binary so the renderer can be shown without any asset files.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Demo {
+    /// Cornell box with two white boxes lit by a ceiling light.
+    Cornell,
+
+    /// Glass, diffuse and metal spheres from the cover of Ray Tracing in One Weekend.
+    Weekend,
+
+    /// Spheres showing constant, scalar, checker and noise textures.
+    Textures,
+}
+
+impl Demo {
+    /// Returns the name of the demo as given on the command line.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Cornell => "cornell",
+            Self::Weekend => "weekend",
+            Self::Textures => "textures",
+        }
+    }
+
+    /// Parses the demo's scene file.
+    pub fn load(&self) -> Result<SceneFile> {
+        SceneFile::from_json_str(self.get_json())
+            .with_context(|| format!("Unable to load the {} demo", self.get_name()))
+    }
+
+    fn get_json(&self) -> &'static str {
+        match self {
+            Self::Cornell => include_str!("../../assets/cornell-box.json"),
+            Self::Weekend => include_str!("../../assets/weekend-cover.json"),
+            Self::Textures => include_str!("../../assets/texture-test.json"),
+        }
+    }
+}
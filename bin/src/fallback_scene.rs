@@ -0,0 +1,78 @@
+use anyhow::Result;
+use scene_file::{
+    Camera, Film, PixelFilter, Quality, Render, ScalingPolicy, SceneBuilder, SceneFile, Sky,
+};
+
+/// Returns a small built-in scene with three spheres resting on a ground sphere. This is shown
+/// when there is no scene file to load so the application still starts.
+pub fn create_fallback_scene() -> Result<SceneFile> {
+    let render = Render {
+        camera: "default".to_string(),
+        samples_per_pixel: 8,
+        sample_batches: 16,
+        max_ray_depth: 20,
+        aspect_ratio: 16.0 / 9.0,
+        resolution: None,
+        scaling: ScalingPolicy::default(),
+        strict_textures: false,
+        layers: None,
+        tags: None,
+        film: Film::default(),
+        pixel_filter: PixelFilter::default(),
+        supersample: None,
+        shutter: None,
+        caustics: false,
+        transparent_shadows: false,
+        denoise: false,
+        texture_filter: None,
+        strip_unused: false,
+        compact_acceleration: false,
+        budget: None,
+        quality: Quality::default(),
+    };
+
+    let sky = Sky::VerticalGradient {
+        factor: 0.5,
+        top: [0.5, 0.7, 1.0],
+        bottom: [1.0, 1.0, 1.0],
+    };
+
+    let mut scene = SceneBuilder::new(render, sky);
+
+    scene.add_camera(Camera::Perspective {
+        name: "default".to_string(),
+        eye: [0.0, -1.5, 8.0],
+        look_at: [0.0, -1.0, 0.0],
+        up: Some([0.0, 1.0, 0.0]),
+        orientation: None,
+        fov_y: 30.0,
+        z_near: 0.01,
+        z_far: 100.0,
+        focal_length: 8.0,
+        aperture_size: 0.0,
+        exposure: None,
+    });
+
+    let ground_material = scene.lambertian_rgb([0.5, 0.5, 0.5]);
+    let ground = scene
+        .add_sphere([0.0, 1000.0, 0.0], 1000.0)
+        .name("ground")
+        .tessellation(128, 256)
+        .material(&ground_material)
+        .build()?;
+
+    let spheres = [
+        ([-2.2, -1.0, 0.0], scene.lambertian_rgb([0.7, 0.2, 0.1])),
+        ([0.0, -1.0, 0.0], scene.dielectric(1.5)),
+        ([2.2, -1.0, 0.0], scene.metal_rgb([0.8, 0.7, 0.6], 0.05)),
+    ];
+    for (center, material) in spheres {
+        scene
+            .add_sphere(center, 1.0)
+            .material(&material)
+            .touching_ground(&ground)
+            .build()?;
+    }
+
+    scene.build()
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use log::info;
+use raytracer::{
+    CancellationToken, FilmResponse, RenderHooks, RenderMetadata, Scene, Vk, save_png,
+};
+
+use crate::config::Config;
+
+/// Renders the configured scene or demo without opening a window and saves the image as a PNG
+/// with the scene's film response applied. The image is rendered at the scene's `resolution`, or
+/// at the size the window would have for the scene if it doesn't set one. This always uses the
+/// best GPU the renderer supports.
+pub fn render_headless(config: &Config, output_path: &str) -> Result<()> {
+    let (scene_file, metadata) = match config.demo {
+        Some(demo) => (config.load_demo(demo)?, None),
+        None => {
+            let scene_file = config.load_scene_file(&config.scene)?;
+            let metadata = RenderMetadata::new(&config.scene, &scene_file)?;
+            (scene_file, Some(metadata))
+        }
+    };
+
+    let window_size = config.get_window_size(scene_file.render.aspect_ratio);
+    let [width, height] = scene_file.render.resolution.unwrap_or([
+        window_size[0].round().max(1.0) as u32,
+        window_size[1].round().max(1.0) as u32,
+    ]);
+    info!("Rendering {width} x {height} headless to {output_path}");
+
+    let vk = Vk::new_headless()?;
+    let mut scene = Scene::new_headless(
+        vk,
+        &scene_file,
+        width,
+        height,
+        CancellationToken::new(),
+        RenderHooks::default(),
+    )?;
+    let output = scene.render_offline()?;
+
+    let film = FilmResponse::new(&scene_file.render.film);
+    let pixels = film.apply_to_pixels(&output.beauty);
+    save_png(output_path, width, height, &pixels, metadata.as_ref())?;
+
+    info!("Saved {output_path}");
+    Ok(())
+}
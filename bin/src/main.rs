@@ -1,25 +1,37 @@
 mod app;
+mod config;
+mod demo;
+mod fallback_scene;
+mod headless;
+mod settings_panel;
+mod surface_format;
+mod watcher;
 
-use clap::Parser;
-use winit::{error::EventLoopError, event_loop::EventLoop};
+use anyhow::Result;
+use raytracer::Capabilities;
+use winit::event_loop::EventLoop;
 
-use crate::app::App;
+use crate::{app::App, config::Config, headless::render_headless};
 
-#[derive(Debug, Parser)]
-#[command(version, about, long_about = None)]
-struct Cli {
-    /// Path
-    #[arg(short, long, default_value = "assets/final-one-weekend.json")]
-    path: String,
-}
-
-fn main() -> Result<(), EventLoopError> {
+fn main() -> Result<()> {
     env_logger::init();
 
-    let cli = Cli::parse();
+    let config = Config::load()?;
+
+    if config.print_caps {
+        for capabilities in Capabilities::query_all()? {
+            println!("{capabilities}\n");
+        }
+        return Ok(());
+    }
+
+    if let Some(output_path) = &config.headless_output {
+        return render_headless(&config, output_path);
+    }
 
     let event_loop = EventLoop::new().unwrap();
 
-    let mut app = App::new(&event_loop, false, &cli.path);
-    event_loop.run_app(&mut app)
+    let mut app = App::new(&event_loop, config);
+    event_loop.run_app(&mut app)?;
+    Ok(())
 }
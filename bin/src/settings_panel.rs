@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use egui_winit_vulkano::{
+    Gui, GuiConfig,
+    egui::{
+        self, CollapsingHeader, ProgressBar, Rgba, ScrollArea, Slider, Ui,
+        color_picker::{self, Alpha},
+    },
+};
+use log::error;
+use raytracer::Scene;
+use scene_file::{EditedSceneFile, MAX_SAMPLES_PER_PIXEL, Sky, Texture};
+use vulkano::{image::view::ImageView, sync::GpuFuture};
+use vulkano_util::renderer::VulkanoWindowRenderer;
+use winit::{event::WindowEvent, event_loop::ActiveEventLoop};
+
+/// Largest maximum ray depth offered by the slider. Higher depths can still be set with the keys.
+const MAX_RAY_DEPTH: u32 = 64;
+
+/// Maximum height of the list of materials before it scrolls.
+const MATERIALS_MAX_HEIGHT: f32 = 300.0;
+
+/// Overlay for inspecting the progress of the render and tweaking its settings, the sky and the
+/// albedo of materials while it runs. Changes restart accumulation and are kept with the other
+/// edits of the scene file.
+pub struct SettingsPanel {
+    /// Integration of egui with winit and vulkano.
+    gui: Gui,
+
+    /// Whether the panel is shown.
+    is_visible: bool,
+}
+
+impl SettingsPanel {
+    /// Creates a hidden panel drawn over the window's swapchain images.
+    pub fn new(event_loop: &ActiveEventLoop, renderer: &VulkanoWindowRenderer) -> Self {
+        let gui = Gui::new(
+            event_loop,
+            renderer.surface(),
+            renderer.graphics_queue(),
+            renderer.swapchain_format(),
+            GuiConfig {
+                allow_srgb_render_target: true,
+                is_overlay: true,
+                ..Default::default()
+            },
+        );
+
+        Self {
+            gui,
+            is_visible: false,
+        }
+    }
+
+    /// Returns true if the panel is shown.
+    pub fn is_visible(&self) -> bool {
+        self.is_visible
+    }
+
+    /// Shows or hides the panel.
+    pub fn set_visible(&mut self, is_visible: bool) {
+        self.is_visible = is_visible;
+    }
+
+    /// Passes a window event to the panel. Returns true if the panel used it, e.g. a click on one
+    /// of its controls, so it shouldn't also control the scene. egui always sees the events so
+    /// its state stays current while the panel is hidden.
+    pub fn update(&mut self, event: &WindowEvent) -> bool {
+        let is_consumed = self.gui.update(event);
+        self.is_visible && is_consumed
+    }
+
+    /// Draws the panel over the rendered image if it is shown and applies the changes made with
+    /// it to the scene and the edited scene file.
+    pub fn draw(
+        &mut self,
+        scene: &mut Scene,
+        mut edited_scene_file: Option<&mut EditedSceneFile>,
+        before_future: Box<dyn GpuFuture>,
+        image_view: Arc<ImageView>,
+    ) -> Box<dyn GpuFuture> {
+        if !self.is_visible {
+            return before_future;
+        }
+
+        self.gui.immediate_ui(|gui| {
+            let ctx = gui.context();
+            egui::Window::new("Settings")
+                .default_pos([10.0, 10.0])
+                .resizable(false)
+                .show(&ctx, |ui| {
+                    show_progress(ui, scene);
+                    ui.separator();
+                    show_render_settings(ui, scene, edited_scene_file.as_deref_mut());
+
+                    // The sky and materials are only known from the scene file.
+                    if let Some(edited_scene_file) = edited_scene_file.as_deref_mut() {
+                        ui.separator();
+                        show_sky(ui, scene, edited_scene_file);
+                        show_materials(ui, scene, edited_scene_file);
+                    }
+                });
+        });
+
+        self.gui.draw_on_image(before_future, image_view)
+    }
+}
+
+/// Shows how many samples per pixel have accumulated out of the total for the render.
+fn show_progress(ui: &mut Ui, scene: &Scene) {
+    let accumulated = scene.get_accumulated_samples_per_pixel();
+    if scene.is_progressive() {
+        // Progressive rendering has no total.
+        ui.label(format!("{accumulated} samples per pixel"));
+    } else {
+        let total = scene.get_samples_per_pixel() * scene.get_sample_batches();
+        let progress = accumulated.min(total) as f32 / total.max(1) as f32;
+        ui.add(
+            ProgressBar::new(progress)
+                .text(format!("{accumulated} / {total} samples per pixel"))
+                .show_percentage(),
+        );
+    }
+}
+
+/// Shows sliders for the samples per pixel and the maximum ray depth.
+fn show_render_settings(
+    ui: &mut Ui,
+    scene: &mut Scene,
+    mut edited_scene_file: Option<&mut EditedSceneFile>,
+) {
+    let mut samples_per_pixel = scene.get_samples_per_pixel();
+    let response = ui.add(
+        Slider::new(&mut samples_per_pixel, 1..=MAX_SAMPLES_PER_PIXEL)
+            .logarithmic(true)
+            .text("Samples per pixel"),
+    );
+    if response.changed() {
+        match scene.set_samples_per_pixel(samples_per_pixel) {
+            Ok(()) => {
+                let samples_per_pixel = scene.get_samples_per_pixel();
+                if let Some(edited_scene_file) = edited_scene_file.as_deref_mut() {
+                    edited_scene_file
+                        .edit(|scene_file| scene_file.render.samples_per_pixel = samples_per_pixel);
+                }
+            }
+            Err(e) => error!("Unable to set samples per pixel. {e:?}"),
+        }
+    }
+
+    let mut max_ray_depth = scene.get_max_ray_depth();
+    let response = ui.add(
+        Slider::new(&mut max_ray_depth, 1..=MAX_RAY_DEPTH.max(max_ray_depth)).text("Max ray depth"),
+    );
+    if response.changed() {
+        match scene.set_max_ray_depth(max_ray_depth) {
+            Ok(()) => {
+                let max_ray_depth = scene.get_max_ray_depth();
+                if let Some(edited_scene_file) = edited_scene_file.as_deref_mut() {
+                    edited_scene_file
+                        .edit(|scene_file| scene_file.render.max_ray_depth = max_ray_depth);
+                }
+            }
+            Err(e) => error!("Unable to set max ray depth. {e:?}"),
+        }
+    }
+}
+
+/// Shows colour pickers for the sky.
+fn show_sky(ui: &mut Ui, scene: &mut Scene, edited_scene_file: &mut EditedSceneFile) {
+    let mut sky = edited_scene_file.get_scene_file().sky.clone();
+    let is_changed = match &mut sky {
+        Sky::Solid { rgb } => edit_linear_rgb(ui, "Sky", rgb),
+        Sky::VerticalGradient {
+            factor,
+            top,
+            bottom,
+        } => {
+            let is_top_changed = edit_linear_rgb(ui, "Sky top", top);
+            let is_bottom_changed = edit_linear_rgb(ui, "Sky bottom", bottom);
+            let is_factor_changed = ui
+                .add(Slider::new(factor, 0.0..=1.0).text("Sky gradient"))
+                .changed();
+            is_top_changed || is_bottom_changed || is_factor_changed
+        }
+    };
+
+    if is_changed {
+        match scene.set_sky(&sky) {
+            Ok(()) => edited_scene_file.edit(|scene_file| scene_file.sky = sky),
+            Err(e) => error!("Unable to set the sky. {e:?}"),
+        }
+    }
+}
+
+/// Shows colour pickers for the albedo of the materials whose albedo is a constant colour.
+/// Materials sharing a texture change together.
+fn show_materials(ui: &mut Ui, scene: &mut Scene, edited_scene_file: &mut EditedSceneFile) {
+    let scene_file = edited_scene_file.get_scene_file();
+    let textures = scene_file.get_textures();
+    let albedos: Vec<(String, String, [f32; 3])> = scene_file
+        .materials
+        .iter()
+        .filter_map(|material| {
+            let texture = material.get_albedo()?;
+            match textures.get(texture)? {
+                Texture::Constant { rgb, .. } => {
+                    Some((material.get_name().to_string(), texture.to_string(), *rgb))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+
+    CollapsingHeader::new(format!("Materials ({})", albedos.len()))
+        .default_open(false)
+        .show(ui, |ui| {
+            ScrollArea::vertical()
+                .max_height(MATERIALS_MAX_HEIGHT)
+                .show(ui, |ui| {
+                    for (material, texture, mut rgb) in albedos {
+                        if !edit_linear_rgb(ui, &material, &mut rgb) {
+                            continue;
+                        }
+
+                        match scene.set_constant_colour(&texture, rgb) {
+                            Ok(()) => edited_scene_file.edit(|scene_file| {
+                                for t in scene_file.textures.iter_mut() {
+                                    if let Texture::Constant { name, rgb: value } = t
+                                        && *name == texture
+                                    {
+                                        *value = rgb;
+                                    }
+                                }
+                            }),
+                            Err(e) => error!("Unable to set the albedo of {material}. {e:?}"),
+                        }
+                    }
+                });
+        });
+}
+
+/// Shows a labelled colour picker for a linear RGB colour. Returns true if the colour changed.
+fn edit_linear_rgb(ui: &mut Ui, label: &str, rgb: &mut [f32; 3]) -> bool {
+    let mut colour = Rgba::from_rgb(rgb[0], rgb[1], rgb[2]);
+    let is_changed = ui
+        .horizontal(|ui| {
+            let response = color_picker::color_edit_button_rgba(ui, &mut colour, Alpha::Opaque);
+            ui.label(label);
+            response.changed()
+        })
+        .inner;
+
+    if is_changed {
+        *rgb = [colour.r(), colour.g(), colour.b()];
+    }
+    is_changed
+}
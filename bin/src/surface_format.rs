@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    format::{Format, NumericFormat},
+    swapchain::{ColorSpace, Surface, SurfaceInfo},
+};
+use vulkano_util::context::VulkanoContext;
+use winit::{event_loop::ActiveEventLoop, window::Window};
+
+use crate::config::SurfaceFormatPreference;
+
+/// Returns the formats and colour spaces the device can present to windows with. vulkano util
+/// creates the swapchain with the first one, which depends on the driver, so they are queried
+/// with a hidden window before the real one is created.
+pub fn get_surface_formats(
+    event_loop: &ActiveEventLoop,
+    context: &VulkanoContext,
+) -> Result<Vec<(Format, ColorSpace)>> {
+    let window = event_loop.create_window(Window::default_attributes().with_visible(false))?;
+    let surface = Surface::from_window(context.instance().clone(), Arc::new(window))?;
+    Ok(context
+        .device()
+        .physical_device()
+        .surface_formats(&surface, SurfaceInfo::default())?)
+}
+
+/// Returns the best supported format and colour space for the swapchain, or `None` if there are
+/// none. Formats in the sRGB colour space are picked by their score, with ties going to the one
+/// the driver lists first. The first format is used if none are in the sRGB colour space.
+pub fn choose_surface_format(
+    formats: &[(Format, ColorSpace)],
+    preference: SurfaceFormatPreference,
+) -> Option<(Format, ColorSpace)> {
+    formats
+        .iter()
+        .rev()
+        .filter(|(_, color_space)| *color_space == ColorSpace::SrgbNonLinear)
+        .max_by_key(|(format, _)| get_score(*format, preference))
+        .or(formats.first())
+        .copied()
+}
+
+/// Returns the score of a format. The preferred kind of format counts the most, then 8-bit
+/// formats, which the dithering of the rendered image is tuned for, then BGRA channel order, which
+/// most presentation engines use natively.
+fn get_score(format: Format, preference: SurfaceFormatPreference) -> u32 {
+    let numeric_format = match preference {
+        SurfaceFormatPreference::Srgb => NumericFormat::SRGB,
+        SurfaceFormatPreference::Unorm => NumericFormat::UNORM,
+    };
+    let is_preferred = format.numeric_format_color() == Some(numeric_format);
+    let is_bgra = matches!(format, Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM);
+    let is_8_bit = is_bgra || matches!(format, Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM);
+
+    u32::from(is_preferred) * 4 + u32::from(is_8_bit) * 2 + u32::from(is_bgra)
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Interval between checks of the file's modification time.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Time the modification time must stay unchanged before a change is reported. Editors often
+/// write a file in several steps, e.g. truncating it before writing the new contents, so this
+/// avoids reloading a partially written file.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches a file for changes by polling its modification time.
+pub struct FileWatcher {
+    /// Path of the watched file.
+    path: PathBuf,
+
+    /// Modification time of the file when it was last reported or acknowledged.
+    modified: Option<SystemTime>,
+
+    /// Modification time of a change that hasn't been reported yet and when it was first seen.
+    pending: Option<(SystemTime, Instant)>,
+
+    /// When the modification time was last checked.
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    /// Starts watching the file at the given path.
+    pub fn new(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let modified = get_modified(&path);
+        Self {
+            path,
+            modified,
+            pending: None,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns `true` once the file has changed and then stayed unchanged for the debounce
+    /// interval. A missing file, e.g. while an editor replaces it, isn't a change.
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.last_poll) < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = now;
+
+        let Some(modified) = get_modified(&self.path) else {
+            return false;
+        };
+        if Some(modified) == self.modified {
+            self.pending = None;
+            return false;
+        }
+
+        match self.pending {
+            Some((pending, since)) if pending == modified => {
+                if now.duration_since(since) < DEBOUNCE {
+                    return false;
+                }
+                self.modified = Some(modified);
+                self.pending = None;
+                true
+            }
+            _ => {
+                self.pending = Some((modified, now));
+                false
+            }
+        }
+    }
+
+    /// Treats the current contents of the file as seen, e.g. after the application saved it, so
+    /// it isn't reported as a change.
+    pub fn acknowledge(&mut self) {
+        self.modified = get_modified(&self.path);
+        self.pending = None;
+    }
+}
+
+/// Returns the modification time of the file, or `None` if it can't be read.
+fn get_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
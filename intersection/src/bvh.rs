@@ -0,0 +1,347 @@
+use glam::Vec3;
+
+use crate::{Hit, Ray};
+
+/// Maximum number of triangles stored in a leaf node.
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// A BVH node.
+#[derive(Clone, Copy, Debug)]
+pub struct BvhNode {
+    /// Minimum corner of the bounding box.
+    pub aabb_min: Vec3,
+
+    /// Maximum corner of the bounding box.
+    pub aabb_max: Vec3,
+
+    /// For leaf nodes, the index of the first triangle in `Bvh::triangle_indices`. Otherwise the
+    /// index of the left child and the right child follows it.
+    pub left_first: u32,
+
+    /// Number of triangles in a leaf node. This is 0 for interior nodes.
+    pub triangle_count: u32,
+}
+
+impl BvhNode {
+    /// Returns true if this is a leaf node.
+    pub fn is_leaf(&self) -> bool {
+        self.triangle_count > 0
+    }
+}
+
+/// Bounding volume hierarchy over a list of triangles for closest hit and any hit ray queries.
+/// Triangles are split at the median centroid along the longest axis.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    /// The nodes. The first node is the root. This is empty if there are no triangles.
+    pub nodes: Vec<BvhNode>,
+
+    /// The triangles in the order they were given.
+    pub triangles: Vec<[Vec3; 3]>,
+
+    /// Indices into `triangles` ordered so each leaf references a contiguous range.
+    pub triangle_indices: Vec<u32>,
+}
+
+impl Bvh {
+    /// Build a new BVH over the triangles.
+    pub fn new(triangles: Vec<[Vec3; 3]>) -> Self {
+        let mut triangle_indices: Vec<u32> = (0..triangles.len() as u32).collect();
+        let nodes = if triangles.is_empty() {
+            vec![]
+        } else {
+            build_nodes(&triangles, &mut triangle_indices)
+        };
+
+        Self {
+            nodes,
+            triangles,
+            triangle_indices,
+        }
+    }
+
+    /// Build a new BVH over an indexed triangle mesh.
+    pub fn from_mesh(vertices: &[Vec3], indices: &[u32]) -> Self {
+        Self::new(
+            indices
+                .chunks_exact(3)
+                .map(|t| [0, 1, 2].map(|i| vertices[t[i] as usize]))
+                .collect(),
+        )
+    }
+
+    /// Returns the closest intersection within (`t_min`, `t_max`).
+    pub fn closest_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        self.traverse(ray, t_min, t_max, |hit| {
+            closest = Some(hit);
+            false
+        });
+        closest
+    }
+
+    /// Returns true if any triangle is hit within (`t_min`, `t_max`). This stops at the first
+    /// intersection found so it is cheaper than `closest_hit` for visibility tests.
+    pub fn any_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let mut is_hit = false;
+        self.traverse(ray, t_min, t_max, |_| {
+            is_hit = true;
+            true
+        });
+        is_hit
+    }
+
+    /// Visits the leaves the ray passes through nearest first and calls `on_hit` for each
+    /// intersection closer than the previous ones. Traversal stops early if `on_hit` returns true.
+    fn traverse(&self, ray: &Ray, t_min: f32, t_max: f32, mut on_hit: impl FnMut(Hit) -> bool) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_direction = ray.direction.recip();
+        let intersect_node = |node: &BvhNode, t_max: f32| {
+            ray.intersect_aabb(inv_direction, node.aabb_min, node.aabb_max, t_min, t_max)
+        };
+
+        let mut closest_t = t_max;
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if intersect_node(node, closest_t).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                let first = node.left_first as usize;
+                for &triangle in &self.triangle_indices[first..first + node.triangle_count as usize]
+                {
+                    let triangle = triangle as usize;
+                    if let Some(hit) =
+                        ray.intersect_triangle(&self.triangles[triangle], t_min, closest_t)
+                    {
+                        closest_t = hit.t;
+                        if on_hit(Hit { triangle, ..hit }) {
+                            return;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // Visit the nearer child first so more of the farther one can be culled.
+            let left = node.left_first as usize;
+            let right = left + 1;
+            let t_left = intersect_node(&self.nodes[left], closest_t);
+            let t_right = intersect_node(&self.nodes[right], closest_t);
+
+            match (t_left, t_right) {
+                (Some(t_left), Some(t_right)) if t_left <= t_right => stack.extend([right, left]),
+                (Some(_), Some(_)) => stack.extend([left, right]),
+                (Some(_), None) => stack.push(left),
+                (None, Some(_)) => stack.push(right),
+                (None, None) => (),
+            }
+        }
+    }
+}
+
+/// Builds the BVH nodes by recursively splitting triangles at the median centroid along the
+/// longest axis. The triangle indices are reordered so each leaf references a contiguous range
+/// and the children of a node are stored next to each other.
+fn build_nodes(triangles: &[[Vec3; 3]], triangle_indices: &mut [u32]) -> Vec<BvhNode> {
+    let bounds: Vec<(Vec3, Vec3)> = triangles
+        .iter()
+        .map(|[p0, p1, p2]| (p0.min(*p1).min(*p2), p0.max(*p1).max(*p2)))
+        .collect();
+    let centroids: Vec<Vec3> = triangles
+        .iter()
+        .map(|[p0, p1, p2]| (p0 + p1 + p2) / 3.0)
+        .collect();
+
+    let mut nodes = vec![empty_node()];
+
+    // Node index, first triangle and triangle count of nodes that still need to be built.
+    let mut stack = vec![(0, 0, triangle_indices.len())];
+
+    while let Some((node_index, first, count)) = stack.pop() {
+        let node_triangles = &mut triangle_indices[first..first + count];
+
+        let (aabb_min, aabb_max) =
+            node_triangles
+                .iter()
+                .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &i| {
+                    let (aabb_min, aabb_max) = bounds[i as usize];
+                    (min.min(aabb_min), max.max(aabb_max))
+                });
+
+        if count <= MAX_LEAF_TRIANGLES {
+            nodes[node_index] = BvhNode {
+                aabb_min,
+                aabb_max,
+                left_first: first as u32,
+                triangle_count: count as u32,
+            };
+            continue;
+        }
+
+        let (centroid_min, centroid_max) =
+            node_triangles
+                .iter()
+                .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &i| {
+                    let centroid = centroids[i as usize];
+                    (min.min(centroid), max.max(centroid))
+                });
+        let axis = (centroid_max - centroid_min).max_position();
+
+        let mid = count / 2;
+        node_triangles.select_nth_unstable_by(mid, |&a, &b| {
+            centroids[a as usize][axis].total_cmp(&centroids[b as usize][axis])
+        });
+
+        let left = nodes.len();
+        nodes.extend([empty_node(), empty_node()]);
+        nodes[node_index] = BvhNode {
+            aabb_min,
+            aabb_max,
+            left_first: left as u32,
+            triangle_count: 0,
+        };
+
+        stack.push((left, first, mid));
+        stack.push((left + 1, first + mid, count - mid));
+    }
+
+    nodes
+}
+
+/// Returns a placeholder node that is filled in once its triangles are known.
+fn empty_node() -> BvhNode {
+    BvhNode {
+        aabb_min: Vec3::ZERO,
+        aabb_max: Vec3::ZERO,
+        left_first: 0,
+        triangle_count: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generates the same sequence of numbers in [0, 1] on every run.
+    struct Xorshift(u32);
+
+    impl Xorshift {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f32 / u32::MAX as f32
+        }
+
+        fn next_vec3(&mut self, min: f32, max: f32) -> Vec3 {
+            Vec3::new(self.next(), self.next(), self.next()) * (max - min) + min
+        }
+    }
+
+    /// Returns small triangles scattered through a cube.
+    fn random_triangles(rng: &mut Xorshift, count: usize) -> Vec<[Vec3; 3]> {
+        (0..count)
+            .map(|_| {
+                let centre = rng.next_vec3(-10.0, 10.0);
+                [0, 1, 2].map(|_| centre + rng.next_vec3(-1.0, 1.0))
+            })
+            .collect()
+    }
+
+    /// Returns rays aimed at random points in the cube, including ones parallel to each axis.
+    fn random_rays(rng: &mut Xorshift, count: usize) -> Vec<Ray> {
+        let mut rays: Vec<Ray> = (0..count)
+            .map(|_| {
+                let origin = rng.next_vec3(-15.0, 15.0);
+                Ray::new(origin, rng.next_vec3(-10.0, 10.0) - origin)
+            })
+            .collect();
+
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            for _ in 0..count / 10 {
+                let origin = rng.next_vec3(-10.0, 10.0);
+                rays.push(Ray::new(origin, axis));
+                rays.push(Ray::new(origin, -axis));
+            }
+        }
+
+        rays
+    }
+
+    /// Returns the closest hit by testing every triangle.
+    fn brute_force_closest_hit(
+        triangles: &[[Vec3; 3]],
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        for (triangle, vertices) in triangles.iter().enumerate() {
+            let t_max = closest.map_or(t_max, |hit| hit.t);
+            if let Some(hit) = ray.intersect_triangle(vertices, t_min, t_max) {
+                closest = Some(Hit { triangle, ..hit });
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn hits_match_brute_force() {
+        let mut rng = Xorshift(0x2545_f491);
+        let triangles = random_triangles(&mut rng, 500);
+        let bvh = Bvh::new(triangles.clone());
+
+        let mut hit_count = 0;
+        for ray in random_rays(&mut rng, 1000) {
+            for (t_min, t_max) in [(0.0, f32::INFINITY), (0.5, 1.5)] {
+                let expected = brute_force_closest_hit(&triangles, &ray, t_min, t_max);
+                let actual = bvh.closest_hit(&ray, t_min, t_max);
+
+                match (expected, actual) {
+                    (Some(expected), Some(actual)) => {
+                        assert_eq!(expected.triangle, actual.triangle, "{ray:?}");
+                        assert_eq!(expected.t, actual.t, "{ray:?}");
+                        hit_count += 1;
+                    }
+                    (None, None) => (),
+                    _ => panic!("{ray:?}: expected {expected:?}, got {actual:?}"),
+                }
+
+                assert_eq!(
+                    expected.is_some(),
+                    bvh.any_hit(&ray, t_min, t_max),
+                    "{ray:?}"
+                );
+            }
+        }
+
+        // Make sure the rays aren't all missing.
+        assert!(hit_count > 100, "only {hit_count} hits");
+    }
+
+    #[test]
+    fn empty_bvh_is_never_hit() {
+        let bvh = Bvh::new(vec![]);
+        let ray = Ray::new(Vec3::ZERO, Vec3::X);
+        assert!(bvh.closest_hit(&ray, 0.0, f32::INFINITY).is_none());
+        assert!(!bvh.any_hit(&ray, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn mesh_triangles_are_indexed() {
+        let vertices = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::new(1.0, 1.0, 0.0)];
+        let bvh = Bvh::from_mesh(&vertices, &[0, 1, 2, 1, 3, 2]);
+
+        let ray = Ray::new(Vec3::new(0.75, 0.75, 1.0), Vec3::NEG_Z);
+        let hit = bvh.closest_hit(&ray, 0.0, f32::INFINITY).unwrap();
+        assert_eq!(hit.triangle, 1);
+        assert_eq!(hit.t, 1.0);
+    }
+}
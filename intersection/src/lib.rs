@@ -0,0 +1,9 @@
+//! # Intersection
+//!
+//! Ray intersection queries against triangle meshes on the CPU.
+
+mod bvh;
+mod ray;
+
+pub use bvh::*;
+pub use ray::*;
@@ -0,0 +1,225 @@
+use glam::{Vec2, Vec3};
+
+/// A ray with an origin and direction.
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    /// Origin of the ray.
+    pub origin: Vec3,
+
+    /// Direction of the ray. This does not need to be normalized but distances along the ray are
+    /// measured in multiples of its length.
+    pub direction: Vec3,
+}
+
+/// Intersection of a ray with a triangle.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    /// Distance along the ray.
+    pub t: f32,
+
+    /// Index of the triangle that was hit.
+    pub triangle: usize,
+
+    /// Barycentric coordinates of the hit point relative to the second and third vertices.
+    pub barycentrics: Vec2,
+
+    /// Geometric normal of the triangle that was hit. This is not normalized and faces whichever
+    /// way the triangle's winding order gives.
+    pub normal: Vec3,
+}
+
+impl Ray {
+    /// Create a new ray.
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    /// Returns the point at distance `t` along the ray.
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// Möller–Trumbore ray/triangle intersection. Returns the intersection if the triangle is hit
+    /// within (`t_min`, `t_max`). The triangle index of the hit is 0.
+    pub fn intersect_triangle(
+        &self,
+        [p0, p1, p2]: &[Vec3; 3],
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Hit> {
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+
+        let pvec = self.direction.cross(edge2);
+        let det = edge1.dot(pvec);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = self.origin - p0;
+        let u = tvec.dot(pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let v = self.direction.dot(qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_det;
+        (t > t_min && t < t_max).then(|| Hit {
+            t,
+            triangle: 0,
+            barycentrics: Vec2::new(u, v),
+            normal: edge1.cross(edge2),
+        })
+    }
+
+    /// Returns the distance along the ray to an axis aligned bounding box if it is hit within
+    /// (`t_min`, `t_max`).
+    pub fn intersect_aabb(
+        &self,
+        inv_direction: Vec3,
+        aabb_min: Vec3,
+        aabb_max: Vec3,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<f32> {
+        let t0 = (aabb_min - self.origin) * inv_direction;
+        let t1 = (aabb_max - self.origin) * inv_direction;
+
+        let t_enter = t0.min(t1).max_element().max(t_min);
+        let t_exit = t0.max(t1).min_element().min(t_max);
+
+        (t_enter <= t_exit).then_some(t_enter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit cube used by the bounding box tests.
+    const AABB: (Vec3, Vec3) = (Vec3::ZERO, Vec3::ONE);
+
+    fn intersect_aabb(ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        ray.intersect_aabb(ray.direction.recip(), AABB.0, AABB.1, t_min, t_max)
+    }
+
+    #[test]
+    fn axis_parallel_rays_hit_aabb() {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let ray = Ray::new(Vec3::splat(0.5) - axis, axis);
+            assert_eq!(
+                intersect_aabb(&ray, 0.0, f32::INFINITY),
+                Some(0.5),
+                "{ray:?}"
+            );
+
+            let ray = Ray::new(Vec3::splat(0.5) + axis, -axis);
+            assert_eq!(
+                intersect_aabb(&ray, 0.0, f32::INFINITY),
+                Some(0.5),
+                "{ray:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn axis_parallel_rays_outside_slab_miss_aabb() {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            // Offset the origin along another axis so the ray passes beside the box.
+            let offset = Vec3::new(axis.y + axis.z, axis.x, 0.0) * 2.0;
+            let ray = Ray::new(Vec3::splat(0.5) - axis + offset, axis);
+            assert_eq!(intersect_aabb(&ray, 0.0, f32::INFINITY), None, "{ray:?}");
+        }
+    }
+
+    #[test]
+    fn rays_starting_inside_aabb_hit_at_t_min() {
+        for direction in [Vec3::X, Vec3::NEG_Y, Vec3::new(1.0, 2.0, -3.0)] {
+            let ray = Ray::new(Vec3::splat(0.5), direction);
+            assert_eq!(
+                intersect_aabb(&ray, 0.0, f32::INFINITY),
+                Some(0.0),
+                "{ray:?}"
+            );
+            assert_eq!(
+                intersect_aabb(&ray, 0.1, f32::INFINITY),
+                Some(0.1),
+                "{ray:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn aabb_outside_interval_is_missed() {
+        let ray = Ray::new(Vec3::new(0.5, 0.5, -1.0), Vec3::Z);
+        assert_eq!(intersect_aabb(&ray, 0.0, 0.5), None);
+        assert_eq!(intersect_aabb(&ray, 2.5, f32::INFINITY), None);
+
+        let ray = Ray::new(Vec3::new(0.5, 0.5, -1.0), Vec3::NEG_Z);
+        assert_eq!(intersect_aabb(&ray, 0.0, f32::INFINITY), None);
+    }
+
+    #[test]
+    fn triangle_hit_has_barycentrics() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let ray = Ray::new(Vec3::new(0.25, 0.5, 2.0), Vec3::NEG_Z);
+
+        let hit = ray
+            .intersect_triangle(&triangle, 0.0, f32::INFINITY)
+            .unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.barycentrics, Vec2::new(0.25, 0.5));
+        assert_eq!(hit.normal, Vec3::Z);
+
+        assert!(ray.intersect_triangle(&triangle, 0.0, 2.0).is_none());
+        assert!(
+            ray.intersect_triangle(&triangle, 2.0, f32::INFINITY)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn triangle_is_missed_outside_edges() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        for origin in [[-0.1, 0.5], [0.5, -0.1], [0.6, 0.6]] {
+            let ray = Ray::new(Vec3::new(origin[0], origin[1], 1.0), Vec3::NEG_Z);
+            assert!(
+                ray.intersect_triangle(&triangle, 0.0, f32::INFINITY)
+                    .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn degenerate_cases_miss_triangle() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+
+        // Ray in the plane of the triangle.
+        let ray = Ray::new(Vec3::new(-1.0, 0.25, 0.0), Vec3::X);
+        assert!(
+            ray.intersect_triangle(&triangle, 0.0, f32::INFINITY)
+                .is_none()
+        );
+
+        // Ray parallel to the plane of the triangle.
+        let ray = Ray::new(Vec3::new(-1.0, 0.25, 1.0), Vec3::X);
+        assert!(
+            ray.intersect_triangle(&triangle, 0.0, f32::INFINITY)
+                .is_none()
+        );
+
+        // Triangle with collinear vertices.
+        let ray = Ray::new(Vec3::new(0.5, 0.0, 1.0), Vec3::NEG_Z);
+        let collinear = [Vec3::ZERO, Vec3::X, Vec3::X * 2.0];
+        assert!(
+            ray.intersect_triangle(&collinear, 0.0, f32::INFINITY)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::Random;
+
+/// Standard deviation of the Gaussian used to measure how clustered points are.
+const SIGMA: f32 = 1.5;
+
+/// Fraction of the pixels set in the initial binary pattern.
+const INITIAL_DENSITY: f32 = 0.1;
+
+/// Generates blue noise textures. Blue noise has no low frequencies so neighbouring values are
+/// evenly spread out, which makes errors from sampling or quantizing with it look like fine grain
+/// instead of blotches or banding.
+pub struct BlueNoise {}
+
+impl BlueNoise {
+    /// Returns a square blue noise texture that tiles seamlessly, generated with Ulichney's
+    /// void-and-cluster algorithm. The initial pattern is random so a seeded generator produces
+    /// the same texture every time.
+    /// See https://cv.ulichney.com/papers/1993-void-cluster.pdf.
+    ///
+    /// The values are in row major order. Each value is a unique rank `(r + 0.5) / size^2` so
+    /// the values are uniformly distributed in (0, 1).
+    ///
+    /// * `size` - Width and height of the texture.
+    pub fn void_and_cluster(size: usize) -> Vec<f32> {
+        let n = size * size;
+        if n == 0 {
+            return vec![];
+        }
+
+        let weights = get_weights(size);
+
+        // Start with random points and move points from the tightest cluster to the largest void
+        // until they are evenly spread out.
+        let minority_count = ((n as f32 * INITIAL_DENSITY) as usize).max(1);
+        let mut indices: Vec<usize> = (0..n).collect();
+        Random::permute(&mut indices);
+
+        let mut initial_pattern = vec![false; n];
+        let mut initial_energy = vec![0.0; n];
+        for &i in indices.iter().take(minority_count) {
+            initial_pattern[i] = true;
+            splat(&mut initial_energy, &weights, size, i, 1.0);
+        }
+
+        loop {
+            let cluster = find_extreme(&initial_pattern, &initial_energy, true, true);
+            initial_pattern[cluster] = false;
+            splat(&mut initial_energy, &weights, size, cluster, -1.0);
+
+            let void = find_extreme(&initial_pattern, &initial_energy, false, false);
+            initial_pattern[void] = true;
+            splat(&mut initial_energy, &weights, size, void, 1.0);
+
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0; n];
+
+        // Phase 1: rank the initial points by removing the tightest cluster each time.
+        let mut pattern = initial_pattern.clone();
+        let mut energy = initial_energy.clone();
+        for rank in (0..minority_count).rev() {
+            let cluster = find_extreme(&pattern, &energy, true, true);
+            pattern[cluster] = false;
+            splat(&mut energy, &weights, size, cluster, -1.0);
+            ranks[cluster] = rank;
+        }
+
+        // Phase 2: fill the largest void each time until half the pixels are set.
+        let mut pattern = initial_pattern;
+        let mut energy = initial_energy;
+        for rank in minority_count..n / 2 {
+            let void = find_extreme(&pattern, &energy, false, false);
+            pattern[void] = true;
+            splat(&mut energy, &weights, size, void, 1.0);
+            ranks[void] = rank;
+        }
+
+        // Phase 3: the unset pixels are now the minority, so fill the tightest cluster of them
+        // each time.
+        let mut energy = vec![0.0; n];
+        for i in (0..n).filter(|&i| !pattern[i]) {
+            splat(&mut energy, &weights, size, i, 1.0);
+        }
+        for rank in (n / 2).max(minority_count)..n {
+            let cluster = find_extreme(&pattern, &energy, false, true);
+            pattern[cluster] = true;
+            splat(&mut energy, &weights, size, cluster, -1.0);
+            ranks[cluster] = rank;
+        }
+
+        ranks
+            .into_iter()
+            .map(|rank| (rank as f32 + 0.5) / n as f32)
+            .collect()
+    }
+}
+
+/// Returns the Gaussian weight for every offset between two pixels. Offsets wrap around the
+/// texture so it tiles.
+fn get_weights(size: usize) -> Vec<f32> {
+    (0..size * size)
+        .map(|i| {
+            let (dx, dy) = (i % size, i / size);
+            let dx = dx.min(size - dx) as f32;
+            let dy = dy.min(size - dy) as f32;
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect()
+}
+
+/// Adds the energy of a point to every pixel scaled by `sign`.
+fn splat(energy: &mut [f32], weights: &[f32], size: usize, point: usize, sign: f32) {
+    let (px, py) = (point % size, point / size);
+    for y in 0..size {
+        let dy = (y + size - py) % size;
+        for x in 0..size {
+            let dx = (x + size - px) % size;
+            energy[y * size + x] += sign * weights[dy * size + dx];
+        }
+    }
+}
+
+/// Returns the pixel with the highest energy if `is_max` is set, or the lowest energy otherwise,
+/// among the pixels whose value in the pattern is `is_set`.
+fn find_extreme(pattern: &[bool], energy: &[f32], is_set: bool, is_max: bool) -> usize {
+    let candidates = (0..pattern.len()).filter(|&i| pattern[i] == is_set);
+    let compare = |a: &usize, b: &usize| energy[*a].total_cmp(&energy[*b]);
+    if is_max {
+        candidates.max_by(compare)
+    } else {
+        candidates.min_by(compare)
+    }
+    .unwrap()
+}
@@ -4,6 +4,12 @@
 
 #![allow(dead_code)]
 
+mod blue_noise;
+mod scatter;
+
+pub use blue_noise::*;
+pub use scatter::*;
+
 use glam::Vec3;
 use rand::distr::uniform::SampleUniform;
 use rand::distr::{Distribution, StandardUniform};
@@ -0,0 +1,182 @@
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+use crate::Random;
+
+/// A sphere that scattered objects must stay clear of, such as an object that was already placed.
+#[derive(Clone, Copy, Debug)]
+pub struct Exclusion {
+    /// Centre of the excluded region.
+    pub center: Vec3,
+
+    /// Radius of the excluded region.
+    pub radius: f32,
+}
+
+impl Exclusion {
+    /// Returns true if a sphere at `p` with the given radius overlaps the excluded region.
+    ///
+    /// * `p` - Centre of the sphere.
+    /// * `radius` - Radius of the sphere.
+    pub fn overlaps(&self, p: Vec3, radius: f32) -> bool {
+        self.center.distance(p) <= self.radius + radius
+    }
+}
+
+/// Placement of objects for scene generators. Points are generated using the thread local random
+/// number generator so a seeded generator produces the same scene every time.
+pub struct Scatter {}
+
+impl Scatter {
+    /// Returns points in the rectangle [`min`, `max`] that are at least `min_distance` apart using
+    /// Bridson's Poisson-disk sampling algorithm.
+    /// See https://www.cs.ubc.ca/~rbridson/docs/bridson-siggraph07-poissondisk.pdf.
+    ///
+    /// * `min` - Minimum corner of the rectangle.
+    /// * `max` - Maximum corner of the rectangle.
+    /// * `min_distance` - Minimum distance between points.
+    /// * `max_attempts` - Number of candidates tried around each point before giving up on it.
+    pub fn poisson_disk_plane(
+        min: Vec2,
+        max: Vec2,
+        min_distance: f32,
+        max_attempts: u32,
+    ) -> Vec<Vec2> {
+        let size = max - min;
+        if min_distance <= 0.0 || size.x <= 0.0 || size.y <= 0.0 {
+            return vec![];
+        }
+
+        // Each cell is small enough to hold at most one point.
+        let cell_size = min_distance / 2.0_f32.sqrt();
+        let columns = (size.x / cell_size).ceil() as usize;
+        let rows = (size.y / cell_size).ceil() as usize;
+        let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+
+        let get_cell = |p: Vec2| {
+            let c = ((p - min) / cell_size).floor();
+            (
+                (c.x as usize).min(columns - 1),
+                (c.y as usize).min(rows - 1),
+            )
+        };
+
+        let first = min + size * Vec2::new(Random::sample(), Random::sample());
+        let (column, row) = get_cell(first);
+        grid[row * columns + column] = Some(0);
+
+        let mut points = vec![first];
+        let mut active = vec![0];
+
+        while !active.is_empty() {
+            let active_index = Random::sample_in_range(0, active.len());
+            let p = points[active[active_index]];
+
+            let candidate = (0..max_attempts).find_map(|_| {
+                // Pick a point in the annulus between min_distance and twice that around p.
+                let angle = Random::sample_in_range(0.0, 2.0 * PI);
+                let distance = Random::sample_in_range(min_distance, 2.0 * min_distance);
+                let q = p + Vec2::from_angle(angle) * distance;
+                if q.cmplt(min).any() || q.cmpgt(max).any() {
+                    return None;
+                }
+
+                let (column, row) = get_cell(q);
+                let is_clear = (row.saturating_sub(2)..(row + 3).min(rows)).all(|r| {
+                    (column.saturating_sub(2)..(column + 3).min(columns)).all(|c| {
+                        grid[r * columns + c].is_none_or(|i| points[i].distance(q) >= min_distance)
+                    })
+                });
+                is_clear.then_some((q, row * columns + column))
+            });
+
+            match candidate {
+                Some((q, cell)) => {
+                    grid[cell] = Some(points.len());
+                    active.push(points.len());
+                    points.push(q);
+                }
+                None => {
+                    active.swap_remove(active_index);
+                }
+            }
+        }
+
+        points
+    }
+
+    /// Returns points on the surface of a sphere that are at least `min_distance` apart (measured
+    /// in a straight line). Candidates are picked uniformly over the surface and rejected if they
+    /// are too close to an accepted point. This stops once `max_attempts` candidates in a row are
+    /// rejected.
+    ///
+    /// * `center` - Centre of the sphere.
+    /// * `radius` - Radius of the sphere.
+    /// * `min_distance` - Minimum distance between points.
+    /// * `max_attempts` - Number of consecutive rejected candidates before stopping.
+    pub fn poisson_disk_sphere(
+        center: Vec3,
+        radius: f32,
+        min_distance: f32,
+        max_attempts: u32,
+    ) -> Vec<Vec3> {
+        let mut points: Vec<Vec3> = vec![];
+        if min_distance <= 0.0 || radius <= 0.0 {
+            return points;
+        }
+
+        let mut attempts = 0;
+        while attempts < max_attempts {
+            let p = center + Random::unit_vec3() * radius;
+            if points.iter().all(|q| q.distance(p) >= min_distance) {
+                points.push(p);
+                attempts = 0;
+            } else {
+                attempts += 1;
+            }
+        }
+
+        points
+    }
+
+    /// Returns one point per cell of a grid dividing the rectangle [`min`, `max`], offset randomly
+    /// from the cell's centre. The points are ordered row by row.
+    ///
+    /// * `min` - Minimum corner of the rectangle.
+    /// * `max` - Maximum corner of the rectangle.
+    /// * `cells` - Number of columns and rows.
+    /// * `jitter` - Fraction of the cell size points are moved by in [0, 1]. Use 0 for a regular
+    ///   grid and 1 to place points anywhere in their cell.
+    pub fn jittered_grid(min: Vec2, max: Vec2, cells: [u32; 2], jitter: f32) -> Vec<Vec2> {
+        let [columns, rows] = cells;
+        let cell_size = (max - min) / Vec2::new(columns as f32, rows as f32);
+        let jitter = jitter.clamp(0.0, 1.0);
+
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let cell_center =
+                    min + cell_size * Vec2::new(column as f32 + 0.5, row as f32 + 0.5);
+                let offset = Vec2::new(Random::sample::<f32>(), Random::sample::<f32>()) - 0.5;
+                cell_center + offset * jitter * cell_size
+            })
+            .collect()
+    }
+
+    /// Returns the points where a sphere with the given radius doesn't overlap any of the
+    /// excluded regions.
+    ///
+    /// * `points` - Centres of the spheres.
+    /// * `radius` - Radius of the spheres.
+    /// * `exclusions` - Regions the spheres must stay clear of.
+    pub fn exclude(
+        points: impl IntoIterator<Item = Vec3>,
+        radius: f32,
+        exclusions: &[Exclusion],
+    ) -> Vec<Vec3> {
+        points
+            .into_iter()
+            .filter(|&p| !exclusions.iter().any(|e| e.overlaps(p, radius)))
+            .collect()
+    }
+}
@@ -1,10 +1,16 @@
-use std::{collections::HashMap, iter, mem::size_of, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    mem::size_of,
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
-use log::{debug, warn};
+use log::{debug, info, warn};
+use scene_file::BuildPreference;
 use shaders::ray_gen::MeshVertex;
 use vulkano::{
-    Packed24_8,
+    DeviceSize, Packed24_8,
     acceleration_structure::{
         AccelerationStructure, AccelerationStructureBuildGeometryInfo,
         AccelerationStructureBuildRangeInfo, AccelerationStructureBuildType,
@@ -12,15 +18,20 @@ use vulkano::{
         AccelerationStructureGeometryInstancesData, AccelerationStructureGeometryInstancesDataType,
         AccelerationStructureGeometryTrianglesData, AccelerationStructureInstance,
         AccelerationStructureType, BuildAccelerationStructureFlags, BuildAccelerationStructureMode,
+        CopyAccelerationStructureInfo, CopyAccelerationStructureMode,
     },
     buffer::{Buffer, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer},
     command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract},
     format::Format,
     memory::allocator::{AllocationCreateInfo, DeviceLayout, MemoryTypeFilter},
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
     sync::GpuFuture,
 };
 
-use crate::{Mesh, MeshInstance, Vk};
+use crate::{CancellationToken, Mesh, MeshInstance, Transform, Vk};
+
+/// Largest scratch buffer shared by acceleration structure builds recorded into one command buffer.
+const MAX_SCRATCH_POOL_SIZE: DeviceSize = 256 * 1024 * 1024;
 
 /// Stores the acceleration structures.
 pub struct AccelerationStructures {
@@ -28,22 +39,36 @@ pub struct AccelerationStructures {
     pub tlas: Arc<AccelerationStructure>,
 
     /// The bottom-level acceleration structure is required to be kept alive even though renderer will not
-    /// directly use it. The top-level acceleration structure needs it.
+    /// directly use it. The top-level acceleration structure needs it. These are keyed by the name
+    /// of the primitive the geometry comes from so instances of it share one.
     blas_map: HashMap<String, Arc<AccelerationStructure>>,
+
+    /// Whether any mesh instance is animated. The top-level acceleration structure is only
+    /// updated if so.
+    is_animated: bool,
 }
 
 impl AccelerationStructures {
-    /// Create new acceleration structures for the given model.
+    /// Create new acceleration structures for the given model. If `compact` is set, the
+    /// bottom-level acceleration structures are copied into buffers of their compacted sizes once
+    /// built. Stops with a `Cancelled` error before the bottom-level acceleration structures are
+    /// built if `cancel` is cancelled.
     pub fn new(
         vk: Arc<Vk>,
         mesh_instances: &[MeshInstance],
         meshes: &[Arc<Mesh>],
         batch_ray_time: f32,
+        compact: bool,
+        cancel: &CancellationToken,
     ) -> Result<Self> {
         let mut mesh_map: HashMap<String, Arc<Mesh>> = HashMap::new();
+        let mut animated_meshes: HashSet<String> = HashSet::new();
         for mesh_instance in mesh_instances.iter() {
             let mesh = meshes[mesh_instance.mesh_index].clone();
-            let name = mesh.name.clone();
+            let name = mesh.geometry.clone();
+            if matches!(mesh_instance.object_to_world, Transform::Animated { .. }) {
+                animated_meshes.insert(name.clone());
+            }
             mesh_map.entry(name).or_insert_with(|| mesh);
         }
 
@@ -60,23 +85,50 @@ impl AccelerationStructures {
         }
 
         let mut blas_map: HashMap<String, Arc<AccelerationStructure>> = HashMap::new();
+        let mut blas_builds = Vec::with_capacity(vertex_buffers.len());
         for (name, vertex_buffer) in vertex_buffers.iter() {
+            cancel.check()?;
+
             let index_buffer = index_buffers
                 .get(name)
                 .with_context(|| format!("Index buffer {name} not found"))?;
 
-            let acc =
-                build_acceleration_structure_triangles(vk.clone(), vertex_buffer, index_buffer)?;
-            blas_map.insert(name.clone(), acc);
+            let mut flags = get_blas_build_flags(&mesh_map[name], animated_meshes.contains(name));
+            if compact {
+                flags |= BuildAccelerationStructureFlags::ALLOW_COMPACTION;
+            }
+            debug!("BLAS {name} build flags: {flags:?}");
+
+            let build = prepare_triangles_build(vk.clone(), vertex_buffer, index_buffer, flags)?;
+            blas_map.insert(name.clone(), build.get_acceleration_structure());
+            blas_builds.push(build);
+        }
+
+        // Build all the bottom-level acceleration structures at once instead of waiting for each
+        // one. The top-level acceleration structure is built afterwards since its instances only
+        // reference them by device address, so nothing would order the builds within one command
+        // buffer.
+        cancel.check()?;
+        unsafe { build_acceleration_structures(vk.clone(), blas_builds) }?;
+
+        if compact {
+            cancel.check()?;
+            compact_acceleration_structures(vk.clone(), &mut blas_map)?;
         }
 
         let as_instances = build_as_instances(mesh_instances, meshes, &blas_map, batch_ray_time)?;
 
         // Build the top-level acceleration structure.
-        let tlas =
-            unsafe { build_top_level_acceleration_structure(vk.clone(), as_instances, None) }?;
+        let is_animated = !animated_meshes.is_empty();
+        let tlas = unsafe {
+            build_top_level_acceleration_structure(vk.clone(), as_instances, is_animated, None)
+        }?;
 
-        Ok(Self { blas_map, tlas })
+        Ok(Self {
+            blas_map,
+            tlas,
+            is_animated,
+        })
     }
 
     /// Update acceleration structures for motion blur.
@@ -95,6 +147,11 @@ impl AccelerationStructures {
         meshes: &[Arc<Mesh>],
         batch_ray_time: f32,
     ) -> Result<()> {
+        // Nothing moves and the top-level acceleration structure doesn't allow updates.
+        if !self.is_animated {
+            return Ok(());
+        }
+
         let as_instances =
             build_as_instances(mesh_instances, meshes, &self.blas_map, batch_ray_time)?;
 
@@ -107,6 +164,7 @@ impl AccelerationStructures {
             build_top_level_acceleration_structure(
                 vk.clone(),
                 as_instances,
+                self.is_animated,
                 Some(self.tlas.clone()),
             )
         }?;
@@ -115,103 +173,212 @@ impl AccelerationStructures {
     }
 }
 
-fn get_as_build_flags(is_update_mode: bool) -> BuildAccelerationStructureFlags {
-    let mut build_as_flags = BuildAccelerationStructureFlags::ALLOW_UPDATE;
-    if is_update_mode {
-        // Update/refit mode for motion blur:
-        // Prioritize updating the acceleration structure over tracing ray since we will
-        // call updates.
-        build_as_flags |= BuildAccelerationStructureFlags::PREFER_FAST_BUILD;
+/// Returns the build flags for a mesh's bottom-level acceleration structure. The mesh's
+/// preferences are used where given. Otherwise it is built for fast tracing and allows updates if
+/// any of its instances are animated.
+fn get_blas_build_flags(mesh: &Mesh, is_animated: bool) -> BuildAccelerationStructureFlags {
+    let mut flags = match mesh.acceleration.preference.unwrap_or_default() {
+        BuildPreference::FastTrace => BuildAccelerationStructureFlags::PREFER_FAST_TRACE,
+        BuildPreference::FastBuild => BuildAccelerationStructureFlags::PREFER_FAST_BUILD,
+    };
+    if mesh.acceleration.allow_update.unwrap_or(is_animated) {
+        flags |= BuildAccelerationStructureFlags::ALLOW_UPDATE;
+    }
+    flags
+}
+
+/// Returns the build flags for the top-level acceleration structure. An update must use the same
+/// flags as the first build.
+fn get_tlas_build_flags(is_animated: bool) -> BuildAccelerationStructureFlags {
+    if is_animated {
+        // The instance transforms are refit for every sample batch for motion blur, so
+        // prioritize updating the acceleration structure over tracing rays.
+        BuildAccelerationStructureFlags::ALLOW_UPDATE
+            | BuildAccelerationStructureFlags::PREFER_FAST_BUILD
     } else {
-        // First full build:
-        // Prioritize fast tracing since building happens on first batch only.
-        build_as_flags |= BuildAccelerationStructureFlags::PREFER_FAST_TRACE;
+        // Prioritize fast tracing since it is only built once.
+        BuildAccelerationStructureFlags::PREFER_FAST_TRACE
     }
-    build_as_flags
 }
 
-/// A helper function to build a acceleration structure and wait for its completion.
-///
-/// # Safety
-///
-/// - If you are referencing a bottom-level acceleration structure in a top-level acceleration
-///   structure, you must ensure that the bottom-level acceleration structure is kept alive.
-fn build_acceleration_structure_common(
+/// An acceleration structure build whose commands haven't been recorded yet.
+struct AccelerationStructureBuild {
+    /// Geometry, mode and destination of the build. The scratch buffer is set when the build is
+    /// recorded.
+    info: AccelerationStructureBuildGeometryInfo,
+
+    /// Number of primitives in the geometry.
+    primitive_count: u32,
+
+    /// Size in bytes of the scratch buffer the build needs.
+    scratch_size: DeviceSize,
+}
+
+impl AccelerationStructureBuild {
+    /// Returns the acceleration structure the build writes to.
+    fn get_acceleration_structure(&self) -> Arc<AccelerationStructure> {
+        self.info
+            .dst_acceleration_structure
+            .clone()
+            .expect("Acceleration structure build has no destination")
+    }
+}
+
+/// Prepares the build of an acceleration structure. A new acceleration structure is created unless
+/// an old one is given to update in place.
+fn prepare_acceleration_structure_build(
     vk: Arc<Vk>,
     geometries: AccelerationStructureGeometries,
     primitive_count: u32,
     ty: AccelerationStructureType,
+    build_as_flags: BuildAccelerationStructureFlags,
     old_acceleration_structure: Option<Arc<AccelerationStructure>>,
-) -> Result<Arc<AccelerationStructure>> {
+) -> Result<AccelerationStructureBuild> {
     // Setup information for building the acceleration structure.
-    let is_update_mode = old_acceleration_structure.is_some();
-    let build_as_flags = get_as_build_flags(is_update_mode);
-
-    let mut as_build_geometry_info = if is_update_mode {
-        let old_acc = old_acceleration_structure.as_ref().unwrap().clone();
-        AccelerationStructureBuildGeometryInfo {
-            mode: BuildAccelerationStructureMode::Update(old_acc),
-            flags: build_as_flags,
-            ..AccelerationStructureBuildGeometryInfo::new(geometries)
-        }
-    } else {
-        AccelerationStructureBuildGeometryInfo {
-            mode: BuildAccelerationStructureMode::Build,
-            flags: build_as_flags,
-            ..AccelerationStructureBuildGeometryInfo::new(geometries)
-        }
+    let mut info = AccelerationStructureBuildGeometryInfo {
+        mode: match old_acceleration_structure.as_ref() {
+            Some(old_acc) => BuildAccelerationStructureMode::Update(old_acc.clone()),
+            None => BuildAccelerationStructureMode::Build,
+        },
+        flags: build_as_flags,
+        ..AccelerationStructureBuildGeometryInfo::new(geometries)
     };
 
     let as_build_sizes_info = vk.device.acceleration_structure_build_sizes(
         AccelerationStructureBuildType::Device,
-        &as_build_geometry_info,
+        &info,
         &[primitive_count],
     )?;
+    debug!(
+        "AS scratch buffer size: {}",
+        as_build_sizes_info.build_scratch_size
+    );
+
+    let acceleration = if let Some(old_acc) = old_acceleration_structure {
+        old_acc // Update
+    } else {
+        // Build
+        create_acceleration_structure(
+            vk.clone(),
+            ty,
+            as_build_sizes_info.acceleration_structure_size,
+        )?
+    };
+    info.dst_acceleration_structure = Some(acceleration);
+
+    Ok(AccelerationStructureBuild {
+        info,
+        primitive_count,
+        scratch_size: as_build_sizes_info.build_scratch_size,
+    })
+}
 
-    // Create a memory layout so the scratch buffer address is aligned correctly for the
-    // acceleration structure.
+/// Records the builds into as few command buffers as possible and waits for them to complete.
+/// Builds share one scratch buffer per command buffer, with each build using its own region so
+/// they don't depend on each other. A new command buffer is started once the scratch buffer would
+/// exceed `MAX_SCRATCH_POOL_SIZE`.
+///
+/// # Safety
+///
+/// - If you are referencing a bottom-level acceleration structure in a top-level acceleration
+///   structure, you must ensure that the bottom-level acceleration structure is kept alive and
+///   was built by an earlier call. Builds are only ordered by buffers vulkano can see, and the
+///   instances reference bottom-level acceleration structures by device address.
+unsafe fn build_acceleration_structures(
+    vk: Arc<Vk>,
+    builds: Vec<AccelerationStructureBuild>,
+) -> Result<()> {
+    // Scratch buffer addresses must be aligned for the acceleration structure.
     let device_properties = vk.device.physical_device().properties();
-    let min_scratch_offset = device_properties
+    let min_scratch_offset: DeviceSize = device_properties
         .min_acceleration_structure_scratch_offset_alignment
         .context(
             "Unable to get min_acceleration_structure_scratch_offset_alignment device property",
         )?
         .into();
+    debug!("AS min_acceleration_structure_scratch_offset_alignment: {min_scratch_offset}");
 
-    let scratch_buffer_size = as_build_sizes_info.build_scratch_size;
-
-    let scratch_buffer_layout =
-        DeviceLayout::from_size_alignment(scratch_buffer_size, min_scratch_offset)
-            .context("Unable to create scratch buffer device layout")?;
+    let mut builds = builds.into_iter().peekable();
+    while builds.peek().is_some() {
+        // Take builds until the scratch buffer is full. A build needing more scratch memory than
+        // the limit gets a scratch buffer of its own.
+        let mut batch = vec![];
+        let mut scratch_offsets = vec![];
+        let mut scratch_pool_size: DeviceSize = 0;
+        while let Some(build) = builds.next_if(|build| {
+            batch.is_empty()
+                || scratch_pool_size.next_multiple_of(min_scratch_offset) + build.scratch_size
+                    <= MAX_SCRATCH_POOL_SIZE
+        }) {
+            let offset = scratch_pool_size.next_multiple_of(min_scratch_offset);
+            scratch_pool_size = offset + build.scratch_size;
+            scratch_offsets.push(offset);
+            batch.push(build);
+        }
 
-    debug!("AS min_acceleration_structure_scratch_offset_alignment: {min_scratch_offset}");
-    debug!("AS scratch buffer size: {}", scratch_buffer_size);
-    debug!("AS scratch buffer layout: {:?}", scratch_buffer_layout);
+        let scratch_pool_layout =
+            DeviceLayout::from_size_alignment(scratch_pool_size, min_scratch_offset)
+                .context("Unable to create scratch buffer device layout")?;
 
-    // We create a new scratch buffer for each acceleration structure for simplicity. You may want
-    // to reuse scratch buffers if you need to build many acceleration structures.
-    let scratch_buffer = Subbuffer::new(Buffer::new(
-        vk.memory_allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::TRANSFER_SRC
-                | BufferUsage::SHADER_DEVICE_ADDRESS
-                | BufferUsage::STORAGE_BUFFER,
-            ..Default::default()
-        },
-        AllocationCreateInfo::default(),
-        scratch_buffer_layout,
-    )?);
+        debug!(
+            "Building {} acceleration structures with a {scratch_pool_size} byte scratch buffer",
+            batch.len()
+        );
 
-    let scratch_buffer_device_address: u64 = scratch_buffer.device_address().unwrap().into();
-    debug!(
-        "AS scratch buffer device addr: {scratch_buffer_device_address} is {}",
-        if scratch_buffer_device_address.is_multiple_of(min_scratch_offset) {
-            "aligned"
-        } else {
-            "NOT ALIGNED"
+        let scratch_pool = Subbuffer::new(Buffer::new(
+            vk.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC
+                    | BufferUsage::SHADER_DEVICE_ADDRESS
+                    | BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+            scratch_pool_layout,
+        )?);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vk.command_buffer_allocator.clone(),
+            vk.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        for (mut build, offset) in batch.into_iter().zip(scratch_offsets) {
+            build.info.scratch_data = Some(
+                scratch_pool
+                    .clone()
+                    .slice(offset..offset + build.scratch_size),
+            );
+
+            let as_build_range_info = AccelerationStructureBuildRangeInfo {
+                primitive_count: build.primitive_count,
+                ..Default::default()
+            };
+
+            unsafe {
+                builder.build_acceleration_structure(
+                    build.info,
+                    iter::once(as_build_range_info).collect(),
+                )?
+            };
         }
-    );
 
+        builder
+            .build()?
+            .execute(vk.queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+    }
+
+    Ok(())
+}
+
+/// Creates an acceleration structure backed by a new buffer of the given size in bytes.
+fn create_acceleration_structure(
+    vk: Arc<Vk>,
+    ty: AccelerationStructureType,
+    size: u64,
+) -> Result<Arc<AccelerationStructure>> {
     let as_create_info = AccelerationStructureCreateInfo {
         ty,
         ..AccelerationStructureCreateInfo::new(Buffer::new_slice::<u8>(
@@ -222,26 +389,39 @@ fn build_acceleration_structure_common(
                 ..Default::default()
             },
             AllocationCreateInfo::default(),
-            as_build_sizes_info.acceleration_structure_size,
+            size,
         )?)
     };
 
-    let acceleration = if let Some(old_acc) = old_acceleration_structure {
-        old_acc.clone() // Update
-    } else {
-        unsafe { AccelerationStructure::new(vk.device.clone(), as_create_info) }? // Build
-    };
+    Ok(unsafe { AccelerationStructure::new(vk.device.clone(), as_create_info) }?)
+}
 
-    as_build_geometry_info.dst_acceleration_structure = Some(acceleration.clone());
-    as_build_geometry_info.scratch_data = Some(scratch_buffer);
+/// Replaces the bottom-level acceleration structures with copies in buffers of their compacted
+/// sizes. The build size is a worst case estimate, so the compacted copies are often much smaller.
+/// They must have been built with `ALLOW_COMPACTION`.
+fn compact_acceleration_structures(
+    vk: Arc<Vk>,
+    blas_map: &mut HashMap<String, Arc<AccelerationStructure>>,
+) -> Result<()> {
+    if blas_map.is_empty() {
+        return Ok(());
+    }
 
-    let as_build_range_info = AccelerationStructureBuildRangeInfo {
-        primitive_count,
-        ..Default::default()
-    };
+    let (names, structures): (Vec<String>, Vec<Arc<AccelerationStructure>>) = blas_map
+        .iter()
+        .map(|(name, acc)| (name.clone(), acc.clone()))
+        .unzip();
+    let query_count = structures.len() as u32;
+
+    // Query the compacted sizes once the builds have completed.
+    let query_pool = QueryPool::new(
+        vk.device.clone(),
+        QueryPoolCreateInfo {
+            query_count,
+            ..QueryPoolCreateInfo::query_type(QueryType::AccelerationStructureCompactedSize)
+        },
+    )?;
 
-    // For simplicity, we build a single command buffer that builds the acceleration structure,
-    // then waits for its execution to complete.
     let mut builder = AutoCommandBufferBuilder::primary(
         vk.command_buffer_allocator.clone(),
         vk.queue.queue_family_index(),
@@ -249,11 +429,13 @@ fn build_acceleration_structure_common(
     )?;
 
     unsafe {
-        builder.build_acceleration_structure(
-            as_build_geometry_info,
-            iter::once(as_build_range_info).collect(),
-        )?
-    };
+        builder.reset_query_pool(query_pool.clone(), 0..query_count)?;
+        builder.write_acceleration_structures_properties(
+            structures.iter().cloned().collect(),
+            query_pool.clone(),
+            0,
+        )?;
+    }
 
     builder
         .build()?
@@ -261,15 +443,61 @@ fn build_acceleration_structure_common(
         .then_signal_fence_and_flush()?
         .wait(None)?;
 
-    Ok(acceleration)
+    let mut compacted_sizes = vec![0_u64; structures.len()];
+    query_pool.get_results(0..query_count, &mut compacted_sizes, QueryResultFlags::WAIT)?;
+
+    // Copy each acceleration structure into one of its compacted size.
+    let mut builder = AutoCommandBufferBuilder::primary(
+        vk.command_buffer_allocator.clone(),
+        vk.queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+
+    let mut compacted = Vec::with_capacity(structures.len());
+    for (acc, &size) in structures.iter().zip(compacted_sizes.iter()) {
+        let compacted_acc = create_acceleration_structure(
+            vk.clone(),
+            AccelerationStructureType::BottomLevel,
+            size,
+        )?;
+
+        unsafe {
+            builder.copy_acceleration_structure(CopyAccelerationStructureInfo {
+                mode: CopyAccelerationStructureMode::Compact,
+                ..CopyAccelerationStructureInfo::new(acc.clone(), compacted_acc.clone())
+            })?
+        };
+
+        compacted.push(compacted_acc);
+    }
+
+    builder
+        .build()?
+        .execute(vk.queue.clone())?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+
+    let original_size: u64 = structures.iter().map(|acc| acc.buffer().size()).sum();
+    let compacted_size: u64 = compacted_sizes.iter().sum();
+    info!(
+        "Compacted {} bottom-level acceleration structures from {original_size} to \
+        {compacted_size} bytes",
+        structures.len()
+    );
+
+    // The original acceleration structures are freed once dropped here.
+    blas_map.extend(names.into_iter().zip(compacted));
+
+    Ok(())
 }
 
-/// Builds a bottom level accerlation strucuture for a set of triangles.
-fn build_acceleration_structure_triangles(
+/// Prepares the build of a bottom level accerlation strucuture for a set of triangles.
+fn prepare_triangles_build(
     vk: Arc<Vk>,
     vertex_buffer: &Subbuffer<[MeshVertex]>,
     index_buffer: &Subbuffer<[u32]>,
-) -> Result<Arc<AccelerationStructure>> {
+    build_as_flags: BuildAccelerationStructureFlags,
+) -> Result<AccelerationStructureBuild> {
     let primitive_count = (index_buffer.len() / 3) as u32;
 
     // NOTE: Unfortunately the clone of vertex_buffer/index_buffer is unavoidable because of
@@ -284,11 +512,12 @@ fn build_acceleration_structure_triangles(
 
     let geometries = AccelerationStructureGeometries::Triangles(vec![as_geometry_triangles_data]);
 
-    build_acceleration_structure_common(
+    prepare_acceleration_structure_build(
         vk,
         geometries,
         primitive_count,
         AccelerationStructureType::BottomLevel,
+        build_as_flags,
         None,
     )
 }
@@ -302,6 +531,7 @@ fn build_acceleration_structure_triangles(
 unsafe fn build_top_level_acceleration_structure(
     vk: Arc<Vk>,
     as_instances: Vec<AccelerationStructureInstance>,
+    is_animated: bool,
     old_acceleration_structure: Option<Arc<AccelerationStructure>>,
 ) -> Result<Arc<AccelerationStructure>> {
     let primitive_count = as_instances.len() as u32;
@@ -327,13 +557,19 @@ unsafe fn build_top_level_acceleration_structure(
 
     let geometries = AccelerationStructureGeometries::Instances(as_geometry_instances_data);
 
-    build_acceleration_structure_common(
-        vk,
+    let build = prepare_acceleration_structure_build(
+        vk.clone(),
         geometries,
         primitive_count,
         AccelerationStructureType::TopLevel,
+        get_tlas_build_flags(is_animated),
         old_acceleration_structure,
-    )
+    )?;
+    let acceleration = build.get_acceleration_structure();
+
+    unsafe { build_acceleration_structures(vk, vec![build]) }?;
+
+    Ok(acceleration)
 }
 
 fn build_as_instances(
@@ -352,9 +588,11 @@ fn build_as_instances(
 
         // Ideally we should use this to point to materials directly. For now, just use it to
         // point to the mesh index we should be using to extract material data in the shader.
-        let instance_custom_index_and_mask = Packed24_8::new(mesh_index as u32, 0xFF);
+        // The mask selects the render layer so layers can be culled by the ray's cull mask.
+        let instance_custom_index_and_mask =
+            Packed24_8::new(mesh_index as u32, meshes[mesh_index].mask as u8);
 
-        let name = meshes[mesh_index].name.clone();
+        let name = meshes[mesh_index].geometry.clone();
         let blas = blas_map
             .get(&name)
             .with_context(|| format!("BLAS not found {name}"))?;
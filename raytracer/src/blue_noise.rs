@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use image::RgbaImage;
+
+/// Tiling blue noise texture generated by `tools gen-blue-noise`. Each channel is an independent
+/// blue noise pattern.
+const BLUE_NOISE_PNG: &[u8] = include_bytes!("../../assets/blue-noise.png");
+
+/// Channel of the blue noise texture used for dithering 8-bit output. The first two channels are
+/// used for pixel jitter by the shaders.
+pub const BLUE_NOISE_DITHER_CHANNEL: usize = 2;
+
+/// Returns the blue noise texture.
+pub fn load_blue_noise() -> Result<RgbaImage> {
+    let image = image::load_from_memory(BLUE_NOISE_PNG)
+        .context("Unable to decode the blue noise texture")?;
+    Ok(image.to_rgba8())
+}
+
+/// Returns the offset in [-0.5, 0.5) added to a value in 8-bit units before it is rounded, so
+/// smooth gradients don't show banding.
+///
+/// * `blue_noise` - The blue noise texture. It is tiled over the image.
+/// * `x` - Pixel column.
+/// * `y` - Pixel row.
+pub fn get_dither_offset(blue_noise: &RgbaImage, x: u32, y: u32) -> f32 {
+    let (width, height) = blue_noise.dimensions();
+    let value = blue_noise.get_pixel(x % width, y % height)[BLUE_NOISE_DITHER_CHANNEL];
+    (value as f32 + 0.5) / 256.0 - 0.5
+}
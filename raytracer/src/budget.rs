@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io::Cursor,
+    mem::size_of,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use image::ImageReader;
+use log::warn;
+use scene_file::{ImageData, Primitive, Quality, SceneFile, Texture};
+use shaders::ray_gen;
+
+use crate::{CancellationToken, Cancelled};
+
+/// Largest number of times sphere tessellation and image texture resolution are halved to fit a
+/// scene into its memory budget.
+const MAX_DETAIL_REDUCTION: u32 = 4;
+
+/// Rough size of a bottom level acceleration structure per triangle. The actual size depends on
+/// the driver, so this errs on the large side.
+const BLAS_BYTES_PER_TRIANGLE: u64 = 64;
+
+/// Number of the largest meshes and image textures listed when a scene exceeds its budget.
+const REPORT_ENTRY_COUNT: usize = 5;
+
+/// Number of bytes in a megabyte.
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Level of detail a scene is built at to fit into its memory budget.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DetailReduction {
+    /// Number of times the rings and segments of UV spheres are halved.
+    pub tessellation: u32,
+
+    /// Largest width or height of image textures, or `None` if they are uploaded at full
+    /// resolution.
+    pub max_texture_size: Option<u32>,
+}
+
+/// Returns the level of detail the scene fits into its memory budget at. This is the scene's own
+/// level of detail unless it exceeds the budget and `reduce_detail` is set, in which case sphere
+/// tessellation and image texture resolution are halved until it fits. Fails with a report of the
+/// meshes and textures using the most memory if the scene doesn't fit.
+///
+/// OBJ meshes are read an extra time to count their triangles when there is a memory budget.
+pub fn fit_memory_budget(scene_file: &SceneFile) -> Result<DetailReduction> {
+    let quality = scene_file.render.quality;
+    let mut detail = DetailReduction {
+        tessellation: 0,
+        max_texture_size: quality.get_max_texture_size(),
+    };
+    let Some(budget) = scene_file.render.budget else {
+        return Ok(detail);
+    };
+    let Some(max_memory_mb) = budget.max_memory_mb else {
+        return Ok(detail);
+    };
+
+    let textures = get_image_texture_sizes(scene_file);
+    let largest_texture_size = textures
+        .iter()
+        .map(|texture| texture.width.max(texture.height))
+        .max()
+        .unwrap_or(0);
+    let max_reduction = if budget.reduce_detail {
+        MAX_DETAIL_REDUCTION
+    } else {
+        0
+    };
+
+    let mut mesh_sizes = HashMap::new();
+    loop {
+        let estimate = MemoryEstimate::new(scene_file, &textures, detail, &mut mesh_sizes)?;
+        let total_bytes = estimate.get_total_bytes();
+        if total_bytes <= max_memory_mb as u64 * BYTES_PER_MB {
+            if detail.tessellation > 0 {
+                warn!(
+                    "Halved sphere tessellation {} times and limited image textures to {} pixels \
+                    to fit the scene into its budget of {max_memory_mb} MB. It needs an \
+                    estimated {} MB.",
+                    detail.tessellation,
+                    detail.max_texture_size.unwrap_or(largest_texture_size),
+                    total_bytes / BYTES_PER_MB,
+                );
+            }
+            return Ok(detail);
+        }
+
+        if detail.tessellation == max_reduction {
+            return Err(anyhow!(
+                estimate.get_report(max_memory_mb, budget.reduce_detail)
+            ));
+        }
+
+        detail.tessellation += 1;
+        let max_texture_size = (largest_texture_size >> detail.tessellation).max(1);
+        detail.max_texture_size = Some(
+            quality
+                .get_max_texture_size()
+                .map_or(max_texture_size, |size| size.min(max_texture_size)),
+        );
+    }
+}
+
+/// Limits how long building a scene may take to the scene's build time budget.
+pub struct BuildDeadline {
+    /// Token that is also cancelled once the build time budget has passed.
+    cancel: CancellationToken,
+
+    /// Token cancelled when the user stops building the scene.
+    user_cancel: CancellationToken,
+
+    /// Longest time in seconds the build may take, if there is a limit.
+    max_build_seconds: Option<f32>,
+}
+
+impl BuildDeadline {
+    /// Starts timing the build of the scene. `cancel` stops the build as before.
+    pub fn new(scene_file: &SceneFile, cancel: &CancellationToken) -> Result<Self> {
+        let max_build_seconds = scene_file
+            .render
+            .budget
+            .and_then(|budget| budget.max_build_seconds);
+
+        let deadline_cancel = match max_build_seconds {
+            Some(seconds) => {
+                let duration = Duration::try_from_secs_f32(seconds)
+                    .ok()
+                    .filter(|duration| !duration.is_zero())
+                    .with_context(|| {
+                        format!("Budget max_build_seconds must be positive, not {seconds}")
+                    })?;
+                cancel.with_deadline(Instant::now() + duration)
+            }
+            None => cancel.clone(),
+        };
+
+        Ok(Self {
+            cancel: deadline_cancel,
+            user_cancel: cancel.clone(),
+            max_build_seconds,
+        })
+    }
+
+    /// Returns the token to check while building, which is cancelled once the deadline passes.
+    pub fn get_cancel(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Replaces the `Cancelled` error of a build that ran out of time with a report including how
+    /// far it got. Other errors, including cancellation by the user, are returned unchanged.
+    pub fn explain(&self, e: Error, progress: impl FnOnce() -> String) -> Error {
+        match self.max_build_seconds {
+            Some(seconds)
+                if e.is::<Cancelled>()
+                    && self.cancel.is_expired()
+                    && !self.user_cancel.is_cancelled() =>
+            {
+                anyhow!(
+                    "Building the scene took longer than its budget of {seconds} s. {} Use fewer \
+                    or simpler meshes, or set max_memory_mb and reduce_detail in the budget to \
+                    build it at a lower level of detail.",
+                    progress()
+                )
+            }
+            _ => e,
+        }
+    }
+}
+
+/// Size of an image texture before it is downscaled.
+struct ImageTextureSize {
+    /// Name of the texture.
+    name: String,
+
+    /// Width in pixels.
+    width: u32,
+
+    /// Height in pixels.
+    height: u32,
+}
+
+impl ImageTextureSize {
+    /// Returns the number of bytes of the texture's RGBA pixels once downscaled to the given
+    /// largest width or height, including the mip levels, which add about a third.
+    fn get_bytes(&self, max_size: Option<u32>) -> u64 {
+        let (width, height) = match max_size {
+            Some(max_size) if self.width.max(self.height) > max_size => {
+                let scale = max_size as f32 / self.width.max(self.height) as f32;
+                (
+                    ((self.width as f32 * scale) as u64).max(1),
+                    ((self.height as f32 * scale) as u64).max(1),
+                )
+            }
+            _ => (self.width as u64, self.height as u64),
+        };
+        width * height * 4 * 4 / 3
+    }
+}
+
+/// Number of vertices and triangles of a mesh.
+#[derive(Clone, Copy)]
+struct MeshSize {
+    /// Number of vertices.
+    vertices: u64,
+
+    /// Number of triangles.
+    triangles: u64,
+}
+
+/// Estimated GPU memory used by a scene at a level of detail.
+struct MemoryEstimate {
+    /// Name, triangle count and bytes of each primitive's mesh.
+    meshes: Vec<(String, u64, u64)>,
+
+    /// Name, size and bytes of each image texture.
+    textures: Vec<(String, [u32; 2], u64)>,
+}
+
+impl MemoryEstimate {
+    /// Estimates the memory used by the scene's meshes, acceleration structures and image
+    /// textures at the given level of detail. The sizes of meshes that don't depend on the level
+    /// of detail are kept in `mesh_sizes` so they are only generated once.
+    fn new(
+        scene_file: &SceneFile,
+        textures: &[ImageTextureSize],
+        detail: DetailReduction,
+        mesh_sizes: &mut HashMap<String, MeshSize>,
+    ) -> Result<Self> {
+        let vertex_bytes = size_of::<ray_gen::MeshVertex>() as u64;
+
+        let mut meshes = Vec::new();
+        for primitive in scene_file.primitives.iter() {
+            let (geometry, _) = primitive.get_geometry(&scene_file.primitives)?;
+            let size = get_mesh_size(
+                geometry,
+                scene_file.render.quality,
+                detail.tessellation,
+                mesh_sizes,
+            )?;
+
+            // Vertices and indices are uploaded once for shading and once for building the
+            // acceleration structure. Instances share the acceleration structure of the primitive
+            // they reference.
+            let mut bytes = 2 * (size.vertices * vertex_bytes + size.triangles * 3 * 4);
+            if !matches!(primitive, Primitive::Instance { .. }) {
+                bytes += size.triangles * BLAS_BYTES_PER_TRIANGLE;
+            }
+            meshes.push((primitive.get_name().to_string(), size.triangles, bytes));
+        }
+
+        let textures = textures
+            .iter()
+            .map(|texture| {
+                (
+                    texture.name.clone(),
+                    [texture.width, texture.height],
+                    texture.get_bytes(detail.max_texture_size),
+                )
+            })
+            .collect();
+
+        Ok(Self { meshes, textures })
+    }
+
+    /// Returns the number of bytes used by meshes and acceleration structures.
+    fn get_mesh_bytes(&self) -> u64 {
+        self.meshes.iter().map(|(_, _, bytes)| bytes).sum()
+    }
+
+    /// Returns the number of bytes used by image textures.
+    fn get_texture_bytes(&self) -> u64 {
+        self.textures.iter().map(|(_, _, bytes)| bytes).sum()
+    }
+
+    /// Returns the total number of bytes.
+    fn get_total_bytes(&self) -> u64 {
+        self.get_mesh_bytes() + self.get_texture_bytes()
+    }
+
+    /// Returns a report explaining that the scene exceeds its memory budget, listing the meshes
+    /// and textures that use the most memory.
+    fn get_report(&self, max_memory_mb: u32, is_reduced: bool) -> String {
+        let triangles: u64 = self.meshes.iter().map(|(_, triangles, _)| triangles).sum();
+
+        let mut report = format!(
+            "The scene needs an estimated {} MB of GPU memory{}, more than its budget of \
+            {max_memory_mb} MB.",
+            self.get_total_bytes() / BYTES_PER_MB,
+            if is_reduced {
+                " at the lowest level of detail"
+            } else {
+                ""
+            },
+        );
+        let _ = write!(
+            report,
+            "\n  Meshes: {} MB for {triangles} triangles\n  Image textures: {} MB",
+            self.get_mesh_bytes() / BYTES_PER_MB,
+            self.get_texture_bytes() / BYTES_PER_MB,
+        );
+
+        let mut meshes: Vec<_> = self.meshes.iter().collect();
+        meshes.sort_by_key(|(_, _, bytes)| std::cmp::Reverse(*bytes));
+        if !meshes.is_empty() {
+            report.push_str("\nLargest meshes:");
+        }
+        for (name, triangles, bytes) in meshes.into_iter().take(REPORT_ENTRY_COUNT) {
+            let _ = write!(
+                report,
+                "\n  {name}: {triangles} triangles, {} MB",
+                bytes / BYTES_PER_MB
+            );
+        }
+
+        let mut textures: Vec<_> = self.textures.iter().collect();
+        textures.sort_by_key(|(_, _, bytes)| std::cmp::Reverse(*bytes));
+        if !textures.is_empty() {
+            report.push_str("\nLargest image textures:");
+        }
+        for (name, [width, height], bytes) in textures.into_iter().take(REPORT_ENTRY_COUNT) {
+            let _ = write!(
+                report,
+                "\n  {name}: {width} x {height}, {} MB",
+                bytes / BYTES_PER_MB
+            );
+        }
+
+        if is_reduced {
+            report.push_str("\nUse fewer or smaller meshes and textures, or raise the budget.");
+        } else {
+            report.push_str(
+                "\nSet reduce_detail in the budget to lower sphere tessellation and texture \
+                resolution until the scene fits, or raise the budget.",
+            );
+        }
+        report
+    }
+}
+
+/// Returns the number of vertices and triangles of a primitive's mesh at the given level of
+/// detail. Only UV spheres depend on it, so the sizes of other meshes are cached in `mesh_sizes`.
+fn get_mesh_size(
+    geometry: &Primitive,
+    quality: Quality,
+    tessellation: u32,
+    mesh_sizes: &mut HashMap<String, MeshSize>,
+) -> Result<MeshSize> {
+    let is_reducible = matches!(geometry, Primitive::UvSphere { .. });
+    if !is_reducible && let Some(size) = mesh_sizes.get(geometry.get_name()) {
+        return Ok(*size);
+    }
+
+    let (vertices, indices) = geometry
+        .with_reduced_tessellation(tessellation)
+        .generate_mesh(quality)?;
+    let size = MeshSize {
+        vertices: vertices.len() as u64,
+        triangles: indices.len() as u64 / 3,
+    };
+    if !is_reducible {
+        mesh_sizes.insert(geometry.get_name().to_string(), size);
+    }
+    Ok(size)
+}
+
+/// Returns the sizes of the scene's image textures, read from the headers of image files. Images
+/// that can't be read are skipped since they are replaced by a small placeholder.
+fn get_image_texture_sizes(scene_file: &SceneFile) -> Vec<ImageTextureSize> {
+    scene_file
+        .get_textures()
+        .into_iter()
+        .filter_map(|(name, texture)| {
+            let (width, height) = match texture {
+                Texture::Image { path, .. } => image::image_dimensions(path).ok()?,
+                Texture::ImageBytes {
+                    data: ImageData::Encoded(bytes),
+                    ..
+                } => ImageReader::new(Cursor::new(bytes))
+                    .with_guessed_format()
+                    .ok()?
+                    .into_dimensions()
+                    .ok()?,
+                Texture::ImageBytes {
+                    data: ImageData::Rgba8 { width, height, .. },
+                    ..
+                } => (width, height),
+                _ => return None,
+            };
+            Some(ImageTextureSize {
+                name,
+                width,
+                height,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use glam::{Mat4, Vec3, Vec4};
+use shaders::bvh_trace::{BvhInstance, BvhNode, BvhTriangle};
+use vulkano::buffer::{BufferUsage, Subbuffer};
+
+use crate::{Mesh, MeshInstance, Transform, Vk, create_device_local_buffer};
+
+/// Bounding volume hierarchy over the world space triangles of all mesh instances. This is built
+/// on the CPU with the same BVH used for CPU ray queries and traversed by a compute shader on
+/// devices that don't support raytracing pipelines.
+pub struct Bvh {
+    /// The nodes. The first node is the root.
+    pub nodes: Subbuffer<[BvhNode]>,
+
+    /// The triangles referenced by leaf nodes.
+    pub triangles: Subbuffer<[BvhTriangle]>,
+
+    /// The transforms of each mesh instance.
+    pub instances: Subbuffer<[BvhInstance]>,
+
+    /// Whether any mesh instance is animated. The BVH needs to be rebuilt for each ray time if so.
+    is_animated: bool,
+
+    /// The ray time the BVH was built for.
+    batch_ray_time: f32,
+}
+
+impl Bvh {
+    /// Build a new BVH for the mesh instances transformed at the given ray time.
+    pub fn new(
+        vk: Arc<Vk>,
+        mesh_instances: &[MeshInstance],
+        meshes: &[Arc<Mesh>],
+        batch_ray_time: f32,
+    ) -> Result<Self> {
+        let instances: Vec<BvhInstance> = mesh_instances
+            .iter()
+            .map(|mesh_instance| {
+                let mask = meshes[mesh_instance.mesh_index].mask;
+                get_bvh_instance(mesh_instance, mask, batch_ray_time)
+            })
+            .collect();
+
+        let mut triangles = get_bvh_triangles(mesh_instances, meshes, &instances);
+
+        // Storage buffers can't be empty. A degenerate triangle is never intersected.
+        if triangles.is_empty() {
+            triangles.push(BvhTriangle {
+                p0: [0.0; 3],
+                meshId: 0,
+                p1: [0.0; 3],
+                primitiveId: 0,
+                p2: [0.0; 3],
+                instanceIndex: 0,
+            });
+        }
+
+        let bvh = intersection::Bvh::new(
+            triangles
+                .iter()
+                .map(|t| [t.p0, t.p1, t.p2].map(Vec3::from))
+                .collect(),
+        );
+        let nodes: Vec<BvhNode> = bvh
+            .nodes
+            .iter()
+            .map(|node| BvhNode {
+                aabbMin: node.aabb_min.to_array(),
+                leftFirst: node.left_first,
+                aabbMax: node.aabb_max.to_array(),
+                triangleCount: node.triangle_count,
+            })
+            .collect();
+
+        // Leaves reference contiguous ranges of triangles so store them in BVH order.
+        let triangles: Vec<BvhTriangle> = bvh
+            .triangle_indices
+            .iter()
+            .map(|&i| triangles[i as usize])
+            .collect();
+
+        let instances = if instances.is_empty() {
+            vec![get_bvh_instance_from_matrix(Mat4::IDENTITY, 0)]
+        } else {
+            instances
+        };
+
+        Ok(Self {
+            nodes: create_device_local_buffer(vk.clone(), BufferUsage::STORAGE_BUFFER, nodes)?,
+            triangles: create_device_local_buffer(
+                vk.clone(),
+                BufferUsage::STORAGE_BUFFER,
+                triangles,
+            )?,
+            instances: create_device_local_buffer(vk, BufferUsage::STORAGE_BUFFER, instances)?,
+            is_animated: mesh_instances.iter().any(|mesh_instance| {
+                matches!(mesh_instance.object_to_world, Transform::Animated { .. })
+            }),
+            batch_ray_time,
+        })
+    }
+
+    /// Update the BVH for motion blur. Unlike acceleration structures, the BVH isn't refit in place
+    /// so it is rebuilt if any mesh instances are animated and the ray time changed.
+    pub fn update(
+        &mut self,
+        vk: Arc<Vk>,
+        mesh_instances: &[MeshInstance],
+        meshes: &[Arc<Mesh>],
+        batch_ray_time: f32,
+    ) -> Result<()> {
+        if self.is_animated && self.batch_ray_time != batch_ray_time {
+            *self = Self::new(vk, mesh_instances, meshes, batch_ray_time)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the transforms of a mesh instance at the given ray time.
+fn get_bvh_instance(mesh_instance: &MeshInstance, mask: u32, batch_ray_time: f32) -> BvhInstance {
+    let [r0, r1, r2] = mesh_instance.get_vulkan_acc_transform(batch_ray_time);
+    let object_to_world = Mat4::from_cols(
+        Vec4::new(r0[0], r1[0], r2[0], 0.0),
+        Vec4::new(r0[1], r1[1], r2[1], 0.0),
+        Vec4::new(r0[2], r1[2], r2[2], 0.0),
+        Vec4::new(r0[3], r1[3], r2[3], 1.0),
+    );
+    get_bvh_instance_from_matrix(object_to_world, mask)
+}
+
+/// Returns the transforms for an object-to-world matrix and the instance mask.
+fn get_bvh_instance_from_matrix(object_to_world: Mat4, mask: u32) -> BvhInstance {
+    // The shaders use 4x3 matrices so drop the last row.
+    let to_mat4x3 = |m: Mat4| m.to_cols_array_2d().map(|c| [c[0], c[1], c[2]]);
+
+    BvhInstance {
+        objectToWorld: to_mat4x3(object_to_world),
+        worldToObject: to_mat4x3(object_to_world.inverse()),
+        mask,
+    }
+}
+
+/// Returns the world space triangles of all mesh instances.
+fn get_bvh_triangles(
+    mesh_instances: &[MeshInstance],
+    meshes: &[Arc<Mesh>],
+    instances: &[BvhInstance],
+) -> Vec<BvhTriangle> {
+    let mut triangles = Vec::new();
+
+    for (instance_index, (mesh_instance, instance)) in
+        mesh_instances.iter().zip(instances).enumerate()
+    {
+        // Instances with an empty mask are never hit so they are left out.
+        if instance.mask == 0 {
+            continue;
+        }
+
+        let mesh = &meshes[mesh_instance.mesh_index];
+        let [c0, c1, c2, c3] = instance.objectToWorld;
+        let to_world = |i: u32| {
+            let [x, y, z] = mesh.vertices[i as usize].p;
+            let c0 = Vec3::from(c0) * x;
+            let c1 = Vec3::from(c1) * y;
+            let c2 = Vec3::from(c2) * z;
+            (c0 + c1 + c2 + Vec3::from(c3)).to_array()
+        };
+
+        for (primitive_id, indices) in mesh.indices.chunks_exact(3).enumerate() {
+            triangles.push(BvhTriangle {
+                p0: to_world(indices[0]),
+                meshId: mesh_instance.mesh_index as u32,
+                p1: to_world(indices[1]),
+                primitiveId: primitive_id as u32,
+                p2: to_world(indices[2]),
+                instanceIndex: instance_index as u32,
+            });
+        }
+    }
+
+    triangles
+}
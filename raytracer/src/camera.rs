@@ -1,12 +1,37 @@
 use std::sync::{Arc, RwLock};
 
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat4, Quat, Vec3};
+use log::warn;
+use scene_file::{Exposure, Orientation};
 
 /// Camera interface.
 pub trait Camera {
     /// Update the rendered image size.
     fn update_image_size(&mut self, image_width: u32, image_height: u32);
 
+    /// Moves the camera relative to its orientation. The offset is given as (right, up, forward)
+    /// in units of the distance between the eye and the look at point.
+    fn move_relative(&mut self, offset: Vec3);
+
+    /// Rolls the camera about its view direction by the given angle in radians.
+    fn roll(&mut self, angle: f32);
+
+    /// Orbits the camera about its look at point by the given angles in radians about its up and
+    /// right axes.
+    fn orbit(&mut self, yaw: f32, pitch: f32);
+
+    /// Moves the camera towards its look at point, scaling the distance to it by the given factor.
+    fn dolly(&mut self, factor: f32);
+
+    /// Returns the position and orientation of the camera.
+    fn get_pose(&self) -> CameraPose;
+
+    /// Sets the position and orientation of the camera.
+    fn set_pose(&mut self, pose: &CameraPose);
+
+    /// Returns the scene file description of the camera with the given name.
+    fn to_scene_camera(&self, name: &str) -> scene_file::Camera;
+
     /// Returns the view matrix.
     fn get_view_matrix(&self) -> Mat4;
 
@@ -22,22 +47,57 @@ pub trait Camera {
     /// Returns the focal length of the lens.
     fn get_focal_length(&self) -> f32;
 
-    /// Returns the aperture size of the lens.
+    /// Sets the focal length of the lens. This is the distance to the plane in focus.
+    fn set_focal_length(&mut self, focal_length: f32);
+
+    /// Returns the aperture size of the lens. Cameras with exposure settings derive it from the
+    /// f-number.
     fn get_aperture_size(&self) -> f32;
+
+    /// Returns the factor to scale scene radiance by based on the camera's exposure settings.
+    fn get_exposure(&self) -> f32;
+}
+
+/// Position and orientation of a camera.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPose {
+    /// Position of the camera.
+    pub eye: Vec3,
+
+    /// Rotates from looking down -Z with +Y up to world space.
+    pub orientation: Quat,
+
+    /// Distance from eye to the point the camera looks at.
+    pub target_distance: f32,
 }
 
-/// Perspective camera.
+impl CameraPose {
+    /// Interpolates between this pose and another. The orientation is spherically interpolated
+    /// and position linearly interpolated.
+    pub fn interpolate(&self, other: &CameraPose, t: f32) -> CameraPose {
+        CameraPose {
+            eye: self.eye.lerp(other.eye, t),
+            orientation: self.orientation.slerp(other.orientation, t),
+            target_distance: self.target_distance
+                + (other.target_distance - self.target_distance) * t,
+        }
+    }
+}
+
+/// Perspective camera. The orientation is stored as a quaternion so the camera can roll freely
+/// and doesn't suffer from gimbal issues when looking straight up or down.
 pub struct PerspectiveCamera {
     eye: Vec3,
-    look_at: Vec3,
-    up: Vec3,
-    fov_y: f32, // Vertical FOV in radians.
+    orientation: Quat,    // Rotates from looking down -Z with +Y up to world space.
+    target_distance: f32, // Distance from eye to the point the camera looks at.
+    fov_y: f32,           // Vertical FOV in radians.
     z_near: f32,
     z_far: f32,
     proj: Mat4,
     view: Mat4,
     focal_length: f32,
     aperture_size: f32,
+    exposure: Option<Exposure>,
 }
 
 impl PerspectiveCamera {
@@ -45,39 +105,120 @@ impl PerspectiveCamera {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         eye: Vec3,
-        look_at: Vec3,
-        up: Vec3,
+        orientation: Quat,
+        target_distance: f32,
         fov_y: f32,
         z_near: f32,
         z_far: f32,
         focal_length: f32,
         aperture_size: f32,
+        exposure: Option<Exposure>,
         image_width: u32,
         image_height: u32,
     ) -> Self {
         let aspect = image_width as f32 / image_height as f32;
         let proj = Mat4::perspective_rh(fov_y, aspect, z_near, z_far);
-        let view = Mat4::look_at_rh(eye, look_at, up);
+        let orientation = orientation.normalize();
         Self {
             eye,
-            look_at,
-            up,
+            orientation,
+            target_distance,
             fov_y,
             z_near,
             z_far,
             focal_length,
             aperture_size,
+            exposure,
             proj,
-            view,
+            view: get_view_matrix(eye, orientation),
         }
     }
 }
 
+/// Returns the orientation of a camera at `eye` looking at `look_at` with the given up vector.
+pub fn get_look_at_orientation(eye: Vec3, look_at: Vec3, up: Vec3) -> Quat {
+    let view = Mat4::look_at_rh(eye, look_at, up);
+    Quat::from_mat3(&Mat3::from_mat4(view.inverse())).normalize()
+}
+
+/// Returns the view matrix for a camera at `eye` with the given orientation.
+fn get_view_matrix(eye: Vec3, orientation: Quat) -> Mat4 {
+    Mat4::from_rotation_translation(orientation, eye).inverse()
+}
+
 impl Camera for PerspectiveCamera {
     fn update_image_size(&mut self, image_width: u32, image_height: u32) {
         let aspect = image_width as f32 / image_height as f32;
         self.proj = Mat4::perspective_rh(self.fov_y, aspect, self.z_near, self.z_far);
-        self.view = Mat4::look_at_rh(self.eye, self.look_at, self.up);
+        self.view = get_view_matrix(self.eye, self.orientation);
+    }
+
+    fn move_relative(&mut self, offset: Vec3) {
+        let right = self.orientation * Vec3::X;
+        let up = self.orientation * Vec3::Y;
+        let forward = self.orientation * Vec3::NEG_Z;
+
+        let delta = (right * offset.x + up * offset.y + forward * offset.z) * self.target_distance;
+        self.eye += delta;
+        self.view = get_view_matrix(self.eye, self.orientation);
+    }
+
+    fn roll(&mut self, angle: f32) {
+        // Rotating about local -Z is a roll about the view direction.
+        self.orientation = (self.orientation * Quat::from_rotation_z(-angle)).normalize();
+        self.view = get_view_matrix(self.eye, self.orientation);
+    }
+
+    fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let look_at = self.eye + self.orientation * Vec3::NEG_Z * self.target_distance;
+
+        // Rotating about the local axes keeps the orbit consistent with the camera's roll.
+        self.orientation =
+            (self.orientation * Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch))
+                .normalize();
+        self.eye = look_at - self.orientation * Vec3::NEG_Z * self.target_distance;
+        self.view = get_view_matrix(self.eye, self.orientation);
+    }
+
+    fn dolly(&mut self, factor: f32) {
+        let forward = self.orientation * Vec3::NEG_Z;
+        let look_at = self.eye + forward * self.target_distance;
+
+        self.target_distance *= factor;
+        self.eye = look_at - forward * self.target_distance;
+        self.view = get_view_matrix(self.eye, self.orientation);
+    }
+
+    fn get_pose(&self) -> CameraPose {
+        CameraPose {
+            eye: self.eye,
+            orientation: self.orientation,
+            target_distance: self.target_distance,
+        }
+    }
+
+    fn set_pose(&mut self, pose: &CameraPose) {
+        self.eye = pose.eye;
+        self.orientation = pose.orientation.normalize();
+        self.target_distance = pose.target_distance;
+        self.view = get_view_matrix(self.eye, self.orientation);
+    }
+
+    fn to_scene_camera(&self, name: &str) -> scene_file::Camera {
+        let look_at = self.eye + self.orientation * Vec3::NEG_Z * self.target_distance;
+        scene_file::Camera::Perspective {
+            name: name.to_string(),
+            eye: self.eye.to_array(),
+            look_at: look_at.to_array(),
+            up: None,
+            orientation: Some(Orientation::Quaternion(self.orientation.to_array())),
+            fov_y: self.fov_y.to_degrees(),
+            z_near: self.z_near,
+            z_far: self.z_far,
+            focal_length: self.focal_length,
+            aperture_size: self.aperture_size,
+            exposure: self.exposure,
+        }
     }
 
     fn get_view_matrix(&self) -> Mat4 {
@@ -100,8 +241,19 @@ impl Camera for PerspectiveCamera {
         self.focal_length
     }
 
+    fn set_focal_length(&mut self, focal_length: f32) {
+        self.focal_length = focal_length;
+    }
+
     fn get_aperture_size(&self) -> f32 {
-        self.aperture_size
+        self.exposure.map_or(self.aperture_size, |exposure| {
+            exposure.get_aperture_size(self.fov_y)
+        })
+    }
+
+    fn get_exposure(&self) -> f32 {
+        self.exposure
+            .map_or(1.0, |exposure| exposure.get_multiplier())
     }
 }
 
@@ -116,22 +268,45 @@ pub fn create_camera(
             eye,
             look_at,
             up,
+            orientation,
             fov_y,
             z_near,
             z_far,
             focal_length,
             aperture_size,
-        } => Arc::new(RwLock::new(PerspectiveCamera::new(
-            Vec3::from_slice(eye),
-            Vec3::from_slice(look_at),
-            Vec3::from_slice(up),
-            fov_y.to_radians(),
-            *z_near,
-            *z_far,
-            *focal_length,
-            *aperture_size,
-            image_width,
-            image_height,
-        ))),
+            exposure,
+        } => {
+            let eye = Vec3::from_slice(eye);
+            let look_at = Vec3::from_slice(look_at);
+            let up_vector = up.map_or(Vec3::Y, |up| Vec3::from_slice(&up));
+
+            let orientation = match orientation {
+                Some(Orientation::Quaternion(q)) => {
+                    if up.is_some() {
+                        warn!("Camera up vector is ignored when orientation is a quaternion");
+                    }
+                    Quat::from_array(*q)
+                }
+                Some(Orientation::Roll(degrees)) => {
+                    get_look_at_orientation(eye, look_at, up_vector)
+                        * Quat::from_rotation_z(-degrees.to_radians())
+                }
+                None => get_look_at_orientation(eye, look_at, up_vector),
+            };
+
+            Arc::new(RwLock::new(PerspectiveCamera::new(
+                eye,
+                orientation,
+                eye.distance(look_at),
+                fov_y.to_radians(),
+                *z_near,
+                *z_far,
+                *focal_length,
+                *aperture_size,
+                *exposure,
+                image_width,
+                image_height,
+            )))
+        }
     }
 }
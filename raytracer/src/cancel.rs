@@ -0,0 +1,78 @@
+use std::{
+    error::Error,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use anyhow::Result;
+
+/// Cooperative cancellation of long-running operations such as building a scene and offline
+/// rendering. Clones share the same state so a token can be cancelled from another thread, e.g. a
+/// Ctrl+C handler, while the operation checks it between sample batches and uploads.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    /// Whether cancellation was requested.
+    is_cancelled: Arc<AtomicBool>,
+
+    /// Time after which the token counts as cancelled even if cancellation wasn't requested.
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that operations checking this token stop.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns a token sharing this token's state that also counts as cancelled once `deadline`
+    /// has passed, e.g. to limit how long building a scene may take.
+    pub fn with_deadline(&self, deadline: Instant) -> Self {
+        Self {
+            is_cancelled: self.is_cancelled.clone(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Returns true if cancellation was requested or the deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed) || self.is_expired()
+    }
+
+    /// Returns true if the token has a deadline and it has passed.
+    pub fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns a `Cancelled` error if cancellation was requested or the deadline has passed so
+    /// operations can stop with `?`.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Error returned by operations that stopped because their cancellation token was cancelled. Use
+/// `anyhow::Error::is::<Cancelled>()` to tell cancellation apart from failures.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl Error for Cancelled {}
@@ -0,0 +1,268 @@
+use std::{fmt, sync::Arc};
+
+use anyhow::{Context, Result};
+use log::warn;
+use vulkano::{
+    Version, VulkanLibrary,
+    device::{
+        DeviceExtensions, DeviceFeatures, QueueFlags,
+        physical::{PhysicalDevice, PhysicalDeviceType},
+    },
+    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo, InstanceExtensions},
+};
+
+use crate::{
+    raytracing_device_extensions, raytracing_device_features, required_device_extensions,
+    required_device_features,
+};
+
+/// Vulkan support of a physical device for the features the renderer uses. Optional features are
+/// enabled only if they are available and anything depending on them is disabled otherwise.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    /// Name of the device.
+    pub device_name: String,
+
+    /// Type of the device.
+    pub device_type: PhysicalDeviceType,
+
+    /// Vendor ID used along with the device ID to identify the device.
+    pub vendor_id: u32,
+
+    /// Device ID used along with the vendor ID to identify the device.
+    pub device_id: u32,
+
+    /// Vulkan version supported by the device.
+    pub api_version: Version,
+
+    /// Everything the renderer requires. Without this the device cannot be used.
+    pub is_supported: bool,
+
+    /// Ray tracing pipelines and acceleration structures. Without these, rays are traced by a
+    /// compute shader that traverses a BVH built on the CPU.
+    pub ray_tracing_pipeline: bool,
+
+    /// Ray queries from any shader stage.
+    pub ray_query: bool,
+
+    /// `VK_KHR_ray_tracing_maintenance1`.
+    pub ray_tracing_maintenance1: bool,
+
+    /// Descriptor arrays whose size is chosen when allocating the descriptor set. Without this,
+    /// image texture arrays are sized by the descriptor set layout instead.
+    pub variable_descriptor_count: bool,
+
+    /// Extended and HDR swapchain colour spaces. This depends on the instance rather than the
+    /// device.
+    pub hdr_surface: bool,
+}
+
+impl Capabilities {
+    /// Returns the capabilities of a physical device.
+    pub fn new(physical_device: &PhysicalDevice) -> Self {
+        let properties = physical_device.properties();
+        let extensions = physical_device.supported_extensions();
+        let features = physical_device.supported_features();
+
+        let has_graphics_queue = physical_device
+            .queue_family_properties()
+            .iter()
+            .any(|q| q.queue_flags.intersects(QueueFlags::GRAPHICS));
+
+        let ray_tracing_pipeline = extensions.contains(&raytracing_device_extensions())
+            && features.contains(&raytracing_device_features());
+
+        Self {
+            device_name: properties.device_name.clone(),
+            device_type: properties.device_type,
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            api_version: physical_device.api_version(),
+            is_supported: physical_device.api_version() >= Version::V1_3
+                && has_graphics_queue
+                && extensions.contains(&required_device_extensions())
+                && features.contains(&required_device_features()),
+            ray_tracing_pipeline,
+            ray_query: ray_tracing_pipeline && extensions.khr_ray_query && features.ray_query,
+            ray_tracing_maintenance1: ray_tracing_pipeline
+                && extensions.khr_ray_tracing_maintenance1
+                && features.ray_tracing_maintenance1,
+            variable_descriptor_count: features.descriptor_binding_variable_descriptor_count,
+            hdr_surface: physical_device
+                .instance()
+                .enabled_extensions()
+                .ext_swapchain_colorspace,
+        }
+    }
+
+    /// Returns the capabilities of all physical devices. This creates a temporary instance so it
+    /// can be called before the real instance is created.
+    pub fn query_all() -> Result<Vec<Self>> {
+        let instance = create_probe_instance()?;
+        Ok(instance
+            .enumerate_physical_devices()?
+            .map(|p| Self::new(&p))
+            .collect())
+    }
+
+    /// Returns the capabilities of the device that will be used for rendering. Devices whose name
+    /// contains `preferred_device` (case insensitive) are picked first, then devices with raytracing
+    /// pipelines and then discrete GPUs.
+    pub fn query(preferred_device: Option<&str>) -> Result<Self> {
+        select_device(Self::query_all()?, preferred_device)
+    }
+
+    /// Returns true if this describes the given physical device.
+    pub fn is_device(&self, physical_device: &PhysicalDevice) -> bool {
+        let properties = physical_device.properties();
+        properties.vendor_id == self.vendor_id
+            && properties.device_id == self.device_id
+            && properties.device_name == self.device_name
+    }
+
+    /// Returns the device extensions to enable. These are the required extensions plus any
+    /// optional ones that are available.
+    pub fn get_device_extensions(&self) -> DeviceExtensions {
+        let mut extensions = DeviceExtensions {
+            khr_ray_query: self.ray_query,
+            khr_ray_tracing_maintenance1: self.ray_tracing_maintenance1,
+            ..required_device_extensions()
+        };
+        if self.ray_tracing_pipeline {
+            extensions = extensions.union(&raytracing_device_extensions());
+        }
+        extensions
+    }
+
+    /// Returns the device features to enable. These are the required features plus any optional
+    /// ones that are available.
+    pub fn get_device_features(&self) -> DeviceFeatures {
+        let mut features = DeviceFeatures {
+            ray_query: self.ray_query,
+            ray_tracing_maintenance1: self.ray_tracing_maintenance1,
+            descriptor_binding_variable_descriptor_count: self.variable_descriptor_count,
+            ..required_device_features()
+        };
+        if self.ray_tracing_pipeline {
+            features = features.union(&raytracing_device_features());
+        }
+        features
+    }
+
+    /// Logs a warning for each optional device feature that is missing and what is disabled
+    /// because of it.
+    pub fn warn_missing(&self) {
+        if !self.ray_tracing_pipeline {
+            warn!("Raytracing pipelines are not supported. Using the compute shader fallback.");
+        }
+        if !self.ray_query {
+            warn!("Ray queries are not supported. They will not be enabled.");
+        }
+        if !self.ray_tracing_maintenance1 {
+            warn!("VK_KHR_ray_tracing_maintenance1 is not supported. It will not be enabled.");
+        }
+        if !self.variable_descriptor_count {
+            warn!("Variable descriptor counts are not supported. Using fixed size texture arrays.");
+        }
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+
+        writeln!(
+            f,
+            "{} ({:?}, Vulkan {})",
+            self.device_name, self.device_type, self.api_version
+        )?;
+        writeln!(
+            f,
+            "  supported                       : {}",
+            yes_no(self.is_supported)
+        )?;
+        writeln!(
+            f,
+            "  ray tracing pipeline            : {}",
+            yes_no(self.ray_tracing_pipeline)
+        )?;
+        writeln!(
+            f,
+            "  ray query                       : {}",
+            yes_no(self.ray_query)
+        )?;
+        writeln!(
+            f,
+            "  ray tracing maintenance 1       : {}",
+            yes_no(self.ray_tracing_maintenance1)
+        )?;
+        writeln!(
+            f,
+            "  variable descriptor count       : {}",
+            yes_no(self.variable_descriptor_count)
+        )?;
+        write!(
+            f,
+            "  HDR surface                     : {}",
+            yes_no(self.hdr_surface)
+        )
+    }
+}
+
+/// Picks the device to render with from those the renderer can use. Devices whose name contains
+/// `preferred_device` (case insensitive) are picked first, then devices with raytracing pipelines
+/// and then discrete GPUs.
+pub(crate) fn select_device(
+    devices: impl IntoIterator<Item = Capabilities>,
+    preferred_device: Option<&str>,
+) -> Result<Capabilities> {
+    let preferred_device = preferred_device.map(|name| name.to_lowercase());
+
+    let device = devices
+        .into_iter()
+        .filter(|d| d.is_supported)
+        .min_by_key(|d| {
+            let is_preferred = preferred_device
+                .as_ref()
+                .is_some_and(|name| d.device_name.to_lowercase().contains(name));
+            let type_priority = match d.device_type {
+                PhysicalDeviceType::DiscreteGpu => 0,
+                PhysicalDeviceType::IntegratedGpu => 1,
+                PhysicalDeviceType::VirtualGpu => 2,
+                PhysicalDeviceType::Cpu => 3,
+                _ => 4,
+            };
+            (!is_preferred, !d.ray_tracing_pipeline, type_priority)
+        })
+        .context("No supported physical device found")?;
+
+    if let Some(name) = preferred_device
+        && !device.device_name.to_lowercase().contains(&name)
+    {
+        warn!("No supported device matches '{name}'");
+    }
+
+    Ok(device)
+}
+
+/// Creates an instance with the extensions that affect capabilities enabled if they are supported.
+fn create_probe_instance() -> Result<Arc<Instance>> {
+    let library = VulkanLibrary::new()?;
+
+    let enabled_extensions = InstanceExtensions {
+        khr_surface: true,
+        ext_swapchain_colorspace: true,
+        ..InstanceExtensions::empty()
+    }
+    .intersection(library.supported_extensions());
+
+    Ok(Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+            application_version: Version::V1_3,
+            enabled_extensions,
+            ..Default::default()
+        },
+    )?)
+}
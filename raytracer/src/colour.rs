@@ -0,0 +1,99 @@
+use glam::{Mat3, Vec3};
+
+/// Converts linear sRGB to CIE XYZ.
+const SRGB_TO_XYZ: Mat3 = from_rows([
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.119192, 0.9503041],
+]);
+
+/// Converts CIE XYZ to linear sRGB.
+const XYZ_TO_SRGB: Mat3 = from_rows([
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.969266, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+]);
+
+/// Converts CIE XYZ to the cone response domain of the Bradford chromatic adaptation transform.
+const XYZ_TO_BRADFORD: Mat3 = from_rows([
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+]);
+
+/// Colour temperature in Kelvin that white balance is relative to. Light of this temperature is
+/// left unchanged.
+pub const REFERENCE_WHITE_KELVIN: f32 = 6500.0;
+
+/// Relative luminance of each linear sRGB channel. This matches `luminance` in the shaders.
+const LUMINANCE_WEIGHTS: Vec3 = Vec3::new(0.2126, 0.7152, 0.0722);
+
+/// Creates a matrix from its rows, which is easier to compare with published matrices.
+const fn from_rows(rows: [[f32; 3]; 3]) -> Mat3 {
+    Mat3::from_cols_array_2d(&[
+        [rows[0][0], rows[1][0], rows[2][0]],
+        [rows[0][1], rows[1][1], rows[2][1]],
+        [rows[0][2], rows[1][2], rows[2][2]],
+    ])
+}
+
+/// Returns the relative luminance of a linear sRGB colour.
+pub fn get_luminance(rgb: [f32; 3]) -> f32 {
+    Vec3::from(rgb).dot(LUMINANCE_WEIGHTS)
+}
+
+/// Returns the CIE XYZ colour of a blackbody at the given temperature in Kelvin, with a luminance
+/// of 1. This uses the cubic spline approximation of the Planckian locus from "Design of Advanced
+/// Color Temperature Control System for HDTV Applications" by Kim et al., which is accurate from
+/// 1667 K to 25000 K. Temperatures are clamped to that range.
+pub fn blackbody_to_xyz(temperature_kelvin: f32) -> Vec3 {
+    let t = temperature_kelvin.clamp(1667.0, 25000.0) as f64;
+    let (t2, t3) = (t * t, t * t * t);
+
+    // Chromaticity of the Planckian locus.
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+
+    Vec3::new((x / y) as f32, 1.0, ((1.0 - x - y) / y) as f32)
+}
+
+/// Returns the linear sRGB colour of a blackbody at the given temperature in Kelvin, with a
+/// luminance of 1. Colours outside the sRGB gamut are clamped.
+pub fn blackbody_to_rgb(temperature_kelvin: f32) -> [f32; 3] {
+    (XYZ_TO_SRGB * blackbody_to_xyz(temperature_kelvin))
+        .max(Vec3::ZERO)
+        .to_array()
+}
+
+/// Returns the matrix white balancing linear sRGB colours so light of the given temperature in
+/// Kelvin looks like light of `REFERENCE_WHITE_KELVIN`. This uses the Bradford chromatic
+/// adaptation transform.
+pub fn get_white_balance_matrix(temperature_kelvin: f32) -> Mat3 {
+    let source = XYZ_TO_BRADFORD * blackbody_to_xyz(temperature_kelvin);
+    let target = XYZ_TO_BRADFORD * blackbody_to_xyz(REFERENCE_WHITE_KELVIN);
+    let adaptation =
+        XYZ_TO_BRADFORD.inverse() * Mat3::from_diagonal(target / source) * XYZ_TO_BRADFORD;
+    XYZ_TO_SRGB * adaptation * SRGB_TO_XYZ
+}
+
+/// Returns the matrix scaling the saturation of linear sRGB colours without changing their
+/// luminance. A saturation of 0 gives greyscale and 1 leaves colours unchanged.
+pub fn get_saturation_matrix(saturation: f32) -> Mat3 {
+    let grey = Mat3::from_cols(
+        Vec3::splat(LUMINANCE_WEIGHTS.x),
+        Vec3::splat(LUMINANCE_WEIGHTS.y),
+        Vec3::splat(LUMINANCE_WEIGHTS.z),
+    );
+    Mat3::IDENTITY * saturation + grey * (1.0 - saturation)
+}
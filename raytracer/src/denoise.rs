@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use shaders::{DenoiseShaderModules, denoise};
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    image::view::ImageView,
+    pipeline::PipelineBindPoint,
+};
+
+use crate::{Vk, pipelines::DenoisePipeline, render_engine::create_accumulated_render_image_view};
+
+/// Number of iterations of the filter. The taps of each iteration are twice as far apart as the
+/// previous one's so the last one covers 2^(n + 1) + 1 pixels across.
+const DENOISE_ITERATIONS: u32 = 5;
+
+/// Sensitivity of the first iteration to colour differences. This halves with every iteration
+/// since noise is smoothed out by the previous ones.
+const DENOISE_COLOUR_PHI: f32 = 0.2;
+
+/// Denoises the presented image with the edge-avoiding À-Trous wavelet filter guided by the
+/// albedo, normal and depth AOVs. This only changes the presented image, so accumulation carries
+/// on with the noisy samples.
+pub struct Denoiser {
+    /// The compute pipeline running an iteration of the filter.
+    pipeline: DenoisePipeline,
+
+    /// Images the iterations alternate between before the last one writes the result.
+    scratch_image_views: [Arc<ImageView>; 2],
+}
+
+impl Denoiser {
+    /// Create the denoise pipeline and the scratch images for the given image size.
+    pub fn new(vk: Arc<Vk>, width: u32, height: u32) -> Result<Self> {
+        let pipeline = DenoisePipeline::new(
+            vk.device.clone(),
+            DenoiseShaderModules::load(vk.device.clone()).stage,
+        )?;
+        let scratch_image_views = create_scratch_image_views(vk, width, height)?;
+
+        Ok(Self {
+            pipeline,
+            scratch_image_views,
+        })
+    }
+
+    /// Reallocates the scratch images for a new image size.
+    pub fn resize(&mut self, vk: Arc<Vk>, width: u32, height: u32) -> Result<()> {
+        self.scratch_image_views = create_scratch_image_views(vk, width, height)?;
+        Ok(())
+    }
+
+    /// Records the commands to denoise `image_view` in place. The AOVs must have the same size.
+    pub fn record(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_view: Arc<ImageView>,
+        albedo_image_view: Arc<ImageView>,
+        normal_image_view: Arc<ImageView>,
+        depth_image_view: Arc<ImageView>,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.get_layout();
+        let layouts = pipeline_layout.set_layouts();
+
+        let extent = image_view.image().extent();
+        let group_counts = [
+            extent[0].div_ceil(DenoisePipeline::WORKGROUP_SIZE),
+            extent[1].div_ceil(DenoisePipeline::WORKGROUP_SIZE),
+            1,
+        ];
+
+        builder.bind_pipeline_compute(self.pipeline.get())?;
+
+        let mut input_image_view = image_view.clone();
+        for iteration in 0..DENOISE_ITERATIONS {
+            let output_image_view = if iteration + 1 == DENOISE_ITERATIONS {
+                image_view.clone()
+            } else {
+                self.scratch_image_views[iteration as usize % 2].clone()
+            };
+
+            let descriptor_set = DescriptorSet::new(
+                vk.descriptor_set_allocator.clone(),
+                layouts[DenoisePipeline::DENOISE_LAYOUT].clone(),
+                [
+                    WriteDescriptorSet::image_view(0, input_image_view),
+                    WriteDescriptorSet::image_view(1, albedo_image_view.clone()),
+                    WriteDescriptorSet::image_view(2, normal_image_view.clone()),
+                    WriteDescriptorSet::image_view(3, depth_image_view.clone()),
+                    WriteDescriptorSet::image_view(4, output_image_view.clone()),
+                ],
+                [],
+            )?;
+
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    pipeline_layout.clone(),
+                    0,
+                    vec![descriptor_set],
+                )?
+                .push_constants(
+                    pipeline_layout.clone(),
+                    0,
+                    denoise::DenoisePushConstants {
+                        stepWidth: 1 << iteration,
+                        colourPhi: DENOISE_COLOUR_PHI / (1 << iteration) as f32,
+                    },
+                )?;
+
+            // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
+            unsafe { builder.dispatch(group_counts)? };
+
+            input_image_view = output_image_view;
+        }
+
+        Ok(())
+    }
+}
+
+/// Create the scratch images for the given image size.
+fn create_scratch_image_views(vk: Arc<Vk>, width: u32, height: u32) -> Result<[Arc<ImageView>; 2]> {
+    Ok([
+        create_accumulated_render_image_view(vk.clone(), width, height)?,
+        create_accumulated_render_image_view(vk, width, height)?,
+    ])
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use shaders::DownsampleShaderModules;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    image::view::ImageView,
+    pipeline::PipelineBindPoint,
+};
+
+use crate::{Vk, pipelines::DownsamplePipeline};
+
+/// Resolves images rendered at a higher resolution than the output by filtering them down to the
+/// output resolution, so supersampling anti-aliases edges and fine texture detail.
+pub struct Downsampler {
+    /// The compute pipeline filtering the supersampled image.
+    pipeline: DownsamplePipeline,
+}
+
+impl Downsampler {
+    /// Create the downsample pipeline.
+    pub fn new(vk: Arc<Vk>) -> Result<Self> {
+        let pipeline = DownsamplePipeline::new(
+            vk.device.clone(),
+            DownsampleShaderModules::load(vk.device.clone()).stage,
+        )?;
+        Ok(Self { pipeline })
+    }
+
+    /// Records the commands to filter `input_image_view` down to the size of
+    /// `output_image_view`.
+    pub fn record(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        input_image_view: Arc<ImageView>,
+        output_image_view: Arc<ImageView>,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.get_layout();
+        let layouts = pipeline_layout.set_layouts();
+
+        let extent = output_image_view.image().extent();
+        let descriptor_set = DescriptorSet::new(
+            vk.descriptor_set_allocator.clone(),
+            layouts[DownsamplePipeline::DOWNSAMPLE_LAYOUT].clone(),
+            [
+                WriteDescriptorSet::image_view(0, input_image_view),
+                WriteDescriptorSet::image_view(1, output_image_view),
+            ],
+            [],
+        )?;
+
+        let group_counts = [
+            extent[0].div_ceil(DownsamplePipeline::WORKGROUP_SIZE),
+            extent[1].div_ceil(DownsamplePipeline::WORKGROUP_SIZE),
+            1,
+        ];
+
+        builder
+            .bind_pipeline_compute(self.pipeline.get())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline_layout.clone(),
+                0,
+                vec![descriptor_set],
+            )?;
+
+        // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
+        unsafe { builder.dispatch(group_counts)? };
+
+        Ok(())
+    }
+}
+
+/// Returns the resolution images are rendered at to be downsampled to the given output size.
+pub fn get_supersampled_size(output_size: [u32; 2], supersample: f32) -> [u32; 2] {
+    output_size.map(|size| ((size as f32 * supersample).round() as u32).max(size))
+}
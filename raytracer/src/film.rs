@@ -0,0 +1,139 @@
+use glam::{Mat3, Vec3};
+use scene_file::{Film, FilmPreset};
+
+use crate::colour::{get_saturation_matrix, get_white_balance_matrix};
+
+// These must match the `FILM_PRESET_*` constants in the shaders.
+pub const FILM_PRESET_LINEAR: u32 = 0;
+pub const FILM_PRESET_FILMIC: u32 = 1;
+pub const FILM_PRESET_AGX: u32 = 2;
+
+/// Exposure bias applied before the filmic curve so middle grey stays at a similar brightness.
+const FILMIC_EXPOSURE_BIAS: f32 = 2.0;
+
+/// Scene radiance mapped to white by the filmic curve.
+const FILMIC_WHITE: f32 = 11.2;
+
+/// Converts linear sRGB to the AgX working space before the curve is applied.
+const AGX_INSET: Mat3 = Mat3::from_cols_array(&[
+    0.84247905,
+    0.042328242,
+    0.042375654,
+    0.0784336,
+    0.87846863,
+    0.0784336,
+    0.079223745,
+    0.07916613,
+    0.879143,
+]);
+
+/// Converts the AgX working space back to linear sRGB after the curve is applied.
+const AGX_OUTSET: Mat3 = Mat3::from_cols_array(&[
+    1.196879,
+    -0.052896854,
+    -0.052971635,
+    -0.09802088,
+    1.1519032,
+    -0.09804345,
+    -0.09902974,
+    -0.098961174,
+    1.1510737,
+]);
+
+/// Range of exposure values relative to a luminance of 1 that the AgX curve covers.
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+/// Film response of a scene, mapping the linear rendered image to the linear colours that are
+/// displayed or saved as 8-bit images. The colour matrix applies white balance and saturation
+/// before the preset's curve. This matches `applyFilm` in the shaders.
+#[derive(Clone, Copy, Debug)]
+pub struct FilmResponse {
+    /// White balance and saturation applied to linear sRGB colours.
+    matrix: Mat3,
+
+    /// Curve compressing scene radiance to the displayable range.
+    preset: FilmPreset,
+}
+
+impl FilmResponse {
+    /// Create the film response for the given settings.
+    pub fn new(film: &Film) -> Self {
+        Self {
+            matrix: get_saturation_matrix(film.saturation.max(0.0))
+                * get_white_balance_matrix(film.white_balance),
+            preset: film.preset,
+        }
+    }
+
+    /// Returns the white balance and saturation matrix.
+    pub fn get_matrix(&self) -> Mat3 {
+        self.matrix
+    }
+
+    /// Returns the preset for shaders.
+    pub fn get_shader_preset(&self) -> u32 {
+        match self.preset {
+            FilmPreset::Linear => FILM_PRESET_LINEAR,
+            FilmPreset::Filmic => FILM_PRESET_FILMIC,
+            FilmPreset::Agx => FILM_PRESET_AGX,
+        }
+    }
+
+    /// Applies the film response to a linear colour.
+    pub fn apply(&self, linear: [f32; 3]) -> [f32; 3] {
+        let colour = (self.matrix * Vec3::from(linear)).max(Vec3::ZERO);
+        match self.preset {
+            FilmPreset::Linear => colour,
+            FilmPreset::Filmic => filmic(colour),
+            FilmPreset::Agx => agx(colour),
+        }
+        .to_array()
+    }
+
+    /// Applies the film response to linear RGBA pixels. Alpha is unchanged.
+    pub fn apply_to_pixels(&self, pixels: &[[f32; 4]]) -> Vec<[f32; 4]> {
+        pixels
+            .iter()
+            .map(|&[r, g, b, a]| {
+                let [r, g, b] = self.apply([r, g, b]);
+                [r, g, b, a]
+            })
+            .collect()
+    }
+}
+
+/// Filmic curve from John Hable's "Uncharted 2: HDR Lighting" talk.
+fn filmic(colour: Vec3) -> Vec3 {
+    hable(colour * FILMIC_EXPOSURE_BIAS) / hable(Vec3::splat(FILMIC_WHITE))
+}
+
+/// Hable's curve before it is normalised to the white point.
+fn hable(x: Vec3) -> Vec3 {
+    const A: f32 = 0.15; // Shoulder strength.
+    const B: f32 = 0.50; // Linear strength.
+    const C: f32 = 0.10; // Linear angle.
+    const D: f32 = 0.20; // Toe strength.
+    const E: f32 = 0.02; // Toe numerator.
+    const F: f32 = 0.30; // Toe denominator.
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+/// AgX curve by Troy Sobotka using the polynomial fit of its default contrast by Benjamin
+/// Wrensch.
+fn agx(colour: Vec3) -> Vec3 {
+    let log = (AGX_INSET * colour)
+        .max(Vec3::splat(1e-10))
+        .log2()
+        .clamp(Vec3::splat(AGX_MIN_EV), Vec3::splat(AGX_MAX_EV));
+    let x = (log - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let curve =
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232;
+
+    // The curve gives display encoded values with a gamma of 2.2.
+    (AGX_OUTSET * curve).max(Vec3::ZERO).powf(2.2)
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+/// Callback observing the progress of a render.
+type ProgressCallback = Box<dyn FnMut(&RenderProgress) + Send>;
+
+/// Callback observing a scene once it is built.
+type SceneBuiltCallback = Box<dyn FnMut(&SceneBuildStats) + Send>;
+
+/// Summary of a newly built scene passed to `RenderHooks::on_scene_built`.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneBuildStats {
+    /// Time taken to load the textures and build the meshes and acceleration structures.
+    pub build_time: Duration,
+
+    /// Number of meshes.
+    pub mesh_count: usize,
+
+    /// Number of mesh instances.
+    pub instance_count: usize,
+}
+
+/// Progress of a render passed to the batch and frame callbacks of `RenderHooks`.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderProgress {
+    /// Number of sample batches rendered since rendering last restarted.
+    pub sample_batch: u32,
+
+    /// Number of sample batches rendered before the image is complete, or `None` while
+    /// accumulating progressively.
+    pub batch_count: Option<u32>,
+
+    /// Number of samples per pixel accumulated so far.
+    pub accumulated_samples_per_pixel: u32,
+
+    /// Whether all sample batches have been rendered.
+    pub is_complete: bool,
+}
+
+/// Callbacks for applications embedding the renderer, such as a command line progress bar, a GUI
+/// or a network server, to observe a render without polling it. Callbacks run on the thread
+/// rendering the scene, so they should return quickly.
+#[derive(Default)]
+pub struct RenderHooks {
+    /// Called once the scene is built.
+    scene_built: Option<SceneBuiltCallback>,
+
+    /// Called after each sample batch. Offline renders call this once the GPU has finished the
+    /// batch and interactive renders once its commands are recorded.
+    batch_complete: Option<ProgressCallback>,
+
+    /// Called after the commands for presenting each frame are recorded, whether or not a sample
+    /// batch was rendered.
+    frame_complete: Option<ProgressCallback>,
+}
+
+impl RenderHooks {
+    /// Sets the callback called once the scene is built.
+    pub fn on_scene_built(
+        mut self,
+        callback: impl FnMut(&SceneBuildStats) + Send + 'static,
+    ) -> Self {
+        self.scene_built = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback called after each sample batch.
+    pub fn on_batch_complete(
+        mut self,
+        callback: impl FnMut(&RenderProgress) + Send + 'static,
+    ) -> Self {
+        self.batch_complete = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback called after each frame presented in a window.
+    pub fn on_frame_complete(
+        mut self,
+        callback: impl FnMut(&RenderProgress) + Send + 'static,
+    ) -> Self {
+        self.frame_complete = Some(Box::new(callback));
+        self
+    }
+
+    /// Calls the scene built callback if it is set.
+    pub(crate) fn notify_scene_built(&mut self, stats: &SceneBuildStats) {
+        if let Some(callback) = self.scene_built.as_mut() {
+            callback(stats);
+        }
+    }
+
+    /// Calls the batch complete callback if it is set.
+    pub(crate) fn notify_batch_complete(&mut self, progress: &RenderProgress) {
+        if let Some(callback) = self.batch_complete.as_mut() {
+            callback(progress);
+        }
+    }
+
+    /// Calls the frame complete callback if it is set.
+    pub(crate) fn notify_frame_complete(&mut self, progress: &RenderProgress) {
+        if let Some(callback) = self.frame_complete.as_mut() {
+            callback(progress);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    MAT_TYPE_DIELECTRIC, MAT_TYPE_DIFFUSE_LIGHT, MAT_TYPE_LAMBERTIAN, MAT_TYPE_METAL, MAT_TYPE_PBR,
+    Materials, textures::Textures,
+};
+
+/// Entry of a storage buffer or descriptor array in `BufferLayouts`.
+#[derive(Clone, Debug, Serialize)]
+pub struct BufferEntry {
+    /// Index of the entry used by shaders.
+    pub index: u32,
+
+    /// Name of the texture or material in the scene file. Copies derived for material variations
+    /// have no name.
+    pub name: Option<String>,
+}
+
+/// Order of the textures and materials in the storage buffers and descriptor arrays indexed by
+/// shaders. Dumping it helps find mismatches between the indices written when the scene is
+/// loaded and the ones shaders read.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BufferLayouts {
+    /// Constant colour textures.
+    pub constant_colour_textures: Vec<BufferEntry>,
+
+    /// Scalar textures.
+    pub scalar_textures: Vec<BufferEntry>,
+
+    /// Image textures.
+    pub image_textures: Vec<BufferEntry>,
+
+    /// Checker textures.
+    pub checker_textures: Vec<BufferEntry>,
+
+    /// Noise textures.
+    pub noise_textures: Vec<BufferEntry>,
+
+    /// Lambertian materials.
+    pub lambertian_materials: Vec<BufferEntry>,
+
+    /// Metal materials.
+    pub metal_materials: Vec<BufferEntry>,
+
+    /// Dielectric materials.
+    pub dielectric_materials: Vec<BufferEntry>,
+
+    /// Diffuse light materials.
+    pub diffuse_light_materials: Vec<BufferEntry>,
+
+    /// PBR materials.
+    pub pbr_materials: Vec<BufferEntry>,
+}
+
+impl BufferLayouts {
+    /// Records the layouts of the final textures and materials, including the copies derived for
+    /// material variations.
+    pub(crate) fn new(textures: &Textures, materials: &Materials) -> Self {
+        let material_entries = |material_type: u32, len: usize| {
+            let indices = materials.ids.iter().filter_map(|(name, id)| {
+                let material = materials.to_shader(*id);
+                (material.material_type == material_type).then_some((name, material.material_index))
+            });
+            get_entries(indices, len)
+        };
+
+        Self {
+            constant_colour_textures: get_texture_entries(
+                &textures.constant_colour_textures.indices,
+                textures.constant_colour_textures.colours.len(),
+            ),
+            scalar_textures: get_texture_entries(
+                &textures.scalar_textures.indices,
+                textures.scalar_textures.values.len(),
+            ),
+            image_textures: get_texture_entries(
+                &textures.image_textures.indices,
+                textures.image_textures.indices.len(),
+            ),
+            checker_textures: get_texture_entries(
+                &textures.checker_textures.indices,
+                textures.checker_textures.textures.len(),
+            ),
+            noise_textures: get_texture_entries(
+                &textures.noise_textures.indices,
+                textures.noise_textures.textures.len(),
+            ),
+            lambertian_materials: material_entries(
+                MAT_TYPE_LAMBERTIAN,
+                materials.lambertian_materials.len(),
+            ),
+            metal_materials: material_entries(MAT_TYPE_METAL, materials.metal_materials.len()),
+            dielectric_materials: material_entries(
+                MAT_TYPE_DIELECTRIC,
+                materials.dielectric_materials.len(),
+            ),
+            diffuse_light_materials: material_entries(
+                MAT_TYPE_DIFFUSE_LIGHT,
+                materials.diffuse_light_materials.len(),
+            ),
+            pbr_materials: material_entries(MAT_TYPE_PBR, materials.pbr_materials.len()),
+        }
+    }
+
+    /// Returns the layouts as pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Returns the entries of a texture buffer with `len` entries given the indices of the named
+/// textures.
+fn get_texture_entries(indices: &HashMap<String, u32>, len: usize) -> Vec<BufferEntry> {
+    get_entries(indices.iter().map(|(name, index)| (name, *index)), len)
+}
+
+/// Returns the entries of a buffer with `len` entries given the names of the named ones and their
+/// indices. The other entries have no name.
+fn get_entries<'a>(
+    indices: impl IntoIterator<Item = (&'a String, u32)>,
+    len: usize,
+) -> Vec<BufferEntry> {
+    let mut entries: Vec<BufferEntry> = (0..len)
+        .map(|index| BufferEntry {
+            index: index as u32,
+            name: None,
+        })
+        .collect();
+
+    for (name, index) in indices {
+        if let Some(entry) = entries.get_mut(index as usize) {
+            entry.name = Some(name.clone());
+        }
+    }
+
+    entries
+}
@@ -1,23 +1,50 @@
 mod acceleration;
+mod blue_noise;
+mod budget;
+mod bvh;
 mod camera;
+mod cancel;
+mod capabilities;
+mod colour;
 mod decomposed_transform;
+mod denoise;
+mod downsample;
+mod film;
+mod hooks;
+mod layout;
 mod light;
+mod light_tree;
 mod material;
 mod mesh;
 mod mesh_instance;
-mod obj_loader;
+mod output;
+mod overlay;
 mod pipelines;
+mod pixel_filter;
+mod probe;
+mod profiler;
 mod render_engine;
 mod scene;
+mod splat;
 mod textures;
+mod tracer;
 mod vk;
 
 pub use camera::*;
+pub use cancel::*;
+pub use capabilities::Capabilities;
 pub use decomposed_transform::*;
+pub use film::FilmResponse;
+pub use hooks::*;
+pub use layout::*;
 pub use light::*;
 pub use material::*;
 pub use mesh::*;
 pub use mesh_instance::*;
-pub use obj_loader::*;
+pub use output::*;
+pub use overlay::OverlayElement;
+pub use probe::{PROBE_RESOLUTION, ProbeResult, ProbeStatistics};
+pub use profiler::{MaterialProfile, MaterialTypeProfile};
+pub use render_engine::{AnalysisMode, Estimator, RANDOM_SEED, SamplingPattern};
 pub use scene::*;
 pub use vk::*;
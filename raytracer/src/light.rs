@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use anyhow::{Result, anyhow};
-use glam::Vec3;
+use glam::{Mat4, Vec3};
 use log::debug;
 use shaders::ray_gen;
 use vulkano::{
@@ -9,12 +9,39 @@ use vulkano::{
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
 };
 
-use crate::{Materials, Mesh, MeshInstance, Transform, Vk};
+use crate::{Materials, Mesh, MeshInstance, Transform, Vk, textures::Textures};
 
-struct Area {
-    value: f32,
-    mesh_index: usize,
-    primitive_index: usize,
+/// A triangle of a light source in world space.
+pub struct LightTriangle {
+    /// The area.
+    pub(crate) area: f32,
+
+    /// Estimate of the emitted power, which is the area times the luminance the light's material
+    /// emits.
+    pub(crate) power: f32,
+
+    /// The vertices.
+    pub(crate) vertices: [Vec3; 3],
+
+    /// Axis of a cone bounding the normals of the side that emits light. This is the geometric
+    /// normal facing the same way as the vertex normals.
+    pub(crate) normal_axis: Vec3,
+
+    /// Cosine of the half angle of the cone bounding the normals. The cone is widened to include
+    /// the vertex normals since the interpolated normal decides which side emits light.
+    pub(crate) normal_cos_theta: f32,
+
+    /// Index of the light's mesh.
+    pub(crate) mesh_index: usize,
+
+    /// Index of the light's mesh instance.
+    pub(crate) instance_index: usize,
+
+    /// Index of the triangle in the mesh.
+    pub(crate) primitive_index: usize,
+
+    /// Transform of the light's mesh instance.
+    pub(crate) object_to_world: Mat4,
 }
 
 pub struct LightSourceAliasTable {
@@ -23,33 +50,41 @@ pub struct LightSourceAliasTable {
     pub total_area: f32,
 }
 
-/// Builds a CDF of triangle areas computed in world space for Vose's alias method.
-/// See https://en.wikipedia.org/wiki/Alias_method.
-///
-/// The areas will be used to sample triangles that are part of meshes used as light sources.
-pub fn create_light_source_alias_table(
-    vk: Arc<Vk>,
+/// Returns the triangles of meshes used as light sources in world space. Degenerate triangles are
+/// left out since they can't be sampled.
+pub fn get_light_triangles(
     mesh_instances: &[MeshInstance],
     meshes: &[Arc<Mesh>],
     materials: &Materials,
-) -> Result<LightSourceAliasTable> {
+    textures: &Textures,
+) -> Result<Vec<LightTriangle>> {
     let light_sources: Vec<_> = mesh_instances
         .iter()
-        .filter(|mesh_instance| {
-            materials
-                .diffuse_light_material_indices
-                .contains_key(&meshes[mesh_instance.mesh_index].material)
+        .enumerate()
+        .filter(|(_, mesh_instance)| {
+            // Lights that aren't rendered don't light the scene either.
+            let mesh = &meshes[mesh_instance.mesh_index];
+            mesh.mask != 0 && materials.is_diffuse_light(mesh.material)
         })
         .collect();
 
     let light_count = light_sources.len();
 
-    let mut world_space_areas = Vec::with_capacity(1024);
+    let mut light_triangles = Vec::with_capacity(1024);
 
-    for light_source in light_sources {
+    for (instance_index, light_source) in light_sources {
         let mesh = meshes[light_source.mesh_index].as_ref();
         let indices = mesh.indices.as_slice();
         let vertices = mesh.vertices.as_slice();
+        let luminance = materials.get_emitted_luminance(mesh.material, textures);
+
+        let light_object_to_world = match light_source.object_to_world {
+            Transform::Static(ref t) => Ok(t.to_mat4()),
+            Transform::Animated { .. } => Err(anyhow!(
+                "Animated transform for light sources not implemented"
+            )),
+        }?;
+        let normal_to_world = light_object_to_world.inverse().transpose();
 
         for i in (0..mesh.indices.len()).step_by(3) {
             let primitive_index = i / 3;
@@ -60,39 +95,68 @@ pub fn create_light_source_alias_table(
                 indices[i + 2] as usize,
             ];
 
-            let light_object_to_world = match light_source.object_to_world {
-                Transform::Static(ref t) => Ok(t.to_mat4()),
-                Transform::Animated { .. } => Err(anyhow!(
-                    "Animated transform for light sources not implemented"
-                )),
-            }?;
-
-            let p = indices.map(|i| {
-                let v = vertices[i].p;
-                let v4 = [v[0], v[1], v[2], 1.0].into();
-                let w = light_object_to_world.mul_vec4(v4);
-                Vec3::new(w.x, w.y, w.z)
+            let p = indices.map(|i| light_object_to_world.transform_point3(vertices[i].p.into()));
+            let n = indices.map(|i| {
+                normal_to_world
+                    .transform_vector3(vertices[i].n.into())
+                    .normalize_or_zero()
             });
 
             let v0 = p[1] - p[0];
             let v1 = p[2] - p[0];
-            let area = 0.5 * v0.cross(v1).length();
+            let cross = v0.cross(v1);
+            let area = 0.5 * cross.length();
 
             // Discard degenerate triangles
             if area > 1e-8 {
-                world_space_areas.push(Area {
-                    value: area,
+                let mut normal_axis = cross.normalize();
+                if normal_axis.dot(n[0] + n[1] + n[2]) < 0.0 {
+                    normal_axis = -normal_axis;
+                }
+                let normal_cos_theta = n
+                    .iter()
+                    .filter(|n| **n != Vec3::ZERO)
+                    .fold(1.0_f32, |cos_theta, n| cos_theta.min(normal_axis.dot(*n)))
+                    .max(-1.0);
+
+                light_triangles.push(LightTriangle {
+                    area,
+                    power: area * luminance,
+                    vertices: p,
+                    normal_axis,
+                    normal_cos_theta,
                     mesh_index: light_source.mesh_index,
+                    instance_index,
                     primitive_index,
+                    object_to_world: light_object_to_world,
                 });
             }
         }
     }
 
-    let triangle_count = world_space_areas.len();
+    debug!(
+        "Found {} light source triangles with non-zero area in {} lights",
+        light_triangles.len(),
+        light_count
+    );
+
+    Ok(light_triangles)
+}
+
+/// Builds a CDF of triangle areas computed in world space for Vose's alias method.
+/// See https://en.wikipedia.org/wiki/Alias_method.
+///
+/// The areas will be used to sample triangles that are part of meshes used as light sources. Each
+/// entry holds the transforms of its light's instance so shaders can evaluate the light's emission
+/// at the sampled point for next event estimation. Entries are in the same order as the triangles.
+pub fn create_light_source_alias_table(
+    vk: Arc<Vk>,
+    light_triangles: &[LightTriangle],
+) -> Result<LightSourceAliasTable> {
+    let triangle_count = light_triangles.len();
 
     let (alias_table, total_area) = if triangle_count > 0 {
-        let (table, total) = build_alias_table(&world_space_areas);
+        let (table, total) = build_alias_table(light_triangles);
         debug_assert!(table.len() == triangle_count, "Alias table size mismatch");
         (table, total)
     } else {
@@ -103,14 +167,17 @@ pub fn create_light_source_alias_table(
             probability: 0.0,
             alias: 0,
             meshId: 0,
+            instanceId: 0,
             primitiveId: 0,
+            objectToWorld: to_mat4x3(Mat4::IDENTITY),
+            worldToObject: to_mat4x3(Mat4::IDENTITY),
         }];
         (table, 0.0)
     };
 
     debug!(
-        "Creating buffer for light source alias table: {} lights, total area: {}, {} triangles with non-zero area",
-        light_count, total_area, triangle_count
+        "Creating buffer for light source alias table: total area: {}, {} triangles",
+        total_area, triangle_count
     );
     let buffer = Buffer::from_iter(
         vk.memory_allocator.clone(),
@@ -133,15 +200,15 @@ pub fn create_light_source_alias_table(
     })
 }
 
-fn build_alias_table(areas: &[Area]) -> (Vec<ray_gen::LightSourceAliasTableEntry>, f32) {
+fn build_alias_table(areas: &[LightTriangle]) -> (Vec<ray_gen::LightSourceAliasTableEntry>, f32) {
     let n = areas.len();
     let total_area = areas
         .iter()
-        .fold(0.0_f64, |acc, area| acc + area.value as f64) as f32;
+        .fold(0.0_f64, |acc, area| acc + area.area as f64) as f32;
 
     let mut q = vec![0.0; n];
     for i in 0..n {
-        q[i] = areas[i].value * n as f32 / total_area;
+        q[i] = areas[i].area * n as f32 / total_area;
     }
 
     let mut small = Vec::new();
@@ -185,10 +252,18 @@ fn build_alias_table(areas: &[Area]) -> (Vec<ray_gen::LightSourceAliasTableEntry
                 probability: *probability,
                 alias: *alias,
                 meshId: areas[i].mesh_index as _,
+                instanceId: areas[i].instance_index as _,
                 primitiveId: areas[i].primitive_index as _,
+                objectToWorld: to_mat4x3(areas[i].object_to_world),
+                worldToObject: to_mat4x3(areas[i].object_to_world.inverse()),
             },
         )
         .collect();
 
     (alias_table, total_area)
 }
+
+/// Returns a matrix without its last row since shaders use 4x3 matrices.
+fn to_mat4x3(m: Mat4) -> [[f32; 3]; 4] {
+    m.to_cols_array_2d().map(|c| [c[0], c[1], c[2]])
+}
@@ -0,0 +1,242 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use anyhow::Result;
+use glam::{Quat, Vec3};
+use log::debug;
+use shaders::ray_gen::LightTreeNode;
+use vulkano::buffer::{BufferUsage, Subbuffer};
+
+use crate::{LightTriangle, Mesh, MeshInstance, Vk, create_device_local_buffer};
+
+/// Marks the absence of a node or offset. This must match `LIGHT_TREE_NONE` in the shaders.
+const LIGHT_TREE_NONE: u32 = u32::MAX;
+
+/// Bounding volume hierarchy over the light source triangles. Next event estimation descends it
+/// from the root to choose a triangle in proportion to an estimate of the light it contributes to
+/// the shading point, based on the power, distance and orientation of the triangles below each
+/// node, rather than by area alone. This makes sampling scenes with many lights practical since
+/// distant lights and lights facing away are rarely chosen.
+pub struct LightTree {
+    /// The nodes. The first node is the root. Leaves hold a single triangle.
+    pub nodes: Subbuffer<[LightTreeNode]>,
+
+    /// Offset of the primitives of each mesh instance in `leaves`, or `LIGHT_TREE_NONE` if the
+    /// instance isn't a light source.
+    pub instance_offsets: Subbuffer<[u32]>,
+
+    /// Leaf node of each primitive of the light sources, or `LIGHT_TREE_NONE` for degenerate
+    /// triangles. Shaders use this to find the density of sampling a light that a ray hit.
+    pub leaves: Subbuffer<[u32]>,
+}
+
+impl LightTree {
+    /// Build a light tree over the light source triangles. Leaves reference triangles by their
+    /// index, which is also their entry in the light source alias table.
+    pub fn new(
+        vk: Arc<Vk>,
+        light_triangles: &[LightTriangle],
+        mesh_instances: &[MeshInstance],
+        meshes: &[Arc<Mesh>],
+    ) -> Result<Self> {
+        let mut nodes = Vec::with_capacity(2 * light_triangles.len());
+        let mut triangle_leaves = vec![LIGHT_TREE_NONE; light_triangles.len()];
+
+        if light_triangles.is_empty() {
+            // Storage buffers can't be empty. The shaders don't sample lights without triangles.
+            nodes.push(LightBounds::EMPTY.to_node(LIGHT_TREE_NONE, 0, LIGHT_TREE_NONE));
+        } else {
+            let mut indices: Vec<u32> = (0..light_triangles.len() as u32).collect();
+            build_nodes(
+                light_triangles,
+                &mut indices,
+                LIGHT_TREE_NONE,
+                &mut nodes,
+                &mut triangle_leaves,
+            );
+        }
+
+        // Lay out the leaves of each light source instance's primitives so they can be looked up
+        // from a hit's instance and primitive.
+        let mut instance_offsets = vec![LIGHT_TREE_NONE; mesh_instances.len().max(1)];
+        let mut leaves = vec![];
+        for (triangle, leaf) in light_triangles.iter().zip(triangle_leaves) {
+            let offset = &mut instance_offsets[triangle.instance_index];
+            if *offset == LIGHT_TREE_NONE {
+                *offset = leaves.len() as u32;
+                let primitive_count = meshes[triangle.mesh_index].indices.len() / 3;
+                leaves.resize(leaves.len() + primitive_count, LIGHT_TREE_NONE);
+            }
+            leaves[(*offset as usize) + triangle.primitive_index] = leaf;
+        }
+        if leaves.is_empty() {
+            leaves.push(LIGHT_TREE_NONE);
+        }
+
+        debug!(
+            "Creating light tree: {} nodes, {} leaves",
+            nodes.len(),
+            leaves.len()
+        );
+
+        Ok(Self {
+            nodes: create_device_local_buffer(vk.clone(), BufferUsage::STORAGE_BUFFER, nodes)?,
+            instance_offsets: create_device_local_buffer(
+                vk.clone(),
+                BufferUsage::STORAGE_BUFFER,
+                instance_offsets,
+            )?,
+            leaves: create_device_local_buffer(vk, BufferUsage::STORAGE_BUFFER, leaves)?,
+        })
+    }
+}
+
+/// Bounds of the position, normals and power of a group of light source triangles.
+#[derive(Clone, Copy, Debug)]
+struct LightBounds {
+    /// Minimum corner of the bounding box.
+    bounds_min: Vec3,
+
+    /// Maximum corner of the bounding box.
+    bounds_max: Vec3,
+
+    /// Sum of the emitted power.
+    power: f32,
+
+    /// Axis of the cone bounding the normals.
+    normal_axis: Vec3,
+
+    /// Cosine of the half angle of the cone bounding the normals.
+    normal_cos_theta: f32,
+}
+
+impl LightBounds {
+    /// Bounds of no triangles.
+    const EMPTY: Self = Self {
+        bounds_min: Vec3::INFINITY,
+        bounds_max: Vec3::NEG_INFINITY,
+        power: 0.0,
+        normal_axis: Vec3::Z,
+        normal_cos_theta: 1.0,
+    };
+
+    /// Returns the bounds of a triangle.
+    fn from_triangle(triangle: &LightTriangle) -> Self {
+        let [p0, p1, p2] = triangle.vertices;
+        Self {
+            bounds_min: p0.min(p1).min(p2),
+            bounds_max: p0.max(p1).max(p2),
+            power: triangle.power,
+            normal_axis: triangle.normal_axis,
+            normal_cos_theta: triangle.normal_cos_theta,
+        }
+    }
+
+    /// Returns the bounds of both groups of triangles. The normal cone is the smallest cone
+    /// containing both cones. See https://pbr-book.org/4ed/Light_Sources/Light_Sampling.
+    fn union(&self, other: &Self) -> Self {
+        let theta_a = self.normal_cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_b = other.normal_cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_d = self
+            .normal_axis
+            .dot(other.normal_axis)
+            .clamp(-1.0, 1.0)
+            .acos();
+
+        let (normal_axis, normal_cos_theta) = if (theta_d + theta_b).min(PI) <= theta_a {
+            (self.normal_axis, self.normal_cos_theta)
+        } else if (theta_d + theta_a).min(PI) <= theta_b {
+            (other.normal_axis, other.normal_cos_theta)
+        } else {
+            let theta_o = 0.5 * (theta_a + theta_d + theta_b);
+            let rotation_axis = self.normal_axis.cross(other.normal_axis);
+            if theta_o >= PI || rotation_axis.length_squared() == 0.0 {
+                (self.normal_axis, -1.0)
+            } else {
+                let rotation = Quat::from_axis_angle(rotation_axis.normalize(), theta_o - theta_a);
+                (rotation * self.normal_axis, theta_o.cos())
+            }
+        };
+
+        Self {
+            bounds_min: self.bounds_min.min(other.bounds_min),
+            bounds_max: self.bounds_max.max(other.bounds_max),
+            power: self.power + other.power,
+            normal_axis,
+            normal_cos_theta,
+        }
+    }
+
+    /// Returns the node for shaders with these bounds.
+    fn to_node(self, parent: u32, left: u32, right: u32) -> LightTreeNode {
+        LightTreeNode {
+            boundsMin: self.bounds_min.to_array(),
+            power: self.power,
+            boundsMax: self.bounds_max.to_array(),
+            cosTheta: self.normal_cos_theta,
+            axis: self.normal_axis.to_array(),
+            parent,
+            left,
+            right,
+        }
+    }
+}
+
+/// Appends the nodes of the subtree over the given triangles and returns its root's index and
+/// bounds. Triangles are split at the median of their centroids along the longest axis of their
+/// bounds, like the BVH, so the tree's depth is logarithmic in the number of triangles.
+fn build_nodes(
+    light_triangles: &[LightTriangle],
+    indices: &mut [u32],
+    parent: u32,
+    nodes: &mut Vec<LightTreeNode>,
+    triangle_leaves: &mut [u32],
+) -> (u32, LightBounds) {
+    let index = nodes.len() as u32;
+
+    if let [triangle_index] = indices {
+        let bounds = LightBounds::from_triangle(&light_triangles[*triangle_index as usize]);
+        nodes.push(bounds.to_node(parent, *triangle_index, LIGHT_TREE_NONE));
+        triangle_leaves[*triangle_index as usize] = index;
+        return (index, bounds);
+    }
+
+    // Reserve the node so it precedes its children. It's filled in once their bounds are known.
+    nodes.push(LightBounds::EMPTY.to_node(parent, 0, 0));
+
+    let centroid = |i: &u32| {
+        let [p0, p1, p2] = light_triangles[*i as usize].vertices;
+        (p0 + p1 + p2) / 3.0
+    };
+    let (centroid_min, centroid_max) = indices
+        .iter()
+        .map(centroid)
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), c| {
+            (min.min(c), max.max(c))
+        });
+    let extent = centroid_max - centroid_min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    let mid = indices.len() / 2;
+    indices.select_nth_unstable_by(mid, |a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+    let (left_indices, right_indices) = indices.split_at_mut(mid);
+
+    let (left, left_bounds) =
+        build_nodes(light_triangles, left_indices, index, nodes, triangle_leaves);
+    let (right, right_bounds) = build_nodes(
+        light_triangles,
+        right_indices,
+        index,
+        nodes,
+        triangle_leaves,
+    );
+
+    let bounds = left_bounds.union(&right_bounds);
+    nodes[index as usize] = bounds.to_node(parent, left, right);
+    (index, bounds)
+}
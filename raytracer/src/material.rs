@@ -1,25 +1,63 @@
 use std::{collections::HashMap, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 use log::debug;
-use scene_file::Material;
+use scene_file::{Clearcoat, Material, MaterialVariation};
 use shaders::ray_gen;
 use vulkano::buffer::{BufferUsage, Subbuffer};
 
-use crate::{Vk, create_device_local_buffer, textures::Textures};
+use crate::{
+    Mesh, MeshInstance, Vk,
+    colour::{blackbody_to_rgb, get_luminance},
+    create_device_local_buffer,
+    textures::Textures,
+};
 
-// NOTE: Update Materials::to_shader() when adding new materials.
+// NOTE: Update Materials::new() when adding new materials.
 pub const MAT_TYPE_NONE: u32 = 0;
 pub const MAT_TYPE_LAMBERTIAN: u32 = 1;
 pub const MAT_TYPE_METAL: u32 = 2;
 pub const MAT_TYPE_DIELECTRIC: u32 = 3;
 pub const MAT_TYPE_DIFFUSE_LIGHT: u32 = 4;
+pub const MAT_TYPE_PBR: u32 = 5;
 
 pub const MAT_PROP_VALUE_TYPE_RGB: u32 = 0;
 pub const MAT_PROP_VALUE_TYPE_IMAGE: u32 = 1;
 pub const MAT_PROP_VALUE_TYPE_CHECKER: u32 = 2;
 pub const MAT_PROP_VALUE_TYPE_NOISE: u32 = 3;
+pub const MAT_PROP_VALUE_TYPE_SCALAR: u32 = 4;
+pub const MAT_PROP_VALUE_TYPE_NONE: u32 = 5;
 
+// NOTE: These must match the IMAGE_CHANNEL_* constants in the shaders.
+pub const IMAGE_CHANNEL_RGB: u32 = 0;
+pub const IMAGE_CHANNEL_R: u32 = 1;
+pub const IMAGE_CHANNEL_G: u32 = 2;
+pub const IMAGE_CHANNEL_B: u32 = 3;
+pub const IMAGE_CHANNEL_A: u32 = 4;
+pub const IMAGE_CHANNEL_LUMINANCE: u32 = 5;
+
+// NOTE: These must match the STOCHASTIC_TILING_* constants in the shaders.
+pub const STOCHASTIC_TILING_NONE: u32 = u32::MAX;
+pub const STOCHASTIC_TILING_LUT_SIZE: u32 = 256;
+
+/// Refractive index of PBR materials that don't give one, which is the default of glTF.
+const DEFAULT_PBR_IOR: f32 = 1.5;
+
+/// Typed handle of a material in the scene file, resolved from its name once when the scene is
+/// loaded so rendering never looks materials up by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(usize);
+
+impl MaterialId {
+    /// Returns the index of the material in the scene file's materials.
+    pub fn get_index(&self) -> usize {
+        self.0
+    }
+}
+
+/// The scene's materials grouped by type. The materials of each type are sorted by name, so a
+/// scene always gets the same indices in the storage buffers however its materials are ordered.
+/// Copies made for material variations follow the named materials.
 #[derive(Debug)]
 pub struct Materials {
     /// The lambertian materials. This will be used to create the storage buffers for shaders.
@@ -34,85 +72,177 @@ pub struct Materials {
     /// The diffuse light materials. This will be used to create the storage buffers for shaders.
     pub diffuse_light_materials: Vec<ray_gen::DiffuseLightMaterial>,
 
-    /// Maps unique lambertian materials to their index in `lambertian_materials`. These indices
-    /// are used in the Mesh structure to be referenced in the storage buffers.
-    pub lambertian_material_indices: HashMap<String, u32>,
-
-    /// Maps unique metal materials to their index in `metal_materials`. These indices
-    /// are used in the Mesh structure to be referenced in the storage buffers.
-    pub metal_material_indices: HashMap<String, u32>,
+    /// The PBR materials. This will be used to create the storage buffers for shaders.
+    pub pbr_materials: Vec<ray_gen::PbrMaterial>,
 
-    /// Maps unique dielectric materials to their index in `dielectric_materials`. These indices
-    /// are used in the Mesh structure to be referenced in the storage buffers.
-    pub dielectric_material_indices: HashMap<String, u32>,
+    /// Maps material names to their handles. Names are only used to resolve the references in
+    /// the scene file when it is loaded.
+    pub ids: HashMap<String, MaterialId>,
 
-    /// Maps unique diffuse light materials to their index in `diffuse_light_materials`. These indices
-    /// are used in the Mesh structure to be referenced in the storage buffers.
-    pub diffuse_light_material_indices: HashMap<String, u32>,
+    /// Type and index in the storage buffer of that type of each material, indexed by its handle.
+    /// These are used in the Mesh structure to be referenced in the storage buffers.
+    types_and_indices: Vec<MaterialAndIndex>,
 }
 
 impl Materials {
-    pub fn new(materials: &[Material], textures: &Textures) -> Self {
+    /// Creates the materials for shaders and resolves the textures they reference. This fails if
+    /// a material references a texture that doesn't exist.
+    pub fn new(materials: &[Material], textures: &Textures) -> Result<Self> {
         let mut lambertian_materials = vec![];
         let mut metal_materials = vec![];
         let mut dielectric_materials = vec![];
         let mut diffuse_light_materials = vec![];
+        let mut pbr_materials = vec![];
 
-        let mut lambertian_material_indices = HashMap::new();
-        let mut metal_material_indices = HashMap::new();
-        let mut dielectric_material_indices = HashMap::new();
-        let mut diffuse_light_material_indices = HashMap::new();
+        let mut ids = HashMap::new();
+        let mut types_and_indices = vec![MaterialAndIndex::new(MAT_TYPE_NONE, 0); materials.len()];
 
-        for material in materials.iter() {
-            match material {
-                Material::Lambertian { name, albedo } => {
-                    lambertian_material_indices
-                        .insert(name.clone(), lambertian_materials.len() as _);
+        // Handles keep the order of the scene file while the storage buffers are sorted by name.
+        let mut sorted: Vec<(usize, &Material)> = materials.iter().enumerate().collect();
+        sorted.sort_by_key(|(_, material)| material.get_name());
 
+        for (index, material) in sorted {
+            let name = material.get_name();
+            let texture = |texture_name: &str| {
+                textures
+                    .get_id(texture_name)
+                    .map(|id| id.to_shader())
+                    .with_context(|| format!("Material '{name}' references an unknown texture"))
+            };
+
+            let type_and_index = match material {
+                Material::Lambertian {
+                    albedo,
+                    normal_map,
+                    clearcoat,
+                    ..
+                } => {
                     lambertian_materials.push(ray_gen::LambertianMaterial {
-                        albedo: textures.to_shader(albedo).unwrap(),
+                        albedo: texture(albedo)?,
+                        normalMap: normal_map.as_deref().map_or(Ok(no_texture()), texture)?,
+                        clearcoat: clearcoat_to_shader(clearcoat.as_ref()),
                     });
+                    MaterialAndIndex::new(
+                        MAT_TYPE_LAMBERTIAN,
+                        (lambertian_materials.len() - 1) as _,
+                    )
                 }
-                Material::Metal { name, albedo, fuzz } => {
-                    metal_material_indices.insert(name.clone(), metal_materials.len() as _);
-
+                Material::Metal {
+                    albedo,
+                    fuzz,
+                    fuzz_v,
+                    fuzz_rotation,
+                    normal_map,
+                    clearcoat,
+                    ..
+                } => {
                     metal_materials.push(ray_gen::MetalMaterial {
-                        albedo: textures.to_shader(albedo).unwrap(),
-                        fuzz: textures.to_shader(fuzz).unwrap(),
+                        albedo: texture(albedo)?,
+                        fuzz: texture(fuzz)?,
+                        fuzzV: texture(fuzz_v.as_deref().unwrap_or(fuzz))?,
+                        fuzzRotation: fuzz_rotation.unwrap_or(0.0).to_radians(),
+                        normalMap: normal_map.as_deref().map_or(Ok(no_texture()), texture)?,
+                        clearcoat: clearcoat_to_shader(clearcoat.as_ref()),
+                    });
+                    MaterialAndIndex::new(MAT_TYPE_METAL, (metal_materials.len() - 1) as _)
+                }
+                Material::Pbr {
+                    base_color,
+                    metallic,
+                    roughness,
+                    ior,
+                    anisotropy,
+                    anisotropy_rotation,
+                    normal_map,
+                    ..
+                } => {
+                    pbr_materials.push(ray_gen::PbrMaterial {
+                        baseColour: texture(base_color)?,
+                        metallic: texture(metallic)?,
+                        roughness: texture(roughness)?,
+                        normalMap: normal_map.as_deref().map_or(Ok(no_texture()), texture)?,
+                        ior: ior.unwrap_or(DEFAULT_PBR_IOR),
+                        anisotropy: anisotropy.unwrap_or(0.0).clamp(0.0, 1.0),
+                        anisotropyRotation: anisotropy_rotation.unwrap_or(0.0).to_radians(),
                     });
+                    MaterialAndIndex::new(MAT_TYPE_PBR, (pbr_materials.len() - 1) as _)
                 }
                 Material::Dielectric {
-                    name,
                     refraction_index,
+                    priority,
+                    clearcoat,
+                    ..
                 } => {
-                    dielectric_material_indices
-                        .insert(name.clone(), dielectric_materials.len() as _);
-
                     dielectric_materials.push(ray_gen::DielectricMaterial {
                         refractionIndex: *refraction_index,
+                        priority: priority.unwrap_or(0),
+                        clearcoat: clearcoat_to_shader(clearcoat.as_ref()),
                     });
+                    MaterialAndIndex::new(
+                        MAT_TYPE_DIELECTRIC,
+                        (dielectric_materials.len() - 1) as _,
+                    )
                 }
-                Material::DiffuseLight { name, emit } => {
-                    diffuse_light_material_indices
-                        .insert(name.clone(), diffuse_light_materials.len() as _);
-
+                Material::DiffuseLight {
+                    emit,
+                    temperature_kelvin,
+                    ..
+                } => {
                     diffuse_light_materials.push(ray_gen::DiffuseLightMaterial {
-                        emit: textures.to_shader(emit).unwrap(),
+                        emit: texture(emit)?,
+                        tint: temperature_to_shader(*temperature_kelvin),
                     });
+                    MaterialAndIndex::new(
+                        MAT_TYPE_DIFFUSE_LIGHT,
+                        (diffuse_light_materials.len() - 1) as _,
+                    )
                 }
-            }
+            };
+
+            ids.insert(name.to_string(), MaterialId(index));
+            types_and_indices[index] = type_and_index;
         }
 
-        Materials {
+        Ok(Materials {
             lambertian_materials,
             metal_materials,
             dielectric_materials,
             diffuse_light_materials,
-            lambertian_material_indices,
-            metal_material_indices,
-            dielectric_material_indices,
-            diffuse_light_material_indices,
+            pbr_materials,
+            ids,
+            types_and_indices,
+        })
+    }
+
+    /// Returns the handle of the named material. This fails if there is no such material.
+    pub fn get_id(&self, name: &str) -> Result<MaterialId> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("Material '{name}' not found"))
+    }
+
+    /// Returns true if the material emits light.
+    pub fn is_diffuse_light(&self, id: MaterialId) -> bool {
+        self.to_shader(id).material_type == MAT_TYPE_DIFFUSE_LIGHT
+    }
+
+    /// Returns an estimate of the luminance a material emits, used to weigh light sources by their
+    /// power. Emission from textures other than constant colours varies over the surface so it
+    /// counts as a luminance of 1. Materials that don't emit light return 0.
+    pub fn get_emitted_luminance(&self, id: MaterialId, textures: &Textures) -> f32 {
+        let material = self.to_shader(id);
+        if material.material_type != MAT_TYPE_DIFFUSE_LIGHT {
+            return 0.0;
         }
+
+        let light = &self.diffuse_light_materials[material.material_index as usize];
+        let emit = if light.emit.propValueType == MAT_PROP_VALUE_TYPE_RGB {
+            get_luminance(textures.constant_colour_textures.colours[light.emit.index as usize])
+        } else {
+            1.0
+        };
+        emit * get_luminance(light.tint)
     }
 
     /// Create a storage buffers for accessing materials in shader code.
@@ -135,6 +265,8 @@ impl Materials {
                         propValueType: 0,
                         index: 0,
                     },
+                    normalMap: no_texture(),
+                    clearcoat: clearcoat_to_shader(None),
                 }]
             },
         )?;
@@ -155,6 +287,13 @@ impl Materials {
                         propValueType: 0,
                         index: 0,
                     },
+                    fuzzV: ray_gen::MaterialPropertyValue {
+                        propValueType: 0,
+                        index: 0,
+                    },
+                    fuzzRotation: 0.0,
+                    normalMap: no_texture(),
+                    clearcoat: clearcoat_to_shader(None),
                 }]
             },
         )?;
@@ -168,6 +307,8 @@ impl Materials {
             } else {
                 vec![ray_gen::DielectricMaterial {
                     refractionIndex: 1.0,
+                    priority: 0,
+                    clearcoat: clearcoat_to_shader(None),
                 }]
             },
         )?;
@@ -184,6 +325,35 @@ impl Materials {
                         propValueType: 0,
                         index: 0,
                     },
+                    tint: [1.0, 1.0, 1.0],
+                }]
+            },
+        )?;
+
+        debug!("Creating PBR materials buffer");
+        let pbr_materials_buffer = create_device_local_buffer(
+            vk.clone(),
+            buffer_usage,
+            if !self.pbr_materials.is_empty() {
+                self.pbr_materials.clone()
+            } else {
+                vec![ray_gen::PbrMaterial {
+                    baseColour: ray_gen::MaterialPropertyValue {
+                        propValueType: 0,
+                        index: 0,
+                    },
+                    metallic: ray_gen::MaterialPropertyValue {
+                        propValueType: 0,
+                        index: 0,
+                    },
+                    roughness: ray_gen::MaterialPropertyValue {
+                        propValueType: 0,
+                        index: 0,
+                    },
+                    normalMap: no_texture(),
+                    ior: DEFAULT_PBR_IOR,
+                    anisotropy: 0.0,
+                    anisotropyRotation: 0.0,
                 }]
             },
         )?;
@@ -193,25 +363,166 @@ impl Materials {
             metal: metal_materials_buffer,
             dielectric: dielectric_materials_buffer,
             diffuse_light: diffuse_light_materials_buffer,
+            pbr: pbr_materials_buffer,
         })
     }
 
-    pub fn to_shader(&self, material: &str) -> MaterialAndIndex {
-        // Material names are unique across all materials.
-        if let Some(index) = self.lambertian_material_indices.get(material) {
-            MaterialAndIndex::new(MAT_TYPE_LAMBERTIAN, *index)
-        } else if let Some(index) = self.metal_material_indices.get(material) {
-            MaterialAndIndex::new(MAT_TYPE_METAL, *index)
-        } else if let Some(index) = self.dielectric_material_indices.get(material) {
-            MaterialAndIndex::new(MAT_TYPE_DIELECTRIC, *index)
-        } else if let Some(index) = self.diffuse_light_material_indices.get(material) {
-            MaterialAndIndex::new(MAT_TYPE_DIFFUSE_LIGHT, *index)
-        } else {
-            MaterialAndIndex::new(MAT_TYPE_NONE, 0)
+    /// Adds a copy of a material with a material variation applied for the array modifier copy
+    /// with the given index. The copy has no handle so it can only be referenced by the returned
+    /// index.
+    pub fn add_variant(
+        &mut self,
+        id: MaterialId,
+        material: &Material,
+        variation: &MaterialVariation,
+        index: u64,
+        textures: &mut Textures,
+    ) -> MaterialAndIndex {
+        // Texture references were checked when the materials were created.
+        let get_id =
+            |textures: &Textures, texture_name: &str| textures.get_id(texture_name).unwrap();
+        let mut vary = |texture_name: &str| {
+            let texture = get_id(textures, texture_name);
+            textures.to_shader_with_variation(texture, variation, index)
+        };
+
+        match material {
+            Material::Lambertian {
+                albedo,
+                normal_map,
+                clearcoat,
+                ..
+            } => {
+                let albedo = vary(albedo);
+                self.lambertian_materials.push(ray_gen::LambertianMaterial {
+                    albedo,
+                    normalMap: normal_map.as_deref().map_or(no_texture(), |normal_map| {
+                        get_id(textures, normal_map).to_shader()
+                    }),
+                    clearcoat: clearcoat_to_shader(clearcoat.as_ref()),
+                });
+                MaterialAndIndex::new(
+                    MAT_TYPE_LAMBERTIAN,
+                    (self.lambertian_materials.len() - 1) as _,
+                )
+            }
+            Material::Metal {
+                albedo,
+                fuzz,
+                fuzz_v,
+                fuzz_rotation,
+                normal_map,
+                clearcoat,
+                ..
+            } => {
+                let albedo = vary(albedo);
+                self.metal_materials.push(ray_gen::MetalMaterial {
+                    albedo,
+                    fuzz: get_id(textures, fuzz).to_shader(),
+                    fuzzV: get_id(textures, fuzz_v.as_deref().unwrap_or(fuzz)).to_shader(),
+                    fuzzRotation: fuzz_rotation.unwrap_or(0.0).to_radians(),
+                    normalMap: normal_map.as_deref().map_or(no_texture(), |normal_map| {
+                        get_id(textures, normal_map).to_shader()
+                    }),
+                    clearcoat: clearcoat_to_shader(clearcoat.as_ref()),
+                });
+                MaterialAndIndex::new(MAT_TYPE_METAL, (self.metal_materials.len() - 1) as _)
+            }
+            Material::Pbr {
+                base_color,
+                metallic,
+                roughness,
+                ior,
+                anisotropy,
+                anisotropy_rotation,
+                normal_map,
+                ..
+            } => {
+                let base_colour = vary(base_color);
+                self.pbr_materials.push(ray_gen::PbrMaterial {
+                    baseColour: base_colour,
+                    metallic: get_id(textures, metallic).to_shader(),
+                    roughness: get_id(textures, roughness).to_shader(),
+                    normalMap: normal_map.as_deref().map_or(no_texture(), |normal_map| {
+                        get_id(textures, normal_map).to_shader()
+                    }),
+                    ior: ior.unwrap_or(DEFAULT_PBR_IOR),
+                    anisotropy: anisotropy.unwrap_or(0.0).clamp(0.0, 1.0),
+                    anisotropyRotation: anisotropy_rotation.unwrap_or(0.0).to_radians(),
+                });
+                MaterialAndIndex::new(MAT_TYPE_PBR, (self.pbr_materials.len() - 1) as _)
+            }
+            Material::Dielectric { .. } => self.to_shader(id),
+            Material::DiffuseLight {
+                emit,
+                temperature_kelvin,
+                ..
+            } => {
+                self.diffuse_light_materials
+                    .push(ray_gen::DiffuseLightMaterial {
+                        emit: vary(emit),
+                        tint: temperature_to_shader(*temperature_kelvin),
+                    });
+                MaterialAndIndex::new(
+                    MAT_TYPE_DIFFUSE_LIGHT,
+                    (self.diffuse_light_materials.len() - 1) as _,
+                )
+            }
         }
     }
+
+    /// Returns the type and index of a material in the storage buffer of that type.
+    pub fn to_shader(&self, id: MaterialId) -> MaterialAndIndex {
+        self.types_and_indices[id.0]
+    }
+}
+
+/// Returns the material of each mesh instance for shaders. Instances with a material variation
+/// get a copy of their mesh's material with derived constant colours, shared by instances of the
+/// same mesh and array modifier copy. Other instances use `MAT_TYPE_NONE` so the mesh's material is
+/// used. The list is empty if no instance has a material variation.
+pub fn get_instance_materials(
+    mesh_instances: &[MeshInstance],
+    meshes: &[Arc<Mesh>],
+    scene_materials: &[Material],
+    materials: &mut Materials,
+    textures: &mut Textures,
+) -> Vec<ray_gen::InstanceMaterial> {
+    if mesh_instances
+        .iter()
+        .all(|mesh_instance| mesh_instance.material_variation.is_none())
+    {
+        return vec![];
+    }
+
+    let mut variants: HashMap<(usize, u64), MaterialAndIndex> = HashMap::new();
+
+    mesh_instances
+        .iter()
+        .map(|mesh_instance| {
+            let variant = mesh_instance
+                .material_variation
+                .map(|(variation, index)| {
+                    let id = meshes[mesh_instance.mesh_index].material;
+                    let material = &scene_materials[id.get_index()];
+
+                    *variants
+                        .entry((mesh_instance.mesh_index, index))
+                        .or_insert_with(|| {
+                            materials.add_variant(id, material, &variation, index, textures)
+                        })
+                })
+                .unwrap_or(MaterialAndIndex::new(MAT_TYPE_NONE, 0));
+
+            ray_gen::InstanceMaterial {
+                materialType: variant.material_type,
+                materialIndex: variant.material_index,
+            }
+        })
+        .collect()
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct MaterialAndIndex {
     pub material_type: u32,
     pub material_index: u32,
@@ -232,4 +543,36 @@ pub struct MaterialBuffers {
     pub metal: Subbuffer<[ray_gen::MetalMaterial]>,
     pub dielectric: Subbuffer<[ray_gen::DielectricMaterial]>,
     pub diffuse_light: Subbuffer<[ray_gen::DiffuseLightMaterial]>,
+    pub pbr: Subbuffer<[ray_gen::PbrMaterial]>,
+}
+
+/// Returns a material's clearcoat layer for shaders. Materials without one get a layer with no
+/// intensity which shaders skip.
+fn clearcoat_to_shader(clearcoat: Option<&Clearcoat>) -> ray_gen::Clearcoat {
+    match clearcoat {
+        Some(clearcoat) => ray_gen::Clearcoat {
+            intensity: clearcoat.intensity.clamp(0.0, 1.0),
+            roughness: clearcoat.roughness.max(0.0),
+            tint: clearcoat.tint.unwrap_or([1.0, 1.0, 1.0]),
+        },
+        None => ray_gen::Clearcoat {
+            intensity: 0.0,
+            roughness: 0.0,
+            tint: [1.0, 1.0, 1.0],
+        },
+    }
+}
+
+/// Returns the texture reference for shaders of an optional texture that isn't set.
+fn no_texture() -> ray_gen::MaterialPropertyValue {
+    ray_gen::MaterialPropertyValue {
+        propValueType: MAT_PROP_VALUE_TYPE_NONE,
+        index: 0,
+    }
+}
+
+/// Returns the tint of a light for shaders, which is white for lights without a colour
+/// temperature.
+fn temperature_to_shader(temperature_kelvin: Option<f32>) -> [f32; 3] {
+    temperature_kelvin.map_or([1.0, 1.0, 1.0], blackbody_to_rgb)
 }
@@ -1,3 +1,6 @@
+use glam::Mat4;
+use scene_file::MaterialVariation;
+
 use crate::DecomposedTransform;
 
 /// Stores decomposed transformations for static or moving mesh instances.
@@ -35,6 +38,10 @@ pub struct MeshInstance {
 
     /// Transformation for this instance.
     pub object_to_world: Transform,
+
+    /// Material variation applied to this instance and the index of the array modifier copy it is
+    /// seeded with. The mesh's material is used as is if not set.
+    pub material_variation: Option<(MaterialVariation, u64)>,
 }
 
 impl MeshInstance {
@@ -43,6 +50,19 @@ impl MeshInstance {
         Self {
             mesh_index,
             object_to_world,
+            material_variation: None,
+        }
+    }
+
+    /// Returns the object-to-world matrix. For animated transforms, it interpolates the
+    /// transformation for time in [0, 1].
+    pub fn get_object_to_world_matrix(&self, time: f32) -> Mat4 {
+        match self.object_to_world {
+            Transform::Static(ref t) => t.to_mat4(),
+            Transform::Animated {
+                start: ref t0,
+                end: ref t1,
+            } => t0.lerp(t1, time).to_mat4(),
         }
     }
 
@@ -0,0 +1,274 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use exr::prelude::{
+    AnyChannel, AnyChannels, AttributeValue, Encoding, FlatSamples, Image, ImageAttributes,
+    IntegerBounds, Layer, LayerAttributes, SmallVec, Text, WritableImage,
+};
+use log::info;
+use scene_file::{SceneFile, fnv1a_hash};
+
+use crate::{
+    RANDOM_SEED,
+    blue_noise::{get_dither_offset, load_blue_noise},
+};
+
+/// The commit hash of the source tree the renderer was built from. This is set by the build script.
+pub const COMMIT_HASH: &str = env!("RAYTRACER_COMMIT_HASH");
+
+/// Render settings embedded in output images so they can be traced back to the exact settings
+/// that produced them.
+#[derive(Clone, Debug)]
+pub struct RenderMetadata {
+    /// Path of the scene file that was rendered.
+    pub scene_path: String,
+
+    /// FNV-1a hash of the scene file contents.
+    pub scene_hash: String,
+
+    /// Seed used for the host side random number generator.
+    pub seed: u64,
+
+    /// Samples per pixel per batch.
+    pub samples_per_pixel: u32,
+
+    /// Number of sample batches.
+    pub sample_batches: u32,
+
+    /// Maximum ray depth.
+    pub max_ray_depth: u32,
+
+    /// Factor the output resolution was multiplied by to get the render resolution.
+    pub supersample: f32,
+
+    /// Name of the camera used to render.
+    pub camera: String,
+
+    /// Commit hash of the renderer.
+    pub commit_hash: String,
+}
+
+impl RenderMetadata {
+    /// Collect metadata for a scene file that was loaded from `scene_path`.
+    pub fn new(scene_path: &str, scene_file: &SceneFile) -> Result<Self> {
+        let contents = std::fs::read(scene_path)
+            .with_context(|| format!("Unable to read scene file '{scene_path}'"))?;
+
+        Ok(Self {
+            scene_path: scene_path.to_string(),
+            scene_hash: format!("{:016x}", fnv1a_hash(&contents)),
+            seed: RANDOM_SEED,
+            samples_per_pixel: scene_file.render.samples_per_pixel,
+            sample_batches: scene_file.render.sample_batches,
+            max_ray_depth: scene_file.render.max_ray_depth,
+            supersample: scene_file.render.get_supersample(),
+            camera: scene_file.get_render_camera()?.get_name().to_string(),
+            commit_hash: COMMIT_HASH.to_string(),
+        })
+    }
+
+    /// Returns the metadata as key/value pairs used for image file text fields.
+    pub fn to_key_values(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "Software",
+                format!("raytracing-vulkan-rs {}", self.commit_hash),
+            ),
+            ("SceneFile", self.scene_path.clone()),
+            ("SceneHash", self.scene_hash.clone()),
+            ("Seed", self.seed.to_string()),
+            ("SamplesPerPixel", self.samples_per_pixel.to_string()),
+            ("SampleBatches", self.sample_batches.to_string()),
+            ("MaxRayDepth", self.max_ray_depth.to_string()),
+            ("Supersample", self.supersample.to_string()),
+            ("Camera", self.camera.clone()),
+            ("CommitHash", self.commit_hash.clone()),
+        ]
+    }
+}
+
+/// Pixels and arbitrary output variables (AOVs) read back from an offline render. All pixels are
+/// ordered row by row starting at the top left.
+#[derive(Clone, Debug)]
+pub struct RenderOutput {
+    /// Image width.
+    pub width: u32,
+
+    /// Image height.
+    pub height: u32,
+
+    /// Accumulated colour in linear space.
+    pub beauty: Vec<[f32; 4]>,
+
+    /// Average surface albedo at the first hit.
+    pub albedo: Vec<[f32; 3]>,
+
+    /// Average world space surface normal at the first hit.
+    pub normal: Vec<[f32; 3]>,
+
+    /// Average distance from the camera to the first hit.
+    pub depth: Vec<f32>,
+
+    /// Per-pixel sample variance of the colour.
+    pub variance: Vec<[f32; 3]>,
+}
+
+/// Writes linear RGBA pixels to an 8-bit sRGB PNG file. Colours are dithered with blue noise to
+/// avoid banding in smooth gradients. If metadata is provided, it will be stored as `tEXt` chunks.
+pub fn save_png(
+    path: &str,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+    metadata: Option<&RenderMetadata>,
+) -> Result<()> {
+    if pixels.len() != (width * height) as usize {
+        return Err(anyhow!(
+            "Pixel count {} does not match image size {width} x {height}",
+            pixels.len()
+        ));
+    }
+
+    let file = File::create(Path::new(path))
+        .with_context(|| format!("Unable to create output file '{path}'"))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    if let Some(metadata) = metadata {
+        for (key, value) in metadata.to_key_values() {
+            encoder.add_text_chunk(key.to_string(), value)?;
+        }
+    }
+
+    let blue_noise = load_blue_noise()?;
+    let data: Vec<u8> = pixels
+        .iter()
+        .enumerate()
+        .flat_map(|(i, p)| {
+            let i = i as u32;
+            let dither = get_dither_offset(&blue_noise, i % width, i / width);
+            [
+                to_srgb_u8(p[0], dither),
+                to_srgb_u8(p[1], dither),
+                to_srgb_u8(p[2], dither),
+                (p[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]
+        })
+        .collect();
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+
+    info!("Saved {width} x {height} image to {path}");
+    Ok(())
+}
+
+/// Writes the render output to a multi-layer OpenEXR file with `beauty`, `albedo`, `normal`,
+/// `depth` and `variance` layers. If metadata is provided, it will be stored as text attributes.
+pub fn save_exr(
+    path: &str,
+    output: &RenderOutput,
+    metadata: Option<&RenderMetadata>,
+) -> Result<()> {
+    let size = (output.width as usize, output.height as usize);
+
+    let layers = vec![
+        exr_layer(
+            "beauty",
+            size,
+            &["R", "G", "B", "A"],
+            output.beauty.iter().map(|p| p.to_vec()),
+        ),
+        exr_layer(
+            "albedo",
+            size,
+            &["R", "G", "B"],
+            output.albedo.iter().map(|p| p.to_vec()),
+        ),
+        exr_layer(
+            "normal",
+            size,
+            &["X", "Y", "Z"],
+            output.normal.iter().map(|p| p.to_vec()),
+        ),
+        exr_layer("depth", size, &["Z"], output.depth.iter().map(|d| vec![*d])),
+        exr_layer(
+            "variance",
+            size,
+            &["R", "G", "B"],
+            output.variance.iter().map(|p| p.to_vec()),
+        ),
+    ];
+
+    let mut attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+    if let Some(metadata) = metadata {
+        for (key, value) in metadata.to_key_values() {
+            let value = Text::new_or_none(&value)
+                .with_context(|| format!("Metadata {key} contains unsupported characters"))?;
+            attributes
+                .other
+                .insert(Text::from(key), AttributeValue::Text(value));
+        }
+    }
+
+    Image::from_layers(attributes, layers)
+        .write()
+        .to_file(path)
+        .with_context(|| format!("Unable to write output file '{path}'"))?;
+
+    info!(
+        "Saved {} x {} multi-layer image to {path}",
+        output.width, output.height
+    );
+    Ok(())
+}
+
+/// Creates an EXR layer with the given channel names from per-pixel channel values.
+fn exr_layer(
+    name: &str,
+    size: (usize, usize),
+    channel_names: &[&str],
+    pixels: impl Iterator<Item = Vec<f32>>,
+) -> Layer<AnyChannels<FlatSamples>> {
+    let mut samples = vec![Vec::with_capacity(size.0 * size.1); channel_names.len()];
+    for pixel in pixels {
+        for (channel, value) in samples.iter_mut().zip(pixel) {
+            channel.push(value);
+        }
+    }
+
+    let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = channel_names
+        .iter()
+        .zip(samples)
+        .map(|(channel_name, values)| AnyChannel::new(*channel_name, FlatSamples::F32(values)))
+        .collect();
+
+    Layer::new(
+        size,
+        LayerAttributes::named(name),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    )
+}
+
+/// Converts a colour component from linear light gamma to sRGB gamma. This matches
+/// `linearTosRGB` in the shaders.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v < 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts a linear colour component to an 8-bit sRGB value. The dither offset in [-0.5, 0.5) is
+/// added before rounding.
+fn to_srgb_u8(v: f32, dither: f32) -> u8 {
+    (linear_to_srgb(v.max(0.0)).clamp(0.0, 1.0) * 255.0 + dither)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
@@ -0,0 +1,347 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::Result;
+use glam::{Mat4, Vec3, Vec4};
+use scene_file::SceneFile;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::graphics::vertex_input::Vertex,
+};
+
+use crate::{Materials, Mesh, MeshInstance, Vk, create_camera};
+
+/// Colour of the ground grid lines.
+const GRID_COLOUR: [f32; 3] = [0.2, 0.2, 0.2];
+
+/// Colours of the X, Y and Z world axes.
+const AXIS_COLOURS: [[f32; 3]; 3] = [[1.0, 0.05, 0.05], [0.05, 1.0, 0.05], [0.05, 0.2, 1.0]];
+
+/// Colour of the light source icons.
+const LIGHT_COLOUR: [f32; 3] = [1.0, 0.8, 0.1];
+
+/// Colour of the camera frustums.
+const CAMERA_COLOUR: [f32; 3] = [0.1, 0.8, 1.0];
+
+/// Minimum number of grid cells from the origin to the edge of the grid.
+const MIN_GRID_CELLS: f32 = 5.0;
+
+/// Elements of the viewport overlay drawn over the rendered image to help with scene orientation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverlayElement {
+    /// Grid on the ground plane through the origin.
+    Grid,
+
+    /// World X, Y and Z axes in red, green and blue.
+    Axes,
+
+    /// Icons marking the center of each light source.
+    Lights,
+
+    /// Frustums of the scene's cameras other than the one being rendered.
+    Cameras,
+}
+
+/// Vertex of an overlay line.
+#[derive(BufferContents, Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct OverlayVertex {
+    /// Position in world space.
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+
+    /// Linear colour.
+    #[format(R32G32B32_SFLOAT)]
+    pub colour: [f32; 3],
+}
+
+impl OverlayVertex {
+    /// Create a vertex.
+    fn new(position: Vec3, colour: [f32; 3]) -> Self {
+        Self {
+            position: position.to_array(),
+            colour,
+        }
+    }
+}
+
+/// Lines of the viewport overlay. The lines are generated once for the scene and each element can
+/// be shown or hidden. Elements are hidden by default.
+pub struct Overlay {
+    /// Line vertices for each element other than the camera frustums, two per line. Elements
+    /// without any lines are left out.
+    lines: Vec<(OverlayElement, Subbuffer<[OverlayVertex]>)>,
+
+    /// Name and frustum line vertices of each of the scene's cameras.
+    camera_lines: Vec<(String, Subbuffer<[OverlayVertex]>)>,
+
+    /// Name of the camera being rendered, whose frustum isn't drawn.
+    active_camera: String,
+
+    /// Elements that are shown.
+    visible_elements: HashSet<OverlayElement>,
+}
+
+impl Overlay {
+    /// Create the overlay lines for a scene. The grid, icons and frustums are sized relative to the
+    /// scene's bounds so they are visible at any scale.
+    pub fn new(
+        vk: Arc<Vk>,
+        scene_file: &SceneFile,
+        mesh_instances: &[MeshInstance],
+        meshes: &[Arc<Mesh>],
+        materials: &Materials,
+        image_size: [u32; 2],
+    ) -> Result<Self> {
+        let (spacing, half_size) = get_grid_size(mesh_instances, meshes);
+
+        let elements = [
+            (OverlayElement::Grid, get_grid_lines(spacing, half_size)),
+            (OverlayElement::Axes, get_axes_lines(half_size / 2.0)),
+            (
+                OverlayElement::Lights,
+                get_light_lines(mesh_instances, meshes, materials, spacing / 2.0),
+            ),
+        ];
+
+        let mut lines = Vec::new();
+        for (element, vertices) in elements {
+            // We can't create buffers from empty lists.
+            if vertices.is_empty() {
+                continue;
+            }
+            lines.push((element, create_vertex_buffer(vk.clone(), vertices)?));
+        }
+
+        let camera_lines = scene_file
+            .cameras
+            .iter()
+            .map(|camera| {
+                let vertices = get_camera_lines(camera, image_size, spacing);
+                let buffer = create_vertex_buffer(vk.clone(), vertices)?;
+                Ok((camera.get_name().to_string(), buffer))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            lines,
+            camera_lines,
+            active_camera: scene_file.render.camera.clone(),
+            visible_elements: HashSet::new(),
+        })
+    }
+
+    /// Sets the name of the camera being rendered so the frustums of the other cameras are drawn.
+    pub fn set_active_camera(&mut self, name: &str) {
+        self.active_camera = name.to_string();
+    }
+
+    /// Returns true if an element is shown.
+    pub fn is_visible(&self, element: OverlayElement) -> bool {
+        self.visible_elements.contains(&element)
+    }
+
+    /// Shows or hides an element.
+    pub fn set_visible(&mut self, element: OverlayElement, is_visible: bool) {
+        if is_visible {
+            self.visible_elements.insert(element);
+        } else {
+            self.visible_elements.remove(&element);
+        }
+    }
+
+    /// Returns the line vertices of the elements that are shown.
+    pub fn get_visible_lines(&self) -> Vec<Subbuffer<[OverlayVertex]>> {
+        let lines = self
+            .lines
+            .iter()
+            .filter(|(element, _)| self.is_visible(*element))
+            .map(|(_, vertices)| vertices);
+
+        // The frustum of the camera being rendered would surround the whole image.
+        let camera_lines = self
+            .camera_lines
+            .iter()
+            .filter(|(name, _)| {
+                self.is_visible(OverlayElement::Cameras) && *name != self.active_camera
+            })
+            .map(|(_, vertices)| vertices);
+
+        lines.chain(camera_lines).cloned().collect()
+    }
+}
+
+/// Returns the spacing of the grid lines and the distance from the origin to the edge of the grid.
+/// The spacing is a power of 10 so there are a reasonable number of lines over the scene's bounds
+/// and the grid covers the bounds.
+fn get_grid_size(mesh_instances: &[MeshInstance], meshes: &[Arc<Mesh>]) -> (f32, f32) {
+    let extent = mesh_instances
+        .iter()
+        .flat_map(|mesh_instance| {
+            let object_to_world = mesh_instance.get_object_to_world_matrix(0.0);
+            meshes[mesh_instance.mesh_index]
+                .vertices
+                .iter()
+                .map(move |v| object_to_world.transform_point3(Vec3::from(v.p)))
+        })
+        .fold(0.0_f32, |extent, p| extent.max(p.x.abs()).max(p.z.abs()));
+
+    let extent = if extent > 0.0 && extent.is_finite() {
+        extent
+    } else {
+        1.0
+    };
+
+    let spacing = 10.0_f32.powf((extent / MIN_GRID_CELLS).log10().floor());
+    let half_size = (extent / spacing).ceil().max(MIN_GRID_CELLS) * spacing;
+    (spacing, half_size)
+}
+
+/// Returns the lines of a grid on the XZ plane centered at the origin.
+fn get_grid_lines(spacing: f32, half_size: f32) -> Vec<OverlayVertex> {
+    let cells = (half_size / spacing).round() as i32;
+    (-cells..=cells)
+        .flat_map(|i| {
+            let offset = i as f32 * spacing;
+            [
+                Vec3::new(offset, 0.0, -half_size),
+                Vec3::new(offset, 0.0, half_size),
+                Vec3::new(-half_size, 0.0, offset),
+                Vec3::new(half_size, 0.0, offset),
+            ]
+        })
+        .map(|p| OverlayVertex::new(p, GRID_COLOUR))
+        .collect()
+}
+
+/// Returns the lines of the world axes starting at the origin.
+fn get_axes_lines(length: f32) -> Vec<OverlayVertex> {
+    [Vec3::X, Vec3::Y, Vec3::Z]
+        .into_iter()
+        .zip(AXIS_COLOURS)
+        .flat_map(|(axis, colour)| {
+            [
+                OverlayVertex::new(Vec3::ZERO, colour),
+                OverlayVertex::new(axis * length, colour),
+            ]
+        })
+        .collect()
+}
+
+/// Returns a star shaped icon at the center of each mesh instance that is a light source.
+fn get_light_lines(
+    mesh_instances: &[MeshInstance],
+    meshes: &[Arc<Mesh>],
+    materials: &Materials,
+    size: f32,
+) -> Vec<OverlayVertex> {
+    let directions = [
+        Vec3::X,
+        Vec3::Y,
+        Vec3::Z,
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(1.0, 1.0, -1.0),
+        Vec3::new(1.0, -1.0, 1.0),
+        Vec3::new(-1.0, 1.0, 1.0),
+    ]
+    .map(|d| d.normalize() * size / 2.0);
+
+    mesh_instances
+        .iter()
+        .filter(|mesh_instance| {
+            let mesh = &meshes[mesh_instance.mesh_index];
+            materials.is_diffuse_light(mesh.material)
+        })
+        .filter_map(|mesh_instance| {
+            let mesh = &meshes[mesh_instance.mesh_index];
+            if mesh.vertices.is_empty() {
+                return None;
+            }
+
+            let object_to_world = mesh_instance.get_object_to_world_matrix(0.0);
+            let center = mesh
+                .vertices
+                .iter()
+                .map(|v| object_to_world.transform_point3(Vec3::from(v.p)))
+                .sum::<Vec3>()
+                / mesh.vertices.len() as f32;
+            Some(center)
+        })
+        .flat_map(|center| {
+            directions.into_iter().flat_map(move |d| {
+                [
+                    OverlayVertex::new(center - d, LIGHT_COLOUR),
+                    OverlayVertex::new(center + d, LIGHT_COLOUR),
+                ]
+            })
+        })
+        .collect()
+}
+
+/// Returns the frustum of a camera cut off at the given depth with a triangle above its top edge
+/// to show which way is up.
+fn get_camera_lines(
+    scene_camera: &scene_file::Camera,
+    image_size: [u32; 2],
+    depth: f32,
+) -> Vec<OverlayVertex> {
+    let camera = create_camera(scene_camera, image_size[0], image_size[1]);
+    let camera = camera.read().unwrap();
+    let view_inverse = camera.get_view_inverse_matrix();
+    let proj_inverse = camera.get_projection_inverse_matrix();
+
+    // Vulkan's normalized device coordinates have y pointing down so the top edge of the image is
+    // at y = -1.
+    let get_point = |x: f32, y: f32| get_frustum_point(view_inverse, proj_inverse, x, y, depth);
+    let eye = view_inverse.transform_point3(Vec3::ZERO);
+    let corners = [
+        get_point(-1.0, -1.0),
+        get_point(1.0, -1.0),
+        get_point(1.0, 1.0),
+        get_point(-1.0, 1.0),
+    ];
+    let up = [
+        get_point(-0.5, -1.1),
+        get_point(0.0, -1.5),
+        get_point(0.5, -1.1),
+    ];
+
+    let mut points = Vec::with_capacity(22);
+    for i in 0..4 {
+        points.extend([eye, corners[i], corners[i], corners[(i + 1) % 4]]);
+    }
+    points.extend([up[0], up[1], up[1], up[2], up[2], up[0]]);
+    points
+        .into_iter()
+        .map(|p| OverlayVertex::new(p, CAMERA_COLOUR))
+        .collect()
+}
+
+/// Creates a vertex buffer holding overlay lines. The list of vertices must not be empty.
+fn create_vertex_buffer(
+    vk: Arc<Vk>,
+    vertices: Vec<OverlayVertex>,
+) -> Result<Subbuffer<[OverlayVertex]>> {
+    let buffer = Buffer::from_iter(
+        vk.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        vertices,
+    )?;
+    Ok(buffer)
+}
+
+/// Returns the point in world space at the given distance from the camera in the direction of the
+/// given normalized device coordinates.
+fn get_frustum_point(view_inverse: Mat4, proj_inverse: Mat4, x: f32, y: f32, depth: f32) -> Vec3 {
+    let target = proj_inverse * Vec4::new(x, y, 1.0, 1.0);
+    let direction = target.truncate().normalize();
+    view_inverse.transform_point3(direction * depth)
+}
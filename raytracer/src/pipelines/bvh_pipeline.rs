@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    descriptor_set::layout::{DescriptorSetLayout, DescriptorSetLayoutCreateInfo},
+    device::Device,
+    pipeline::{
+        ComputePipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        compute::ComputePipelineCreateInfo, layout::PipelineLayoutCreateInfo,
+    },
+    shader::ShaderStages,
+};
+
+use super::rt_pipeline::{create_push_constant_range, create_set_layouts, storage_buffer_binding};
+
+/// The compute pipeline used to trace rays by traversing a BVH on devices that don't support
+/// raytracing pipelines. Apart from `BVH_LAYOUT`, its descriptor set layouts match the `*_LAYOUT`
+/// constants of `RtPipeline`.
+pub struct BvhPipeline {
+    /// The pipeline.
+    pipeline: Arc<ComputePipeline>,
+
+    /// The pipeline layout.
+    pipeline_layout: Arc<PipelineLayout>,
+}
+
+impl BvhPipeline {
+    /// Storage buffers for the BVH nodes, triangles and instances. This replaces the top-level
+    /// acceleration structure.
+    pub const BVH_LAYOUT: usize = 0;
+
+    /// Number of invocations in each dimension of a workgroup. This must match the `local_size_x`
+    /// and `local_size_y` of the shader.
+    pub const WORKGROUP_SIZE: u32 = 8;
+
+    /// Returns the pipeline.
+    pub fn get(&self) -> Arc<ComputePipeline> {
+        self.pipeline.clone()
+    }
+
+    /// Returns the pipeline layout.
+    pub fn get_layout(&self) -> Arc<PipelineLayout> {
+        self.pipeline_layout.clone()
+    }
+
+    /// Create a new BVH traversal compute pipeline.
+    pub fn new(
+        device: Arc<Device>,
+        stage: PipelineShaderStageCreateInfo,
+        image_texture_count: u32,
+    ) -> Result<Self> {
+        let pipeline_layout = PipelineLayout::new(
+            device.clone(),
+            PipelineLayoutCreateInfo {
+                set_layouts: create_set_layouts(
+                    device.clone(),
+                    create_bvh_layout(device.clone()),
+                    ShaderStages::COMPUTE,
+                    image_texture_count,
+                ),
+                push_constant_ranges: vec![create_push_constant_range(ShaderStages::COMPUTE)],
+                ..Default::default()
+            },
+        )?;
+
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, pipeline_layout.clone()),
+        )?;
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+        })
+    }
+}
+
+/// Create a pipeline layout for the BVH storage buffers.
+fn create_bvh_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+    DescriptorSetLayout::new(
+        device,
+        DescriptorSetLayoutCreateInfo {
+            bindings: [
+                (0, storage_buffer_binding(ShaderStages::COMPUTE)), // Nodes.
+                (1, storage_buffer_binding(ShaderStages::COMPUTE)), // Triangles.
+                (2, storage_buffer_binding(ShaderStages::COMPUTE)), // Instances.
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
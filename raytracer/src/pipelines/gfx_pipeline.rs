@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use foldhash::{HashSet, fast::RandomState};
+use shaders::fragment;
 use vulkano::{
     descriptor_set::layout::{
         DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
@@ -20,12 +21,16 @@ use vulkano::{
             vertex_input::VertexInputState,
             viewport::{Viewport, ViewportState},
         },
-        layout::{PipelineDescriptorSetLayoutCreateInfo, PipelineLayoutCreateInfo},
+        layout::{
+            PipelineDescriptorSetLayoutCreateInfo, PipelineLayoutCreateInfo, PushConstantRange,
+        },
     },
     render_pass::{RenderPass, Subpass},
     shader::ShaderStages,
 };
 
+use super::rt_pipeline::storage_buffer_binding;
+
 /// The graphics pipeline used for copying rendered image from RayTracingPipeline which is in
 /// linear colour space to the Swapchain which is using sRGB colour space.
 pub struct GfxPipeline {
@@ -42,7 +47,7 @@ pub struct GfxPipeline {
 impl GfxPipeline {
     // These make it easier to set the descriptor set layout.
 
-    /// Render image.
+    /// Render image, blue noise texture, probe image and luminance histogram.
     pub const RENDER_IMAGE_LAYOUT: usize = 0;
 
     /// Returns the pipeline.
@@ -102,7 +107,11 @@ impl GfxPipeline {
                     // The order should match the `*_LAYOUT` constants.
                     create_render_image_layout(device.clone()),
                 ],
-                push_constant_ranges: vec![],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::FRAGMENT,
+                    offset: 0,
+                    size: size_of::<fragment::FragmentPushConstants>() as _,
+                }],
                 ..Default::default()
             },
         )?;
@@ -141,21 +150,25 @@ impl GfxPipeline {
     }
 }
 
-/// Create a pipeline layout for the combined image + sampler for the render image.
+/// Create a pipeline layout for the combined image + samplers for the render image, the blue noise
+/// texture used for dithering and the probe image used to highlight the instance under the cursor,
+/// and the storage buffer for the luminance histogram.
 fn create_render_image_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+    let binding = DescriptorSetLayoutBinding {
+        descriptor_count: 1,
+        stages: ShaderStages::FRAGMENT,
+        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::CombinedImageSampler)
+    };
+
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
-            bindings: [(
-                0,
-                DescriptorSetLayoutBinding {
-                    descriptor_count: 1,
-                    stages: ShaderStages::FRAGMENT,
-                    ..DescriptorSetLayoutBinding::descriptor_type(
-                        DescriptorType::CombinedImageSampler,
-                    )
-                },
-            )]
+            bindings: [
+                (0, binding.clone()),                                // Render image.
+                (1, binding.clone()),                                // Blue noise.
+                (2, binding),                                        // Probe.
+                (3, storage_buffer_binding(ShaderStages::FRAGMENT)), // Histogram.
+            ]
             .into_iter()
             .collect(),
             ..Default::default()
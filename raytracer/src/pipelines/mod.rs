@@ -1,5 +1,17 @@
+mod bvh_pipeline;
+mod denoise_pipeline;
+mod downsample_pipeline;
 mod gfx_pipeline;
+mod histogram_pipeline;
+mod overlay_pipeline;
 mod rt_pipeline;
+mod splat_resolve_pipeline;
 
+pub use bvh_pipeline::*;
+pub use denoise_pipeline::*;
+pub use downsample_pipeline::*;
 pub use gfx_pipeline::*;
+pub use histogram_pipeline::*;
+pub use overlay_pipeline::*;
 pub use rt_pipeline::*;
+pub use splat_resolve_pipeline::*;
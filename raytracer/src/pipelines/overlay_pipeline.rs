@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use foldhash::{HashSet, fast::RandomState};
+use vulkano::{
+    device::Device,
+    pipeline::{
+        DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+};
+
+use crate::overlay::OverlayVertex;
+
+/// The graphics pipeline used for drawing the lines of the viewport overlay over the rendered
+/// image. It draws in the same subpass as the `GfxPipeline` after the rendered image is copied to
+/// the swapchain image.
+pub struct OverlayPipeline {
+    /// The pipeline.
+    pipeline: Arc<GraphicsPipeline>,
+
+    /// The pipeline layout.
+    pipeline_layout: Arc<PipelineLayout>,
+}
+
+impl OverlayPipeline {
+    // These make it easier to set the descriptor set layout.
+
+    /// Depth image used to fade lines hidden behind surfaces.
+    pub const DEPTH_IMAGE_LAYOUT: usize = 0;
+
+    /// Returns the pipeline.
+    pub fn get(&self) -> Arc<GraphicsPipeline> {
+        self.pipeline.clone()
+    }
+
+    /// Returns the pipeline layout.
+    pub fn get_layout(&self) -> Arc<PipelineLayout> {
+        self.pipeline_layout.clone()
+    }
+
+    /// Create a new overlay pipeline drawing in the first subpass of the given render pass.
+    pub fn new(
+        device: Arc<Device>,
+        stages: &[PipelineShaderStageCreateInfo],
+        render_pass: Arc<RenderPass>,
+    ) -> Result<Self> {
+        let descriptor_set_ci = PipelineDescriptorSetLayoutCreateInfo::from_stages(stages);
+        let layout_ci = descriptor_set_ci.into_pipeline_layout_create_info(device.clone())?;
+        let pipeline_layout = PipelineLayout::new(device.clone(), layout_ci)?;
+
+        let subpass = Subpass::from(render_pass, 0)
+            .with_context(|| "Failed to create overlay pipeline subpass from render pass")?;
+
+        let vertex_input_state = OverlayVertex::per_vertex().definition(&stages[0].entry_point)?;
+
+        let mut dynamic_state = HashSet::with_hasher(RandomState::default());
+        dynamic_state.insert(DynamicState::Viewport);
+
+        let pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState {
+                    topology: PrimitiveTopology::LineList,
+                    ..Default::default()
+                }),
+                viewport_state: Some(ViewportState::default()),
+                dynamic_state,
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState {
+                        blend: Some(AttachmentBlend::alpha()),
+                        ..Default::default()
+                    },
+                )),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(pipeline_layout.clone())
+            },
+        )?;
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+        })
+    }
+}
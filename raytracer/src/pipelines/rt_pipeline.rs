@@ -36,7 +36,7 @@ impl RtPipeline {
     /// Uniform buffer for the camera data.
     pub const CAMERA_BUFFER_LAYOUT: usize = 1;
 
-    /// Storage image used for rendering.
+    /// Storage images used for rendering and the blue noise texture.
     pub const RENDER_IMAGE_LAYOUT: usize = 2;
 
     /// Storage buffer used for mesh data.
@@ -57,7 +57,7 @@ impl RtPipeline {
     /// Uniform buffer for sky.
     pub const SKY_LAYOUT: usize = 8;
 
-    /// Storage buffer for light source alias table.
+    /// Storage buffers for the light source alias table and light tree.
     pub const LIGHT_SOURCE_ALIAS_TABLE: usize = 9;
 
     /// Returns the pipeline.
@@ -80,24 +80,13 @@ impl RtPipeline {
         let pipeline_layout = PipelineLayout::new(
             device.clone(),
             PipelineLayoutCreateInfo {
-                set_layouts: vec![
-                    // The order should match the `*_LAYOUT` constants.
-                    create_tlas_layout(device.clone()),
-                    create_camera_layout(device.clone()),
-                    create_render_image_layout(device.clone()),
-                    create_mesh_data_layout(device.clone()),
-                    create_sampler_and_image_textures_layout(device.clone(), image_texture_count),
-                    create_constant_colour_textures_layout(device.clone()),
-                    create_materials_layout(device.clone()),
-                    create_other_textures_layout(device.clone()),
-                    create_sky_layout(device.clone()),
-                    create_light_source_alias_table_layout(device.clone()),
-                ],
-                push_constant_ranges: vec![PushConstantRange {
-                    stages: ShaderStages::RAYGEN,
-                    offset: 0,
-                    size: size_of::<ray_gen::RayGenPushConstants>() as _,
-                }],
+                set_layouts: create_set_layouts(
+                    device.clone(),
+                    create_tlas_layout(device.clone(), ShaderStages::RAYGEN),
+                    ShaderStages::RAYGEN,
+                    image_texture_count,
+                ),
+                push_constant_ranges: vec![create_push_constant_range(ShaderStages::RAYGEN)],
                 ..Default::default()
             },
         )?;
@@ -120,13 +109,45 @@ impl RtPipeline {
     }
 }
 
+/// Create the descriptor set layouts for a pipeline that traces rays with the shaders in
+/// `path_tracer.glsl`. Only the first layout, which holds the scene geometry, differs between the
+/// raytracing and compute pipelines so it is passed in. The order matches the `*_LAYOUT` constants.
+pub(super) fn create_set_layouts(
+    device: Arc<Device>,
+    geometry_layout: Arc<DescriptorSetLayout>,
+    stages: ShaderStages,
+    image_texture_count: u32,
+) -> Vec<Arc<DescriptorSetLayout>> {
+    vec![
+        geometry_layout,
+        create_camera_layout(device.clone(), stages),
+        create_render_image_layout(device.clone(), stages),
+        create_mesh_data_layout(device.clone(), stages),
+        create_sampler_and_image_textures_layout(device.clone(), stages, image_texture_count),
+        create_constant_colour_textures_layout(device.clone(), stages),
+        create_materials_layout(device.clone(), stages),
+        create_other_textures_layout(device.clone(), stages),
+        create_sky_layout(device.clone(), stages),
+        create_light_source_alias_table_layout(device.clone(), stages),
+    ]
+}
+
+/// Create the push constant range for the path tracer push constants.
+pub(super) fn create_push_constant_range(stages: ShaderStages) -> PushConstantRange {
+    PushConstantRange {
+        stages,
+        offset: 0,
+        size: size_of::<ray_gen::RayGenPushConstants>() as _,
+    }
+}
+
 /// Create a pipeline layout for top level acceleration structure.
-fn create_tlas_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+fn create_tlas_layout(device: Arc<Device>, stages: ShaderStages) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device,
         DescriptorSetLayoutCreateInfo {
             #[rustfmt::skip]
-            bindings: [(0, as_binding(ShaderStages::RAYGEN))]
+            bindings: [(0, as_binding(stages))]
                 .into_iter()
                 .collect(),
             ..Default::default()
@@ -136,27 +157,43 @@ fn create_tlas_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
 }
 
 /// Create a pipeline layout for uniform buffer containing camera matrices.
-fn create_camera_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+fn create_camera_layout(device: Arc<Device>, stages: ShaderStages) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device,
         DescriptorSetLayoutCreateInfo {
-            bindings: [(0, uniform_buffer_binding(ShaderStages::RAYGEN))]
-                .into_iter()
-                .collect(),
+            bindings: [(0, uniform_buffer_binding(stages))].into_iter().collect(),
             ..Default::default()
         },
     )
     .unwrap()
 }
 
-/// Create a pipeline layout for the render image storage buffer.
-fn create_render_image_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+/// Create a pipeline layout for the render image, AOV, history and probe storage images, the blue
+/// noise texture, the material profile counters, the splat buffer and the pixel filter table.
+fn create_render_image_layout(
+    device: Arc<Device>,
+    stages: ShaderStages,
+) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
-            bindings: [(0, storage_image_binding(ShaderStages::RAYGEN))]
-                .into_iter()
-                .collect(),
+            bindings: [
+                (0, storage_image_binding(stages)),          // Render image.
+                (1, storage_image_binding(stages)),          // Albedo.
+                (2, storage_image_binding(stages)),          // Normal.
+                (3, storage_image_binding(stages)),          // Depth.
+                (4, storage_image_binding(stages)),          // Second moment.
+                (5, storage_image_binding(stages)),          // History colour.
+                (6, storage_image_binding(stages)),          // History depth.
+                (7, storage_image_binding(stages)),          // Reprojected history.
+                (8, combined_image_sampler_binding(stages)), // Blue noise.
+                (9, storage_image_binding(stages)),          // Probe.
+                (10, storage_buffer_binding(stages)),        // Material profile.
+                (11, storage_buffer_binding(stages)),        // Splat buffer.
+                (12, storage_buffer_binding(stages)),        // Pixel filter table.
+            ]
+            .into_iter()
+            .collect(),
             ..Default::default()
         },
     )
@@ -164,14 +201,15 @@ fn create_render_image_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
 }
 
 /// Create a pipeline layout for mesh data references storage buffer.
-fn create_mesh_data_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+fn create_mesh_data_layout(device: Arc<Device>, stages: ShaderStages) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
             bindings: [
-                (0, storage_buffer_binding(ShaderStages::RAYGEN)), // Vertex buffer.
-                (1, storage_buffer_binding(ShaderStages::RAYGEN)), // Index buffer.
-                (2, storage_buffer_binding(ShaderStages::RAYGEN)), // Meshes.
+                (0, storage_buffer_binding(stages)), // Vertex buffer.
+                (1, storage_buffer_binding(stages)), // Index buffer.
+                (2, storage_buffer_binding(stages)), // Meshes.
+                (3, storage_buffer_binding(stages)), // Instance materials.
             ]
             .into_iter()
             .collect(),
@@ -181,18 +219,31 @@ fn create_mesh_data_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
     .unwrap()
 }
 
-/// Create a pipeline layout for sampler and image textures.
+/// Create a pipeline layout for samplers and image textures. Each image texture has its own
+/// sampler. If variable descriptor counts are not enabled, the image texture array is sized by the
+/// layout instead. The sampler array is always sized by the layout since only the last binding can
+/// have a variable count.
 fn create_sampler_and_image_textures_layout(
     device: Arc<Device>,
+    stages: ShaderStages,
     image_texture_count: u32,
 ) -> Arc<DescriptorSetLayout> {
+    let image_textures_binding = if device
+        .enabled_features()
+        .descriptor_binding_variable_descriptor_count
+    {
+        variable_sampled_image_binding(stages, image_texture_count)
+    } else {
+        sampled_image_array_binding(stages, image_texture_count)
+    };
+
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
             #[rustfmt::skip]
             bindings: [
-                (0, sampler_binding(ShaderStages::RAYGEN)),
-                (1, variable_sampled_image_binding(ShaderStages::RAYGEN, image_texture_count)),
+                (0, sampler_array_binding(stages, image_texture_count.max(1))),
+                (1, image_textures_binding),
             ]
             .into_iter()
             .collect(),
@@ -202,14 +253,20 @@ fn create_sampler_and_image_textures_layout(
     .unwrap()
 }
 
-/// Create a pipeline layout for constant colour textures (this is just unique colour values).
-fn create_constant_colour_textures_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+/// Create a pipeline layout for constant colour and scalar textures (these are just unique values).
+fn create_constant_colour_textures_layout(
+    device: Arc<Device>,
+    stages: ShaderStages,
+) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
-            bindings: [(0, storage_buffer_binding(ShaderStages::RAYGEN))]
-                .into_iter()
-                .collect(),
+            bindings: [
+                (0, storage_buffer_binding(stages)), // Constant colours.
+                (1, storage_buffer_binding(stages)), // Scalar constants.
+            ]
+            .into_iter()
+            .collect(),
             ..Default::default()
         },
     )
@@ -217,15 +274,16 @@ fn create_constant_colour_textures_layout(device: Arc<Device>) -> Arc<Descriptor
 }
 
 /// Create a pipeline layout for material references storage buffer.
-fn create_materials_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+fn create_materials_layout(device: Arc<Device>, stages: ShaderStages) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
             bindings: [
-                (0, storage_buffer_binding(ShaderStages::RAYGEN)), // Lambertian materials.
-                (1, storage_buffer_binding(ShaderStages::RAYGEN)), // Metal materials.
-                (2, storage_buffer_binding(ShaderStages::RAYGEN)), // Dielectric materials.
-                (3, storage_buffer_binding(ShaderStages::RAYGEN)), // Diffuse light materials.
+                (0, storage_buffer_binding(stages)), // Lambertian materials.
+                (1, storage_buffer_binding(stages)), // Metal materials.
+                (2, storage_buffer_binding(stages)), // Dielectric materials.
+                (3, storage_buffer_binding(stages)), // Diffuse light materials.
+                (4, storage_buffer_binding(stages)), // PBR materials.
             ]
             .into_iter()
             .collect(),
@@ -236,13 +294,18 @@ fn create_materials_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
 }
 
 /// Create a pipeline layout for storage buffer used for other textures besides image and constant colour.
-fn create_other_textures_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+fn create_other_textures_layout(
+    device: Arc<Device>,
+    stages: ShaderStages,
+) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
             bindings: [
-                (0, storage_buffer_binding(ShaderStages::RAYGEN)), // Checker textures.
-                (1, storage_buffer_binding(ShaderStages::RAYGEN)), // Noise textures.
+                (0, storage_buffer_binding(stages)), // Checker textures.
+                (1, storage_buffer_binding(stages)), // Noise textures.
+                (2, storage_buffer_binding(stages)), // Image texture channels.
+                (3, storage_buffer_binding(stages)), // Image texture stochastic tiling.
             ]
             .into_iter()
             .collect(),
@@ -253,27 +316,33 @@ fn create_other_textures_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout>
 }
 
 /// Create a pipeline layout for uniform buffer containing sky.
-fn create_sky_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+fn create_sky_layout(device: Arc<Device>, stages: ShaderStages) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device,
         DescriptorSetLayoutCreateInfo {
-            bindings: [(0, uniform_buffer_binding(ShaderStages::RAYGEN))]
-                .into_iter()
-                .collect(),
+            bindings: [(0, uniform_buffer_binding(stages))].into_iter().collect(),
             ..Default::default()
         },
     )
     .unwrap()
 }
 
-/// Create a pipeline layout for light source alias table storage buffer.
-fn create_light_source_alias_table_layout(device: Arc<Device>) -> Arc<DescriptorSetLayout> {
+/// Create a pipeline layout for the light source alias table and light tree storage buffers.
+fn create_light_source_alias_table_layout(
+    device: Arc<Device>,
+    stages: ShaderStages,
+) -> Arc<DescriptorSetLayout> {
     DescriptorSetLayout::new(
         device.clone(),
         DescriptorSetLayoutCreateInfo {
-            bindings: [(0, storage_buffer_binding(ShaderStages::RAYGEN))]
-                .into_iter()
-                .collect(),
+            bindings: [
+                (0, storage_buffer_binding(stages)), // Light source alias table.
+                (1, storage_buffer_binding(stages)), // Light tree nodes.
+                (2, storage_buffer_binding(stages)), // Light tree instance offsets.
+                (3, storage_buffer_binding(stages)), // Light tree leaves.
+            ]
+            .into_iter()
+            .collect(),
             ..Default::default()
         },
     )
@@ -301,9 +370,17 @@ fn storage_image_binding(stages: ShaderStages) -> DescriptorSetLayoutBinding {
     }
 }
 
-fn sampler_binding(stages: ShaderStages) -> DescriptorSetLayoutBinding {
+fn combined_image_sampler_binding(stages: ShaderStages) -> DescriptorSetLayoutBinding {
     DescriptorSetLayoutBinding {
         stages,
+        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::CombinedImageSampler)
+    }
+}
+
+fn sampler_array_binding(stages: ShaderStages, count: u32) -> DescriptorSetLayoutBinding {
+    DescriptorSetLayoutBinding {
+        stages,
+        descriptor_count: count,
         ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::Sampler)
     }
 }
@@ -317,7 +394,15 @@ fn variable_sampled_image_binding(stages: ShaderStages, count: u32) -> Descripto
     }
 }
 
-fn storage_buffer_binding(stages: ShaderStages) -> DescriptorSetLayoutBinding {
+fn sampled_image_array_binding(stages: ShaderStages, count: u32) -> DescriptorSetLayoutBinding {
+    DescriptorSetLayoutBinding {
+        stages,
+        descriptor_count: count,
+        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::SampledImage)
+    }
+}
+
+pub(super) fn storage_buffer_binding(stages: ShaderStages) -> DescriptorSetLayoutBinding {
     DescriptorSetLayoutBinding {
         stages,
         ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    device::Device,
+    pipeline::{
+        ComputePipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        compute::ComputePipelineCreateInfo, layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+};
+
+/// The compute pipeline used to combine the accumulated image with the light splatted to the film.
+pub struct SplatResolvePipeline {
+    /// The pipeline.
+    pipeline: Arc<ComputePipeline>,
+
+    /// The pipeline layout.
+    pipeline_layout: Arc<PipelineLayout>,
+}
+
+impl SplatResolvePipeline {
+    // These make it easier to set the descriptor set layout.
+
+    /// Accumulated image, splat storage buffer and resolved image.
+    pub const SPLAT_RESOLVE_LAYOUT: usize = 0;
+
+    /// Number of invocations in each dimension of a workgroup. This must match the `local_size_x`
+    /// and `local_size_y` of the shader.
+    pub const WORKGROUP_SIZE: u32 = 16;
+
+    /// Returns the pipeline.
+    pub fn get(&self) -> Arc<ComputePipeline> {
+        self.pipeline.clone()
+    }
+
+    /// Returns the pipeline layout.
+    pub fn get_layout(&self) -> Arc<PipelineLayout> {
+        self.pipeline_layout.clone()
+    }
+
+    /// Create a new splat resolve compute pipeline.
+    pub fn new(device: Arc<Device>, stage: PipelineShaderStageCreateInfo) -> Result<Self> {
+        let descriptor_set_ci = PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage]);
+        let layout_ci = descriptor_set_ci.into_pipeline_layout_create_info(device.clone())?;
+        let pipeline_layout = PipelineLayout::new(device.clone(), layout_ci)?;
+
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, pipeline_layout.clone()),
+        )?;
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+        })
+    }
+}
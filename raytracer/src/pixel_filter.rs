@@ -0,0 +1,117 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use anyhow::Result;
+use scene_file::PixelFilter;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+use crate::Vk;
+
+/// Number of entries in the table mapping uniform random numbers to offsets from the pixel centre.
+const TABLE_SIZE: usize = 256;
+
+/// Number of segments the filter is integrated over to build the table.
+const INTEGRATION_STEPS: usize = 4096;
+
+/// Standard deviation in pixels of the Gaussian filter.
+const GAUSSIAN_STD_DEV: f32 = 0.5;
+
+/// Table for importance sampling a pixel reconstruction filter. The filters are separable, so the
+/// shaders map a stratified random number per axis through the same table to get the offset of a
+/// sample from the pixel centre. Samples are distributed like the filter and keep equal weights.
+pub struct PixelFilterTable {
+    /// Offsets in pixels below which the fraction `i / (TABLE_SIZE - 1)` of the filter's weight
+    /// lies, for each entry `i`. The shaders interpolate between entries.
+    buffer: Subbuffer<[f32]>,
+}
+
+impl PixelFilterTable {
+    /// Create the table for the given filter and upload it to a storage buffer.
+    pub fn new(vk: Arc<Vk>, filter: PixelFilter) -> Result<Self> {
+        let buffer = Buffer::from_iter(
+            vk.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            get_inverse_cdf(filter),
+        )?;
+
+        Ok(Self { buffer })
+    }
+
+    /// Returns the buffer holding the table.
+    pub fn get_buffer(&self) -> Subbuffer<[f32]> {
+        self.buffer.clone()
+    }
+}
+
+/// Returns the distance from the pixel centre in pixels beyond which the filter's weight is 0.
+fn get_radius(filter: PixelFilter) -> f32 {
+    match filter {
+        PixelFilter::Box => 0.5,
+        PixelFilter::Tent => 1.0,
+        PixelFilter::Gaussian => 3.0 * GAUSSIAN_STD_DEV,
+        PixelFilter::BlackmanHarris => 2.0,
+    }
+}
+
+/// Returns the weight of the filter along one axis at an offset in pixels from the pixel centre
+/// within its radius.
+fn get_weight(filter: PixelFilter, offset: f32) -> f32 {
+    let radius = get_radius(filter);
+    match filter {
+        PixelFilter::Box => 1.0,
+        PixelFilter::Tent => (radius - offset.abs()).max(0.0),
+        PixelFilter::Gaussian => {
+            // The value at the radius is subtracted so the filter falls to 0 without a step.
+            let gaussian = |x: f32| (-x * x / (2.0 * GAUSSIAN_STD_DEV * GAUSSIAN_STD_DEV)).exp();
+            (gaussian(offset) - gaussian(radius)).max(0.0)
+        }
+        PixelFilter::BlackmanHarris => {
+            let x = 2.0 * PI * (offset / (2.0 * radius) + 0.5);
+            (0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos())
+                .max(0.0)
+        }
+    }
+}
+
+/// Returns the inverse of the filter's cumulative distribution sampled at `TABLE_SIZE` evenly
+/// spaced fractions from 0 to 1.
+fn get_inverse_cdf(filter: PixelFilter) -> Vec<f32> {
+    let radius = get_radius(filter);
+    let step = 2.0 * radius / INTEGRATION_STEPS as f32;
+
+    // Integrate with the trapezoidal rule. `cdf[i]` is the weight below `-radius + i * step`.
+    let mut cdf = Vec::with_capacity(INTEGRATION_STEPS + 1);
+    cdf.push(0.0);
+    let mut total = 0.0;
+    for i in 0..INTEGRATION_STEPS {
+        let x0 = -radius + i as f32 * step;
+        total += 0.5 * step * (get_weight(filter, x0) + get_weight(filter, x0 + step));
+        cdf.push(total);
+    }
+
+    (0..TABLE_SIZE)
+        .map(|entry| {
+            let fraction = entry as f32 / (TABLE_SIZE - 1) as f32 * total;
+            let i = cdf
+                .partition_point(|&weight| weight < fraction)
+                .clamp(1, INTEGRATION_STEPS);
+            let (below, above) = (cdf[i - 1], cdf[i]);
+            let t = if above > below {
+                (fraction - below) / (above - below)
+            } else {
+                0.0
+            };
+            -radius + (i as f32 - 1.0 + t) * step
+        })
+        .collect()
+}
@@ -0,0 +1,261 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::Result;
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyImageToBufferInfo, PrimaryAutoCommandBuffer},
+    image::view::ImageView,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+use crate::{Vk, render_engine::create_accumulated_render_image_view};
+
+/// Resolution of the probe pass. This is independent of the render resolution so the cost of the
+/// pass and of reading it back stays small.
+pub const PROBE_RESOLUTION: [u32; 2] = [256, 144];
+
+/// Number of buffers the probe image is copied to. Each buffer is read a few frames after it was
+/// written so the GPU is done with it by then.
+const PROBE_READBACK_BUFFER_COUNT: usize = 3;
+
+/// Depth and instance seen through the center of each pixel of the probe pass.
+#[derive(Clone, Debug)]
+pub struct ProbeResult {
+    /// Width of the probe image.
+    pub width: u32,
+
+    /// Height of the probe image.
+    pub height: u32,
+
+    /// Distance from the camera to the first hit, row by row. This is 0 where the ray missed.
+    pub depth: Vec<f32>,
+
+    /// Index of the mesh instance hit, row by row.
+    pub instance: Vec<Option<u32>>,
+
+    /// Index of the mesh hit, row by row.
+    pub mesh: Vec<Option<u32>>,
+}
+
+impl ProbeResult {
+    /// Create the result from the probe image pixels. The shader stores the depth followed by the
+    /// instance and mesh indices plus one so 0 means the ray missed.
+    fn from_pixels(width: u32, height: u32, pixels: &[[f32; 4]]) -> Self {
+        let get_index = |value: f32| (value >= 1.0).then(|| value as u32 - 1);
+        Self {
+            width,
+            height,
+            depth: pixels.iter().map(|p| p[0]).collect(),
+            instance: pixels.iter().map(|p| get_index(p[1])).collect(),
+            mesh: pixels.iter().map(|p| get_index(p[2])).collect(),
+        }
+    }
+
+    /// Returns the index of the probe pixel at the given position in [0, 1]^2 of the rendered
+    /// image, or `None` if the position is outside the image.
+    fn get_pixel_index(&self, uv: [f32; 2]) -> Option<usize> {
+        if !(0.0..=1.0).contains(&uv[0]) || !(0.0..=1.0).contains(&uv[1]) {
+            return None;
+        }
+        let x = ((uv[0] * self.width as f32) as u32).min(self.width - 1);
+        let y = ((uv[1] * self.height as f32) as u32).min(self.height - 1);
+        Some((y * self.width + x) as usize)
+    }
+
+    /// Returns the mesh instance seen at the given position in [0, 1]^2 of the rendered image.
+    pub fn get_instance(&self, uv: [f32; 2]) -> Option<u32> {
+        self.get_pixel_index(uv).and_then(|i| self.instance[i])
+    }
+
+    /// Returns the distance to the surface seen at the given position in [0, 1]^2 of the rendered
+    /// image.
+    pub fn get_depth(&self, uv: [f32; 2]) -> Option<f32> {
+        self.get_pixel_index(uv)
+            .filter(|&i| self.instance[i].is_some())
+            .map(|i| self.depth[i])
+    }
+
+    /// Returns the distance to focus on for the given position in [0, 1]^2 of the rendered image.
+    /// This is the median depth of the surfaces hit within a window around it so a single pixel
+    /// on an edge doesn't pull the focus to the background.
+    ///
+    /// * `uv` - Position to focus on.
+    /// * `radius` - Half the size of the window in probe pixels.
+    pub fn get_focus_distance(&self, uv: [f32; 2], radius: u32) -> Option<f32> {
+        let center = self.get_pixel_index(uv)? as u32;
+        let (cx, cy) = (center % self.width, center / self.width);
+
+        let mut depths: Vec<f32> = (cy.saturating_sub(radius)..=(cy + radius).min(self.height - 1))
+            .flat_map(|y| {
+                (cx.saturating_sub(radius)..=(cx + radius).min(self.width - 1))
+                    .map(move |x| (y * self.width + x) as usize)
+            })
+            .filter(|&i| self.instance[i].is_some())
+            .map(|i| self.depth[i])
+            .collect();
+
+        if depths.is_empty() {
+            return None;
+        }
+        let mid = depths.len() / 2;
+        let (_, median, _) = depths.select_nth_unstable_by(mid, f32::total_cmp);
+        Some(*median)
+    }
+
+    /// Returns statistics about what the camera sees.
+    pub fn get_statistics(&self) -> ProbeStatistics {
+        let hits: Vec<usize> = (0..self.instance.len())
+            .filter(|&i| self.instance[i].is_some())
+            .collect();
+
+        let depths = hits.iter().map(|&i| self.depth[i]);
+        let (min_depth, max_depth) = depths
+            .clone()
+            .fold(None, |acc: Option<(f32, f32)>, d| {
+                Some(acc.map_or((d, d), |(min, max)| (min.min(d), max.max(d))))
+            })
+            .unwrap_or((0.0, 0.0));
+        let mean_depth = if hits.is_empty() {
+            0.0
+        } else {
+            depths.sum::<f32>() / hits.len() as f32
+        };
+
+        ProbeStatistics {
+            coverage: hits.len() as f32 / self.instance.len().max(1) as f32,
+            min_depth,
+            max_depth,
+            mean_depth,
+            visible_instances: self.instance.iter().flatten().collect::<HashSet<_>>().len(),
+            visible_meshes: self.mesh.iter().flatten().collect::<HashSet<_>>().len(),
+        }
+    }
+}
+
+/// Statistics about what the camera sees, gathered from the probe pass.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProbeStatistics {
+    /// Fraction of the image covered by geometry. The rest shows the sky.
+    pub coverage: f32,
+
+    /// Distance to the nearest surface seen.
+    pub min_depth: f32,
+
+    /// Distance to the farthest surface seen.
+    pub max_depth: f32,
+
+    /// Mean distance to the surfaces seen.
+    pub mean_depth: f32,
+
+    /// Number of mesh instances seen.
+    pub visible_instances: usize,
+
+    /// Number of meshes with at least one instance seen.
+    pub visible_meshes: usize,
+}
+
+/// Low resolution pass tracing one ray through the center of each pixel to find the depth and
+/// instance seen through it. The results are copied to a ring of host visible buffers and read
+/// back once the GPU is done with them, so they are a few frames old but reading them never
+/// stalls rendering.
+pub struct Probe {
+    /// Image the probe pass writes to.
+    image_view: Arc<ImageView>,
+
+    /// Buffers the probe image is copied to.
+    readback_buffers: Vec<Subbuffer<[[f32; 4]]>>,
+
+    /// Whether a copy to the buffer with the same index has been recorded but not read yet.
+    is_pending: Vec<bool>,
+
+    /// Buffer to read from and copy to next. This is the oldest one.
+    next_buffer: usize,
+
+    /// Most recent result read back.
+    result: Option<ProbeResult>,
+}
+
+impl Probe {
+    /// Create the probe image and readback buffers.
+    pub fn new(vk: Arc<Vk>) -> Result<Self> {
+        let [width, height] = PROBE_RESOLUTION;
+        let image_view = create_accumulated_render_image_view(vk.clone(), width, height)?;
+
+        let readback_buffers = (0..PROBE_READBACK_BUFFER_COUNT)
+            .map(|_| {
+                Buffer::new_slice::<[f32; 4]>(
+                    vk.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                            | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                        ..Default::default()
+                    },
+                    (width * height) as DeviceSize,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            image_view,
+            readback_buffers,
+            is_pending: vec![false; PROBE_READBACK_BUFFER_COUNT],
+            next_buffer: 0,
+            result: None,
+        })
+    }
+
+    /// Returns the image the probe pass writes to.
+    pub fn get_image_view(&self) -> Arc<ImageView> {
+        self.image_view.clone()
+    }
+
+    /// Returns the most recent result read back, if any.
+    pub fn get_result(&self) -> Option<&ProbeResult> {
+        self.result.as_ref()
+    }
+
+    /// Reads the oldest pending copy of the probe image if the GPU is done with it. Returns true if
+    /// there is a new result.
+    pub fn poll(&mut self) -> bool {
+        if !self.is_pending[self.next_buffer] {
+            return false;
+        }
+
+        // Reading fails while the buffer is still in use by the GPU, in which case the previous
+        // result is kept and this is tried again next frame.
+        let [width, height] = PROBE_RESOLUTION;
+        match self.readback_buffers[self.next_buffer].read() {
+            Ok(pixels) => {
+                self.result = Some(ProbeResult::from_pixels(width, height, &pixels));
+                self.is_pending[self.next_buffer] = false;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Records a copy of the probe image to the next readback buffer. Nothing is recorded if that
+    /// buffer hasn't been read yet so the copy doesn't have to wait for the GPU.
+    pub fn record_readback(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        if self.is_pending[self.next_buffer] {
+            return Ok(());
+        }
+
+        builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            self.image_view.image().clone(),
+            self.readback_buffers[self.next_buffer].clone(),
+        ))?;
+
+        self.is_pending[self.next_buffer] = true;
+        self.next_buffer = (self.next_buffer + 1) % PROBE_READBACK_BUFFER_COUNT;
+        Ok(())
+    }
+}
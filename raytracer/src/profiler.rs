@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo, PrimaryAutoCommandBuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+use crate::Vk;
+
+/// Number of counter slots for each kind of count. These are indexed by the material type used in
+/// shaders with the last slot counting rays that missed everything. This must match
+/// `PROFILE_SLOT_COUNT` in the shaders.
+const PROFILE_SLOT_COUNT: usize = 7;
+
+/// Names of the counter slots.
+const PROFILE_SLOT_NAMES: [&str; PROFILE_SLOT_COUNT] = [
+    "No material",
+    "Lambertian",
+    "Metal",
+    "Dielectric",
+    "Diffuse light",
+    "PBR",
+    "Sky",
+];
+
+/// Number of buffers the counters are copied to. Each buffer is read a few frames after it was
+/// written so the GPU is done with it by then.
+const PROFILE_READBACK_BUFFER_COUNT: usize = 3;
+
+/// Counts of the rays that hit a material type.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialTypeProfile {
+    /// Rays that hit the material type. Each one costs a traversal and shading.
+    pub hits: u64,
+
+    /// Hits that scattered another ray, which costs another traversal.
+    pub scattered: u64,
+}
+
+/// Counts of the rays that hit each material type while profiling. The counts are a proxy for the
+/// time spent on each material type since the cost of a path is dominated by tracing its rays.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialProfile {
+    /// Counts for each slot.
+    counts: [MaterialTypeProfile; PROFILE_SLOT_COUNT],
+}
+
+impl MaterialProfile {
+    /// Returns the total number of rays counted.
+    pub fn get_total_hits(&self) -> u64 {
+        self.counts.iter().map(|count| count.hits).sum()
+    }
+
+    /// Returns the counts of the material types that were hit, most hit first. Rays that missed
+    /// everything are listed as "Sky".
+    pub fn get_entries(&self) -> Vec<(&'static str, MaterialTypeProfile)> {
+        let mut entries: Vec<_> = PROFILE_SLOT_NAMES
+            .into_iter()
+            .zip(self.counts)
+            .filter(|(_, count)| count.hits > 0)
+            .collect();
+        entries.sort_by(|(_, a), (_, b)| b.hits.cmp(&a.hits));
+        entries
+    }
+
+    /// Adds counts read back from the GPU. The hits of every slot are followed by the scattered
+    /// rays of every slot.
+    fn add(&mut self, counters: &[u32]) {
+        for (i, count) in self.counts.iter_mut().enumerate() {
+            count.hits += counters[i] as u64;
+            count.scattered += counters[PROFILE_SLOT_COUNT + i] as u64;
+        }
+    }
+}
+
+/// Counters of the rays that hit each material type. The shaders count into a device buffer which
+/// is copied to a ring of host visible buffers and cleared after each copy, so the counters never
+/// overflow and reading them never stalls rendering.
+pub struct MaterialProfiler {
+    /// Buffer the shaders count into.
+    counters: Subbuffer<[u32]>,
+
+    /// Buffers the counters are copied to.
+    readback_buffers: Vec<Subbuffer<[u32]>>,
+
+    /// Whether a copy to the buffer with the same index has been recorded but not read yet.
+    is_pending: Vec<bool>,
+
+    /// Buffer to read from and copy to next. This is the oldest one.
+    next_buffer: usize,
+
+    /// Whether the counters have to be cleared before they are used again.
+    needs_clear: bool,
+
+    /// Counts read back since the profile was last reset.
+    profile: MaterialProfile,
+}
+
+impl MaterialProfiler {
+    /// Create the counters and readback buffers.
+    pub fn new(vk: Arc<Vk>) -> Result<Self> {
+        let size = (2 * PROFILE_SLOT_COUNT) as DeviceSize;
+
+        let counters = Buffer::new_slice::<u32>(
+            vk.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER
+                    | BufferUsage::TRANSFER_SRC
+                    | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            size,
+        )?;
+
+        let readback_buffers = (0..PROFILE_READBACK_BUFFER_COUNT)
+            .map(|_| {
+                Buffer::new_slice::<u32>(
+                    vk.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                            | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                        ..Default::default()
+                    },
+                    size,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            counters,
+            readback_buffers,
+            is_pending: vec![false; PROFILE_READBACK_BUFFER_COUNT],
+            next_buffer: 0,
+            needs_clear: true,
+            profile: MaterialProfile::default(),
+        })
+    }
+
+    /// Returns the buffer the shaders count into.
+    pub fn get_counters(&self) -> Subbuffer<[u32]> {
+        self.counters.clone()
+    }
+
+    /// Returns the counts read back since the profile was last reset.
+    pub fn get_profile(&self) -> &MaterialProfile {
+        &self.profile
+    }
+
+    /// Discards the counts gathered so far, including copies that haven't been read yet.
+    pub fn reset(&mut self) {
+        self.profile = MaterialProfile::default();
+        self.is_pending.fill(false);
+        self.needs_clear = true;
+    }
+
+    /// Adds the oldest pending copy of the counters to the profile if the GPU is done with it.
+    pub fn poll(&mut self) {
+        if !self.is_pending[self.next_buffer] {
+            return;
+        }
+
+        // Reading fails while the buffer is still in use by the GPU, in which case this is tried
+        // again next frame.
+        if let Ok(counters) = self.readback_buffers[self.next_buffer].read() {
+            self.profile.add(&counters);
+            self.is_pending[self.next_buffer] = false;
+        }
+    }
+
+    /// Records clearing the counters if they were copied since they were last cleared. This must
+    /// be recorded before the rays are traced.
+    pub fn record_clear(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        if self.needs_clear {
+            builder.fill_buffer(self.counters.clone(), 0)?;
+            self.needs_clear = false;
+        }
+        Ok(())
+    }
+
+    /// Records a copy of the counters to the next readback buffer. Nothing is recorded if that
+    /// buffer hasn't been read yet, in which case the counters keep accumulating until the next
+    /// copy.
+    pub fn record_readback(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        if self.is_pending[self.next_buffer] {
+            return Ok(());
+        }
+
+        builder.copy_buffer(CopyBufferInfo::buffers(
+            self.counters.clone(),
+            self.readback_buffers[self.next_buffer].clone(),
+        ))?;
+
+        self.is_pending[self.next_buffer] = true;
+        self.next_buffer = (self.next_buffer + 1) % PROFILE_READBACK_BUFFER_COUNT;
+        self.needs_clear = true;
+        Ok(())
+    }
+}
@@ -1,63 +1,329 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
+    time::Instant,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
+use glam::{Mat4, Vec3};
 use random::Random;
-use scene_file::SceneFile;
-use shaders::{GfxShaderModules, RtShaderModules, ray_gen};
+use scene_file::{
+    MAX_SAMPLE_BATCHES, MAX_SAMPLES_PER_PIXEL, ScalingPolicy, SceneFile, Sky, TextureFilter,
+};
+use shaders::{
+    GfxShaderModules, HistogramShaderModules, OverlayShaderModules, fragment, overlay_vertex,
+    ray_gen,
+};
 use vulkano::{
-    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage},
+    DeviceSize,
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
+        AutoCommandBufferBuilder, ClearColorImageInfo, CommandBufferUsage, CopyImageInfo,
+        CopyImageToBufferInfo, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
         RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
     },
     descriptor_set::{DescriptorSet, WriteDescriptorSet},
-    format::Format,
+    format::{Format, NumericFormat},
     image::{
         Image, ImageAspects, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage,
         SampleCount,
-        sampler::{Sampler, SamplerAddressMode, SamplerCreateInfo},
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
         view::{ImageView, ImageViewCreateInfo, ImageViewType},
     },
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
-    pipeline::{PipelineBindPoint, graphics::viewport::Viewport, ray_tracing::ShaderBindingTable},
+    pipeline::{PipelineBindPoint, graphics::viewport::Viewport},
     render_pass::{Framebuffer, FramebufferCreateInfo},
     sync::GpuFuture,
 };
 
 use crate::{
-    Camera, Materials, Mesh, MeshInstance, Transform, Vk,
-    acceleration::AccelerationStructures,
-    create_light_source_alias_table, create_mesh_index_buffer, create_mesh_storage_buffer,
-    create_mesh_vertex_buffer,
-    pipelines::{GfxPipeline, RtPipeline},
-    textures::Textures,
+    BufferLayouts, Camera, CancellationToken, FilmResponse, Materials, Mesh, MeshInstance,
+    OverlayElement, PROBE_RESOLUTION, RenderHooks, RenderOutput, RenderProgress, SceneBuildStats,
+    Transform, Vk,
+    budget::{BuildDeadline, fit_memory_budget},
+    create_instance_material_buffer, create_light_source_alias_table, create_mesh_index_buffer,
+    create_mesh_storage_buffer, create_mesh_vertex_buffer,
+    denoise::Denoiser,
+    downsample::{Downsampler, get_supersampled_size},
+    get_instance_materials, get_light_triangles,
+    light_tree::LightTree,
+    overlay::Overlay,
+    pipelines::{GfxPipeline, HistogramPipeline, OverlayPipeline, RtPipeline},
+    pixel_filter::PixelFilterTable,
+    probe::{Probe, ProbeResult},
+    profiler::{MaterialProfile, MaterialProfiler},
+    splat::SplatBuffer,
+    textures::{BlueNoiseTexture, ImageTextures, Textures},
+    tracer::Tracer,
 };
 
+/// Seed used for the host side random number generator so renders are reproducible.
+pub const RANDOM_SEED: u64 = 485_674_845_675_491;
+
+/// Size of the magnifier in swapchain pixels.
+const MAGNIFIER_SIZE: f32 = 256.0;
+
+/// Size of a pixel of the rendered image in the magnifier, in swapchain pixels.
+const MAGNIFIER_ZOOM: f32 = 4.0;
+
+/// Distance between the cursor and the magnifier in swapchain pixels.
+const MAGNIFIER_CURSOR_GAP: f32 = 16.0;
+
+/// Number of frames that can be in flight at once, each presenting its own copy of the
+/// accumulated image.
+const FRAMES_IN_FLIGHT: usize = 2;
+
+/// Pattern of pixels traced per sample batch. Patterns other than `Full` only trace a subset of
+/// pixels per batch and reconstruct the rest from previous batches which makes each batch cheaper
+/// while navigating.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplingPattern {
+    /// Trace every pixel in every batch.
+    #[default]
+    Full,
+
+    /// Trace alternating halves of a checkerboard.
+    Checkerboard,
+
+    /// Trace one pixel of each 2x2 block.
+    Interleaved,
+}
+
+impl SamplingPattern {
+    /// Returns the number of sample batches needed to trace every pixel once.
+    pub fn get_period(&self) -> u32 {
+        match self {
+            Self::Full => 1,
+            Self::Checkerboard => 2,
+            Self::Interleaved => 4,
+        }
+    }
+
+    /// Returns the next sampling pattern. This is used to cycle through the patterns.
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Full => Self::Checkerboard,
+            Self::Checkerboard => Self::Interleaved,
+            Self::Interleaved => Self::Full,
+        }
+    }
+
+    /// Returns the value used in shaders.
+    fn to_shader(self) -> u32 {
+        match self {
+            Self::Full => 0,
+            Self::Checkerboard => 1,
+            Self::Interleaved => 2,
+        }
+    }
+}
+
+/// Estimator for the light scattered at each hit. Every estimator converges to the same image so
+/// rendering with two of them and comparing the results checks that a sampling strategy is
+/// unbiased.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Estimator {
+    /// One sample mixture of material and light source sampling.
+    Mixture,
+
+    /// Material sampling only. Lights only contribute when a scattered ray hits them by chance.
+    Material,
+
+    /// Next event estimation. A shadow ray is traced to a sampled point on the light sources at
+    /// each diffuse hit, and combined with material sampling by multiple importance sampling.
+    /// Lights are chosen with the light tree in proportion to their estimated contribution to the
+    /// hit.
+    #[default]
+    NextEvent,
+}
+
+impl Estimator {
+    /// Returns the value used in shaders.
+    fn to_shader(self) -> u32 {
+        match self {
+            Self::Mixture => 0,
+            Self::Material => 1,
+            Self::NextEvent => 2,
+        }
+    }
+}
+
+/// Visualisation of the exposure of the presented image used to judge exposure and clipping.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnalysisMode {
+    /// Show the image as is.
+    #[default]
+    None,
+
+    /// Show a luminance histogram over the image. Bins of pixels that clip are drawn in red.
+    Histogram,
+
+    /// Show exposure zones relative to middle grey in false colour. Clipped pixels are red.
+    FalseColour,
+
+    /// Draw stripes over pixels where any channel clips.
+    Zebra,
+}
+
+impl AnalysisMode {
+    /// Returns the next analysis mode. This is used to cycle through the modes.
+    pub fn next(&self) -> Self {
+        match self {
+            Self::None => Self::Histogram,
+            Self::Histogram => Self::FalseColour,
+            Self::FalseColour => Self::Zebra,
+            Self::Zebra => Self::None,
+        }
+    }
+
+    /// Returns the value used in shaders.
+    fn to_shader(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Histogram => 1,
+            Self::FalseColour => 2,
+            Self::Zebra => 3,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(BufferContents, Clone, Copy)]
 pub struct UnifiedPushConstants {
     pub ray_gen_pc: ray_gen::RayGenPushConstants,
 }
 
+/// Images holding arbitrary output variables (AOVs) accumulated alongside the render image.
+struct AovImageViews {
+    /// First hit surface albedo.
+    albedo: Arc<ImageView>,
+
+    /// First hit surface normal in world space.
+    normal: Arc<ImageView>,
+
+    /// Distance from the camera to the first hit.
+    depth: Arc<ImageView>,
+
+    /// Mean of squared sample colours used to calculate variance.
+    moment: Arc<ImageView>,
+}
+
+impl AovImageViews {
+    /// Create the AOV images for the given size.
+    fn new(vk: Arc<Vk>, width: u32, height: u32) -> Result<Self> {
+        Ok(Self {
+            albedo: create_accumulated_render_image_view(vk.clone(), width, height)?,
+            normal: create_accumulated_render_image_view(vk.clone(), width, height)?,
+            depth: create_accumulated_render_image_view(vk.clone(), width, height)?,
+            moment: create_accumulated_render_image_view(vk, width, height)?,
+        })
+    }
+}
+
+/// Images holding a copy of the accumulated image and depth from before the camera moved. These are
+/// reprojected to keep valid samples instead of restarting accumulation from scratch.
+struct HistoryImageViews {
+    /// Accumulated colour.
+    colour: Arc<ImageView>,
+
+    /// Distance from the camera to the first hit and the number of sample batches accumulated.
+    depth: Arc<ImageView>,
+
+    /// Colour reprojected into each pixel and the number of sample batches it holds. This is kept
+    /// so the history can be weighted against the sample batches rendered after the camera moved.
+    reprojected: Arc<ImageView>,
+}
+
+impl HistoryImageViews {
+    /// Create the history images for the given size.
+    fn new(vk: Arc<Vk>, width: u32, height: u32) -> Result<Self> {
+        Ok(Self {
+            colour: create_accumulated_render_image_view(vk.clone(), width, height)?,
+            depth: create_accumulated_render_image_view(vk.clone(), width, height)?,
+            reprojected: create_accumulated_render_image_view(vk, width, height)?,
+        })
+    }
+}
+
+/// Accumulated images at full resolution kept while rendering a preview so they can be reprojected
+/// once the camera stops moving instead of restarting accumulation from scratch.
+struct FullResolutionHistory {
+    /// Accumulated colour.
+    colour: Arc<ImageView>,
+
+    /// Distance from the camera to the first hit and the number of sample batches accumulated.
+    depth: Arc<ImageView>,
+
+    /// Camera view projection and inverse view matrices used to render the images.
+    camera_matrices: (Mat4, Mat4),
+}
+
+/// Copies of the accumulated image sampled by the graphics pass, one per frame in flight. The
+/// accumulated image is read and written by every sample batch, so a frame that traces the next
+/// batch would otherwise write to the image while a previous frame is still sampling it to present.
+/// Each frame copies the accumulated image into its own display image after tracing and only
+/// samples that copy.
+struct DisplayImageViews {
+    /// Display image of each frame in flight.
+    image_views: Vec<Arc<ImageView>>,
+
+    /// Index of the display image used by the next frame.
+    frame_index: usize,
+}
+
+impl DisplayImageViews {
+    /// Create the display images for the given size.
+    fn new(vk: Arc<Vk>, width: u32, height: u32) -> Result<Self> {
+        let image_views = (0..FRAMES_IN_FLIGHT)
+            .map(|_| create_accumulated_render_image_view(vk.clone(), width, height))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            image_views,
+            frame_index: 0,
+        })
+    }
+
+    /// Returns the size of the display images.
+    fn get_extent(&self) -> [u32; 3] {
+        self.image_views[0].image().extent()
+    }
+
+    /// Returns the display image for the next frame and moves on to the following one.
+    fn next(&mut self) -> Arc<ImageView> {
+        let image_view = self.image_views[self.frame_index].clone();
+        self.frame_index = (self.frame_index + 1) % self.image_views.len();
+        image_view
+    }
+}
+
 /// Stores resources specific to the rendering pipelines and renders an image progressively.
 /// Each frame renders a batch of samples with a given number of samplers per pixel and accumulates
 /// the result over successive calls to its render function.
 pub struct RenderEngine {
-    /// Descriptor set for binding the top-level acceleration structure for the scene.
-    tlas_descriptor_set: Arc<DescriptorSet>,
-
     /// Descriptor set for binding mesh data.
     mesh_data_descriptor_set: Arc<DescriptorSet>,
 
     /// Descriptor set for binding image textures.
     image_textures_descriptor_set: Arc<DescriptorSet>,
 
+    /// Image textures. These are streamed in the background and the descriptor set is recreated
+    /// as they are loaded.
+    image_textures: ImageTextures,
+
     /// Descriptor set for binding constant colour textures.
     constant_colour_textures_descriptor_set: Arc<DescriptorSet>,
 
+    /// Colours of the constant colour textures, including colours derived for material
+    /// variations. The buffer and descriptor set are recreated when a colour is changed.
+    constant_colours: Vec<[f32; 3]>,
+
+    /// Maps the names of constant colour textures to their index in `constant_colours`.
+    constant_colour_indices: HashMap<String, u32>,
+
+    /// Storage buffer of the scalar constant textures, which shares a descriptor set with the
+    /// constant colours.
+    scalar_textures_buffer: Subbuffer<[f32]>,
+
     /// Descriptor set for binding other textures besides image and constant colour.
     other_textures_descriptor_set: Arc<DescriptorSet>,
 
@@ -70,30 +336,145 @@ pub struct RenderEngine {
     /// Descriptor set for binding the light source alias table.
     light_source_alias_table_descriptor_set: Arc<DescriptorSet>,
 
-    /// The shader binding table.
-    shader_binding_table: ShaderBindingTable,
-
-    /// The raytracing pipeline and layout.
-    rt_pipeline: RtPipeline,
+    /// Traces rays with the raytracing pipeline or the compute shader fallback.
+    tracer: Tracer,
 
     /// The graphics pipeline.
     gfx_pipeline: GfxPipeline,
 
+    /// The graphics pipeline for drawing overlay lines.
+    overlay_pipeline: OverlayPipeline,
+
+    /// Lines drawn over the presented image to help with scene orientation.
+    overlay: Overlay,
+
     /// Combined push constants for all shaders.
     push_constants: UnifiedPushConstants,
 
     /// Accumulated sample batches.
     accum_image_view: Arc<ImageView>,
 
+    /// Accumulated arbitrary output variables.
+    aov_image_views: AovImageViews,
+
+    /// Copies of the accumulated image presented by each frame in flight. These have the output
+    /// resolution, which is lower than the render resolution when supersampling.
+    display_image_views: DisplayImageViews,
+
+    /// Copy of the accumulated image at the render resolution that is denoised and downsampled
+    /// to the display image when supersampling. This is `None` when rendering at the output
+    /// resolution.
+    supersampled_image_view: Option<Arc<ImageView>>,
+
+    /// Copies of accumulated images from before the camera moved.
+    history_image_views: HistoryImageViews,
+
+    /// Blue noise texture used for pixel jitter and dithering the presented image.
+    blue_noise_texture: BlueNoiseTexture,
+
+    /// Table for distributing the samples of each pixel according to the pixel filter.
+    pixel_filter_table: PixelFilterTable,
+
+    /// Low resolution pass finding the depth and instance seen through each pixel.
+    probe: Probe,
+
+    /// Light splatted to any pixel of the film. This is only cleared and resolved while splatting
+    /// or the caustics pass is enabled.
+    splat_buffer: SplatBuffer,
+
+    /// Filter denoising the presented image.
+    denoiser: Denoiser,
+
+    /// Whether the presented image is denoised.
+    is_denoising: bool,
+
+    /// Filter resolving supersampled images to the output resolution.
+    downsampler: Downsampler,
+
+    /// Descriptor set for binding the render images to the ray tracing pipeline. This is created
+    /// when it's first needed after the render images are allocated and reused by every sample
+    /// batch until they are reallocated.
+    render_image_descriptor_set: Option<Arc<DescriptorSet>>,
+
+    /// Position of the cursor in swapchain pixels, if it is over the window.
+    cursor_position: Option<[f32; 2]>,
+
+    /// Mesh instance under the cursor according to the last probe result.
+    hovered_instance: Option<u32>,
+
+    /// Whether a magnified crop of the rendered image around the cursor is shown.
+    is_magnifier_visible: bool,
+
+    /// How exposure is visualised in the presented image.
+    analysis_mode: AnalysisMode,
+
+    /// Film response applied to the presented image.
+    film_response: FilmResponse,
+
+    /// Counters of the rays that hit each material type while profiling.
+    material_profiler: MaterialProfiler,
+
+    /// The compute pipeline counting the pixels of the accumulated image for the histogram.
+    histogram_pipeline: HistogramPipeline,
+
+    /// Luminance histogram of the accumulated image. This is only updated while the histogram
+    /// analysis mode is shown.
+    histogram_buffer: Subbuffer<[u32]>,
+
+    /// Camera view projection and inverse view matrices used to render the last sample batch.
+    /// This is `None` if nothing has been rendered since the render images were allocated.
+    last_camera_matrices: Option<(Mat4, Mat4)>,
+
+    /// Camera view projection and inverse view matrices used to render the history images.
+    history_camera_matrices: (Mat4, Mat4),
+
+    /// Whether the history should be reprojected into the first sample batches after a restart.
+    reproject_history: bool,
+
+    /// Accumulated images from before the preview started, if they can be reprojected.
+    full_resolution_history: Option<FullResolutionHistory>,
+
+    /// Full output resolution of the rendered image. Images are rendered at a higher resolution
+    /// when supersampling.
+    image_size: [u32; 2],
+
+    /// How the rendered image is scaled to the swapchain image.
+    scaling: ScalingPolicy,
+
+    /// Factor by which the rendered image is downscaled for a fast preview, if enabled.
+    preview_scale: Option<u32>,
+
+    /// Whether the swapchain images encode colours to sRGB when written. Otherwise the fragment
+    /// shader encodes them.
+    is_srgb_target: bool,
+
+    /// Factor the output resolution is multiplied by to get the resolution images are rendered
+    /// at.
+    supersample: f32,
+
+    /// Pattern of pixels traced per sample batch.
+    sampling_pattern: SamplingPattern,
+
+    /// Estimator for the light scattered at each hit.
+    estimator: Estimator,
+
+    /// Whether tracing is paused. The last accumulated image is still presented.
+    is_paused: bool,
+
+    /// Whether sample batches keep accumulating past the configured number of batches until
+    /// rendering restarts.
+    is_progressive: bool,
+
+    /// Whether the render images need to be cleared before the next sample batch because they
+    /// were just allocated.
+    clear_render_images: bool,
+
     /// Current sample batch to render.
     current_sample_batch: u32,
 
     /// Number of batches to use when rendering.
     sample_batches: u32,
 
-    /// Acceleration structures.
-    acceleration_structures: AccelerationStructures,
-
     /// Meshes.
     meshes: Vec<Arc<Mesh>>,
 
@@ -102,35 +483,87 @@ pub struct RenderEngine {
 
     /// Ray time values for each sample batch.
     batch_ray_times: Vec<f32>,
+
+    /// Interval of time in [0, 1] over which the shutter is open.
+    shutter_interval: [f32; 2],
+
+    /// Stops building the scene, loading textures and offline rendering when cancelled.
+    cancel: CancellationToken,
+
+    /// Callbacks observing the progress of the render.
+    hooks: RenderHooks,
+
+    /// Order of the textures and materials in the buffers indexed by shaders.
+    buffer_layouts: BufferLayouts,
 }
 
 impl RenderEngine {
-    /// Create vulkano resources for rendering a new scene with given models.
+    /// Create vulkano resources for rendering a new scene with given models at the given image
+    /// size. Building the scene stops with a `Cancelled` error if `cancel` is cancelled. Scenes
+    /// exceeding their budget are built at a lower level of detail if allowed, or fail with a
+    /// report of what uses the most. The hooks are told once the scene is built.
     pub fn new(
         vk: Arc<Vk>,
         scene_file: &SceneFile,
-        window_size: &[f32; 2],
+        image_size: &[f32; 2],
         swapchain_format: Format,
+        cancel: CancellationToken,
+        mut hooks: RenderHooks,
     ) -> Result<Self> {
+        let build_start = Instant::now();
+
         // Seed random number generator.
-        Random::seed(485_674_845_675_491);
+        Random::seed(RANDOM_SEED);
+
+        // Check the scene's budget before anything is uploaded.
+        let deadline = BuildDeadline::new(scene_file, &cancel)?;
+        let detail = fit_memory_budget(scene_file)?;
 
         // Load shader modules.
-        let rt_shader_modules = RtShaderModules::load(vk.device.clone());
         let gfx_shader_modules = GfxShaderModules::load(vk.device.clone());
+        let overlay_shader_modules = OverlayShaderModules::load(vk.device.clone());
 
         // Load Textures.
-        let textures = Textures::new(vk.clone(), scene_file)?;
+        let mut textures = Textures::new(
+            vk.clone(),
+            scene_file,
+            detail.max_texture_size,
+            cancel.clone(),
+        )?;
         let image_texture_count = textures.image_textures.image_views.len();
-        let constant_colour_count = textures.constant_colour_textures.colours.len();
+        let scalar_constant_count = textures.scalar_textures.values.len();
         let checker_texture_count = textures.checker_textures.textures.len();
         let noise_texture_count = textures.noise_textures.textures.len();
 
-        // Get meshes.
+        // Get materials. Texture and material names are resolved to handles here so invalid
+        // references fail while loading.
+        let mut materials = Materials::new(&scene_file.materials, &textures)?;
+
+        // Get meshes. Instance primitives get a mesh with the geometry of the primitive they
+        // reference and share its bottom level acceleration structure.
         let mut meshes: Vec<Arc<Mesh>> = Vec::new();
         let mut mesh_name_to_index: HashMap<String, usize> = HashMap::new();
         for primitive in scene_file.primitives.iter() {
-            let mesh = Arc::new(primitive.into());
+            deadline.get_cancel().check().map_err(|e| {
+                deadline.explain(e, || {
+                    format!(
+                        "{} of {} meshes were generated.",
+                        meshes.len(),
+                        scene_file.primitives.len()
+                    )
+                })
+            })?;
+            let mask = scene_file.render.get_instance_mask(primitive);
+            let (geometry, _) = primitive.get_geometry(&scene_file.primitives)?;
+            let geometry = geometry.with_reduced_tessellation(detail.tessellation);
+            let mesh = Arc::new(Mesh::from_primitive(
+                primitive,
+                &geometry,
+                &materials,
+                &scene_file.facing,
+                scene_file.render.quality,
+                mask,
+            )?);
             mesh_name_to_index.insert(primitive.get_name().into(), meshes.len());
             meshes.push(mesh);
         }
@@ -143,32 +576,67 @@ impl RenderEngine {
                 .get(&instance.name)
                 .with_context(|| format!("Mesh {} not found", instance.name))?;
 
+            // Primitives with an array modifier are expanded into an instance per copy. The
+            // primitive's own transform is applied by the instance so its mesh is shared.
+            let primitive = &scene_file.primitives[*mesh_index];
+            let variation = primitive.get_array().and_then(|array| array.variation);
             let object_to_world = instance.get_object_to_world_space_matrix();
-            let transform = Transform::from(object_to_world);
-            mesh_instances.push(MeshInstance::new(*mesh_index, transform));
+            let (_, primitive_to_object) = primitive.get_geometry(&scene_file.primitives)?;
+            let velocity = primitive.get_velocity();
+            for (index, offset) in primitive.get_array_offsets().into_iter().enumerate() {
+                let matrix = object_to_world
+                    .with_offset(offset)
+                    .with_object_transform(primitive_to_object);
+                let transform = Transform::from(match velocity {
+                    Some(velocity) => matrix.with_velocity(velocity),
+                    None => matrix,
+                });
+                let mut mesh_instance = MeshInstance::new(*mesh_index, transform);
+                mesh_instance.material_variation = variation.map(|v| (v, index as u64));
+                mesh_instances.push(mesh_instance);
+            }
         }
 
-        // Get materials.
-        let materials = Materials::new(&scene_file.materials, &textures);
+        // Instances with a material variation get their own materials with derived constant
+        // colours so materials are counted afterwards.
+        let instance_materials = get_instance_materials(
+            &mesh_instances,
+            &meshes,
+            &scene_file.materials,
+            &mut materials,
+            &mut textures,
+        );
+        let instance_material_count = instance_materials.len();
+        let buffer_layouts = BufferLayouts::new(&textures, &materials);
+        let constant_colour_count = textures.constant_colour_textures.colours.len();
         let lambertian_material_count = materials.lambertian_materials.len();
         let metal_material_count = materials.metal_materials.len();
         let dielectric_material_count = materials.dielectric_materials.len();
         let diffuse_light_material_count = materials.diffuse_light_materials.len();
+        let pbr_material_count = materials.pbr_materials.len();
 
-        // Get the light source alias table.
+        // Get the light source alias table and the light tree over the same triangles.
+        let light_triangles = get_light_triangles(&mesh_instances, &meshes, &materials, &textures)?;
         let light_source_alias_table =
-            create_light_source_alias_table(vk.clone(), &mesh_instances, &meshes, &materials)?;
+            create_light_source_alias_table(vk.clone(), &light_triangles)?;
+        let light_tree = LightTree::new(vk.clone(), &light_triangles, &mesh_instances, &meshes)?;
 
         // Get ray time values for each sample batch. This is used for interpolating transforms for
         // each sample batch to produce the motion-blur effect.
         let sample_batches = scene_file.render.sample_batches;
-        let batch_ray_times = get_batch_ray_times(sample_batches);
+        let [shutter_open, shutter_close] = scene_file.render.get_shutter_interval();
+        let batch_ray_times = get_batch_ray_times(sample_batches, shutter_open, shutter_close);
+
+        // Images are rendered at a higher resolution than the output when supersampling.
+        let output_size = [image_size[0] as u32, image_size[1] as u32];
+        let supersample = scene_file.render.get_supersample();
+        let [width, height] = get_supersampled_size(output_size, supersample);
 
         // Push constants.
         // sampleBatch will need to change in Scene::render() but we can store 0 for the first batch.
         let push_constants = UnifiedPushConstants {
             ray_gen_pc: ray_gen::RayGenPushConstants {
-                resolution: [window_size[0] as u32, window_size[1] as u32],
+                resolution: [width, height],
                 samplesPerPixel: scene_file.render.samples_per_pixel,
                 sampleBatch: 0,
                 maxRayDepth: scene_file.render.max_ray_depth,
@@ -184,6 +652,20 @@ impl RenderEngine {
                 lightSourceTriangleCount: light_source_alias_table.triangle_count as _,
                 lightSourceTotalArea: light_source_alias_table.total_area as _,
                 batchRayTime: batch_ray_times[0],
+                samplingPattern: SamplingPattern::Full.to_shader(),
+                samplingPhase: 0,
+                reprojectHistory: 0,
+                scalarConstantCount: scalar_constant_count as _,
+                layerMask: scene_file.render.get_layer_mask(),
+                instanceMaterialCount: instance_material_count as _,
+                estimator: Estimator::default().to_shader(),
+                probePass: 0,
+                profiling: 0,
+                splatting: 0,
+                traceCaustics: scene_file.render.caustics as u32,
+                lightTracingPass: 0,
+                transparentShadows: scene_file.render.transparent_shadows as u32,
+                pbrMaterialCount: pbr_material_count as _,
             },
         };
 
@@ -191,40 +673,55 @@ impl RenderEngine {
         let gfx_pipeline = GfxPipeline::new(
             vk.device.clone(),
             &gfx_shader_modules.stages,
-            window_size,
+            image_size,
             swapchain_format,
         )?;
 
-        // Create the raytracing pipeline.
-        let rt_pipeline = RtPipeline::new(
+        // Create the overlay pipeline and the overlay lines drawn over the rendered image.
+        let overlay_pipeline = OverlayPipeline::new(
             vk.device.clone(),
-            &rt_shader_modules.stages,
-            &rt_shader_modules.groups,
-            image_texture_count as _,
+            &overlay_shader_modules.stages,
+            gfx_pipeline.get_render_pass(),
+        )?;
+        let overlay = Overlay::new(
+            vk.clone(),
+            scene_file,
+            &mesh_instances,
+            &meshes,
+            &materials,
+            [image_size[0] as u32, image_size[1] as u32],
         )?;
-        let pipeline_layout = rt_pipeline.get_layout();
+
+        // Create the raytracing or compute pipeline along with the scene geometry it traces.
+        let tracer = Tracer::new(
+            vk.clone(),
+            &mesh_instances,
+            &meshes,
+            batch_ray_times[0],
+            image_texture_count as _,
+            scene_file.render.compact_acceleration,
+            deadline.get_cancel(),
+        )
+        .map_err(|e| {
+            deadline.explain(e, || {
+                let triangles: usize = meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+                format!(
+                    "The acceleration structures for {triangles} triangles in {mesh_count} \
+                    meshes were being built."
+                )
+            })
+        })?;
+        let pipeline_layout = tracer.get_layout();
         let layouts = pipeline_layout.set_layouts();
 
         // Create descriptor sets for non-changing data.
 
-        // Acceleration structures.
-        let acceleration_structures =
-            AccelerationStructures::new(vk.clone(), &mesh_instances, &meshes, batch_ray_times[0])?;
-
-        let tlas_descriptor_set = DescriptorSet::new(
-            vk.descriptor_set_allocator.clone(),
-            layouts[RtPipeline::TLAS_LAYOUT].clone(),
-            [WriteDescriptorSet::acceleration_structure(
-                0,
-                acceleration_structures.tlas.clone(),
-            )],
-            [],
-        )?;
-
         // Mesh data.
         let vertex_buffer = create_mesh_vertex_buffer(vk.clone(), &meshes)?;
         let index_buffer = create_mesh_index_buffer(vk.clone(), &meshes)?;
         let mesh_buffer = create_mesh_storage_buffer(vk.clone(), &meshes, &materials)?;
+        let instance_material_buffer =
+            create_instance_material_buffer(vk.clone(), instance_materials)?;
 
         let mesh_data_descriptor_set = DescriptorSet::new(
             vk.descriptor_set_allocator.clone(),
@@ -233,49 +730,24 @@ impl RenderEngine {
                 WriteDescriptorSet::buffer(0, vertex_buffer),
                 WriteDescriptorSet::buffer(1, index_buffer),
                 WriteDescriptorSet::buffer(2, mesh_buffer),
+                WriteDescriptorSet::buffer(3, instance_material_buffer),
             ],
             [],
         )?;
 
-        // Sampler + Textures.
-        let sampler = Sampler::new(
-            vk.device.clone(),
-            SamplerCreateInfo {
-                address_mode: [SamplerAddressMode::Repeat; 3],
-                ..Default::default()
-            },
-        )?;
-
-        let mut image_texture_descriptor_writes = vec![WriteDescriptorSet::sampler(0, sampler)];
-
-        if image_texture_count > 0 {
-            // We cannot create descriptor set for empty array. Push constants will have texture count which can
-            // be used in shaders to make sure out-of-bounds access can be checked.
-            image_texture_descriptor_writes.push(WriteDescriptorSet::image_view_array(
-                1,
-                0,
-                textures.image_textures.image_views.clone(),
-            ));
-        }
-
-        let image_textures_descriptor_set = DescriptorSet::new_variable(
-            vk.descriptor_set_allocator.clone(),
-            layouts[RtPipeline::SAMPLERS_AND_TEXTURES_LAYOUT].clone(),
-            image_texture_count as _,
-            image_texture_descriptor_writes,
-            [],
-        )?;
+        // Samplers + Textures.
+        let image_textures_descriptor_set =
+            create_image_textures_descriptor_set(vk.clone(), &tracer, &textures.image_textures)?;
 
-        // Constant colour textures.
-        let constant_colours = if constant_colour_count > 0 {
-            textures.constant_colour_textures.colours.clone()
+        // Scalar constant textures.
+        let scalar_constants = if scalar_constant_count > 0 {
+            textures.scalar_textures.values.clone()
         } else {
-            // We cannot create buffer for empty array. Push constants will have material colours count which can
-            // be used in shaders to make sure out-of-bounds access can be checked.
-            vec![[0.0, 0.0, 0.0]]
+            // We cannot create buffer for empty array. Push constants will have the scalar count.
+            vec![0.0]
         };
 
-        let constant_colour_textures_buffer = Buffer::from_iter(
+        let scalar_textures_buffer = Buffer::from_iter(
             vk.memory_allocator.clone(),
             BufferCreateInfo {
                 usage: BufferUsage::STORAGE_BUFFER,
@@ -286,18 +758,18 @@ impl RenderEngine {
                     | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-            constant_colours,
+            scalar_constants,
         )?;
 
-        let constant_colour_textures_descriptor_set = DescriptorSet::new(
-            vk.descriptor_set_allocator.clone(),
-            layouts[RtPipeline::MATERIAL_COLOURS_LAYOUT].clone(),
-            vec![WriteDescriptorSet::buffer(
-                0,
-                constant_colour_textures_buffer,
-            )],
-            [],
-        )?;
+        // Constant colour textures.
+        let constant_colours = textures.constant_colour_textures.colours.clone();
+        let constant_colour_textures_descriptor_set =
+            create_constant_colour_textures_descriptor_set(
+                vk.clone(),
+                &tracer,
+                &constant_colours,
+                scalar_textures_buffer.clone(),
+            )?;
 
         // Materials.
         let material_buffers = materials.create_buffers(vk.clone())?;
@@ -310,6 +782,7 @@ impl RenderEngine {
                 WriteDescriptorSet::buffer(1, material_buffers.metal),
                 WriteDescriptorSet::buffer(2, material_buffers.dielectric),
                 WriteDescriptorSet::buffer(3, material_buffers.diffuse_light),
+                WriteDescriptorSet::buffer(4, material_buffers.pbr),
             ],
             [],
         )?;
@@ -323,73 +796,128 @@ impl RenderEngine {
             vec![
                 WriteDescriptorSet::buffer(0, texture_buffers.checker),
                 WriteDescriptorSet::buffer(1, texture_buffers.noise),
+                WriteDescriptorSet::buffer(2, texture_buffers.image_channels),
+                WriteDescriptorSet::buffer(3, texture_buffers.image_stochastic_tiling),
             ],
             [],
         )?;
 
         // Sky.
-        let sky_buffer = Buffer::from_data(
-            vk.memory_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::UNIFORM_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            scene_file.sky.to_shader(),
-        )?;
-        let sky_descriptor_set = DescriptorSet::new(
-            vk.descriptor_set_allocator.clone(),
-            layouts[RtPipeline::SKY_LAYOUT].clone(),
-            vec![WriteDescriptorSet::buffer(0, sky_buffer)],
-            [],
-        )?;
+        let sky_descriptor_set = create_sky_descriptor_set(vk.clone(), &tracer, &scene_file.sky)?;
 
-        // Light source alias table.
+        // Light source alias table and light tree.
         let light_source_alias_table_descriptor_set = DescriptorSet::new(
             vk.descriptor_set_allocator.clone(),
             layouts[RtPipeline::LIGHT_SOURCE_ALIAS_TABLE].clone(),
-            vec![WriteDescriptorSet::buffer(
-                0,
-                light_source_alias_table.buffer,
-            )],
+            vec![
+                WriteDescriptorSet::buffer(0, light_source_alias_table.buffer),
+                WriteDescriptorSet::buffer(1, light_tree.nodes),
+                WriteDescriptorSet::buffer(2, light_tree.instance_offsets),
+                WriteDescriptorSet::buffer(3, light_tree.leaves),
+            ],
             [],
         )?;
 
         // Create render image to accumulate sample batches.
-        let accum_image_view = create_accumulated_render_image_view(
-            vk.clone(),
-            window_size[0] as u32,
-            window_size[1] as u32,
+        let accum_image_view = create_accumulated_render_image_view(vk.clone(), width, height)?;
+        let aov_image_views = AovImageViews::new(vk.clone(), width, height)?;
+        let display_image_views =
+            DisplayImageViews::new(vk.clone(), output_size[0], output_size[1])?;
+        let supersampled_image_view =
+            create_supersampled_image_view(vk.clone(), output_size, [width, height])?;
+        let history_image_views = HistoryImageViews::new(vk.clone(), width, height)?;
+        let blue_noise_texture = BlueNoiseTexture::new(vk.clone())?;
+        let pixel_filter_table = PixelFilterTable::new(vk.clone(), scene_file.render.pixel_filter)?;
+        let probe = Probe::new(vk.clone())?;
+        let splat_buffer = SplatBuffer::new(vk.clone(), width, height)?;
+        let denoiser = Denoiser::new(vk.clone(), width, height)?;
+        let downsampler = Downsampler::new(vk.clone())?;
+        let material_profiler = MaterialProfiler::new(vk.clone())?;
+
+        let histogram_pipeline = HistogramPipeline::new(
+            vk.device.clone(),
+            HistogramShaderModules::load(vk.device.clone()).stage,
+        )?;
+        let histogram_buffer = Buffer::new_slice::<u32>(
+            vk.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            HistogramPipeline::BIN_COUNT as DeviceSize,
         )?;
 
-        // Create the shader binding table.
-        let shader_binding_table =
-            ShaderBindingTable::new(vk.memory_allocator.clone(), &rt_pipeline.get())?;
+        hooks.notify_scene_built(&SceneBuildStats {
+            build_time: build_start.elapsed(),
+            mesh_count: meshes.len(),
+            instance_count: mesh_instances.len(),
+        });
 
         Ok(Self {
-            tlas_descriptor_set,
             mesh_data_descriptor_set,
             image_textures_descriptor_set,
+            image_textures: textures.image_textures,
             constant_colour_textures_descriptor_set,
+            constant_colours,
+            constant_colour_indices: textures.constant_colour_textures.indices,
+            scalar_textures_buffer,
             other_textures_descriptor_set,
             materials_descriptor_set,
             sky_descriptor_set,
             light_source_alias_table_descriptor_set,
-            shader_binding_table,
-            rt_pipeline,
+            tracer,
             gfx_pipeline,
+            overlay_pipeline,
+            overlay,
             push_constants,
             accum_image_view,
+            aov_image_views,
+            display_image_views,
+            supersampled_image_view,
+            history_image_views,
+            blue_noise_texture,
+            pixel_filter_table,
+            probe,
+            splat_buffer,
+            denoiser,
+            is_denoising: scene_file.render.denoise,
+            downsampler,
+            render_image_descriptor_set: None,
+            cursor_position: None,
+            hovered_instance: None,
+            is_magnifier_visible: false,
+            analysis_mode: AnalysisMode::None,
+            film_response: FilmResponse::new(&scene_file.render.film),
+            material_profiler,
+            histogram_pipeline,
+            histogram_buffer,
+            last_camera_matrices: None,
+            history_camera_matrices: (Mat4::IDENTITY, Mat4::IDENTITY),
+            reproject_history: false,
+            full_resolution_history: None,
+            image_size: output_size,
+            scaling: scene_file.render.scaling,
+            preview_scale: None,
+            is_srgb_target: swapchain_format.numeric_format_color() == Some(NumericFormat::SRGB),
+            supersample,
+            sampling_pattern: SamplingPattern::Full,
+            estimator: Estimator::default(),
+            is_paused: false,
+            is_progressive: false,
+            clear_render_images: true,
             current_sample_batch: 0,
             sample_batches,
-            acceleration_structures,
             mesh_instances,
             meshes,
             batch_ray_times,
+            shutter_interval: [shutter_open, shutter_close],
+            cancel,
+            hooks,
+            buffer_layouts,
         })
     }
 
@@ -400,98 +928,1009 @@ impl RenderEngine {
         image_width: u32,
         image_height: u32,
     ) -> Result<()> {
-        // Update resolution for camera.
-        self.push_constants.ray_gen_pc.resolution = [image_width, image_height];
+        self.image_size = [image_width, image_height];
+        self.full_resolution_history = None;
+        self.allocate_render_images(vk)
+    }
 
-        // Update resolution for rendering the accumulated image.
-        self.accum_image_view =
-            create_accumulated_render_image_view(vk, image_width, image_height)?;
+    /// Enables rendering at a resolution reduced by the given factor, or restores the full
+    /// resolution if `None`. The graphics pass upscales the smaller image to the swapchain image
+    /// so this is used for a responsive preview while the camera is moving.
+    ///
+    /// If `reproject_history` is set, the full resolution images are kept while previewing and
+    /// reprojected when the full resolution is restored.
+    pub fn set_preview_scale(
+        &mut self,
+        vk: Arc<Vk>,
+        preview_scale: Option<u32>,
+        reproject_history: bool,
+    ) -> Result<()> {
+        if self.preview_scale == preview_scale {
+            return Ok(());
+        }
 
-        // Reset the sample batches to restart rendering sample batches again.
-        self.current_sample_batch = 0;
+        // Keep the full resolution images before they are replaced by the preview images.
+        if self.preview_scale.is_none() {
+            self.full_resolution_history = match self.last_camera_matrices {
+                Some(camera_matrices) if reproject_history && !self.clear_render_images => {
+                    Some(FullResolutionHistory {
+                        colour: self.accum_image_view.clone(),
+                        depth: self.aov_image_views.depth.clone(),
+                        camera_matrices,
+                    })
+                }
+                _ => None,
+            };
+        }
 
+        self.preview_scale = preview_scale;
+        self.allocate_render_images(vk)?;
+
+        // Reproject the full resolution images kept before the preview started. They are used as
+        // the history directly so the first sample batch doesn't copy over them.
+        if preview_scale.is_none() {
+            let extent = self.accum_image_view.image().extent();
+            let history = self
+                .full_resolution_history
+                .take()
+                .filter(|history| reproject_history && history.colour.image().extent() == extent);
+            if let Some(history) = history {
+                self.history_image_views.colour = history.colour;
+                self.history_image_views.depth = history.depth;
+                self.history_camera_matrices = history.camera_matrices;
+                self.reproject_history = true;
+            }
+        }
         Ok(())
     }
 
-    /// Renders to the given swapchain image view after the given future completes.
-    /// This will return a new future for the rendering operation.
-    ///
-    /// # Panics
-    ///
-    /// - Panics if render fails for any reason.
-    pub fn render(
+    /// Sets the pattern of pixels traced per sample batch and restarts rendering.
+    pub fn set_sampling_pattern(
         &mut self,
         vk: Arc<Vk>,
-        before_future: Box<dyn GpuFuture>,
-        swapchain_image_view: Arc<ImageView>,
-        camera: Arc<RwLock<dyn Camera>>,
-    ) -> Box<dyn GpuFuture> {
-        // Build a command buffer to bind resources and trace rays.
-        let mut builder = AutoCommandBufferBuilder::primary(
-            vk.command_buffer_allocator.clone(),
-            vk.queue.queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        sampling_pattern: SamplingPattern,
+    ) -> Result<()> {
+        if self.sampling_pattern != sampling_pattern {
+            self.sampling_pattern = sampling_pattern;
+            self.push_constants.ray_gen_pc.samplingPattern = sampling_pattern.to_shader();
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
 
-        // Perform the rendering passes.
-        self.render_raytracing_pass(vk.clone(), camera, &mut builder);
-        self.render_graphics_pass(vk.clone(), swapchain_image_view, &mut builder);
+    /// Sets the estimator for the light scattered at each hit and restarts rendering.
+    pub fn set_estimator(&mut self, vk: Arc<Vk>, estimator: Estimator) -> Result<()> {
+        if self.estimator != estimator {
+            self.estimator = estimator;
+            self.push_constants.ray_gen_pc.estimator = estimator.to_shader();
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
 
-        // Build the command buffer.
-        let command_buffer = builder.build().unwrap();
+    /// Restarts rendering sample batches. This is used when the camera changes so previously
+    /// accumulated samples are no longer valid.
+    pub fn restart(&mut self, vk: Arc<Vk>) -> Result<()> {
+        // The scene geometry is only updated from the 2nd batch onwards so it needs to be updated
+        // to the time of the first batch if it has moved on.
+        if self.current_sample_batch > 1 {
+            self.tracer.update(
+                vk,
+                &self.mesh_instances,
+                &self.meshes,
+                self.batch_ray_times[0],
+            )?;
+        }
 
-        // Execute command buffer.
-        let next_future = before_future
-            .then_execute(vk.queue.clone(), command_buffer)
-            .unwrap();
+        // Reset the sample batches to restart rendering sample batches again. Rotate the sampling
+        // phase so restarts on every frame don't keep tracing the same subset of pixels.
+        self.current_sample_batch = 0;
+        self.push_constants.ray_gen_pc.samplingPhase =
+            self.push_constants.ray_gen_pc.samplingPhase.wrapping_add(1);
 
-        next_future.boxed()
+        Ok(())
     }
 
-    /// Render the next batch of samples using raytracing. If all batches are complete, it returns
-    /// early.
-    ///
-    /// # Panics
-    ///
-    /// - Panics if render fails for any reason.
-    fn render_raytracing_pass(
-        &mut self,
-        vk: Arc<Vk>,
-        camera: Arc<RwLock<dyn Camera>>,
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    /// Restarts rendering sample batches but reprojects the previously accumulated image into the
+    /// new camera view so valid samples are kept. This is used when the camera moves.
+    pub fn restart_with_reprojection(&mut self, vk: Arc<Vk>) -> Result<()> {
+        let has_history = self.last_camera_matrices.is_some() && !self.clear_render_images;
+        self.restart(vk)?;
+        self.reproject_history = has_history;
+        Ok(())
+    }
+
+    /// Allocates the render images at the full image size reduced by the preview scale and
+    /// increased by the supersampling factor, and restarts rendering. The display images have
+    /// the output resolution without supersampling.
+    fn allocate_render_images(&mut self, vk: Arc<Vk>) -> Result<()> {
+        let scale = self.preview_scale.unwrap_or(1).max(1);
+        let output_size = self.image_size.map(|size| (size / scale).max(1));
+        let [image_width, image_height] = get_supersampled_size(output_size, self.supersample);
+
+        // Update resolution for camera.
+        self.push_constants.ray_gen_pc.resolution = [image_width, image_height];
+
+        // Update resolution for rendering the accumulated image.
+        self.accum_image_view =
+            create_accumulated_render_image_view(vk.clone(), image_width, image_height)?;
+        self.aov_image_views = AovImageViews::new(vk.clone(), image_width, image_height)?;
+        self.display_image_views =
+            DisplayImageViews::new(vk.clone(), output_size[0], output_size[1])?;
+        self.supersampled_image_view =
+            create_supersampled_image_view(vk.clone(), output_size, [image_width, image_height])?;
+        self.history_image_views = HistoryImageViews::new(vk.clone(), image_width, image_height)?;
+        self.splat_buffer
+            .resize(vk.clone(), image_width, image_height)?;
+        self.denoiser
+            .resize(vk.clone(), image_width, image_height)?;
+        self.render_image_descriptor_set = None;
+        self.clear_render_images = true;
+
+        // Nothing to reproject from the newly allocated images.
+        self.last_camera_matrices = None;
+        self.reproject_history = false;
+
+        self.restart(vk)
+    }
+
+    /// Sets the interval of time in [0, 1] over which the shutter is open and restarts rendering
+    /// sample batches. Animated transforms are interpolated over this interval for motion blur.
+    /// This is used to render individual frames of an animation.
+    pub fn set_shutter_interval(&mut self, vk: Arc<Vk>, open: f32, close: f32) -> Result<()> {
+        self.shutter_interval = [open, close];
+        self.batch_ray_times = get_batch_ray_times(self.sample_batches, open, close);
+
+        // The first batch does not update the scene geometry so do that here.
+        self.tracer.update(
+            vk,
+            &self.mesh_instances,
+            &self.meshes,
+            self.batch_ray_times[0],
+        )?;
+
+        // Reset the sample batches to restart rendering sample batches again.
+        self.current_sample_batch = 0;
+
+        Ok(())
+    }
+
+    /// Returns the number of samples per pixel per sample batch.
+    pub fn get_samples_per_pixel(&self) -> u32 {
+        self.push_constants.ray_gen_pc.samplesPerPixel
+    }
+
+    /// Sets the number of samples per pixel per sample batch, limited to
+    /// `MAX_SAMPLES_PER_PIXEL`, and restarts rendering.
+    pub fn set_samples_per_pixel(&mut self, vk: Arc<Vk>, samples_per_pixel: u32) -> Result<()> {
+        let samples_per_pixel = samples_per_pixel.clamp(1, MAX_SAMPLES_PER_PIXEL);
+        if self.push_constants.ray_gen_pc.samplesPerPixel != samples_per_pixel {
+            self.push_constants.ray_gen_pc.samplesPerPixel = samples_per_pixel;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of sample batches that are accumulated.
+    pub fn get_sample_batches(&self) -> u32 {
+        self.sample_batches
+    }
+
+    /// Sets the number of sample batches to accumulate, limited to `MAX_SAMPLE_BATCHES`, and
+    /// restarts rendering. The ray times of each batch are spread over the same shutter interval.
+    pub fn set_sample_batches(&mut self, vk: Arc<Vk>, sample_batches: u32) -> Result<()> {
+        let sample_batches = sample_batches.clamp(1, MAX_SAMPLE_BATCHES);
+        if self.sample_batches != sample_batches {
+            self.sample_batches = sample_batches;
+            let [open, close] = self.shutter_interval;
+            self.set_shutter_interval(vk, open, close)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the maximum number of bounces per ray.
+    pub fn get_max_ray_depth(&self) -> u32 {
+        self.push_constants.ray_gen_pc.maxRayDepth
+    }
+
+    /// Sets the maximum number of bounces per ray and restarts rendering.
+    pub fn set_max_ray_depth(&mut self, vk: Arc<Vk>, max_ray_depth: u32) -> Result<()> {
+        let max_ray_depth = max_ray_depth.max(1);
+        if self.push_constants.ray_gen_pc.maxRayDepth != max_ray_depth {
+            self.push_constants.ray_gen_pc.maxRayDepth = max_ray_depth;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if tracing is paused.
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// Pauses or resumes tracing sample batches. While paused, rendering only presents the last
+    /// accumulated image.
+    pub fn set_paused(&mut self, is_paused: bool) {
+        self.is_paused = is_paused;
+    }
+
+    /// Returns true if sample batches keep accumulating past the configured number of batches.
+    pub fn is_progressive(&self) -> bool {
+        self.is_progressive
+    }
+
+    /// Enables or disables accumulating sample batches past the configured number of batches. While
+    /// enabled, the image keeps converging as long as the camera and scene don't change, which
+    /// makes high sample counts practical interactively. Offline rendering always stops after the
+    /// configured number of batches.
+    pub fn set_progressive(&mut self, is_progressive: bool) {
+        self.is_progressive = is_progressive;
+    }
+
+    /// Sets the position of the cursor in swapchain pixels, or `None` if it left the window. The
+    /// mesh instance under it is highlighted.
+    pub fn set_cursor_position(&mut self, cursor_position: Option<[f32; 2]>) {
+        self.cursor_position = cursor_position;
+    }
+
+    /// Returns true if the magnifier is shown.
+    pub fn is_magnifier_visible(&self) -> bool {
+        self.is_magnifier_visible
+    }
+
+    /// Shows or hides a magnified crop of the rendered image around the cursor.
+    pub fn set_magnifier_visible(&mut self, is_magnifier_visible: bool) {
+        self.is_magnifier_visible = is_magnifier_visible;
+    }
+
+    /// Returns how exposure is visualised in the presented image.
+    pub fn get_analysis_mode(&self) -> AnalysisMode {
+        self.analysis_mode
+    }
+
+    /// Sets how exposure is visualised in the presented image. This doesn't affect accumulation.
+    pub fn set_analysis_mode(&mut self, analysis_mode: AnalysisMode) {
+        self.analysis_mode = analysis_mode;
+    }
+
+    /// Returns true if light can be splatted to any pixel of the film and is added to the
+    /// accumulated image.
+    pub fn is_splatting(&self) -> bool {
+        self.push_constants.ray_gen_pc.splatting != 0
+    }
+
+    /// Enables or disables splatting light to any pixel of the film and restarts rendering.
+    pub fn set_splatting(&mut self, vk: Arc<Vk>, is_splatting: bool) -> Result<()> {
+        if self.is_splatting() != is_splatting {
+            self.push_constants.ray_gen_pc.splatting = is_splatting as u32;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if paths traced from the light sources in a separate pass render caustics.
+    pub fn is_tracing_caustics(&self) -> bool {
+        self.push_constants.ray_gen_pc.traceCaustics != 0
+    }
+
+    /// Enables or disables the caustics pass and restarts rendering.
+    pub fn set_tracing_caustics(&mut self, vk: Arc<Vk>, is_tracing_caustics: bool) -> Result<()> {
+        if self.is_tracing_caustics() != is_tracing_caustics {
+            self.push_constants.ray_gen_pc.traceCaustics = is_tracing_caustics as u32;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if shadow rays pass through dielectrics.
+    pub fn is_transparent_shadows(&self) -> bool {
+        self.push_constants.ray_gen_pc.transparentShadows != 0
+    }
+
+    /// Enables or disables shadow rays passing through dielectrics and restarts rendering.
+    pub fn set_transparent_shadows(
+        &mut self,
+        vk: Arc<Vk>,
+        is_transparent_shadows: bool,
+    ) -> Result<()> {
+        if self.is_transparent_shadows() != is_transparent_shadows {
+            self.push_constants.ray_gen_pc.transparentShadows = is_transparent_shadows as u32;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the filter used for every image texture instead of the one in its sampler options.
+    pub fn get_texture_filter(&self) -> Option<TextureFilter> {
+        self.image_textures.get_filter_override()
+    }
+
+    /// Sets the filter used for every image texture, or `None` to use each texture's own filter,
+    /// and restarts rendering.
+    pub fn set_texture_filter(
+        &mut self,
+        vk: Arc<Vk>,
+        texture_filter: Option<TextureFilter>,
+    ) -> Result<()> {
+        if self.get_texture_filter() != texture_filter {
+            self.image_textures.set_filter_override(texture_filter)?;
+            self.image_textures_descriptor_set = create_image_textures_descriptor_set(
+                vk.clone(),
+                &self.tracer,
+                &self.image_textures,
+            )?;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the colour of a constant colour texture, re-uploads the colours and restarts
+    /// rendering. Colours derived from it for material variations keep their colour.
+    pub fn set_constant_colour(&mut self, vk: Arc<Vk>, texture: &str, rgb: [f32; 3]) -> Result<()> {
+        let index = *self
+            .constant_colour_indices
+            .get(texture)
+            .ok_or_else(|| anyhow!("'{texture}' is not a constant colour texture"))?;
+
+        if self.constant_colours[index as usize] != rgb {
+            self.constant_colours[index as usize] = rgb;
+            self.constant_colour_textures_descriptor_set =
+                create_constant_colour_textures_descriptor_set(
+                    vk.clone(),
+                    &self.tracer,
+                    &self.constant_colours,
+                    self.scalar_textures_buffer.clone(),
+                )?;
+            self.restart(vk)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the sky, re-uploads it and restarts rendering.
+    pub fn set_sky(&mut self, vk: Arc<Vk>, sky: &Sky) -> Result<()> {
+        self.sky_descriptor_set = create_sky_descriptor_set(vk.clone(), &self.tracer, sky)?;
+        self.restart(vk)
+    }
+
+    /// Returns true if the splat buffer is added to the accumulated image, which is needed by
+    /// splatting and the caustics pass.
+    fn is_resolving_splats(&self) -> bool {
+        self.is_splatting() || self.is_tracing_caustics()
+    }
+
+    /// Returns true if the presented image is denoised.
+    pub fn is_denoising(&self) -> bool {
+        self.is_denoising
+    }
+
+    /// Enables or disables denoising the presented image. This doesn't affect accumulation.
+    pub fn set_denoising(&mut self, is_denoising: bool) {
+        self.is_denoising = is_denoising;
+    }
+
+    /// Returns true if the rays that hit each material type are counted.
+    pub fn is_profiling(&self) -> bool {
+        self.push_constants.ray_gen_pc.profiling != 0
+    }
+
+    /// Starts or stops counting the rays that hit each material type. Counting slows rendering
+    /// down so it is off by default. Starting discards the previous counts.
+    pub fn set_profiling(&mut self, is_profiling: bool) {
+        if is_profiling && !self.is_profiling() {
+            self.material_profiler.reset();
+        }
+        self.push_constants.ray_gen_pc.profiling = is_profiling as u32;
+    }
+
+    /// Returns the counts of the rays that hit each material type since profiling started. These
+    /// lag the presented image by a few frames.
+    pub fn get_material_profile(&self) -> &MaterialProfile {
+        self.material_profiler.get_profile()
+    }
+
+    /// Returns the mesh instance under the cursor as of the last presented frame.
+    pub fn get_hovered_instance(&self) -> Option<u32> {
+        self.hovered_instance
+    }
+
+    /// Returns the name of the mesh of a mesh instance.
+    pub fn get_instance_mesh_name(&self, instance: u32) -> Option<&str> {
+        self.mesh_instances
+            .get(instance as usize)
+            .map(|mesh_instance| self.meshes[mesh_instance.mesh_index].name.as_str())
+    }
+
+    /// Returns the most recent result of the probe pass read back from the GPU. It lags the
+    /// presented image by a few frames.
+    pub fn get_probe_result(&self) -> Option<&ProbeResult> {
+        self.probe.get_result()
+    }
+
+    /// Returns true if an element of the viewport overlay is shown.
+    pub fn is_overlay_visible(&self, element: OverlayElement) -> bool {
+        self.overlay.is_visible(element)
+    }
+
+    /// Shows or hides an element of the viewport overlay.
+    pub fn set_overlay_visible(&mut self, element: OverlayElement, is_visible: bool) {
+        self.overlay.set_visible(element, is_visible);
+    }
+
+    /// Sets the name of the camera being rendered so the viewport overlay draws the frustums of
+    /// the other cameras.
+    pub fn set_active_camera(&mut self, name: &str) {
+        self.overlay.set_active_camera(name);
+    }
+
+    /// Returns the order of the textures and materials in the buffers indexed by shaders.
+    pub fn get_buffer_layouts(&self) -> &BufferLayouts {
+        &self.buffer_layouts
+    }
+
+    /// Returns the full output resolution of the rendered image.
+    pub fn get_image_size(&self) -> [u32; 2] {
+        self.image_size
+    }
+
+    /// Returns true if all sample batches have been rendered. This is never the case while
+    /// accumulating progressively.
+    pub fn is_complete(&self) -> bool {
+        !self.is_progressive && self.current_sample_batch >= self.get_batch_count()
+    }
+
+    /// Returns the number of sample batches rendered before the image is complete. Sampling
+    /// patterns that trace a subset of pixels per batch need a period of batches per full batch.
+    fn get_batch_count(&self) -> u32 {
+        self.sample_batches * self.sampling_pattern.get_period()
+    }
+
+    /// Renders all remaining sample batches without presenting them, waiting for each batch to
+    /// complete before starting the next one. This is used for offline rendering. Stops with a
+    /// `Cancelled` error between batches if the cancellation token is cancelled.
+    pub fn render_all_batches(
+        &mut self,
+        vk: Arc<Vk>,
+        camera: Arc<RwLock<dyn Camera>>,
+    ) -> Result<()> {
+        self.update_image_textures(vk.clone(), true)?;
+
+        while self.current_sample_batch < self.get_batch_count() {
+            self.cancel.check()?;
+
+            let mut builder = AutoCommandBufferBuilder::primary(
+                vk.command_buffer_allocator.clone(),
+                vk.queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit,
+            )?;
+
+            self.render_raytracing_pass(vk.clone(), camera.clone(), &mut builder);
+
+            builder
+                .build()?
+                .execute(vk.queue.clone())?
+                .then_signal_fence_and_flush()?
+                .wait(None /* timeout */)?;
+
+            let progress = self.get_progress();
+            self.hooks.notify_batch_complete(&progress);
+        }
+        Ok(())
+    }
+
+    /// Returns the progress of the render for the hooks.
+    fn get_progress(&self) -> RenderProgress {
+        RenderProgress {
+            sample_batch: self.current_sample_batch,
+            batch_count: (!self.is_progressive).then(|| self.get_batch_count()),
+            accumulated_samples_per_pixel: self.get_accumulated_samples_per_pixel(),
+            is_complete: self.is_complete(),
+        }
+    }
+
+    /// Copies the accumulated image and AOVs to host memory. The pixels are ordered row by row.
+    /// The splatted light is added to the accumulated image when splatting. When supersampling,
+    /// every image is downsampled to the output resolution first.
+    pub fn read_render_output(&self, vk: Arc<Vk>) -> Result<RenderOutput> {
+        let [width, height, _] = self.display_image_views.get_extent();
+
+        let beauty = if self.is_resolving_splats() {
+            self.read_output_image(vk.clone(), self.resolve_splats(vk.clone())?)?
+        } else {
+            self.read_output_image(vk.clone(), self.accum_image_view.clone())?
+        };
+        let albedo = self.read_output_image(vk.clone(), self.aov_image_views.albedo.clone())?;
+        let normal = self.read_output_image(vk.clone(), self.aov_image_views.normal.clone())?;
+        let depth = self.read_output_image(vk.clone(), self.aov_image_views.depth.clone())?;
+        let moment = self.read_output_image(vk, self.aov_image_views.moment.clone())?;
+
+        // Variance is E[X^2] - E[X]^2 per channel.
+        let variance = beauty
+            .iter()
+            .zip(moment.iter())
+            .map(|(mean, m2)| [0, 1, 2].map(|i| (m2[i] - mean[i] * mean[i]).max(0.0)))
+            .collect();
+
+        Ok(RenderOutput {
+            width,
+            height,
+            beauty,
+            albedo: albedo.iter().map(|p| [p[0], p[1], p[2]]).collect(),
+            normal: normal.iter().map(|p| [p[0], p[1], p[2]]).collect(),
+            depth: depth.iter().map(|p| p[0]).collect(),
+            variance,
+        })
+    }
+
+    /// Copies an image at the render resolution to host memory, downsampling it to the output
+    /// resolution first when supersampling and waiting for the downsample pass to complete.
+    fn read_output_image(&self, vk: Arc<Vk>, image_view: Arc<ImageView>) -> Result<Vec<[f32; 4]>> {
+        if self.supersampled_image_view.is_none() {
+            return read_image(vk, &image_view);
+        }
+
+        let [width, height, _] = self.display_image_views.get_extent();
+        let output_image_view = create_accumulated_render_image_view(vk.clone(), width, height)?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vk.command_buffer_allocator.clone(),
+            vk.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        self.downsampler.record(
+            vk.clone(),
+            &mut builder,
+            image_view,
+            output_image_view.clone(),
+        )?;
+
+        builder
+            .build()?
+            .execute(vk.queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None /* timeout */)?;
+
+        read_image(vk, &output_image_view)
+    }
+
+    /// Returns a new image with the accumulated image plus the splatted light, waiting for the
+    /// resolve pass to complete.
+    fn resolve_splats(&self, vk: Arc<Vk>) -> Result<Arc<ImageView>> {
+        let [width, height, _] = self.accum_image_view.image().extent();
+        let resolved_image_view = create_accumulated_render_image_view(vk.clone(), width, height)?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vk.command_buffer_allocator.clone(),
+            vk.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        self.splat_buffer.record_resolve(
+            vk.clone(),
+            &mut builder,
+            self.accum_image_view.clone(),
+            resolved_image_view.clone(),
+            self.get_splat_sample_count(),
+        )?;
+
+        builder
+            .build()?
+            .execute(vk.queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None /* timeout */)?;
+
+        Ok(resolved_image_view)
+    }
+
+    /// Returns the number of samples per pixel accumulated so far. Sampling patterns that trace a
+    /// subset of pixels per batch trace every pixel once per period.
+    pub fn get_accumulated_samples_per_pixel(&self) -> u32 {
+        let pixel_batches = self
+            .current_sample_batch
+            .div_ceil(self.sampling_pattern.get_period());
+        self.push_constants.ray_gen_pc.samplesPerPixel * pixel_batches
+    }
+
+    /// Returns the number of samples per pixel the splatted light is averaged over, which is at
+    /// least the samples of one batch.
+    fn get_splat_sample_count(&self) -> u32 {
+        self.get_accumulated_samples_per_pixel()
+            .max(self.push_constants.ray_gen_pc.samplesPerPixel)
+    }
+
+    /// Renders to the given swapchain image view after the given future completes.
+    /// This will return a new future for the rendering operation.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if render fails for any reason.
+    pub fn render(
+        &mut self,
+        vk: Arc<Vk>,
+        before_future: Box<dyn GpuFuture>,
+        swapchain_image_view: Arc<ImageView>,
+        camera: Arc<RwLock<dyn Camera>>,
+    ) -> Box<dyn GpuFuture> {
+        // Show textures that finished loading since the last frame.
+        self.update_image_textures(vk.clone(), false).unwrap();
+
+        // Pick up probe results and profile counts the GPU has finished with since the last frame.
+        self.probe.poll();
+        if self.is_profiling() {
+            self.material_profiler.poll();
+        }
+
+        // Build a command buffer to bind resources and trace rays.
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vk.command_buffer_allocator.clone(),
+            vk.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        // Perform the rendering passes.
+        let sample_batch = self.current_sample_batch;
+        if !self.is_paused {
+            self.render_probe_pass(vk.clone(), camera.clone(), &mut builder);
+            if self.is_profiling() {
+                self.material_profiler.record_clear(&mut builder).unwrap();
+            }
+            self.render_raytracing_pass(vk.clone(), camera.clone(), &mut builder);
+            if self.is_profiling() {
+                self.material_profiler
+                    .record_readback(&mut builder)
+                    .unwrap();
+            }
+        }
+        if self.analysis_mode == AnalysisMode::Histogram {
+            self.render_histogram_pass(vk.clone(), &mut builder);
+        }
+
+        // Present a copy of the accumulated image so the next frame can trace into it while this
+        // one is still being presented. The splatted light is added to the copy when splatting.
+        // When supersampling, the copy is made at the render resolution so it can be denoised
+        // with the AOVs and is then downsampled to the display image.
+        let display_image_view = self.display_image_views.next();
+        let copy_image_view = self
+            .supersampled_image_view
+            .clone()
+            .unwrap_or_else(|| display_image_view.clone());
+        if self.is_resolving_splats() {
+            self.splat_buffer
+                .record_resolve(
+                    vk.clone(),
+                    &mut builder,
+                    self.accum_image_view.clone(),
+                    copy_image_view.clone(),
+                    self.get_splat_sample_count(),
+                )
+                .unwrap();
+        } else {
+            builder
+                .copy_image(CopyImageInfo::images(
+                    self.accum_image_view.image().clone(),
+                    copy_image_view.image().clone(),
+                ))
+                .unwrap();
+        }
+        if self.is_denoising {
+            self.denoiser
+                .record(
+                    vk.clone(),
+                    &mut builder,
+                    copy_image_view.clone(),
+                    self.aov_image_views.albedo.clone(),
+                    self.aov_image_views.normal.clone(),
+                    self.aov_image_views.depth.clone(),
+                )
+                .unwrap();
+        }
+        if self.supersampled_image_view.is_some() {
+            self.downsampler
+                .record(
+                    vk.clone(),
+                    &mut builder,
+                    copy_image_view,
+                    display_image_view.clone(),
+                )
+                .unwrap();
+        }
+        self.render_graphics_pass(
+            vk.clone(),
+            swapchain_image_view,
+            display_image_view,
+            camera,
+            &mut builder,
+        );
+
+        // Build the command buffer.
+        let command_buffer = builder.build().unwrap();
+
+        // Execute command buffer.
+        let next_future = before_future
+            .then_execute(vk.queue.clone(), command_buffer)
+            .unwrap();
+
+        let progress = self.get_progress();
+        if self.current_sample_batch != sample_batch {
+            self.hooks.notify_batch_complete(&progress);
+        }
+        self.hooks.notify_frame_complete(&progress);
+
+        next_future.boxed()
+    }
+
+    /// Requests that loading textures and offline rendering stop.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Uploads image textures that finished loading and recreates their descriptor set. Rendering
+    /// restarts since samples accumulated with the placeholder or preview images are stale. If
+    /// `wait` is set, this blocks until every texture is loaded at full resolution.
+    fn update_image_textures(&mut self, vk: Arc<Vk>, wait: bool) -> Result<()> {
+        let is_changed = if wait {
+            self.image_textures.wait(vk.clone())?
+        } else {
+            self.image_textures.poll(vk.clone())?
+        };
+
+        if is_changed {
+            self.image_textures_descriptor_set = create_image_textures_descriptor_set(
+                vk.clone(),
+                &self.tracer,
+                &self.image_textures,
+            )?;
+            self.restart(vk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Render the next batch of samples using raytracing. If all batches are complete, it returns
+    /// early.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if render fails for any reason.
+    fn render_raytracing_pass(
+        &mut self,
+        vk: Arc<Vk>,
+        camera: Arc<RwLock<dyn Camera>>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     ) {
-        if self.current_sample_batch >= self.sample_batches {
+        if self.is_complete() {
             return;
         }
 
-        // Starting at 2nd batch we need to update acceleration structures so we can account for
-        // motion blur.
-        if self.current_sample_batch > 0 {
-            self.acceleration_structures
-                .update(
+        // With sampling patterns that trace a subset of pixels per batch, every pixel is traced
+        // once over a period so the ray time only advances once per period. Batches past the
+        // configured number while accumulating progressively cycle through the same ray times.
+        let pixel_batch = (self.current_sample_batch / self.sampling_pattern.get_period()) as usize;
+        let batch_ray_time = self.batch_ray_times[pixel_batch % self.batch_ray_times.len()];
+
+        // Starting at 2nd batch we need to update the scene geometry so we can account for
+        // motion blur.
+        if self.current_sample_batch > 0 {
+            self.tracer
+                .update(
+                    vk.clone(),
+                    &self.mesh_instances,
+                    &self.meshes,
+                    batch_ray_time,
+                )
+                .unwrap();
+        }
+
+        // Newly allocated images have undefined contents. Clear them so pixels skipped by the
+        // sampling pattern don't show garbage.
+        if self.clear_render_images {
+            for image_view in [
+                &self.accum_image_view,
+                &self.aov_image_views.albedo,
+                &self.aov_image_views.normal,
+                &self.aov_image_views.depth,
+                &self.aov_image_views.moment,
+            ] {
+                builder
+                    .clear_color_image(ClearColorImageInfo::image(image_view.image().clone()))
+                    .unwrap();
+            }
+            self.clear_render_images = false;
+        }
+
+        // Splats are summed rather than averaged so they start over with the accumulated image.
+        if self.is_resolving_splats() && self.current_sample_batch == 0 {
+            self.splat_buffer.record_clear(builder).unwrap();
+        }
+
+        // Get the camera matrices.
+        let camera = camera.read().unwrap();
+        let view_proj = camera.get_projection_matrix() * camera.get_view_matrix();
+        let view_inverse = camera.get_view_inverse_matrix();
+
+        // Copy the accumulated images before the first batch overwrites them so they can be
+        // reprojected. Sampling patterns that trace a subset of pixels per batch need to reproject
+        // over a full period.
+        let period = self.sampling_pattern.get_period();
+        let reproject = self.reproject_history && self.current_sample_batch < period;
+        if reproject && self.current_sample_batch == 0 {
+            if let Some(last_camera_matrices) = self.last_camera_matrices {
+                for (src, dst) in [
+                    (&self.accum_image_view, &self.history_image_views.colour),
+                    (&self.aov_image_views.depth, &self.history_image_views.depth),
+                ] {
+                    builder
+                        .copy_image(CopyImageInfo::images(
+                            src.image().clone(),
+                            dst.image().clone(),
+                        ))
+                        .unwrap();
+                }
+                self.history_camera_matrices = last_camera_matrices;
+            }
+        }
+
+        // Load current sample batch information to push constants.
+        let mut push_constants = self.push_constants;
+        push_constants.ray_gen_pc.sampleBatch = self.current_sample_batch;
+
+        push_constants.ray_gen_pc.batchRayTime = batch_ray_time;
+        push_constants.ray_gen_pc.reprojectHistory = reproject as u32;
+
+        let camera_buffer_descriptor_set = self
+            .create_camera_descriptor_set(vk.clone(), &*camera)
+            .unwrap();
+        let render_image_descriptor_set = self.get_render_image_descriptor_set(vk.clone()).unwrap();
+        let descriptor_sets = vec![
+            camera_buffer_descriptor_set,
+            render_image_descriptor_set,
+            self.mesh_data_descriptor_set.clone(),
+            self.image_textures_descriptor_set.clone(),
+            self.constant_colour_textures_descriptor_set.clone(),
+            self.materials_descriptor_set.clone(),
+            self.other_textures_descriptor_set.clone(),
+            self.sky_descriptor_set.clone(),
+            self.light_source_alias_table_descriptor_set.clone(),
+        ];
+
+        self.tracer
+            .trace(
+                vk.clone(),
+                builder,
+                descriptor_sets.clone(),
+                push_constants,
+                self.accum_image_view.image().extent(),
+            )
+            .unwrap();
+
+        // Trace paths from the light sources for the caustics the camera paths skipped and splat
+        // them onto the image.
+        if self.is_tracing_caustics() {
+            push_constants.ray_gen_pc.lightTracingPass = 1;
+            push_constants.ray_gen_pc.profiling = 0;
+            self.tracer
+                .trace(
                     vk.clone(),
-                    &self.mesh_instances,
-                    &self.meshes,
-                    self.batch_ray_times[self.current_sample_batch as usize],
+                    builder,
+                    descriptor_sets,
+                    push_constants,
+                    self.accum_image_view.image().extent(),
                 )
                 .unwrap();
         }
 
-        // Create the uniform buffer for the camera.
+        // Remember the camera for reprojecting this batch later.
+        self.last_camera_matrices = Some((view_proj, view_inverse));
+        if self.current_sample_batch + 1 >= period {
+            self.reproject_history = false;
+        }
+
+        // Increment for next batch.
+        self.current_sample_batch += 1;
+    }
+
+    /// Traces the probe pass and records a copy of its results to host memory. The copy is read
+    /// back a few frames later by `Probe::poll` once the GPU is done with it.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if render fails for any reason.
+    fn render_probe_pass(
+        &mut self,
+        vk: Arc<Vk>,
+        camera: Arc<RwLock<dyn Camera>>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
         let camera = camera.read().unwrap();
+        let camera_buffer_descriptor_set = self
+            .create_camera_descriptor_set(vk.clone(), &*camera)
+            .unwrap();
+        let render_image_descriptor_set = self.get_render_image_descriptor_set(vk.clone()).unwrap();
+
+        let mut push_constants = self.push_constants;
+        push_constants.ray_gen_pc.resolution = PROBE_RESOLUTION;
+        push_constants.ray_gen_pc.probePass = 1;
+        push_constants.ray_gen_pc.profiling = 0;
+
+        self.tracer
+            .trace(
+                vk.clone(),
+                builder,
+                vec![
+                    camera_buffer_descriptor_set,
+                    render_image_descriptor_set,
+                    self.mesh_data_descriptor_set.clone(),
+                    self.image_textures_descriptor_set.clone(),
+                    self.constant_colour_textures_descriptor_set.clone(),
+                    self.materials_descriptor_set.clone(),
+                    self.other_textures_descriptor_set.clone(),
+                    self.sky_descriptor_set.clone(),
+                    self.light_source_alias_table_descriptor_set.clone(),
+                ],
+                push_constants,
+                self.probe.get_image_view().image().extent(),
+            )
+            .unwrap();
+
+        self.probe.record_readback(builder).unwrap();
+    }
 
-        // Create the descriptor sets for the raytracing pipeline.
-        let pipeline_layout = self.rt_pipeline.get_layout();
+    /// Records the commands to count the pixels of the accumulated image for the histogram.
+    fn render_histogram_pass(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let pipeline_layout = self.histogram_pipeline.get_layout();
         let layouts = pipeline_layout.set_layouts();
 
-        // Load current sample batch information to push constants.
-        let mut push_constants = self.push_constants;
-        push_constants.ray_gen_pc.sampleBatch = self.current_sample_batch;
+        let histogram_descriptor_set_0 = DescriptorSet::new(
+            vk.descriptor_set_allocator.clone(),
+            layouts[HistogramPipeline::HISTOGRAM_LAYOUT].clone(),
+            [
+                WriteDescriptorSet::image_view(0, self.accum_image_view.clone()),
+                WriteDescriptorSet::buffer(1, self.histogram_buffer.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+
+        let extent = self.accum_image_view.image().extent();
+        let group_counts = [
+            extent[0].div_ceil(HistogramPipeline::WORKGROUP_SIZE),
+            extent[1].div_ceil(HistogramPipeline::WORKGROUP_SIZE),
+            1,
+        ];
+
+        builder
+            .fill_buffer(self.histogram_buffer.clone(), 0)
+            .unwrap()
+            .bind_pipeline_compute(self.histogram_pipeline.get())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline_layout.clone(),
+                0,
+                vec![histogram_descriptor_set_0],
+            )
+            .unwrap();
+
+        // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
+        unsafe { builder.dispatch(group_counts).unwrap() };
+    }
 
-        push_constants.ray_gen_pc.batchRayTime =
-            self.batch_ray_times[self.current_sample_batch as usize];
+    /// Create the descriptor set for the camera uniform buffer. The camera used for the history
+    /// images is included for reprojection.
+    fn create_camera_descriptor_set(
+        &self,
+        vk: Arc<Vk>,
+        camera: &dyn Camera,
+    ) -> Result<Arc<DescriptorSet>> {
+        let pipeline_layout = self.tracer.get_layout();
+        let layouts = pipeline_layout.set_layouts();
 
+        let view_proj = camera.get_projection_matrix() * camera.get_view_matrix();
         let camera_buffer = Buffer::from_data(
             vk.memory_allocator.clone(),
             BufferCreateInfo {
@@ -504,78 +1943,72 @@ impl RenderEngine {
                 ..Default::default()
             },
             ray_gen::Camera {
-                viewProj: (camera.get_projection_matrix() * camera.get_view_matrix())
-                    .to_cols_array_2d(),
+                viewProj: view_proj.to_cols_array_2d(),
                 viewInverse: camera.get_view_inverse_matrix().to_cols_array_2d(),
                 projInverse: camera.get_projection_inverse_matrix().to_cols_array_2d(),
+                prevViewProj: self.history_camera_matrices.0.to_cols_array_2d(),
+                prevViewInverse: self.history_camera_matrices.1.to_cols_array_2d(),
                 focalLength: camera.get_focal_length(),
                 apertureSize: camera.get_aperture_size(),
+                exposure: camera.get_exposure(),
             },
-        )
-        .unwrap();
+        )?;
 
-        let camera_buffer_descriptor_set = DescriptorSet::new(
+        let descriptor_set = DescriptorSet::new(
             vk.descriptor_set_allocator.clone(),
             layouts[RtPipeline::CAMERA_BUFFER_LAYOUT].clone(),
             [WriteDescriptorSet::buffer(0, camera_buffer)],
             [],
-        )
-        .unwrap();
+        )?;
 
-        let render_image_descriptor_set = DescriptorSet::new(
+        Ok(descriptor_set)
+    }
+
+    /// Returns the descriptor set for the render, AOV, history and probe images, the blue noise
+    /// texture and the splat buffer, creating it if the render images were allocated since it was
+    /// last used.
+    fn get_render_image_descriptor_set(&mut self, vk: Arc<Vk>) -> Result<Arc<DescriptorSet>> {
+        if let Some(descriptor_set) = &self.render_image_descriptor_set {
+            return Ok(descriptor_set.clone());
+        }
+
+        let pipeline_layout = self.tracer.get_layout();
+        let layouts = pipeline_layout.set_layouts();
+
+        let descriptor_set = DescriptorSet::new(
             vk.descriptor_set_allocator.clone(),
             layouts[RtPipeline::RENDER_IMAGE_LAYOUT].clone(),
-            [WriteDescriptorSet::image_view(
-                0,
-                self.accum_image_view.clone(),
-            )],
+            [
+                WriteDescriptorSet::image_view(0, self.accum_image_view.clone()),
+                WriteDescriptorSet::image_view(1, self.aov_image_views.albedo.clone()),
+                WriteDescriptorSet::image_view(2, self.aov_image_views.normal.clone()),
+                WriteDescriptorSet::image_view(3, self.aov_image_views.depth.clone()),
+                WriteDescriptorSet::image_view(4, self.aov_image_views.moment.clone()),
+                WriteDescriptorSet::image_view(5, self.history_image_views.colour.clone()),
+                WriteDescriptorSet::image_view(6, self.history_image_views.depth.clone()),
+                WriteDescriptorSet::image_view(7, self.history_image_views.reprojected.clone()),
+                WriteDescriptorSet::image_view_sampler(
+                    8,
+                    self.blue_noise_texture.image_view.clone(),
+                    self.blue_noise_texture.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view(9, self.probe.get_image_view()),
+                WriteDescriptorSet::buffer(10, self.material_profiler.get_counters()),
+                WriteDescriptorSet::buffer(11, self.splat_buffer.get_buffer()),
+                WriteDescriptorSet::buffer(12, self.pixel_filter_table.get_buffer()),
+            ],
             [],
-        )
-        .unwrap();
-
-        builder
-            .bind_descriptor_sets(
-                PipelineBindPoint::RayTracing,
-                pipeline_layout.clone(),
-                0,
-                vec![
-                    self.tlas_descriptor_set.clone(),
-                    camera_buffer_descriptor_set,
-                    render_image_descriptor_set,
-                    self.mesh_data_descriptor_set.clone(),
-                    self.image_textures_descriptor_set.clone(),
-                    self.constant_colour_textures_descriptor_set.clone(),
-                    self.materials_descriptor_set.clone(),
-                    self.other_textures_descriptor_set.clone(),
-                    self.sky_descriptor_set.clone(),
-                    self.light_source_alias_table_descriptor_set.clone(),
-                ],
-            )
-            .unwrap()
-            .push_constants(pipeline_layout.clone(), 0, push_constants)
-            .unwrap()
-            .bind_pipeline_ray_tracing(self.rt_pipeline.get())
-            .unwrap();
-
-        // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
-        unsafe {
-            builder
-                .trace_rays(
-                    self.shader_binding_table.addresses().clone(),
-                    self.accum_image_view.image().extent(),
-                )
-                .unwrap();
-        }
+        )?;
 
-        // Increment for next batch.
-        self.current_sample_batch += 1;
+        self.render_image_descriptor_set = Some(descriptor_set.clone());
+        Ok(descriptor_set)
     }
 
     /// Perform the graphics pass to copy rendered image to the swapchain image view using a
     /// big triangle that covers the viewport.
     ///
-    /// It will convert the accumulated sample batches that are linear space to the swapchain
-    /// image format which should be sRGB.
+    /// It will convert the frame's copy of the accumulated sample batches that are linear space to
+    /// the swapchain image format which should be sRGB.
     ///
     /// # Panics
     ///
@@ -584,6 +2017,8 @@ impl RenderEngine {
         &mut self,
         vk: Arc<Vk>,
         swapchain_image_view: Arc<ImageView>,
+        display_image_view: Arc<ImageView>,
+        camera: Arc<RwLock<dyn Camera>>,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     ) {
         let extent = swapchain_image_view.image().extent();
@@ -592,17 +2027,113 @@ impl RenderEngine {
         let gfx_layouts = gfx_pipeline_layout.set_layouts();
         let gfx_render_pass = self.gfx_pipeline.get_render_pass();
 
-        let render_image_sampler =
-            Sampler::new(vk.device.clone(), SamplerCreateInfo::simple_repeat_linear()).unwrap();
+        // Integer scaling keeps pixels sharp unless a lower resolution preview is being shown.
+        let filter = if self.scaling == ScalingPolicy::Integer && self.preview_scale.is_none() {
+            Filter::Nearest
+        } else {
+            Filter::Linear
+        };
+        let render_image_sampler = Sampler::new(
+            vk.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: filter,
+                min_filter: filter,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // The probe image holds indices so it must not be filtered.
+        let probe_sampler = Sampler::new(
+            vk.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let viewport = get_scaled_viewport(self.scaling, self.image_size, [extent[0], extent[1]]);
+
+        // Position of the cursor in [0, 1]^2 of the rendered image.
+        let cursor_uv = self.cursor_position.map(|[x, y]| {
+            [
+                (x - viewport.offset[0]) / viewport.extent[0],
+                (y - viewport.offset[1]) / viewport.extent[1],
+            ]
+        });
+
+        // Find the instance under the cursor so it can be highlighted.
+        self.hovered_instance = cursor_uv.and_then(|uv| self.probe.get_result()?.get_instance(uv));
+
+        let film_matrix = Mat4::from_mat3(self.film_response.get_matrix()).to_cols_array_2d();
+        let push_constants = fragment::FragmentPushConstants {
+            viewportOffset: viewport.offset,
+            viewportExtent: viewport.extent,
+            magnifierCenter: [0.0, 0.0],
+            hoveredInstance: self.hovered_instance.map_or(0, |instance| instance + 1),
+            isMagnifier: 0,
+            magnifierZoom: MAGNIFIER_ZOOM,
+            analysisMode: self.analysis_mode.to_shader(),
+            filmMatrix: film_matrix,
+            filmPreset: self.film_response.get_shader_preset(),
+            isSrgbTarget: self.is_srgb_target as u32,
+        };
+
+        // The magnifier is only shown while the cursor is over the rendered image. It is drawn
+        // with the same pipeline as the rendered image into its own viewport.
+        let magnifier = self
+            .cursor_position
+            .zip(cursor_uv)
+            .filter(|(_, uv)| {
+                self.is_magnifier_visible && uv.iter().all(|c| (0.0..=1.0).contains(c))
+            })
+            .map(|(cursor_position, uv)| {
+                let magnifier_viewport =
+                    get_magnifier_viewport(cursor_position, [extent[0], extent[1]]);
+                let accum_extent = display_image_view.image().extent();
+                let magnifier_push_constants = fragment::FragmentPushConstants {
+                    viewportOffset: magnifier_viewport.offset,
+                    viewportExtent: magnifier_viewport.extent,
+                    magnifierCenter: [
+                        uv[0] * accum_extent[0] as f32,
+                        uv[1] * accum_extent[1] as f32,
+                    ],
+                    hoveredInstance: 0,
+                    isMagnifier: 1,
+                    magnifierZoom: MAGNIFIER_ZOOM,
+                    analysisMode: AnalysisMode::None.to_shader(),
+                    filmMatrix: film_matrix,
+                    filmPreset: self.film_response.get_shader_preset(),
+                    isSrgbTarget: self.is_srgb_target as u32,
+                };
+                (magnifier_viewport, magnifier_push_constants)
+            });
 
         let render_image_descriptor_set_2 = DescriptorSet::new(
             vk.descriptor_set_allocator.clone(),
             gfx_layouts[GfxPipeline::RENDER_IMAGE_LAYOUT].clone(),
-            [WriteDescriptorSet::image_view_sampler(
-                0,
-                self.accum_image_view.clone(),
-                render_image_sampler,
-            )],
+            [
+                WriteDescriptorSet::image_view_sampler(
+                    0,
+                    display_image_view.clone(),
+                    render_image_sampler,
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    1,
+                    self.blue_noise_texture.image_view.clone(),
+                    self.blue_noise_texture.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    2,
+                    self.probe.get_image_view(),
+                    probe_sampler,
+                ),
+                WriteDescriptorSet::buffer(3, self.histogram_buffer.clone()),
+            ],
             [],
         )
         .unwrap();
@@ -632,32 +2163,175 @@ impl RenderEngine {
                 PipelineBindPoint::Graphics,
                 gfx_pipeline_layout.clone(),
                 0,
-                vec![render_image_descriptor_set_2],
+                vec![render_image_descriptor_set_2.clone()],
             )
             .unwrap()
+            .push_constants(gfx_pipeline_layout.clone(), 0, push_constants)
+            .unwrap()
             .bind_pipeline_graphics(self.gfx_pipeline.get())
             .unwrap();
 
         builder
-            .set_viewport(
-                0,
-                vec![Viewport {
-                    offset: [0.0, 0.0],
-                    extent: [extent[0] as _, extent[1] as _],
-                    depth_range: 0.0..=1.0,
-                }]
-                .into(),
-            )
+            .set_viewport(0, vec![viewport.clone()].into())
             .unwrap();
 
         unsafe { builder.draw(3, 1, 0, 0).unwrap() };
 
+        self.render_overlay(vk, camera, viewport, builder);
+
+        // Draw the magnifier last so it is on top of the overlay.
+        if let Some((magnifier_viewport, magnifier_push_constants)) = magnifier {
+            builder
+                .bind_pipeline_graphics(self.gfx_pipeline.get())
+                .unwrap()
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    gfx_pipeline_layout.clone(),
+                    0,
+                    vec![render_image_descriptor_set_2],
+                )
+                .unwrap()
+                .push_constants(gfx_pipeline_layout.clone(), 0, magnifier_push_constants)
+                .unwrap()
+                .set_viewport(0, vec![magnifier_viewport].into())
+                .unwrap();
+
+            unsafe { builder.draw(3, 1, 0, 0).unwrap() };
+        }
+
         builder.end_render_pass(SubpassEndInfo::default()).unwrap();
     }
+
+    /// Records the commands to draw the visible overlay lines over the rendered image. This must be
+    /// called within the graphics render pass.
+    fn render_overlay(
+        &self,
+        vk: Arc<Vk>,
+        camera: Arc<RwLock<dyn Camera>>,
+        viewport: Viewport,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let lines = self.overlay.get_visible_lines();
+        if lines.is_empty() {
+            return;
+        }
+
+        let camera = camera.read().unwrap();
+        let view_proj = camera.get_projection_matrix() * camera.get_view_matrix();
+        let eye = camera
+            .get_view_inverse_matrix()
+            .transform_point3(Vec3::ZERO);
+
+        let overlay_pipeline_layout = self.overlay_pipeline.get_layout();
+        let overlay_layouts = overlay_pipeline_layout.set_layouts();
+
+        // Depth is compared per pixel so it must not be filtered.
+        let depth_sampler = Sampler::new(
+            vk.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let depth_image_descriptor_set_0 = DescriptorSet::new(
+            vk.descriptor_set_allocator.clone(),
+            overlay_layouts[OverlayPipeline::DEPTH_IMAGE_LAYOUT].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                self.aov_image_views.depth.clone(),
+                depth_sampler,
+            )],
+            [],
+        )
+        .unwrap();
+
+        let push_constants = overlay_vertex::OverlayPushConstants {
+            viewProj: view_proj.to_cols_array_2d(),
+            eye: eye.extend(1.0).to_array(),
+            viewportOffset: viewport.offset,
+            viewportExtent: viewport.extent,
+        };
+
+        builder
+            .bind_pipeline_graphics(self.overlay_pipeline.get())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                overlay_pipeline_layout.clone(),
+                0,
+                vec![depth_image_descriptor_set_0],
+            )
+            .unwrap()
+            .push_constants(overlay_pipeline_layout.clone(), 0, push_constants)
+            .unwrap()
+            .set_viewport(0, vec![viewport].into())
+            .unwrap();
+
+        for vertices in lines {
+            let vertex_count = vertices.len() as u32;
+            builder.bind_vertex_buffers(0, vertices).unwrap();
+            unsafe { builder.draw(vertex_count, 1, 0, 0).unwrap() };
+        }
+    }
+}
+
+/// Returns the viewport of the magnifier. It is placed below and to the right of the cursor unless
+/// that would put it outside the swapchain image, in which case it is flipped to the other side.
+fn get_magnifier_viewport(cursor_position: [f32; 2], swapchain_size: [u32; 2]) -> Viewport {
+    let offset = [0, 1].map(|i| {
+        let after = cursor_position[i] + MAGNIFIER_CURSOR_GAP;
+        if after + MAGNIFIER_SIZE <= swapchain_size[i] as f32 {
+            after
+        } else {
+            (cursor_position[i] - MAGNIFIER_CURSOR_GAP - MAGNIFIER_SIZE).max(0.0)
+        }
+    });
+
+    Viewport {
+        offset,
+        extent: [MAGNIFIER_SIZE; 2],
+        depth_range: 0.0..=1.0,
+    }
+}
+
+/// Returns the viewport the rendered image is scaled to within the swapchain image. The viewport
+/// is centered and may extend past the swapchain image when filling it.
+fn get_scaled_viewport(
+    scaling: ScalingPolicy,
+    image_size: [u32; 2],
+    swapchain_size: [u32; 2],
+) -> Viewport {
+    let image_size = [image_size[0].max(1) as f32, image_size[1].max(1) as f32];
+    let swapchain_size = [swapchain_size[0] as f32, swapchain_size[1] as f32];
+
+    let fit_scale = (swapchain_size[0] / image_size[0]).min(swapchain_size[1] / image_size[1]);
+    let scale = match scaling {
+        ScalingPolicy::Fit => fit_scale,
+        ScalingPolicy::Fill => {
+            (swapchain_size[0] / image_size[0]).max(swapchain_size[1] / image_size[1])
+        }
+        ScalingPolicy::Integer if fit_scale >= 1.0 => fit_scale.floor(),
+        ScalingPolicy::Integer => fit_scale,
+    };
+
+    let extent = [image_size[0] * scale, image_size[1] * scale];
+    Viewport {
+        // Round the offset so integer scaled pixels line up with swapchain pixels.
+        offset: [
+            ((swapchain_size[0] - extent[0]) / 2.0).round(),
+            ((swapchain_size[1] - extent[1]) / 2.0).round(),
+        ],
+        extent,
+        depth_range: 0.0..=1.0,
+    }
 }
 
 /// Create a new image to hold the accumulated sample batches.
-fn create_accumulated_render_image_view(
+pub(crate) fn create_accumulated_render_image_view(
     vk: Arc<Vk>,
     width: u32,
     height: u32,
@@ -672,7 +2346,10 @@ fn create_accumulated_render_image_view(
             array_layers: 1,
             samples: SampleCount::Sample1,
             tiling: vulkano::image::ImageTiling::Optimal,
-            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::SAMPLED,
+            usage: ImageUsage::STORAGE
+                | ImageUsage::TRANSFER_SRC
+                | ImageUsage::TRANSFER_DST
+                | ImageUsage::SAMPLED,
             ..Default::default()
         },
         AllocationCreateInfo::default(),
@@ -695,16 +2372,208 @@ fn create_accumulated_render_image_view(
     Ok(image_view)
 }
 
-/// Calculate jittered stratified sampling for time values over [0, 1] based on number of sample batches.
-/// The sample is biased around the center rather than uniform across the full time interval.
-fn get_batch_ray_times(sample_batches: u32) -> Vec<f32> {
-    let d = 1.0 / sample_batches as f32;
+/// Create the descriptor set for the constant colour textures, uploading the colours to a new
+/// buffer, and the scalar constant textures.
+fn create_constant_colour_textures_descriptor_set(
+    vk: Arc<Vk>,
+    tracer: &Tracer,
+    constant_colours: &[[f32; 3]],
+    scalar_textures_buffer: Subbuffer<[f32]>,
+) -> Result<Arc<DescriptorSet>> {
+    let pipeline_layout = tracer.get_layout();
+    let layout = pipeline_layout.set_layouts()[RtPipeline::MATERIAL_COLOURS_LAYOUT].clone();
+
+    let constant_colours = if !constant_colours.is_empty() {
+        constant_colours.to_vec()
+    } else {
+        // We cannot create buffer for empty array. Push constants will have material colours count which can
+        // be used in shaders to make sure out-of-bounds access can be checked.
+        vec![[0.0, 0.0, 0.0]]
+    };
+
+    let constant_colour_textures_buffer = Buffer::from_iter(
+        vk.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        constant_colours,
+    )?;
+
+    let descriptor_set = DescriptorSet::new(
+        vk.descriptor_set_allocator.clone(),
+        layout,
+        vec![
+            WriteDescriptorSet::buffer(0, constant_colour_textures_buffer),
+            WriteDescriptorSet::buffer(1, scalar_textures_buffer),
+        ],
+        [],
+    )?;
+
+    Ok(descriptor_set)
+}
+
+/// Create the descriptor set for the sky, uploading it to a new uniform buffer.
+fn create_sky_descriptor_set(
+    vk: Arc<Vk>,
+    tracer: &Tracer,
+    sky: &Sky,
+) -> Result<Arc<DescriptorSet>> {
+    let pipeline_layout = tracer.get_layout();
+    let layout = pipeline_layout.set_layouts()[RtPipeline::SKY_LAYOUT].clone();
+
+    let sky_buffer = Buffer::from_data(
+        vk.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        sky.to_shader(),
+    )?;
+
+    let descriptor_set = DescriptorSet::new(
+        vk.descriptor_set_allocator.clone(),
+        layout,
+        vec![WriteDescriptorSet::buffer(0, sky_buffer)],
+        [],
+    )?;
+
+    Ok(descriptor_set)
+}
+
+/// Create the descriptor set for the image texture samplers and image views.
+fn create_image_textures_descriptor_set(
+    vk: Arc<Vk>,
+    tracer: &Tracer,
+    image_textures: &ImageTextures,
+) -> Result<Arc<DescriptorSet>> {
+    let pipeline_layout = tracer.get_layout();
+    let layout = pipeline_layout.set_layouts()[RtPipeline::SAMPLERS_AND_TEXTURES_LAYOUT].clone();
+
+    let image_views = &image_textures.image_views;
+
+    // The sampler array always has at least one element so write a default sampler if there are
+    // no textures.
+    let samplers = if !image_textures.samplers.is_empty() {
+        image_textures.samplers.clone()
+    } else {
+        vec![Sampler::new(
+            vk.device.clone(),
+            SamplerCreateInfo::default(),
+        )?]
+    };
+
+    let mut image_texture_descriptor_writes =
+        vec![WriteDescriptorSet::sampler_array(0, 0, samplers)];
+
+    if !image_views.is_empty() {
+        // We cannot create descriptor set for empty array. Push constants will have texture count which can
+        // be used in shaders to make sure out-of-bounds access can be checked.
+        image_texture_descriptor_writes.push(WriteDescriptorSet::image_view_array(
+            1,
+            0,
+            image_views.to_vec(),
+        ));
+    }
+
+    let descriptor_set = if vk
+        .device
+        .enabled_features()
+        .descriptor_binding_variable_descriptor_count
+    {
+        DescriptorSet::new_variable(
+            vk.descriptor_set_allocator.clone(),
+            layout,
+            image_views.len() as _,
+            image_texture_descriptor_writes,
+            [],
+        )?
+    } else {
+        // The layout already has the exact number of image textures.
+        DescriptorSet::new(
+            vk.descriptor_set_allocator.clone(),
+            layout,
+            image_texture_descriptor_writes,
+            [],
+        )?
+    };
+
+    Ok(descriptor_set)
+}
+
+/// Creates the image the accumulated image is copied to at the render resolution before it is
+/// downsampled to the display image, or returns `None` if the render and output resolutions are
+/// the same.
+fn create_supersampled_image_view(
+    vk: Arc<Vk>,
+    output_size: [u32; 2],
+    render_size: [u32; 2],
+) -> Result<Option<Arc<ImageView>>> {
+    if render_size == output_size {
+        return Ok(None);
+    }
+    let image_view = create_accumulated_render_image_view(vk, render_size[0], render_size[1])?;
+    Ok(Some(image_view))
+}
+
+/// Copies an accumulated image to host memory. The pixels are ordered row by row.
+fn read_image(vk: Arc<Vk>, image_view: &Arc<ImageView>) -> Result<Vec<[f32; 4]>> {
+    let image = image_view.image().clone();
+    let extent = image.extent();
+
+    let buffer = Buffer::new_slice::<[f32; 4]>(
+        vk.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        (extent[0] * extent[1]) as DeviceSize,
+    )?;
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        vk.command_buffer_allocator.clone(),
+        vk.queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+
+    builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))?;
+
+    builder
+        .build()?
+        .execute(vk.queue.clone())?
+        .then_signal_fence_and_flush()?
+        .wait(None /* timeout */)?;
+
+    let pixels = buffer.read()?.to_vec();
+    Ok(pixels)
+}
+
+/// Calculate jittered stratified sampling for time values over [open, close] based on number of
+/// sample batches. The sample is biased around the center rather than uniform across the full time
+/// interval.
+fn get_batch_ray_times(sample_batches: u32, open: f32, close: f32) -> Vec<f32> {
+    let d = (close - open) / sample_batches as f32;
 
     (0..sample_batches)
         .map(|i| {
-            let t_center = (i as f32 + 0.5) * d;
+            let t_center = open + (i as f32 + 0.5) * d;
             let jitter = Random::sample_in_range(-0.5, 0.5);
-            (t_center + jitter * d).clamp(0.0, 1.0)
+            (t_center + jitter * d).clamp(open.min(close), open.max(close))
         })
         .collect()
 }
@@ -1,11 +1,58 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
 
 use anyhow::{Context, Result};
-use log::debug;
-use scene_file::SceneFile;
+use glam::Vec3;
+use log::{debug, info};
+use scene_file::{SceneFile, Sky, TextureFilter};
 use vulkano::{format::Format, image::view::ImageView, sync::GpuFuture};
 
-use crate::{Camera, Vk, create_camera, render_engine::RenderEngine};
+use crate::{
+    AnalysisMode, BufferLayouts, Camera, CameraPose, CancellationToken, Estimator, MaterialProfile,
+    OverlayElement, ProbeStatistics, RenderHooks, RenderOutput, SamplingPattern, Vk, create_camera,
+    render_engine::RenderEngine,
+};
+
+/// Colour attachment format used for the graphics pipeline when there is no swapchain.
+const HEADLESS_FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+/// Factor by which the render resolution is reduced while the camera is moving.
+const PREVIEW_SCALE: u32 = 4;
+
+/// Time without camera movement after which full resolution rendering resumes.
+const PREVIEW_IDLE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Number of camera bookmarks that can be stored.
+pub const CAMERA_BOOKMARK_COUNT: usize = 9;
+
+/// Half the size in probe pixels of the window at the center of the image used for autofocus.
+const AUTOFOCUS_RADIUS: u32 = 2;
+
+/// Relative change in the focus distance below which autofocus doesn't refocus. This avoids
+/// restarting accumulation for changes too small to see.
+const AUTOFOCUS_TOLERANCE: f32 = 0.02;
+
+/// Default time taken to move the camera to a bookmark.
+const DEFAULT_CAMERA_TRANSITION_DURATION: Duration = Duration::from_secs(1);
+
+/// Returns the name of the scene file camera used to persist a camera bookmark.
+pub fn get_camera_bookmark_name(index: usize) -> String {
+    format!("bookmark_{}", index + 1)
+}
+
+/// Smooth transition of the camera between two poses.
+struct CameraTransition {
+    /// Pose at the start of the transition.
+    from: CameraPose,
+
+    /// Pose at the end of the transition.
+    to: CameraPose,
+
+    /// Time the transition started.
+    start: Instant,
+}
 
 /// Describes the scene for raytracing.
 pub struct Scene {
@@ -15,55 +62,770 @@ pub struct Scene {
     /// Camera.
     camera: Arc<RwLock<dyn Camera>>,
 
+    /// Cameras described by the scene file that can be rendered.
+    cameras: Vec<scene_file::Camera>,
+
+    /// Name of the camera being rendered.
+    active_camera: String,
+
     /// The render engine to use.
     render_engine: Option<RenderEngine>,
+
+    /// Resolution of the rendered image. If `None`, it follows the window's logical size.
+    resolution: Option<[u32; 2]>,
+
+    /// Time the camera was last moved. This is used to render a downscaled preview while
+    /// navigating.
+    last_camera_move: Option<Instant>,
+
+    /// Pattern of pixels traced per sample batch while the camera is moving.
+    navigation_sampling_pattern: SamplingPattern,
+
+    /// Whether to reproject accumulated samples when the camera moves instead of discarding them.
+    temporal_reprojection: bool,
+
+    /// Camera bookmarks.
+    camera_bookmarks: [Option<CameraPose>; CAMERA_BOOKMARK_COUNT],
+
+    /// Current transition to a camera bookmark.
+    camera_transition: Option<CameraTransition>,
+
+    /// Time taken to move the camera to a bookmark.
+    camera_transition_duration: Duration,
+
+    /// Whether the camera focuses on the surface at the center of the image.
+    is_autofocus: bool,
 }
 
 impl Scene {
-    /// Create a new scene from the given models and camera.
+    /// Create a new scene from the given models and camera for a window with the given physical
+    /// size and scale factor. The image is rendered at the resolution in the scene file or at the
+    /// window's logical size if it has none. Cancelling `cancel` stops building the scene and
+    /// loading its textures. The hooks observe building and rendering the scene.
     pub fn new(
         vk: Arc<Vk>,
         scene_file: &SceneFile,
         window_size: &[f32; 2],
+        scale_factor: f64,
         swapchain_format: Format,
+        cancel: CancellationToken,
+        hooks: RenderHooks,
     ) -> Result<Self> {
-        let render_camera = &scene_file.render.camera;
+        let resolution = scene_file.render.resolution;
+        let image_size = get_render_resolution(resolution, *window_size, scale_factor);
+        Self::new_with_image_size(
+            vk,
+            scene_file,
+            image_size,
+            resolution,
+            swapchain_format,
+            cancel,
+            hooks,
+        )
+    }
 
-        let scene_camera = scene_file
-            .cameras
-            .iter()
-            .find(|&cam| cam.get_name() == render_camera)
-            .with_context(|| format!("Camera ${render_camera} is no specified in cameras"))?;
+    /// Create a new scene for offline rendering at the given image size. Cancelling `cancel` stops
+    /// building the scene and rendering with a `Cancelled` error. The hooks observe building and
+    /// rendering the scene.
+    pub fn new_headless(
+        vk: Arc<Vk>,
+        scene_file: &SceneFile,
+        image_width: u32,
+        image_height: u32,
+        cancel: CancellationToken,
+        hooks: RenderHooks,
+    ) -> Result<Self> {
+        let image_size = [image_width, image_height];
+        Self::new_with_image_size(
+            vk,
+            scene_file,
+            image_size,
+            Some(image_size),
+            HEADLESS_FORMAT,
+            cancel,
+            hooks,
+        )
+    }
+
+    /// Create a new scene rendering at the given image size. If `resolution` is set, the image
+    /// size stays fixed when the window is resized.
+    fn new_with_image_size(
+        vk: Arc<Vk>,
+        scene_file: &SceneFile,
+        image_size: [u32; 2],
+        resolution: Option<[u32; 2]>,
+        swapchain_format: Format,
+        cancel: CancellationToken,
+        hooks: RenderHooks,
+    ) -> Result<Self> {
+        // Strip materials and textures nothing uses before anything is uploaded. The caller's
+        // scene file keeps them so they are still saved.
+        let stripped_scene_file;
+        let scene_file = if scene_file.render.strip_unused {
+            let mut copy = scene_file.clone();
+            let report = copy.strip_unused()?;
+            if !report.is_empty() {
+                info!("{report}");
+            }
+            stripped_scene_file = copy;
+            &stripped_scene_file
+        } else {
+            scene_file
+        };
+
+        // Scenes naming a missing camera render with the first camera instead of failing.
+        let scene_camera = scene_file.get_render_camera()?;
         debug!("{scene_camera:?}");
 
-        let camera = create_camera(scene_camera, window_size[0] as u32, window_size[1] as u32);
+        let camera = create_camera(scene_camera, image_size[0], image_size[1]);
 
-        RenderEngine::new(vk.clone(), scene_file, window_size, swapchain_format).map(
-            |render_engine| Scene {
-                vk,
-                render_engine: Some(render_engine),
-                camera,
-            },
-        )
+        // Restore camera bookmarks persisted in the scene file.
+        let camera_bookmarks = std::array::from_fn(|index| {
+            let name = get_camera_bookmark_name(index);
+            scene_file
+                .cameras
+                .iter()
+                .find(|&cam| cam.get_name() == name)
+                .map(|cam| {
+                    create_camera(cam, image_size[0], image_size[1])
+                        .read()
+                        .unwrap()
+                        .get_pose()
+                })
+        });
+
+        let image_size = [image_size[0] as f32, image_size[1] as f32];
+        let mut render_engine = RenderEngine::new(
+            vk.clone(),
+            scene_file,
+            &image_size,
+            swapchain_format,
+            cancel,
+            hooks,
+        )?;
+        render_engine.set_active_camera(scene_camera.get_name());
+
+        Ok(Scene {
+            vk,
+            render_engine: Some(render_engine),
+            resolution,
+            camera,
+            cameras: scene_file.cameras.clone(),
+            active_camera: scene_camera.get_name().to_string(),
+            last_camera_move: None,
+            navigation_sampling_pattern: SamplingPattern::Full,
+            temporal_reprojection: true,
+            camera_bookmarks,
+            camera_transition: None,
+            camera_transition_duration: DEFAULT_CAMERA_TRANSITION_DURATION,
+            is_autofocus: false,
+        })
+    }
+
+    /// Renders all sample batches and returns the linear colour pixels and AOVs.
+    pub fn render_offline(&mut self) -> Result<RenderOutput> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.render_all_batches(self.vk.clone(), self.camera.clone())?;
+        render_engine.read_render_output(self.vk.clone())
+    }
+
+    /// Requests that loading the textures that haven't loaded yet and offline rendering stop.
+    pub fn cancel(&self) {
+        if let Some(render_engine) = self.render_engine.as_ref() {
+            render_engine.cancel();
+        }
+    }
+
+    /// Sets the interval of time in [0, 1] over which the shutter is open for the next render.
+    /// This is used to render frames of an animation.
+    pub fn set_shutter_interval(&mut self, open: f32, close: f32) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_shutter_interval(self.vk.clone(), open, close)
+    }
+
+    /// Sets the estimator for the light scattered at each hit and restarts accumulation. This is
+    /// used to check that estimators converge to the same image.
+    pub fn set_estimator(&mut self, estimator: Estimator) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_estimator(self.vk.clone(), estimator)
+    }
+
+    /// Returns true if light can be splatted to any pixel of the film and is added to the
+    /// accumulated image.
+    pub fn is_splatting(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_splatting())
+    }
+
+    /// Enables or disables splatting light to any pixel of the film and restarts accumulation.
+    /// This is groundwork for integrators such as light tracing and bidirectional path tracing
+    /// whose paths contribute to pixels other than the one being rendered.
+    pub fn set_splatting(&mut self, is_splatting: bool) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_splatting(self.vk.clone(), is_splatting)
+    }
+
+    /// Returns true if paths traced from the light sources in a separate pass render caustics.
+    pub fn is_tracing_caustics(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_tracing_caustics())
+    }
+
+    /// Enables or disables the caustics pass and restarts accumulation. Camera paths skip the
+    /// caustics the pass splats onto the image so they aren't counted twice.
+    pub fn set_tracing_caustics(&mut self, is_tracing_caustics: bool) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_tracing_caustics(self.vk.clone(), is_tracing_caustics)
+    }
+
+    /// Returns true if shadow rays pass through dielectrics.
+    pub fn is_transparent_shadows(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_transparent_shadows())
+    }
+
+    /// Enables or disables shadow rays passing through dielectrics and restarts accumulation.
+    /// Glass then casts lighter, tinted shadows instead of black ones.
+    pub fn set_transparent_shadows(&mut self, is_transparent_shadows: bool) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_transparent_shadows(self.vk.clone(), is_transparent_shadows)
+    }
+
+    /// Returns the filter used for every image texture instead of the one in its sampler options.
+    pub fn get_texture_filter(&self) -> Option<TextureFilter> {
+        self.render_engine
+            .as_ref()
+            .and_then(|render_engine| render_engine.get_texture_filter())
+    }
+
+    /// Sets the filter used for every image texture, or `None` to use each texture's own filter,
+    /// and restarts accumulation. `Nearest` renders pixel art textures with crisp texels.
+    pub fn set_texture_filter(&mut self, texture_filter: Option<TextureFilter>) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_texture_filter(self.vk.clone(), texture_filter)
+    }
+
+    /// Sets the colour of a constant colour texture and restarts accumulation. This changes the
+    /// albedo of every material using the texture.
+    pub fn set_constant_colour(&mut self, texture: &str, rgb: [f32; 3]) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_constant_colour(self.vk.clone(), texture, rgb)
+    }
+
+    /// Sets the sky and restarts accumulation.
+    pub fn set_sky(&mut self, sky: &Sky) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_sky(self.vk.clone(), sky)
     }
 
-    /// Updates the camera image size to match a new window size.
+    /// Updates the image size to match a new window physical size and scale factor. This does
+    /// nothing if the scene file sets the resolution. Windows with no area, e.g. minimized ones,
+    /// are ignored so the render images and accumulated samples are kept until the window is
+    /// restored to a real size.
     ///
     /// # Panics
     ///
     /// - Panics if the render_engine fails to update image size.
-    pub fn update_window_size(&mut self, window_size: [f32; 2]) {
+    pub fn update_window_size(&mut self, window_size: [f32; 2], scale_factor: f64) {
+        if self.resolution.is_some() || window_size.contains(&0.0) {
+            return;
+        }
+        let [image_width, image_height] = get_render_resolution(None, window_size, scale_factor);
+
+        let mut camera = self.camera.write().unwrap();
+        camera.update_image_size(image_width, image_height);
+
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine
+                .update_image_size(self.vk.clone(), image_width, image_height)
+                .unwrap();
+        }
+    }
+
+    /// Returns the pattern of pixels traced per sample batch while the camera is moving.
+    pub fn get_navigation_sampling_pattern(&self) -> SamplingPattern {
+        self.navigation_sampling_pattern
+    }
+
+    /// Sets the pattern of pixels traced per sample batch while the camera is moving. Patterns
+    /// that trace a subset of pixels reduce the cost of each frame and reconstruct the rest of the
+    /// image from previous frames.
+    pub fn set_navigation_sampling_pattern(&mut self, sampling_pattern: SamplingPattern) {
+        self.navigation_sampling_pattern = sampling_pattern;
+    }
+
+    /// Returns true if accumulated samples are reprojected when the camera moves.
+    pub fn get_temporal_reprojection(&self) -> bool {
+        self.temporal_reprojection
+    }
+
+    /// Sets whether accumulated samples are reprojected when the camera moves instead of being
+    /// discarded.
+    pub fn set_temporal_reprojection(&mut self, temporal_reprojection: bool) {
+        self.temporal_reprojection = temporal_reprojection;
+    }
+
+    /// Returns the number of samples per pixel per sample batch.
+    pub fn get_samples_per_pixel(&self) -> u32 {
+        self.render_engine
+            .as_ref()
+            .map_or(0, |render_engine| render_engine.get_samples_per_pixel())
+    }
+
+    /// Sets the number of samples per pixel per sample batch and restarts accumulation.
+    pub fn set_samples_per_pixel(&mut self, samples_per_pixel: u32) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_samples_per_pixel(self.vk.clone(), samples_per_pixel)
+    }
+
+    /// Returns the number of sample batches that are accumulated.
+    pub fn get_sample_batches(&self) -> u32 {
+        self.render_engine
+            .as_ref()
+            .map_or(0, |render_engine| render_engine.get_sample_batches())
+    }
+
+    /// Sets the number of sample batches to accumulate and restarts accumulation.
+    pub fn set_sample_batches(&mut self, sample_batches: u32) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_sample_batches(self.vk.clone(), sample_batches)
+    }
+
+    /// Returns the order of the textures and materials in the buffers indexed by shaders, for
+    /// debugging index mismatches. This is `None` until the scene is built.
+    pub fn get_buffer_layouts(&self) -> Option<&BufferLayouts> {
+        self.render_engine
+            .as_ref()
+            .map(|render_engine| render_engine.get_buffer_layouts())
+    }
+
+    /// Returns the number of samples per pixel accumulated so far.
+    pub fn get_accumulated_samples_per_pixel(&self) -> u32 {
+        self.render_engine.as_ref().map_or(0, |render_engine| {
+            render_engine.get_accumulated_samples_per_pixel()
+        })
+    }
+
+    /// Returns the maximum number of bounces per ray.
+    pub fn get_max_ray_depth(&self) -> u32 {
+        self.render_engine
+            .as_ref()
+            .map_or(0, |render_engine| render_engine.get_max_ray_depth())
+    }
+
+    /// Sets the maximum number of bounces per ray and restarts accumulation.
+    pub fn set_max_ray_depth(&mut self, max_ray_depth: u32) -> Result<()> {
+        let render_engine = self
+            .render_engine
+            .as_mut()
+            .context("Render engine is not available")?;
+
+        render_engine.set_max_ray_depth(self.vk.clone(), max_ray_depth)
+    }
+
+    /// Returns true if tracing is paused.
+    pub fn is_paused(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_paused())
+    }
+
+    /// Pauses or resumes tracing. While paused, no sample batches are traced and rendering only
+    /// presents the last accumulated image.
+    pub fn set_paused(&mut self, is_paused: bool) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_paused(is_paused);
+        }
+    }
+
+    /// Returns true if sample batches keep accumulating past the configured number of batches.
+    pub fn is_progressive(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_progressive())
+    }
+
+    /// Enables or disables accumulating sample batches past the configured number of batches
+    /// until the camera moves or the scene changes.
+    pub fn set_progressive(&mut self, is_progressive: bool) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_progressive(is_progressive);
+        }
+    }
+
+    /// Returns true if the camera focuses on the surface at the center of the image.
+    pub fn is_autofocus(&self) -> bool {
+        self.is_autofocus
+    }
+
+    /// Sets whether the camera focuses on the surface at the center of the image. This only has
+    /// a visible effect if the camera has an aperture.
+    pub fn set_autofocus(&mut self, is_autofocus: bool) {
+        self.is_autofocus = is_autofocus;
+    }
+
+    /// Sets the position of the cursor in physical pixels of the window, or `None` if it left the
+    /// window. The mesh instance under it is highlighted.
+    pub fn set_cursor_position(&mut self, cursor_position: Option<[f32; 2]>) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_cursor_position(cursor_position);
+        }
+    }
+
+    /// Returns how exposure is visualised in the presented image.
+    pub fn get_analysis_mode(&self) -> AnalysisMode {
+        self.render_engine
+            .as_ref()
+            .map_or(AnalysisMode::None, |render_engine| {
+                render_engine.get_analysis_mode()
+            })
+    }
+
+    /// Sets how exposure is visualised in the presented image. Offline renders are unaffected.
+    pub fn set_analysis_mode(&mut self, analysis_mode: AnalysisMode) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_analysis_mode(analysis_mode);
+        }
+    }
+
+    /// Returns true if the presented image is denoised.
+    pub fn is_denoising(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_denoising())
+    }
+
+    /// Enables or disables denoising the presented image. Accumulation carries on with the noisy
+    /// samples so this can be toggled to compare them.
+    pub fn set_denoising(&mut self, is_denoising: bool) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_denoising(is_denoising);
+        }
+    }
+
+    /// Returns true if the rays that hit each material type are counted.
+    pub fn is_profiling(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_profiling())
+    }
+
+    /// Starts or stops counting the rays that hit each material type. Starting discards the
+    /// previous counts.
+    pub fn set_profiling(&mut self, is_profiling: bool) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_profiling(is_profiling);
+        }
+    }
+
+    /// Returns the counts of the rays that hit each material type since profiling started.
+    pub fn get_material_profile(&self) -> Option<&MaterialProfile> {
+        self.render_engine
+            .as_ref()
+            .map(|render_engine| render_engine.get_material_profile())
+    }
+
+    /// Returns true if the magnifier is shown.
+    pub fn is_magnifier_visible(&self) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_magnifier_visible())
+    }
+
+    /// Shows or hides a magnified crop of the rendered image around the cursor. Like the overlay,
+    /// it is only drawn in the window.
+    pub fn set_magnifier_visible(&mut self, is_magnifier_visible: bool) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_magnifier_visible(is_magnifier_visible);
+        }
+    }
+
+    /// Returns the index and mesh name of the mesh instance under the cursor.
+    pub fn get_hovered_instance(&self) -> Option<(u32, &str)> {
+        let render_engine = self.render_engine.as_ref()?;
+        let instance = render_engine.get_hovered_instance()?;
+        let mesh_name = render_engine.get_instance_mesh_name(instance)?;
+        Some((instance, mesh_name))
+    }
+
+    /// Returns statistics about what the camera sees. These lag the presented image by a few
+    /// frames.
+    pub fn get_probe_statistics(&self) -> Option<ProbeStatistics> {
+        let render_engine = self.render_engine.as_ref()?;
+        render_engine
+            .get_probe_result()
+            .map(|result| result.get_statistics())
+    }
+
+    /// Returns true if an element of the viewport overlay is shown.
+    pub fn is_overlay_visible(&self, element: OverlayElement) -> bool {
+        self.render_engine
+            .as_ref()
+            .is_some_and(|render_engine| render_engine.is_overlay_visible(element))
+    }
+
+    /// Shows or hides an element of the viewport overlay. The overlay is only drawn in the window
+    /// and never appears in offline renders.
+    pub fn set_overlay_visible(&mut self, element: OverlayElement, is_visible: bool) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.set_overlay_visible(element, is_visible);
+        }
+    }
+
+    /// Focuses the camera on the surface at the center of the image if autofocus is enabled and
+    /// the focus distance changed noticeably.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the render_engine fails to restart.
+    fn update_autofocus(&mut self) {
+        if !self.is_autofocus {
+            return;
+        }
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+
         let mut camera = self.camera.write().unwrap();
-        camera.update_image_size(window_size[0] as u32, window_size[1] as u32);
+        if camera.get_aperture_size() <= 0.0 {
+            return;
+        }
+
+        let focus_distance = render_engine
+            .get_probe_result()
+            .and_then(|result| result.get_focus_distance([0.5, 0.5], AUTOFOCUS_RADIUS));
+        if let Some(focus_distance) = focus_distance {
+            let focal_length = camera.get_focal_length();
+            if (focus_distance - focal_length).abs() > AUTOFOCUS_TOLERANCE * focal_length {
+                camera.set_focal_length(focus_distance);
+                render_engine.restart(self.vk.clone()).unwrap();
+            }
+        }
+    }
+
+    /// Moves the camera relative to its orientation by (right, up, forward) in units of the
+    /// distance between the eye and the look at point. Rendering switches to a downscaled preview
+    /// until the camera stops moving.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the render_engine fails to switch to the preview resolution or sampling pattern.
+    pub fn move_camera(&mut self, offset: [f32; 3]) {
+        self.camera_transition = None;
+        self.camera
+            .write()
+            .unwrap()
+            .move_relative(Vec3::from_array(offset));
+
+        self.camera_changed();
+    }
+
+    /// Rolls the camera about its view direction by the given angle in degrees. Rendering switches
+    /// to a downscaled preview until the camera stops moving.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the render_engine fails to switch to the preview resolution or sampling pattern.
+    pub fn roll_camera(&mut self, degrees: f32) {
+        self.camera_transition = None;
+        self.camera.write().unwrap().roll(degrees.to_radians());
+        self.camera_changed();
+    }
+
+    /// Orbits the camera about its look at point by the given angles in degrees about its up and
+    /// right axes. Rendering switches to a downscaled preview until the camera stops moving.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the render_engine fails to switch to the preview resolution or sampling pattern.
+    pub fn orbit_camera(&mut self, yaw_degrees: f32, pitch_degrees: f32) {
+        self.camera_transition = None;
+        self.camera
+            .write()
+            .unwrap()
+            .orbit(yaw_degrees.to_radians(), pitch_degrees.to_radians());
+        self.camera_changed();
+    }
+
+    /// Moves the camera towards its look at point, scaling the distance to it by the given factor.
+    /// Factors below 1 move closer. Rendering switches to a downscaled preview until the camera
+    /// stops moving.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the render_engine fails to switch to the preview resolution or sampling pattern.
+    pub fn dolly_camera(&mut self, factor: f32) {
+        if factor <= 0.0 {
+            return;
+        }
+        self.camera_transition = None;
+        self.camera.write().unwrap().dolly(factor);
+        self.camera_changed();
+    }
+
+    /// Returns the names of the cameras described by the scene file.
+    pub fn get_camera_names(&self) -> Vec<&str> {
+        self.cameras
+            .iter()
+            .map(|camera| camera.get_name())
+            .collect()
+    }
+
+    /// Returns the name of the camera being rendered.
+    pub fn get_active_camera(&self) -> &str {
+        &self.active_camera
+    }
+
+    /// Switches rendering to the scene file camera with the given name and restarts rendering.
+    /// Changes made to the previous camera by navigating are discarded.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the render_engine fails to restart rendering.
+    pub fn set_active_camera(&mut self, name: &str) -> Result<()> {
+        let scene_camera = self
+            .cameras
+            .iter()
+            .find(|camera| camera.get_name() == name)
+            .with_context(|| format!("Camera '{name}' is not specified in cameras"))?;
+
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            let [image_width, image_height] = render_engine.get_image_size();
+            self.camera = create_camera(scene_camera, image_width, image_height);
+            render_engine.set_active_camera(name);
+            render_engine.restart(self.vk.clone()).unwrap();
+        }
+
+        self.active_camera = name.to_string();
+        self.camera_transition = None;
+        Ok(())
+    }
+
+    /// Sets the time taken to move the camera to a bookmark.
+    pub fn set_camera_transition_duration(&mut self, duration: Duration) {
+        self.camera_transition_duration = duration;
+    }
+
+    /// Stores the current camera as a bookmark and returns the scene file description of the
+    /// camera so it can be persisted.
+    pub fn store_camera_bookmark(&mut self, index: usize) -> Result<scene_file::Camera> {
+        let bookmark = self
+            .camera_bookmarks
+            .get_mut(index)
+            .with_context(|| format!("Invalid camera bookmark {}", index + 1))?;
+
+        let camera = self.camera.read().unwrap();
+        *bookmark = Some(camera.get_pose());
+        Ok(camera.to_scene_camera(&get_camera_bookmark_name(index)))
+    }
+
+    /// Starts moving the camera smoothly to a bookmark. Returns false if there is no such
+    /// bookmark.
+    pub fn go_to_camera_bookmark(&mut self, index: usize) -> bool {
+        match self.camera_bookmarks.get(index).copied().flatten() {
+            Some(to) => {
+                self.camera_transition = Some(CameraTransition {
+                    from: self.camera.read().unwrap().get_pose(),
+                    to,
+                    start: Instant::now(),
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Advances the camera transition to a bookmark if there is one.
+    fn update_camera_transition(&mut self) {
+        if let Some(transition) = self.camera_transition.as_ref() {
+            let duration = self.camera_transition_duration.as_secs_f32();
+            let t = if duration > 0.0 {
+                (transition.start.elapsed().as_secs_f32() / duration).min(1.0)
+            } else {
+                1.0
+            };
+
+            // Ease in and out so the camera doesn't start and stop abruptly.
+            let eased = t * t * (3.0 - 2.0 * t);
+            let pose = transition.from.interpolate(&transition.to, eased);
+            self.camera.write().unwrap().set_pose(&pose);
+
+            if t >= 1.0 {
+                self.camera_transition = None;
+            }
+            self.camera_changed();
+        }
+    }
+
+    /// Restarts rendering at the preview resolution after the camera changes.
+    fn camera_changed(&mut self) {
+        self.last_camera_move = Some(Instant::now());
 
         if let Some(render_engine) = self.render_engine.as_mut() {
             render_engine
-                .update_image_size(
+                .set_preview_scale(
                     self.vk.clone(),
-                    window_size[0] as u32,
-                    window_size[1] as u32,
+                    Some(PREVIEW_SCALE),
+                    self.temporal_reprojection,
                 )
                 .unwrap();
+            render_engine
+                .set_sampling_pattern(self.vk.clone(), self.navigation_sampling_pattern)
+                .unwrap();
+            if self.temporal_reprojection {
+                render_engine
+                    .restart_with_reprojection(self.vk.clone())
+                    .unwrap();
+            } else {
+                render_engine.restart(self.vk.clone()).unwrap();
+            }
         }
     }
 
@@ -72,13 +834,30 @@ impl Scene {
     ///
     /// # Panics
     ///
-    /// - Panics if the render_engine fails to create.
+    /// - Panics if the render_engine fails to create or fails to restore full resolution.
     pub fn render(
         &mut self,
         before_future: Box<dyn GpuFuture>,
         swapchain_image_view: Arc<ImageView>,
     ) -> Box<dyn GpuFuture> {
+        self.update_camera_transition();
+        self.update_autofocus();
+
         if let Some(render_engine) = self.render_engine.as_mut() {
+            // Switch back to full resolution once the camera stops moving.
+            if self
+                .last_camera_move
+                .is_some_and(|t| t.elapsed() >= PREVIEW_IDLE_TIMEOUT)
+            {
+                self.last_camera_move = None;
+                render_engine
+                    .set_preview_scale(self.vk.clone(), None, self.temporal_reprojection)
+                    .unwrap();
+                render_engine
+                    .set_sampling_pattern(self.vk.clone(), SamplingPattern::Full)
+                    .unwrap();
+            }
+
             render_engine.render(
                 self.vk.clone(),
                 before_future,
@@ -91,3 +870,17 @@ impl Scene {
         }
     }
 }
+
+/// Returns the resolution to render at. This is the given resolution if any, otherwise the window's
+/// size in logical pixels so high-DPI displays don't multiply the number of rays traced.
+fn get_render_resolution(
+    resolution: Option<[u32; 2]>,
+    window_size: [f32; 2],
+    scale_factor: f64,
+) -> [u32; 2] {
+    let [width, height] = resolution.unwrap_or_else(|| {
+        let scale_factor = scale_factor.max(1.0);
+        window_size.map(|size| (size as f64 / scale_factor).round() as u32)
+    });
+    [width.max(1), height.max(1)]
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use shaders::{SplatResolveShaderModules, splat_resolve};
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    image::view::ImageView,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::PipelineBindPoint,
+};
+
+use crate::{Vk, pipelines::SplatResolvePipeline};
+
+/// Number of channels splatted per pixel.
+const SPLAT_CHANNEL_COUNT: DeviceSize = 3;
+
+/// Light splatted to any pixel of the film, for techniques such as light tracing and bidirectional
+/// connections that contribute to pixels other than the one being rendered. The shaders add to a
+/// buffer of fixed point channels with integer atomics and a resolve pass adds the average over
+/// the samples per pixel to the accumulated image.
+pub struct SplatBuffer {
+    /// The compute pipeline combining the accumulated image with the splat buffer.
+    resolve_pipeline: SplatResolvePipeline,
+
+    /// Fixed point channels the shaders splat into, 3 per pixel row by row.
+    buffer: Subbuffer<[u32]>,
+}
+
+impl SplatBuffer {
+    /// Create the splat buffer for the given image size and the resolve pipeline.
+    pub fn new(vk: Arc<Vk>, width: u32, height: u32) -> Result<Self> {
+        let resolve_pipeline = SplatResolvePipeline::new(
+            vk.device.clone(),
+            SplatResolveShaderModules::load(vk.device.clone()).stage,
+        )?;
+        let buffer = create_splat_buffer(vk, width, height)?;
+
+        Ok(Self {
+            resolve_pipeline,
+            buffer,
+        })
+    }
+
+    /// Reallocates the splat buffer for a new image size. Its contents are undefined until it is
+    /// cleared.
+    pub fn resize(&mut self, vk: Arc<Vk>, width: u32, height: u32) -> Result<()> {
+        self.buffer = create_splat_buffer(vk, width, height)?;
+        Ok(())
+    }
+
+    /// Returns the buffer the shaders splat into.
+    pub fn get_buffer(&self) -> Subbuffer<[u32]> {
+        self.buffer.clone()
+    }
+
+    /// Records clearing the splat buffer. This must be recorded before the first sample batch.
+    pub fn record_clear(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<()> {
+        builder.fill_buffer(self.buffer.clone(), 0)?;
+        Ok(())
+    }
+
+    /// Records the commands to write the accumulated image plus the splatted light averaged over
+    /// `sample_count` samples per pixel to `resolved_image_view`. Both images must have the size
+    /// the splat buffer was allocated for.
+    pub fn record_resolve(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        accum_image_view: Arc<ImageView>,
+        resolved_image_view: Arc<ImageView>,
+        sample_count: u32,
+    ) -> Result<()> {
+        let pipeline_layout = self.resolve_pipeline.get_layout();
+        let layouts = pipeline_layout.set_layouts();
+
+        let extent = accum_image_view.image().extent();
+        let descriptor_set = DescriptorSet::new(
+            vk.descriptor_set_allocator.clone(),
+            layouts[SplatResolvePipeline::SPLAT_RESOLVE_LAYOUT].clone(),
+            [
+                WriteDescriptorSet::image_view(0, accum_image_view),
+                WriteDescriptorSet::buffer(1, self.buffer.clone()),
+                WriteDescriptorSet::image_view(2, resolved_image_view),
+            ],
+            [],
+        )?;
+
+        let group_counts = [
+            extent[0].div_ceil(SplatResolvePipeline::WORKGROUP_SIZE),
+            extent[1].div_ceil(SplatResolvePipeline::WORKGROUP_SIZE),
+            1,
+        ];
+
+        builder
+            .bind_pipeline_compute(self.resolve_pipeline.get())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline_layout.clone(),
+                0,
+                vec![descriptor_set],
+            )?
+            .push_constants(
+                pipeline_layout.clone(),
+                0,
+                splat_resolve::SplatResolvePushConstants {
+                    sampleCount: sample_count,
+                },
+            )?;
+
+        // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
+        unsafe { builder.dispatch(group_counts)? };
+
+        Ok(())
+    }
+}
+
+/// Create the splat buffer for the given image size.
+fn create_splat_buffer(vk: Arc<Vk>, width: u32, height: u32) -> Result<Subbuffer<[u32]>> {
+    let buffer = Buffer::new_slice::<u32>(
+        vk.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        width as DeviceSize * height as DeviceSize * SPLAT_CHANNEL_COUNT,
+    )?;
+    Ok(buffer)
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+    },
+    sync::GpuFuture,
+};
+
+use super::image_texture::create_texture;
+use crate::{Vk, blue_noise::load_blue_noise};
+
+/// The tiling blue noise texture used by the shaders for pixel jitter and dithering. Unlike image
+/// textures it doesn't depend on the scene so it is always bound.
+pub struct BlueNoiseTexture {
+    /// The texture image view.
+    pub image_view: Arc<ImageView>,
+
+    /// Nearest neighbour sampler that repeats the texture.
+    pub sampler: Arc<Sampler>,
+}
+
+impl BlueNoiseTexture {
+    /// Uploads the blue noise texture and waits for the copy to complete.
+    pub fn new(vk: Arc<Vk>) -> Result<Self> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vk.command_buffer_allocator.clone(),
+            vk.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        // The values are thresholds rather than colours so they are stored as linear values.
+        let image_view = create_texture(
+            vk.clone(),
+            &load_blue_noise()?,
+            Format::R8G8B8A8_UNORM,
+            false,
+            &mut builder,
+        )?;
+
+        builder
+            .build()?
+            .execute(vk.queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        let sampler = Sampler::new(
+            vk.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::Repeat; 3],
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            image_view,
+            sampler,
+        })
+    }
+}
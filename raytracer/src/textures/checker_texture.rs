@@ -2,9 +2,8 @@ use core::fmt;
 use std::collections::{HashMap, hash_map::Entry};
 
 use scene_file::Texture;
-use shaders::ray_gen;
 
-use crate::MAT_PROP_VALUE_TYPE_CHECKER;
+use super::get_sorted_textures;
 
 #[derive(Debug)]
 pub struct CheckerTexture {
@@ -24,7 +23,7 @@ impl CheckerTextures {
         let mut textures = vec![];
         let mut indices = HashMap::new();
 
-        for texture in all_textures.values() {
+        for texture in get_sorted_textures(all_textures) {
             if let Texture::Checker {
                 name,
                 scale,
@@ -45,15 +44,6 @@ impl CheckerTextures {
 
         CheckerTextures { textures, indices }
     }
-
-    pub fn to_shader(&self, name: &str) -> Option<ray_gen::MaterialPropertyValue> {
-        self.indices
-            .get(name)
-            .map(|i| ray_gen::MaterialPropertyValue {
-                propValueType: MAT_PROP_VALUE_TYPE_CHECKER,
-                index: *i,
-            })
-    }
 }
 
 impl fmt::Debug for CheckerTextures {
@@ -5,6 +5,7 @@ use ordered_float::OrderedFloat;
 use scene_file::Texture;
 use shaders::ray_gen;
 
+use super::get_sorted_textures;
 use crate::MAT_PROP_VALUE_TYPE_RGB;
 
 /// Stores unique material RGB values which will be added to to a storage buffer used by the
@@ -24,7 +25,7 @@ impl ConstantColourTextures {
         let mut colours = vec![];
         let mut indices = HashMap::new();
 
-        for texture in textures.values() {
+        for texture in get_sorted_textures(textures) {
             if let Texture::Constant { name, rgb } = texture
                 && let Entry::Vacant(e) = indices.entry(name.clone())
             {
@@ -36,13 +37,20 @@ impl ConstantColourTextures {
         ConstantColourTextures { colours, indices }
     }
 
-    pub fn to_shader(&self, name: &str) -> Option<ray_gen::MaterialPropertyValue> {
-        self.indices
-            .get(name)
-            .map(|i| ray_gen::MaterialPropertyValue {
-                propValueType: MAT_PROP_VALUE_TYPE_RGB,
-                index: *i,
-            })
+    /// Adds a colour derived from the colour at the given index and returns it as a material
+    /// property value. Derived colours have no name so they can only be referenced through the
+    /// returned index.
+    pub fn add_derived(
+        &mut self,
+        index: u32,
+        derive: impl Fn([f32; 3]) -> [f32; 3],
+    ) -> ray_gen::MaterialPropertyValue {
+        let colour = derive(self.colours[index as usize]);
+        self.colours.push(colour);
+        ray_gen::MaterialPropertyValue {
+            propValueType: MAT_PROP_VALUE_TYPE_RGB,
+            index: (self.colours.len() - 1) as _,
+        }
     }
 }
 
@@ -1,52 +1,170 @@
 use std::{
     collections::{HashMap, hash_map::Entry},
     fmt,
-    sync::Arc,
+    fs::File,
+    io::{BufReader, Cursor},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
 };
 
-use anyhow::Result;
-use image::{GenericImageView, ImageReader};
-use log::info;
-use scene_file::Texture;
-use shaders::ray_gen;
+use anyhow::{Context, Result, anyhow};
+use image::{DynamicImage, GenericImageView, ImageReader, Rgba, RgbaImage, imageops};
+use log::{info, warn};
+use scene_file::{Channel, ColorSpace, ImageData, SamplerOptions, Texture, TextureFilter};
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo,
-        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+        AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, CopyBufferToImageInfo,
+        ImageBlit, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
     },
     format::Format,
-    image::{Image, ImageCreateInfo, ImageType, ImageUsage, view::ImageView},
+    image::{
+        Image, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
+        sampler::{Filter, Sampler},
+        view::ImageView,
+    },
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture,
+};
+
+use super::{SamplerCache, get_sorted_textures, transform_for_stochastic_tiling};
+use crate::{
+    CancellationToken, IMAGE_CHANNEL_A, IMAGE_CHANNEL_B, IMAGE_CHANNEL_G, IMAGE_CHANNEL_LUMINANCE,
+    IMAGE_CHANNEL_R, IMAGE_CHANNEL_RGB, STOCHASTIC_TILING_NONE, Vk,
 };
 
-use crate::{MAT_PROP_VALUE_TYPE_IMAGE, Vk};
+/// Largest width or height of the preview image uploaded before the full resolution image.
+const PREVIEW_SIZE: u32 = 256;
+
+/// How often waiting for textures checks whether loading was cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Colour of the placeholder image shown until a texture has loaded.
+const PLACEHOLDER_COLOUR: [u8; 4] = [128, 128, 128, 255];
+
+/// Colours of the checker pattern shown in place of textures that can't be loaded.
+const MISSING_COLOURS: [[u8; 4]; 2] = [[255, 0, 255, 255], [0, 0, 0, 255]];
+
+/// Width and height of the missing texture image. Each quarter is a square of the checker pattern
+/// so it repeats across the texture.
+const MISSING_SIZE: u32 = 16;
 
 /// Stores texture image views that will be added to a `SampledImage` variable descriptor used by
 /// the shader.
+///
+/// Textures are streamed so that the scene can be shown before large images are loaded. Each
+/// texture starts as a 1 x 1 placeholder while a background thread decodes the image and sends a
+/// downscaled preview followed by the full resolution image. These are uploaded by `poll` or
+/// `wait`, which replace the image views so the descriptor set needs to be updated afterwards.
+/// Textures that can't be loaded are replaced by a magenta and black checker pattern unless
+/// strict mode is enabled.
+/// Stochastically tiled textures hold their image transformed by `transform_for_stochastic_tiling`
+/// and are followed by an extra image view for the lookup table mapping it back. Cancelling loading stops the background threads and keeps the images
+/// that were uploaded so far.
+/// Uploads are submitted to the same queue as rendering so the images never need a queue family
+/// ownership transfer.
 pub struct ImageTextures {
     /// The texture image views used by the shaders.
     pub image_views: Vec<Arc<ImageView>>,
 
+    /// The sampler for each texture in `image_views`. Textures with the same sampler options share
+    /// a sampler.
+    pub samplers: Vec<Arc<Sampler>>,
+
+    /// The sampler options of each texture in `image_views` as given in the scene file.
+    sampler_options: Vec<SamplerOptions>,
+
+    /// Filter used for every texture instead of the one in its sampler options, if set.
+    filter_override: Option<TextureFilter>,
+
+    /// Creates the samplers, which are recreated when the filter override changes.
+    sampler_cache: SamplerCache,
+
+    /// The channel used for each texture in `image_views` as one of the `IMAGE_CHANNEL_*` values.
+    pub channels: Vec<u32>,
+
+    /// Index in `image_views` of the lookup table of each stochastically tiled texture in
+    /// `image_views`, or `STOCHASTIC_TILING_NONE` for other textures and lookup tables.
+    pub stochastic_tiling_luts: Vec<u32>,
+
     /// Maps unique texture paths to their index in `image_view`. These indices are used in the
     /// MaterialPropertyValue structure.
     pub indices: HashMap<String, u32>,
+
+    /// The image format for each texture in `image_views`, which depends on its colour space.
+    formats: Vec<Format>,
+
+    /// Receives the images decoded by the background threads.
+    receiver: Receiver<StreamedImage>,
+
+    /// Number of textures whose full resolution image hasn't been uploaded yet.
+    pending_count: usize,
+
+    /// Whether textures that can't be loaded are an error.
+    is_strict: bool,
+
+    /// Stops the background threads and waiting for textures when cancelled.
+    cancel: CancellationToken,
+}
+
+/// An image decoded by a background thread.
+struct StreamedImage {
+    /// Index of the texture in `ImageTextures::image_views`.
+    index: usize,
+
+    /// The decoded image or the reason decoding failed.
+    image: Result<RgbaImage>,
+
+    /// Lookup table of the image if the texture is stochastically tiled.
+    lut: Option<RgbaImage>,
+
+    /// Whether this is the full resolution image rather than a preview.
+    is_final: bool,
 }
 
 impl fmt::Debug for ImageTextures {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ImageTextures")
             .field("image_views", &self.image_views.len())
+            .field("samplers", &self.samplers.len())
+            .field("filter_override", &self.filter_override)
+            .field("channels", &self.channels)
+            .field("stochastic_tiling_luts", &self.stochastic_tiling_luts)
             .field("indices", &self.indices)
+            .field("pending_count", &self.pending_count)
+            .field("is_strict", &self.is_strict)
             .finish()
     }
 }
 
 impl ImageTextures {
-    /// Load all unique texture paths from all scene objects. Assumes images have alpha channel.
-    pub fn load(vk: Arc<Vk>, textures: &HashMap<String, Texture>) -> Result<Self> {
+    /// Start loading all unique texture paths from all scene objects in the background. Every
+    /// texture is a placeholder until it has loaded. Images larger than `max_size` are downscaled
+    /// to fit it before they are uploaded. Files that can't be opened are reported immediately and
+    /// are an error if `is_strict` is set. If `filter_override` is set, every texture is sampled
+    /// with that filter.
+    pub fn load(
+        vk: Arc<Vk>,
+        textures: &HashMap<String, Texture>,
+        max_size: Option<u32>,
+        is_strict: bool,
+        filter_override: Option<TextureFilter>,
+        cancel: CancellationToken,
+    ) -> Result<Self> {
         let mut image_views = vec![];
+        let mut samplers = vec![];
+        let mut sampler_options = vec![];
+        let mut formats = vec![];
+        let mut channels = vec![];
+        let mut stochastic_tiling_luts = vec![];
+        let mut pending_count = 0;
+        let mut sampler_cache = SamplerCache::new(vk.device.clone());
         let mut indices = HashMap::new();
+        let (sender, receiver) = mpsc::channel();
 
         let mut builder = AutoCommandBufferBuilder::primary(
             vk.command_buffer_allocator.clone(),
@@ -54,58 +172,446 @@ impl ImageTextures {
             CommandBufferUsage::OneTimeSubmit,
         )?;
 
-        for texture in textures.values() {
-            if let Texture::Image { name, path } = texture
+        for texture in get_sorted_textures(textures) {
+            if let Texture::Image {
+                name,
+                sampler,
+                color_space,
+                channel,
+                stochastic_tiling,
+                ..
+            }
+            | Texture::ImageBytes {
+                name,
+                sampler,
+                color_space,
+                channel,
+                stochastic_tiling,
+                ..
+            } = texture
                 && let Entry::Vacant(e) = indices.entry(name.clone())
             {
-                let texture = load_texture(vk.clone(), path, &mut builder)?;
-                e.insert(image_views.len() as u32);
-                image_views.push(texture);
+                let decoder = match texture {
+                    Texture::Image { path, .. } => ImageReader::open(path)
+                        .with_context(|| format!("Unable to open texture '{path}'"))
+                        .map(|reader| Decoder::File(path.clone(), reader)),
+                    Texture::ImageBytes { data, .. } => {
+                        Ok(Decoder::Memory(name.clone(), data.clone()))
+                    }
+                    _ => unreachable!(),
+                };
+
+                let placeholder = match decoder {
+                    Ok(decoder) => {
+                        spawn_decoder(
+                            image_views.len(),
+                            decoder,
+                            max_size,
+                            *stochastic_tiling,
+                            cancel.clone(),
+                            sender.clone(),
+                        );
+                        pending_count += 1;
+                        RgbaImage::from_pixel(1, 1, Rgba(PLACEHOLDER_COLOUR))
+                    }
+                    Err(e) if is_strict => return Err(e),
+                    Err(e) => {
+                        warn!("{e:?}. Using a placeholder instead.");
+                        create_missing_image()
+                    }
+                };
+
+                let format = match color_space {
+                    ColorSpace::Srgb => Format::R8G8B8A8_SRGB,
+                    ColorSpace::Linear => Format::R8G8B8A8_UNORM,
+                };
+
+                let channel = match channel {
+                    None => IMAGE_CHANNEL_RGB,
+                    Some(Channel::R) => IMAGE_CHANNEL_R,
+                    Some(Channel::G) => IMAGE_CHANNEL_G,
+                    Some(Channel::B) => IMAGE_CHANNEL_B,
+                    Some(Channel::A) => IMAGE_CHANNEL_A,
+                    Some(Channel::Luminance) => IMAGE_CHANNEL_LUMINANCE,
+                };
+
+                // The transformed image of a stochastically tiled texture holds values as they are
+                // and its lookup table is encoded like the original image. Lookup tables are read
+                // texel by texel so they have no mip levels.
+                let index = image_views.len() as u32;
+                let images = match prepare_image(placeholder, *stochastic_tiling) {
+                    (image, Some(lut)) => vec![
+                        (image, Format::R8G8B8A8_UNORM, index + 1, true),
+                        (lut, format, STOCHASTIC_TILING_NONE, false),
+                    ],
+                    (image, None) => vec![(image, format, STOCHASTIC_TILING_NONE, true)],
+                };
+
+                e.insert(index);
+                let texture_sampler =
+                    sampler_cache.get(&apply_filter_override(sampler, filter_override))?;
+                for (image, format, lut_index, is_mipmapped) in images {
+                    image_views.push(create_texture(
+                        vk.clone(),
+                        &image,
+                        format,
+                        is_mipmapped,
+                        &mut builder,
+                    )?);
+                    samplers.push(texture_sampler.clone());
+                    sampler_options.push(*sampler);
+                    formats.push(format);
+                    channels.push(channel);
+                    stochastic_tiling_luts.push(lut_index);
+                }
             }
         }
 
         let _ = builder.build()?.execute(vk.queue.clone())?;
 
         Ok(Self {
+            pending_count,
             image_views,
+            samplers,
+            sampler_options,
+            filter_override,
+            sampler_cache,
+            channels,
+            stochastic_tiling_luts,
             indices,
+            formats,
+            receiver,
+            is_strict,
+            cancel,
         })
     }
 
-    pub fn to_shader(&self, name: &str) -> Option<ray_gen::MaterialPropertyValue> {
-        self.indices
-            .get(name)
-            .map(|i| ray_gen::MaterialPropertyValue {
-                propValueType: MAT_PROP_VALUE_TYPE_IMAGE,
-                index: *i,
+    /// Returns the filter used for every texture instead of the one in its sampler options.
+    pub fn get_filter_override(&self) -> Option<TextureFilter> {
+        self.filter_override
+    }
+
+    /// Sets the filter used for every texture instead of the one in its sampler options, or
+    /// `None` to use each texture's own filter. The samplers are replaced so the descriptor set
+    /// needs to be updated afterwards.
+    pub fn set_filter_override(&mut self, filter_override: Option<TextureFilter>) -> Result<()> {
+        self.filter_override = filter_override;
+        self.samplers = self
+            .sampler_options
+            .iter()
+            .map(|options| {
+                self.sampler_cache
+                    .get(&apply_filter_override(options, filter_override))
             })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Returns true if any texture hasn't been loaded at full resolution yet.
+    pub fn is_streaming(&self) -> bool {
+        self.pending_count > 0
+    }
+
+    /// Uploads the images that were decoded since the last call without blocking. Returns true if
+    /// any image view was replaced. Once loading is cancelled, textures that haven't loaded keep
+    /// their placeholder or preview image.
+    pub fn poll(&mut self, vk: Arc<Vk>) -> Result<bool> {
+        if !self.is_streaming() {
+            return Ok(false);
+        }
+
+        if self.cancel.is_cancelled() {
+            info!("Cancelled loading {} textures", self.pending_count);
+            self.pending_count = 0;
+            return Ok(false);
+        }
+
+        let streamed_images: Vec<_> = self.receiver.try_iter().collect();
+        self.upload(vk, streamed_images)
+    }
+
+    /// Blocks until every texture has been loaded at full resolution and uploads them. Previews
+    /// that haven't been uploaded yet are skipped. Returns true if any image view was replaced.
+    /// Stops with a `Cancelled` error if loading is cancelled while waiting.
+    pub fn wait(&mut self, vk: Arc<Vk>) -> Result<bool> {
+        let mut streamed_images = vec![];
+        let mut remaining = self.pending_count;
+
+        while remaining > 0 {
+            self.cancel.check()?;
+            let streamed_image = match self.receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(streamed_image) => streamed_image,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow!("Texture loading stopped unexpectedly"));
+                }
+            };
+            if streamed_image.is_final {
+                remaining -= 1;
+                streamed_images.push(streamed_image);
+            }
+        }
+
+        self.upload(vk, streamed_images)
+    }
+
+    /// Uploads decoded images and waits for the copies to complete before replacing the image
+    /// views, so the shader never samples an image that is still being written. Returns true if
+    /// any image view was replaced.
+    fn upload(&mut self, vk: Arc<Vk>, streamed_images: Vec<StreamedImage>) -> Result<bool> {
+        if streamed_images.is_empty() {
+            return Ok(false);
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            vk.command_buffer_allocator.clone(),
+            vk.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        let mut uploaded = vec![];
+        for streamed_image in streamed_images {
+            if streamed_image.is_final {
+                self.pending_count -= 1;
+            }
+            let lut_index = self.stochastic_tiling_luts[streamed_image.index];
+            let (image, lut) = match streamed_image.image {
+                Ok(image) => (image, streamed_image.lut),
+                Err(e) if self.is_strict => return Err(e),
+                Err(e) => {
+                    warn!("{e:?}. Using a placeholder instead.");
+                    prepare_image(create_missing_image(), lut_index != STOCHASTIC_TILING_NONE)
+                }
+            };
+            let format = self.formats[streamed_image.index];
+            let image_view = create_texture(vk.clone(), &image, format, true, &mut builder)?;
+            uploaded.push((streamed_image.index, image_view));
+
+            if let Some(lut) = lut {
+                let lut_index = lut_index as usize;
+                let image_view = create_texture(
+                    vk.clone(),
+                    &lut,
+                    self.formats[lut_index],
+                    false,
+                    &mut builder,
+                )?;
+                uploaded.push((lut_index, image_view));
+            }
+        }
+
+        if uploaded.is_empty() {
+            return Ok(false);
+        }
+
+        builder
+            .build()?
+            .execute(vk.queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        for (index, image_view) in uploaded {
+            self.image_views[index] = image_view;
+        }
+
+        if !self.is_streaming() {
+            info!("All textures loaded");
+        }
+
+        Ok(true)
+    }
+}
+
+/// Returns the sampler options with the filter replaced by the override, if set.
+fn apply_filter_override(
+    options: &SamplerOptions,
+    filter_override: Option<TextureFilter>,
+) -> SamplerOptions {
+    SamplerOptions {
+        filter: filter_override.unwrap_or(options.filter),
+        ..*options
+    }
+}
+
+/// Source of an image decoded by a background thread.
+enum Decoder {
+    /// An image file with its path.
+    File(String, ImageReader<BufReader<File>>),
+
+    /// An in-memory image with the name of its texture.
+    Memory(String, ImageData),
+}
+
+impl Decoder {
+    /// Returns a description of the image for messages.
+    fn get_description(&self) -> String {
+        match self {
+            Self::File(path, _) => path.clone(),
+            Self::Memory(name, _) => format!("in-memory image {name}"),
+        }
+    }
+
+    /// Decodes the image. The format of image files and encoded in-memory images is guessed from
+    /// their contents.
+    fn decode(self) -> Result<DynamicImage> {
+        match self {
+            Self::File(_, reader) => Ok(reader.with_guessed_format()?.decode()?),
+            Self::Memory(_, ImageData::Encoded(bytes)) => Ok(ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()?
+                .decode()?),
+            Self::Memory(
+                _,
+                ImageData::Rgba8 {
+                    width,
+                    height,
+                    pixels,
+                },
+            ) => RgbaImage::from_raw(width, height, pixels.to_vec())
+                .map(DynamicImage::ImageRgba8)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Expected {} bytes for {width} x {height} pixels",
+                        width as usize * height as usize * 4
+                    )
+                }),
+        }
     }
 }
 
-/// Loads the image texture into an new image view. Assumes image has alpha.
-fn load_texture(
+/// Decodes an image on a background thread and sends a downscaled preview, if the image is larger
+/// than the preview, followed by the full resolution image, which is downscaled to `max_size` if
+/// it is given. Both are transformed for stochastic tiling if `is_stochastic` is set. Assumes image
+/// has alpha. Nothing more is sent once `cancel` is cancelled.
+fn spawn_decoder(
+    index: usize,
+    decoder: Decoder,
+    max_size: Option<u32>,
+    is_stochastic: bool,
+    cancel: CancellationToken,
+    sender: Sender<StreamedImage>,
+) {
+    thread::spawn(move || {
+        // The receiver is dropped if the scene is closed while loading so send errors are
+        // ignored.
+        let send = |image: Result<RgbaImage>, is_final: bool| {
+            let (image, lut) = match image {
+                Ok(image) => {
+                    let (image, lut) = prepare_image(image, is_stochastic);
+                    (Ok(image), lut)
+                }
+                Err(e) => (Err(e), None),
+            };
+            let _ = sender.send(StreamedImage {
+                index,
+                image,
+                lut,
+                is_final,
+            });
+        };
+
+        if cancel.is_cancelled() {
+            return;
+        }
+
+        let path = decoder.get_description();
+        info!("Loading texture {path}...");
+
+        let img = match decoder.decode() {
+            Ok(img) => img,
+            Err(e) => {
+                send(
+                    Err(e.context(format!("Unable to load texture '{path}'"))),
+                    true,
+                );
+                return;
+            }
+        };
+
+        let (width, height) = img.dimensions();
+        let channels = img.color().channel_count();
+        let mut rgba_image = img.to_rgba8();
+
+        info!("Loaded texture {path}: {width} x {height} x {channels}");
+
+        if let Some(max_size) = max_size
+            && width.max(height) > max_size
+        {
+            rgba_image = downscale(&rgba_image, max_size);
+            info!(
+                "Downscaled texture {path} to {} x {}",
+                rgba_image.width(),
+                rgba_image.height()
+            );
+        }
+
+        if rgba_image.width().max(rgba_image.height()) > PREVIEW_SIZE {
+            send(Ok(downscale(&rgba_image, PREVIEW_SIZE)), false);
+        }
+
+        if !cancel.is_cancelled() {
+            send(Ok(rgba_image), true);
+        }
+    });
+}
+
+/// Returns the image to upload for a texture and, if it is stochastically tiled, its lookup table.
+fn prepare_image(image: RgbaImage, is_stochastic: bool) -> (RgbaImage, Option<RgbaImage>) {
+    if is_stochastic {
+        let (image, lut) = transform_for_stochastic_tiling(&image);
+        (image, Some(lut))
+    } else {
+        (image, None)
+    }
+}
+
+/// Returns the image scaled down so its width and height fit in `size` keeping its aspect ratio.
+fn downscale(image: &RgbaImage, size: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let scale = size as f32 / width.max(height) as f32;
+    imageops::thumbnail(
+        image,
+        ((width as f32 * scale) as u32).max(1),
+        ((height as f32 * scale) as u32).max(1),
+    )
+}
+
+/// Returns the checker pattern shown in place of a texture that can't be loaded.
+fn create_missing_image() -> RgbaImage {
+    RgbaImage::from_fn(MISSING_SIZE, MISSING_SIZE, |x, y| {
+        let is_odd = (x < MISSING_SIZE / 2) != (y < MISSING_SIZE / 2);
+        Rgba(MISSING_COLOURS[is_odd as usize])
+    })
+}
+
+/// Creates an image view for a texture and records copying the pixels into it. If `is_mipmapped`
+/// is set, a full chain of mip levels down to 1 x 1 is generated from the pixels so minified
+/// textures don't shimmer. The format must have 4 x 8-bit channels.
+pub(super) fn create_texture(
     vk: Arc<Vk>,
-    path: &str,
+    rgba_image: &RgbaImage,
+    format: Format,
+    is_mipmapped: bool,
     builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
 ) -> Result<Arc<ImageView>> {
-    info!("Loading texture {path}...");
-
-    let img = ImageReader::open(path)?.with_guessed_format()?.decode()?;
-    let (width, height) = img.dimensions();
-    let colour_type = img.color();
-    let channels = colour_type.channel_count();
-    let rgab_image = img.to_rgba8();
-
-    info!("Loaded texture {path}: {width} x {height} x {channels}");
+    let (width, height) = rgba_image.dimensions();
+    let (mip_levels, usage) = if is_mipmapped {
+        (
+            width.max(height).max(1).ilog2() + 1,
+            ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+        )
+    } else {
+        (1, ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED)
+    };
 
     let image = Image::new(
         vk.memory_allocator.clone(),
         ImageCreateInfo {
             image_type: ImageType::Dim2d,
-            format: Format::R8G8B8A8_SRGB, // Needs to match image format from device.
+            format,
             extent: [width, height, 1],
             array_layers: 1,
-            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            mip_levels,
+            usage,
             ..Default::default()
         },
         AllocationCreateInfo::default(),
@@ -122,16 +628,39 @@ fn load_texture(
                 | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
             ..Default::default()
         },
-        rgab_image.len() as _,
+        rgba_image.as_raw().len() as _,
     )?;
 
     {
         let mut writer = buffer.write()?;
-        writer.copy_from_slice(&rgab_image);
+        writer.copy_from_slice(rgba_image.as_raw());
     }
 
     builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(buffer, image.clone()))?;
 
+    // Each mip level is blitted from the previous one at half its size.
+    let get_extent = |level: u32| [(width >> level).max(1), (height >> level).max(1), 1];
+    for mip_level in 1..mip_levels {
+        builder.blit_image(BlitImageInfo {
+            regions: [ImageBlit {
+                src_subresource: ImageSubresourceLayers {
+                    mip_level: mip_level - 1,
+                    ..image.subresource_layers()
+                },
+                src_offsets: [[0, 0, 0], get_extent(mip_level - 1)],
+                dst_subresource: ImageSubresourceLayers {
+                    mip_level,
+                    ..image.subresource_layers()
+                },
+                dst_offsets: [[0, 0, 0], get_extent(mip_level)],
+                ..Default::default()
+            }]
+            .into(),
+            filter: Filter::Linear,
+            ..BlitImageInfo::images(image.clone(), image.clone())
+        })?;
+    }
+
     let image_view = ImageView::new_default(image)?;
 
     Ok(image_view)
@@ -1,31 +1,78 @@
+mod blue_noise_texture;
 mod checker_texture;
 mod constant_colour_texture;
 mod image_texture;
 mod noise_texture;
+mod sampler_cache;
+mod scalar_texture;
+mod stochastic_tiling;
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+pub use blue_noise_texture::*;
 pub use checker_texture::*;
 pub use constant_colour_texture::*;
 pub use image_texture::*;
 use log::debug;
 pub use noise_texture::*;
-use scene_file::SceneFile;
+pub use sampler_cache::*;
+pub use scalar_texture::*;
+use scene_file::{MaterialVariation, SceneFile, Texture};
 use shaders::ray_gen;
+pub use stochastic_tiling::*;
 use vulkano::buffer::{BufferUsage, Subbuffer};
 
-use crate::{MAT_PROP_VALUE_TYPE_RGB, Vk, create_device_local_buffer};
+use crate::{
+    CancellationToken, IMAGE_CHANNEL_RGB, MAT_PROP_VALUE_TYPE_CHECKER, MAT_PROP_VALUE_TYPE_IMAGE,
+    MAT_PROP_VALUE_TYPE_NOISE, MAT_PROP_VALUE_TYPE_RGB, MAT_PROP_VALUE_TYPE_SCALAR,
+    STOCHASTIC_TILING_NONE, Vk, create_device_local_buffer,
+};
+
+/// Typed handle of a texture in the scene file, resolved from its name once when the scene is
+/// loaded so materials never look textures up by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId {
+    /// Texture type (`MAT_PROP_VALUE_TYPE_*`).
+    value_type: u32,
+
+    /// Index of the texture in the storage buffer or descriptor array of its type.
+    index: u32,
+}
+
+impl TextureId {
+    /// Returns the material property value referencing this texture in shaders.
+    pub fn to_shader(&self) -> ray_gen::MaterialPropertyValue {
+        ray_gen::MaterialPropertyValue {
+            propValueType: self.value_type,
+            index: self.index,
+        }
+    }
+}
 
+/// The scene's textures grouped by type. The textures of each type are sorted by name, so a scene
+/// always gets the same indices in the storage buffers and descriptor arrays however its textures
+/// are ordered. Derived colours of material variations follow the named constant colours.
 pub struct Textures {
     pub constant_colour_textures: ConstantColourTextures,
+    pub scalar_textures: ScalarTextures,
     pub image_textures: ImageTextures,
     pub checker_textures: CheckerTextures,
     pub noise_textures: NoiseTextures,
+
+    /// Maps texture names to their handles. Names are only used to resolve the references in the
+    /// scene file when it is loaded.
+    pub ids: HashMap<String, TextureId>,
 }
 
 impl Textures {
-    pub fn new(vk: Arc<Vk>, scene_file: &SceneFile) -> Result<Self> {
+    /// Loads the scene's textures. Image textures larger than `max_texture_size` are downscaled.
+    pub fn new(
+        vk: Arc<Vk>,
+        scene_file: &SceneFile,
+        max_texture_size: Option<u32>,
+        cancel: CancellationToken,
+    ) -> Result<Self> {
         let all_textures = scene_file.get_textures();
 
         for texture in scene_file.textures.iter() {
@@ -33,36 +80,73 @@ impl Textures {
         }
 
         let constant_colour_textures = ConstantColourTextures::new(&all_textures);
-        let image_textures = ImageTextures::load(vk, &all_textures)?;
+        let scalar_textures = ScalarTextures::new(&all_textures);
+        let image_textures = ImageTextures::load(
+            vk,
+            &all_textures,
+            max_texture_size,
+            scene_file.render.strict_textures,
+            scene_file.render.texture_filter,
+            cancel,
+        )?;
         let checker_textures = CheckerTextures::new(&all_textures);
         let noise_textures = NoiseTextures::new(&all_textures);
 
         debug!("{constant_colour_textures:?}");
+        debug!("{scalar_textures:?}");
         debug!("{image_textures:?}");
 
+        // Texture names are unique across all texture types.
+        let mut ids = HashMap::new();
+        for (value_type, indices) in [
+            (MAT_PROP_VALUE_TYPE_RGB, &constant_colour_textures.indices),
+            (MAT_PROP_VALUE_TYPE_SCALAR, &scalar_textures.indices),
+            (MAT_PROP_VALUE_TYPE_IMAGE, &image_textures.indices),
+            (MAT_PROP_VALUE_TYPE_CHECKER, &checker_textures.indices),
+            (MAT_PROP_VALUE_TYPE_NOISE, &noise_textures.indices),
+        ] {
+            for (name, index) in indices.iter() {
+                let id = TextureId {
+                    value_type,
+                    index: *index,
+                };
+                ids.insert(name.clone(), id);
+            }
+        }
+
         Ok(Self {
             constant_colour_textures,
+            scalar_textures,
             image_textures,
             checker_textures,
             noise_textures,
+            ids,
         })
     }
 
-    pub fn to_shader(&self, name: &str) -> Option<ray_gen::MaterialPropertyValue> {
-        // Texture names will be unique across all texture types.
-        if let Some(v) = self.constant_colour_textures.to_shader(name) {
-            return Some(v);
-        }
-        if let Some(v) = self.image_textures.to_shader(name) {
-            return Some(v);
-        }
-        if let Some(v) = self.checker_textures.to_shader(name) {
-            return Some(v);
-        }
-        if let Some(v) = self.noise_textures.to_shader(name) {
-            return Some(v);
+    /// Returns the handle of the named texture. This fails if there is no such texture.
+    pub fn get_id(&self, name: &str) -> Result<TextureId> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("Texture '{name}' not found"))
+    }
+
+    /// Returns the material property value for a texture with a material variation applied for
+    /// the copy with the given index. Constant colours are replaced by a derived colour and other
+    /// textures are used unchanged.
+    pub fn to_shader_with_variation(
+        &mut self,
+        id: TextureId,
+        variation: &MaterialVariation,
+        index: u64,
+    ) -> ray_gen::MaterialPropertyValue {
+        if id.value_type == MAT_PROP_VALUE_TYPE_RGB {
+            self.constant_colour_textures
+                .add_derived(id.index, |rgb| variation.apply(index, rgb))
+        } else {
+            id.to_shader()
         }
-        None
     }
 
     /// Create a storage buffers for accessing materials in shader code.
@@ -80,12 +164,14 @@ impl Textures {
                 self.checker_textures
                     .textures
                     .iter()
-                    .map(|t| ray_gen::CheckerTexture {
-                        scale: t.scale,
-                        odd: self.to_shader(&t.odd).unwrap(), // TODO could return Err() when odd/even not found.
-                        even: self.to_shader(&t.even).unwrap(),
+                    .map(|t| {
+                        Ok(ray_gen::CheckerTexture {
+                            scale: t.scale,
+                            odd: self.get_id(&t.odd)?.to_shader(),
+                            even: self.get_id(&t.even)?.to_shader(),
+                        })
                     })
-                    .collect()
+                    .collect::<Result<_>>()?
             } else {
                 vec![ray_gen::CheckerTexture {
                     scale: 1.0,
@@ -116,15 +202,49 @@ impl Textures {
             },
         )?;
 
+        debug!("Creating image texture channels storage buffer");
+        let image_channels_buffer = create_device_local_buffer(
+            vk.clone(),
+            buffer_usage,
+            if !self.image_textures.channels.is_empty() {
+                self.image_textures.channels.clone()
+            } else {
+                vec![IMAGE_CHANNEL_RGB]
+            },
+        )?;
+
+        debug!("Creating image texture stochastic tiling storage buffer");
+        let image_stochastic_tiling_buffer = create_device_local_buffer(
+            vk.clone(),
+            buffer_usage,
+            if !self.image_textures.stochastic_tiling_luts.is_empty() {
+                self.image_textures.stochastic_tiling_luts.clone()
+            } else {
+                vec![STOCHASTIC_TILING_NONE]
+            },
+        )?;
+
         Ok(TextureBuffers {
             checker: checker_buffer,
             noise: noise_buffer,
+            image_channels: image_channels_buffer,
+            image_stochastic_tiling: image_stochastic_tiling_buffer,
         })
     }
 }
 
-/// Holds the storage buffers for the textures other than constant colour and image types.
+/// Returns the textures sorted by name.
+fn get_sorted_textures(textures: &HashMap<String, Texture>) -> Vec<&Texture> {
+    let mut sorted: Vec<(&String, &Texture)> = textures.iter().collect();
+    sorted.sort_unstable_by_key(|(name, _)| *name);
+    sorted.into_iter().map(|(_, texture)| texture).collect()
+}
+
+/// Holds the storage buffers for the textures other than constant colour and image types, and the
+/// channel selected and stochastic tiling lookup table of each image texture.
 pub struct TextureBuffers {
     pub checker: Subbuffer<[ray_gen::CheckerTexture]>,
     pub noise: Subbuffer<[ray_gen::NoiseTexture]>,
+    pub image_channels: Subbuffer<[u32]>,
+    pub image_stochastic_tiling: Subbuffer<[u32]>,
 }
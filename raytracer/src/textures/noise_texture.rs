@@ -2,9 +2,8 @@ use core::fmt;
 use std::collections::{HashMap, hash_map::Entry};
 
 use scene_file::Texture;
-use shaders::ray_gen;
 
-use crate::MAT_PROP_VALUE_TYPE_NOISE;
+use super::get_sorted_textures;
 
 #[derive(Debug)]
 pub struct NoiseTexture {
@@ -21,7 +20,7 @@ impl NoiseTextures {
         let mut textures = vec![];
         let mut indices = HashMap::new();
 
-        for texture in all_textures.values() {
+        for texture in get_sorted_textures(all_textures) {
             if let Texture::Noise { name, scale } = texture
                 && let Entry::Vacant(e) = indices.entry(name.clone())
             {
@@ -32,15 +31,6 @@ impl NoiseTextures {
 
         Self { textures, indices }
     }
-
-    pub fn to_shader(&self, name: &str) -> Option<ray_gen::MaterialPropertyValue> {
-        self.indices
-            .get(name)
-            .map(|i| ray_gen::MaterialPropertyValue {
-                propValueType: MAT_PROP_VALUE_TYPE_NOISE,
-                index: *i,
-            })
-    }
 }
 
 impl fmt::Debug for NoiseTextures {
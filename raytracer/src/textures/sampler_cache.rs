@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use scene_file::{BorderColour, SamplerOptions, TextureFilter, TextureWrap};
+use vulkano::{
+    device::Device,
+    image::sampler::{
+        BorderColor, Filter, LOD_CLAMP_NONE, Sampler, SamplerAddressMode, SamplerCreateInfo,
+        SamplerMipmapMode,
+    },
+};
+
+/// Creates samplers for image textures, reusing one sampler for all textures with the same
+/// options.
+pub struct SamplerCache {
+    /// The device the samplers are created on.
+    device: Arc<Device>,
+
+    /// Samplers created so far, keyed by their options.
+    samplers: HashMap<SamplerOptions, Arc<Sampler>>,
+}
+
+impl SamplerCache {
+    /// Create a new empty sampler cache.
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            device,
+            samplers: HashMap::new(),
+        }
+    }
+
+    /// Returns the sampler for the given options, creating it if needed.
+    pub fn get(&mut self, options: &SamplerOptions) -> Result<Arc<Sampler>> {
+        if let Some(sampler) = self.samplers.get(options) {
+            return Ok(sampler.clone());
+        }
+
+        // Linear filtering also blends between mip levels.
+        let (filter, mipmap_mode) = match options.filter {
+            TextureFilter::Nearest => (Filter::Nearest, SamplerMipmapMode::Nearest),
+            TextureFilter::Linear => (Filter::Linear, SamplerMipmapMode::Linear),
+        };
+
+        let address_mode = match options.wrap {
+            TextureWrap::Repeat => SamplerAddressMode::Repeat,
+            TextureWrap::MirroredRepeat => SamplerAddressMode::MirroredRepeat,
+            TextureWrap::ClampToEdge => SamplerAddressMode::ClampToEdge,
+            TextureWrap::ClampToBorder => SamplerAddressMode::ClampToBorder,
+        };
+
+        let border_color = match options.border {
+            BorderColour::TransparentBlack => BorderColor::FloatTransparentBlack,
+            BorderColour::OpaqueBlack => BorderColor::FloatOpaqueBlack,
+            BorderColour::OpaqueWhite => BorderColor::FloatOpaqueWhite,
+        };
+
+        let sampler = Sampler::new(
+            self.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_mode,
+                lod: 0.0..=LOD_CLAMP_NONE,
+                address_mode: [address_mode; 3],
+                border_color,
+                ..Default::default()
+            },
+        )?;
+
+        self.samplers.insert(*options, sampler.clone());
+        Ok(sampler)
+    }
+}
@@ -0,0 +1,44 @@
+use core::fmt;
+use std::collections::{HashMap, hash_map::Entry};
+
+use scene_file::Texture;
+
+use super::get_sorted_textures;
+
+/// Stores scalar texture values which will be added to a storage buffer used by the shader.
+pub struct ScalarTextures {
+    /// The values. This will be used to create the storage buffers for shaders.
+    pub values: Vec<f32>,
+
+    /// Maps unique texture names to their index in `values`. These indices are used in the
+    /// MaterialPropertyValue structure.
+    pub indices: HashMap<String, u32>,
+}
+
+impl ScalarTextures {
+    /// Loads all scalar textures from scene file.
+    pub fn new(textures: &HashMap<String, Texture>) -> Self {
+        let mut values = vec![];
+        let mut indices = HashMap::new();
+
+        for texture in get_sorted_textures(textures) {
+            if let Texture::Scalar { name, value } = texture
+                && let Entry::Vacant(e) = indices.entry(name.clone())
+            {
+                e.insert(values.len() as _);
+                values.push(*value);
+            }
+        }
+
+        Self { values, indices }
+    }
+}
+
+impl fmt::Debug for ScalarTextures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScalarTextures")
+            .field("values", &self.values)
+            .field("indices", &self.indices)
+            .finish()
+    }
+}
@@ -0,0 +1,88 @@
+use std::{array, f64::consts::SQRT_2};
+
+use image::{Rgba, RgbaImage};
+
+use crate::STOCHASTIC_TILING_LUT_SIZE;
+
+/// Number of values of an 8-bit channel.
+const CHANNEL_VALUES: usize = 256;
+
+/// Standard deviation of the Gaussian distribution the channels of a stochastically tiled image
+/// are transformed to. Its mean is 0.5 so nearly all of it fits in [0, 1].
+const GAUSSIAN_STD_DEV: f64 = 1.0 / 6.0;
+
+/// Transforms an image for stochastic tiling, which blends randomly offset copies of it so it
+/// doesn't visibly repeat. Blending averages out the contrast of the image unless its values have
+/// a Gaussian distribution, so each channel's histogram is transformed to one. Returns the
+/// transformed image and the lookup table mapping the transformed values back to the original
+/// ones as a `STOCHASTIC_TILING_LUT_SIZE` x 1 image.
+///
+/// See https://eheitzresearch.wordpress.com/738-2/ (High-Performance By-Example Noise using a
+/// Histogram-Preserving Blending Operator).
+pub fn transform_for_stochastic_tiling(image: &RgbaImage) -> (RgbaImage, RgbaImage) {
+    let pixel_count = (image.width() as f64 * image.height() as f64).max(1.0);
+
+    // The transformed values are quantised to 8 bits, so each value maps to the level whose
+    // share of the Gaussian distribution contains the middle of the value's share of the pixels.
+    // The boundaries between the shares of the levels are halfway between them.
+    let level_boundaries: Vec<f64> = (0..CHANNEL_VALUES - 1)
+        .map(|level| get_gaussian_cdf((level as f64 + 0.5) / (CHANNEL_VALUES - 1) as f64))
+        .collect();
+
+    let mut transforms = [[0u8; CHANNEL_VALUES]; 4];
+    let mut lut = RgbaImage::new(STOCHASTIC_TILING_LUT_SIZE, 1);
+
+    for (channel, transform) in transforms.iter_mut().enumerate() {
+        let mut histogram = [0u32; CHANNEL_VALUES];
+        for pixel in image.pixels() {
+            histogram[pixel[channel] as usize] += 1;
+        }
+
+        // Fraction of the pixels with a value up to and including each value.
+        let mut cdf = [0.0; CHANNEL_VALUES];
+        let mut count = 0;
+        for (value, fraction) in cdf.iter_mut().enumerate() {
+            count += histogram[value];
+            *fraction = count as f64 / pixel_count;
+        }
+
+        let mut below = 0.0;
+        for (value, level) in transform.iter_mut().enumerate() {
+            let middle = 0.5 * (below + cdf[value]);
+            *level = level_boundaries.partition_point(|&boundary| boundary < middle) as u8;
+            below = cdf[value];
+        }
+
+        // The inverse maps each level of the table to the value whose share of the pixels
+        // contains the level's position in the Gaussian distribution.
+        for x in 0..STOCHASTIC_TILING_LUT_SIZE {
+            let rank = get_gaussian_cdf(x as f64 / (STOCHASTIC_TILING_LUT_SIZE - 1) as f64);
+            let value = cdf.partition_point(|&fraction| fraction < rank);
+            lut.get_pixel_mut(x, 0)[channel] = value.min(CHANNEL_VALUES - 1) as u8;
+        }
+    }
+
+    let transformed = RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        Rgba(array::from_fn(|channel| {
+            transforms[channel][pixel[channel] as usize]
+        }))
+    });
+
+    (transformed, lut)
+}
+
+/// Returns the fraction of the Gaussian distribution that transformed values have below `value`.
+fn get_gaussian_cdf(value: f64) -> f64 {
+    0.5 * (1.0 + erf((value - 0.5) / (GAUSSIAN_STD_DEV * SQRT_2)))
+}
+
+/// Returns the error function using the approximation 7.1.26 in Abramowitz and Stegun, which is
+/// accurate to 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = ((((1.061405429 * t - 1.453152027) * t + 1.421413741) * t - 0.284496736) * t
+        + 0.254829592)
+        * t;
+    (1.0 - polynomial * (-x * x).exp()).copysign(x)
+}
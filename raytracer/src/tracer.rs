@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use shaders::{BvhShaderModules, RtShaderModules};
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    pipeline::{PipelineBindPoint, PipelineLayout, ray_tracing::ShaderBindingTable},
+};
+
+use crate::{
+    CancellationToken, Mesh, MeshInstance, Vk,
+    acceleration::AccelerationStructures,
+    bvh::Bvh,
+    pipelines::{BvhPipeline, RtPipeline},
+    render_engine::UnifiedPushConstants,
+};
+
+/// Traces rays through the scene geometry. Devices with raytracing pipelines use hardware
+/// acceleration structures and other devices, like those using MoltenVK, fall back to a compute
+/// shader traversing a BVH built on the CPU. Both share the same path tracing shader code and
+/// descriptor set layouts apart from the first one.
+pub enum Tracer {
+    /// Raytracing pipeline with hardware acceleration structures.
+    Hardware {
+        /// The raytracing pipeline.
+        rt_pipeline: RtPipeline,
+
+        /// The shader binding table.
+        shader_binding_table: ShaderBindingTable,
+
+        /// The acceleration structures.
+        acceleration_structures: AccelerationStructures,
+
+        /// Descriptor set for binding the top-level acceleration structure for the scene.
+        tlas_descriptor_set: Arc<DescriptorSet>,
+    },
+
+    /// Compute pipeline traversing a BVH.
+    Software {
+        /// The compute pipeline.
+        bvh_pipeline: BvhPipeline,
+
+        /// The BVH.
+        bvh: Bvh,
+    },
+}
+
+impl Tracer {
+    /// Create the tracer for the scene geometry. The hardware tracer is used if raytracing
+    /// pipelines are enabled on the device. Its bottom-level acceleration structures are compacted
+    /// if `compact` is set. Building stops with a `Cancelled` error if `cancel` is cancelled.
+    pub fn new(
+        vk: Arc<Vk>,
+        mesh_instances: &[MeshInstance],
+        meshes: &[Arc<Mesh>],
+        batch_ray_time: f32,
+        image_texture_count: u32,
+        compact: bool,
+        cancel: &CancellationToken,
+    ) -> Result<Self> {
+        if !vk.device.enabled_extensions().khr_ray_tracing_pipeline {
+            let bvh_shader_modules = BvhShaderModules::load(vk.device.clone());
+            let bvh_pipeline = BvhPipeline::new(
+                vk.device.clone(),
+                bvh_shader_modules.stage,
+                image_texture_count,
+            )?;
+            cancel.check()?;
+            let bvh = Bvh::new(vk, mesh_instances, meshes, batch_ray_time)?;
+
+            return Ok(Self::Software { bvh_pipeline, bvh });
+        }
+
+        let rt_shader_modules = RtShaderModules::load(vk.device.clone());
+        let rt_pipeline = RtPipeline::new(
+            vk.device.clone(),
+            &rt_shader_modules.stages,
+            &rt_shader_modules.groups,
+            image_texture_count,
+        )?;
+
+        let acceleration_structures = AccelerationStructures::new(
+            vk.clone(),
+            mesh_instances,
+            meshes,
+            batch_ray_time,
+            compact,
+            cancel,
+        )?;
+
+        let tlas_descriptor_set = DescriptorSet::new(
+            vk.descriptor_set_allocator.clone(),
+            rt_pipeline.get_layout().set_layouts()[RtPipeline::TLAS_LAYOUT].clone(),
+            [WriteDescriptorSet::acceleration_structure(
+                0,
+                acceleration_structures.tlas.clone(),
+            )],
+            [],
+        )?;
+
+        let shader_binding_table =
+            ShaderBindingTable::new(vk.memory_allocator.clone(), &rt_pipeline.get())?;
+
+        Ok(Self::Hardware {
+            rt_pipeline,
+            shader_binding_table,
+            acceleration_structures,
+            tlas_descriptor_set,
+        })
+    }
+
+    /// Returns the pipeline layout. The descriptor set layouts other than the first one are the
+    /// same for both tracers.
+    pub fn get_layout(&self) -> Arc<PipelineLayout> {
+        match self {
+            Self::Hardware { rt_pipeline, .. } => rt_pipeline.get_layout(),
+            Self::Software { bvh_pipeline, .. } => bvh_pipeline.get_layout(),
+        }
+    }
+
+    /// Update the scene geometry for the given ray time for motion blur.
+    pub fn update(
+        &mut self,
+        vk: Arc<Vk>,
+        mesh_instances: &[MeshInstance],
+        meshes: &[Arc<Mesh>],
+        batch_ray_time: f32,
+    ) -> Result<()> {
+        match self {
+            Self::Hardware {
+                acceleration_structures,
+                ..
+            } => acceleration_structures.update(vk, mesh_instances, meshes, batch_ray_time),
+            Self::Software { bvh, .. } => bvh.update(vk, mesh_instances, meshes, batch_ray_time),
+        }
+    }
+
+    /// Records commands to trace a sample batch for every pixel of an image with the given extent.
+    /// `descriptor_sets` are bound after the scene geometry descriptor set.
+    pub fn trace(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        descriptor_sets: Vec<Arc<DescriptorSet>>,
+        push_constants: UnifiedPushConstants,
+        extent: [u32; 3],
+    ) -> Result<()> {
+        let pipeline_layout = self.get_layout();
+
+        match self {
+            Self::Hardware {
+                rt_pipeline,
+                shader_binding_table,
+                tlas_descriptor_set,
+                ..
+            } => {
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::RayTracing,
+                        pipeline_layout.clone(),
+                        0,
+                        [vec![tlas_descriptor_set.clone()], descriptor_sets].concat(),
+                    )?
+                    .push_constants(pipeline_layout.clone(), 0, push_constants)?
+                    .bind_pipeline_ray_tracing(rt_pipeline.get())?;
+
+                // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
+                unsafe {
+                    builder.trace_rays(shader_binding_table.addresses().clone(), extent)?;
+                }
+            }
+
+            Self::Software { bvh_pipeline, bvh } => {
+                let bvh_descriptor_set = DescriptorSet::new(
+                    vk.descriptor_set_allocator.clone(),
+                    pipeline_layout.set_layouts()[BvhPipeline::BVH_LAYOUT].clone(),
+                    [
+                        WriteDescriptorSet::buffer(0, bvh.nodes.clone()),
+                        WriteDescriptorSet::buffer(1, bvh.triangles.clone()),
+                        WriteDescriptorSet::buffer(2, bvh.instances.clone()),
+                    ],
+                    [],
+                )?;
+
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Compute,
+                        pipeline_layout.clone(),
+                        0,
+                        [vec![bvh_descriptor_set], descriptor_sets].concat(),
+                    )?
+                    .push_constants(pipeline_layout.clone(), 0, push_constants)?
+                    .bind_pipeline_compute(bvh_pipeline.get())?;
+
+                let group_counts = [
+                    extent[0].div_ceil(BvhPipeline::WORKGROUP_SIZE),
+                    extent[1].div_ceil(BvhPipeline::WORKGROUP_SIZE),
+                    1,
+                ];
+
+                // https://docs.rs/vulkano/latest/vulkano/shader/index.html#safety
+                unsafe {
+                    builder.dispatch(group_counts)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,39 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{Context, Result, anyhow};
-use log::debug;
+use log::{debug, info};
 use vulkano::{
-    DeviceSize,
-    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    DeviceSize, Version, VulkanLibrary,
+    buffer::{
+        Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+    },
     command_buffer::{
         AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo, PrimaryCommandBufferAbstract,
-        allocator::CommandBufferAllocator,
+        allocator::{CommandBufferAllocator, StandardCommandBufferAllocator},
+    },
+    descriptor_set::allocator::{DescriptorSetAllocator, StandardDescriptorSetAllocator},
+    device::{
+        Device, DeviceCreateInfo, DeviceExtensions, DeviceFeatures, Queue, QueueCreateInfo,
+        QueueFlags,
+    },
+    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
+    memory::{
+        MemoryPropertyFlags,
+        allocator::{
+            AllocationCreateInfo, DeviceLayout, MemoryAllocator, MemoryTypeFilter,
+            StandardMemoryAllocator,
+        },
     },
-    descriptor_set::allocator::DescriptorSetAllocator,
-    device::{Device, Queue},
-    memory::allocator::{AllocationCreateInfo, DeviceLayout, MemoryAllocator, MemoryTypeFilter},
     sync::GpuFuture,
 };
 
+use crate::capabilities::{Capabilities, select_device};
+
+/// Size of the arenas of host memory that data is staged in before it is copied to device local
+/// buffers. Larger buffers get a staging buffer of their own.
+const STAGING_ARENA_SIZE: DeviceSize = 16 * 1024 * 1024;
+
 /// Our own vulkano context. Wraps some common resources we will want to use.
 pub struct Vk {
     pub device: Arc<Device>,
@@ -22,10 +41,154 @@ pub struct Vk {
     pub memory_allocator: Arc<dyn MemoryAllocator>,
     pub command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
     pub descriptor_set_allocator: Arc<dyn DescriptorSetAllocator>,
+
+    /// Reusable host memory that data is written to before it is copied to device local buffers.
+    /// Its arenas are recycled once the copies complete.
+    pub staging_allocator: Mutex<SubbufferAllocator>,
+}
+
+impl Vk {
+    /// Create a new vulkano context without a window, surface or swapchain. This is used for
+    /// offline rendering, the tools and headless mode. The queue must support compute for tracing
+    /// and post-processing, and graphics for the pipeline presenting the rendered image.
+    pub fn new_headless() -> Result<Arc<Self>> {
+        let library = VulkanLibrary::new()?;
+
+        let instance = Instance::new(
+            library,
+            InstanceCreateInfo {
+                flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+                application_version: Version::V1_3,
+                ..Default::default()
+            },
+        )?;
+
+        // Pick the best physical device that the renderer can use.
+        let physical_devices: Vec<_> = instance.enumerate_physical_devices()?.collect();
+        let capabilities =
+            select_device(physical_devices.iter().map(|p| Capabilities::new(p)), None)?;
+        capabilities.warn_missing();
+
+        let physical_device = physical_devices
+            .into_iter()
+            .find(|p| capabilities.is_device(p))
+            .context("Unable to find selected physical device")?;
+        let queue_family_index = physical_device
+            .queue_family_properties()
+            .iter()
+            .position(|q| {
+                q.queue_flags
+                    .contains(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+            })
+            .context("Unable to find a queue family supporting graphics and compute")?
+            as u32;
+
+        info!(
+            "Using device: {} (type: {:?})",
+            physical_device.properties().device_name,
+            physical_device.properties().device_type,
+        );
+
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                enabled_extensions: capabilities.get_device_extensions(),
+                enabled_features: capabilities.get_device_features(),
+                ..Default::default()
+            },
+        )?;
+        let queue = queues.next().context("Unable to get device queue")?;
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+        let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
+            device.clone(),
+            Default::default(),
+        ));
+
+        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device.clone(),
+            Default::default(),
+        ));
+
+        Ok(Arc::new(Self {
+            device,
+            queue,
+            staging_allocator: create_staging_allocator(memory_allocator.clone()),
+            memory_allocator,
+            command_buffer_allocator,
+            descriptor_set_allocator,
+        }))
+    }
+}
+
+/// Creates the allocator for `Vk::staging_allocator`.
+pub fn create_staging_allocator(
+    memory_allocator: Arc<dyn MemoryAllocator>,
+) -> Mutex<SubbufferAllocator> {
+    Mutex::new(SubbufferAllocator::new(
+        memory_allocator,
+        SubbufferAllocatorCreateInfo {
+            arena_size: STAGING_ARENA_SIZE,
+            buffer_usage: BufferUsage::TRANSFER_SRC,
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    ))
+}
+
+/// Returns the device extensions needed on every device. Callers presenting to a window will need
+/// to add `khr_swapchain`. Optional extensions are added by `Capabilities::get_device_extensions`.
+pub fn required_device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        khr_synchronization2: true,
+        ..DeviceExtensions::empty()
+    }
+}
+
+/// Returns the device features needed on every device. Optional features are added by
+/// `Capabilities::get_device_features`.
+pub fn required_device_features() -> DeviceFeatures {
+    DeviceFeatures {
+        buffer_device_address: true,
+        runtime_descriptor_array: true,
+        scalar_block_layout: true,
+        shader_int64: true,
+        synchronization2: true,
+        ..DeviceFeatures::empty()
+    }
+}
+
+/// Returns the device extensions needed for hardware raytracing. Without these, rays are traced
+/// by a compute shader instead.
+pub fn raytracing_device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        khr_acceleration_structure: true,
+        khr_deferred_host_operations: true,
+        khr_ray_tracing_pipeline: true,
+        ..DeviceExtensions::empty()
+    }
+}
+
+/// Returns the device features needed for hardware raytracing. Without these, rays are traced by
+/// a compute shader instead.
+pub fn raytracing_device_features() -> DeviceFeatures {
+    DeviceFeatures {
+        acceleration_structure: true,
+        ray_tracing_pipeline: true,
+        ..DeviceFeatures::empty()
+    }
 }
 
 /// This will create buffers that can be accessed only by the GPU. One specific use case is to
-/// access them via device addresses in shaders.
+/// access them via device addresses in shaders. The data is written to host memory and copied to
+/// device local memory the host can't access where possible, which is the fastest for shaders to
+/// read.
 pub fn create_device_local_buffer<T, I>(
     vk: Arc<Vk>,
     usage: BufferUsage,
@@ -44,48 +207,47 @@ where
         return Err(anyhow!("Cannot create device local buffer with empty data"));
     }
 
-    // Create a memory layout so the scratch buffer address is aligned correctly for the storage buffer.
+    // Create a memory layout so the buffer address is aligned correctly for the storage buffer.
     let device_properties = vk.device.physical_device().properties();
-    let min_scratch_offset = device_properties.min_storage_buffer_offset_alignment.into();
-    let buffer_layout = DeviceLayout::from_size_alignment(size_bytes, min_scratch_offset)
+    let min_offset = device_properties.min_storage_buffer_offset_alignment.into();
+    let buffer_layout = DeviceLayout::from_size_alignment(size_bytes, min_offset)
         .context("Unable to create buffer device layout")?;
 
-    debug!("Storage buffer min_storage_buffer_offset_alignment: {min_scratch_offset}");
+    debug!("Storage buffer min_storage_buffer_offset_alignment: {min_offset}");
     debug!("Storage buffer size: {size} ({size_bytes} bytes)");
     debug!("Storage buffer layout: {:?}", buffer_layout);
 
-    let scratch_buffer = Subbuffer::new(Buffer::new(
-        vk.memory_allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::TRANSFER_SRC | BufferUsage::SHADER_DEVICE_ADDRESS,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_HOST
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
-        buffer_layout,
-    )?)
-    .reinterpret::<[T]>();
+    // Most buffers are staged in the reusable arenas. Larger ones get a staging buffer of their
+    // own so the arenas stay small.
+    let staging_buffer = if size_bytes <= STAGING_ARENA_SIZE {
+        vk.staging_allocator
+            .lock()
+            .unwrap()
+            .allocate_slice::<T>(size as _)?
+    } else {
+        debug!("Creating a dedicated staging buffer for {size_bytes} bytes");
+        Buffer::new_slice::<T>(
+            vk.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            size as _,
+        )?
+    };
 
     {
-        let mut write_guard = scratch_buffer.write()?;
+        let mut write_guard = staging_buffer.write()?;
         for (o, i) in write_guard.iter_mut().zip(iter) {
             *o = i;
         }
     }
 
-    let scratch_buffer_address: u64 = scratch_buffer.device_address()?.into();
-    debug!(
-        "Scratch buffer device addr: {scratch_buffer_address} is {}",
-        if scratch_buffer_address.is_multiple_of(min_scratch_offset) {
-            "aligned"
-        } else {
-            "NOT ALIGNED"
-        }
-    );
-
     let device_local_buffer = Subbuffer::new(Buffer::new(
         vk.memory_allocator.clone(),
         BufferCreateInfo {
@@ -93,7 +255,12 @@ where
             ..Default::default()
         },
         AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            // Avoid device local memory the host can map, like a resizable BAR, where there is
+            // other device local memory. Integrated GPUs only have memory the host can map.
+            memory_type_filter: MemoryTypeFilter {
+                not_preferred_flags: MemoryPropertyFlags::HOST_VISIBLE,
+                ..MemoryTypeFilter::PREFER_DEVICE
+            },
             ..Default::default()
         },
         buffer_layout,
@@ -103,7 +270,7 @@ where
     let device_local_buffer_address: u64 = device_local_buffer.device_address()?.into();
     debug!(
         "Device local buffer device addr: {device_local_buffer_address} is {}",
-        if device_local_buffer_address.is_multiple_of(min_scratch_offset) {
+        if device_local_buffer_address.is_multiple_of(min_offset) {
             "aligned"
         } else {
             "NOT ALIGNED"
@@ -117,7 +284,7 @@ where
     )?;
 
     builder.copy_buffer(CopyBufferInfo::buffers(
-        scratch_buffer,
+        staging_buffer,
         device_local_buffer.clone(),
     ))?;
 
@@ -0,0 +1,29 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Preferences for building a primitive's bottom-level acceleration structure. Settings that
+/// aren't given are chosen based on how the primitive is instanced.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccelerationBuild {
+    /// Whether to favour tracing speed or build speed. Defaults to `fast_trace`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preference: Option<BuildPreference>,
+
+    /// Whether the acceleration structure can be updated in place rather than rebuilt. Defaults
+    /// to true if any instance of the primitive is animated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_update: Option<bool>,
+}
+
+/// What an acceleration structure build is optimized for.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildPreference {
+    /// Take longer to build so rays are traced faster. This suits geometry that is built once.
+    #[default]
+    FastTrace,
+
+    /// Build quickly at the cost of slower tracing. This suits geometry that is rebuilt often.
+    FastBuild,
+}
@@ -0,0 +1,138 @@
+use glam::Vec3;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Repeats a primitive on a regular grid. Every instance of the primitive is expanded into one copy
+/// per grid cell, offset in object space before the instance's transform is applied.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArrayModifier {
+    /// Number of copies along the x axis.
+    pub count_x: u32,
+
+    /// Number of copies along the y axis.
+    pub count_y: u32,
+
+    /// Number of copies along the z axis.
+    pub count_z: u32,
+
+    /// Distance between neighbouring copies along each axis in object space.
+    pub spacing: [f32; 3],
+
+    /// Random offsets applied to each copy. Copies are placed on a regular grid if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<ArrayJitter>,
+
+    /// Random changes to the colours of each copy's material so copies don't look identical.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variation: Option<MaterialVariation>,
+}
+
+impl Default for ArrayModifier {
+    fn default() -> Self {
+        Self {
+            count_x: 1,
+            count_y: 1,
+            count_z: 1,
+            spacing: [1.0; 3],
+            jitter: None,
+            variation: None,
+        }
+    }
+}
+
+/// Random offsets of the copies made by an array modifier.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArrayJitter {
+    /// Seed for the offsets. The same seed always produces the same offsets.
+    pub seed: u64,
+
+    /// Fraction of the spacing copies are moved by in [0, 1]. Use 1 to place copies anywhere in
+    /// their cell.
+    pub amount: f32,
+}
+
+impl Default for ArrayJitter {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            amount: 0.5,
+        }
+    }
+}
+
+/// Random changes to the constant colours of the material of each copy made by an array modifier.
+/// Other textures are used unchanged.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialVariation {
+    /// Seed for the changes. The same seed always produces the same colours.
+    pub seed: u64,
+
+    /// Largest change of hue in degrees.
+    pub hue: f32,
+
+    /// Largest fraction the brightness is raised or lowered by in [0, 1].
+    pub brightness: f32,
+}
+
+impl ArrayModifier {
+    /// Returns the object space offset of each copy. The grid starts at the origin and copies are
+    /// ordered along x, then y, then z.
+    pub fn get_offsets(&self) -> Vec<Vec3> {
+        let spacing = Vec3::from(self.spacing);
+        let [count_x, count_y, count_z] =
+            [self.count_x, self.count_y, self.count_z].map(|c| c.max(1));
+
+        (0..count_z)
+            .flat_map(|z| (0..count_y).flat_map(move |y| (0..count_x).map(move |x| (x, y, z))))
+            .enumerate()
+            .map(|(index, (x, y, z))| {
+                let offset = Vec3::new(x as f32, y as f32, z as f32) * spacing;
+                match self.jitter {
+                    Some(jitter) => offset + jitter.get_offset(index as u64) * spacing,
+                    None => offset,
+                }
+            })
+            .collect()
+    }
+}
+
+impl ArrayJitter {
+    /// Returns the random offset of a copy as a fraction of the spacing.
+    fn get_offset(&self, index: u64) -> Vec3 {
+        let amount = self.amount.clamp(0.0, 1.0);
+        let [x, y, z] = [0, 1, 2].map(|axis| get_unit_hash(self.seed, index * 3 + axis) - 0.5);
+        Vec3::new(x, y, z) * amount
+    }
+}
+
+impl MaterialVariation {
+    /// Returns the colour for the copy with the given index. The hue is rotated about the grey
+    /// axis, which keeps the brightness the same, and the result is then scaled.
+    pub fn apply(&self, index: u64, rgb: [f32; 3]) -> [f32; 3] {
+        let hue = (get_unit_hash(self.seed, index * 2) * 2.0 - 1.0) * self.hue.to_radians();
+        let brightness =
+            (get_unit_hash(self.seed, index * 2 + 1) * 2.0 - 1.0) * self.brightness.clamp(0.0, 1.0);
+
+        let axis = Vec3::ONE.normalize();
+        let rgb = Vec3::from(rgb);
+        let (sin, cos) = hue.sin_cos();
+        let rotated = rgb * cos + axis.cross(rgb) * sin + axis * axis.dot(rgb) * (1.0 - cos);
+
+        (rotated * (1.0 + brightness)).max(Vec3::ZERO).to_array()
+    }
+}
+
+/// Returns a value in [0, 1) that depends only on the seed and index. This uses the SplitMix64
+/// finalizer so the offset of a copy doesn't depend on how many copies came before it.
+fn get_unit_hash(seed: u64, index: u64) -> f32 {
+    let mut z = seed
+        .wrapping_add(index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
@@ -0,0 +1,178 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::info;
+
+use crate::fnv1a_hash;
+
+/// Environment variable listing extra directories to search for assets. Directories are separated
+/// the same way as in `PATH`.
+pub const ASSET_DIRS_ENV: &str = "RAYTRACER_ASSET_DIRS";
+
+/// Environment variable setting the directory downloaded assets are cached in.
+pub const ASSET_CACHE_ENV: &str = "RAYTRACER_ASSET_CACHE";
+
+/// Environment variable that allows assets to be downloaded when set to `true`.
+pub const ALLOW_DOWNLOADS_ENV: &str = "RAYTRACER_ALLOW_DOWNLOADS";
+
+/// Finds the files referenced by scene files so scenes can be shared without rewriting their
+/// paths.
+///
+/// Absolute paths are used as is. Relative paths are looked up in the scene file's directory and
+/// then in each search path in order. `http://` and `https://` URLs are downloaded with `curl`
+/// (must be on the `PATH`) into a cache directory and reused from there afterwards, but only if
+/// downloads are allowed.
+#[derive(Clone, Debug)]
+pub struct AssetResolver {
+    /// Directories searched after the scene file's directory.
+    pub search_paths: Vec<PathBuf>,
+
+    /// Directory downloaded assets are stored in.
+    pub cache_dir: PathBuf,
+
+    /// Whether URLs are downloaded. Otherwise they fail to resolve so opening a scene file never
+    /// reaches out to the network unless asked to.
+    pub allow_downloads: bool,
+}
+
+impl Default for AssetResolver {
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+impl AssetResolver {
+    /// Create a resolver that searches the given directories followed by those listed in
+    /// `RAYTRACER_ASSET_DIRS`. Downloads are cached in `RAYTRACER_ASSET_CACHE` if set, otherwise
+    /// in a directory under the system's temporary directory. Downloads are allowed if
+    /// `RAYTRACER_ALLOW_DOWNLOADS` is `true`.
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        let mut search_paths = search_paths;
+        if let Some(dirs) = env::var_os(ASSET_DIRS_ENV) {
+            search_paths.extend(env::split_paths(&dirs));
+        }
+
+        let cache_dir = env::var_os(ASSET_CACHE_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| env::temp_dir().join("raytracer-assets"));
+
+        let allow_downloads = env::var(ALLOW_DOWNLOADS_ENV).is_ok_and(|v| v == "true");
+
+        Self {
+            search_paths,
+            cache_dir,
+            allow_downloads,
+        }
+    }
+
+    /// Returns the local path of an asset referenced by a scene file in `scene_dir`. If a relative
+    /// path isn't found anywhere, it is returned relative to the scene file so the error reported
+    /// when opening it names the expected location.
+    pub fn resolve(&self, path: &str, scene_dir: &Path) -> Result<String> {
+        if is_url(path) {
+            if !self.allow_downloads {
+                return Err(anyhow!(
+                    "Unable to load '{path}' because downloads are disabled. Pass \
+                    --allow-downloads or set {ALLOW_DOWNLOADS_ENV}=true to allow them"
+                ));
+            }
+            return self.download(path);
+        }
+
+        let path_buf = Path::new(path);
+        if path_buf.is_absolute() {
+            return Ok(path.to_string());
+        }
+
+        let relative_to_scene = scene_dir.join(path_buf);
+        let resolved = std::iter::once(relative_to_scene.clone())
+            .chain(self.search_paths.iter().map(|dir| dir.join(path_buf)))
+            .find(|candidate| candidate.exists())
+            .unwrap_or(relative_to_scene);
+
+        resolved
+            .to_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Asset path '{}' is not valid UTF-8", resolved.display()))
+    }
+
+    /// Downloads an asset into the cache unless it is already there and returns its local path.
+    fn download(&self, url: &str) -> Result<String> {
+        let cached_path = self.cache_dir.join(get_cache_file_name(url));
+        let cached = cached_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Cache path '{}' is not valid UTF-8", cached_path.display()))?
+            .to_string();
+
+        if cached_path.exists() {
+            return Ok(cached);
+        }
+
+        fs::create_dir_all(&self.cache_dir).with_context(|| {
+            format!(
+                "Unable to create asset cache '{}'",
+                self.cache_dir.display()
+            )
+        })?;
+
+        // Download to a temporary file so an interrupted download isn't mistaken for a cached one.
+        let partial_path = cached_path.with_extension("partial");
+
+        info!("Downloading {url}");
+        let status = Command::new("curl")
+            .args([
+                "--fail",
+                "--silent",
+                "--show-error",
+                "--location",
+                "--output",
+            ])
+            .arg(&partial_path)
+            .arg(url)
+            .status()
+            .context("Unable to run curl. Make sure it is installed and on the PATH")?;
+
+        if !status.success() {
+            let _ = fs::remove_file(&partial_path);
+            return Err(anyhow!(
+                "Unable to download '{url}'. curl failed with {status}"
+            ));
+        }
+
+        fs::rename(&partial_path, &cached_path)
+            .with_context(|| format!("Unable to cache '{url}' at '{cached}'"))?;
+
+        Ok(cached)
+    }
+}
+
+/// Returns true if the path is an `http://` or `https://` URL.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Returns the file name of a cached download. This is a hash of the whole URL, so different URLs
+/// never share a file however long they are, followed by the URL's extension so image formats can
+/// be guessed.
+fn get_cache_file_name(url: &str) -> String {
+    let hash = format!("{:016x}", fnv1a_hash(url.as_bytes()));
+
+    // Leave out the scheme, host, query and fragment so only the path's extension is used.
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let extension = without_scheme
+        .split(['?', '#'])
+        .next()
+        .and_then(|rest| rest.split_once('/'))
+        .and_then(|(_, path)| Path::new(path).extension())
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    match extension {
+        Some(extension) => format!("{hash}.{extension}"),
+        None => hash,
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::info;
+
+use crate::{SceneFile, fnv1a_hash};
+
+/// Environment variable setting the directory recovery copies of edited scenes are saved in.
+pub const RECOVERY_DIR_ENV: &str = "RAYTRACER_RECOVERY_DIR";
+
+/// How often recovery copies of edited scenes are saved.
+#[derive(Clone, Copy, Debug)]
+pub struct AutosavePolicy {
+    /// Time after the first unsaved edit when a recovery copy is saved.
+    pub interval: Duration,
+
+    /// Number of unsaved edits after which a recovery copy is saved straight away.
+    pub edits: u32,
+}
+
+impl Default for AutosavePolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(120),
+            edits: 10,
+        }
+    }
+}
+
+/// A scene file that is changed while it is shown. Changes are made through `edit` so they can be
+/// tracked and a recovery copy saved regularly, which is offered again if the application exits
+/// without discarding it, e.g. after a crash.
+#[derive(Debug)]
+pub struct EditedSceneFile {
+    /// The scene file including all edits.
+    scene_file: SceneFile,
+
+    /// Path the recovery copy is saved to.
+    recovery_path: PathBuf,
+
+    /// How often the recovery copy is saved.
+    policy: AutosavePolicy,
+
+    /// Number of edits since the recovery copy was last saved.
+    unsaved_edits: u32,
+
+    /// Time of the first edit since the recovery copy was last saved.
+    first_unsaved_edit: Option<Instant>,
+
+    /// Time after which saving the recovery copy is tried again after it failed.
+    retry_after: Option<Instant>,
+}
+
+impl EditedSceneFile {
+    /// Starts tracking edits of a scene file. The name identifies the scene across runs, e.g. its
+    /// path, and is used to find its recovery copy.
+    pub fn new(scene_file: SceneFile, name: &str, policy: AutosavePolicy) -> Self {
+        Self {
+            scene_file,
+            recovery_path: get_recovery_path(name),
+            policy,
+            unsaved_edits: 0,
+            first_unsaved_edit: None,
+            retry_after: None,
+        }
+    }
+
+    /// Returns the scene file including all edits.
+    pub fn get_scene_file(&self) -> &SceneFile {
+        &self.scene_file
+    }
+
+    /// Changes the scene file and marks it as having unsaved edits.
+    pub fn edit<T>(&mut self, f: impl FnOnce(&mut SceneFile) -> T) -> T {
+        self.unsaved_edits += 1;
+        self.first_unsaved_edit.get_or_insert_with(Instant::now);
+        f(&mut self.scene_file)
+    }
+
+    /// Returns true if there are edits that aren't in the recovery copy.
+    pub fn is_dirty(&self) -> bool {
+        self.unsaved_edits > 0
+    }
+
+    /// Saves the recovery copy if there are enough unsaved edits or the oldest one was made long
+    /// enough ago. Returns true if it was saved. If saving fails, it isn't tried again until the
+    /// interval has passed.
+    pub fn autosave_if_due(&mut self) -> Result<bool> {
+        let is_due = self.unsaved_edits >= self.policy.edits.max(1)
+            || self
+                .first_unsaved_edit
+                .is_some_and(|t| t.elapsed() >= self.policy.interval);
+
+        if !is_due || self.retry_after.is_some_and(|t| Instant::now() < t) {
+            return Ok(false);
+        }
+
+        if let Err(e) = self.autosave() {
+            self.retry_after = Some(Instant::now() + self.policy.interval);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Saves the recovery copy. It is written to a temporary file first so a crash while saving
+    /// doesn't leave a partial copy.
+    pub fn autosave(&mut self) -> Result<()> {
+        if let Some(dir) = self.recovery_path.parent() {
+            fs::create_dir_all(dir).with_context(|| {
+                format!("Unable to create recovery directory '{}'", dir.display())
+            })?;
+        }
+
+        let partial_path = self.recovery_path.with_extension("partial");
+        let partial = partial_path.to_str().ok_or_else(|| {
+            anyhow!(
+                "Recovery path '{}' is not valid UTF-8",
+                partial_path.display()
+            )
+        })?;
+        self.scene_file.save_json(partial)?;
+        fs::rename(&partial_path, &self.recovery_path).with_context(|| {
+            format!(
+                "Unable to save recovery copy '{}'",
+                self.recovery_path.display()
+            )
+        })?;
+
+        info!("Saved recovery copy {}", self.recovery_path.display());
+        self.unsaved_edits = 0;
+        self.first_unsaved_edit = None;
+        self.retry_after = None;
+        Ok(())
+    }
+
+    /// Deletes the recovery copy, e.g. when the edits are no longer wanted.
+    pub fn discard_recovery(&self) -> Result<()> {
+        remove_recovery_file(&self.recovery_path)
+    }
+}
+
+/// Returns the recovery copy of the scene with the given name if there is one. Paths in the copy
+/// have already been resolved, so relative ones are relative to the working directory.
+pub fn load_recovery(name: &str) -> Result<Option<SceneFile>> {
+    let path = get_recovery_path(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let serialized = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read recovery copy '{}'", path.display()))?;
+    SceneFile::from_json_str(&serialized)
+        .with_context(|| format!("Unable to parse recovery copy '{}'", path.display()))
+        .map(Some)
+}
+
+/// Deletes the recovery copy of the scene with the given name if there is one.
+pub fn discard_recovery(name: &str) -> Result<()> {
+    remove_recovery_file(&get_recovery_path(name))
+}
+
+/// Returns the path of the recovery copy of the scene with the given name. Copies are saved in
+/// `RAYTRACER_RECOVERY_DIR` if set, otherwise in a directory under the system's temporary
+/// directory. Names of existing files are made absolute so the same file is found from any working
+/// directory, and the copy is named by a hash of that.
+fn get_recovery_path(name: &str) -> PathBuf {
+    let dir = env::var_os(RECOVERY_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::temp_dir().join("raytracer-recovery"));
+
+    let name = fs::canonicalize(name)
+        .ok()
+        .and_then(|p| p.to_str().map(str::to_string))
+        .unwrap_or_else(|| name.to_string());
+
+    dir.join(format!("{:016x}.json", fnv1a_hash(name.as_bytes())))
+}
+
+fn remove_recovery_file(path: &Path) -> Result<()> {
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|| format!("Unable to delete recovery copy '{}'", path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,584 @@
+use anyhow::{Result, anyhow};
+use glam::{Mat4, Quat, Vec3};
+use intersection::{Bvh, Ray};
+
+use crate::{
+    Camera, Facing, Instance, Material, Matrix, Primitive, Quality, Render, Rotate,
+    SCENE_FILE_VERSION, SceneFile, Sky, SpherePoles, SphereSeam, Texture, Transform, TransformType,
+};
+
+/// Distance spheres placed on the ground are pushed into it so they don't appear to float.
+const GROUND_CONTACT_DEPTH: f32 = 0.035;
+
+/// Builds a scene file in code. Textures, materials and primitives are given unique names as they
+/// are added, and each primitive is instanced once unless it's only added as geometry for instance
+/// primitives, so generators don't need to keep names and the scene file's lists in sync.
+#[derive(Debug)]
+pub struct SceneBuilder {
+    /// Cameras available for rendering.
+    cameras: Vec<Camera>,
+
+    /// Textures used by materials.
+    textures: Vec<Texture>,
+
+    /// Materials used by primitives.
+    materials: Vec<Material>,
+
+    /// Geometry that can be instanced.
+    primitives: Vec<Primitive>,
+
+    /// Primitives placed in the scene.
+    instances: Vec<Instance>,
+
+    /// Background colour.
+    sky: Sky,
+
+    /// Render settings.
+    render: Render,
+
+    /// Normal orientation and winding applied to primitives that don't set their own.
+    facing: Facing,
+}
+
+impl SceneBuilder {
+    /// Create a new scene builder with the given render settings and sky. The camera named in the
+    /// render settings must be added before the scene is built.
+    pub fn new(render: Render, sky: Sky) -> Self {
+        Self {
+            cameras: vec![],
+            textures: vec![],
+            materials: vec![],
+            primitives: vec![],
+            instances: vec![],
+            sky,
+            render,
+            facing: Facing::default(),
+        }
+    }
+
+    /// Sets the normal orientation and winding applied to primitives that don't set their own.
+    pub fn facing(&mut self, facing: Facing) -> &mut Self {
+        self.facing = facing;
+        self
+    }
+
+    /// Adds a camera.
+    pub fn add_camera(&mut self, camera: Camera) -> &mut Self {
+        self.cameras.push(camera);
+        self
+    }
+
+    /// Adds a texture and returns its name.
+    pub fn add_texture(&mut self, texture: Texture) -> String {
+        let name = texture.get_name().to_string();
+        self.textures.push(texture);
+        name
+    }
+
+    /// Adds a solid colour texture and returns its name.
+    pub fn constant_texture(&mut self, rgb: [f32; 3]) -> String {
+        let name = self.get_texture_name();
+        self.add_texture(Texture::Constant { name, rgb })
+    }
+
+    /// Adds a single value texture and returns its name.
+    pub fn scalar_texture(&mut self, value: f32) -> String {
+        let name = self.get_texture_name();
+        self.add_texture(Texture::Scalar { name, value })
+    }
+
+    /// Adds a 3D checker texture alternating between two textures and returns its name.
+    pub fn checker_texture(&mut self, scale: f32, even: &str, odd: &str) -> String {
+        let name = self.get_texture_name();
+        self.add_texture(Texture::Checker {
+            name,
+            scale,
+            even: even.to_string(),
+            odd: odd.to_string(),
+        })
+    }
+
+    /// Adds a Perlin noise texture with the given frequency and returns its name.
+    pub fn noise_texture(&mut self, scale: f32) -> String {
+        let name = self.get_texture_name();
+        self.add_texture(Texture::Noise { name, scale })
+    }
+
+    /// Adds a material and returns its name.
+    pub fn add_material(&mut self, material: Material) -> String {
+        let name = material.get_name().to_string();
+        self.materials.push(material);
+        name
+    }
+
+    /// Adds a diffuse material with the given texture and returns its name.
+    pub fn lambertian(&mut self, albedo: &str) -> String {
+        let name = self.get_material_name();
+        self.add_material(Material::Lambertian {
+            name,
+            albedo: albedo.to_string(),
+            normal_map: None,
+            clearcoat: None,
+        })
+    }
+
+    /// Adds a diffuse material with a solid colour and returns its name.
+    pub fn lambertian_rgb(&mut self, rgb: [f32; 3]) -> String {
+        let albedo = self.constant_texture(rgb);
+        self.lambertian(&albedo)
+    }
+
+    /// Adds a reflective material with a solid colour and roughness and returns its name.
+    pub fn metal_rgb(&mut self, rgb: [f32; 3], fuzz: f32) -> String {
+        let albedo = self.constant_texture(rgb);
+        let fuzz = self.scalar_texture(fuzz);
+        let name = self.get_material_name();
+        self.add_material(Material::Metal {
+            name,
+            albedo,
+            fuzz,
+            fuzz_v: None,
+            fuzz_rotation: None,
+            normal_map: None,
+            clearcoat: None,
+        })
+    }
+
+    /// Adds a transparent material and returns its name.
+    pub fn dielectric(&mut self, refraction_index: f32) -> String {
+        let name = self.get_material_name();
+        self.add_material(Material::Dielectric {
+            name,
+            refraction_index,
+            priority: None,
+            clearcoat: None,
+        })
+    }
+
+    /// Adds a light emitting material with a solid colour and returns its name.
+    pub fn diffuse_light_rgb(&mut self, rgb: [f32; 3]) -> String {
+        let emit = self.constant_texture(rgb);
+        let name = self.get_material_name();
+        self.add_material(Material::DiffuseLight {
+            name,
+            emit,
+            temperature_kelvin: None,
+        })
+    }
+
+    /// Adds a primitive along with an instance of it and returns its name.
+    pub fn add_primitive(
+        &mut self,
+        primitive: Primitive,
+        transform: Option<TransformType>,
+    ) -> String {
+        let name = primitive.get_name().to_string();
+        self.primitives.push(primitive);
+        self.instances.push(Instance {
+            name: name.clone(),
+            transform,
+        });
+        name
+    }
+
+    /// Adds a primitive without an instance so it's only placed by instance primitives that
+    /// reference it, and returns its name.
+    pub fn add_geometry(&mut self, primitive: Primitive) -> String {
+        let name = primitive.get_name().to_string();
+        self.primitives.push(primitive);
+        name
+    }
+
+    /// Adds an instance primitive that reuses the geometry of the primitive named `mesh` with its
+    /// own material and transform, along with an instance of it, and returns its name. Instances
+    /// of the same primitive share one acceleration structure.
+    pub fn add_instance_of(
+        &mut self,
+        name: &str,
+        mesh: &str,
+        material: &str,
+        transform: Transform,
+        instance_transform: Option<TransformType>,
+    ) -> String {
+        self.add_primitive(
+            Primitive::Instance {
+                name: name.to_string(),
+                mesh: mesh.to_string(),
+                material: material.to_string(),
+                tags: vec![],
+                layer: None,
+                array: None,
+                transform: Some(transform),
+                velocity: None,
+            },
+            instance_transform,
+        )
+    }
+
+    /// Starts adding a sphere. The sphere is added when `build` is called.
+    pub fn add_sphere(&mut self, center: [f32; 3], radius: f32) -> SphereBuilder<'_> {
+        SphereBuilder {
+            scene_builder: self,
+            name: None,
+            center,
+            radius,
+            rings: 32,
+            segments: 64,
+            poles: SpherePoles::default(),
+            seam: SphereSeam::default(),
+            material: None,
+            facing: None,
+            tags: vec![],
+            layer: None,
+            transform: None,
+            velocity: None,
+        }
+    }
+
+    /// Moves a primitive along `direction` until it rests on the surface of another primitive. If
+    /// `align` is set, the primitive is first rotated about its centre so that `direction` points
+    /// into the surface where a ray cast from its centre hits it. Both primitives are tessellated
+    /// and intersected on the CPU. The placement is stored in the primitive's instance transform
+    /// so it must not already have one.
+    pub fn place_on(
+        &mut self,
+        name: &str,
+        target: &str,
+        direction: [f32; 3],
+        align: bool,
+    ) -> Result<()> {
+        let direction = Vec3::from(direction).normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return Err(anyhow!("Placement direction must not be zero"));
+        }
+
+        let instance_index = self
+            .instances
+            .iter()
+            .position(|instance| instance.name == name)
+            .ok_or_else(|| anyhow!("Instance '{name}' not found"))?;
+        if self.instances[instance_index].transform.is_some() {
+            return Err(anyhow!("Instance '{name}' already has a transform"));
+        }
+
+        let target_to_world = match self
+            .instances
+            .iter()
+            .find(|instance| instance.name == target)
+            .ok_or_else(|| anyhow!("Instance '{target}' not found"))?
+            .get_object_to_world_space_matrix()
+        {
+            Matrix::Static(m) => m,
+            Matrix::Animated(..) => {
+                return Err(anyhow!("Cannot place on animated instance '{target}'"));
+            }
+        };
+
+        let (target_vertices, target_indices) = self.get_world_mesh(target, target_to_world)?;
+        let target_bvh = Bvh::from_mesh(&target_vertices, &target_indices);
+        let (mut vertices, indices) = self.get_world_mesh(name, Mat4::IDENTITY)?;
+
+        let (min, max) = vertices
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+                (min.min(p), max.max(p))
+            });
+        let center = (min + max) / 2.0;
+
+        let rotation = if align {
+            let hit = target_bvh
+                .closest_hit(&Ray::new(center, direction), 0.0, f32::INFINITY)
+                .ok_or_else(|| anyhow!("'{target}' is not below the centre of '{name}'"))?;
+            let normal = hit.normal.normalize();
+            let normal = if normal.dot(direction) > 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            Quat::from_rotation_arc(-direction, normal)
+        } else {
+            Quat::IDENTITY
+        };
+
+        let direction = rotation * direction;
+        for p in vertices.iter_mut() {
+            *p = rotation * (*p - center) + center;
+        }
+        let bvh = Bvh::from_mesh(&vertices, &indices);
+
+        // Contact can happen at a vertex of either primitive so cast rays both ways.
+        let distance = vertices
+            .iter()
+            .filter_map(|&p| target_bvh.closest_hit(&Ray::new(p, direction), 0.0, f32::INFINITY))
+            .chain(
+                target_vertices
+                    .iter()
+                    .filter_map(|&p| bvh.closest_hit(&Ray::new(p, -direction), 0.0, f32::INFINITY)),
+            )
+            .map(|hit| hit.t)
+            .min_by(f32::total_cmp)
+            .ok_or_else(|| anyhow!("'{name}' does not land on '{target}'"))?;
+
+        // Transforms rotate about the origin so the translation accounts for rotating about the
+        // centre instead.
+        let translate = center + direction * distance - rotation * center;
+        let (axis, angle) = rotation.to_axis_angle();
+        self.instances[instance_index].transform = Some(TransformType::Static(Transform {
+            translate: Some(translate.to_array()),
+            rotate: (angle.abs() > f32::EPSILON).then(|| Rotate {
+                axis: axis.to_array(),
+                degrees: angle.to_degrees(),
+            }),
+            scale: None,
+        }));
+
+        Ok(())
+    }
+
+    /// Returns the scene file. This fails if the camera named in the render settings was not
+    /// added.
+    pub fn build(self) -> Result<SceneFile> {
+        if !self
+            .cameras
+            .iter()
+            .any(|camera| camera.get_name() == self.render.camera)
+        {
+            return Err(anyhow!("Camera '{}' was not added", self.render.camera));
+        }
+
+        Ok(SceneFile {
+            version: SCENE_FILE_VERSION,
+            cameras: self.cameras,
+            textures: self.textures,
+            materials: self.materials,
+            primitives: self.primitives,
+            instances: self.instances,
+            sky: self.sky,
+            render: self.render,
+            facing: self.facing,
+        })
+    }
+
+    /// Returns the vertex positions transformed to world space and triangle indices of a
+    /// primitive.
+    fn get_world_mesh(&self, name: &str, object_to_world: Mat4) -> Result<(Vec<Vec3>, Vec<u32>)> {
+        let primitive = self
+            .primitives
+            .iter()
+            .find(|primitive| primitive.get_name() == name)
+            .ok_or_else(|| anyhow!("Primitive '{name}' not found"))?;
+
+        let (geometry, geometry_to_object) = primitive.get_geometry(&self.primitives)?;
+        let (vertices, indices) = geometry.generate_mesh(Quality::Final)?;
+        let object_to_world = object_to_world * geometry_to_object;
+        let vertices = vertices
+            .iter()
+            .map(|v| object_to_world.transform_point3(Vec3::from(v.p)))
+            .collect();
+
+        Ok((vertices, indices))
+    }
+
+    /// Returns a unique name for the next texture.
+    fn get_texture_name(&self) -> String {
+        format!("texture_{}", self.textures.len())
+    }
+
+    /// Returns a unique name for the next material.
+    fn get_material_name(&self) -> String {
+        format!("material_{}", self.materials.len())
+    }
+}
+
+/// A sphere that was added to a scene.
+#[derive(Clone, Debug)]
+pub struct Sphere {
+    /// Name of the primitive and its instance.
+    pub name: String,
+
+    /// Centre in object space.
+    pub center: [f32; 3],
+
+    /// Radius in object space.
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// Returns the centre of a sphere with the given radius moved towards or away from the centre
+    /// of this sphere so it rests on its surface.
+    pub fn get_resting_center(&self, center: [f32; 3], radius: f32) -> [f32; 3] {
+        let ground_center = Vec3::from(self.center);
+        let direction = (Vec3::from(center) - ground_center).normalize();
+        (direction * (self.radius + radius - GROUND_CONTACT_DEPTH) + ground_center).to_array()
+    }
+
+    /// Returns true if this sphere overlaps a sphere with the given centre and radius.
+    pub fn overlaps(&self, center: [f32; 3], radius: f32) -> bool {
+        Vec3::from(self.center).distance(Vec3::from(center)) <= self.radius + radius
+    }
+}
+
+/// Sets up a sphere before adding it to a scene.
+#[derive(Debug)]
+pub struct SphereBuilder<'a> {
+    /// The scene the sphere is added to.
+    scene_builder: &'a mut SceneBuilder,
+
+    /// Unique name of the primitive. A name is generated if this isn't set.
+    name: Option<String>,
+
+    /// Centre in object space.
+    center: [f32; 3],
+
+    /// Radius in object space.
+    radius: f32,
+
+    /// Number of rings from pole to pole.
+    rings: u32,
+
+    /// Number of segments around the equator.
+    segments: u32,
+
+    /// How the rings next to the poles are triangulated.
+    poles: SpherePoles,
+
+    /// Whether vertices along the texture seam are duplicated.
+    seam: SphereSeam,
+
+    /// Name of the material.
+    material: Option<String>,
+
+    /// Overrides the scene's normal orientation and winding.
+    facing: Option<Facing>,
+
+    /// Labels used to select which primitives are rendered.
+    tags: Vec<String>,
+
+    /// Render layer.
+    layer: Option<u32>,
+
+    /// Object to world space transform of the instance.
+    transform: Option<TransformType>,
+
+    /// World space distance the sphere moves from time 0 to 1 for motion blur.
+    velocity: Option<[f32; 3]>,
+}
+
+impl SphereBuilder<'_> {
+    /// Sets the name of the primitive.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the number of rings from pole to pole and segments around the equator.
+    pub fn tessellation(mut self, rings: u32, segments: u32) -> Self {
+        self.rings = rings;
+        self.segments = segments;
+        self
+    }
+
+    /// Sets how the rings next to the poles are triangulated.
+    pub fn poles(mut self, poles: SpherePoles) -> Self {
+        self.poles = poles;
+        self
+    }
+
+    /// Sets whether vertices along the texture seam are duplicated.
+    pub fn seam(mut self, seam: SphereSeam) -> Self {
+        self.seam = seam;
+        self
+    }
+
+    /// Sets the material by name.
+    pub fn material(mut self, material: &str) -> Self {
+        self.material = Some(material.to_string());
+        self
+    }
+
+    /// Overrides the scene's normal orientation and winding.
+    pub fn facing(mut self, facing: Facing) -> Self {
+        self.facing = Some(facing);
+        self
+    }
+
+    /// Adds a label used to select which primitives are rendered.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Sets the render layer.
+    pub fn layer(mut self, layer: u32) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+
+    /// Moves the sphere towards or away from the centre of the ground sphere so it rests on its
+    /// surface.
+    pub fn touching_ground(mut self, ground: &Sphere) -> Self {
+        self.center = ground.get_resting_center(self.center, self.radius);
+        self
+    }
+
+    /// Sets the transform of the instance.
+    pub fn transform(mut self, transform: TransformType) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Sets the world space distance the sphere moves from time 0 to 1 for motion blur.
+    pub fn velocity(mut self, velocity: [f32; 3]) -> Self {
+        self.velocity = Some(velocity);
+        self
+    }
+
+    /// Adds the sphere to the scene. This fails if no material was set.
+    pub fn build(self) -> Result<Sphere> {
+        let primitive = self.get_primitive()?;
+        let sphere = Sphere {
+            name: primitive.get_name().to_string(),
+            center: self.center,
+            radius: self.radius,
+        };
+        self.scene_builder.add_primitive(primitive, self.transform);
+        Ok(sphere)
+    }
+
+    /// Adds the sphere to the scene without an instance so it's only placed by instance
+    /// primitives that reference it, and returns its name. This fails if no material was set.
+    pub fn build_geometry(self) -> Result<String> {
+        let primitive = self.get_primitive()?;
+        Ok(self.scene_builder.add_geometry(primitive))
+    }
+
+    /// Returns the UV sphere primitive. This fails if no material was set.
+    fn get_primitive(&self) -> Result<Primitive> {
+        let name = self
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("sphere_{}", self.scene_builder.primitives.len()));
+        let material = self
+            .material
+            .clone()
+            .ok_or_else(|| anyhow!("Sphere '{name}' has no material"))?;
+
+        Ok(Primitive::UvSphere {
+            name,
+            center: self.center,
+            radius: self.radius,
+            rings: self.rings,
+            segments: self.segments,
+            poles: self.poles,
+            seam: self.seam,
+            material,
+            facing: self.facing,
+            acceleration: None,
+            tags: self.tags.clone(),
+            layer: self.layer,
+            array: None,
+            transform: None,
+            velocity: self.velocity,
+        })
+    }
+}
@@ -1,25 +1,164 @@
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Exposure value at ISO 100 that leaves scene radiance unchanged. Typical daylight settings such
+/// as ISO 100, f/8 and 1/125 s render about as bright as a camera without exposure settings.
+const REFERENCE_EV100: f32 = 13.0;
+
+/// Height of a full frame sensor in metres, used when `Exposure::sensor_height` isn't given.
+const DEFAULT_SENSOR_HEIGHT: f32 = 0.024;
+
+/// Camera that can be selected for rendering by name.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Camera {
+    /// Pinhole or thin lens camera with a perspective projection.
     Perspective {
+        /// Unique name of the camera.
         name: String,
+
+        /// Position of the camera.
         eye: [f32; 3],
+
+        /// Point the camera looks at.
         look_at: [f32; 3],
-        up: [f32; 3],
-        fov_y: f32, // Vertical FOV in degrees.
+
+        /// Up direction. Defaults to +Y. Ignored if orientation is a quaternion.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        up: Option<[f32; 3]>,
+
+        /// Overrides or adjusts the orientation given by `look_at` and `up`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        orientation: Option<Orientation>,
+
+        /// Vertical field of view in degrees.
+        fov_y: f32,
+
+        /// Distance to the near clipping plane.
         z_near: f32,
+
+        /// Distance to the far clipping plane.
         z_far: f32,
+
+        /// Distance to the plane in perfect focus.
         focal_length: f32,
+
+        /// Lens aperture diameter. Use 0 for a pinhole camera. Ignored if `exposure` is given.
         aperture_size: f32,
+
+        /// Physical camera settings. Defaults to no exposure adjustment and `aperture_size`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exposure: Option<Exposure>,
     },
 }
 
+/// Physical camera settings that determine how bright the rendered image is. The f-number also
+/// sets the aperture and with it the depth of field. The shutter speed doesn't affect motion blur,
+/// which is set by the render's shutter interval.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Exposure {
+    /// Sensor sensitivity.
+    pub iso: f32,
+
+    /// Ratio of the focal length to the aperture diameter.
+    pub f_stop: f32,
+
+    /// Shutter speed in seconds.
+    pub shutter_speed: f32,
+
+    /// Height of the sensor in scene units. With the vertical field of view this gives the focal
+    /// length of the lens. Defaults to a full frame sensor, 24mm, for scenes in metres.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_height: Option<f32>,
+}
+
+impl Exposure {
+    /// Returns an error naming the first setting that isn't positive and finite.
+    pub fn validate(&self) -> Result<()> {
+        let settings = [
+            ("iso", Some(self.iso)),
+            ("f_stop", Some(self.f_stop)),
+            ("shutter_speed", Some(self.shutter_speed)),
+            ("sensor_height", self.sensor_height),
+        ];
+        for (name, value) in settings {
+            if let Some(value) = value
+                && !(value.is_finite() && value > 0.0)
+            {
+                return Err(anyhow!("{name}: must be positive and finite, got {value}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the exposure value at ISO 100 for these settings.
+    /// See https://en.wikipedia.org/wiki/Exposure_value.
+    pub fn get_ev100(&self) -> f32 {
+        (self.f_stop * self.f_stop / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    /// Returns the factor to scale scene radiance by. The luminance that saturates the sensor is
+    /// 1.2 * 2^EV100 (https://en.wikipedia.org/wiki/Film_speed#Saturation-based_speed), so
+    /// relative to `REFERENCE_EV100` the factor halves with each stop the exposure value increases.
+    pub fn get_multiplier(&self) -> f32 {
+        (REFERENCE_EV100 - self.get_ev100()).exp2()
+    }
+
+    /// Returns the aperture diameter for a lens with the given vertical field of view in radians.
+    /// This is the focal length of the lens divided by the f-number.
+    pub fn get_aperture_size(&self, fov_y: f32) -> f32 {
+        let sensor_height = self.sensor_height.unwrap_or(DEFAULT_SENSOR_HEIGHT);
+        let lens_focal_length = 0.5 * sensor_height / (0.5 * fov_y).tan();
+        lens_focal_length / self.f_stop
+    }
+}
+
+/// Orientation of a camera. This can be used in place of the `up` vector to roll the camera or to
+/// specify the orientation directly.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    /// Quaternion (x, y, z, w) that rotates the camera from looking down -Z with +Y up. The
+    /// distance from eye to look_at is kept as the distance to the target.
+    Quaternion([f32; 4]),
+
+    /// Roll in degrees about the direction from eye to look_at.
+    Roll(f32),
+}
+
 impl Camera {
     pub fn get_name(&self) -> &str {
         match self {
             Self::Perspective { name, .. } => name,
         }
     }
+
+    /// Returns an error naming the first invalid field, relative to the camera.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Perspective {
+                orientation,
+                exposure,
+                ..
+            } => {
+                if let Some(Orientation::Quaternion(q)) = orientation {
+                    let length_squared: f32 = q.iter().map(|v| v * v).sum();
+                    if !(length_squared.is_finite() && length_squared > 0.0) {
+                        return Err(anyhow!(
+                            "perspective.orientation.quaternion: must be finite with a non-zero \
+                            length, got {q:?}"
+                        ));
+                    }
+                }
+                if let Some(exposure) = exposure {
+                    exposure
+                        .validate()
+                        .map_err(|e| anyhow!("perspective.exposure.{e}"))?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
@@ -0,0 +1,132 @@
+use glam::Vec3;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::Vertex;
+
+/// Orientation of a mesh's vertex normals and triangle winding. Front faces are counter-clockwise
+/// when viewed from the side their normals point to. Normals are adjusted first and the winding
+/// is then adjusted to the new normals.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Facing {
+    /// How vertex normals are oriented.
+    pub normals: NormalOrientation,
+
+    /// How triangles are wound.
+    pub winding: Winding,
+}
+
+/// Orientation of vertex normals.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalOrientation {
+    /// Normals are used as given.
+    #[default]
+    Keep,
+
+    /// Normals pointing towards the centroid of the vertices are flipped. This suits closed
+    /// meshes viewed from outside.
+    Outward,
+
+    /// Normals pointing away from the centroid of the vertices are flipped. This suits meshes
+    /// viewed from inside, such as a room.
+    Inward,
+
+    /// Normals are flipped where they point against the triangles that use them.
+    FollowWinding,
+}
+
+/// Order of the vertices of each triangle.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Winding {
+    /// Triangles are used as given.
+    #[default]
+    Keep,
+
+    /// Triangles are reversed where they are clockwise when viewed from the side their vertex
+    /// normals point to.
+    FollowNormals,
+
+    /// Every triangle is reversed.
+    Reverse,
+}
+
+impl Facing {
+    /// Adjusts the normals and winding of a mesh in place.
+    pub fn apply(&self, vertices: &mut [Vertex], indices: &mut [u32]) {
+        match self.normals {
+            NormalOrientation::Keep => (),
+
+            NormalOrientation::Outward | NormalOrientation::Inward => {
+                let centroid = vertices.iter().map(|v| Vec3::from(v.p)).sum::<Vec3>()
+                    / vertices.len().max(1) as f32;
+                let sign = if self.normals == NormalOrientation::Outward {
+                    1.0
+                } else {
+                    -1.0
+                };
+
+                for vertex in vertices.iter_mut() {
+                    let n = Vec3::from(vertex.n);
+                    if sign * n.dot(Vec3::from(vertex.p) - centroid) < 0.0 {
+                        vertex.n = (-n).into();
+                    }
+                }
+            }
+
+            NormalOrientation::FollowWinding => {
+                let mut face_normals = vec![Vec3::ZERO; vertices.len()];
+                for triangle in indices.chunks_exact(3) {
+                    let n = get_geometric_normal(vertices, triangle);
+                    for &i in triangle {
+                        face_normals[i as usize] += n;
+                    }
+                }
+
+                for (vertex, face_normal) in vertices.iter_mut().zip(face_normals) {
+                    let n = Vec3::from(vertex.n);
+                    if n.dot(face_normal) < 0.0 {
+                        vertex.n = (-n).into();
+                    }
+                }
+            }
+        }
+
+        match self.winding {
+            Winding::Keep => (),
+
+            Winding::FollowNormals => {
+                for triangle in indices.chunks_exact_mut(3) {
+                    if !is_counter_clockwise(vertices, triangle) {
+                        triangle.swap(1, 2);
+                    }
+                }
+            }
+
+            Winding::Reverse => {
+                for triangle in indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the normal of a triangle given by 3 vertex indices assuming counter-clockwise winding.
+/// This is not normalized and its length is twice the triangle's area.
+pub fn get_geometric_normal(vertices: &[Vertex], triangle: &[u32]) -> Vec3 {
+    let [p0, p1, p2] = [0, 1, 2].map(|i| Vec3::from(vertices[triangle[i] as usize].p));
+    (p1 - p0).cross(p2 - p0)
+}
+
+/// Returns true if a triangle given by 3 vertex indices is counter-clockwise when viewed from the
+/// side its vertex normals point to. Triangles with no area are treated as counter-clockwise.
+pub fn is_counter_clockwise(vertices: &[Vertex], triangle: &[u32]) -> bool {
+    let vertex_normal: Vec3 = triangle
+        .iter()
+        .map(|&i| Vec3::from(vertices[i as usize].n))
+        .sum();
+    get_geometric_normal(vertices, triangle).dot(vertex_normal) >= 0.0
+}
@@ -1,10 +1,15 @@
 use glam::{Mat4, Vec3};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Places a primitive in the scene.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Instance {
+    /// Name of the primitive.
     pub name: String,
+
+    /// Object to world space transform. Defaults to the identity.
     pub transform: Option<TransformType>,
 }
 
@@ -17,25 +22,39 @@ impl Instance {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Object to world space transform of an instance.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TransformType {
+    /// Transform that does not change over time.
     Static(Transform),
+
+    /// Transforms at the start and end of the shutter interval used for motion blur.
     Animated(Transform, Transform),
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Rotation about an axis.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Rotate {
+    /// Axis of rotation. This must be normalized.
     pub axis: [f32; 3],
+
+    /// Angle of rotation in degrees.
     pub degrees: f32,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Transform applied as scale, then rotate, then translate.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Transform {
+    /// Translation.
     pub translate: Option<[f32; 3]>,
+
+    /// Rotation.
     pub rotate: Option<Rotate>,
+
+    /// Non-uniform scale.
     pub scale: Option<[f32; 3]>,
 }
 
@@ -59,6 +78,36 @@ pub enum Matrix {
     Static(Mat4),
     Animated(Mat4, Mat4),
 }
+
+impl Matrix {
+    /// Returns the matrix with an object space translation applied before it.
+    pub fn with_offset(&self, offset: Vec3) -> Self {
+        let t = Mat4::from_translation(offset);
+        match self {
+            Self::Static(m) => Self::Static(m.mul_mat4(&t)),
+            Self::Animated(m1, m2) => Self::Animated(m1.mul_mat4(&t), m2.mul_mat4(&t)),
+        }
+    }
+
+    /// Returns the matrix with an object space transform applied before it.
+    pub fn with_object_transform(&self, transform: Mat4) -> Self {
+        match self {
+            Self::Static(m) => Self::Static(m.mul_mat4(&transform)),
+            Self::Animated(m1, m2) => {
+                Self::Animated(m1.mul_mat4(&transform), m2.mul_mat4(&transform))
+            }
+        }
+    }
+
+    /// Returns the matrix animated to move by a world space distance from time 0 to 1.
+    pub fn with_velocity(&self, velocity: Vec3) -> Self {
+        let t = Mat4::from_translation(velocity);
+        match self {
+            Self::Static(m) => Self::Animated(*m, t.mul_mat4(m)),
+            Self::Animated(m1, m2) => Self::Animated(*m1, t.mul_mat4(m2)),
+        }
+    }
+}
 impl From<&TransformType> for Matrix {
     fn from(value: &TransformType) -> Self {
         match value {
@@ -1,49 +1,113 @@
+mod acceleration;
+mod array;
+mod asset;
+mod autosave;
+mod builder;
 mod camera;
+mod facing;
+mod hash;
 mod instance;
 mod material;
+mod mesh;
+mod migration;
+mod obj;
+mod package;
+mod parse_error;
 mod primitive;
 mod render;
 mod sky;
 mod texture;
+mod unused;
 
+pub use acceleration::*;
+pub use array::*;
+pub use asset::*;
+pub use autosave::*;
+pub use builder::*;
 pub use camera::*;
+pub use facing::*;
+pub use hash::*;
 pub use instance::*;
 pub use material::*;
+pub use mesh::*;
+pub use migration::SCENE_FILE_VERSION;
+pub use obj::*;
+pub use package::*;
 pub use primitive::*;
 pub use render::*;
 pub use sky::*;
 pub use texture::*;
+pub use unused::UnusedReport;
 
 use std::{
-    collections::{HashMap, hash_map::Entry},
+    collections::{HashMap, HashSet, hash_map::Entry},
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use log::{info, warn};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Scene description loaded from JSON.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct SceneFile {
+    /// Version of the scene file format. Older files are migrated when loaded.
+    pub version: u32,
+
+    /// Cameras available for rendering.
     pub cameras: Vec<Camera>,
+
+    /// Textures used by materials.
     pub textures: Vec<Texture>,
+
+    /// Materials used by primitives.
     pub materials: Vec<Material>,
+
+    /// Geometry that can be instanced.
     pub primitives: Vec<Primitive>,
+
+    /// Primitives placed in the scene.
     pub instances: Vec<Instance>,
+
+    /// Background colour.
     pub sky: Sky,
+
+    /// Render settings.
     pub render: Render,
+
+    /// Normal orientation and winding applied to primitives that don't set their own.
+    #[serde(default)]
+    pub facing: Facing,
 }
 
 impl SceneFile {
     pub fn load_json(path: &str) -> Result<Self> {
-        let serialized = std::fs::read_to_string(path)?;
-        let mut deserialized: Self = serde_json::from_str(&serialized)
-            .with_context(|| format!("Unable to parse scene file '{path}'"))?;
+        Self::load_json_with_resolver(path, &AssetResolver::default())
+    }
+
+    /// Loads a scene file and finds the assets it references with the given resolver. Scene
+    /// packages are extracted into the resolver's cache directory and loaded from there.
+    pub fn load_json_with_resolver(path: &str, resolver: &AssetResolver) -> Result<Self> {
+        if is_package(path) {
+            // Packages with the same file name in different directories are kept apart.
+            let canonical_path = std::fs::canonicalize(path)
+                .with_context(|| format!("Unable to find scene package '{path}'"))?;
+            let hash = fnv1a_hash(canonical_path.as_os_str().as_encoded_bytes());
+            let dir = resolver
+                .cache_dir
+                .join("packages")
+                .join(format!("{hash:016x}"));
+            let scene_path = extract_package(path, &dir)?;
+            return Self::load_json_with_resolver(&scene_path, resolver);
+        }
+
+        let mut deserialized = Self::parse_json(path)?;
 
         let path_buf = PathBuf::from(path);
-        let relative_to = path_buf.parent().unwrap();
-        deserialized.adjust_relative_paths(relative_to);
+        let scene_dir = path_buf.parent().unwrap();
+        deserialized.resolve_paths(resolver, scene_dir);
         deserialized.enforce_render_limits();
 
         Ok(deserialized)
@@ -55,26 +119,224 @@ impl SceneFile {
         Ok(())
     }
 
-    fn adjust_relative_paths(&mut self, relative_to: &Path) {
+    /// Replaces render settings with the overrides that are set. The same limits apply as for
+    /// settings loaded from the file.
+    pub fn apply_render_overrides(&mut self, overrides: &RenderOverrides) {
+        if let Some(samples_per_pixel) = overrides.samples_per_pixel {
+            self.render.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(sample_batches) = overrides.sample_batches {
+            self.render.sample_batches = sample_batches;
+        }
+        if let Some(max_ray_depth) = overrides.max_ray_depth {
+            self.render.max_ray_depth = max_ray_depth;
+        }
+        if let Some(strict_textures) = overrides.strict_textures {
+            self.render.strict_textures = strict_textures;
+        }
+        if let Some(quality) = overrides.quality {
+            self.render.quality = quality;
+        }
+        self.enforce_render_limits();
+    }
+
+    /// Returns the JSON Schema for scene files. This can be used by editors for completion and
+    /// validation.
+    pub fn get_json_schema() -> Result<String> {
+        let schema = schemars::schema_for!(SceneFile);
+        Ok(serde_json::to_string_pretty(&schema)?)
+    }
+
+    /// Adds a camera to the scene file at the given path, replacing any existing camera with the
+    /// same name. Only the cameras array of the JSON is changed, so everything else, including
+    /// relative paths, render settings and fields left at their defaults, is written back as is.
+    /// Fails for scene packages, which can't be changed.
+    pub fn save_camera(path: &str, camera: &Camera) -> Result<()> {
+        if is_package(path) {
+            return Err(anyhow!("Unable to save a camera to scene package '{path}'"));
+        }
+
+        let serialized = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read scene file '{path}'"))?;
+        let mut value: serde_json::Value = serde_json::from_str(&serialized)
+            .with_context(|| format!("Unable to parse scene file '{path}'"))?;
+
+        // The camera is written in the current format, so the rest of the file must be too.
+        migration::migrate(&mut value)
+            .with_context(|| format!("Unable to migrate scene file '{path}'"))?;
+
+        // Make sure the file is a scene file before changing it.
+        serde_json::from_value::<Self>(value.clone())
+            .with_context(|| format!("Unable to parse scene file '{path}'"))?;
+
+        let cameras = value
+            .get_mut("cameras")
+            .and_then(|cameras| cameras.as_array_mut())
+            .with_context(|| format!("Scene file '{path}' has no cameras array"))?;
+
+        let camera_value = serde_json::to_value(camera)?;
+        match cameras
+            .iter_mut()
+            .find(|c| get_camera_name(c) == Some(camera.get_name()))
+        {
+            Some(existing) => *existing = camera_value,
+            None => cameras.push(camera_value),
+        }
+
+        std::fs::write(path, serde_json::to_string_pretty(&value)?)
+            .with_context(|| format!("Unable to write scene file '{path}'"))
+    }
+
+    /// Replaces the camera with the same name or adds it if there isn't one.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        match self
+            .cameras
+            .iter_mut()
+            .find(|c| c.get_name() == camera.get_name())
+        {
+            Some(existing) => *existing = camera.clone(),
+            None => self.cameras.push(camera.clone()),
+        }
+    }
+
+    /// Returns the camera named by the render settings. If there is no such camera, e.g. because
+    /// of a typo, the first camera is returned with a warning so the scene still renders. Fails
+    /// if the scene has no cameras.
+    pub fn get_render_camera(&self) -> Result<&Camera> {
+        let name = &self.render.camera;
+        if let Some(camera) = self.cameras.iter().find(|c| c.get_name() == name) {
+            return Ok(camera);
+        }
+
+        let camera = self
+            .cameras
+            .first()
+            .ok_or_else(|| anyhow!("Camera '{name}' is not specified in cameras"))?;
+        warn!(
+            "Camera '{name}' is not specified in cameras. Using '{}' instead.",
+            camera.get_name()
+        );
+        Ok(camera)
+    }
+
+    /// Removes the materials no primitive uses and then the textures no remaining material uses,
+    /// directly or through a checker texture. Returns the names of what was removed. Fails if
+    /// the material of an OBJ mesh can't be read.
+    pub fn strip_unused(&mut self) -> Result<UnusedReport> {
+        let used_materials = self
+            .primitives
+            .iter()
+            .map(Primitive::get_material)
+            .collect::<Result<HashSet<_>>>()?;
+
+        let mut report = UnusedReport::default();
+        self.materials.retain(|material| {
+            let is_used = used_materials.contains(material.get_name());
+            if !is_used {
+                report.materials.push(material.get_name().to_string());
+            }
+            is_used
+        });
+
+        // Checker textures are never nested, so textures used by the materials and the textures
+        // those are made of are all the textures used.
+        let textures = self.get_textures();
+        let used_textures: HashSet<&str> = self
+            .materials
+            .iter()
+            .flat_map(Material::get_textures)
+            .flat_map(|name| {
+                let parts = textures.get(name).map(Texture::get_textures);
+                std::iter::once(name).chain(parts.into_iter().flatten())
+            })
+            .collect();
+
+        self.textures.retain(|texture| {
+            let is_used = used_textures.contains(texture.get_name());
+            if !is_used {
+                report.textures.push(texture.get_name().to_string());
+            }
+            is_used
+        });
+
+        Ok(report)
+    }
+
+    /// Parses a scene file from a string without touching the disk, e.g. for applications that
+    /// generate scenes. Relative texture paths are relative to the working directory. Textures can
+    /// also be held in memory by adding `Texture::ImageBytes` to `textures`.
+    pub fn from_json_str(serialized: &str) -> Result<Self> {
+        let mut deserialized =
+            Self::parse_json_str(serialized).context("Unable to parse scene file")?;
+        deserialized.enforce_render_limits();
+        Ok(deserialized)
+    }
+
+    /// Reads the scene file at the given path and migrates it to the current version.
+    fn parse_json(path: &str) -> Result<Self> {
+        let serialized = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read scene file '{path}'"))?;
+        Self::parse_json_str(&serialized)
+            .with_context(|| format!("Unable to parse scene file '{path}'"))
+    }
+
+    /// Parses a scene file and migrates it to the current version.
+    fn parse_json_str(serialized: &str) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(serialized)?;
+
+        let is_current_version =
+            value.get("version").and_then(|v| v.as_u64()) == Some(SCENE_FILE_VERSION as u64);
+
+        migration::migrate(&mut value).context("Unable to migrate scene file")?;
+
+        // Files that didn't need migrating are deserialized from the original text so errors can
+        // report line and column numbers.
+        let result = if is_current_version {
+            serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(serialized))
+        } else {
+            serde_path_to_error::deserialize(value)
+        };
+
+        let scene_file: Self = result.map_err(|e| anyhow!(parse_error::describe(&e)))?;
+        for (i, camera) in scene_file.cameras.iter().enumerate() {
+            camera.validate().map_err(|e| anyhow!("cameras[{i}].{e}"))?;
+        }
+        Ok(scene_file)
+    }
+
+    fn resolve_paths(&mut self, resolver: &AssetResolver, scene_dir: &Path) {
         for texture in self.textures.iter_mut() {
-            texture.adjust_relative_path(relative_to);
+            texture.resolve_path(resolver, scene_dir);
+        }
+        for primitive in self.primitives.iter_mut() {
+            primitive.resolve_path(resolver, scene_dir);
         }
     }
 
     fn enforce_render_limits(&mut self) {
-        if self.render.samples_per_pixel > 64 {
+        let max_samples_per_pixel = self.render.quality.get_max_samples_per_pixel();
+        if self.render.samples_per_pixel > max_samples_per_pixel {
             info!(
-                "Samples per pixel {} too high. Limiting to 64.",
+                "Samples per pixel {} too high. Limiting to {max_samples_per_pixel}.",
                 self.render.samples_per_pixel
             );
-            self.render.samples_per_pixel = 64;
+            self.render.samples_per_pixel = max_samples_per_pixel;
         }
-        if self.render.sample_batches > 32 {
+        let max_sample_batches = self.render.quality.get_max_sample_batches();
+        if self.render.sample_batches > max_sample_batches {
             info!(
-                "Sample batches {} too high. Limiting to 32.",
+                "Sample batches {} too high. Limiting to {max_sample_batches}.",
                 self.render.sample_batches
             );
-            self.render.sample_batches = 32;
+            self.render.sample_batches = max_sample_batches;
+        }
+        if self.render.samples_per_pixel == 0 {
+            info!("Samples per pixel must be at least 1. Using 1.");
+            self.render.samples_per_pixel = 1;
+        }
+        if self.render.sample_batches == 0 {
+            info!("Sample batches must be at least 1. Using 1.");
+            self.render.sample_batches = 1;
         }
     }
 
@@ -94,3 +356,8 @@ impl SceneFile {
         textures
     }
 }
+
+/// Returns the name of a camera in its JSON form, where the camera type is the only key.
+fn get_camera_name(camera: &serde_json::Value) -> Option<&str> {
+    camera.as_object()?.values().next()?.get("name")?.as_str()
+}
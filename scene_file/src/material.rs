@@ -1,24 +1,141 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Surface material. Colours are given as names of textures.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Material {
+    /// Diffuse material.
     Lambertian {
+        /// Unique name of the material.
         name: String,
+
+        /// Texture for the diffuse colour.
         albedo: String,
+
+        /// Texture with the tangent space normals of the surface, usually an image, perturbing the
+        /// shading normal. Image textures should have a `linear` colour space.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        normal_map: Option<String>,
+
+        /// Glossy clear layer over the material.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clearcoat: Option<Clearcoat>,
     },
+
+    /// Reflective material.
     Metal {
+        /// Unique name of the material.
         name: String,
+
+        /// Texture for the reflected colour.
         albedo: String,
+
+        /// Texture for the roughness of the reflection. This is best given by a scalar texture or an
+        /// image texture with a channel selected. Only the red channel of a colour is used.
         fuzz: String,
+
+        /// Texture for the roughness in the direction the texture's v coordinate increases, for
+        /// anisotropic metals such as brushed metal. `fuzz` is then the roughness in the direction
+        /// the u coordinate increases. The roughness is the same in every direction if this isn't
+        /// given.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fuzz_v: Option<String>,
+
+        /// Rotation of the directions of anisotropic roughness around the surface normal in
+        /// degrees.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fuzz_rotation: Option<f32>,
+
+        /// Texture with the tangent space normals of the surface, usually an image, perturbing the
+        /// shading normal. Image textures should have a `linear` colour space.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        normal_map: Option<String>,
+
+        /// Glossy clear layer over the material.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clearcoat: Option<Clearcoat>,
+    },
+
+    /// Physically based material with the metallic-roughness parameters of glTF. Light either
+    /// reflects off a rough surface of GGX microfacets, tinted by the base colour where it is
+    /// metallic, or scatters diffusely with the base colour.
+    Pbr {
+        /// Unique name of the material.
+        name: String,
+
+        /// Texture for the base colour, which is the diffuse colour of the non-metallic parts and
+        /// the reflectance of the metallic parts.
+        base_color: String,
+
+        /// Texture for how metallic the surface is from 0 to 1. This is best given by a scalar
+        /// texture or an image texture with a channel selected. Only the red channel of a colour
+        /// is used.
+        metallic: String,
+
+        /// Texture for the roughness of the reflection from 0 (mirror-like) to 1. This is
+        /// perceptual roughness, which is squared to get the width of the GGX distribution. Only
+        /// the red channel of a colour is used.
+        roughness: String,
+
+        /// Refractive index of the non-metallic parts, which sets how much light they reflect
+        /// instead of scattering diffusely. This is 1.5 if not given.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ior: Option<f32>,
+
+        /// Strength of anisotropic roughness from 0 to 1, for surfaces such as brushed metal. The
+        /// roughness is stretched towards 1 in the anisotropy direction as in
+        /// `KHR_materials_anisotropy`. The roughness is the same in every direction if this isn't
+        /// given.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        anisotropy: Option<f32>,
+
+        /// Rotation of the anisotropy direction from the direction the texture's u coordinate
+        /// increases around the surface normal in degrees.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        anisotropy_rotation: Option<f32>,
+
+        /// Texture with the tangent space normals of the surface, usually an image, perturbing the
+        /// shading normal. Image textures should have a `linear` colour space.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        normal_map: Option<String>,
     },
+
+    /// Transparent material such as glass or water.
     Dielectric {
+        /// Unique name of the material.
         name: String,
+
+        /// Refractive index relative to the surrounding medium, or to a vacuum if the material has
+        /// a priority.
         refraction_index: f32,
+
+        /// Priority when dielectrics are nested, e.g. liquid in a glass. Where volumes with a
+        /// priority overlap, the one with the highest priority is the medium the ray travels
+        /// through and surfaces of the others are ignored, so the volumes only need to overlap
+        /// instead of sharing surfaces. Dielectrics without a priority, or with 0, aren't nested.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        priority: Option<u32>,
+
+        /// Glossy clear layer over the material.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clearcoat: Option<Clearcoat>,
     },
+
+    /// Light emitting material.
     DiffuseLight {
+        /// Unique name of the material.
         name: String,
+
+        /// Texture for the emitted radiance.
         emit: String,
+
+        /// Colour temperature of the emitted light in Kelvin, e.g. 2700 for a warm incandescent
+        /// bulb or 6500 for daylight. The radiance given by `emit` is tinted by the colour of a
+        /// blackbody at this temperature, which has a luminance of 1 so the brightness is
+        /// unchanged. Temperatures are clamped to the range 1667 to 25000.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        temperature_kelvin: Option<f32>,
     },
 }
 
@@ -27,8 +144,85 @@ impl Material {
         match self {
             Self::Lambertian { name, .. } => name.as_ref(),
             Self::Metal { name, .. } => name.as_ref(),
+            Self::Pbr { name, .. } => name.as_ref(),
             Self::Dielectric { name, .. } => name.as_ref(),
             Self::DiffuseLight { name, .. } => name.as_ref(),
         }
     }
+
+    /// Returns the name of the texture for the material's albedo, if it has one. This is the base
+    /// colour of PBR materials.
+    pub fn get_albedo(&self) -> Option<&str> {
+        match self {
+            Self::Lambertian { albedo, .. } | Self::Metal { albedo, .. } => Some(albedo),
+            Self::Pbr { base_color, .. } => Some(base_color),
+            _ => None,
+        }
+    }
+
+    /// Returns the names of the textures the material uses.
+    pub fn get_textures(&self) -> Vec<&str> {
+        match self {
+            Self::Lambertian {
+                albedo, normal_map, ..
+            } => [Some(albedo), normal_map.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect(),
+            Self::Metal {
+                albedo,
+                fuzz,
+                fuzz_v,
+                normal_map,
+                ..
+            } => [
+                Some(albedo),
+                Some(fuzz),
+                fuzz_v.as_ref(),
+                normal_map.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect(),
+            Self::Pbr {
+                base_color,
+                metallic,
+                roughness,
+                normal_map,
+                ..
+            } => [
+                Some(base_color),
+                Some(metallic),
+                Some(roughness),
+                normal_map.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect(),
+            Self::Dielectric { .. } => vec![],
+            Self::DiffuseLight { emit, .. } => vec![emit],
+        }
+    }
+}
+
+/// Glossy clear layer over a material, such as the lacquer of car paint or varnish on wood. Light
+/// is either reflected off the layer or passes through it to the material below, tinted by the
+/// layer's colour.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Clearcoat {
+    /// Strength of the layer from 0 (no layer) to 1. This scales the layer's reflectance and tint.
+    pub intensity: f32,
+
+    /// Roughness of the layer's reflection from 0 (mirror-like) to 1.
+    #[serde(default)]
+    pub roughness: f32,
+
+    /// Linear colour of the layer that light passing through it is tinted by. The layer is clear
+    /// if this isn't given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tint: Option<[f32; 3]>,
 }
@@ -0,0 +1,557 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use anyhow::{Result, anyhow};
+use glam::Vec3;
+use log::debug;
+use shaders::ray_gen;
+
+use crate::{Facing, Primitive, Quality, SpherePoles, SphereSeam, is_counter_clockwise, load_obj};
+
+/// Distance below which vertices are treated as the same point when checking that a mesh is
+/// closed, relative to the size of the mesh.
+const WELD_TOLERANCE: f32 = 1e-5;
+
+// This is used for cleaner code and it represents the data that the shader's MeshVertex structure needs.
+#[derive(Clone, Debug)]
+pub struct Vertex {
+    pub p: [f32; 3],
+    pub n: [f32; 3],
+    pub uv: [f32; 2],
+
+    /// Tangent in the direction the u texture coordinate increases, orthogonal to the normal. `w`
+    /// is the sign of the bitangent `w * cross(n, t)`, which points in the direction v increases.
+    /// This is set by `generate_tangents`.
+    pub t: [f32; 4],
+}
+
+impl Vertex {
+    pub fn new(p: [f32; 3], n: [f32; 3], uv: [f32; 2]) -> Self {
+        Self {
+            p,
+            n,
+            uv,
+            t: [0.0; 4],
+        }
+    }
+}
+
+impl From<&Vertex> for ray_gen::MeshVertex {
+    // Convert Vertex to shader struct.
+    fn from(value: &Vertex) -> Self {
+        Self {
+            p: value.p,
+            n: value.n,
+            u: value.uv[0],
+            v: value.uv[1],
+            t: value.t,
+        }
+    }
+}
+
+impl Primitive {
+    /// Returns the vertices and triangle indices of the primitive in object space at the given
+    /// quality with the primitive's normal orientation and winding applied, or `default_facing`
+    /// if it doesn't set one. Tangents are generated for the oriented normals.
+    pub fn generate_oriented_mesh(
+        &self,
+        default_facing: &Facing,
+        quality: Quality,
+    ) -> Result<(Vec<Vertex>, Vec<u32>)> {
+        let (mut vertices, mut indices) = self.generate_mesh(quality)?;
+        self.get_facing()
+            .unwrap_or(*default_facing)
+            .apply(&mut vertices, &mut indices);
+        generate_tangents(&mut vertices, &indices);
+        Ok((vertices, indices))
+    }
+
+    /// Returns the vertices and triangle indices of the primitive in object space as generated at
+    /// the given quality.
+    /// Triangles are counter-clockwise when viewed from the side their normals point to, except
+    /// for triangles and quads which use the points and normal as given and OBJ meshes which use
+    /// the winding of the OBJ file. Instances reuse the mesh of the primitive they reference, see
+    /// `Primitive::get_geometry`.
+    pub fn generate_mesh(&self, quality: Quality) -> Result<(Vec<Vertex>, Vec<u32>)> {
+        let mesh = match self {
+            Self::UvSphere {
+                center,
+                radius,
+                rings,
+                segments,
+                poles,
+                seam,
+                ..
+            } => {
+                let (rings, segments) = quality.get_sphere_tessellation(*rings, *segments);
+                generate_uv_sphere(center, *radius, rings, segments, *poles, *seam)
+            }
+
+            Self::Triangle {
+                points, normal, uv, ..
+            } => {
+                let vertices: Vec<_> = points
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| Vertex::new(*p, *normal, uv[i]))
+                    .collect();
+                (vertices, vec![0, 1, 2])
+            }
+
+            Self::Quad {
+                points, normal, uv, ..
+            } => {
+                let vertices: Vec<_> = points
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| Vertex::new(*p, *normal, uv[i]))
+                    .collect();
+                (vertices, vec![0, 1, 2, 0, 2, 3])
+            }
+
+            Self::Box { corners, .. } => generate_box(corners),
+
+            Self::ObjMesh { path, .. } => generate_obj_mesh(path)?,
+
+            Self::Instance { name, mesh, .. } => {
+                return Err(anyhow!(
+                    "Instance '{name}' has no mesh of its own. Generate the mesh of '{mesh}'."
+                ));
+            }
+        };
+        Ok(mesh)
+    }
+}
+
+/// Loads an OBJ file and merges its models into one mesh.
+fn generate_obj_mesh(path: &str) -> Result<(Vec<Vertex>, Vec<u32>)> {
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for (model_vertices, model_indices) in load_obj(path)? {
+        let offset = vertices.len() as u32;
+        vertices.extend(model_vertices);
+        indices.extend(model_indices.iter().map(|i| i + offset));
+    }
+
+    if vertices.is_empty() {
+        return Err(anyhow!("OBJ file '{path}' has no triangles"));
+    }
+
+    Ok((vertices, indices))
+}
+
+fn uv_sphere_vertex(
+    center: &Vec3,
+    radius: f32,
+    ring: u32,
+    segment: u32,
+    du: f32,
+    dv: f32,
+    top_or_bottom: bool,
+) -> Vertex {
+    let shift_u = if top_or_bottom { du / 2.0 } else { 0.0 };
+    let u = segment as f32 * du + shift_u;
+    let v = ring as f32 * dv;
+
+    let theta = 2.0 * PI * u;
+    let phi = PI * v;
+
+    let n = Vec3::new(
+        -phi.sin() * theta.cos(),
+        -phi.cos(),
+        phi.sin() * theta.sin(),
+    );
+    let p = center + radius * n;
+
+    Vertex::new(p.into(), n.into(), [u, v])
+}
+
+/// Generates a UV sphere. At least 2 rings and 3 segments are used.
+fn generate_uv_sphere(
+    center: &[f32; 3],
+    radius: f32,
+    rings: u32,
+    segments: u32,
+    poles: SpherePoles,
+    seam: SphereSeam,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let rings = rings.max(2);
+    let segments = segments.max(3);
+
+    let c = Vec3::from_slice(center);
+    let du = 1.0 / segments as f32;
+    let dv = 1.0 / rings as f32;
+
+    // Number of vertices in each ring that isn't a fan pole.
+    let columns = match seam {
+        SphereSeam::Duplicated => segments + 1,
+        SphereSeam::Shared => segments,
+    };
+    let get_column = |s: u32| s % columns;
+
+    let is_fan = poles == SpherePoles::Fan;
+
+    let mut vertices = vec![];
+    let mut ring_offsets = vec![];
+
+    for r in 0..=rings {
+        ring_offsets.push(vertices.len() as u32);
+
+        let is_pole = r == 0 || r == rings;
+        if is_pole && is_fan {
+            // One vertex per triangle of the fan.
+            for s in 0..segments {
+                vertices.push(uv_sphere_vertex(&c, radius, r, s, du, dv, true));
+            }
+        } else {
+            for s in 0..columns {
+                vertices.push(uv_sphere_vertex(&c, radius, r, s, du, dv, false));
+            }
+        }
+    }
+
+    let mut indices = vec![];
+
+    for r in 0..rings {
+        let o1 = ring_offsets[r as usize];
+        let o2 = ring_offsets[r as usize + 1];
+        debug!("r={r}, o1: {o1}, o2: {o2}");
+
+        for s in 0..segments {
+            let (a, b) = (get_column(s), get_column(s + 1));
+
+            if r == 0 && is_fan {
+                // Top triangles.
+                indices.extend([o1 + s, o2 + b, o2 + a]);
+            } else if r == rings - 1 && is_fan {
+                // Bottom triangles.
+                indices.extend([o1 + a, o1 + b, o2 + s]);
+            } else {
+                // Ring quads (2 triangles). The first triangle has no area on the bottom ring and
+                // the second has no area on the top ring when the pole vertices are collapsed.
+                if r != rings - 1 {
+                    indices.extend([o1 + a, o2 + b, o2 + a]);
+                }
+                if r != 0 {
+                    indices.extend([o1 + a, o1 + b, o2 + b]);
+                }
+            }
+        }
+    }
+
+    debug!(
+        "Vertex count: {}, Indices count: {}",
+        vertices.len(),
+        indices.len()
+    );
+
+    /*
+    debug!("-------------------------------------------------------------------------------");
+    debug!("     Position                     Normal                       UV");
+    debug!("-------------------------------------------------------------------------------");
+    for (i, v) in vertices.iter().enumerate() {
+        debug!(
+            "{i: >3}  [{: >7.4}, {: >7.4}, {: >7.4}]  [{: >7.4}, {: >7.4}, {: >7.4}]  [{:.4}, {:.4}]",
+            v.p[0], v.p[1], v.p[2], v.n[0], v.n[1], v.n[2], v.uv[0], v.uv[1],
+        );
+    }
+    debug!("-------------------------------------------------------------------------------");
+    debug!("Indices {indices:?}");
+    debug!("-------------------------------------------------------------------------------");
+    */
+
+    (vertices, indices)
+}
+
+/// Sets the tangent of each vertex from the texture coordinates of the triangles using it, so
+/// normal maps line up with the texture. The tangents of the triangles sharing a vertex are
+/// weighted by their area in texture space and the sum is made orthogonal to the vertex normal.
+/// Vertices without a texture parameterisation get an arbitrary tangent.
+fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [v0, v1, v2] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let edge1 = Vec3::from(v1.p) - Vec3::from(v0.p);
+        let edge2 = Vec3::from(v2.p) - Vec3::from(v0.p);
+        let (du1, dv1) = (v1.uv[0] - v0.uv[0], v1.uv[1] - v0.uv[1]);
+        let (du2, dv2) = (v2.uv[0] - v0.uv[0], v2.uv[1] - v0.uv[1]);
+
+        // Scaling by the sign of the determinant instead of dividing by it weights the tangents
+        // by twice the triangle's area in texture space.
+        let det = du1 * dv2 - du2 * dv1;
+        if det == 0.0 {
+            continue;
+        }
+        let tangent = (edge1 * dv2 - edge2 * dv1) * det.signum();
+        let bitangent = (edge2 * du1 - edge1 * du2) * det.signum();
+
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+            bitangents[i as usize] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let n = Vec3::from(vertex.n).try_normalize().unwrap_or(Vec3::Z);
+        let t = (tangent - n * n.dot(tangent))
+            .try_normalize()
+            .unwrap_or_else(|| n.any_orthonormal_vector());
+        let w = if n.cross(t).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.t = [t.x, t.y, t.z, w];
+    }
+}
+
+/// Checks that a triangle mesh is well formed. Every index must refer to a vertex, positions and
+/// texture coordinates must be finite, normals must be finite and non-zero and triangles must be
+/// counter-clockwise when viewed from the side their normals point to.
+///
+/// If `is_closed` is true, every edge must also be shared by exactly two triangles wound in
+/// opposite directions. Vertices at the same position are treated as one so seams with duplicated
+/// vertices still count as closed, and triangles with no area are ignored.
+pub fn validate_mesh(vertices: &[Vertex], indices: &[u32], is_closed: bool) -> Result<()> {
+    if !indices.len().is_multiple_of(3) {
+        return Err(anyhow!(
+            "Index count {} is not a multiple of 3",
+            indices.len()
+        ));
+    }
+
+    for (i, vertex) in vertices.iter().enumerate() {
+        if !vertex.p.iter().chain(&vertex.uv).all(|x| x.is_finite()) {
+            return Err(anyhow!(
+                "Vertex {i} has a non-finite position or texture coordinate"
+            ));
+        }
+
+        let n = Vec3::from(vertex.n);
+        if !n.is_finite() || n.length_squared() == 0.0 {
+            return Err(anyhow!("Vertex {i} has an invalid normal {n}"));
+        }
+    }
+
+    if let Some((i, index)) = indices
+        .iter()
+        .enumerate()
+        .find(|(_, index)| **index as usize >= vertices.len())
+    {
+        return Err(anyhow!(
+            "Index {i} refers to vertex {index} but there are only {} vertices",
+            vertices.len()
+        ));
+    }
+
+    if let Some(i) = indices
+        .chunks_exact(3)
+        .position(|triangle| !is_counter_clockwise(vertices, triangle))
+    {
+        return Err(anyhow!(
+            "Triangle {i} is clockwise when viewed from the side its normals point to"
+        ));
+    }
+
+    if is_closed {
+        validate_closed_mesh(vertices, indices)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that every edge is shared by exactly two triangles wound in opposite directions.
+fn validate_closed_mesh(vertices: &[Vertex], indices: &[u32]) -> Result<()> {
+    let (min, max) = vertices.iter().fold(
+        (Vec3::INFINITY, Vec3::NEG_INFINITY),
+        |(min, max), vertex| {
+            let p = Vec3::from(vertex.p);
+            (min.min(p), max.max(p))
+        },
+    );
+    let tolerance = (WELD_TOLERANCE * (max - min).max_element()).max(f32::MIN_POSITIVE);
+
+    // Give vertices at the same position the same id.
+    let mut ids_by_key: HashMap<[i64; 3], u32> = HashMap::new();
+    let mut points: Vec<Vec3> = vec![];
+    let ids: Vec<u32> = vertices
+        .iter()
+        .map(|vertex| {
+            let key = vertex.p.map(|x| (x / tolerance).round() as i64);
+            *ids_by_key.entry(key).or_insert_with(|| {
+                points.push(Vec3::from(vertex.p));
+                points.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    // Number of times each directed edge is used.
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| ids[triangle[i] as usize]);
+        if a == b || b == c || c == a {
+            continue;
+        }
+        for edge in [(a, b), (b, c), (c, a)] {
+            *edges.entry(edge).or_default() += 1;
+        }
+    }
+
+    for (&(a, b), &count) in edges.iter() {
+        if count > 1 {
+            return Err(anyhow!(
+                "Edge from {} to {} is used by {count} triangles with the same winding",
+                points[a as usize],
+                points[b as usize],
+            ));
+        }
+        if !edges.contains_key(&(b, a)) {
+            return Err(anyhow!(
+                "Edge from {} to {} is not shared by a triangle wound the opposite way",
+                points[a as usize],
+                points[b as usize],
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn uv_rect(col: usize, row: usize, cols: usize, rows: usize) -> [[f32; 2]; 4] {
+    let cell_w = 1.0 / cols as f32;
+    let cell_h = 1.0 / rows as f32;
+
+    let u0 = col as f32 * cell_w;
+    let v0 = 1.0 - (row as f32 + 1.0) * cell_h; // flip V: 0 at top
+    let u1 = u0 + cell_w;
+    let v1 = v0 + cell_h;
+
+    [
+        [u0, v1], // BL
+        [u1, v1], // BR
+        [u1, v0], // TR
+        [u0, v0], // TL
+    ]
+}
+
+#[rustfmt::skip]
+fn generate_box(corners: &[[f32; 3]; 2]) -> (Vec<Vertex>, Vec<u32>) {
+    let a = Vec3::from_slice(&corners[0]);
+    let b = Vec3::from_slice(&corners[1]);
+
+    let [x0, y0, z0] = a.min(b).to_array();
+    let [x1, y1, z1] = a.max(b).to_array();
+
+    let (lx, hx) = (x0, x1);
+    let (ly, hy) = (y0, y1);
+    let (lz, hz) = (z0, z1);
+
+    let uv_front =  uv_rect(1, 1, 4, 3);
+    let uv_back =   uv_rect(3, 1, 4, 3);
+    let uv_left =   uv_rect(0, 1, 4, 3);
+    let uv_right =  uv_rect(2, 1, 4, 3);
+    let uv_top =    uv_rect(1, 0, 4, 3);
+    let uv_bottom = uv_rect(1, 2, 4, 3);
+
+    let vertices = vec![
+        // Front (+Z)
+        Vertex::new([lx, ly, hz], [ 0.0,  0.0,  1.0],  uv_front[0]),
+        Vertex::new([hx, ly, hz], [ 0.0,  0.0,  1.0],  uv_front[1]),
+        Vertex::new([hx, hy, hz], [ 0.0,  0.0,  1.0],  uv_front[2]),
+        Vertex::new([lx, hy, hz], [ 0.0,  0.0,  1.0],  uv_front[3]),
+
+        // Back (-Z)
+        Vertex::new([hx, ly, lz], [ 0.0,  0.0, -1.0],   uv_back[0]),
+        Vertex::new([lx, ly, lz], [ 0.0,  0.0, -1.0],   uv_back[1]),
+        Vertex::new([lx, hy, lz], [ 0.0,  0.0, -1.0],   uv_back[2]),
+        Vertex::new([hx, hy, lz], [ 0.0,  0.0, -1.0],   uv_back[3]),
+
+        // Left (-X)
+        Vertex::new([lx, ly, lz], [-1.0,  0.0,  0.0],   uv_left[0]),
+        Vertex::new([lx, ly, hz], [-1.0,  0.0,  0.0],   uv_left[1]),
+        Vertex::new([lx, hy, hz], [-1.0,  0.0,  0.0],   uv_left[2]),
+        Vertex::new([lx, hy, lz], [-1.0,  0.0,  0.0],   uv_left[3]),
+
+        // Right (+X)
+        Vertex::new([hx, ly, hz], [ 1.0,  0.0,  0.0],  uv_right[0]),
+        Vertex::new([hx, ly, lz], [ 1.0,  0.0,  0.0],  uv_right[1]),
+        Vertex::new([hx, hy, lz], [ 1.0,  0.0,  0.0],  uv_right[2]),
+        Vertex::new([hx, hy, hz], [ 1.0,  0.0,  0.0],  uv_right[3]),
+
+        // Top (+Y)
+        Vertex::new([lx, hy, hz], [ 0.0,  1.0,  0.0],    uv_top[0]),
+        Vertex::new([hx, hy, hz], [ 0.0,  1.0,  0.0],    uv_top[1]),
+        Vertex::new([hx, hy, lz], [ 0.0,  1.0,  0.0],    uv_top[2]),
+        Vertex::new([lx, hy, lz], [ 0.0,  1.0,  0.0],    uv_top[3]),
+
+        // Bottom (-Y)
+        Vertex::new([lx, ly, lz], [ 0.0, -1.0,  0.0], uv_bottom[0]),
+        Vertex::new([hx, ly, lz], [ 0.0, -1.0,  0.0], uv_bottom[1]),
+        Vertex::new([hx, ly, hz], [ 0.0, -1.0,  0.0], uv_bottom[2]),
+        Vertex::new([lx, ly, hz], [ 0.0, -1.0,  0.0], uv_bottom[3]),
+    ];
+
+    // 6 faces, each with 2 triangles = 6 indices per face
+    let indices = vec![
+        // Front
+        0, 1, 2,
+        2, 3, 0,
+
+        // Back
+        4, 5, 6,
+        6, 7, 4,
+
+        // Left
+        8, 9, 10,
+        10, 11, 8,
+
+        // Right
+        12, 13, 14,
+        14, 15, 12,
+
+        // Top
+        16, 17, 18,
+        18, 19, 16,
+
+        // Bottom
+        20, 21, 22,
+        22, 23, 20,
+    ];
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Numbers of rings checked, including the smallest and odd numbers.
+    const RINGS: [u32; 6] = [2, 3, 4, 7, 32, 128];
+
+    /// Numbers of segments checked, including the smallest and odd numbers.
+    const SEGMENTS: [u32; 5] = [3, 4, 9, 64, 256];
+
+    #[test]
+    fn uv_spheres_are_valid() {
+        for rings in RINGS {
+            for segments in SEGMENTS {
+                for poles in [SpherePoles::Fan, SpherePoles::CollapsedQuads] {
+                    for seam in [SphereSeam::Duplicated, SphereSeam::Shared] {
+                        let (vertices, indices) =
+                            generate_uv_sphere(&[1.0, 2.0, 3.0], 1.5, rings, segments, poles, seam);
+                        if let Err(e) = validate_mesh(&vertices, &indices, true) {
+                            panic!("{rings}x{segments} sphere with {poles:?} {seam:?}: {e}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn box_is_valid() {
+        let (vertices, indices) = generate_box(&[[1.0, 2.0, 3.0], [-1.0, -2.0, -3.0]]);
+        validate_mesh(&vertices, &indices, true).unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{Result, anyhow};
+use log::info;
+use serde_json::{Map, Value, json};
+
+/// Current version of the scene file schema. Increment this whenever the schema changes in a way
+/// that older files can no longer be deserialized and add a migration to `MIGRATIONS`.
+pub const SCENE_FILE_VERSION: u32 = 1;
+
+/// Version assumed for scene files that don't specify one. The format before versioning was added
+/// is version 1.
+const UNVERSIONED: u32 = 1;
+
+/// A migration converts a scene file from one version to the next.
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// Migrations indexed by the version they migrate from, starting at version 1. There are none
+/// until the schema changes in a way that breaks older files.
+const MIGRATIONS: [Migration; (SCENE_FILE_VERSION - UNVERSIONED) as usize] = [];
+
+/// Upgrades a scene file in its JSON form to the current version so it can be deserialized.
+pub fn migrate(value: &mut Value) -> Result<()> {
+    let root = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Scene file must be a JSON object"))?;
+
+    let version = match root.get("version") {
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| anyhow!("Scene file version must be a positive integer"))?
+            as u32,
+        None => UNVERSIONED,
+    };
+
+    if version > SCENE_FILE_VERSION {
+        return Err(anyhow!(
+            "Scene file version {version} is newer than the supported version {SCENE_FILE_VERSION}"
+        ));
+    }
+    if version < UNVERSIONED {
+        return Err(anyhow!("Invalid scene file version {version}"));
+    }
+
+    root.entry("version").or_insert(json!(version));
+
+    for from in version..SCENE_FILE_VERSION {
+        info!("Migrating scene file from version {from} to {}", from + 1);
+        MIGRATIONS[(from - UNVERSIONED) as usize](root)?;
+        root.insert("version".to_string(), json!(from + 1));
+    }
+
+    Ok(())
+}
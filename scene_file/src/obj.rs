@@ -0,0 +1,108 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use glam::Vec3;
+use log::debug;
+
+use crate::Vertex;
+
+/// Load a Wavefront OBJ file. Each model in the file is returned as its own mesh. Models without
+/// normals use the normal of each face and models without texture coordinates use (0, 0).
+pub fn load_obj(path: &str) -> Result<Vec<(Vec<Vertex>, Vec<u32>)>> {
+    let (models, _materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .with_context(|| format!("Unable to load OBJ file '{path}'"))?;
+
+    let mut result = vec![];
+
+    for model in models.iter() {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        let mesh = &model.mesh;
+        let has_normals = !mesh.normals.is_empty();
+        let has_tex_coords = !mesh.texcoords.is_empty();
+
+        for index in mesh.indices.iter() {
+            let pos_offset = (3 * index) as usize;
+            let tex_coord_offset = (2 * index) as usize;
+
+            let n = if has_normals {
+                [
+                    mesh.normals[pos_offset],
+                    mesh.normals[pos_offset + 1],
+                    mesh.normals[pos_offset + 2],
+                ]
+            } else {
+                [0.0; 3]
+            };
+            let uv = if has_tex_coords {
+                [
+                    mesh.texcoords[tex_coord_offset],
+                    1.0 - mesh.texcoords[tex_coord_offset + 1],
+                ]
+            } else {
+                [0.0; 2]
+            };
+
+            let p = [
+                mesh.positions[pos_offset],
+                mesh.positions[pos_offset + 1],
+                mesh.positions[pos_offset + 2],
+            ];
+            let vertex = Vertex::new(p, n, uv);
+
+            let vertex_index = vertices.len() as u32;
+
+            vertices.push(vertex);
+            indices.push(vertex_index);
+        }
+
+        // Vertices aren't shared between triangles so each one can be given its face normal.
+        if !has_normals {
+            for triangle in vertices.chunks_exact_mut(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(triangle[i].p));
+                let n = (b - a).cross(c - a).normalize_or_zero();
+                for vertex in triangle.iter_mut() {
+                    vertex.n = n.into();
+                }
+            }
+        }
+
+        debug!(
+            "Vertex count: {}, Indices count: {}",
+            vertices.len(),
+            indices.len()
+        );
+
+        /*
+        debug!("-------------------------------------------------------------------------------");
+        debug!("     Position                     Normal                       UV");
+        debug!("-------------------------------------------------------------------------------");
+        for (i, v) in vertices.iter().enumerate() {
+            debug!(
+                "{i: >3}  [{: >7.4}, {: >7.4}, {: >7.4}]  [{: >7.4}, {: >7.4}, {: >7.4}]  [{:.4}, {:.4}]",
+                v.p[0], v.p[1], v.p[2], v.n[0], v.n[1], v.n[2], v.uv[0], v.uv[1],
+            );
+        }
+        debug!("-------------------------------------------------------------------------------");
+        debug!("Indices {indices:?}");
+        debug!("-------------------------------------------------------------------------------");
+        */
+
+        result.push((vertices, indices));
+    }
+
+    Ok(result)
+}
+
+/// Returns the name of the first material assigned with `usemtl` in a Wavefront OBJ file. The
+/// material library isn't read since the name refers to a material in the scene file.
+pub fn get_obj_material(path: &str) -> Result<Option<String>> {
+    let obj =
+        fs::read_to_string(path).with_context(|| format!("Unable to read OBJ file '{path}'"))?;
+    Ok(obj.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("usemtl ")
+            .map(|name| name.trim().to_string())
+    }))
+}
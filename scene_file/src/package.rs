@@ -0,0 +1,235 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::info;
+
+use crate::{Primitive, SceneFile, Texture};
+
+/// Bytes at the start of every scene package.
+const MAGIC: &[u8; 8] = b"SCENEPKG";
+
+/// Version of the scene package layout.
+const PACKAGE_VERSION: u32 = 1;
+
+/// Name of the scene file inside a package.
+const SCENE_ENTRY: &str = "scene.json";
+
+/// Directory textures are stored in inside a package.
+const TEXTURES_DIR: &str = "textures";
+
+/// Directory OBJ meshes are stored in inside a package.
+const MESHES_DIR: &str = "meshes";
+
+/// A file stored in a scene package.
+#[derive(Clone, Debug)]
+pub struct PackageEntry {
+    /// Relative path of the file inside the package using `/` as the separator.
+    pub name: String,
+
+    /// Contents of the file.
+    pub data: Vec<u8>,
+}
+
+/// Bundles a scene file and every file it references into a single scene package so it can be
+/// shared as one file. Paths in the packaged scene file are rewritten to point into the package.
+/// Returns the number of files that were packaged besides the scene file.
+///
+/// A package starts with `SCENEPKG`, a little-endian `u32` version and `u32` entry count. Each
+/// entry is a `u32` name length, the UTF-8 name, a `u64` data length and the data. The first entry
+/// is always the scene file.
+pub fn pack_scene(scene_path: &str, output_path: &str) -> Result<usize> {
+    let mut scene_file = SceneFile::load_json(scene_path)?;
+
+    let mut entries = vec![];
+    let mut entry_names: HashMap<String, String> = HashMap::new();
+
+    for texture in scene_file.textures.iter_mut() {
+        if let Texture::Image { path, .. } = texture {
+            *path = add_entry(path, TEXTURES_DIR, &mut entries, &mut entry_names)?;
+        }
+    }
+
+    for primitive in scene_file.primitives.iter_mut() {
+        if let Primitive::ObjMesh { path, .. } = primitive {
+            *path = add_entry(path, MESHES_DIR, &mut entries, &mut entry_names)?;
+        }
+    }
+
+    let file_count = entries.len();
+
+    entries.insert(
+        0,
+        PackageEntry {
+            name: SCENE_ENTRY.to_string(),
+            data: serde_json::to_vec_pretty(&scene_file)?,
+        },
+    );
+
+    write_package(output_path, &entries)?;
+
+    Ok(file_count)
+}
+
+/// Adds a file to the package unless it was already added and returns its name in the package.
+fn add_entry(
+    path: &str,
+    dir: &str,
+    entries: &mut Vec<PackageEntry>,
+    entry_names: &mut HashMap<String, String>,
+) -> Result<String> {
+    match entry_names.entry(path.to_string()) {
+        Entry::Occupied(e) => Ok(e.get().clone()),
+        Entry::Vacant(e) => {
+            let data =
+                fs::read(e.key()).with_context(|| format!("Unable to read '{}'", e.key()))?;
+
+            // Prefix the index so files with the same name from different directories don't
+            // collide.
+            let file_name = Path::new(e.key())
+                .file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or("asset");
+            let name = format!("{dir}/{}-{file_name}", entries.len());
+
+            entries.push(PackageEntry {
+                name: name.clone(),
+                data,
+            });
+            Ok(e.insert(name).clone())
+        }
+    }
+}
+
+/// Returns true if the file starts with the scene package header.
+pub fn is_package(path: &str) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == MAGIC)
+}
+
+/// Writes the entries to a scene package.
+pub fn write_package(path: &str, entries: &[PackageEntry]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Unable to create package '{path}'"))?;
+    let mut writer = BufWriter::new(file);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&PACKAGE_VERSION.to_le_bytes())?;
+    writer.write_all(&(entries.len() as u32).to_le_bytes())?;
+
+    for entry in entries {
+        writer.write_all(&(entry.name.len() as u32).to_le_bytes())?;
+        writer.write_all(entry.name.as_bytes())?;
+        writer.write_all(&(entry.data.len() as u64).to_le_bytes())?;
+        writer.write_all(&entry.data)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads all entries from a scene package.
+pub fn read_package(path: &str) -> Result<Vec<PackageEntry>> {
+    let file = File::open(path).with_context(|| format!("Unable to open package '{path}'"))?;
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(anyhow!("'{path}' is not a scene package"));
+    }
+
+    let version = read_u32(&mut reader)?;
+    if version != PACKAGE_VERSION {
+        return Err(anyhow!(
+            "Scene package '{path}' has unsupported version {version}"
+        ));
+    }
+
+    let count = read_u32(&mut reader)?;
+    (0..count)
+        .map(|_| {
+            let name_len = read_u32(&mut reader)? as u64;
+            let name = read_bytes(&mut reader, name_len)?;
+
+            let data_len = read_u64(&mut reader)?;
+            let data = read_bytes(&mut reader, data_len)?;
+
+            Ok(PackageEntry {
+                name: String::from_utf8(name)?,
+                data,
+            })
+        })
+        .collect::<Result<_>>()
+        .with_context(|| format!("Scene package '{path}' is corrupt"))
+}
+
+/// Extracts a scene package into a directory, replacing anything already there, and returns the
+/// path of the extracted scene file.
+pub fn extract_package(path: &str, dir: &Path) -> Result<String> {
+    let entries = read_package(path)?;
+
+    if entries.first().is_none_or(|e| e.name != SCENE_ENTRY) {
+        return Err(anyhow!("Scene package '{path}' has no scene file"));
+    }
+
+    if dir.exists() {
+        fs::remove_dir_all(dir).with_context(|| format!("Unable to clear '{}'", dir.display()))?;
+    }
+
+    info!("Extracting {path} to {}", dir.display());
+    for entry in entries.iter() {
+        let entry_path = get_entry_path(dir, &entry.name)
+            .with_context(|| format!("Scene package '{path}' is corrupt"))?;
+        if let Some(parent) = entry_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&entry_path, &entry.data)
+            .with_context(|| format!("Unable to write '{}'", entry_path.display()))?;
+    }
+
+    dir.join(SCENE_ENTRY)
+        .to_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Extracted path is not valid UTF-8"))
+}
+
+/// Returns the path an entry is extracted to. Names that would escape the directory are rejected.
+fn get_entry_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(anyhow!("Invalid entry name '{name}'"));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Reads the given number of bytes. This fails rather than allocating a huge buffer if a corrupt
+/// length is larger than the rest of the file.
+fn read_bytes(reader: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(anyhow!("Unexpected end of file"));
+    }
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
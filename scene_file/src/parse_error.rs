@@ -0,0 +1,104 @@
+use serde_path_to_error::{Error, Path, Segment};
+
+/// Maximum edit distance between an unknown name and an expected one for it to be suggested.
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// Fields holding enums and the name used for their variants in error messages.
+const ENUM_KINDS: [(&str, &str); 18] = [
+    ("cameras", "camera"),
+    ("orientation", "orientation"),
+    ("textures", "texture"),
+    ("materials", "material"),
+    ("primitives", "primitive"),
+    ("transform", "transform"),
+    ("sky", "sky"),
+    ("scaling", "scaling"),
+    ("poles", "pole"),
+    ("seam", "seam"),
+    ("normals", "normal orientation"),
+    ("winding", "winding"),
+    ("preference", "build preference"),
+    ("filter", "filter"),
+    ("wrap", "wrap"),
+    ("border", "border colour"),
+    ("color_space", "colour space"),
+    ("channel", "channel"),
+];
+
+/// Returns a readable description of a scene file deserialization error. This leads with the
+/// location of the field that failed, followed by the line and column if known, and suggests the
+/// closest match for misspelled enum variants.
+pub fn describe(error: &Error<serde_json::Error>) -> String {
+    let inner = error.inner();
+
+    // serde_json appends the position to its message so strip it to report it separately.
+    let mut message = inner.to_string();
+    let mut location = String::new();
+    if inner.line() > 0 {
+        let position = format!(" at line {} column {}", inner.line(), inner.column());
+        if let Some(stripped) = message.strip_suffix(&position) {
+            message = stripped.to_string();
+        }
+        location = format!(" (line {}, column {})", inner.line(), inner.column());
+    }
+
+    if let Some(description) = describe_unknown_variant(&message, error.path()) {
+        message = description;
+    }
+
+    format!("{}{location}: {message}", error.path())
+}
+
+/// Rewrites serde's "unknown variant `x`, expected one of `a`, `b`" message so that it names the
+/// kind of thing that was misspelled and suggests the closest expected variant.
+fn describe_unknown_variant(message: &str, path: &Path) -> Option<String> {
+    if !message.starts_with("unknown variant ") {
+        return None;
+    }
+
+    // Every other piece between backticks is a quoted name. The first one is the unknown variant.
+    let mut names = message.split('`').skip(1).step_by(2);
+    let variant = names.next()?;
+    let expected: Vec<&str> = names.collect();
+
+    let kind = get_enum_kind(path)
+        .map(|kind| format!("{kind} type"))
+        .unwrap_or_else(|| "variant".to_string());
+
+    Some(match get_suggestion(variant, &expected) {
+        Some(suggestion) => {
+            format!("unknown {kind} `{variant}`, did you mean `{suggestion}`?")
+        }
+        None => format!(
+            "unknown {kind} `{variant}`, expected one of {}",
+            expected
+                .iter()
+                .map(|name| format!("`{name}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    })
+}
+
+/// Returns the kind of enum at the end of the path based on the innermost named field.
+fn get_enum_kind(path: &Path) -> Option<&'static str> {
+    let field = path.iter().rev().find_map(|segment| match segment {
+        Segment::Map { key } => Some(key.as_str()),
+        _ => None,
+    })?;
+
+    ENUM_KINDS
+        .iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, kind)| *kind)
+}
+
+/// Returns the expected name closest to the given name if it is similar enough.
+fn get_suggestion<'a>(name: &str, expected: &[&'a str]) -> Option<&'a str> {
+    expected
+        .iter()
+        .map(|candidate| (strsim::levenshtein(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
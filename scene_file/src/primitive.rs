@@ -1,37 +1,331 @@
+use std::{borrow::Cow, path::Path};
+
+use anyhow::{Result, anyhow};
+use glam::{Mat4, Vec3};
+use log::warn;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+use crate::{AccelerationBuild, ArrayModifier, AssetResolver, Facing, Transform, get_obj_material};
+
+/// Number of render layers. Layers are selected with a bit per layer in the instance mask of the
+/// acceleration structure, which has 8 bits.
+pub const LAYER_COUNT: u32 = 8;
+
+/// Geometry that is placed in the scene by instances referencing it by name.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Primitive {
+    /// Sphere tessellated along lines of latitude and longitude.
     UvSphere {
+        /// Unique name of the primitive.
         name: String,
+
+        /// Centre in object space.
         center: [f32; 3],
+
+        /// Radius in object space.
         radius: f32,
+
+        /// Number of rings from pole to pole.
         rings: u32,
+
+        /// Number of segments around the equator.
         segments: u32,
+
+        /// How the rings next to the poles are triangulated.
+        #[serde(default)]
+        poles: SpherePoles,
+
+        /// Whether vertices along the texture seam are duplicated.
+        #[serde(default)]
+        seam: SphereSeam,
+
+        /// Name of the material.
         material: String,
+
+        /// Overrides the scene's normal orientation and winding for this primitive.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        facing: Option<Facing>,
+
+        /// Preferences for building the acceleration structure.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        acceleration: Option<AccelerationBuild>,
+
+        /// Labels used to select which primitives are rendered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        /// Render layer from 0 to 7. Defaults to layer 0.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<u32>,
+
+        /// Repeats the primitive on a grid for every instance.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        array: Option<ArrayModifier>,
+
+        /// Transform from the primitive's space to object space, applied before the transform of
+        /// each instance. Defaults to the identity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transform: Option<Transform>,
+
+        /// World space distance every instance moves from time 0 to 1 for motion blur, on top of
+        /// any animated instance transform.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        velocity: Option<[f32; 3]>,
     },
+
+    /// Single triangle.
     Triangle {
+        /// Unique name of the primitive.
         name: String,
+
+        /// Vertex positions in object space.
         points: [[f32; 3]; 3],
+
+        /// Surface normal shared by all vertices.
         normal: [f32; 3],
+
+        /// Texture coordinates of each vertex.
         uv: [[f32; 2]; 3],
+
+        /// Name of the material.
         material: String,
+
+        /// Overrides the scene's normal orientation and winding for this primitive.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        facing: Option<Facing>,
+
+        /// Preferences for building the acceleration structure.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        acceleration: Option<AccelerationBuild>,
+
+        /// Labels used to select which primitives are rendered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        /// Render layer from 0 to 7. Defaults to layer 0.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<u32>,
+
+        /// Repeats the primitive on a grid for every instance.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        array: Option<ArrayModifier>,
+
+        /// Transform from the primitive's space to object space, applied before the transform of
+        /// each instance. Defaults to the identity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transform: Option<Transform>,
+
+        /// World space distance every instance moves from time 0 to 1 for motion blur, on top of
+        /// any animated instance transform.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        velocity: Option<[f32; 3]>,
     },
+
+    /// Planar quadrilateral.
     Quad {
+        /// Unique name of the primitive.
         name: String,
+
+        /// Vertex positions in object space in winding order.
         points: [[f32; 3]; 4],
+
+        /// Surface normal shared by all vertices.
         normal: [f32; 3],
+
+        /// Texture coordinates of each vertex.
         uv: [[f32; 2]; 4],
+
+        /// Name of the material.
         material: String,
+
+        /// Overrides the scene's normal orientation and winding for this primitive.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        facing: Option<Facing>,
+
+        /// Preferences for building the acceleration structure.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        acceleration: Option<AccelerationBuild>,
+
+        /// Labels used to select which primitives are rendered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        /// Render layer from 0 to 7. Defaults to layer 0.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<u32>,
+
+        /// Repeats the primitive on a grid for every instance.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        array: Option<ArrayModifier>,
+
+        /// Transform from the primitive's space to object space, applied before the transform of
+        /// each instance. Defaults to the identity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transform: Option<Transform>,
+
+        /// World space distance every instance moves from time 0 to 1 for motion blur, on top of
+        /// any animated instance transform.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        velocity: Option<[f32; 3]>,
     },
+
+    /// Axis aligned box.
     Box {
+        /// Unique name of the primitive.
         name: String,
+
+        /// Opposite corners in object space.
         corners: [[f32; 3]; 2],
+
+        /// Name of the material.
         material: String,
+
+        /// Overrides the scene's normal orientation and winding for this primitive.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        facing: Option<Facing>,
+
+        /// Preferences for building the acceleration structure.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        acceleration: Option<AccelerationBuild>,
+
+        /// Labels used to select which primitives are rendered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        /// Render layer from 0 to 7. Defaults to layer 0.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<u32>,
+
+        /// Repeats the primitive on a grid for every instance.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        array: Option<ArrayModifier>,
+
+        /// Transform from the primitive's space to object space, applied before the transform of
+        /// each instance. Defaults to the identity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transform: Option<Transform>,
+
+        /// World space distance every instance moves from time 0 to 1 for motion blur, on top of
+        /// any animated instance transform.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        velocity: Option<[f32; 3]>,
+    },
+
+    /// Triangle mesh loaded from a Wavefront OBJ file. All models in the file are merged into one
+    /// mesh.
+    ObjMesh {
+        /// Unique name of the primitive.
+        name: String,
+
+        /// Path to the OBJ file. Relative paths are relative to the scene file.
+        path: String,
+
+        /// Name of the material. Defaults to the first material assigned with `usemtl` in the OBJ
+        /// file, which must be a material in the scene file.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material_override: Option<String>,
+
+        /// Overrides the scene's normal orientation and winding for this primitive.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        facing: Option<Facing>,
+
+        /// Preferences for building the acceleration structure.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        acceleration: Option<AccelerationBuild>,
+
+        /// Labels used to select which primitives are rendered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        /// Render layer from 0 to 7. Defaults to layer 0.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<u32>,
+
+        /// Repeats the primitive on a grid for every instance.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        array: Option<ArrayModifier>,
+
+        /// Transform from the primitive's space to object space, applied before the transform of
+        /// each instance. Defaults to the identity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transform: Option<Transform>,
+
+        /// World space distance every instance moves from time 0 to 1 for motion blur, on top of
+        /// any animated instance transform.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        velocity: Option<[f32; 3]>,
+    },
+
+    /// Reuses the geometry of another primitive with its own material and transform. Instances
+    /// of the same primitive share one bottom level acceleration structure, so procedural scenes
+    /// with many copies of the same shape don't build and store it for every copy.
+    Instance {
+        /// Unique name of the primitive.
+        name: String,
+
+        /// Name of the primitive whose geometry is used, along with its normal orientation,
+        /// winding and acceleration structure preferences. This can't be another instance.
+        mesh: String,
+
+        /// Name of the material.
+        material: String,
+
+        /// Labels used to select which primitives are rendered.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        /// Render layer from 0 to 7. Defaults to layer 0.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<u32>,
+
+        /// Repeats the primitive on a grid for every instance.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        array: Option<ArrayModifier>,
+
+        /// Transform from the referenced primitive's object space to this primitive's object
+        /// space, applied after the referenced primitive's own transform. Defaults to the identity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transform: Option<Transform>,
+
+        /// World space distance every instance moves from time 0 to 1 for motion blur, on top of
+        /// any animated instance transform.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        velocity: Option<[f32; 3]>,
     },
 }
 
+/// Triangulation of the rings next to the poles of a UV sphere.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpherePoles {
+    /// One triangle per segment meeting at the pole. Each triangle has its own pole vertex with
+    /// the texture coordinate centred on the segment so the texture isn't skewed towards the pole.
+    #[default]
+    Fan,
+
+    /// The pole rows have a vertex per segment boundary like every other ring, so texture
+    /// coordinates follow the same grid all the way to the pole. The zero area half of each quad
+    /// touching the pole is dropped.
+    CollapsedQuads,
+}
+
+/// Handling of the vertices where the texture coordinates wrap around a UV sphere.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SphereSeam {
+    /// The first column of vertices is repeated with `u = 1` so the texture doesn't run backwards
+    /// across the last segment.
+    #[default]
+    Duplicated,
+
+    /// The last segment reuses the first column of vertices so every edge is shared by two
+    /// triangles. Textures wrap incorrectly across the last segment, so use this for untextured
+    /// spheres.
+    Shared,
+}
+
 impl Primitive {
     pub fn get_name(&self) -> &str {
         match self {
@@ -39,6 +333,190 @@ impl Primitive {
             Self::Triangle { name, .. } => name,
             Self::Quad { name, .. } => name,
             Self::Box { name, .. } => name,
+            Self::ObjMesh { name, .. } => name,
+            Self::Instance { name, .. } => name,
         }
     }
+
+    /// Returns the name of the material. OBJ meshes without a material override read it from the
+    /// OBJ file.
+    pub fn get_material(&self) -> Result<String> {
+        match self {
+            Self::UvSphere { material, .. } => Ok(material.clone()),
+            Self::Triangle { material, .. } => Ok(material.clone()),
+            Self::Quad { material, .. } => Ok(material.clone()),
+            Self::Box { material, .. } => Ok(material.clone()),
+            Self::ObjMesh {
+                name,
+                path,
+                material_override,
+                ..
+            } => match material_override {
+                Some(material) => Ok(material.clone()),
+                None => get_obj_material(path)?.ok_or_else(|| {
+                    anyhow!("OBJ mesh '{name}' has no material. Set a material override.")
+                }),
+            },
+            Self::Instance { material, .. } => Ok(material.clone()),
+        }
+    }
+
+    /// Returns the primitive's normal orientation and winding if it overrides the scene's.
+    /// Instances use the ones of the primitive they reference.
+    pub fn get_facing(&self) -> Option<Facing> {
+        match self {
+            Self::UvSphere { facing, .. } => *facing,
+            Self::Triangle { facing, .. } => *facing,
+            Self::Quad { facing, .. } => *facing,
+            Self::Box { facing, .. } => *facing,
+            Self::ObjMesh { facing, .. } => *facing,
+            Self::Instance { .. } => None,
+        }
+    }
+
+    /// Returns the preferences for building the acceleration structure. Instances use the ones of
+    /// the primitive they reference.
+    pub fn get_acceleration(&self) -> AccelerationBuild {
+        match self {
+            Self::UvSphere { acceleration, .. } => acceleration,
+            Self::Triangle { acceleration, .. } => acceleration,
+            Self::Quad { acceleration, .. } => acceleration,
+            Self::Box { acceleration, .. } => acceleration,
+            Self::ObjMesh { acceleration, .. } => acceleration,
+            Self::Instance { .. } => &None,
+        }
+        .unwrap_or_default()
+    }
+
+    /// Returns the labels used to select which primitives are rendered.
+    pub fn get_tags(&self) -> &[String] {
+        match self {
+            Self::UvSphere { tags, .. } => tags,
+            Self::Triangle { tags, .. } => tags,
+            Self::Quad { tags, .. } => tags,
+            Self::Box { tags, .. } => tags,
+            Self::ObjMesh { tags, .. } => tags,
+            Self::Instance { tags, .. } => tags,
+        }
+    }
+
+    /// Returns the render layer. Layers past the last one are clamped to it.
+    pub fn get_layer(&self) -> u32 {
+        match self {
+            Self::UvSphere { layer, .. } => layer,
+            Self::Triangle { layer, .. } => layer,
+            Self::Quad { layer, .. } => layer,
+            Self::Box { layer, .. } => layer,
+            Self::ObjMesh { layer, .. } => layer,
+            Self::Instance { layer, .. } => layer,
+        }
+        .unwrap_or_default()
+        .min(LAYER_COUNT - 1)
+    }
+
+    /// Returns the array modifier that repeats the primitive for every instance.
+    pub fn get_array(&self) -> Option<ArrayModifier> {
+        match self {
+            Self::UvSphere { array, .. } => *array,
+            Self::Triangle { array, .. } => *array,
+            Self::Quad { array, .. } => *array,
+            Self::Box { array, .. } => *array,
+            Self::ObjMesh { array, .. } => *array,
+            Self::Instance { array, .. } => *array,
+        }
+    }
+
+    /// Returns the world space distance every instance of the primitive moves from time 0 to 1.
+    pub fn get_velocity(&self) -> Option<Vec3> {
+        match self {
+            Self::UvSphere { velocity, .. } => velocity,
+            Self::Triangle { velocity, .. } => velocity,
+            Self::Quad { velocity, .. } => velocity,
+            Self::Box { velocity, .. } => velocity,
+            Self::ObjMesh { velocity, .. } => velocity,
+            Self::Instance { velocity, .. } => velocity,
+        }
+        .map(Vec3::from)
+    }
+
+    /// Returns the transform from the primitive's space to object space. The geometry isn't
+    /// transformed so instances of the primitive share one acceleration structure and the
+    /// transform is applied by each instance instead.
+    fn get_transform_matrix(&self) -> Mat4 {
+        match self {
+            Self::UvSphere { transform, .. } => transform,
+            Self::Triangle { transform, .. } => transform,
+            Self::Quad { transform, .. } => transform,
+            Self::Box { transform, .. } => transform,
+            Self::ObjMesh { transform, .. } => transform,
+            Self::Instance { transform, .. } => transform,
+        }
+        .as_ref()
+        .map_or(Mat4::IDENTITY, Transform::to_matrix)
+    }
+
+    /// Returns the primitive whose geometry this primitive uses, which is itself unless it's an
+    /// instance, along with the transform from the geometry's space to this primitive's object
+    /// space. Instances must reference a primitive in `primitives` that isn't an instance.
+    pub fn get_geometry<'a>(&'a self, primitives: &'a [Primitive]) -> Result<(&'a Self, Mat4)> {
+        let Self::Instance { name, mesh, .. } = self else {
+            return Ok((self, self.get_transform_matrix()));
+        };
+
+        let geometry = primitives
+            .iter()
+            .find(|primitive| primitive.get_name() == mesh)
+            .ok_or_else(|| anyhow!("Instance '{name}' references unknown primitive '{mesh}'"))?;
+        if matches!(geometry, Self::Instance { .. }) {
+            return Err(anyhow!(
+                "Instance '{name}' references instance '{mesh}'. Reference its primitive instead."
+            ));
+        }
+
+        Ok((
+            geometry,
+            self.get_transform_matrix() * geometry.get_transform_matrix(),
+        ))
+    }
+
+    /// Returns the primitive with the rings and segments of a UV sphere halved `reduction` times,
+    /// e.g. to fit a scene into its memory budget. Other primitives are returned unchanged.
+    pub fn with_reduced_tessellation(&self, reduction: u32) -> Cow<'_, Self> {
+        if reduction == 0 || !matches!(self, Self::UvSphere { .. }) {
+            return Cow::Borrowed(self);
+        }
+
+        let mut reduced = self.clone();
+        if let Self::UvSphere {
+            rings, segments, ..
+        } = &mut reduced
+        {
+            *rings = rings.checked_shr(reduction).unwrap_or(0);
+            *segments = segments.checked_shr(reduction).unwrap_or(0);
+        }
+        Cow::Owned(reduced)
+    }
+
+    /// Returns the object space offset of each copy of the primitive placed by an instance. There
+    /// is a single copy at the origin unless the primitive has an array modifier.
+    pub fn get_array_offsets(&self) -> Vec<Vec3> {
+        self.get_array()
+            .map_or_else(|| vec![Vec3::ZERO], |array| array.get_offsets())
+    }
+
+    /// Resolves the path of an OBJ mesh relative to the scene file.
+    pub fn resolve_path(&mut self, resolver: &AssetResolver, scene_dir: &Path) {
+        if let Self::ObjMesh { path, .. } = self {
+            match resolver.resolve(path, scene_dir) {
+                Ok(resolved) => *path = resolved,
+                Err(e) => warn!("{e:?}"),
+            }
+        }
+    }
+
+    /// Returns true if the primitive's mesh encloses a volume. OBJ meshes aren't assumed to be
+    /// closed and instances have no mesh of their own.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::UvSphere { .. } | Self::Box { .. })
+    }
 }
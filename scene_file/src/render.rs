@@ -1,11 +1,365 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+use crate::{LAYER_COUNT, Primitive, TextureFilter};
+
+/// Largest number of samples per pixel per batch.
+pub const MAX_SAMPLES_PER_PIXEL: u32 = 64;
+
+/// Largest number of sample batches.
+pub const MAX_SAMPLE_BATCHES: u32 = 32;
+
+/// Largest factor the output resolution is multiplied by to get the resolution images are
+/// rendered at when supersampling.
+pub const MAX_SUPERSAMPLE: f32 = 4.0;
+
+/// Largest number of samples per pixel per batch at preview quality.
+pub const PREVIEW_MAX_SAMPLES_PER_PIXEL: u32 = 4;
+
+/// Largest number of sample batches at preview quality.
+pub const PREVIEW_MAX_SAMPLE_BATCHES: u32 = 4;
+
+/// Largest width or height of image textures at preview quality. Larger images are downscaled
+/// before they are uploaded.
+pub const PREVIEW_MAX_TEXTURE_SIZE: u32 = 512;
+
+/// Factor the rings and segments of UV spheres are divided by at preview quality.
+const PREVIEW_TESSELLATION_DIVISOR: u32 = 4;
+
+/// Render settings.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Render {
+    /// Name of the camera to render from.
     pub camera: String,
+
+    /// Samples per pixel per batch. Limited to `MAX_SAMPLES_PER_PIXEL`.
     pub samples_per_pixel: u32,
+
+    /// Number of sample batches to accumulate. Limited to `MAX_SAMPLE_BATCHES`.
     pub sample_batches: u32,
+
+    /// Maximum number of bounces per ray.
     pub max_ray_depth: u32,
+
+    /// Ratio of image width to height.
     pub aspect_ratio: f32,
+
+    /// Width and height in pixels of the image that is rendered interactively. If not set, the
+    /// window's size in logical pixels is used so high-DPI displays don't multiply the number of
+    /// rays traced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<[u32; 2]>,
+
+    /// How the rendered image is scaled to the window when their sizes differ.
+    #[serde(default)]
+    pub scaling: ScalingPolicy,
+
+    /// Fail to load the scene if an image texture can't be loaded. Otherwise missing textures are
+    /// replaced by a magenta and black checker pattern.
+    #[serde(default)]
+    pub strict_textures: bool,
+
+    /// Render layers from 0 to 7 to render. Every layer is rendered if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers: Option<Vec<u32>>,
+
+    /// Only primitives with at least one of these tags are rendered. Every primitive is rendered if
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    /// Film response used to map the rendered image to display colours.
+    #[serde(default)]
+    pub film: Film,
+
+    /// Filter weighting the samples of a pixel by their offset from its centre.
+    #[serde(default)]
+    pub pixel_filter: PixelFilter,
+
+    /// Factor the output resolution is multiplied by to get the resolution the image is rendered
+    /// at, from 1 to 4. The rendered image is filtered down to the output resolution, which
+    /// anti-aliases edges and fine texture detail at the cost of tracing more pixels. Fractional
+    /// factors such as 1.5 are allowed. Defaults to 1, which renders at the output resolution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supersample: Option<f32>,
+
+    /// Interval of time in [0, 1] over which the shutter is open for motion blur. Animated
+    /// transforms and primitive velocities move objects from time 0 to 1. Defaults to [0, 1].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutter: Option<[f32; 2]>,
+
+    /// Trace paths from the light sources in a separate pass to render caustics cast onto diffuse
+    /// surfaces by dielectrics and metals, which paths from the camera rarely find.
+    #[serde(default)]
+    pub caustics: bool,
+
+    /// Let shadow rays through dielectrics so glass casts lighter shadows tinted by its clearcoat
+    /// instead of black ones. The light is transmitted in a straight line, ignoring refraction, and
+    /// replaces the caustics camera paths would find through the glass. The caustics pass takes
+    /// precedence if it is enabled.
+    #[serde(default)]
+    pub transparent_shadows: bool,
+
+    /// Denoise the presented image with an edge-avoiding filter guided by the albedo, normal and
+    /// depth of the first hits, which makes noisy previews easier to judge. Accumulation and
+    /// offline renders keep the noisy image.
+    #[serde(default)]
+    pub denoise: bool,
+
+    /// Filter used for every image texture instead of the filter in its sampler options, e.g.
+    /// `nearest` so pixel art textures render with crisp texels. Each texture's own filter is used
+    /// if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_filter: Option<TextureFilter>,
+
+    /// Remove materials no primitive uses and textures no material uses before the scene is
+    /// uploaded, logging what was removed. Generated scenes often accumulate unused entries that
+    /// waste GPU memory and descriptors. Saved scene files keep them.
+    #[serde(default)]
+    pub strip_unused: bool,
+
+    /// Copy the bottom-level acceleration structures into buffers of their compacted sizes once
+    /// built. This saves device memory in scenes with large meshes at the cost of a longer build.
+    /// It has no effect without hardware raytracing.
+    #[serde(default)]
+    pub compact_acceleration: bool,
+
+    /// Limits on the GPU memory and build time of the scene. Scenes that exceed them fail to load
+    /// with a report of what uses the most, or are built at a lower level of detail if allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<Budget>,
+
+    /// Level of detail the scene is rendered at. This isn't stored in scene files and is only set
+    /// by render setting overrides, so offline renders use full quality unless asked not to.
+    #[serde(skip)]
+    pub quality: Quality,
+}
+
+impl Render {
+    /// Returns the mask of render layers to render with a bit per layer.
+    pub fn get_layer_mask(&self) -> u32 {
+        match &self.layers {
+            Some(layers) => layers
+                .iter()
+                .filter(|&&layer| layer < LAYER_COUNT)
+                .fold(0, |mask, layer| mask | (1 << layer)),
+            None => (1 << LAYER_COUNT) - 1,
+        }
+    }
+
+    /// Returns the mask of the instances of a primitive. This is the bit for the primitive's
+    /// layer, or 0 if the layer isn't rendered or the primitive is excluded by `tags`.
+    pub fn get_instance_mask(&self, primitive: &Primitive) -> u32 {
+        let is_tagged = self
+            .tags
+            .as_ref()
+            .is_none_or(|tags| primitive.get_tags().iter().any(|tag| tags.contains(tag)));
+
+        if is_tagged {
+            (1 << primitive.get_layer()) & self.get_layer_mask()
+        } else {
+            0
+        }
+    }
+
+    /// Returns the interval of time over which the shutter is open.
+    pub fn get_shutter_interval(&self) -> [f32; 2] {
+        self.shutter.unwrap_or([0.0, 1.0])
+    }
+
+    /// Returns the factor the output resolution is multiplied by to get the resolution the image
+    /// is rendered at, limited to `MAX_SUPERSAMPLE`.
+    pub fn get_supersample(&self) -> f32 {
+        self.supersample.unwrap_or(1.0).clamp(1.0, MAX_SUPERSAMPLE)
+    }
+}
+
+/// Limits on the resources used by a scene, checked while it is built so oversized scenes fail
+/// with an explanation instead of exhausting GPU memory or building for a very long time.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Budget {
+    /// Largest GPU memory in megabytes the meshes, acceleration structures and image textures are
+    /// estimated to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u32>,
+
+    /// Longest time in seconds building the meshes and acceleration structures may take. Image
+    /// textures load in the background and aren't included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_build_seconds: Option<f32>,
+
+    /// Halve the tessellation of UV spheres and the resolution of image textures until the scene
+    /// fits in `max_memory_mb` instead of refusing to load it.
+    pub reduce_detail: bool,
+}
+
+/// Level of detail a scene is rendered at.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    /// Coarser sphere tessellation, downscaled image textures and fewer samples so scenes load
+    /// and converge quickly while they are being set up.
+    Preview,
+
+    /// The scene as described by the scene file.
+    #[default]
+    Final,
+}
+
+impl Quality {
+    /// Returns the number of rings and segments used for a UV sphere with the given tessellation.
+    pub fn get_sphere_tessellation(&self, rings: u32, segments: u32) -> (u32, u32) {
+        match self {
+            Self::Preview => (
+                rings / PREVIEW_TESSELLATION_DIVISOR,
+                segments / PREVIEW_TESSELLATION_DIVISOR,
+            ),
+            Self::Final => (rings, segments),
+        }
+    }
+
+    /// Returns the largest width or height of image textures, or `None` if they are uploaded at
+    /// full resolution.
+    pub fn get_max_texture_size(&self) -> Option<u32> {
+        match self {
+            Self::Preview => Some(PREVIEW_MAX_TEXTURE_SIZE),
+            Self::Final => None,
+        }
+    }
+
+    /// Returns the largest number of samples per pixel per batch.
+    pub fn get_max_samples_per_pixel(&self) -> u32 {
+        match self {
+            Self::Preview => PREVIEW_MAX_SAMPLES_PER_PIXEL,
+            Self::Final => MAX_SAMPLES_PER_PIXEL,
+        }
+    }
+
+    /// Returns the largest number of sample batches.
+    pub fn get_max_sample_batches(&self) -> u32 {
+        match self {
+            Self::Preview => PREVIEW_MAX_SAMPLE_BATCHES,
+            Self::Final => MAX_SAMPLE_BATCHES,
+        }
+    }
+}
+
+impl FromStr for Quality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "preview" => Ok(Self::Preview),
+            "final" => Ok(Self::Final),
+            _ => Err(anyhow!(
+                "Unknown quality '{s}'. Expected 'preview' or 'final'."
+            )),
+        }
+    }
+}
+
+/// How the rendered image is scaled to the window.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalingPolicy {
+    /// Scale to the largest size that fits in the window keeping the aspect ratio. The rest of the
+    /// window is black.
+    #[default]
+    Fit,
+
+    /// Scale to the smallest size that covers the window keeping the aspect ratio. The image is
+    /// cropped.
+    Fill,
+
+    /// Scale by the largest whole number that fits in the window without filtering so pixels stay
+    /// sharp. Images larger than the window are scaled down to fit.
+    Integer,
+}
+
+/// Film response mapping scene radiance to display colours, so scenes rendered with the same
+/// settings have a consistent look.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Film {
+    /// Curve compressing scene radiance to the displayable range.
+    pub preset: FilmPreset,
+
+    /// Colour temperature in Kelvin of light that appears white. Lower values make the image
+    /// cooler and higher values warmer. The default of 6500 leaves colours unchanged.
+    pub white_balance: f32,
+
+    /// Colour saturation where 0 is greyscale and 1 leaves colours unchanged.
+    pub saturation: f32,
+}
+
+impl Default for Film {
+    fn default() -> Self {
+        Self {
+            preset: FilmPreset::default(),
+            white_balance: 6500.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+/// Curve compressing scene radiance to the displayable range.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilmPreset {
+    /// Colours are clipped at 1.
+    #[default]
+    Linear,
+
+    /// Filmic curve from Uncharted 2 with a soft shoulder that rolls off highlights.
+    Filmic,
+
+    /// AgX, which desaturates bright colours towards white like film instead of skewing their
+    /// hue.
+    Agx,
+}
+
+/// Pixel reconstruction filter. Samples are distributed around the pixel centre in proportion to
+/// the filter so they all have the same weight. Wider filters trade a little sharpness for
+/// smoother edges.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelFilter {
+    /// Samples are spread evenly over the pixel.
+    #[default]
+    Box,
+
+    /// Weight falls off linearly to 0 one pixel from the centre.
+    Tent,
+
+    /// Gaussian with a standard deviation of half a pixel, cut off 1.5 pixels from the centre.
+    Gaussian,
+
+    /// Blackman-Harris window reaching 2 pixels from the centre, which is about as smooth as the
+    /// Gaussian but keeps edges sharper.
+    BlackmanHarris,
+}
+
+/// Optional replacements for the render settings of a scene file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderOverrides {
+    /// Samples per pixel per batch.
+    pub samples_per_pixel: Option<u32>,
+
+    /// Number of sample batches to accumulate.
+    pub sample_batches: Option<u32>,
+
+    /// Maximum number of bounces per ray.
+    pub max_ray_depth: Option<u32>,
+
+    /// Fail to load scenes with image textures that can't be loaded.
+    pub strict_textures: Option<bool>,
+
+    /// Level of detail the scene is rendered at.
+    pub quality: Option<Quality>,
 }
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use shaders::ray_gen;
 
@@ -5,15 +6,25 @@ const _SKY_TYPE_NONE: u32 = 0;
 const SKY_TYPE_SOLID: u32 = 1;
 const SKY_TYPE_VERTICAL_GRADIENT: u32 = 2;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Background colour for rays that miss all geometry.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Sky {
+    /// Single colour.
     Solid {
+        /// Linear RGB colour.
         rgb: [f32; 3],
     },
+
+    /// Blend between a top and bottom colour.
     VerticalGradient {
+        /// Amount of the bottom colour blended with the top colour in [0, 1].
         factor: f32,
+
+        /// Linear RGB top colour.
         top: [f32; 3],
+
+        /// Linear RGB bottom colour.
         bottom: [f32; 3],
     },
 }
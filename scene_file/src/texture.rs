@@ -1,61 +1,284 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
 
 use anyhow::{Result, anyhow};
-use log::debug;
+use log::{debug, warn};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+use crate::AssetResolver;
+
+/// Texture referenced by materials by name. Names must be unique across all texture types.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Texture {
+    /// Solid colour.
     Constant {
+        /// Unique name of the texture.
         name: String,
+
+        /// Linear RGB colour.
         rgb: [f32; 3],
     },
+
+    /// Single value for scalar properties such as roughness. This is a grey colour if used as a
+    /// colour.
+    Scalar {
+        /// Unique name of the texture.
+        name: String,
+
+        /// The value.
+        value: f32,
+    },
+
+    /// Image file.
     Image {
+        /// Unique name of the texture.
         name: String,
+
+        /// Path to the image. Relative paths are relative to the scene file.
         path: String,
+
+        /// How the image is filtered and wrapped.
+        #[serde(default)]
+        sampler: SamplerOptions,
+
+        /// How the image's values are encoded. Use `linear` for data such as masks and normal maps.
+        #[serde(default)]
+        color_space: ColorSpace,
+
+        /// Channel used as a single value, e.g. for a roughness mask. The image is used as a colour
+        /// if this is not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<Channel>,
+
+        /// Blend randomly offset copies of the image so it doesn't visibly repeat when tiled, e.g.
+        /// across a large ground plane. This suits images of irregular patterns such as grass or
+        /// gravel rather than ones with regular features such as bricks.
+        #[serde(default)]
+        stochastic_tiling: bool,
+    },
+
+    /// Image held in memory, e.g. generated by an application embedding the renderer. This can't be
+    /// stored in scene files and saving a scene file with one is an error.
+    #[serde(skip)]
+    ImageBytes {
+        /// Unique name of the texture.
+        name: String,
+
+        /// The image.
+        data: ImageData,
+
+        /// How the image is filtered and wrapped.
+        sampler: SamplerOptions,
+
+        /// How the image's values are encoded.
+        color_space: ColorSpace,
+
+        /// Channel used as a single value. The image is used as a colour if this is not set.
+        channel: Option<Channel>,
+
+        /// Blend randomly offset copies of the image so it doesn't visibly repeat when tiled.
+        stochastic_tiling: bool,
     },
+
+    /// 3D checker pattern alternating between two textures.
     Checker {
+        /// Unique name of the texture.
         name: String,
+
+        /// Size of each square in world space.
         scale: f32,
+
+        /// Texture used for even squares. This cannot be another checker texture.
         even: String,
+
+        /// Texture used for odd squares. This cannot be another checker texture.
         odd: String,
     },
+
+    /// Perlin noise.
     Noise {
+        /// Unique name of the texture.
         name: String,
+
+        /// Frequency of the noise.
         scale: f32,
     },
 }
 
+/// Encoding of the values in an image texture.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    /// Colours are gamma encoded and converted to linear RGB when sampled.
+    #[default]
+    Srgb,
+
+    /// Values are used as stored.
+    Linear,
+}
+
+/// Contents of an in-memory image texture. The data is shared so textures can be cloned cheaply.
+#[derive(Clone)]
+pub enum ImageData {
+    /// Contents of an image file in any supported format, such as PNG or JPEG.
+    Encoded(Arc<[u8]>),
+
+    /// 8-bit RGBA pixels ordered row by row.
+    Rgba8 {
+        /// Width in pixels.
+        width: u32,
+
+        /// Height in pixels.
+        height: u32,
+
+        /// The pixels. This must hold `width * height * 4` bytes.
+        pixels: Arc<[u8]>,
+    },
+}
+
+impl fmt::Debug for ImageData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Encoded(bytes) => f.debug_tuple("Encoded").field(&bytes.len()).finish(),
+            Self::Rgba8 {
+                width,
+                height,
+                pixels,
+            } => f
+                .debug_struct("Rgba8")
+                .field("width", width)
+                .field("height", height)
+                .field("pixels", &pixels.len())
+                .finish(),
+        }
+    }
+}
+
+/// Channel of an image texture used as a single value.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    /// Red.
+    R,
+
+    /// Green.
+    G,
+
+    /// Blue.
+    B,
+
+    /// Alpha.
+    A,
+
+    /// Luminance of the linear RGB colour.
+    Luminance,
+}
+
+/// How an image texture is sampled.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Hash, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplerOptions {
+    /// Filter used when the image is magnified or minified.
+    pub filter: TextureFilter,
+
+    /// How texture coordinates outside [0, 1] are handled.
+    pub wrap: TextureWrap,
+
+    /// Colour returned outside [0, 1] when `wrap` is `clamp_to_border`.
+    pub border: BorderColour,
+}
+
+/// Filter used when sampling an image texture.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    /// Use the nearest texel.
+    #[default]
+    Nearest,
+
+    /// Interpolate between the nearest 2 x 2 texels.
+    Linear,
+}
+
+/// Handling of texture coordinates outside [0, 1].
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureWrap {
+    /// Tile the image.
+    #[default]
+    Repeat,
+
+    /// Tile the image, mirroring every other tile.
+    MirroredRepeat,
+
+    /// Repeat the texels at the edge of the image.
+    ClampToEdge,
+
+    /// Use the border colour.
+    ClampToBorder,
+}
+
+/// Colour of an image texture outside [0, 1] when clamping to the border.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderColour {
+    /// Black with zero alpha.
+    #[default]
+    TransparentBlack,
+
+    /// Black.
+    OpaqueBlack,
+
+    /// White.
+    OpaqueWhite,
+}
+
 impl Texture {
     pub fn get_name(&self) -> &str {
         match self {
             Self::Constant { name, .. } => name,
+            Self::Scalar { name, .. } => name,
             Self::Image { name, .. } => name,
+            Self::ImageBytes { name, .. } => name,
             Self::Checker { name, .. } => name,
             Self::Noise { name, .. } => name,
         }
     }
 
-    pub fn adjust_relative_path(&mut self, relative_to: &Path) {
+    /// Returns the names of the other textures this texture is made of.
+    pub fn get_textures(&self) -> Vec<&str> {
+        match self {
+            Self::Checker { even, odd, .. } => vec![even, odd],
+            _ => vec![],
+        }
+    }
+
+    /// Replaces the path of an image texture with the local path found by the resolver. The path
+    /// is left unchanged if it can't be resolved.
+    pub fn resolve_path(&mut self, resolver: &AssetResolver, scene_dir: &Path) {
         if let Self::Image { path, .. } = self {
-            let path_buf = Path::new(path).to_path_buf();
-            if path_buf.is_relative() {
-                let mut new_path_buf = relative_to.to_path_buf();
-                new_path_buf.push(path_buf);
-                *path = new_path_buf.to_str().unwrap().to_owned();
+            match resolver.resolve(path, scene_dir) {
+                Ok(resolved) => *path = resolved,
+                Err(e) => warn!("{e:?}"),
             }
         }
     }
 
     pub fn is_valid(&self, all_textures: &HashMap<String, Self>) -> Result<()> {
         match self {
-            Self::Constant { .. } | Self::Image { .. } | Self::Noise { .. } => Ok(()),
+            Self::Constant { .. }
+            | Self::Scalar { .. }
+            | Self::Image { .. }
+            | Self::ImageBytes { .. }
+            | Self::Noise { .. } => Ok(()),
             Self::Checker {
                 name, odd, even, ..
             } => match all_textures.get(odd) {
                 Some(Self::Constant { .. })
+                | Some(Self::Scalar { .. })
                 | Some(Self::Image { .. })
+                | Some(Self::ImageBytes { .. })
                 | Some(Self::Noise { .. }) => Ok(()),
                 Some(Self::Checker { .. }) => Err(anyhow!("Checker texture cannot be recursive.")),
                 None => Err(anyhow!(
@@ -64,7 +287,9 @@ impl Texture {
             }
             .and(match all_textures.get(even) {
                 Some(Self::Constant { .. })
+                | Some(Self::Scalar { .. })
                 | Some(Self::Image { .. })
+                | Some(Self::ImageBytes { .. })
                 | Some(Self::Noise { .. }) => Ok(()),
                 Some(Self::Checker { .. }) => Err(anyhow!("Checker texture cannot be recursive.")),
                 None => Err(anyhow!(
@@ -96,7 +321,9 @@ impl Texture {
         debug!("{seen:?}");
         match self {
             Self::Constant { name, .. } => seen.contains(name),
+            Self::Scalar { name, .. } => seen.contains(name),
             Self::Image { name, .. } => seen.contains(name),
+            Self::ImageBytes { name, .. } => seen.contains(name),
             Self::Noise { name, .. } => seen.contains(name),
             Self::Checker {
                 name, even, odd, ..
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Names of the materials and textures removed from a scene because nothing used them.
+#[derive(Clone, Debug, Default)]
+pub struct UnusedReport {
+    /// Materials no primitive uses.
+    pub materials: Vec<String>,
+
+    /// Textures no remaining material or texture uses.
+    pub textures: Vec<String>,
+}
+
+impl UnusedReport {
+    /// Returns true if nothing was removed.
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty() && self.textures.is_empty()
+    }
+}
+
+impl fmt::Display for UnusedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Removed {} unused materials and {} unused textures.",
+            self.materials.len(),
+            self.textures.len()
+        )?;
+        if !self.materials.is_empty() {
+            write!(f, "\nMaterials: {}", self.materials.join(", "))?;
+        }
+        if !self.textures.is_empty() {
+            write!(f, "\nTextures: {}", self.textures.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : enable
+
+#include "common.glsl"
+
+// Compute shader fallback for devices without raytracing pipelines. Rays are traced by traversing a
+// BVH over the world space triangles of all mesh instances that is built on the CPU.
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+// NOTE: The order of fields below will ensure data is aligned/packed correctly and
+// we can avoid having to use padding fields.
+
+// A BVH node. Leaf nodes have a non-zero triangle count and leftFirst is the index of their first
+// triangle. Otherwise leftFirst is the index of the left child and the right child follows it.
+struct BvhNode {
+    vec3 aabbMin;
+    uint leftFirst;
+    vec3 aabbMax;
+    uint triangleCount;
+};
+
+// A triangle in world space along with what is needed to look up its mesh data.
+struct BvhTriangle {
+    vec3 p0;
+    uint meshId;
+    vec3 p1;
+    uint primitiveId;
+    vec3 p2;
+    uint instanceIndex;
+};
+
+// Transforms of a mesh instance at the ray time the BVH was built for and its instance mask.
+struct BvhInstance {
+    mat4x3 objectToWorld;
+    mat4x3 worldToObject;
+    uint   mask;
+};
+
+layout(set = 0, binding = 0, scalar) buffer BvhNodes {
+    BvhNode values[];
+} bvhNodes;
+layout(set = 0, binding = 1, scalar) buffer BvhTriangles {
+    BvhTriangle values[];
+} bvhTriangles;
+layout(set = 0, binding = 2, scalar) buffer BvhInstances {
+    BvhInstance values[];
+} bvhInstances;
+
+// Maximum depth of the traversal stack. The BVH is built with median splits so its depth is
+// logarithmic in the number of triangles.
+const uint BVH_STACK_SIZE = 64;
+
+RayPayload rayPayload;
+
+// Returns the distance along the ray to the bounding box or tMax if it is missed.
+float intersectAabb(vec3 origin, vec3 invDirection, vec3 aabbMin, vec3 aabbMax, float tMin, float tMax) {
+    vec3 t0 = (aabbMin - origin) * invDirection;
+    vec3 t1 = (aabbMax - origin) * invDirection;
+
+    vec3 tNear = min(t0, t1);
+    vec3 tFar  = max(t0, t1);
+
+    float tEnter = max(max(tNear.x, tNear.y), max(tNear.z, tMin));
+    float tExit  = min(min(tFar.x, tFar.y), min(tFar.z, tMax));
+
+    return tEnter <= tExit ? tEnter : tMax;
+}
+
+// Möller–Trumbore ray/triangle intersection. Returns true if the triangle is hit closer than tMax
+// and updates tMax and the barycentric coordinates of p1 and p2.
+bool intersectTriangle(Ray ray, BvhTriangle triangle, float tMin, inout float tMax, out vec2 hitAttribs) {
+    vec3 edge1 = triangle.p1 - triangle.p0;
+    vec3 edge2 = triangle.p2 - triangle.p0;
+
+    vec3 pvec = cross(ray.direction, edge2);
+    float det = dot(edge1, pvec);
+    if (abs(det) < 1e-8) {
+        return false;
+    }
+    float invDet = 1.0 / det;
+
+    vec3 tvec = ray.origin - triangle.p0;
+    float u = dot(tvec, pvec) * invDet;
+    if (u < 0.0 || u > 1.0) {
+        return false;
+    }
+
+    vec3 qvec = cross(tvec, edge1);
+    float v = dot(ray.direction, qvec) * invDet;
+    if (v < 0.0 || u + v > 1.0) {
+        return false;
+    }
+
+    float t = dot(edge2, qvec) * invDet;
+    if (t <= tMin || t >= tMax) {
+        return false;
+    }
+
+    tMax = t;
+    hitAttribs = vec2(u, v);
+    return true;
+}
+
+void traceClosestHit(Ray ray, float tMin, float tMax, uint cullMask) {
+    vec3 invDirection = 1.0 / ray.direction;
+
+    float closestT = tMax;
+    uint  closestTriangle = 0;
+    vec2  closestHitAttribs = vec2(0.0);
+    bool  isHit = false;
+
+    uint stack[BVH_STACK_SIZE];
+    uint stackSize = 0;
+    stack[stackSize++] = 0;
+
+    while (stackSize > 0) {
+        BvhNode node = bvhNodes.values[stack[--stackSize]];
+        if (intersectAabb(ray.origin, invDirection, node.aabbMin, node.aabbMax, tMin, closestT) >= closestT) {
+            continue;
+        }
+
+        if (node.triangleCount > 0) {
+            for (uint i = node.leftFirst; i < node.leftFirst + node.triangleCount; i++) {
+                BvhTriangle triangle = bvhTriangles.values[i];
+                if ((bvhInstances.values[triangle.instanceIndex].mask & cullMask) == 0) {
+                    continue;
+                }
+
+                vec2 hitAttribs;
+                if (intersectTriangle(ray, triangle, tMin, closestT, hitAttribs)) {
+                    closestTriangle = i;
+                    closestHitAttribs = hitAttribs;
+                    isHit = true;
+                }
+            }
+            continue;
+        }
+
+        // Visit the nearer child first so more of the farther one can be culled.
+        uint left = node.leftFirst;
+        uint right = node.leftFirst + 1;
+        BvhNode leftNode = bvhNodes.values[left];
+        BvhNode rightNode = bvhNodes.values[right];
+        float tLeft = intersectAabb(ray.origin, invDirection, leftNode.aabbMin, leftNode.aabbMax, tMin, closestT);
+        float tRight = intersectAabb(ray.origin, invDirection, rightNode.aabbMin, rightNode.aabbMax, tMin, closestT);
+
+        if (stackSize + 2 > BVH_STACK_SIZE) {
+            break;
+        }
+        if (tLeft <= tRight) {
+            stack[stackSize++] = right;
+            stack[stackSize++] = left;
+        } else {
+            stack[stackSize++] = left;
+            stack[stackSize++] = right;
+        }
+    }
+
+    rayPayload.isMissed = !isHit;
+    if (!isHit) {
+        return;
+    }
+
+    BvhTriangle triangle = bvhTriangles.values[closestTriangle];
+    BvhInstance instance = bvhInstances.values[triangle.instanceIndex];
+
+    rayPayload.meshId      = triangle.meshId;
+    rayPayload.instanceId  = triangle.instanceIndex;
+    rayPayload.primitiveId = triangle.primitiveId;
+    rayPayload.hitAttribs  = closestHitAttribs;
+
+    rayPayload.objectToWorld     = instance.objectToWorld;
+    rayPayload.worldToObject     = instance.worldToObject;
+    rayPayload.worldRayDirection = ray.direction;
+}
+
+#include "path_tracer.glsl"
+
+void main() {
+    uvec2 pixel = gl_GlobalInvocationID.xy;
+    if (any(greaterThanEqual(pixel, pc.resolution))) {
+        return;
+    }
+
+    renderPixel(pixel);
+}
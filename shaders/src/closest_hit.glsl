@@ -8,6 +8,7 @@ hitAttributeEXT vec2 hitAttribs;
 
 void main() {
     rayPayload.meshId      = gl_InstanceCustomIndexEXT;
+    rayPayload.instanceId  = gl_InstanceID;
     rayPayload.primitiveId = gl_PrimitiveID;
 
     rayPayload.isMissed   = false;
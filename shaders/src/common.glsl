@@ -9,6 +9,9 @@ const float TWO_PI = 2.0 * PI;
 const float PI_OVER_2 = PI / 2.0;
 const float PI_OVER_4 = PI / 4.0;
 
+// Largest float below 1 so values in [0, 1) stay below 1 after rounding.
+const float ONE_MINUS_EPSILON = 0.99999994;
+
 // --------------------------------------------------------------------------------
 // Materials.
 
@@ -17,32 +20,75 @@ const uint MAT_TYPE_LAMBERTIAN = 1;
 const uint MAT_TYPE_METAL = 2;
 const uint MAT_TYPE_DIELECTRIC = 3;
 const uint MAT_TYPE_DIFFUSE_LIGHT = 4;
+const uint MAT_TYPE_PBR = 5;
 
 const uint MAT_PROP_VALUE_TYPE_RGB = 0;
 const uint MAT_PROP_VALUE_TYPE_IMAGE = 1;
 const uint MAT_PROP_VALUE_TYPE_CHECKER = 2;
 const uint MAT_PROP_VALUE_TYPE_NOISE = 3;
+const uint MAT_PROP_VALUE_TYPE_SCALAR = 4;
+const uint MAT_PROP_VALUE_TYPE_NONE = 5; // An optional texture that isn't set.
+
+// Channel of an image texture used as a single value.
+const uint IMAGE_CHANNEL_RGB = 0; // All channels are used as a colour.
+const uint IMAGE_CHANNEL_R = 1;
+const uint IMAGE_CHANNEL_G = 2;
+const uint IMAGE_CHANNEL_B = 3;
+const uint IMAGE_CHANNEL_A = 4;
+const uint IMAGE_CHANNEL_LUMINANCE = 5;
+
+// Lookup tables of stochastically tiled image textures.
+const uint STOCHASTIC_TILING_NONE = 0xffffffffu; // The image texture isn't stochastically tiled.
+const uint STOCHASTIC_TILING_LUT_SIZE = 256;      // Number of entries of each lookup table.
 
 struct MaterialPropertyValue {
     uint propValueType;
     uint index;
 };
 
+// Glossy clear layer over a material. An intensity of 0 means there is no layer.
+struct Clearcoat {
+    float intensity; // Scales the layer's reflectance and tint.
+    float roughness; // Roughness of the layer's reflection.
+    vec3  tint;      // Colour light passing through the layer is tinted by.
+};
+
+const Clearcoat NO_CLEARCOAT = Clearcoat(0.0, 0.0, vec3(1.0));
+
 struct LambertianMaterial {
     MaterialPropertyValue albedo;
+    MaterialPropertyValue normalMap; // Tangent space normals or MAT_PROP_VALUE_TYPE_NONE.
+    Clearcoat             clearcoat;
 };
 
 struct MetalMaterial {
     MaterialPropertyValue albedo;
-    MaterialPropertyValue fuzz;
+    MaterialPropertyValue fuzz;         // Roughness in the direction of the tangent.
+    MaterialPropertyValue fuzzV;        // Roughness in the direction of the bitangent.
+    float                 fuzzRotation; // Rotation of the tangent around the normal in radians.
+    MaterialPropertyValue normalMap;    // Tangent space normals or MAT_PROP_VALUE_TYPE_NONE.
+    Clearcoat             clearcoat;
+};
+
+struct PbrMaterial {
+    MaterialPropertyValue baseColour;
+    MaterialPropertyValue metallic;           // Only the red channel is used.
+    MaterialPropertyValue roughness;          // Perceptual roughness. Only the red channel is used.
+    MaterialPropertyValue normalMap;          // Tangent space normals or MAT_PROP_VALUE_TYPE_NONE.
+    float                 ior;                // Refractive index of the non-metallic parts.
+    float                 anisotropy;         // Stretches the roughness along the anisotropy direction.
+    float                 anisotropyRotation; // Rotation of the tangent around the normal in radians.
 };
 
 struct DielectricMaterial {
-    float refractionIndex;
+    float     refractionIndex;
+    uint      priority; // 0 if the material isn't nested in other dielectrics.
+    Clearcoat clearcoat;
 };
 
 struct DiffuseLightMaterial {
     MaterialPropertyValue emit;
+    vec3                  tint; // Colour of the light's temperature, or white.
 };
 
 struct CheckerTexture {
@@ -83,6 +129,7 @@ struct MeshVertex {
     float u; // u- texture coordinate
     vec3  n; // normal
     float v; // v- texture coordinate
+    vec4  t; // tangent in the direction u increases, w is the sign of the bitangent w * cross(n, t)
 };
 
 struct Mesh {
@@ -92,13 +139,21 @@ struct Mesh {
     uint materialIndex;
 };
 
+// Material of a mesh instance. A type of MAT_TYPE_NONE uses the mesh's material.
+struct InstanceMaterial {
+    uint materialType;
+    uint materialIndex;
+};
+
 // --------------------------------------------------------------------------------
 // Hit record
 
 struct HitRecord {
     MeshVertex meshVertex;
     bool       isFrontFace;
-    vec3       normal; // Points against the incident ray.
+    vec3       normal;  // Points against the incident ray.
+    vec3       tangent; // Direction the u texture coordinate increases in, orthogonal to the normal.
+    float      uvLod;   // Log2 of the ray's footprint in texture coordinates, used to choose image texture mip levels.
 };
 
 
@@ -106,8 +161,11 @@ struct HitRecord {
 // Light source sample.
 
 struct LightSample {
-    vec3 position;
-    vec3 normal;
+    vec3  position;
+    vec3  normal;       // Geometric normal of the sampled triangle.
+    uint  entryIndex;   // Index of the sampled triangle in the light source alias table.
+    vec2  barycentrics; // Barycentric coordinates of the position in the same form as hit attributes.
+    float areaPdf;      // Density of sampling the position with respect to area.
 };
 
 
@@ -128,6 +186,7 @@ struct Ray {
 
 struct RayPayload {
     uint   meshId;
+    uint   instanceId;
     uint   primitiveId;
     bool   isMissed;
     vec2   hitAttribs;
@@ -170,10 +229,30 @@ EmissionRecord initEmissionRecord() {
 // proportional area.
 
 struct LightSourceAliasTableEntry {
-    float probability;
-    uint alias;
-    uint meshId;
-    uint primitiveId;
+    float  probability;
+    uint   alias;
+    uint   meshId;
+    uint   instanceId;
+    uint   primitiveId;
+    mat4x3 objectToWorld; // Transforms of the light's instance.
+    mat4x3 worldToObject;
+};
+
+// --------------------------------------------------------------------------------
+// Light tree over the light source triangles used to sample them in proportion to an estimate of
+// their contribution to a shading point.
+
+const uint LIGHT_TREE_NONE = 0xffffffffu;
+
+struct LightTreeNode {
+    vec3  boundsMin;
+    float power;    // Sum of the emitted power of the triangles below the node.
+    vec3  boundsMax;
+    float cosTheta; // Cosine of the half angle of the cone bounding the normals of the triangles.
+    vec3  axis;     // Axis of the cone bounding the normals.
+    uint  parent;   // LIGHT_TREE_NONE for the root.
+    uint  left;     // First child, or the light source alias table entry of a leaf's triangle.
+    uint  right;    // Second child, or LIGHT_TREE_NONE for a leaf.
 };
 
 
@@ -372,15 +451,17 @@ vec2 sampleUniformDiskConcentric(inout uint rngState) {
     return r * vec2(cos(theta), sin(theta));
 }
 
-// Returns the vector to a random point in the square sub-pixel specified by grid
-// indices s_i and s_j, for an idealized unit square pixel [-.5,-.5] to [+.5,+.5].
-vec2 sampleSquareStratified(inout uint rngState, int si, int sj, float recipSqrtSpp) {
-    float px = ((si + randomFloat(rngState)) * recipSqrtSpp) - 0.5;
-    float py = ((sj + randomFloat(rngState)) * recipSqrtSpp) - 0.5;
+// Returns the vector to the point at `jitter` in [0, 1)^2 within the square sub-pixel specified by
+// grid indices s_i and s_j, for an idealized unit square pixel [-.5,-.5] to [+.5,+.5].
+vec2 sampleSquareStratified(vec2 jitter, int si, int sj, float recipSqrtSpp) {
+    float px = ((si + jitter.x) * recipSqrtSpp) - 0.5;
+    float py = ((sj + jitter.y) * recipSqrtSpp) - 0.5;
     return vec2(px, py);
 }
 
-vec3 sampleTriangleUniform(inout uint rngState, vec3 p0, vec3 p1, vec3 p2) {
+// Returns uniformly distributed barycentric coordinates (b1, b2) of a point p0 + b1 (p1 - p0) +
+// b2 (p2 - p0) in a triangle.
+vec2 sampleTriangleBarycentrics(inout uint rngState) {
     // Sample a unit square.
     vec2 r = randomVec2(rngState);
 
@@ -390,7 +471,7 @@ vec3 sampleTriangleUniform(inout uint rngState, vec3 p0, vec3 p1, vec3 p2) {
         r.y = 1.0 - r.y;
     }
 
-    return p0 + r.x * (p1 - p0) + r.y * (p2 - p0);
+    return r;
 }
 
 
@@ -425,6 +506,11 @@ vec4 sRGBToLinear(vec4 sRGB)
     return vec4(colour, sRGB.a);
 }
 
+// Returns the relative luminance of a linear colour.
+float luminance(vec3 linearRGB) {
+    return dot(linearRGB, vec3(0.2126, 0.7152, 0.0722));
+}
+
 float linearToGamma(float v) {
     if (v > 0) {
         return sqrt(v);
@@ -439,3 +525,37 @@ vec3 linearToGamma(vec3 linearRGB) {
     ); 
 }
 
+// --------------------------------------------------------------------------------
+// Exposure analysis.
+
+// The luminance histogram covers this range of exposure values relative to a luminance of 1, which
+// is where the presented image clips. Pixels outside the range are counted in the first or last bin.
+const uint  HISTOGRAM_BIN_COUNT = 64;
+const float HISTOGRAM_MIN_EV    = -10.0;
+const float HISTOGRAM_MAX_EV    = 2.0;
+
+// Returns the histogram bin for a luminance.
+uint getHistogramBin(float value) {
+    float ev = log2(max(value, 1e-10));
+    float t = (ev - HISTOGRAM_MIN_EV) / (HISTOGRAM_MAX_EV - HISTOGRAM_MIN_EV);
+    return uint(clamp(t * float(HISTOGRAM_BIN_COUNT), 0.0, float(HISTOGRAM_BIN_COUNT - 1)));
+}
+
+// --------------------------------------------------------------------------------
+// Splatting.
+
+// Light splatted to the film is accumulated as fixed point so it can be added with integer atomics
+// from any pixel. Each channel has this many steps per unit of radiance, which keeps 12 bits of
+// fraction so the dim splats of light paths aren't rounded away and leaves room for a sum of 2^20
+// before it overflows.
+const float SPLAT_FIXED_POINT_SCALE = 4096.0;
+
+// Returns a channel of splatted light in fixed point.
+uint encodeSplat(float value) {
+    return uint(clamp(value, 0.0, 1048575.0) * SPLAT_FIXED_POINT_SCALE + 0.5);
+}
+
+// Returns a channel of splatted light from fixed point.
+float decodeSplat(uint value) {
+    return float(value) / SPLAT_FIXED_POINT_SCALE;
+}
@@ -0,0 +1,82 @@
+#version 460
+
+// One iteration of the edge-avoiding À-Trous wavelet filter for denoising the presented image. See
+// "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination Filtering" by Holger
+// Dammertz et al. Each iteration blurs with a 5x5 B3 spline kernel whose taps are spread further
+// apart, and taps across edges in colour, albedo, normal or depth are weighted down so edges and
+// texture detail stay sharp.
+
+#include "common.glsl"
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba32f) uniform readonly image2D inputImage;
+layout(set = 0, binding = 1, rgba32f) uniform readonly image2D albedoImage;
+layout(set = 0, binding = 2, rgba32f) uniform readonly image2D normalImage;
+layout(set = 0, binding = 3, rgba32f) uniform readonly image2D depthImage;
+layout(set = 0, binding = 4, rgba32f) uniform writeonly image2D outputImage;
+
+layout(push_constant) uniform DenoisePushConstants {
+    int   stepWidth;  // Distance in pixels between the taps of the kernel.
+    float colourPhi;  // Sensitivity to colour differences. Smaller values keep more detail.
+} pc;
+
+// Weights of the B3 spline kernel by distance from the center tap.
+const float KERNEL[3] = float[](3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0);
+
+// Sensitivity to albedo differences.
+const float ALBEDO_PHI = 0.05;
+
+// Sensitivity to normal differences.
+const float NORMAL_PHI = 0.1;
+
+// Sensitivity to depth differences relative to the depth and the distance between taps.
+const float DEPTH_PHI = 0.05;
+
+// Compresses HDR colours into [0, 1) so colour differences of bright pixels don't stop filtering.
+vec3 compressColour(vec3 colour) {
+    return colour / (1.0 + colour);
+}
+
+void main() {
+    ivec2 size = imageSize(inputImage);
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+
+    vec4  colour = imageLoad(inputImage, pixel);
+    vec3  albedo = imageLoad(albedoImage, pixel).rgb;
+    vec3  normal = imageLoad(normalImage, pixel).xyz;
+    float depth  = imageLoad(depthImage, pixel).r;
+    vec3  compressed = compressColour(max(colour.rgb, vec3(0.0)));
+
+    vec3  summedColour = vec3(0.0);
+    float summedWeight = 0.0;
+    for (int j = -2; j <= 2; j++) {
+        for (int i = -2; i <= 2; i++) {
+            ivec2 offset = ivec2(i, j) * pc.stepWidth;
+            ivec2 tap = pixel + offset;
+            if (any(lessThan(tap, ivec2(0))) || any(greaterThanEqual(tap, size))) {
+                continue;
+            }
+
+            vec3  tapColour = imageLoad(inputImage, tap).rgb;
+            vec3  tapAlbedo = imageLoad(albedoImage, tap).rgb;
+            vec3  tapNormal = imageLoad(normalImage, tap).xyz;
+            float tapDepth  = imageLoad(depthImage, tap).r;
+
+            float colourWeight = exp(-lengthSquared(compressed - compressColour(max(tapColour, vec3(0.0)))) / pc.colourPhi);
+            float albedoWeight = exp(-lengthSquared(albedo - tapAlbedo) / ALBEDO_PHI);
+            float normalWeight = exp(-lengthSquared(normal - tapNormal) / NORMAL_PHI);
+            float depthWeight  = exp(-abs(depth - tapDepth) / (DEPTH_PHI * max(depth, 1e-4) * length(vec2(offset)) + 1e-4));
+
+            float weight = KERNEL[abs(i)] * KERNEL[abs(j)] * colourWeight * albedoWeight * normalWeight * depthWeight;
+            summedColour += weight * tapColour;
+            summedWeight += weight;
+        }
+    }
+
+    // The center tap always has a weight so the sum is never 0.
+    imageStore(outputImage, pixel, vec4(summedColour / summedWeight, colour.a));
+}
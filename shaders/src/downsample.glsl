@@ -0,0 +1,67 @@
+#version 460
+
+// Downsamples an image rendered at a higher resolution than the output with a Mitchell-Netravali
+// filter. See "Reconstruction Filters in Computer Graphics" by Don P. Mitchell and Arun N.
+// Netravali. The filter reaches 2 output pixels from the centre of each output pixel, so it also
+// smooths out aliasing at fractional scale factors, and its small negative lobes keep edges
+// sharper than a box or tent filter.
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba32f) uniform readonly image2D inputImage;
+layout(set = 0, binding = 1, rgba32f) uniform writeonly image2D outputImage;
+
+// Distance in output pixels from the centre beyond which the filter's weight is 0.
+const float FILTER_RADIUS = 2.0;
+
+// Parameters of the filter recommended by Mitchell and Netravali.
+const float MITCHELL_B = 1.0 / 3.0;
+const float MITCHELL_C = 1.0 / 3.0;
+
+// Returns the weight of the filter along one axis at a distance in output pixels from the centre.
+float mitchell(float x) {
+    x = abs(x);
+    if (x < 1.0) {
+        return ((12.0 - 9.0 * MITCHELL_B - 6.0 * MITCHELL_C) * x * x * x
+            + (-18.0 + 12.0 * MITCHELL_B + 6.0 * MITCHELL_C) * x * x
+            + (6.0 - 2.0 * MITCHELL_B)) / 6.0;
+    } else if (x < 2.0) {
+        return ((-MITCHELL_B - 6.0 * MITCHELL_C) * x * x * x
+            + (6.0 * MITCHELL_B + 30.0 * MITCHELL_C) * x * x
+            + (-12.0 * MITCHELL_B - 48.0 * MITCHELL_C) * x
+            + (8.0 * MITCHELL_B + 24.0 * MITCHELL_C)) / 6.0;
+    }
+    return 0.0;
+}
+
+void main() {
+    ivec2 inputSize = imageSize(inputImage);
+    ivec2 outputSize = imageSize(outputImage);
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pixel, outputSize))) {
+        return;
+    }
+
+    // Input pixels per output pixel along each axis.
+    vec2 scale = vec2(inputSize) / vec2(outputSize);
+
+    // Centre of the output pixel and the input pixels the filter reaches, in input pixels.
+    vec2 centre = (vec2(pixel) + 0.5) * scale;
+    ivec2 start = max(ivec2(floor(centre - FILTER_RADIUS * scale)), ivec2(0));
+    ivec2 end = min(ivec2(ceil(centre + FILTER_RADIUS * scale)), inputSize);
+
+    vec4 sum = vec4(0.0);
+    float weightSum = 0.0;
+    for (int y = start.y; y < end.y; y++) {
+        float weightY = mitchell((float(y) + 0.5 - centre.y) / scale.y);
+        for (int x = start.x; x < end.x; x++) {
+            float weight = weightY * mitchell((float(x) + 0.5 - centre.x) / scale.x);
+            sum += weight * imageLoad(inputImage, ivec2(x, y));
+            weightSum += weight;
+        }
+    }
+
+    // The negative lobes can overshoot below 0 next to very bright pixels.
+    vec4 colour = weightSum > 0.0 ? sum / weightSum : imageLoad(inputImage, ivec2(centre));
+    imageStore(outputImage, pixel, max(colour, vec4(0.0)));
+}
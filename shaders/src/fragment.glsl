@@ -4,10 +4,225 @@
 
 layout(location = 0) out vec4 outColor;
 layout(set = 0, binding = 0) uniform sampler2D accumTexture;
+layout(set = 0, binding = 1) uniform sampler2D blueNoise; // Tiling blue noise used for dithering.
+layout(set = 0, binding = 2) uniform sampler2D probeTexture; // Instance seen by the probe pass.
+layout(set = 0, binding = 3) readonly buffer Histogram {
+    uint bins[HISTOGRAM_BIN_COUNT]; // Luminance histogram of the rendered image.
+} histogram;
+
+// The rendered image is scaled to this viewport which may be smaller or larger than the swapchain
+// image depending on the scaling policy.
+layout(push_constant) uniform FragmentPushConstants {
+    mat4 filmMatrix; // White balance and saturation applied before the film curve.
+    vec2 viewportOffset; // Top left corner of the viewport in swapchain pixels.
+    vec2 viewportExtent; // Size of the viewport in swapchain pixels.
+    vec2 magnifierCenter; // Pixel of the rendered image at the center of the magnifier.
+    uint hoveredInstance; // Instance under the cursor plus one, or 0 if there is none.
+    uint isMagnifier; // 1 if the viewport is the magnifier quad rather than the rendered image.
+    float magnifierZoom; // Size of a pixel of the rendered image in the magnifier.
+    uint analysisMode; // How exposure is visualised. See `ANALYSIS_MODE_*`.
+    uint filmPreset; // Curve compressing scene radiance to the displayable range. See `FILM_PRESET_*`.
+    uint isSrgbTarget; // 1 if the swapchain image encodes colours to sRGB when they are written.
+} pc;
+
+const uint ANALYSIS_MODE_NONE         = 0;
+const uint ANALYSIS_MODE_HISTOGRAM    = 1;
+const uint ANALYSIS_MODE_FALSE_COLOUR = 2;
+const uint ANALYSIS_MODE_ZEBRA        = 3;
+
+const uint FILM_PRESET_LINEAR = 0;
+const uint FILM_PRESET_FILMIC = 1;
+const uint FILM_PRESET_AGX    = 2;
+
+// Exposure bias applied before the filmic curve and the scene radiance it maps to white.
+const float FILMIC_EXPOSURE_BIAS = 2.0;
+const float FILMIC_WHITE         = 11.2;
+
+// Conversions between linear sRGB and the AgX working space, and the range of exposure values
+// relative to a luminance of 1 that the AgX curve covers.
+const mat3 AGX_INSET = mat3(
+    0.84247905, 0.042328242, 0.042375654,
+    0.0784336, 0.87846863, 0.0784336,
+    0.079223745, 0.07916613, 0.879143
+);
+const mat3 AGX_OUTSET = mat3(
+    1.196879, -0.052896854, -0.052971635,
+    -0.09802088, 1.1519032, -0.09804345,
+    -0.09902974, -0.098961174, 1.1510737
+);
+const float AGX_MIN_EV = -12.47393;
+const float AGX_MAX_EV = 4.026069;
+
+// Colour blended over the instance under the cursor and how much of it to blend.
+const vec3  HOVER_COLOUR = vec3(1.0, 0.6, 0.1);
+const float HOVER_WEIGHT = 0.25;
+
+// Colour of the border around the magnifier.
+const vec3 MAGNIFIER_BORDER_COLOUR = vec3(1.0);
+
+// Size of the histogram and its distance from the top left corner of the visible image.
+const vec2 HISTOGRAM_SIZE   = vec2(256.0, 96.0);
+const vec2 HISTOGRAM_MARGIN = vec2(16.0);
+
+// Exposure values relative to middle grey where each false colour zone ends, and the colour of
+// each zone. Pixels at or above a luminance of 1 clip and are shown in the last colour. Zones with
+// a negative colour show the pixel's luminance in grey.
+const float MIDDLE_GREY = 0.18;
+const float FALSE_COLOUR_ZONE_EVS[6] = float[](-5.0, -3.0, -0.5, 0.5, 1.5, 2.4);
+const vec3  FALSE_COLOUR_ZONE_COLOURS[7] = vec3[](
+    vec3(0.4, 0.0, 0.6),  // Crushed shadows.
+    vec3(0.0, 0.2, 1.0),  // Deep shadows.
+    vec3(-1.0),           // Shadows.
+    vec3(0.1, 0.8, 0.1),  // Middle grey.
+    vec3(-1.0),           // Highlights.
+    vec3(1.0, 0.9, 0.0),  // Bright highlights.
+    vec3(1.0, 0.0, 0.0)   // Clipped.
+);
+
+// Width of the zebra stripes over clipped pixels in swapchain pixels.
+const float ZEBRA_STRIPE_WIDTH = 6.0;
+
+// Filmic curve from John Hable's "Uncharted 2: HDR Lighting" talk before it is normalised to the
+// white point.
+vec3 hable(vec3 x) {
+    const float A = 0.15; // Shoulder strength.
+    const float B = 0.50; // Linear strength.
+    const float C = 0.10; // Linear angle.
+    const float D = 0.20; // Toe strength.
+    const float E = 0.02; // Toe numerator.
+    const float F = 0.30; // Toe denominator.
+    return (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F;
+}
+
+vec3 filmic(vec3 colour) {
+    return hable(colour * FILMIC_EXPOSURE_BIAS) / hable(vec3(FILMIC_WHITE));
+}
+
+// AgX curve by Troy Sobotka using the polynomial fit of its default contrast by Benjamin Wrensch.
+vec3 agx(vec3 colour) {
+    vec3 x = clamp(log2(max(AGX_INSET * colour, vec3(1e-10))), AGX_MIN_EV, AGX_MAX_EV);
+    x = (x - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    vec3 curve = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+    // The curve gives display encoded values with a gamma of 2.2.
+    return pow(max(AGX_OUTSET * curve, vec3(0.0)), vec3(2.2));
+}
+
+// Maps a linear colour of the rendered image to the linear colour that is displayed. This matches
+// `FilmResponse::apply`.
+vec3 applyFilm(vec3 linear) {
+    vec3 colour = max(mat3(pc.filmMatrix) * linear, vec3(0.0));
+    switch (pc.filmPreset) {
+        case FILM_PRESET_FILMIC: return filmic(colour);
+        case FILM_PRESET_AGX:    return agx(colour);
+        default:                 return colour;
+    }
+}
+
+// Returns the false colour exposure zone colour for a pixel.
+vec3 getFalseColour(vec3 linear) {
+    float value = luminance(linear);
+    if (any(greaterThanEqual(linear, vec3(1.0)))) {
+        return FALSE_COLOUR_ZONE_COLOURS[6];
+    }
+
+    float ev = log2(max(value, 1e-10) / MIDDLE_GREY);
+    uint zone = 0;
+    while (zone < 6 && ev >= FALSE_COLOUR_ZONE_EVS[zone]) {
+        zone++;
+    }
+
+    vec3 colour = FALSE_COLOUR_ZONE_COLOURS[zone];
+    return colour.r < 0.0 ? vec3(value) : colour;
+}
+
+// Draws diagonal stripes over the displayed colour of pixels where any channel of the rendered
+// image clips.
+vec3 getZebraColour(vec3 linear, vec3 colour) {
+    if (!any(greaterThanEqual(linear, vec3(1.0)))) {
+        return colour;
+    }
+    float stripe = mod(gl_FragCoord.x + gl_FragCoord.y, 2.0 * ZEBRA_STRIPE_WIDTH);
+    return stripe < ZEBRA_STRIPE_WIDTH ? vec3(0.0) : colour;
+}
+
+// Draws the luminance histogram over the top left corner of the visible image. Bins at or above a
+// luminance of 1 are drawn in red since those pixels clip.
+vec3 getHistogramColour(vec3 colour) {
+    vec2 position = gl_FragCoord.xy - max(pc.viewportOffset, vec2(0.0)) - HISTOGRAM_MARGIN;
+    if (any(lessThan(position, vec2(0.0))) || any(greaterThanEqual(position, HISTOGRAM_SIZE))) {
+        return colour;
+    }
+
+    uint maxCount = 1;
+    for (uint i = 0; i < HISTOGRAM_BIN_COUNT; i++) {
+        maxCount = max(maxCount, histogram.bins[i]);
+    }
+
+    uint bin = uint(position.x / HISTOGRAM_SIZE.x * float(HISTOGRAM_BIN_COUNT));
+    float height = float(histogram.bins[bin]) / float(maxCount) * HISTOGRAM_SIZE.y;
+    if (HISTOGRAM_SIZE.y - position.y > height) {
+        return colour * 0.25;
+    }
+
+    float binEv = mix(HISTOGRAM_MIN_EV, HISTOGRAM_MAX_EV, float(bin) / float(HISTOGRAM_BIN_COUNT));
+    return binEv >= 0.0 ? vec3(1.0, 0.1, 0.1) : vec3(0.8);
+}
+
+// Shows an enlarged crop of the rendered image around the cursor. Pixels are fetched without
+// filtering so each one is shown as a sharp square.
+vec3 getMagnifiedColour() {
+    vec2 quadPosition = gl_FragCoord.xy - pc.viewportOffset;
+    if (any(lessThan(quadPosition, vec2(1.0))) ||
+        any(greaterThan(quadPosition, pc.viewportExtent - 1.0))) {
+        return MAGNIFIER_BORDER_COLOUR;
+    }
+
+    vec2 offset = (quadPosition - pc.viewportExtent / 2.0) / pc.magnifierZoom;
+    ivec2 pixel = ivec2(floor(pc.magnifierCenter + offset));
+    if (any(lessThan(pixel, ivec2(0))) ||
+        any(greaterThanEqual(pixel, textureSize(accumTexture, 0)))) {
+        return vec3(0.0);
+    }
+    return texelFetch(accumTexture, pixel, 0).rgb;
+}
+
+// Returns the output for an sRGB encoded colour. sRGB swapchain images encode colours when they are
+// written, so the colour is decoded for them instead of being encoded twice. UNORM images store it
+// as is.
+vec4 toOutput(vec3 encoded) {
+    return vec4(pc.isSrgbTarget != 0 ? sRGBToLinear(encoded) : encoded, 1.0);
+}
 
 void main() {
-    vec2 uv = gl_FragCoord.xy / vec2(textureSize(accumTexture, 0));
+    if (pc.isMagnifier != 0) {
+        outColor = toOutput(linearTosRGB(applyFilm(getMagnifiedColour())));
+        return;
+    }
+
+    vec2 uv = (gl_FragCoord.xy - pc.viewportOffset) / pc.viewportExtent;
     vec3 linear = texture(accumTexture, uv).rgb;
-    outColor = vec4(linearTosRGB(linear), 1.0);
-}
 
+    // Highlight the instance under the cursor. The probe image has a much lower resolution so the
+    // highlight is blocky but it is only a hint of what would be picked.
+    if (pc.hoveredInstance != 0 && uint(texture(probeTexture, uv).g) == pc.hoveredInstance) {
+        linear = mix(linear, HOVER_COLOUR, HOVER_WEIGHT);
+    }
+
+    // Exposure is analysed on the rendered image rather than the displayed colours.
+    vec3 colour = applyFilm(linear);
+    switch (pc.analysisMode) {
+        case ANALYSIS_MODE_HISTOGRAM:    colour = getHistogramColour(colour); break;
+        case ANALYSIS_MODE_FALSE_COLOUR: colour = getFalseColour(linear); break;
+        case ANALYSIS_MODE_ZEBRA:        colour = getZebraColour(linear, colour); break;
+    }
+
+    // Dither before the colour is quantized to the 8-bit swapchain image so smooth gradients, like
+    // the sky, don't show banding. The noise is added to the sRGB encoded colour since that is
+    // what is quantized. This uses a different channel than the pixel jitter.
+    float noise = texelFetch(blueNoise, ivec2(gl_FragCoord.xy) % textureSize(blueNoise, 0), 0).b;
+    outColor = toOutput(linearTosRGB(colour) + (noise - 0.5) / 255.0);
+}
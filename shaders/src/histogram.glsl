@@ -0,0 +1,35 @@
+#version 460
+
+// Counts the pixels of the accumulated image in bins of log2 luminance for the histogram analysis
+// view. Each workgroup counts its pixels in shared memory first so there are far fewer atomic adds
+// to the histogram buffer.
+
+#include "common.glsl"
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba32f) uniform readonly image2D accumImage;
+layout(set = 0, binding = 1) buffer Histogram {
+    uint bins[HISTOGRAM_BIN_COUNT];
+} histogram;
+
+shared uint localBins[HISTOGRAM_BIN_COUNT];
+
+void main() {
+    uint i = gl_LocalInvocationIndex;
+    if (i < HISTOGRAM_BIN_COUNT) {
+        localBins[i] = 0u;
+    }
+    barrier();
+
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (all(lessThan(pixel, imageSize(accumImage)))) {
+        float value = luminance(imageLoad(accumImage, pixel).rgb);
+        atomicAdd(localBins[getHistogramBin(value)], 1u);
+    }
+    barrier();
+
+    if (i < HISTOGRAM_BIN_COUNT && localBins[i] != 0) {
+        atomicAdd(histogram.bins[i], localBins[i]);
+    }
+}
@@ -29,6 +29,46 @@ pub mod ray_miss {
     }
 }
 
+pub mod bvh_trace {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/bvh_trace.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
+pub mod histogram {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/histogram.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
+pub mod splat_resolve {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/splat_resolve.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
+pub mod denoise {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/denoise.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
+pub mod downsample {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/downsample.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
 pub mod vertex {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -45,6 +85,22 @@ pub mod fragment {
     }
 }
 
+pub mod overlay_vertex {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/overlay_vertex.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
+pub mod overlay_fragment {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/overlay_fragment.glsl",
+        vulkan_version: "1.3",
+    }
+}
+
 pub struct RtShaderModules {
     pub stages: Vec<PipelineShaderStageCreateInfo>,
     pub groups: Vec<RayTracingShaderGroupCreateInfo>,
@@ -89,6 +145,91 @@ impl RtShaderModules {
     }
 }
 
+pub struct BvhShaderModules {
+    pub stage: PipelineShaderStageCreateInfo,
+}
+
+impl BvhShaderModules {
+    pub fn load(device: Arc<Device>) -> Self {
+        let bvh_trace = bvh_trace::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        Self {
+            stage: PipelineShaderStageCreateInfo::new(bvh_trace),
+        }
+    }
+}
+
+pub struct HistogramShaderModules {
+    pub stage: PipelineShaderStageCreateInfo,
+}
+
+impl HistogramShaderModules {
+    pub fn load(device: Arc<Device>) -> Self {
+        let histogram = histogram::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        Self {
+            stage: PipelineShaderStageCreateInfo::new(histogram),
+        }
+    }
+}
+
+pub struct SplatResolveShaderModules {
+    pub stage: PipelineShaderStageCreateInfo,
+}
+
+impl SplatResolveShaderModules {
+    pub fn load(device: Arc<Device>) -> Self {
+        let splat_resolve = splat_resolve::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        Self {
+            stage: PipelineShaderStageCreateInfo::new(splat_resolve),
+        }
+    }
+}
+
+pub struct DenoiseShaderModules {
+    pub stage: PipelineShaderStageCreateInfo,
+}
+
+impl DenoiseShaderModules {
+    pub fn load(device: Arc<Device>) -> Self {
+        let denoise = denoise::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        Self {
+            stage: PipelineShaderStageCreateInfo::new(denoise),
+        }
+    }
+}
+
+pub struct DownsampleShaderModules {
+    pub stage: PipelineShaderStageCreateInfo,
+}
+
+impl DownsampleShaderModules {
+    pub fn load(device: Arc<Device>) -> Self {
+        let downsample = downsample::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        Self {
+            stage: PipelineShaderStageCreateInfo::new(downsample),
+        }
+    }
+}
+
 pub struct GfxShaderModules {
     pub stages: Vec<PipelineShaderStageCreateInfo>,
 }
@@ -115,6 +256,32 @@ impl GfxShaderModules {
     }
 }
 
+pub struct OverlayShaderModules {
+    pub stages: Vec<PipelineShaderStageCreateInfo>,
+}
+
+impl OverlayShaderModules {
+    pub fn load(device: Arc<Device>) -> Self {
+        let vertex = overlay_vertex::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        let fragment = overlay_fragment::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        // Make a list of the shader stages that the pipeline will have.
+        let stages = vec![
+            PipelineShaderStageCreateInfo::new(vertex),
+            PipelineShaderStageCreateInfo::new(fragment),
+        ];
+
+        Self { stages }
+    }
+}
+
 impl fmt::Debug for ray_gen::RayGenPushConstants {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("closest_hit::RayGenPushConstants")
@@ -134,6 +301,21 @@ impl fmt::Debug for ray_gen::RayGenPushConstants {
             .field("diffuseLightMaterialCount", &self.diffuseLightMaterialCount)
             .field("lightSourceTriangleCount", &self.lightSourceTriangleCount)
             .field("lightSourceTotalArea", &self.lightSourceTotalArea)
+            .field("batchRayTime", &self.batchRayTime)
+            .field("samplingPattern", &self.samplingPattern)
+            .field("samplingPhase", &self.samplingPhase)
+            .field("reprojectHistory", &self.reprojectHistory)
+            .field("scalarConstantCount", &self.scalarConstantCount)
+            .field("layerMask", &self.layerMask)
+            .field("instanceMaterialCount", &self.instanceMaterialCount)
+            .field("estimator", &self.estimator)
+            .field("probePass", &self.probePass)
+            .field("profiling", &self.profiling)
+            .field("splatting", &self.splatting)
+            .field("traceCaustics", &self.traceCaustics)
+            .field("lightTracingPass", &self.lightTracingPass)
+            .field("transparentShadows", &self.transparentShadows)
+            .field("pbrMaterialCount", &self.pbrMaterialCount)
             .finish()
     }
 }
@@ -147,10 +329,22 @@ impl fmt::Debug for ray_gen::MaterialPropertyValue {
     }
 }
 
+impl fmt::Debug for ray_gen::Clearcoat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ray_gen::Clearcoat")
+            .field("intensity", &self.intensity)
+            .field("roughness", &self.roughness)
+            .field("tint", &self.tint)
+            .finish()
+    }
+}
+
 impl fmt::Debug for ray_gen::LambertianMaterial {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ray_gen::LambertianMaterial")
             .field("albedo", &self.albedo)
+            .field("normalMap", &self.normalMap)
+            .field("clearcoat", &self.clearcoat)
             .finish()
     }
 }
@@ -160,6 +354,10 @@ impl fmt::Debug for ray_gen::MetalMaterial {
         f.debug_struct("ray_gen::MetalMaterial")
             .field("albedo", &self.albedo)
             .field("fuzz", &self.fuzz)
+            .field("fuzzV", &self.fuzzV)
+            .field("fuzzRotation", &self.fuzzRotation)
+            .field("normalMap", &self.normalMap)
+            .field("clearcoat", &self.clearcoat)
             .finish()
     }
 }
@@ -168,6 +366,22 @@ impl fmt::Debug for ray_gen::DielectricMaterial {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ray_gen::DielectricMaterial")
             .field("refractionIndex", &self.refractionIndex)
+            .field("priority", &self.priority)
+            .field("clearcoat", &self.clearcoat)
+            .finish()
+    }
+}
+
+impl fmt::Debug for ray_gen::PbrMaterial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ray_gen::PbrMaterial")
+            .field("baseColour", &self.baseColour)
+            .field("metallic", &self.metallic)
+            .field("roughness", &self.roughness)
+            .field("normalMap", &self.normalMap)
+            .field("ior", &self.ior)
+            .field("anisotropy", &self.anisotropy)
+            .field("anisotropyRotation", &self.anisotropyRotation)
             .finish()
     }
 }
@@ -176,6 +390,7 @@ impl fmt::Debug for ray_gen::DiffuseLightMaterial {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ray_gen::DiffuseLightMaterial")
             .field("emit", &self.emit)
+            .field("tint", &self.tint)
             .finish()
     }
 }
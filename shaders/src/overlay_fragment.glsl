@@ -0,0 +1,33 @@
+#version 460
+
+#include "common.glsl"
+
+layout(location = 0) in vec3 fragColour;
+layout(location = 1) in vec3 fragPosition;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform sampler2D depthTexture; // Distance to the first hit.
+
+layout(push_constant) uniform OverlayPushConstants {
+    mat4 viewProj;       // Camera view * projection.
+    vec4 eye;            // Camera position in world space. w is unused.
+    vec2 viewportOffset; // Top left corner of the viewport in swapchain pixels.
+    vec2 viewportExtent; // Size of the viewport in swapchain pixels.
+} pc;
+
+// Relative distance a line can be behind the rendered surface before it is considered hidden. The
+// depth is averaged over the pixel so edges need some slack.
+const float DEPTH_TOLERANCE = 0.01;
+
+// Opacity of lines hidden behind surfaces. They are still drawn faintly so they can be followed.
+const float HIDDEN_OPACITY = 0.25;
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - pc.viewportOffset) / pc.viewportExtent;
+    float sceneDepth = texture(depthTexture, uv).r;
+    float lineDepth = distance(pc.eye.xyz, fragPosition);
+
+    float opacity = lineDepth > sceneDepth * (1.0 + DEPTH_TOLERANCE) ? HIDDEN_OPACITY : 1.0;
+    outColor = vec4(linearTosRGB(fragColour), opacity);
+}
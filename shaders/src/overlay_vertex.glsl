@@ -0,0 +1,22 @@
+#version 460
+
+// Draws the lines of the viewport overlay, e.g. the ground grid and camera frustums, in world space.
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 colour;
+
+layout(location = 0) out vec3 fragColour;
+layout(location = 1) out vec3 fragPosition;
+
+layout(push_constant) uniform OverlayPushConstants {
+    mat4 viewProj;       // Camera view * projection.
+    vec4 eye;            // Camera position in world space. w is unused.
+    vec2 viewportOffset; // Top left corner of the viewport in swapchain pixels.
+    vec2 viewportExtent; // Size of the viewport in swapchain pixels.
+} pc;
+
+void main() {
+    fragColour = colour;
+    fragPosition = position;
+    gl_Position = pc.viewProj * vec4(position, 1.0);
+}
@@ -0,0 +1,1900 @@
+// Path tracer shared by the raytracing pipeline and the compute shader fallback. Shaders including
+// this must first include "common.glsl", declare the `rayPayload` global and define:
+//
+// void traceClosestHit(Ray ray, float tMin, float tMax, uint cullMask);
+//
+// It finds the closest intersection along the ray with an instance whose mask shares a bit with
+// `cullMask` and stores it in `rayPayload`, setting `rayPayload.isMissed` if there is none.
+
+#include "perlin.glsl"
+
+layout(set = 1, binding = 0) uniform Camera {
+    mat4  viewProj;        // Camera view * projection
+    mat4  viewInverse;     // Camera inverse view matrix
+    mat4  projInverse;     // Camera inverse projection matrix
+    mat4  prevViewProj;    // Camera view * projection used for the history images
+    mat4  prevViewInverse; // Camera inverse view matrix used for the history images
+    float focalLength;     // Focal length of lens.
+    float apertureSize;    // Aperture size (diameter of lens).
+    float exposure;        // Factor to scale radiance by based on physical camera settings.
+} camera;
+
+layout(set = 2, binding = 0, rgba32f) uniform image2D image;
+layout(set = 2, binding = 1, rgba32f) uniform image2D albedoImage;
+layout(set = 2, binding = 2, rgba32f) uniform image2D normalImage;
+layout(set = 2, binding = 3, rgba32f) uniform image2D depthImage;
+layout(set = 2, binding = 4, rgba32f) uniform image2D momentImage; // Mean of squared colour for variance.
+layout(set = 2, binding = 5, rgba32f) uniform image2D historyImage;      // Colour before the camera moved.
+layout(set = 2, binding = 6, rgba32f) uniform image2D historyDepthImage; // Depth and batch count before the camera moved.
+layout(set = 2, binding = 7, rgba32f) uniform image2D reprojectedImage;  // History colour and batch count blended into the pixel.
+layout(set = 2, binding = 8) uniform sampler2D blueNoise; // Tiling blue noise used for pixel jitter.
+layout(set = 2, binding = 9, rgba32f) uniform image2D probeImage; // Depth and instance seen by the probe pass.
+
+// Counters of the rays that hit each material type, indexed by `MAT_TYPE_*`, and of the rays that
+// missed everything. These are only updated when profiling.
+const uint PROFILE_SLOT_MISS  = 6;
+const uint PROFILE_SLOT_COUNT = 7;
+layout(set = 2, binding = 10) buffer MaterialProfile {
+    uint hits[PROFILE_SLOT_COUNT];      // Rays that hit a material type.
+    uint scattered[PROFILE_SLOT_COUNT]; // Hits that scattered another ray which has to be traced.
+} materialProfile;
+
+// Light splatted to any pixel of the film in fixed point, 3 channels per pixel row by row. This is
+// only used when splatting is enabled and is combined with the accumulated image by the resolve
+// pass.
+layout(set = 2, binding = 11) buffer SplatBuffer {
+    uint values[];
+} splat;
+
+// Inverse cumulative distribution of the pixel filter along one axis. Entry i is the offset from
+// the pixel centre below which the fraction i / (length - 1) of the filter's weight lies.
+layout(set = 2, binding = 12, scalar) buffer PixelFilterTable {
+    float offsets[];
+} pixelFilter;
+
+layout(set = 3, binding = 0, scalar) buffer MeshVertices {
+    MeshVertex values[];
+} meshVertexData;
+layout(set = 3, binding = 1, scalar) buffer MeshIndices {
+    uint values[];
+} meshIndexData;
+layout(set = 3, binding = 2, scalar) buffer Meshes {
+    Mesh values[];
+} meshData;
+layout(set = 3, binding = 3, scalar) buffer InstanceMaterials {
+    InstanceMaterial values[];
+} instanceMaterialData;
+
+layout(set = 4, binding = 0) uniform sampler imageTextureSamplers[];
+layout(set = 4, binding = 1) uniform texture2D imageTextures[];
+
+layout(set = 5, binding = 0, scalar) buffer ConstantColours {
+    vec3 values[];
+} constantColour;
+layout(set = 5, binding = 1, scalar) buffer ScalarConstants {
+    float values[];
+} scalarConstant;
+
+layout(set = 6, binding = 0, scalar) buffer LambertianMaterials {
+    LambertianMaterial values[];
+} lambertianMaterial;
+layout(set = 6, binding = 1, scalar) buffer MetalMaterials {
+    MetalMaterial values[];
+} metalMaterial;
+layout(set = 6, binding = 2, scalar) buffer DielectricMaterials {
+    DielectricMaterial values[];
+} dielectricMaterial;
+layout(set = 6, binding = 3, scalar) buffer DiffuseLightMaterials {
+    DiffuseLightMaterial values[];
+} diffuseLightMaterial;
+layout(set = 6, binding = 4, scalar) buffer PbrMaterials {
+    PbrMaterial values[];
+} pbrMaterial;
+
+layout(set = 7, binding = 0, scalar) buffer CheckerTextures {
+    CheckerTexture values[];
+} checkerTexture;
+layout(set = 7, binding = 1, scalar) buffer NoiseTextures {
+    NoiseTexture values[];
+} noiseTexture;
+layout(set = 7, binding = 2, scalar) buffer ImageTextureChannels {
+    uint values[];
+} imageTextureChannel;
+layout(set = 7, binding = 3, scalar) buffer ImageTextureStochasticTiling {
+    uint values[]; // Index of the lookup table in imageTextures or STOCHASTIC_TILING_NONE.
+} imageTextureStochasticTiling;
+
+layout(set = 8, binding = 0) uniform SkyData {
+    Sky value;
+} sky;
+
+layout(set = 9, binding = 0, scalar) buffer LightSourceAliasTable {
+    LightSourceAliasTableEntry values[];
+} lightSourceAliasTableData;
+layout(set = 9, binding = 1, scalar) buffer LightTreeNodes {
+    LightTreeNode values[];
+} lightTreeNodes;
+layout(set = 9, binding = 2, scalar) buffer LightTreeInstanceOffsets {
+    uint values[];
+} lightTreeInstanceOffsets;
+layout(set = 9, binding = 3, scalar) buffer LightTreeLeaves {
+    uint values[];
+} lightTreeLeaves;
+
+// NOTES:
+//
+// See https://nvpro-samples.github.io/vk_mini_path_tracer/extras.html#moresamples.
+// It explains not exceeding 64 samples per pixel and 32 batches to avoid timeouts and long renders.
+// We now do progressive rendering so 64 samples per pixel is still good and you can do higher number
+// of batches especially for motion blur.
+//
+// The batchRayTime is included here for correctness. However, it is used when building the acceleration
+// structures with interpolated transformations for moving objects. At the moment, it is not used for
+// anything but included for correctness. Later we could use it for time dependent features such as:
+// - Animated materials
+// - Time-varying emission
+// - Procedural textures
+// - Camera motion blur
+// - Light sampling
+// - BSDFs with time dependence
+// - Random number decorrelation
+layout(push_constant) uniform RayGenPushConstants {
+    layout(offset =  0) uvec2 resolution;
+    layout(offset =  8) uint  samplesPerPixel;
+    layout(offset = 12) uint  sampleBatch;
+    layout(offset = 16) uint  maxRayDepth;
+    layout(offset = 20) uint  meshCount;
+    layout(offset = 24) uint  imageTextureCount;
+    layout(offset = 28) uint  constantColourCount;
+    layout(offset = 32) uint  checkerTextureCount;
+    layout(offset = 36) uint  noiseTextureCount;
+    layout(offset = 40) uint  lambertianMaterialCount;
+    layout(offset = 44) uint  metalMaterialCount;
+    layout(offset = 48) uint  dielectricMaterialCount;
+    layout(offset = 52) uint  diffuseLightMaterialCount;
+    layout(offset = 56) uint  lightSourceTriangleCount;
+    layout(offset = 60) float lightSourceTotalArea;
+    layout(offset = 64) float batchRayTime;
+    layout(offset = 68) uint  samplingPattern;
+    layout(offset = 72) uint  samplingPhase;
+    layout(offset = 76) uint  reprojectHistory;
+    layout(offset = 80) uint  scalarConstantCount;
+    layout(offset = 84) uint  layerMask;
+    layout(offset = 88) uint  instanceMaterialCount;
+    layout(offset = 92) uint  estimator;
+    layout(offset = 96) uint  probePass;
+    layout(offset = 100) uint profiling;
+    layout(offset = 104) uint splatting;
+    layout(offset = 108) uint traceCaustics;
+    layout(offset = 112) uint lightTracingPass;
+    layout(offset = 116) uint transparentShadows;
+    layout(offset = 120) uint pbrMaterialCount;
+} pc;
+
+// Estimators for the light scattered at each hit. All converge to the same image, so comparing them
+// validates that a sampling strategy is unbiased.
+const uint ESTIMATOR_MIXTURE    = 0; // 50-50 mixture of material and light source sampling.
+const uint ESTIMATOR_MATERIAL   = 1; // Material sampling only. Lights are only found by chance.
+const uint ESTIMATOR_NEXT_EVENT = 2; // Shadow rays to sampled lights combined with material sampling.
+
+// Sampling patterns used to trace a subset of pixels per sample batch in interactive mode. Pixels
+// that are skipped keep their previous value so the image is reconstructed over successive batches.
+const uint SAMPLING_PATTERN_FULL         = 0; // Every pixel, every batch.
+const uint SAMPLING_PATTERN_CHECKERBOARD = 1; // Alternating checkerboard halves.
+const uint SAMPLING_PATTERN_INTERLEAVED  = 2; // One pixel of each 2x2 block.
+
+
+struct MeshMaterial {
+    uint type;
+    uint index;
+};
+
+// Surface properties at the first intersection of a camera ray used for AOVs.
+struct FirstHit {
+    vec3  albedo;
+    vec3  normal;
+    float depth;
+};
+
+struct MeshTriangle {
+    MeshVertex v0;
+    MeshVertex v1;
+    MeshVertex v2;
+};
+
+MeshMaterial unpackInstanceMaterial(const uint meshId, const uint instanceId) {
+    // Instances with a material variation override the mesh's material.
+    if (instanceId < pc.instanceMaterialCount) {
+        InstanceMaterial instanceMaterial = instanceMaterialData.values[instanceId];
+        if (instanceMaterial.materialType != MAT_TYPE_NONE) {
+            return MeshMaterial(instanceMaterial.materialType, instanceMaterial.materialIndex);
+        }
+    }
+
+    Mesh mesh = meshData.values[meshId];
+    return MeshMaterial(mesh.materialType, mesh.materialIndex);
+}
+
+MeshTriangle unpackInstanceVertex(const uint meshId, const uint primitiveId) {
+    // Note if we got here meshId >= 1 and pc.meshCount >= 1 because there was an intersection.
+    uint indexBufferOffset = 0;
+    uint vertexBufferOffset = 0;
+    for (uint id = 0; id < meshId && id < pc.meshCount; id++) {
+        indexBufferOffset += meshData.values[id].indexBufferSize;
+        vertexBufferOffset += meshData.values[id].vertexBufferSize;
+    }
+
+    uint i = indexBufferOffset + primitiveId * 3;
+    uint i0 = meshIndexData.values[i];
+    uint i1 = meshIndexData.values[i + 1];
+    uint i2 = meshIndexData.values[i + 2];
+
+    MeshVertex v0 = meshVertexData.values[vertexBufferOffset + i0];
+    MeshVertex v1 = meshVertexData.values[vertexBufferOffset + i1];
+    MeshVertex v2 = meshVertexData.values[vertexBufferOffset + i2];
+
+    return MeshTriangle(v0, v1, v2);
+}
+
+// Footprint of rays whose cone isn't tracked, such as shadow rays and light paths. Image textures are sampled at full
+// resolution for them.
+const float UV_LOD_NONE = -64.0;
+
+HitRecord getIntersection(
+        MeshTriangle hitTriangle,
+        vec2         hitAttribs,
+        mat4x3       objectToWorld,
+        mat4x3       worldToObject,
+        vec3         worldRayDirection) {
+    vec3 barycentricCoords = vec3(1.0 - hitAttribs.x - hitAttribs.y, hitAttribs.x, hitAttribs.y);
+
+    const vec3 position =
+        hitTriangle.v0.p * barycentricCoords.x +
+        hitTriangle.v1.p * barycentricCoords.y +
+        hitTriangle.v2.p * barycentricCoords.z;
+
+    const vec3 normal =
+        hitTriangle.v0.n * barycentricCoords.x +
+        hitTriangle.v1.n * barycentricCoords.y +
+        hitTriangle.v2.n * barycentricCoords.z;
+
+    const float u =
+        hitTriangle.v0.u * barycentricCoords.x +
+        hitTriangle.v1.u * barycentricCoords.y +
+        hitTriangle.v2.u * barycentricCoords.z;
+
+    const float v =
+        hitTriangle.v0.v * barycentricCoords.x +
+        hitTriangle.v1.v * barycentricCoords.y +
+        hitTriangle.v2.v * barycentricCoords.z;
+
+    const vec4 tangent =
+        hitTriangle.v0.t * barycentricCoords.x +
+        hitTriangle.v1.t * barycentricCoords.y +
+        hitTriangle.v2.t * barycentricCoords.z;
+
+    const vec3 worldSpacePosition = vec3(objectToWorld * vec4(position, 1.0));
+    const vec3 worldSpaceNormal = normalize(vec3(normal * worldToObject));
+
+    // The tangent is the direction of increasing u, interpolated from the vertex tangents and made
+    // orthogonal to the interpolated normal. Mirroring transforms flip the bitangent.
+    vec3 worldSpaceTangent = vec3(objectToWorld * vec4(tangent.xyz, 0.0));
+    worldSpaceTangent -= worldSpaceNormal * dot(worldSpaceNormal, worldSpaceTangent);
+    worldSpaceTangent = lengthSquared(worldSpaceTangent) > 1e-12
+        ? normalize(worldSpaceTangent)
+        : createOrthonormalBases(worldSpaceNormal).axis[0];
+
+    float bitangentSign = tangent.w < 0.0 ? -1.0 : 1.0;
+    if (determinant(mat3(objectToWorld)) < 0.0) {
+        bitangentSign = -bitangentSign;
+    }
+
+    bool frontFace = isFrontFace(worldRayDirection, worldSpaceNormal);
+
+    return HitRecord(
+        MeshVertex(worldSpacePosition, u, worldSpaceNormal, v, vec4(worldSpaceTangent, bitangentSign)),
+        frontFace,
+        frontFace ? worldSpaceNormal : -worldSpaceNormal,
+        worldSpaceTangent,
+        UV_LOD_NONE
+    );
+}
+
+// Returns the channel of an image texture colour selected by one of the IMAGE_CHANNEL_* values as a grey colour.
+// The colour is returned unchanged for IMAGE_CHANNEL_RGB.
+vec3 selectImageChannel(vec4 colour, uint channel) {
+    switch (channel) {
+        case IMAGE_CHANNEL_R:         return vec3(colour.r);
+        case IMAGE_CHANNEL_G:         return vec3(colour.g);
+        case IMAGE_CHANNEL_B:         return vec3(colour.b);
+        case IMAGE_CHANNEL_A:         return vec3(colour.a);
+        case IMAGE_CHANNEL_LUMINANCE: return vec3(dot(colour.rgb, vec3(0.2126, 0.7152, 0.0722)));
+        default:                      return colour.rgb;
+    }
+}
+
+// Samples an image texture at the mip level matching a footprint in texture coordinates, see getUvLod().
+vec4 sampleImageTexture(uint index, vec2 uv, float uvLod) {
+    ivec2 size = textureSize(nonuniformEXT(sampler2D(imageTextures[index], imageTextureSamplers[index])), 0);
+    float lod = uvLod + 0.5 * log2(float(size.x) * float(size.y));
+    return textureLod(nonuniformEXT(sampler2D(imageTextures[index], imageTextureSamplers[index])), uv, lod);
+}
+
+// Returns the random offset of the copy of a stochastically tiled image at a vertex of the triangle grid.
+vec2 getStochasticTilingOffset(ivec2 vertex) {
+    return fract(sin(vec2(vertex) * mat2(127.1, 311.7, 269.5, 183.3)) * 43758.5453);
+}
+
+// Samples a stochastically tiled image texture. Randomly offset copies of the image are blended across a grid of
+// triangles so it doesn't visibly repeat. The image holds values transformed to a Gaussian distribution, which
+// blending preserves once the variance lost by averaging is restored, and the lookup table maps them back to the
+// original values. See https://eheitzresearch.wordpress.com/738-2/ and
+// https://eheitzresearch.wordpress.com/722-2/ (Procedural Stochastic Textures by Tiling and Blending).
+vec4 sampleStochasticImageTexture(uint index, uint lutIndex, vec2 uv, float uvLod) {
+    // Skew the grid of equilateral triangles to a grid of squares split along their diagonals. Each triangle's edges
+    // are about a third of the image across.
+    vec2 skewed = 2.0 * sqrt(3.0) * uv * mat2(1.0, 0.0, -0.57735027, 1.15470054);
+    ivec2 base = ivec2(floor(skewed));
+    vec3 barycentrics = vec3(fract(skewed), 0.0);
+    barycentrics.z = 1.0 - barycentrics.x - barycentrics.y;
+
+    vec3 weights;
+    ivec2 vertices[3];
+    if (barycentrics.z > 0.0) {
+        weights = vec3(barycentrics.z, barycentrics.y, barycentrics.x);
+        vertices = ivec2[3](base, base + ivec2(0, 1), base + ivec2(1, 0));
+    } else {
+        weights = vec3(-barycentrics.z, 1.0 - barycentrics.y, 1.0 - barycentrics.x);
+        vertices = ivec2[3](base + ivec2(1, 1), base + ivec2(1, 0), base + ivec2(0, 1));
+    }
+
+    vec4 gaussian = vec4(0.0);
+    for (int i = 0; i < 3; i++) {
+        gaussian += weights[i] * (sampleImageTexture(index, uv + getStochasticTilingOffset(vertices[i]), uvLod) - 0.5);
+    }
+    gaussian = clamp(gaussian / length(weights) + 0.5, 0.0, 1.0);
+
+    // Map each channel back through the lookup table, interpolating between its entries.
+    vec4 colour;
+    for (int c = 0; c < 4; c++) {
+        float x = gaussian[c] * float(STOCHASTIC_TILING_LUT_SIZE - 1);
+        int i = min(int(x), int(STOCHASTIC_TILING_LUT_SIZE) - 2);
+        vec4 lower = texelFetch(
+                nonuniformEXT(sampler2D(imageTextures[lutIndex], imageTextureSamplers[lutIndex])),
+                ivec2(i, 0),
+                0
+                );
+        vec4 upper = texelFetch(
+                nonuniformEXT(sampler2D(imageTextures[lutIndex], imageTextureSamplers[lutIndex])),
+                ivec2(i + 1, 0),
+                0
+                );
+        colour[c] = mix(lower[c], upper[c], x - float(i));
+    }
+    return colour;
+}
+
+// This only handles constant colour, scalar, image and noise textures. Other textures like checker texture can
+// reference these "basic" textures for their own properties. Scalar textures are returned as a grey colour. Image
+// textures are sampled at the mip level matching the footprint uvLod, see getUvLod().
+vec3 getBasicTextureValue(MaterialPropertyValue matPropValue, MeshVertex vertex, float uvLod) {
+    vec3 colour = vec3(0.0);
+
+    switch (matPropValue.propValueType) {
+        case MAT_PROP_VALUE_TYPE_RGB:
+            if (matPropValue.index >= 0 && matPropValue.index < pc.constantColourCount) {
+                colour = constantColour.values[matPropValue.index];
+            }
+            break;
+
+        case MAT_PROP_VALUE_TYPE_SCALAR:
+            if (matPropValue.index >= 0 && matPropValue.index < pc.scalarConstantCount) {
+                colour = vec3(scalarConstant.values[matPropValue.index]);
+            }
+            break;
+
+        case MAT_PROP_VALUE_TYPE_IMAGE:
+            if (matPropValue.index >= 0 && matPropValue.index < pc.imageTextureCount) {
+                vec2 uv = vec2(vertex.u, vertex.v);
+                uint lutIndex = imageTextureStochasticTiling.values[matPropValue.index];
+                vec4 texel = lutIndex == STOCHASTIC_TILING_NONE
+                        ? sampleImageTexture(matPropValue.index, uv, uvLod)
+                        : sampleStochasticImageTexture(matPropValue.index, lutIndex, uv, uvLod);
+                colour = selectImageChannel(texel, imageTextureChannel.values[matPropValue.index]);
+            }
+            break;
+
+        case MAT_PROP_VALUE_TYPE_NOISE:
+            if (matPropValue.index >= 0 && matPropValue.index < pc.noiseTextureCount) {
+                float scale = noiseTexture.values[matPropValue.index].scale;
+                colour = vec3(0.5, 0.5, 0.5) * (1.0 + sin(scale * vertex.p.z + 10 * turbulence(vertex.p, 7)));
+            }
+            break;
+    }
+
+    return colour;
+}
+
+vec3 getMaterialPropertyValue(MaterialPropertyValue matPropValue, MeshVertex vertex, float uvLod) {
+    vec3 colour = vec3(0.0);
+
+    switch (matPropValue.propValueType) {
+        case MAT_PROP_VALUE_TYPE_RGB:
+        case MAT_PROP_VALUE_TYPE_SCALAR:
+        case MAT_PROP_VALUE_TYPE_IMAGE:
+        case MAT_PROP_VALUE_TYPE_NOISE:
+            colour = getBasicTextureValue(matPropValue, vertex, uvLod);
+            break;
+
+        case MAT_PROP_VALUE_TYPE_CHECKER:
+            if (matPropValue.index >= 0 && matPropValue.index < pc.checkerTextureCount) {
+                CheckerTexture texture = checkerTexture.values[matPropValue.index];
+
+                float invScale = 1.0 / texture.scale;
+                int xInteger = int(floor(invScale * vertex.p.x));
+                int yInteger = int(floor(invScale * vertex.p.y));
+                int zInteger = int(floor(invScale * vertex.p.z));
+
+                bool isEven = (xInteger + yInteger + zInteger) % 2 == 0;
+
+                colour = isEven 
+                    ? getBasicTextureValue(texture.even, vertex, uvLod)
+                    : getBasicTextureValue(texture.odd, vertex, uvLod);
+            }
+            break;
+    }
+
+    return colour;
+}
+
+// Perturbs the shading normal of a hit by its material's normal map, if it has one. Normal maps
+// hold tangent space normals mapped from [-1, 1] to [0, 1], with x along the tangent, y along the
+// bitangent and z along the interpolated normal. The hit keeps the side it was hit from.
+void applyNormalMap(MeshMaterial material, inout HitRecord rec) {
+    MaterialPropertyValue normalMap;
+    if (material.type == MAT_TYPE_LAMBERTIAN && material.index < pc.lambertianMaterialCount) {
+        normalMap = lambertianMaterial.values[material.index].normalMap;
+    } else if (material.type == MAT_TYPE_METAL && material.index < pc.metalMaterialCount) {
+        normalMap = metalMaterial.values[material.index].normalMap;
+    } else if (material.type == MAT_TYPE_PBR && material.index < pc.pbrMaterialCount) {
+        normalMap = pbrMaterial.values[material.index].normalMap;
+    } else {
+        return;
+    }
+    if (normalMap.propValueType == MAT_PROP_VALUE_TYPE_NONE) {
+        return;
+    }
+
+    vec3 n = rec.meshVertex.n;
+    vec3 t = rec.meshVertex.t.xyz;
+    vec3 b = rec.meshVertex.t.w * cross(n, t);
+    vec3 tangentSpaceNormal = getMaterialPropertyValue(normalMap, rec.meshVertex, rec.uvLod) * 2.0 - 1.0;
+    vec3 normal = tangentSpaceNormal.x * t + tangentSpaceNormal.y * b + tangentSpaceNormal.z * n;
+    if (lengthSquared(normal) <= 1e-12) {
+        return;
+    }
+    normal = normalize(normal);
+
+    vec3 tangent = t - normal * dot(normal, t);
+    tangent = lengthSquared(tangent) > 1e-12 ? normalize(tangent) : createOrthonormalBases(normal).axis[0];
+
+    rec.meshVertex.n = normal;
+    rec.meshVertex.t.xyz = tangent;
+    rec.normal = rec.isFrontFace ? normal : -normal;
+    rec.tangent = tangent;
+}
+
+// Use Schlick's approximation for reflectance.
+float schlickReflectance(float cosine, float refractionIndex) {
+    float r0 = (1.0 - refractionIndex) / (1.0 + refractionIndex);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow((1.0 - cosine), 5);
+}
+
+// Returns the world space geometric normal of a triangle.
+vec3 getWorldSpaceFaceNormal(MeshTriangle triangle, mat4x3 objectToWorld) {
+    vec3 p0 = vec3(objectToWorld * vec4(triangle.v0.p, 1.0));
+    vec3 p1 = vec3(objectToWorld * vec4(triangle.v1.p, 1.0));
+    vec3 p2 = vec3(objectToWorld * vec4(triangle.v2.p, 1.0));
+    return normalize(cross(p1 - p0, p2 - p0));
+}
+
+// Returns a point sampled uniformly on the triangle of a light source alias table entry along with
+// the triangle's area. The sample's density still has to be set by the caller.
+LightSample sampleLightTriangle(inout uint rngState, uint entryIndex, out float area) {
+    // The triangle is transformed by its light's instance.
+    LightSourceAliasTableEntry entry = lightSourceAliasTableData.values[entryIndex];
+    MeshTriangle light = unpackInstanceVertex(entry.meshId, entry.primitiveId);
+
+    vec2 barycentrics = sampleTriangleBarycentrics(rngState);
+    vec3 p0 = vec3(entry.objectToWorld * vec4(light.v0.p, 1.0));
+    vec3 p1 = vec3(entry.objectToWorld * vec4(light.v1.p, 1.0));
+    vec3 p2 = vec3(entry.objectToWorld * vec4(light.v2.p, 1.0));
+
+    vec3 position = p0 + barycentrics.x * (p1 - p0) + barycentrics.y * (p2 - p0);
+    vec3 normal   = getWorldSpaceFaceNormal(light, entry.objectToWorld);
+    area = 0.5 * length(cross(p1 - p0, p2 - p0));
+
+    return LightSample(position, normal, entryIndex, barycentrics, 0.0);
+}
+
+// Samples a point uniformly by area over all light sources.
+LightSample sampleLightSources(inout uint rngState) {
+    if (pc.lightSourceTriangleCount == 0) {
+        return LightSample(vec3(0.0), vec3(0.0), 0u, vec2(0.0), 0.0);
+    }
+
+    float u1 = randomFloat(rngState);
+    float u2 = randomFloat(rngState);
+
+    uint i = min(uint(u1 * pc.lightSourceTriangleCount), pc.lightSourceTriangleCount - 1);
+
+    uint triangleIndex;
+    if (u2 < lightSourceAliasTableData.values[i].probability) {
+        triangleIndex = i;
+    } else {
+        triangleIndex = lightSourceAliasTableData.values[i].alias;
+    }
+
+    float area;
+    LightSample lightSample = sampleLightTriangle(rngState, triangleIndex, area);
+    lightSample.areaPdf = 1.0 / pc.lightSourceTotalArea;
+    return lightSample;
+}
+
+// Returns an estimate of the light the triangles below a light tree node contribute to a point,
+// based on their power, distance and orientation. The estimate is conservative about orientation so
+// triangles that could light the point never get an importance of 0.
+// See https://pbr-book.org/4ed/Light_Sources/Light_Sampling.
+float getLightTreeImportance(LightTreeNode node, vec3 point) {
+    vec3 centre = 0.5 * (node.boundsMin + node.boundsMax);
+    vec3 fromCentre = point - centre;
+    float distanceSquared = dot(fromCentre, fromCentre);
+    float radiusSquared = 0.25 * lengthSquared(node.boundsMax - node.boundsMin);
+
+    // Points inside the bounds could be lit by any of the triangles from any direction. The
+    // distance is clamped to the radius of the bounds so nearby nodes don't get unbounded weights.
+    if (distanceSquared <= radiusSquared) {
+        return node.power / radiusSquared;
+    }
+
+    // The angle between the point and the normal cone, less the angles the cone and the bounds
+    // subtend, bounds the angle at which any triangle below the node sees the point. Triangles
+    // only emit light from their front face so nodes beyond 90 degrees contribute nothing.
+    float thetaW = acos(clamp(dot(node.axis, fromCentre) / sqrt(distanceSquared), -1.0, 1.0));
+    float thetaO = acos(clamp(node.cosTheta, -1.0, 1.0));
+    float thetaB = asin(sqrt(radiusSquared / distanceSquared));
+    float theta  = max(0.0, thetaW - thetaO - thetaB);
+    if (theta >= PI_OVER_2) {
+        return 0.0;
+    }
+    return node.power * cos(theta) / distanceSquared;
+}
+
+// Returns the probability of choosing the first child of a light tree node from a point.
+float getLightTreeLeftProbability(LightTreeNode node, vec3 point) {
+    float left  = getLightTreeImportance(lightTreeNodes.values[node.left], point);
+    float right = getLightTreeImportance(lightTreeNodes.values[node.right], point);
+    return left + right > 0.0 ? left / (left + right) : -1.0;
+}
+
+// Samples a point on the light sources for next event estimation at a point. A triangle is chosen
+// by descending the light tree, choosing children in proportion to their importance to the point,
+// and the position is sampled uniformly on it.
+LightSample sampleLightTree(inout uint rngState, vec3 point) {
+    if (pc.lightSourceTriangleCount == 0) {
+        return LightSample(vec3(0.0), vec3(0.0), 0u, vec2(0.0), 0.0);
+    }
+
+    // The random number is rescaled after each choice so it is reused all the way down.
+    float u = randomFloat(rngState);
+    float pmf = 1.0;
+    uint index = 0;
+    LightTreeNode node = lightTreeNodes.values[0];
+    while (node.right != LIGHT_TREE_NONE) {
+        float pLeft = getLightTreeLeftProbability(node, point);
+        if (pLeft < 0.0) {
+            // No triangle below the node can light the point.
+            return LightSample(vec3(0.0), vec3(0.0), 0u, vec2(0.0), 0.0);
+        }
+
+        if (u < pLeft) {
+            u = min(u / pLeft, ONE_MINUS_EPSILON);
+            pmf *= pLeft;
+            index = node.left;
+        } else {
+            u = min((u - pLeft) / (1.0 - pLeft), ONE_MINUS_EPSILON);
+            pmf *= 1.0 - pLeft;
+            index = node.right;
+        }
+        node = lightTreeNodes.values[index];
+    }
+
+    float area;
+    LightSample lightSample = sampleLightTriangle(rngState, node.left, area);
+    lightSample.areaPdf = area > 0.0 ? pmf / area : 0.0;
+    return lightSample;
+}
+
+// Returns the density with respect to area with which sampleLightTree samples a point on a light
+// source triangle with the given world space area from a point. This is 0 for triangles that aren't
+// part of a light source.
+float getLightTreeAreaPdf(vec3 point, uint instanceId, uint primitiveId, float area) {
+    uint offset = lightTreeInstanceOffsets.values[instanceId];
+    if (offset == LIGHT_TREE_NONE || area <= 0.0) {
+        return 0.0;
+    }
+    uint index = lightTreeLeaves.values[offset + primitiveId];
+    if (index == LIGHT_TREE_NONE) {
+        return 0.0;
+    }
+
+    // Walk up to the root multiplying the probabilities of the choices leading to the leaf.
+    float pmf = 1.0;
+    uint parent = lightTreeNodes.values[index].parent;
+    while (parent != LIGHT_TREE_NONE) {
+        LightTreeNode node = lightTreeNodes.values[parent];
+        float pLeft = getLightTreeLeftProbability(node, point);
+        if (pLeft < 0.0) {
+            return 0.0;
+        }
+        pmf *= node.left == index ? pLeft : 1.0 - pLeft;
+        index = parent;
+        parent = node.parent;
+    }
+    return pmf / area;
+}
+
+// Returns the world space area of a triangle.
+float getWorldSpaceArea(MeshTriangle triangle, mat4x3 objectToWorld) {
+    vec3 p0 = vec3(objectToWorld * vec4(triangle.v0.p, 1.0));
+    vec3 p1 = vec3(objectToWorld * vec4(triangle.v1.p, 1.0));
+    vec3 p2 = vec3(objectToWorld * vec4(triangle.v2.p, 1.0));
+    return 0.5 * length(cross(p1 - p0, p2 - p0));
+}
+
+// Returns the angle by which the ray cone through a pixel spreads, from the camera's vertical field of view.
+float getPixelSpreadAngle() {
+    return 2.0 * abs(camera.projInverse[1][1]) / float(pc.resolution.y);
+}
+
+// Returns log2 of the footprint in texture coordinates of a ray cone of the given width hitting a triangle. Adding
+// log2 of an image's size gives the mip level to sample. See "Improved Shader and Texture Level of Detail Using Ray
+// Cones" (Akenine-Moller et al. 2021).
+float getUvLod(MeshTriangle triangle, mat4x3 objectToWorld, vec3 worldRayDirection, float coneWidth) {
+    vec2 deltaUv1 = vec2(triangle.v1.u - triangle.v0.u, triangle.v1.v - triangle.v0.v);
+    vec2 deltaUv2 = vec2(triangle.v2.u - triangle.v0.u, triangle.v2.v - triangle.v0.v);
+    float uvArea = 0.5 * abs(deltaUv1.x * deltaUv2.y - deltaUv2.x * deltaUv1.y);
+    float worldArea = getWorldSpaceArea(triangle, objectToWorld);
+    float cosine = abs(dot(getWorldSpaceFaceNormal(triangle, objectToWorld), normalize(worldRayDirection)));
+
+    if (coneWidth <= 0.0 || uvArea <= 0.0 || worldArea <= 0.0 || cosine <= 0.0) {
+        return UV_LOD_NONE;
+    }
+    return 0.5 * log2(uvArea / worldArea) + log2(coneWidth / cosine);
+}
+
+// Returns the solid angle density of sampling a point on the light sources with the given geometric
+// normal from the origin, given the density of sampling it with respect to area.
+float getLightPdf(vec3 origin, vec3 position, vec3 normal, float areaPdf) {
+    vec3 direction = position - origin;
+    float distanceSquared = dot(direction, direction);
+    float cosTheta = abs(dot(normal, direction)) / sqrt(distanceSquared);
+    if (cosTheta <= 0.0 || areaPdf <= 0.0) {
+        return 0.0;
+    }
+    return areaPdf * distanceSquared / cosTheta;
+}
+
+// Returns the weight of a sample from a strategy with density pdfA combined with a strategy with
+// density pdfB by multiple importance sampling with the power heuristic.
+float powerHeuristic(float pdfA, float pdfB) {
+    float a = pdfA * pdfA;
+    float b = pdfB * pdfB;
+    return a + b > 0.0 ? a / (a + b) : 0.0;
+}
+
+float getPdfValue(uint pdfType, vec3 direction, HitRecord rec, LightSample lightSample) {
+    float cosTheta;
+    switch (pdfType) {
+        case SPHERE_PDF:
+            return 1.0 / (4.0 * PI);
+        case COSINE_PDF:
+            cosTheta = dot(normalize(direction), rec.normal);
+            return max(0.0, cosTheta / PI);
+        case LIGHT_PDF:
+            float distanceSquared = dot(direction, direction);
+            cosTheta = abs(dot(lightSample.normal, -normalize(direction)));
+            if (cosTheta <= 0.0) {
+                return 0.0;
+            }
+            return (distanceSquared / cosTheta) * (1.0 / pc.lightSourceTotalArea);
+        default:
+            0.0;
+    }
+}
+
+vec3 genScatterDirection(inout uint rngState, uint pdfType, HitRecord rec, LightSample lightSample) {
+    switch (pdfType) {
+        case SPHERE_PDF:
+            return randomUnitVec3(rngState);
+        case COSINE_PDF:
+            ONB onb = createOrthonormalBases(rec.normal);
+            return onbTransform(onb, randomVec3CosineDirection(rngState));
+        case LIGHT_PDF:
+            return lightSample.position - rec.meshVertex.p;
+        default:
+            return vec3(0.0);
+    }
+}
+
+// Returns true if the scene has light sources to sample.
+bool hasLightSources() {
+    return pc.lightSourceTriangleCount > 0 && pc.lightSourceTotalArea > 0.0;
+}
+
+// Returns true if light sources are sampled along with the material.
+bool isSamplingLights() {
+    return pc.estimator == ESTIMATOR_MIXTURE && hasLightSources();
+}
+
+// Returns true if light sources are sampled with shadow rays at each hit.
+bool isNextEventEstimation() {
+    return pc.estimator == ESTIMATOR_NEXT_EVENT && hasLightSources();
+}
+
+uint chooseMixturePdf(inout uint rngState, uint matPdfType) {
+    // No lights or material sampling only, fallback to material PDF.
+    if (!isSamplingLights()) {
+        return matPdfType;
+    }
+
+    // 50-50 mixture.
+    float r = randomFloat(rngState);
+    return (r < 0.5) ? LIGHT_PDF : matPdfType;
+}
+
+// Refractive index of clearcoat layers, which is typical of lacquers and varnishes.
+const float CLEARCOAT_REFRACTION_INDEX = 1.5;
+
+// Reflects off a material's clearcoat layer with the probability of the layer's reflectance.
+// Returns false if the ray passes through the layer to the material below instead, or if there is
+// no layer.
+bool clearcoatScatter(inout uint rngState, Clearcoat clearcoat, HitRecord rec, vec3 worldRayDirection, float time, out ScatterRecord srec) {
+    srec = initScatterRecord();
+
+    if (clearcoat.intensity <= 0.0 || !rec.isFrontFace) {
+        return false;
+    }
+
+    vec3 unitDirection = normalize(worldRayDirection);
+    float cosTheta = min(dot(-unitDirection, rec.normal), 1.0);
+    float reflectance = clearcoat.intensity * schlickReflectance(cosTheta, CLEARCOAT_REFRACTION_INDEX);
+    if (randomFloat(rngState) >= reflectance) {
+        return false;
+    }
+
+    vec3 reflectedDirection = reflect(unitDirection, rec.normal) + clearcoat.roughness * randomUnitVec3(rngState);
+
+    srec.attenuation          = vec3(1.0);
+    srec.isScattered          = dot(reflectedDirection, rec.normal) > 0;
+    srec.matPdfType           = NO_PDF;
+    srec.skipPdf              = true;
+    srec.skipPdfRay.origin    = rec.meshVertex.p;
+    srec.skipPdfRay.direction = reflectedDirection;
+    srec.skipPdfRay.time      = time;
+    return true;
+}
+
+// Returns the tint of light that passed through a material's clearcoat layer.
+vec3 getClearcoatTint(Clearcoat clearcoat, HitRecord rec) {
+    return rec.isFrontFace ? mix(vec3(1.0), clearcoat.tint, clearcoat.intensity) : vec3(1.0);
+}
+
+ScatterRecord lambertianMaterialScatter(inout uint rngState, uint materialIndex, HitRecord rec, vec3 worldRayDirection, float time) {
+    ScatterRecord srec = initScatterRecord();
+
+    if (materialIndex >= 0 && materialIndex < pc.lambertianMaterialCount) {
+        LambertianMaterial material = lambertianMaterial.values[materialIndex];
+        if (clearcoatScatter(rngState, material.clearcoat, rec, worldRayDirection, time, srec)) {
+            return srec;
+        }
+
+        vec3 albedo = getMaterialPropertyValue(material.albedo, rec.meshVertex, rec.uvLod);
+
+        srec.attenuation = albedo * getClearcoatTint(material.clearcoat, rec);
+        srec.isScattered = true;
+        srec.skipPdf     = false;
+        srec.matPdfType  = COSINE_PDF;
+    }
+
+    return srec;
+}
+
+ScatterRecord metalMaterialScatter(inout uint rngState, uint materialIndex, HitRecord rec, vec3 worldRayDirection, float time) {
+    ScatterRecord srec = initScatterRecord();
+
+    if (materialIndex >= 0 && materialIndex < pc.metalMaterialCount) {
+        MetalMaterial material = metalMaterial.values[materialIndex];
+        if (clearcoatScatter(rngState, material.clearcoat, rec, worldRayDirection, time, srec)) {
+            return srec;
+        }
+
+        vec3 albedo = getMaterialPropertyValue(material.albedo, rec.meshVertex, rec.uvLod);
+        float fuzz = getMaterialPropertyValue(material.fuzz, rec.meshVertex, rec.uvLod).r;
+        float fuzzV = getMaterialPropertyValue(material.fuzzV, rec.meshVertex, rec.uvLod).r;
+
+        vec3 reflectedDirection = reflect(worldRayDirection, rec.normal);
+
+        // Anisotropic roughness scales the perturbation of the reflection differently along the
+        // rotated tangent and bitangent. Along the normal it uses the mean so the same roughness
+        // in both directions matches isotropic roughness.
+        vec3 perturbation = randomUnitVec3(rngState);
+        if (fuzz != fuzzV) {
+            vec3 bitangent = cross(rec.normal, rec.tangent);
+            vec3 t = cos(material.fuzzRotation) * rec.tangent + sin(material.fuzzRotation) * bitangent;
+            vec3 b = cross(rec.normal, t);
+            perturbation =
+                fuzz  * dot(perturbation, t) * t +
+                fuzzV * dot(perturbation, b) * b +
+                0.5 * (fuzz + fuzzV) * dot(perturbation, rec.normal) * rec.normal;
+        } else {
+            perturbation *= fuzz;
+        }
+
+        srec.attenuation          = albedo * getClearcoatTint(material.clearcoat, rec);
+        srec.isScattered          = dot(reflectedDirection, rec.normal) > 0;
+        srec.matPdfType           = NO_PDF;
+        srec.skipPdf              = true;
+        srec.skipPdfRay.origin    = rec.meshVertex.p;
+        srec.skipPdfRay.direction = normalize(reflectedDirection) + perturbation;
+        srec.skipPdfRay.time      = time;
+    }
+
+    return srec;
+}
+
+// Dielectric media the current path is inside of, for dielectrics with a priority. Entries are
+// dielectric material indices. Where media overlap, the ray travels through the one with the
+// highest priority and surfaces of the others are ignored. See "Simple Nested Dielectrics in Ray
+// Traced Images" by Charles M. Schmidt and Brian Budge.
+const uint MAX_NESTED_MEDIA = 4;
+uint nestedMediaCount = 0;
+uint nestedMedia[MAX_NESTED_MEDIA];
+
+// Returns the position in the media stack of the medium with the highest priority, ignoring the
+// one at position `excluded`, or -1 if there is none.
+int getCurrentMedium(int excluded) {
+    int current = -1;
+    for (int i = 0; i < int(nestedMediaCount); i++) {
+        if (i != excluded && (current < 0 ||
+                dielectricMaterial.values[nestedMedia[i]].priority >
+                dielectricMaterial.values[nestedMedia[current]].priority)) {
+            current = i;
+        }
+    }
+    return current;
+}
+
+// Returns the position of a dielectric material in the media stack, or -1 if the ray isn't inside it.
+int findMedium(uint materialIndex) {
+    for (int i = int(nestedMediaCount) - 1; i >= 0; i--) {
+        if (nestedMedia[i] == materialIndex) {
+            return i;
+        }
+    }
+    return -1;
+}
+
+// Adds a medium the ray enters to the media stack. Media past the maximum nesting are ignored.
+void pushMedium(uint materialIndex) {
+    if (nestedMediaCount < MAX_NESTED_MEDIA) {
+        nestedMedia[nestedMediaCount++] = materialIndex;
+    }
+}
+
+// Removes a medium the ray leaves from the media stack.
+void removeMedium(int position) {
+    if (position < 0) {
+        return;
+    }
+    for (uint i = uint(position); i + 1 < nestedMediaCount; i++) {
+        nestedMedia[i] = nestedMedia[i + 1];
+    }
+    nestedMediaCount--;
+}
+
+ScatterRecord dielectricMaterialScatter(inout uint rngState, uint materialIndex, HitRecord rec, vec3 worldRayDirection, float time) {
+    ScatterRecord srec = initScatterRecord();
+
+    if (materialIndex >= 0 && materialIndex < pc.dielectricMaterialCount) {
+        DielectricMaterial material = dielectricMaterial.values[materialIndex];
+        float refractionIndex = material.refractionIndex;
+
+        vec3 attenuation = vec3(1.0);
+
+        vec3 unitDirection = normalize(worldRayDirection);
+
+        float ri = rec.isFrontFace ? (1.0 / refractionIndex) : refractionIndex;
+
+        // Nested dielectrics use the refraction index of the medium on the other side of the
+        // surface, which is the one with the highest priority the ray is in apart from this one.
+        int inside = findMedium(materialIndex);
+        if (material.priority != 0) {
+            int outside = getCurrentMedium(rec.isFrontFace ? -1 : inside);
+            DielectricMaterial outsideMaterial = outside < 0
+                ? DielectricMaterial(1.0, 0u, NO_CLEARCOAT)
+                : dielectricMaterial.values[nestedMedia[outside]];
+
+            // Surfaces within a medium with a higher priority aren't interfaces so the ray passes
+            // straight through, but whether it is inside this medium is still tracked.
+            if (outsideMaterial.priority > material.priority) {
+                if (rec.isFrontFace) {
+                    pushMedium(materialIndex);
+                } else {
+                    removeMedium(inside);
+                }
+
+                srec.attenuation          = attenuation;
+                srec.isScattered          = true;
+                srec.matPdfType           = NO_PDF;
+                srec.skipPdf              = true;
+                srec.skipPdfRay.origin    = rec.meshVertex.p;
+                srec.skipPdfRay.direction = unitDirection;
+                srec.skipPdfRay.time      = time;
+                return srec;
+            }
+
+            ri = rec.isFrontFace
+                ? outsideMaterial.refractionIndex / refractionIndex
+                : refractionIndex / outsideMaterial.refractionIndex;
+        }
+
+        if (clearcoatScatter(rngState, material.clearcoat, rec, worldRayDirection, time, srec)) {
+            return srec;
+        }
+
+        float cosTheta = min(dot(-unitDirection, rec.normal), 1.0);
+        float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+
+        bool cannotRefract = ri * sinTheta > 1.0; 
+        cannotRefract = cannotRefract || schlickReflectance(cosTheta, ri) > randomFloat(rngState);
+
+        vec3 refractedDirection = cannotRefract
+            ? reflect(unitDirection, rec.normal) // Total internal reflection.
+            : refract(unitDirection, rec.normal, ri);
+
+        // Track the media of nested dielectrics the ray enters or leaves.
+        if (material.priority != 0 && !cannotRefract) {
+            if (rec.isFrontFace) {
+                pushMedium(materialIndex);
+            } else {
+                removeMedium(inside);
+            }
+        }
+
+        srec.attenuation          = attenuation * getClearcoatTint(material.clearcoat, rec);
+        srec.isScattered          = true;
+        srec.matPdfType           = NO_PDF;
+        srec.skipPdf              = true;
+        srec.skipPdfRay.origin    = rec.meshVertex.p;
+        srec.skipPdfRay.direction = refractedDirection;
+        srec.skipPdfRay.time      = time;
+    }
+
+    return srec;
+}
+
+EmissionRecord diffuseLightMaterialEmission(inout uint rngState, uint materialIndex, HitRecord rec) {
+    EmissionRecord erec =  initEmissionRecord();
+
+    if (materialIndex >= 0 && materialIndex < pc.diffuseLightMaterialCount) {
+        DiffuseLightMaterial material = diffuseLightMaterial.values[materialIndex];
+        if (rec.isFrontFace) {
+            erec.emissionColour = getMaterialPropertyValue(material.emit, rec.meshVertex, rec.uvLod) * material.tint;
+        }
+    }
+
+    return erec;
+}
+
+// Smallest width of the GGX distribution, which keeps mirror-like PBR materials from dividing by 0.
+const float MIN_GGX_ALPHA = 1e-3;
+
+// Samples a normal of an anisotropic GGX distribution that is visible from a view direction, both in
+// the local frame where the surface normal is z. See "Sampling the GGX Distribution of Visible
+// Normals" (Heitz 2018).
+vec3 sampleGgxVisibleNormal(inout uint rngState, vec3 view, float alphaX, float alphaY) {
+    // Stretch the view so the distribution becomes a hemisphere.
+    vec3 stretched = normalize(vec3(alphaX * view.x, alphaY * view.y, view.z));
+    float lengthSq = stretched.x * stretched.x + stretched.y * stretched.y;
+    vec3 t1 = lengthSq > 0.0 ? vec3(-stretched.y, stretched.x, 0.0) * inversesqrt(lengthSq) : vec3(1.0, 0.0, 0.0);
+    vec3 t2 = cross(stretched, t1);
+
+    // Sample the projected area of the hemisphere seen from the view direction.
+    float r = sqrt(randomFloat(rngState));
+    float phi = 2.0 * PI * randomFloat(rngState);
+    float p1 = r * cos(phi);
+    float p2 = r * sin(phi);
+    float s = 0.5 * (1.0 + stretched.z);
+    p2 = (1.0 - s) * sqrt(1.0 - p1 * p1) + s * p2;
+    vec3 normal = p1 * t1 + p2 * t2 + sqrt(max(0.0, 1.0 - p1 * p1 - p2 * p2)) * stretched;
+
+    // Unstretch the normal.
+    return normalize(vec3(alphaX * normal.x, alphaY * normal.y, max(0.0, normal.z)));
+}
+
+// Returns the Smith Lambda function of an anisotropic GGX distribution for a direction in the local
+// frame, which gives the fraction of the microfacets masked from it.
+float getGgxLambda(vec3 direction, float alphaX, float alphaY) {
+    float cos2Theta = max(direction.z * direction.z, 1e-12);
+    float alpha2Tan2Theta = (alphaX * alphaX * direction.x * direction.x + alphaY * alphaY * direction.y * direction.y) / cos2Theta;
+    return 0.5 * (sqrt(1.0 + alpha2Tan2Theta) - 1.0);
+}
+
+// Returns the Fresnel reflectance of a metal with the given reflectance at normal incidence using
+// Schlick's approximation.
+vec3 schlickFresnel(vec3 f0, float cosine) {
+    return f0 + (1.0 - f0) * pow(1.0 - cosine, 5.0);
+}
+
+// Returns the probability that light arriving from a direction at a PBR material is reflected by
+// its specular layer. The rest is scattered diffusely.
+float getPbrSpecularProbability(float metallic, float ior, float cosine) {
+    return mix(schlickReflectance(cosine, ior), 1.0, metallic);
+}
+
+// Scatters off a PBR material. The specular layer is chosen with the probability it reflects the
+// incident light and samples visible GGX normals, so the weight of its rays is the Fresnel
+// reflectance times the masking of the reflected direction. Otherwise the ray scatters diffusely,
+// and since light reaches the diffuse layer with the probability of doing so, its weight is just
+// the base colour.
+ScatterRecord pbrMaterialScatter(inout uint rngState, uint materialIndex, HitRecord rec, vec3 worldRayDirection, float time) {
+    ScatterRecord srec = initScatterRecord();
+
+    if (materialIndex >= pc.pbrMaterialCount) {
+        return srec;
+    }
+
+    PbrMaterial material = pbrMaterial.values[materialIndex];
+    vec3 baseColour = getMaterialPropertyValue(material.baseColour, rec.meshVertex, rec.uvLod);
+    float metallic = clamp(getMaterialPropertyValue(material.metallic, rec.meshVertex, rec.uvLod).r, 0.0, 1.0);
+    float roughness = clamp(getMaterialPropertyValue(material.roughness, rec.meshVertex, rec.uvLod).r, 0.0, 1.0);
+
+    // Local frame with the anisotropy direction along x.
+    vec3 bitangent = cross(rec.normal, rec.tangent);
+    vec3 t = cos(material.anisotropyRotation) * rec.tangent + sin(material.anisotropyRotation) * bitangent;
+    vec3 b = cross(rec.normal, t);
+    vec3 view = -normalize(worldRayDirection);
+    vec3 localView = vec3(dot(view, t), dot(view, b), max(dot(view, rec.normal), 1e-4));
+
+    float specularProbability = getPbrSpecularProbability(metallic, material.ior, localView.z);
+    if (randomFloat(rngState) < specularProbability) {
+        float alpha = max(roughness * roughness, MIN_GGX_ALPHA);
+        float alphaX = mix(alpha, 1.0, material.anisotropy * material.anisotropy);
+        float alphaY = alpha;
+
+        vec3 microfacetNormal = sampleGgxVisibleNormal(rngState, localView, alphaX, alphaY);
+        vec3 localDirection = reflect(-localView, microfacetNormal);
+        if (localDirection.z <= 0.0) {
+            // Reflected into the surface, so the light is absorbed.
+            return srec;
+        }
+
+        float cosine = clamp(dot(localView, microfacetNormal), 0.0, 1.0);
+        vec3 fresnel = mix(vec3(schlickReflectance(cosine, material.ior)), schlickFresnel(baseColour, cosine), metallic);
+        float viewLambda = getGgxLambda(localView, alphaX, alphaY);
+        float masking = (1.0 + viewLambda) / (1.0 + viewLambda + getGgxLambda(localDirection, alphaX, alphaY));
+
+        srec.attenuation          = fresnel * masking / specularProbability;
+        srec.isScattered          = true;
+        srec.matPdfType           = NO_PDF;
+        srec.skipPdf              = true;
+        srec.skipPdfRay.origin    = rec.meshVertex.p;
+        srec.skipPdfRay.direction = localDirection.x * t + localDirection.y * b + localDirection.z * rec.normal;
+        srec.skipPdfRay.time      = time;
+        return srec;
+    }
+
+    srec.attenuation = baseColour;
+    srec.isScattered = true;
+    srec.skipPdf     = false;
+    srec.matPdfType  = COSINE_PDF;
+    return srec;
+}
+
+ScatterRecord calculateScatter(inout uint rngState, MeshMaterial material, HitRecord rec, vec3 worldRayDirection, float time) {
+    switch (material.type) {
+        case MAT_TYPE_LAMBERTIAN:
+            return lambertianMaterialScatter(rngState, material.index, rec, worldRayDirection, time);
+
+        case MAT_TYPE_METAL:
+            return metalMaterialScatter(rngState, material.index, rec, worldRayDirection, time);
+
+        case MAT_TYPE_DIELECTRIC:
+            return dielectricMaterialScatter(rngState, material.index, rec, worldRayDirection, time);
+
+        case MAT_TYPE_PBR:
+            return pbrMaterialScatter(rngState, material.index, rec, worldRayDirection, time);
+
+        default:
+            // Materials that don't support scattering.
+            return initScatterRecord();
+    }
+}
+
+EmissionRecord calculateEmission(inout uint rngState, MeshMaterial material, HitRecord rec) {
+    switch (material.type) {
+        case MAT_TYPE_DIFFUSE_LIGHT:
+            return diffuseLightMaterialEmission(rngState, material.index, rec);
+
+        default:
+            // Non-emissive materials.
+            return initEmissionRecord();
+    }
+}
+
+// Returns the light emitted from a light source sample towards a hit. The direction is that of a ray
+// from the hit to the sample.
+vec3 getLightSampleEmission(inout uint rngState, LightSample lightSample, vec3 direction) {
+    LightSourceAliasTableEntry entry = lightSourceAliasTableData.values[lightSample.entryIndex];
+    MeshTriangle light = unpackInstanceVertex(entry.meshId, entry.primitiveId);
+
+    HitRecord rec = getIntersection(
+            light,
+            lightSample.barycentrics,
+            entry.objectToWorld,
+            entry.worldToObject,
+            direction);
+
+    MeshMaterial material = unpackInstanceMaterial(entry.meshId, entry.instanceId);
+    return calculateEmission(rngState, material, rec).emissionColour;
+}
+
+// Fraction of the distance to a sampled light that shadow rays are traced so the light's own
+// triangle doesn't occlude it.
+const float SHADOW_RAY_EXTENT = 0.999;
+
+// Maximum number of dielectric surfaces a transparent shadow ray passes through before it counts as
+// blocked.
+const uint MAX_SHADOW_TRANSMISSIONS = 8;
+
+// Returns true if the caustics pass traces light paths from the light sources. Camera paths skip the
+// caustics it finds so they aren't counted twice.
+bool isTracingCaustics() {
+    return pc.traceCaustics != 0 && hasLightSources();
+}
+
+// Returns true if shadow rays pass through dielectrics. The caustics pass finds the light
+// transmitted through them instead when it's enabled.
+bool isTransparentShadows() {
+    return pc.transparentShadows != 0 && !isTracingCaustics();
+}
+
+// Returns the fraction of light a dielectric transmits straight through a surface along the given
+// direction. This is what isn't reflected by the clearcoat or the surface, tinted by the clearcoat.
+vec3 getDielectricTransmittance(uint materialIndex, HitRecord rec, vec3 direction) {
+    if (materialIndex >= pc.dielectricMaterialCount) {
+        return vec3(0.0);
+    }
+    DielectricMaterial material = dielectricMaterial.values[materialIndex];
+
+    float ri = rec.isFrontFace ? (1.0 / material.refractionIndex) : material.refractionIndex;
+    float cosTheta = min(dot(-normalize(direction), rec.normal), 1.0);
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+    if (ri * sinTheta > 1.0) {
+        // Total internal reflection.
+        return vec3(0.0);
+    }
+
+    float transmitted = 1.0 - schlickReflectance(cosTheta, ri);
+    if (rec.isFrontFace) {
+        transmitted *= 1.0 - material.clearcoat.intensity * schlickReflectance(cosTheta, CLEARCOAT_REFRACTION_INDEX);
+    }
+    return transmitted * getClearcoatTint(material.clearcoat, rec);
+}
+
+// Traces a shadow ray and returns the fraction of light that reaches its end. Any surface blocks the
+// light unless shadows are transparent, in which case the ray is traced again past dielectrics
+// attenuated by the light they transmit. isTransmitted is set if the ray passed through any.
+vec3 traceShadowRay(Ray ray, float tMin, float tMax, out bool isTransmitted) {
+    vec3 transmittance = vec3(1.0);
+    isTransmitted = false;
+
+    for (uint i = 0; i <= MAX_SHADOW_TRANSMISSIONS; i++) {
+        traceClosestHit(ray, tMin, tMax, pc.layerMask);
+        if (rayPayload.isMissed) {
+            return transmittance;
+        }
+        if (!isTransparentShadows() || i == MAX_SHADOW_TRANSMISSIONS) {
+            break;
+        }
+
+        MeshMaterial material = unpackInstanceMaterial(rayPayload.meshId, rayPayload.instanceId);
+        if (material.type != MAT_TYPE_DIELECTRIC) {
+            break;
+        }
+
+        MeshTriangle hitTriangle = unpackInstanceVertex(rayPayload.meshId, rayPayload.primitiveId);
+        HitRecord rec = getIntersection(
+                hitTriangle,
+                rayPayload.hitAttribs,
+                rayPayload.objectToWorld,
+                rayPayload.worldToObject,
+                rayPayload.worldRayDirection);
+
+        transmittance *= getDielectricTransmittance(material.index, rec, ray.direction);
+        isTransmitted = true;
+        if (all(lessThanEqual(transmittance, vec3(0.0)))) {
+            break;
+        }
+
+        // Carry on from the hit. The direction is normalized so the remaining extent is a distance.
+        tMax -= distance(ray.origin, rec.meshVertex.p);
+        ray.origin = rec.meshVertex.p;
+    }
+
+    return vec3(0.0);
+}
+
+// Next event estimation. Samples a point on the light sources with the light tree and returns the light arriving from
+// it at the hit if a shadow ray reaches it, scaled by the material's scattering PDF for the direction
+// and weighted against finding the same light by material sampling. The result still needs to be
+// multiplied by the material's attenuation.
+vec3 sampleDirectLight(inout uint rngState, HitRecord rec, uint matPdfType, float tMin, float time) {
+    LightSample lightSample = sampleLightTree(rngState, rec.meshVertex.p);
+    if (lightSample.areaPdf <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec3 toLight = lightSample.position - rec.meshVertex.p;
+    float distanceToLight = length(toLight);
+    if (distanceToLight <= tMin) {
+        return vec3(0.0);
+    }
+    vec3 direction = toLight / distanceToLight;
+
+    float scatteringPdf = getPdfValue(matPdfType, direction, rec, lightSample);
+    float lightPdf = getLightPdf(rec.meshVertex.p, lightSample.position, lightSample.normal, lightSample.areaPdf);
+    if (scatteringPdf <= 0.0 || lightPdf <= 0.0) {
+        return vec3(0.0);
+    }
+
+    // Back faces of lights don't emit so they don't need a shadow ray.
+    vec3 emission = getLightSampleEmission(rngState, lightSample, direction);
+    if (all(lessThanEqual(emission, vec3(0.0)))) {
+        return vec3(0.0);
+    }
+
+    Ray shadowRay = Ray(rec.meshVertex.p, direction, time);
+    bool isTransmitted;
+    vec3 transmittance = traceShadowRay(shadowRay, tMin, distanceToLight * SHADOW_RAY_EXTENT, isTransmitted);
+    if (all(lessThanEqual(transmittance, vec3(0.0)))) {
+        return vec3(0.0);
+    }
+
+    // Material sampling skips light found through dielectrics when shadows are transparent so light
+    // transmitted to the shadow ray is counted in full.
+    float weight = isTransmitted ? 1.0 : powerHeuristic(lightPdf, scatteringPdf);
+    return transmittance * emission * scatteringPdf * weight / lightPdf;
+}
+
+vec3 getBackgroundColour(Ray ray) {
+    vec3 unitDirection = normalize(ray.direction);
+    float a = 0.5 * (unitDirection.y + 1.0);
+
+    switch (sky.value.skyType) {
+        case SKY_TYPE_SOLID:
+            return sky.value.solid;
+        case SKY_TYPE_VERTICAL_GRADIENT:
+            return mix(sky.value.vTop, sky.value.vBottom, sky.value.vFactor);
+            break;
+        default:
+            return vec3(0.0);
+    }
+}
+
+// Counts a ray that hit a material type, or missed, when profiling. Each hit costs a traversal and
+// shading so the counts are a proxy for the time spent on each material type.
+void countProfileHit(uint slot, bool isScattered) {
+    if (pc.profiling == 0) {
+        return;
+    }
+    atomicAdd(materialProfile.hits[slot], 1u);
+    if (isScattered) {
+        atomicAdd(materialProfile.scattered[slot], 1u);
+    }
+}
+
+
+// Returns true if light can be splatted to any pixel of the film. The caustics pass needs it so it's
+// enabled along with it.
+bool isSplatting() {
+    return pc.splatting != 0 || pc.traceCaustics != 0;
+}
+
+// Adds light to a pixel of the splat buffer when splatting is enabled. Unlike camera samples this
+// can be called for any pixel, e.g. by light tracing or bidirectional connections that land on the
+// film away from the pixel being rendered. The light is weighted like a single camera sample and
+// averaged over all samples per pixel by the resolve pass.
+void addSplat(ivec2 pixel, vec3 colour) {
+    if (!isSplatting() || any(lessThan(pixel, ivec2(0))) ||
+            any(greaterThanEqual(pixel, ivec2(pc.resolution)))) {
+        return;
+    }
+    uint i = 3 * (uint(pixel.y) * pc.resolution.x + uint(pixel.x));
+    atomicAdd(splat.values[i], encodeSplat(colour.r));
+    atomicAdd(splat.values[i + 1], encodeSplat(colour.g));
+    atomicAdd(splat.values[i + 2], encodeSplat(colour.b));
+}
+
+vec3 rayColour(inout uint rngState, Ray ray, float tMin, float tMax, out FirstHit firstHit) {
+    vec3 accumulated = vec3(0.0);
+    vec3 throughput  = vec3(1.0);
+
+    firstHit = FirstHit(vec3(0.0), vec3(0.0), tMax);
+    nestedMediaCount = 0;
+
+    // Density with which material sampling chose the ray when its hit also sampled the lights with
+    // next event estimation, so light found by the ray is weighted against the shadow ray. 0 if it
+    // didn't, and the light is counted in full.
+    float neeScatteringPdf = 0.0;
+
+    // Number of specular bounces since a diffuse first hit, or -1 if the first hit wasn't diffuse
+    // or a later bounce was. Light found after at least one is a caustic on the first hit, which
+    // the caustics pass finds instead when it's enabled.
+    int causticBounces = -1;
+
+    // Whether the ray was only transmitted straight through dielectrics since a hit that sampled
+    // the lights. Light it finds was counted by the transparent shadow ray when they're enabled.
+    bool isShadowTransmitted = false;
+
+    // Distance travelled from the camera, which widens the ray cone used to choose image texture mip levels. The
+    // cone isn't widened by curvature or rough bounces, so textures seen after bounces err on the sharp side.
+    float pathLength = 0.0;
+
+    for (uint depth = pc.maxRayDepth; depth > 0; --depth) {
+        traceClosestHit(ray, tMin, tMax, pc.layerMask);
+
+        // Tracing sets rayPayload.isMissed.
+        if (rayPayload.isMissed) {
+            vec3 bgColour = getBackgroundColour(ray);
+            accumulated += throughput * bgColour;
+            countProfileHit(PROFILE_SLOT_MISS, false);
+
+            if (depth == pc.maxRayDepth) {
+                firstHit.albedo = bgColour;
+            }
+            break;
+        }
+
+        MeshTriangle hitTriangle = unpackInstanceVertex(rayPayload.meshId, rayPayload.primitiveId);
+
+        HitRecord rec = getIntersection(
+                hitTriangle,
+                rayPayload.hitAttribs,
+                rayPayload.objectToWorld,
+                rayPayload.worldToObject,
+                rayPayload.worldRayDirection);
+
+        pathLength += distance(ray.origin, rec.meshVertex.p);
+        rec.uvLod = getUvLod(
+                hitTriangle,
+                rayPayload.objectToWorld,
+                rayPayload.worldRayDirection,
+                getPixelSpreadAngle() * pathLength);
+
+        MeshMaterial material = unpackInstanceMaterial(rayPayload.meshId, rayPayload.instanceId);
+        applyNormalMap(material, rec);
+
+        // Emission
+        EmissionRecord erec = calculateEmission(rngState, material, rec);
+        float emissionWeight = 1.0;
+        if (neeScatteringPdf > 0.0 && any(greaterThan(erec.emissionColour, vec3(0.0)))) {
+            vec3 lightNormal = getWorldSpaceFaceNormal(hitTriangle, rayPayload.objectToWorld);
+            float lightArea = getWorldSpaceArea(hitTriangle, rayPayload.objectToWorld);
+            float lightAreaPdf = getLightTreeAreaPdf(ray.origin, rayPayload.instanceId, rayPayload.primitiveId, lightArea);
+            float lightPdf = getLightPdf(ray.origin, rec.meshVertex.p, lightNormal, lightAreaPdf);
+            emissionWeight = powerHeuristic(neeScatteringPdf, lightPdf);
+        }
+        if ((causticBounces > 0 && isTracingCaustics()) || (isShadowTransmitted && isTransparentShadows())) {
+            emissionWeight = 0.0;
+        }
+        accumulated += throughput * erec.emissionColour * emissionWeight;
+
+        // Scatter
+        ScatterRecord srec = calculateScatter(rngState, material, rec, rayPayload.worldRayDirection, ray.time);
+        countProfileHit(material.type, srec.isScattered);
+
+        if (depth == pc.maxRayDepth) {
+            firstHit.albedo = srec.isScattered ? srec.attenuation : erec.emissionColour;
+            firstHit.normal = rec.normal;
+            firstHit.depth  = distance(ray.origin, rec.meshVertex.p);
+        }
+        if (!srec.isScattered) {
+            break;
+        }
+
+        // Return early if we don't have to evaluate scattering PDF. Specular scattering can't
+        // sample lights so light found by the ray is counted in full.
+        if (srec.skipPdf) {
+            bool isTransmitted = material.type == MAT_TYPE_DIELECTRIC && dot(srec.skipPdfRay.direction, rec.normal) < 0.0;
+            isShadowTransmitted = isTransmitted && (neeScatteringPdf > 0.0 || isShadowTransmitted);
+
+            throughput *= srec.attenuation;
+            ray = srec.skipPdfRay;
+            neeScatteringPdf = 0.0;
+            if (causticBounces >= 0) {
+                causticBounces++;
+            }
+            continue;
+        }
+        causticBounces = depth == pc.maxRayDepth ? 0 : -1;
+
+        // Next event estimation. The shadow ray overwrites the ray payload.
+        if (isNextEventEstimation()) {
+            accumulated += throughput * srec.attenuation * sampleDirectLight(rngState, rec, srec.matPdfType, tMin, ray.time);
+        }
+
+        // Get a the light source sample.
+        LightSample lightSample = sampleLightSources(rngState);
+
+        // Choose between material and light PDF with a 50-50 chance.
+        uint chosenPdfType = chooseMixturePdf(rngState, srec.matPdfType);
+        vec3 scatterDirection = genScatterDirection(rngState, chosenPdfType, rec, lightSample);
+
+        // Use material PDFs.
+        float scatteringPdf = getPdfValue(srec.matPdfType, scatterDirection, rec, lightSample);
+        float pdfMat        = scatteringPdf;
+        float pdfValue      = pdfMat;
+
+        // See if we want to use a Mixture PDF.
+        if (isSamplingLights()) {
+            float pdfLight = getPdfValue(LIGHT_PDF, scatterDirection, rec, lightSample);
+            pdfValue = 0.5 * pdfLight + 0.5 * pdfMat;
+        }
+
+        // Update throughput.
+        throughput *= srec.attenuation * scatteringPdf / pdfValue;
+        neeScatteringPdf = isNextEventEstimation() ? pdfMat : 0.0;
+        isShadowTransmitted = false;
+
+        // Calculate ray for next depth.
+        ray = Ray(rec.meshVertex.p, normalize(scatterDirection), ray.time);
+    }
+
+    return accumulated;
+}
+
+// Returns the jitter in [0, 1)^2 for a sample of a pixel. The pixel's blue noise value is offset by
+// the R2 low discrepancy sequence so successive samples cover the square, while the error between
+// neighbouring pixels is blue noise which looks like fine grain instead of blotches at low sample
+// counts. See https://extremelearning.com.au/unreasonable-effectiveness-of-quasirandom-sequences/.
+vec2 getBlueNoiseJitter(uvec2 pixel, uint sampleIndex) {
+    vec2 noise = texelFetch(blueNoise, ivec2(pixel) % textureSize(blueNoise, 0), 0).rg;
+
+    // The R2 sequence in 0.32 fixed point so it wraps exactly for any sample index.
+    vec2 r2 = vec2(sampleIndex * uvec2(3242174889u, 2447445413u)) * exp2(-32.0);
+    return fract(noise + r2);
+}
+
+// Maps a uniform random number in [0, 1) to an offset from the pixel centre distributed like the
+// pixel filter, so samples don't need to be weighted.
+float samplePixelFilter(float u) {
+    uint lastEntry = uint(pixelFilter.offsets.length()) - 1;
+    float position = clamp(u, 0.0, 1.0) * float(lastEntry);
+    uint i = min(uint(position), lastEntry - 1);
+    return mix(pixelFilter.offsets[i], pixelFilter.offsets[i + 1], position - float(i));
+}
+
+Ray getRay(inout uint rngState, vec2 pixelCenter, vec2 jitter, int si, int sj, float recipSqrtSpp) {
+    // The filters are separable so each axis is sampled independently.
+    const vec2 u = sampleSquareStratified(jitter, si, sj, recipSqrtSpp) + 0.5;
+    const vec2 offset = vec2(samplePixelFilter(u.x), samplePixelFilter(u.y));
+    const vec2 offsetPixelCenter = pixelCenter + offset;
+
+    const vec2 screenUV = offsetPixelCenter / vec2(pc.resolution);
+    vec2 d = screenUV * 2.0 - 1.0;
+
+    vec4 origin = camera.viewInverse * vec4(0.0, 0.0, 0.0, 1.0);
+    vec4 target = camera.projInverse * vec4(d.x, d.y, 1.0, 1.0);
+    vec4 direction = camera.viewInverse * vec4(normalize(target.xyz), 0.0);
+
+    if (camera.apertureSize > 0.0) {
+        vec4 focalPoint = vec4(camera.focalLength * normalize(target.xyz), 1.0);
+
+        vec2 randomLensPos = sampleUniformDiskConcentric(rngState) * camera.apertureSize / 2.0;
+        origin.xy += vec2(randomLensPos.x * d.x, randomLensPos.y * d.y);
+
+        direction = vec4((normalize((camera.viewInverse * focalPoint) - origin).xyz), 0.0);
+    }
+
+    // For simplicity, to do motion blur sample time in [0, 1] as start time and end time.
+    float time = pc.batchRayTime;
+
+    Ray ray;
+    ray.origin    = origin.xyz;
+    ray.direction = direction.xyz;
+    ray.time      = time;
+    return ray;
+}
+
+// Returns the number of sample batches needed to trace every pixel once.
+uint getSamplingPatternPeriod() {
+    switch (pc.samplingPattern) {
+        case SAMPLING_PATTERN_CHECKERBOARD:
+            return 2;
+        case SAMPLING_PATTERN_INTERLEAVED:
+            return 4;
+        default:
+            return 1;
+    }
+}
+
+// Returns the sample batch within a sampling pattern period in which the pixel is traced.
+uint getSamplingPatternSlot(uvec2 pixel) {
+    switch (pc.samplingPattern) {
+        case SAMPLING_PATTERN_CHECKERBOARD:
+            return (pixel.x + pixel.y) & 1;
+        case SAMPLING_PATTERN_INTERLEAVED:
+            return (pixel.x & 1) + 2 * (pixel.y & 1);
+        default:
+            return 0;
+    }
+}
+
+// Maximum number of batches the reprojected history counts for when blending it with the batches
+// after the camera moves. This bounds how long stale view dependent shading takes to fade out.
+const float MAX_HISTORY_BATCHES = 32.0;
+
+// Maximum relative difference between the depth in the history and the expected depth of a surface
+// for the history to be considered valid. This rejects disocclusions.
+const float HISTORY_DEPTH_TOLERANCE = 0.05;
+
+// Calculates the motion vector in pixels from the current frame to the previous frame for the first
+// surface seen through the pixel center at the given depth. The world position is also returned.
+// Returns false if the surface was behind the previous camera.
+bool getMotionVector(vec2 pixelCenter, float depth, out vec2 motion, out vec3 worldPosition) {
+    vec2 d = pixelCenter / vec2(pc.resolution) * 2.0 - 1.0;
+
+    vec4 origin = camera.viewInverse * vec4(0.0, 0.0, 0.0, 1.0);
+    vec4 target = camera.projInverse * vec4(d.x, d.y, 1.0, 1.0);
+    vec3 direction = (camera.viewInverse * vec4(normalize(target.xyz), 0.0)).xyz;
+    worldPosition = origin.xyz + direction * depth;
+
+    vec4 prevClip = camera.prevViewProj * vec4(worldPosition, 1.0);
+    if (prevClip.w <= 0.0) {
+        return false;
+    }
+
+    vec2 prevPixelCenter = (prevClip.xy / prevClip.w * 0.5 + 0.5) * vec2(pc.resolution);
+    motion = prevPixelCenter - pixelCenter;
+    return true;
+}
+
+// Looks up the colour in the history images for the surface seen through the pixel center at the
+// given depth and the number of batches accumulated into it. Returns false if it was not visible
+// before the camera moved.
+bool reprojectHistoryColour(vec2 pixelCenter, float depth, out vec3 historyColour, out float historyBatches) {
+    vec2 motion;
+    vec3 worldPosition;
+    if (!getMotionVector(pixelCenter, depth, motion, worldPosition)) {
+        return false;
+    }
+
+    ivec2 prevPixel = ivec2(floor(pixelCenter + motion));
+    if (any(lessThan(prevPixel, ivec2(0))) || any(greaterThanEqual(prevPixel, ivec2(pc.resolution)))) {
+        return false;
+    }
+
+    vec3 prevEye = (camera.prevViewInverse * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
+    float expectedDepth = distance(prevEye, worldPosition);
+    vec2 history = imageLoad(historyDepthImage, prevPixel).rg;
+    if (abs(history.r - expectedDepth) > HISTORY_DEPTH_TOLERANCE * expectedDepth) {
+        return false;
+    }
+
+    historyColour = imageLoad(historyImage, prevPixel).rgb;
+    historyBatches = history.g;
+    return true;
+}
+
+// Traces a single ray through the center of a probe image pixel and stores the distance to the first
+// hit along with the hit instance and mesh. The host reads these back to drive autofocus, picking and
+// scene statistics. Indices are stored off by one so 0 means the ray missed.
+void renderProbePixel(uvec2 pixel) {
+    vec2 d = (vec2(pixel) + vec2(0.5)) / vec2(pc.resolution) * 2.0 - 1.0;
+
+    vec4 origin = camera.viewInverse * vec4(0.0, 0.0, 0.0, 1.0);
+    vec4 target = camera.projInverse * vec4(d.x, d.y, 1.0, 1.0);
+    vec4 direction = camera.viewInverse * vec4(normalize(target.xyz), 0.0);
+    Ray ray = Ray(origin.xyz, direction.xyz, pc.batchRayTime);
+
+    traceClosestHit(ray, 0.001, 10000.0, pc.layerMask);
+    if (rayPayload.isMissed) {
+        imageStore(probeImage, ivec2(pixel), vec4(0.0));
+        return;
+    }
+
+    MeshTriangle hitTriangle = unpackInstanceVertex(rayPayload.meshId, rayPayload.primitiveId);
+    HitRecord rec = getIntersection(
+            hitTriangle,
+            rayPayload.hitAttribs,
+            rayPayload.objectToWorld,
+            rayPayload.worldToObject,
+            rayPayload.worldRayDirection);
+
+    imageStore(probeImage, ivec2(pixel), vec4(
+            distance(ray.origin, rec.meshVertex.p),
+            float(rayPayload.instanceId + 1),
+            float(rayPayload.meshId + 1),
+            0.0));
+}
+
+// Offset of the random number generator state of light paths so they don't reuse the random numbers
+// of the camera paths of the same pixel.
+const uint LIGHT_PATH_RNG_OFFSET = 0x9e3779b9u;
+
+// Returns the area of the image plane at a distance of 1 in front of the camera.
+float getImagePlaneArea() {
+    vec4 corner = camera.projInverse * vec4(1.0, 1.0, 1.0, 1.0);
+    return 4.0 * abs(corner.x * corner.y) / (corner.z * corner.z);
+}
+
+// Returns the light a Lambertian or PBR material scatters towards a direction per unit of light
+// arriving at the hit. Camera paths only scatter diffusely when the clearcoat or specular layer
+// doesn't reflect them, so this is scaled by the probability of that. Other materials return 0.
+vec3 getDiffuseScattering(MeshMaterial material, HitRecord rec, vec3 direction) {
+    if (material.type == MAT_TYPE_PBR && material.index < pc.pbrMaterialCount) {
+        PbrMaterial pbr = pbrMaterial.values[material.index];
+        vec3 baseColour = getMaterialPropertyValue(pbr.baseColour, rec.meshVertex, rec.uvLod);
+        float metallic = clamp(getMaterialPropertyValue(pbr.metallic, rec.meshVertex, rec.uvLod).r, 0.0, 1.0);
+        float cosTheta = clamp(dot(direction, rec.normal), 0.0, 1.0);
+        return (1.0 - getPbrSpecularProbability(metallic, pbr.ior, cosTheta)) * baseColour / PI;
+    }
+    if (material.type != MAT_TYPE_LAMBERTIAN || material.index >= pc.lambertianMaterialCount) {
+        return vec3(0.0);
+    }
+
+    LambertianMaterial lambertian = lambertianMaterial.values[material.index];
+    vec3 albedo = getMaterialPropertyValue(lambertian.albedo, rec.meshVertex, rec.uvLod);
+
+    float reflectance = 0.0;
+    if (lambertian.clearcoat.intensity > 0.0 && rec.isFrontFace) {
+        float cosTheta = min(dot(direction, rec.normal), 1.0);
+        reflectance = lambertian.clearcoat.intensity *
+            schlickReflectance(cosTheta, CLEARCOAT_REFRACTION_INDEX);
+    }
+
+    return (1.0 - reflectance) * albedo * getClearcoatTint(lambertian.clearcoat, rec) / PI;
+}
+
+// Splats the light a Lambertian surface hit by a light path scatters towards the camera onto the
+// pixel it's seen through, if the camera sees it. The light arriving at the hit is the path's
+// throughput. The lens is treated as a pinhole so caustics are always in focus.
+void splatToCamera(MeshMaterial material, HitRecord rec, vec3 throughput, float tMin, float time) {
+    vec4 clip = camera.viewProj * vec4(rec.meshVertex.p, 1.0);
+    if (clip.w <= 0.0) {
+        return;
+    }
+    ivec2 pixel = ivec2(floor((clip.xy / clip.w * 0.5 + 0.5) * vec2(pc.resolution)));
+    if (any(lessThan(pixel, ivec2(0))) || any(greaterThanEqual(pixel, ivec2(pc.resolution)))) {
+        return;
+    }
+
+    vec3 eye = (camera.viewInverse * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
+    vec3 toCamera = eye - rec.meshVertex.p;
+    float distanceToCamera = length(toCamera);
+    vec3 direction = toCamera / distanceToCamera;
+
+    vec3 viewForward = normalize((camera.projInverse * vec4(0.0, 0.0, 1.0, 1.0)).xyz);
+    vec3 forward = normalize((camera.viewInverse * vec4(viewForward, 0.0)).xyz);
+    float cosSurface = dot(rec.normal, direction);
+    float cosCamera = dot(-direction, forward);
+    if (cosSurface <= 0.0 || cosCamera <= 0.0) {
+        return;
+    }
+
+    vec3 scattering = getDiffuseScattering(material, rec, direction);
+    if (all(lessThanEqual(scattering, vec3(0.0)))) {
+        return;
+    }
+
+    // The ray to the camera overwrites the ray payload.
+    traceClosestHit(Ray(rec.meshVertex.p, direction, time), tMin, distanceToCamera * SHADOW_RAY_EXTENT, pc.layerMask);
+    if (!rayPayload.isMissed) {
+        return;
+    }
+
+    // Camera paths sample the image plane uniformly so the pixel's share of the light depends on
+    // the area of the image plane the hit's area projects to.
+    float importance = cosSurface /
+        (distanceToCamera * distanceToCamera * cosCamera * cosCamera * cosCamera * getImagePlaneArea());
+    addSplat(pixel, camera.exposure * throughput * scattering * importance);
+}
+
+// Traces a light path for the caustics pass. It starts at a point sampled on the light sources and
+// follows specular bounces off dielectrics, metals and clearcoats. Each Lambertian surface reached
+// after at least one of them is connected to the camera. Diffuse bounces end the path since camera
+// paths find the light they scatter well enough.
+void traceLightPath(inout uint rngState, float tMin, float tMax) {
+    LightSample lightSample = sampleLightSources(rngState);
+
+    // Emit in a cosine weighted direction from whichever side of the light emits.
+    ONB onb = createOrthonormalBases(lightSample.normal);
+    vec3 direction = onbTransform(onb, randomVec3CosineDirection(rngState));
+    vec3 emission = getLightSampleEmission(rngState, lightSample, -direction);
+    if (all(lessThanEqual(emission, vec3(0.0)))) {
+        direction = -direction;
+        emission = getLightSampleEmission(rngState, lightSample, -direction);
+    }
+    if (all(lessThanEqual(emission, vec3(0.0)))) {
+        return;
+    }
+
+    // The emitted light divided by the densities of sampling the position by area and the
+    // direction by cosine.
+    vec3 throughput = emission * PI * pc.lightSourceTotalArea;
+
+    Ray ray = Ray(lightSample.position, direction, pc.batchRayTime);
+    nestedMediaCount = 0;
+    bool isCaustic = false;
+
+    for (uint depth = pc.maxRayDepth; depth > 0; --depth) {
+        traceClosestHit(ray, tMin, tMax, pc.layerMask);
+        if (rayPayload.isMissed) {
+            break;
+        }
+
+        MeshTriangle hitTriangle = unpackInstanceVertex(rayPayload.meshId, rayPayload.primitiveId);
+
+        HitRecord rec = getIntersection(
+                hitTriangle,
+                rayPayload.hitAttribs,
+                rayPayload.objectToWorld,
+                rayPayload.worldToObject,
+                rayPayload.worldRayDirection);
+
+        MeshMaterial material = unpackInstanceMaterial(rayPayload.meshId, rayPayload.instanceId);
+        applyNormalMap(material, rec);
+        ScatterRecord srec = calculateScatter(rngState, material, rec, rayPayload.worldRayDirection, ray.time);
+
+        if (isCaustic) {
+            splatToCamera(material, rec, throughput, tMin, ray.time);
+        }
+        if (!srec.isScattered || !srec.skipPdf) {
+            break;
+        }
+
+        throughput *= srec.attenuation;
+        ray = srec.skipPdfRay;
+        isCaustic = true;
+    }
+}
+
+// Traces the light paths of the caustics pass for a pixel's share of the sample batch. Light paths
+// aren't tied to the pixel so it only sets the number traced, which is the same as the number of
+// camera paths.
+void traceLightPaths(uvec2 pixel) {
+    uint period = getSamplingPatternPeriod();
+    if (!isTracingCaustics() ||
+            getSamplingPatternSlot(pixel) != (pc.sampleBatch + pc.samplingPhase) % period) {
+        return;
+    }
+
+    uint rngState = initRNG(pc.sampleBatch, pixel, pc.resolution) ^ LIGHT_PATH_RNG_OFFSET;
+
+    float tMin = 0.001;
+    float tMax = 10000.0;
+
+    for (uint i = 0; i < pc.samplesPerPixel; i++) {
+        traceLightPath(rngState, tMin, tMax);
+    }
+}
+
+// Traces the sample batch for a pixel and accumulates it in the render images.
+void renderPixel(uvec2 pixel) {
+    if (pc.probePass != 0) {
+        renderProbePixel(pixel);
+        return;
+    }
+    if (pc.lightTracingPass != 0) {
+        traceLightPaths(pixel);
+        return;
+    }
+
+    // Skip pixels not traced in this sample batch and determine how many batches this pixel has
+    // accumulated. The phase rotates which pixels are traced first after a restart so all pixels
+    // get updated while the camera keeps moving.
+    uint period = getSamplingPatternPeriod();
+    if (getSamplingPatternSlot(pixel) != (pc.sampleBatch + pc.samplingPhase) % period) {
+        return;
+    }
+    uint pixelBatch = pc.sampleBatch / period;
+
+    uint rngState = initRNG(pc.sampleBatch, pixel, pc.resolution);
+
+    float tMin = 0.001;
+    float tMax = 10000.0;
+
+    const vec2 pixelCenter = vec2(pixel) + vec2(0.5);
+
+    float sqrtSpp = sqrt(float(pc.samplesPerPixel));
+    float recipSqrtSpp = 1.0 / sqrtSpp;
+    float spp = int(sqrtSpp) * int(sqrtSpp); // In case pc.samplesPerPixel is not a perfect square.
+
+    vec3  summedPixelColour   = vec3(0.0);
+    vec3  summedPixelMoment   = vec3(0.0);
+    vec3  summedAlbedo        = vec3(0.0);
+    vec3  summedNormal        = vec3(0.0);
+    float summedDepth         = 0.0;
+    for (int sj = 0; sj < sqrtSpp; ++sj) {
+        for (int si = 0; si < sqrtSpp; ++si) {
+            uint sampleIndex = (pixelBatch * uint(sqrtSpp) + uint(sj)) * uint(sqrtSpp) + uint(si);
+            vec2 jitter = getBlueNoiseJitter(pixel, sampleIndex);
+            Ray ray = getRay(rngState, pixelCenter, jitter, si, sj, recipSqrtSpp);
+
+            FirstHit firstHit;
+            vec3 attenuation = camera.exposure * rayColour(rngState, ray, tMin, tMax, firstHit);
+            summedPixelColour += attenuation;
+            summedPixelMoment += attenuation * attenuation;
+            summedAlbedo      += firstHit.albedo;
+            summedNormal      += firstHit.normal;
+            summedDepth       += firstHit.depth;
+        }
+    }
+
+    // Blend with the averaged image in the buffer:
+    vec3  averagePixelColour = summedPixelColour / spp;
+    vec3  averagePixelMoment = summedPixelMoment / spp;
+    vec3  averageAlbedo      = summedAlbedo / spp;
+    vec3  averageNormal      = summedNormal / spp;
+    float averageDepth       = summedDepth / spp;
+    ivec2 p = ivec2(pixel);
+
+    // History from before the camera moved is reprojected at the first batch of the pixel and kept
+    // with the number of batches it holds so later batches can tell it apart from new samples.
+    vec4 reprojected = vec4(0.0);
+    if (pixelBatch == 0) {
+        vec3 historyColour;
+        float historyBatches;
+        if (pc.reprojectHistory != 0 &&
+                reprojectHistoryColour(pixelCenter, averageDepth, historyColour, historyBatches)) {
+            reprojected = vec4(historyColour, min(historyBatches, MAX_HISTORY_BATCHES));
+        }
+    } else {
+        reprojected = imageLoad(reprojectedImage, p);
+    }
+
+    if (pixelBatch != 0) {
+        float n = float(pixelBatch);
+
+        // Remove the history blended into the previous batches to get the mean of the new ones.
+        vec3 accumulatedColour = imageLoad(image, p).rgb;
+        if (reprojected.a > 0.0) {
+            accumulatedColour = ((reprojected.a + n) * accumulatedColour - reprojected.a * reprojected.rgb) / n;
+        }
+
+        averagePixelColour = (n * accumulatedColour + averagePixelColour) / (n + 1.0);
+        averagePixelMoment = (n * imageLoad(momentImage, p).rgb + averagePixelMoment) / (n + 1.0);
+        averageAlbedo      = (n * imageLoad(albedoImage, p).rgb + averageAlbedo) / (n + 1.0);
+        averageNormal      = (n * imageLoad(normalImage, p).xyz + averageNormal) / (n + 1.0);
+        averageDepth       = (n * imageLoad(depthImage, p).r + averageDepth) / (n + 1.0);
+    }
+
+    // Weight the history by the batches it holds and drop it once the new batches reach that count.
+    float batchCount = float(pixelBatch + 1);
+    if (reprojected.a > batchCount) {
+        averagePixelColour = (reprojected.a * reprojected.rgb + batchCount * averagePixelColour) / (reprojected.a + batchCount);
+        batchCount += reprojected.a;
+    } else {
+        reprojected = vec4(0.0);
+    }
+
+    imageStore(image, p, vec4(averagePixelColour, 1.0));
+    imageStore(momentImage, p, vec4(averagePixelMoment, 1.0));
+    imageStore(albedoImage, p, vec4(averageAlbedo, 1.0));
+    imageStore(normalImage, p, vec4(averageNormal, 0.0));
+    imageStore(depthImage, p, vec4(averageDepth, batchCount, 0.0, 0.0));
+    imageStore(reprojectedImage, p, reprojected);
+}
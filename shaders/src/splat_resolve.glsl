@@ -0,0 +1,36 @@
+#version 460
+
+// Combines the accumulated image with the light splatted to the film. Splats are summed over every
+// sample so they are averaged over the samples per pixel to match the accumulated image.
+
+#include "common.glsl"
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba32f) uniform readonly image2D accumImage;
+layout(set = 0, binding = 1) readonly buffer SplatBuffer {
+    uint values[];
+} splat;
+layout(set = 0, binding = 2, rgba32f) uniform writeonly image2D resolvedImage;
+
+layout(push_constant) uniform SplatResolvePushConstants {
+    uint sampleCount; // Samples per pixel accumulated so far.
+} pc;
+
+void main() {
+    ivec2 size = imageSize(accumImage);
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+
+    uint i = 3 * (uint(pixel.y) * uint(size.x) + uint(pixel.x));
+    vec3 splatted = vec3(
+        decodeSplat(splat.values[i]),
+        decodeSplat(splat.values[i + 1]),
+        decodeSplat(splat.values[i + 2]));
+
+    vec4 colour = imageLoad(accumImage, pixel);
+    colour.rgb += splatted / float(max(pc.sampleCount, 1u));
+    imageStore(resolvedImage, pixel, colour);
+}
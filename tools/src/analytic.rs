@@ -0,0 +1,380 @@
+use std::{f32::consts::PI, fs, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use raytracer::{CancellationToken, RenderHooks, Scene, Vk};
+use scene_file::{
+    Camera, Film, PixelFilter, Primitive, Quality, Render, ScalingPolicy, SceneBuilder, SceneFile,
+    Sky,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::render::get_height;
+
+/// File name of the test suite written next to the generated scenes.
+const TEST_SUITE_FILE_NAME: &str = "tests.json";
+
+/// Uniform radiance of the sky surrounding the furnace test objects.
+const FURNACE_RADIANCE: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// Emitted radiance of the square light in the quad light test.
+const QUAD_LIGHT_RADIANCE: [f32; 3] = [10.0, 10.0, 10.0];
+
+/// Side length of the square light in the quad light test.
+const QUAD_LIGHT_SIZE: f32 = 1.0;
+
+/// Height of the square light above the ground in the quad light test.
+const QUAD_LIGHT_HEIGHT: f32 = 1.0;
+
+/// Albedo of the ground lit by the square light in the quad light test.
+const QUAD_GROUND_ALBEDO: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// Validation scenes along with the values their renders are expected to average to.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestSuite {
+    /// The tests in the order they are run.
+    pub tests: Vec<TestCase>,
+}
+
+/// A validation scene with an analytic solution.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    /// Name printed in the results.
+    pub name: String,
+
+    /// Path to the scene file. Relative paths are relative to the test suite.
+    pub scene: String,
+
+    /// Expected mean linear radiance of each RGB channel.
+    pub expected: [f32; 3],
+
+    /// Maximum relative error allowed in any channel.
+    pub tolerance: f32,
+
+    /// Fraction of the image width and height around the centre that is averaged, in (0, 1].
+    pub window: f32,
+}
+
+/// Generates the validation scenes and the test suite describing their expected results in the
+/// given directory:
+///
+/// - White furnace: a white diffuse sphere under a uniform sky. A convex object that reflects all
+///   light is indistinguishable from the sky, so the whole image averages to the sky radiance.
+/// - Metal furnace: the same with a perfect white mirror.
+/// - Quad light: a diffuse ground lit only by a square light directly above the point the camera
+///   looks at. The radiance there is the albedo times the light's radiance times the form factor
+///   from the point to the light.
+pub fn generate_test_scenes(output_dir: &str) -> Result<()> {
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Unable to create directory '{output_dir}'"))?;
+
+    let white_furnace = "white-furnace.json";
+    let metal_furnace = "metal-furnace.json";
+    let quad_light = "quad-light.json";
+
+    let mut scene = furnace_scene_builder();
+    let material = scene.lambertian_rgb([1.0, 1.0, 1.0]);
+    add_furnace_sphere(&mut scene, &material)?;
+    save_test_scene(scene, output_dir, white_furnace)?;
+
+    let mut scene = furnace_scene_builder();
+    let material = scene.metal_rgb([1.0, 1.0, 1.0], 0.0);
+    add_furnace_sphere(&mut scene, &material)?;
+    save_test_scene(scene, output_dir, metal_furnace)?;
+
+    save_test_scene(quad_light_scene_builder(), output_dir, quad_light)?;
+
+    let form_factor = get_square_form_factor(QUAD_LIGHT_SIZE, QUAD_LIGHT_HEIGHT);
+    let suite = TestSuite {
+        tests: vec![
+            TestCase {
+                name: "White furnace".to_string(),
+                scene: white_furnace.to_string(),
+                expected: FURNACE_RADIANCE,
+                tolerance: 0.01,
+                window: 1.0,
+            },
+            TestCase {
+                name: "Metal furnace".to_string(),
+                scene: metal_furnace.to_string(),
+                expected: FURNACE_RADIANCE,
+                tolerance: 0.01,
+                window: 1.0,
+            },
+            TestCase {
+                name: "Quad light".to_string(),
+                scene: quad_light.to_string(),
+                expected: [0, 1, 2]
+                    .map(|i| QUAD_GROUND_ALBEDO[i] * QUAD_LIGHT_RADIANCE[i] * form_factor),
+                tolerance: 0.02,
+                window: 0.25,
+            },
+        ],
+    };
+
+    let suite_path = Path::new(output_dir).join(TEST_SUITE_FILE_NAME);
+    println!("Generating test suite {}", suite_path.display());
+
+    let serialized = serde_json::to_string_pretty(&suite)?;
+    fs::write(&suite_path, serialized)
+        .with_context(|| format!("Unable to write test suite '{}'", suite_path.display()))
+}
+
+/// Renders every scene in the test suite headless and compares the mean radiance over its window
+/// against the expected value. A failing test doesn't stop the remaining ones from running.
+/// Returns true if all tests passed.
+pub fn run_tests(suite_path: &str, width: u32, cancel: &CancellationToken) -> Result<bool> {
+    let serialized = fs::read_to_string(suite_path)
+        .with_context(|| format!("Unable to read test suite '{suite_path}'"))?;
+    let suite: TestSuite = serde_json::from_str(&serialized)
+        .with_context(|| format!("Unable to parse test suite '{suite_path}'"))?;
+    if suite.tests.is_empty() {
+        return Err(anyhow!("Test suite '{suite_path}' has no tests"));
+    }
+
+    let base_dir = Path::new(suite_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let vk = Vk::new_headless()?;
+
+    let mut passed = 0;
+    for test in suite.tests.iter() {
+        let scene_path = base_dir.join(&test.scene);
+        let result = SceneFile::load_json(&scene_path.to_string_lossy()).and_then(|scene_file| {
+            let height = get_height(width, &scene_file);
+            let mut scene = Scene::new_headless(
+                vk.clone(),
+                &scene_file,
+                width,
+                height,
+                cancel.clone(),
+                RenderHooks::default(),
+            )?;
+            let pixels = scene.render_offline()?.beauty;
+            get_window_mean(&pixels, width, height, test.window)
+        });
+        cancel.check()?;
+
+        match result {
+            Ok(mean) => {
+                let errors = [0, 1, 2].map(|i| {
+                    (mean[i] - test.expected[i]).abs() / test.expected[i].max(f32::EPSILON)
+                });
+                let is_passed = errors.iter().all(|e| *e <= test.tolerance);
+                if is_passed {
+                    passed += 1;
+                }
+
+                println!(
+                    "{}: mean [{:.4}, {:.4}, {:.4}], expected [{:.4}, {:.4}, {:.4}], \
+                    max relative error {:.4} (tolerance {}) => {}",
+                    test.name,
+                    mean[0],
+                    mean[1],
+                    mean[2],
+                    test.expected[0],
+                    test.expected[1],
+                    test.expected[2],
+                    errors.iter().copied().fold(0.0, f32::max),
+                    test.tolerance,
+                    if is_passed { "PASS" } else { "FAIL" }
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: failed to render {}. {e:?}",
+                    test.name,
+                    scene_path.display()
+                );
+            }
+        }
+    }
+
+    println!("{passed}/{} tests passed", suite.tests.len());
+
+    Ok(passed == suite.tests.len())
+}
+
+/// Returns the render settings shared by the validation scenes. Enough samples are taken for the
+/// mean over the test window to converge.
+fn get_test_render_settings(max_ray_depth: u32) -> Render {
+    Render {
+        camera: "default".to_string(),
+        samples_per_pixel: 16,
+        sample_batches: 64,
+        max_ray_depth,
+        aspect_ratio: 1.0,
+        resolution: None,
+        scaling: ScalingPolicy::default(),
+        strict_textures: false,
+        layers: None,
+        tags: None,
+        film: Film::default(),
+        pixel_filter: PixelFilter::default(),
+        supersample: None,
+        shutter: None,
+        caustics: false,
+        transparent_shadows: false,
+        denoise: false,
+        texture_filter: None,
+        strip_unused: false,
+        compact_acceleration: false,
+        budget: None,
+        quality: Quality::default(),
+    }
+}
+
+/// Starts a furnace scene with a uniform sky and a camera looking at the origin.
+fn furnace_scene_builder() -> SceneBuilder {
+    // Rays can bounce around inside the tessellated sphere before escaping, so allow plenty of
+    // bounces for all the energy to reach the sky.
+    let mut scene = SceneBuilder::new(
+        get_test_render_settings(50),
+        Sky::Solid {
+            rgb: FURNACE_RADIANCE,
+        },
+    );
+    scene.add_camera(Camera::Perspective {
+        name: "default".to_string(),
+        eye: [0.0, 0.0, -4.0],
+        look_at: [0.0, 0.0, 0.0],
+        up: Some([0.0, 1.0, 0.0]),
+        orientation: None,
+        fov_y: 30.0,
+        z_near: 0.01,
+        z_far: 100.0,
+        focal_length: 1.0,
+        aperture_size: 0.0,
+        exposure: None,
+    });
+    scene
+}
+
+/// Adds the sphere at the origin of a furnace scene.
+fn add_furnace_sphere(scene: &mut SceneBuilder, material: &str) -> Result<()> {
+    scene
+        .add_sphere([0.0, 0.0, 0.0], 1.0)
+        .name("sphere")
+        .tessellation(128, 256)
+        .material(material)
+        .build()?;
+    Ok(())
+}
+
+/// Returns the quad light scene. The camera looks steeply down at the point on the ground below
+/// the centre of the light with a narrow field of view, so the test window only covers a small
+/// area where the irradiance barely changes. The sky is black so the light is the only source of
+/// radiance.
+fn quad_light_scene_builder() -> SceneBuilder {
+    let mut scene = SceneBuilder::new(
+        get_test_render_settings(4),
+        Sky::Solid {
+            rgb: [0.0, 0.0, 0.0],
+        },
+    );
+
+    // The eye is placed so the view of the ground isn't blocked by the light.
+    scene.add_camera(Camera::Perspective {
+        name: "default".to_string(),
+        eye: [0.0, -4.0, -3.0],
+        look_at: [0.0, 0.0, 0.0],
+        up: Some([0.0, 1.0, 0.0]),
+        orientation: None,
+        fov_y: 2.0,
+        z_near: 0.01,
+        z_far: 100.0,
+        focal_length: 1.0,
+        aperture_size: 0.0,
+        exposure: None,
+    });
+
+    let ground = scene.lambertian_rgb(QUAD_GROUND_ALBEDO);
+    let light = scene.diffuse_light_rgb(QUAD_LIGHT_RADIANCE);
+
+    // The ground is large enough for its edges to be far outside the light's influence.
+    let g = 100.0;
+    scene.add_primitive(
+        quad(
+            "ground",
+            [[-g, 0.0, -g], [g, 0.0, -g], [g, 0.0, g], [-g, 0.0, g]],
+            [0.0, -1.0, 0.0],
+            &ground,
+        ),
+        None,
+    );
+
+    // The light faces down towards the ground. Negative y is up.
+    let (l, h) = (QUAD_LIGHT_SIZE * 0.5, -QUAD_LIGHT_HEIGHT);
+    scene.add_primitive(
+        quad(
+            "light",
+            [[l, h, -l], [-l, h, -l], [-l, h, l], [l, h, l]],
+            [0.0, 1.0, 0.0],
+            &light,
+        ),
+        None,
+    );
+
+    scene
+}
+
+/// Returns a quad primitive with texture coordinates spanning [0, 1].
+fn quad(name: &str, points: [[f32; 3]; 4], normal: [f32; 3], material: &str) -> Primitive {
+    Primitive::Quad {
+        name: name.to_string(),
+        points,
+        normal,
+        uv: [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+        material: material.to_string(),
+        facing: None,
+        acceleration: None,
+        tags: vec![],
+        layer: None,
+        array: None,
+        transform: None,
+        velocity: None,
+    }
+}
+
+/// Builds the scene and saves it to the output directory.
+fn save_test_scene(scene: SceneBuilder, output_dir: &str, file_name: &str) -> Result<()> {
+    let path = Path::new(output_dir).join(file_name);
+    println!("Generating test scene {}", path.display());
+    scene.build()?.save_json(&path.to_string_lossy())
+}
+
+/// Returns the form factor from a differential area to a parallel square of the given size
+/// centred directly above it at the given height. The square is split into four rectangles with a
+/// corner directly above the differential area, each of which has a closed form solution.
+fn get_square_form_factor(size: f32, height: f32) -> f32 {
+    let a = size * 0.5 / height;
+    let s = (1.0 + a * a).sqrt();
+    let corner = (2.0 * a / s * (a / s).atan()) / (2.0 * PI);
+    4.0 * corner
+}
+
+/// Returns the mean of each RGB channel over a window of the image centred on the image.
+fn get_window_mean(pixels: &[[f32; 4]], width: u32, height: u32, window: f32) -> Result<[f32; 3]> {
+    if window <= 0.0 || window > 1.0 {
+        return Err(anyhow!("Window must be in (0, 1]"));
+    }
+
+    let window_size = |size: u32| ((size as f32 * window).round() as u32).clamp(1, size);
+    let (window_width, window_height) = (window_size(width), window_size(height));
+    let (x0, y0) = ((width - window_width) / 2, (height - window_height) / 2);
+
+    let mut sum = [0.0_f64; 3];
+    for y in y0..y0 + window_height {
+        for x in x0..x0 + window_width {
+            let p = pixels[(y * width + x) as usize];
+            for i in 0..3 {
+                sum[i] += p[i] as f64;
+            }
+        }
+    }
+
+    let count = (window_width * window_height) as f64;
+    Ok(sum.map(|s| (s / count) as f32))
+}
@@ -0,0 +1,168 @@
+use std::{fs, path::Path, process::Command, time::Instant};
+
+use anyhow::{Context, Result, anyhow};
+use raytracer::{
+    CancellationToken, FilmResponse, RenderHooks, RenderMetadata, Scene, Vk, save_png,
+};
+use scene_file::SceneFile;
+
+use crate::{
+    history::{self, HistoryEntry},
+    render::get_height,
+};
+
+/// Settings for rendering an animation.
+#[derive(Debug)]
+pub struct AnimationSettings<'a> {
+    /// Path to the scene file.
+    pub scene_path: &'a str,
+
+    /// Image width. The height is derived from the scene's aspect ratio.
+    pub width: u32,
+
+    /// Number of frames to render over the scene's time interval [0, 1].
+    pub frames: u32,
+
+    /// Fraction of each frame's duration the shutter is open for motion blur.
+    pub shutter: f32,
+
+    /// Directory to write the image sequence to.
+    pub frames_dir: &'a str,
+
+    /// Frames per second used when encoding the video.
+    pub fps: u32,
+
+    /// Optional path of the video file to encode with `ffmpeg`.
+    pub video_path: Option<&'a str>,
+}
+
+/// Renders each frame of the animation to a PNG image sequence and optionally encodes the frames
+/// into a video. The whole animation is added to the render history log as one render. Cancelling
+/// keeps the frames that were already saved.
+pub fn render_animation(settings: &AnimationSettings, cancel: &CancellationToken) -> Result<()> {
+    if settings.frames == 0 {
+        return Err(anyhow!("At least one frame is required"));
+    }
+    if !(0.0..=1.0).contains(&settings.shutter) {
+        return Err(anyhow!("Shutter must be in [0, 1]"));
+    }
+
+    let scene_file = SceneFile::load_json(settings.scene_path)?;
+    let metadata = RenderMetadata::new(settings.scene_path, &scene_file)?;
+
+    let width = settings.width;
+    let height = get_height(width, &scene_file);
+
+    fs::create_dir_all(settings.frames_dir)
+        .with_context(|| format!("Unable to create directory '{}'", settings.frames_dir))?;
+    remove_frames(settings.frames_dir)?;
+
+    // Encoding to yuv420p requires even frame dimensions.
+    let (width, height) = match settings.video_path {
+        Some(_) => (width.next_multiple_of(2), height.next_multiple_of(2)),
+        None => (width, height),
+    };
+
+    let start = Instant::now();
+    let vk = Vk::new_headless()?;
+    let mut scene = Scene::new_headless(
+        vk,
+        &scene_file,
+        width,
+        height,
+        cancel.clone(),
+        RenderHooks::default(),
+    )?;
+
+    let film = FilmResponse::new(&scene_file.render.film);
+
+    let frame_duration = 1.0 / settings.frames as f32;
+    for frame in 0..settings.frames {
+        let open = frame as f32 * frame_duration;
+        let close = open + settings.shutter * frame_duration;
+
+        println!(
+            "Rendering frame {}/{} at {width} x {height}",
+            frame + 1,
+            settings.frames
+        );
+        scene.set_shutter_interval(open, close)?;
+        let output = scene.render_offline()?;
+
+        let frame_path = Path::new(settings.frames_dir).join(frame_file_name(frame));
+        save_png(
+            &frame_path.to_string_lossy(),
+            width,
+            height,
+            &film.apply_to_pixels(&output.beauty),
+            Some(&metadata),
+        )?;
+    }
+
+    if let Some(video_path) = settings.video_path {
+        encode_video(settings.frames_dir, settings.fps, video_path)?;
+    }
+
+    history::record(&HistoryEntry::new(
+        &metadata,
+        [width, height],
+        start.elapsed(),
+        settings.video_path.unwrap_or(settings.frames_dir),
+    ));
+    Ok(())
+}
+
+/// Returns the file name of a frame in the image sequence.
+fn frame_file_name(frame: u32) -> String {
+    format!("frame_{frame:05}.png")
+}
+
+/// Removes the frames left in the directory by an earlier render so they don't end up in the
+/// video.
+fn remove_frames(frames_dir: &str) -> Result<()> {
+    let entries = fs::read_dir(frames_dir)
+        .with_context(|| format!("Unable to read directory '{frames_dir}'"))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        let is_frame = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("frame_") && name.ends_with(".png"));
+
+        if is_frame {
+            fs::remove_file(&path)
+                .with_context(|| format!("Unable to remove '{}'", path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encodes the image sequence into a video by running `ffmpeg`. The codec is chosen based on the
+/// extension of the video file: VP9 for `.webm` and H.264 otherwise.
+fn encode_video(frames_dir: &str, fps: u32, video_path: &str) -> Result<()> {
+    let input = Path::new(frames_dir).join("frame_%05d.png");
+
+    let is_webm = Path::new(video_path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("webm"));
+    let codec = if is_webm { "libvpx-vp9" } else { "libx264" };
+
+    println!("Encoding {video_path} with ffmpeg ({codec})");
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-framerate"])
+        .arg(fps.to_string())
+        .arg("-i")
+        .arg(&input)
+        .args(["-c:v", codec, "-pix_fmt", "yuv420p"])
+        .arg(video_path)
+        .status()
+        .context("Unable to run ffmpeg. Make sure it is installed and on the PATH")?;
+
+    if !status.success() {
+        return Err(anyhow!("ffmpeg failed with {status}"));
+    }
+
+    Ok(())
+}
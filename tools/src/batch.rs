@@ -0,0 +1,181 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
+use raytracer::{CancellationToken, Cancelled, Vk};
+use scene_file::{RenderOverrides, SceneFile};
+use serde::Deserialize;
+
+use crate::render::{get_height, render_scene};
+
+/// Image width used when neither the job nor the scene file sets the resolution.
+const DEFAULT_WIDTH: u32 = 1280;
+
+/// A list of scenes to render one after another.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The render jobs in the order they are rendered.
+    pub jobs: Vec<Job>,
+}
+
+/// A scene to render along with overrides for its render settings.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// Path to the scene file. Relative paths are relative to the manifest.
+    pub scene: String,
+
+    /// Path to the output image. Use an `.exr` extension to include AOV layers. Relative paths are
+    /// relative to the manifest.
+    pub output: String,
+
+    /// Image width and height. If not set, the scene file's resolution is used or a default width
+    /// with the height derived from the scene's aspect ratio.
+    #[serde(default)]
+    pub resolution: Option<[u32; 2]>,
+
+    /// Replacements for the scene's render settings.
+    #[serde(default)]
+    pub render: RenderOverrides,
+}
+
+/// Outcome of a render job.
+#[derive(Debug)]
+struct JobResult {
+    /// Path to the scene file.
+    scene: PathBuf,
+
+    /// Path to the output image.
+    output: PathBuf,
+
+    /// Time taken to load and render the scene.
+    duration: Duration,
+
+    /// The error if the job failed.
+    error: Option<anyhow::Error>,
+}
+
+/// Renders every job in the manifest headless and prints a summary. A failing job doesn't stop
+/// the remaining ones from rendering. Returns true if all jobs succeeded. Cancelling stops after
+/// printing a summary of the jobs that finished.
+pub fn render_batch(manifest_path: &str, cancel: &CancellationToken) -> Result<bool> {
+    let manifest = load_manifest(manifest_path)?;
+    if manifest.jobs.is_empty() {
+        return Err(anyhow!("Manifest '{manifest_path}' has no jobs"));
+    }
+
+    let base_dir = Path::new(manifest_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let vk = Vk::new_headless()?;
+
+    let job_count = manifest.jobs.len();
+    let mut results = Vec::with_capacity(job_count);
+
+    for (index, job) in manifest.jobs.iter().enumerate() {
+        let scene = base_dir.join(&job.scene);
+        let output = base_dir.join(&job.output);
+        println!("[{}/{job_count}] {}", index + 1, scene.display());
+
+        let start = Instant::now();
+        let error = render_job(vk.clone(), job, &scene, &output, cancel).err();
+        let duration = start.elapsed();
+
+        if error.as_ref().is_some_and(|e| e.is::<Cancelled>()) {
+            print_summary(&results);
+            return Err(Cancelled.into());
+        }
+
+        if let Some(e) = &error {
+            eprintln!("Failed to render {}. {e:?}", scene.display());
+        }
+
+        results.push(JobResult {
+            scene,
+            output,
+            duration,
+            error,
+        });
+    }
+
+    print_summary(&results);
+
+    Ok(results.iter().all(|result| result.error.is_none()))
+}
+
+/// Loads the manifest from a JSON file.
+fn load_manifest(path: &str) -> Result<Manifest> {
+    let serialized =
+        fs::read_to_string(path).with_context(|| format!("Unable to read manifest '{path}'"))?;
+    serde_json::from_str(&serialized).with_context(|| format!("Unable to parse manifest '{path}'"))
+}
+
+/// Renders a single job and saves the output image.
+fn render_job(
+    vk: Arc<Vk>,
+    job: &Job,
+    scene_path: &Path,
+    output_path: &Path,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let scene_path = scene_path.to_string_lossy();
+    let output_path = output_path.to_string_lossy();
+
+    let mut scene_file = SceneFile::load_json(&scene_path)?;
+    scene_file.apply_render_overrides(&job.render);
+
+    let [width, height] = job
+        .resolution
+        .or(scene_file.render.resolution)
+        .unwrap_or_else(|| [DEFAULT_WIDTH, get_height(DEFAULT_WIDTH, &scene_file)]);
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Resolution must be non-zero"));
+    }
+
+    if let Some(dir) = Path::new(output_path.as_ref()).parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create directory '{}'", dir.display()))?;
+    }
+
+    println!(
+        "Rendering at {width} x {height} with {} samples per pixel",
+        scene_file.render.samples_per_pixel * scene_file.render.sample_batches
+    );
+    render_scene(
+        vk,
+        &scene_path,
+        &scene_file,
+        [width, height],
+        &output_path,
+        cancel,
+    )
+}
+
+/// Prints the outcome and time taken for each job.
+fn print_summary(results: &[JobResult]) {
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    let total: Duration = results.iter().map(|r| r.duration).sum();
+
+    println!();
+    println!("Summary:");
+    for result in results {
+        let status = if result.error.is_some() { "FAIL" } else { "OK" };
+        println!(
+            "  {status:<4} {:>8.1}s  {} -> {}",
+            result.duration.as_secs_f32(),
+            result.scene.display(),
+            result.output.display()
+        );
+    }
+    println!(
+        "{} succeeded, {failed} failed in {:.1}s",
+        results.len() - failed,
+        total.as_secs_f32()
+    );
+}
@@ -0,0 +1,173 @@
+use anyhow::{Result, anyhow};
+use glam::Vec2;
+use random::{Random, Scatter};
+use scene_file::{Camera, Film, PixelFilter, Quality, Render, ScalingPolicy, SceneBuilder, Sky};
+
+/// Radius of the spheres scattered on the ground.
+const SPHERE_RADIUS: f32 = 0.2;
+
+/// Distance between neighbouring spheres. The field grows with the number of spheres so the
+/// density stays the same.
+const SPHERE_SPACING: f32 = 0.8;
+
+/// Radius of the light spheres.
+const LIGHT_RADIUS: f32 = 0.5;
+
+/// Settings for generating a benchmark scene.
+#[derive(Debug)]
+pub struct BenchmarkSettings<'a> {
+    /// Number of spheres scattered on the ground.
+    pub spheres: u32,
+
+    /// Number of light emitting spheres floating above the others.
+    pub lights: u32,
+
+    /// Number of distinct albedo textures shared by the spheres' materials.
+    pub textures: u32,
+
+    /// Seed for the random layout, colours and materials. The same settings always generate the
+    /// same scene.
+    pub seed: u64,
+
+    /// Path of the generated scene file.
+    pub output_path: &'a str,
+}
+
+/// Generates a stress scene for measuring performance. Spheres are scattered on a jittered grid
+/// on a ground sphere, each using one of the textured materials, with lights floating above them.
+/// Sweeping one setting while keeping the others and the seed fixed varies one aspect of the
+/// scene's complexity at a time.
+pub fn generate_benchmark_scene(settings: &BenchmarkSettings) -> Result<()> {
+    if settings.spheres == 0 {
+        return Err(anyhow!("At least one sphere is required"));
+    }
+    if settings.textures == 0 {
+        return Err(anyhow!("At least one texture is required"));
+    }
+
+    println!(
+        "Generating benchmark scene {} with {} spheres, {} lights and {} textures (seed {})",
+        settings.output_path, settings.spheres, settings.lights, settings.textures, settings.seed
+    );
+
+    Random::seed(settings.seed);
+
+    let columns = (settings.spheres as f32).sqrt().ceil() as u32;
+    let half_extent = columns as f32 * SPHERE_SPACING * 0.5;
+
+    let render = Render {
+        camera: "default".to_string(),
+        samples_per_pixel: 4,
+        sample_batches: 16,
+        max_ray_depth: 10,
+        aspect_ratio: 16.0 / 9.0,
+        resolution: None,
+        scaling: ScalingPolicy::default(),
+        strict_textures: false,
+        layers: None,
+        tags: None,
+        film: Film::default(),
+        pixel_filter: PixelFilter::default(),
+        supersample: None,
+        shutter: None,
+        caustics: false,
+        transparent_shadows: false,
+        denoise: false,
+        texture_filter: None,
+        strip_unused: false,
+        compact_acceleration: false,
+        budget: None,
+        quality: Quality::default(),
+    };
+
+    // Keep the sky dim when there are lights so they dominate the lighting.
+    let sky = if settings.lights > 0 {
+        Sky::Solid {
+            rgb: [0.05, 0.05, 0.06],
+        }
+    } else {
+        Sky::VerticalGradient {
+            factor: 0.5,
+            top: [0.5, 0.7, 1.0],
+            bottom: [1.0, 1.0, 1.0],
+        }
+    };
+
+    let mut scene = SceneBuilder::new(render, sky);
+
+    // Look down at the field from far enough away to see all of it.
+    let distance = half_extent * 2.5 + 5.0;
+    scene.add_camera(Camera::Perspective {
+        name: "default".to_string(),
+        eye: [0.0, -distance * 0.5, distance],
+        look_at: [0.0, 0.0, 0.0],
+        up: Some([0.0, 1.0, 0.0]),
+        orientation: None,
+        fov_y: 40.0,
+        z_near: 0.01,
+        z_far: distance * 4.0,
+        focal_length: 1.0,
+        aperture_size: 0.0,
+        exposure: None,
+    });
+
+    // Cycle through the texture types so every type is used once there are enough textures.
+    let materials: Vec<String> = (0..settings.textures)
+        .map(|i| {
+            let albedo = match i % 3 {
+                0 => scene.constant_texture(Random::vec3().to_array()),
+                1 => {
+                    let even = scene.constant_texture(Random::vec3().to_array());
+                    let odd = scene.constant_texture(Random::vec3().to_array());
+                    scene.checker_texture(Random::sample_in_range(0.05, 0.2), &even, &odd)
+                }
+                _ => scene.noise_texture(Random::sample_in_range(2.0, 8.0)),
+            };
+            scene.lambertian(&albedo)
+        })
+        .collect();
+
+    let ground_material = scene.lambertian_rgb([0.5, 0.5, 0.5]);
+    let ground = scene
+        .add_sphere([0.0, 1000.0, 0.0], 1000.0)
+        .name("ground")
+        .tessellation(128, 256)
+        .material(&ground_material)
+        .build()?;
+
+    let centers = Scatter::jittered_grid(
+        Vec2::splat(-half_extent),
+        Vec2::splat(half_extent),
+        [columns, columns],
+        0.5,
+    );
+    for (i, center) in centers
+        .into_iter()
+        .take(settings.spheres as usize)
+        .enumerate()
+    {
+        let material = &materials[Random::sample_in_range(0, materials.len())];
+        scene
+            .add_sphere([center.x, -SPHERE_RADIUS, center.y], SPHERE_RADIUS)
+            .name(&format!("sphere_{i}"))
+            .tessellation(16, 32)
+            .material(material)
+            .touching_ground(&ground)
+            .build()?;
+    }
+
+    for i in 0..settings.lights {
+        let material = scene.diffuse_light_rgb(Random::vec3_in_range(2.0, 6.0).to_array());
+        let x = Random::sample_in_range(-half_extent, half_extent);
+        let z = Random::sample_in_range(-half_extent, half_extent);
+        let height = Random::sample_in_range(2.0, 4.0);
+        scene
+            .add_sphere([x, -height, z], LIGHT_RADIUS)
+            .name(&format!("light_{i}"))
+            .tessellation(16, 32)
+            .material(&material)
+            .build()?;
+    }
+
+    scene.build()?.save_json(settings.output_path)
+}
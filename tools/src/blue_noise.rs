@@ -0,0 +1,26 @@
+use anyhow::{Context, Result, anyhow};
+use image::{Rgba, RgbaImage};
+use random::{BlueNoise, Random};
+
+/// Generates a tiling RGBA blue noise texture. Each channel is an independent blue noise pattern
+/// so channels can be combined for 2D jitter without being correlated.
+pub fn generate_blue_noise(size: u32, seed: u64, output_path: &str) -> Result<()> {
+    if size == 0 {
+        return Err(anyhow!("Size must be non-zero"));
+    }
+
+    println!("Generating {size} x {size} blue noise texture {output_path} (seed {seed})");
+
+    Random::seed(seed);
+    let channels: Vec<Vec<f32>> = (0..4)
+        .map(|_| BlueNoise::void_and_cluster(size as usize))
+        .collect();
+
+    let image = RgbaImage::from_fn(size, size, |x, y| {
+        let i = (y * size + x) as usize;
+        Rgba([0, 1, 2, 3].map(|c| (channels[c][i] * 256.0) as u8))
+    });
+    image
+        .save(output_path)
+        .with_context(|| format!("Unable to save blue noise texture '{output_path}'"))
+}
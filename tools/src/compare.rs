@@ -0,0 +1,147 @@
+use anyhow::{Context, Result, anyhow};
+use raytracer::{CancellationToken, RenderHooks, Scene, Vk, linear_to_srgb, save_png};
+use scene_file::SceneFile;
+
+/// Window size used for computing SSIM.
+const SSIM_WINDOW_SIZE: usize = 8;
+
+/// Results of comparing a rendered image against a reference image.
+#[derive(Debug)]
+pub struct ComparisonResult {
+    /// Root mean squared error over RGB channels in sRGB space [0, 1].
+    pub rmse: f32,
+
+    /// Mean structural similarity over luminance.
+    pub ssim: f32,
+}
+
+/// Renders the scene deterministically at the resolution of the reference image and compares the
+/// two. Returns true if the RMSE is within the threshold.
+pub fn compare(
+    scene_path: &str,
+    reference_path: &str,
+    threshold: f32,
+    output_path: Option<&str>,
+    cancel: &CancellationToken,
+) -> Result<bool> {
+    let reference = image::open(reference_path)
+        .with_context(|| format!("Unable to open reference image '{reference_path}'"))?
+        .to_rgb32f();
+    let (width, height) = reference.dimensions();
+
+    let scene_file = SceneFile::load_json(scene_path)?;
+
+    println!("Rendering {scene_path} at {width} x {height}");
+    let vk = Vk::new_headless()?;
+    let mut scene = Scene::new_headless(
+        vk,
+        &scene_file,
+        width,
+        height,
+        cancel.clone(),
+        RenderHooks::default(),
+    )?;
+    let pixels = scene.render_offline()?.beauty;
+
+    if let Some(output_path) = output_path {
+        save_png(output_path, width, height, &pixels, None)?;
+    }
+
+    // The reference image is stored in sRGB so compare in that space.
+    let rendered: Vec<[f32; 3]> = pixels
+        .iter()
+        .map(|p| [p[0], p[1], p[2]].map(|c| linear_to_srgb(c.max(0.0)).clamp(0.0, 1.0)))
+        .collect();
+    let reference: Vec<[f32; 3]> = reference.pixels().map(|p| p.0).collect();
+
+    let result = compare_images(&rendered, &reference, width as usize, height as usize)?;
+    let passed = result.rmse <= threshold;
+
+    println!(
+        "RMSE: {:.6} (threshold {threshold}), SSIM: {:.6} => {}",
+        result.rmse,
+        result.ssim,
+        if passed { "PASS" } else { "FAIL" }
+    );
+
+    Ok(passed)
+}
+
+/// Computes the RMSE and SSIM between two images of the same size.
+pub fn compare_images(
+    a: &[[f32; 3]],
+    b: &[[f32; 3]],
+    width: usize,
+    height: usize,
+) -> Result<ComparisonResult> {
+    if a.len() != b.len() || a.len() != width * height {
+        return Err(anyhow!("Image sizes do not match"));
+    }
+
+    Ok(ComparisonResult {
+        rmse: rmse(a, b),
+        ssim: ssim(a, b, width, height),
+    })
+}
+
+/// Root mean squared error over all RGB channels.
+fn rmse(a: &[[f32; 3]], b: &[[f32; 3]]) -> f32 {
+    let sum = a.iter().zip(b.iter()).fold(0.0_f64, |acc, (pa, pb)| {
+        acc + (0..3)
+            .map(|i| ((pa[i] - pb[i]) as f64).powi(2))
+            .sum::<f64>()
+    });
+    (sum / (a.len() * 3) as f64).sqrt() as f32
+}
+
+/// Mean SSIM over luminance using non-overlapping windows.
+/// See https://en.wikipedia.org/wiki/Structural_similarity_index_measure.
+fn ssim(a: &[[f32; 3]], b: &[[f32; 3]], width: usize, height: usize) -> f32 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let luma_a: Vec<f64> = a.iter().map(luminance).collect();
+    let luma_b: Vec<f64> = b.iter().map(luminance).collect();
+
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for y0 in (0..height).step_by(SSIM_WINDOW_SIZE) {
+        for x0 in (0..width).step_by(SSIM_WINDOW_SIZE) {
+            let indices: Vec<usize> = (y0..(y0 + SSIM_WINDOW_SIZE).min(height))
+                .flat_map(|y| (x0..(x0 + SSIM_WINDOW_SIZE).min(width)).map(move |x| y * width + x))
+                .collect();
+            let n = indices.len() as f64;
+
+            let mean_a = indices.iter().map(|&i| luma_a[i]).sum::<f64>() / n;
+            let mean_b = indices.iter().map(|&i| luma_b[i]).sum::<f64>() / n;
+
+            let (mut var_a, mut var_b, mut covar) = (0.0, 0.0, 0.0);
+            for &i in indices.iter() {
+                let da = luma_a[i] - mean_a;
+                let db = luma_b[i] - mean_b;
+                var_a += da * da;
+                var_b += db * db;
+                covar += da * db;
+            }
+            var_a /= n;
+            var_b /= n;
+            covar /= n;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+
+    if windows > 0 {
+        (total / windows as f64) as f32
+    } else {
+        1.0
+    }
+}
+
+/// Relative luminance of an RGB colour.
+fn luminance(c: &[f32; 3]) -> f64 {
+    0.2126 * c[0] as f64 + 0.7152 * c[1] as f64 + 0.0722 * c[2] as f64
+}
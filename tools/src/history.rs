@@ -0,0 +1,191 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use raytracer::RenderMetadata;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable setting the render history log. Renders are logged to `renders.log` in
+/// the working directory if it isn't set.
+pub const HISTORY_ENV: &str = "RAYTRACER_RENDER_LOG";
+
+/// Render history log used if `RAYTRACER_RENDER_LOG` isn't set.
+const DEFAULT_HISTORY_PATH: &str = "renders.log";
+
+/// A completed offline render. Each entry is stored as one line of JSON in the history log.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// Time the render finished in seconds since the Unix epoch.
+    pub finished_at: u64,
+
+    /// Path of the scene file that was rendered.
+    pub scene_path: String,
+
+    /// FNV-1a hash of the scene file contents.
+    pub scene_hash: String,
+
+    /// Image width and height.
+    pub resolution: [u32; 2],
+
+    /// Samples per pixel per batch.
+    pub samples_per_pixel: u32,
+
+    /// Number of sample batches.
+    pub sample_batches: u32,
+
+    /// Maximum ray depth.
+    pub max_ray_depth: u32,
+
+    /// Name of the camera used to render.
+    pub camera: String,
+
+    /// Time taken to render and save the output in seconds.
+    pub duration: f32,
+
+    /// Path of the output image, video or image sequence directory.
+    pub output_path: String,
+
+    /// Commit hash of the renderer.
+    pub commit_hash: String,
+}
+
+impl HistoryEntry {
+    /// Creates an entry for a render that just finished.
+    pub fn new(
+        metadata: &RenderMetadata,
+        resolution: [u32; 2],
+        duration: Duration,
+        output_path: &str,
+    ) -> Self {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            finished_at,
+            scene_path: metadata.scene_path.clone(),
+            scene_hash: metadata.scene_hash.clone(),
+            resolution,
+            samples_per_pixel: metadata.samples_per_pixel,
+            sample_batches: metadata.sample_batches,
+            max_ray_depth: metadata.max_ray_depth,
+            camera: metadata.camera.clone(),
+            duration: duration.as_secs_f32(),
+            output_path: output_path.to_string(),
+            commit_hash: metadata.commit_hash.clone(),
+        }
+    }
+}
+
+/// Appends a completed render to the history log. Nothing is sent anywhere; the log only exists
+/// on this machine. A render isn't failed because it couldn't be logged, so errors are printed
+/// instead.
+pub fn record(entry: &HistoryEntry) {
+    if let Err(e) = append(entry) {
+        eprintln!("Unable to add the render to the history log. {e:?}");
+    }
+}
+
+/// Prints the logged renders, oldest first. Only renders of scenes whose path contains `scene`
+/// are printed if it is given, and only the last `limit` of them.
+pub fn print_history(scene: Option<&str>, limit: Option<usize>) -> Result<()> {
+    let path = get_history_path();
+    let serialized = match fs::read_to_string(&path) {
+        Ok(serialized) => serialized,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!("No renders have been logged to {path}");
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Unable to read render history '{path}'"));
+        }
+    };
+
+    let entries = serialized
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str::<HistoryEntry>(line)
+                .with_context(|| format!("Unable to parse line {} of '{path}'", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let matching: Vec<_> = entries
+        .iter()
+        .filter(|entry| scene.is_none_or(|scene| entry.scene_path.contains(scene)))
+        .collect();
+    let skip = limit.map_or(0, |limit| matching.len().saturating_sub(limit));
+
+    for entry in &matching[skip..] {
+        println!(
+            "{}  {:>8.1}s  {} ({}) -> {}",
+            format_utc(entry.finished_at),
+            entry.duration,
+            entry.scene_path,
+            entry.scene_hash,
+            entry.output_path
+        );
+        println!(
+            "    {} x {}, {} spp x {} batches, depth {}, camera {}, commit {}",
+            entry.resolution[0],
+            entry.resolution[1],
+            entry.samples_per_pixel,
+            entry.sample_batches,
+            entry.max_ray_depth,
+            entry.camera,
+            entry.commit_hash
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the path of the history log.
+fn get_history_path() -> String {
+    env::var(HISTORY_ENV).unwrap_or_else(|_| DEFAULT_HISTORY_PATH.to_string())
+}
+
+/// Appends an entry to the history log as a line of JSON.
+fn append(entry: &HistoryEntry) -> Result<()> {
+    let path = get_history_path();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Unable to open render history '{path}'"))?;
+
+    // Write the line at once so renders finishing at the same time don't interleave.
+    let line = format!("{}\n", serde_json::to_string(entry)?);
+    file.write_all(line.as_bytes())
+        .with_context(|| format!("Unable to write render history '{path}'"))
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+fn format_utc(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // Convert days to a civil date (Howard Hinnant's days_from_civil in reverse).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
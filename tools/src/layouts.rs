@@ -0,0 +1,45 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use raytracer::{CancellationToken, RenderHooks, Scene, Vk};
+use scene_file::SceneFile;
+
+use crate::render::get_height;
+
+/// Width of the image the scene is built for. Nothing is rendered so it only needs to be valid.
+const WIDTH: u32 = 64;
+
+/// Builds a scene file and writes the order of its textures and materials in the buffers indexed
+/// by shaders as JSON to `output_path`, or prints it if not set.
+pub fn dump_layouts(
+    scene_path: &str,
+    output_path: Option<&str>,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let scene_file = SceneFile::load_json(scene_path)?;
+    let height = get_height(WIDTH, &scene_file);
+
+    let vk = Vk::new_headless()?;
+    let scene = Scene::new_headless(
+        vk,
+        &scene_file,
+        WIDTH,
+        height,
+        cancel.clone(),
+        RenderHooks::default(),
+    )?;
+
+    let json = scene
+        .get_buffer_layouts()
+        .context("Scene has no buffer layouts")?
+        .to_json()?;
+
+    match output_path {
+        Some(path) => {
+            fs::write(path, json).with_context(|| format!("Unable to write '{path}'"))?;
+            println!("Wrote buffer layouts to {path}");
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
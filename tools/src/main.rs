@@ -1,12 +1,33 @@
+mod analytic;
+mod animation;
+mod batch;
+mod benchmark;
+mod blue_noise;
+mod compare;
+mod history;
+mod layouts;
+mod pack;
+mod preview;
+mod render;
+mod validate;
+mod verify;
+
+use std::path::Path;
+
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use glam::Vec3;
-use random::Random;
+use glam::{Vec2, Vec3};
+use random::{Exclusion, Random, Scatter};
+use raytracer::{CancellationToken, Cancelled};
 use scene_file::{
-    Camera, Instance, Material, Primitive, Render, SceneFile, Sky, Texture, Transform,
-    TransformType,
+    Camera, Film, PixelFilter, Quality, Render, ScalingPolicy, SceneBuilder, SceneFile, Sky,
+    Transform, TransformType,
 };
 
+/// Exit code used when a command is cancelled with Ctrl+C, following the shell convention for
+/// processes stopped by SIGINT.
+const CANCELLED_EXIT_CODE: i32 = 130;
+
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
@@ -17,6 +38,234 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Commands {
     GenFinalOneWeekend,
+
+    /// Generate a stress scene with the given number of spheres, lights and textures for
+    /// measuring performance across scene complexity.
+    GenBenchmark {
+        /// Number of spheres scattered on the ground.
+        #[arg(long, default_value_t = 100)]
+        spheres: u32,
+
+        /// Number of light emitting spheres.
+        #[arg(long, default_value_t = 4)]
+        lights: u32,
+
+        /// Number of distinct textures used by the spheres.
+        #[arg(long, default_value_t = 8)]
+        textures: u32,
+
+        /// Seed for the random layout and materials.
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+
+        /// Path of the generated scene file.
+        #[arg(long, default_value = "assets/benchmark.json")]
+        output: String,
+    },
+
+    /// Generate the tiling blue noise texture used by the renderer for pixel jitter and dithering.
+    GenBlueNoise {
+        /// Width and height of the texture.
+        #[arg(long, default_value_t = 64)]
+        size: u32,
+
+        /// Seed for the initial random pattern.
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+
+        /// Path of the generated PNG.
+        #[arg(long, default_value = "assets/blue-noise.png")]
+        output: String,
+    },
+
+    /// Wrap a material definition in a standard shaderball scene and render it, for material
+    /// library thumbnails. The definition is a JSON object with the `material` and the `textures`
+    /// it references, in the same format as in scene files.
+    GenMaterialPreview {
+        /// Path to the material definition.
+        #[arg(long)]
+        material: String,
+
+        /// Path to the preview image. Defaults to the material definition's path with a `.png`
+        /// extension. The generated scene file is saved next to it with a `-scene` suffix.
+        #[arg(long)]
+        output: Option<String>,
+
+        /// Width and height of the preview image.
+        #[arg(long, default_value_t = 256)]
+        size: u32,
+
+        /// Number of images to render while the camera turns around the shaderball.
+        #[arg(long, default_value_t = 1)]
+        frames: u32,
+    },
+
+    /// Generate validation scenes with analytic solutions, such as white furnace tests, and a test
+    /// suite with their expected results.
+    GenTests {
+        /// Directory to write the scenes and test suite to.
+        #[arg(long, default_value = "assets/tests")]
+        output_dir: String,
+    },
+
+    /// Render the scenes in a test suite and compare their mean radiance against the expected
+    /// values.
+    RunTests {
+        /// Path to the test suite.
+        #[arg(long, default_value = "assets/tests/tests.json")]
+        suite: String,
+
+        /// Image width. The height is derived from the scene's aspect ratio.
+        #[arg(long, default_value_t = 128)]
+        width: u32,
+    },
+
+    /// Print the offline renders logged to the render history, oldest first.
+    History {
+        /// Only print renders of scene files whose path contains this text.
+        #[arg(long)]
+        scene: Option<String>,
+
+        /// Only print this many of the most recent renders.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Render a scene deterministically and compare it against a reference image.
+    Compare {
+        /// Path to the scene file.
+        #[arg(long)]
+        scene: String,
+
+        /// Path to the reference image.
+        #[arg(long)]
+        reference: String,
+
+        /// Maximum RMSE allowed before the comparison fails.
+        #[arg(long, default_value_t = 0.01)]
+        threshold: f32,
+
+        /// Optional path to save the rendered image.
+        #[arg(long)]
+        output: Option<String>,
+    },
+
+    /// Render a scene deterministically and save it as PNG or multi-layer OpenEXR.
+    Render {
+        /// Path to the scene file.
+        #[arg(long)]
+        scene: String,
+
+        /// Image width. The height is derived from the scene's aspect ratio.
+        #[arg(long, default_value_t = 1280)]
+        width: u32,
+
+        /// Path to the output image. Use an `.exr` extension to include AOV layers.
+        #[arg(long)]
+        output: String,
+
+        /// Render each render layer to its own image with a `-layer<n>` suffix.
+        #[arg(long)]
+        separate_layers: bool,
+    },
+
+    /// Render an animation as an image sequence and optionally encode it to a video with ffmpeg.
+    RenderAnimation {
+        /// Path to the scene file.
+        #[arg(long)]
+        scene: String,
+
+        /// Image width. The height is derived from the scene's aspect ratio.
+        #[arg(long, default_value_t = 1280)]
+        width: u32,
+
+        /// Number of frames to render.
+        #[arg(long, default_value_t = 24)]
+        frames: u32,
+
+        /// Fraction of each frame's duration the shutter is open.
+        #[arg(long, default_value_t = 0.5)]
+        shutter: f32,
+
+        /// Directory to write the image sequence to.
+        #[arg(long, default_value = "frames")]
+        frames_dir: String,
+
+        /// Frames per second of the video.
+        #[arg(long, default_value_t = 24)]
+        fps: u32,
+
+        /// Optional path to the video file (`.mp4` or `.webm`).
+        #[arg(long)]
+        video: Option<String>,
+    },
+
+    /// Render the scenes listed in a JSON manifest one after another and print a summary.
+    RenderBatch {
+        /// Path to the manifest.
+        #[arg(long)]
+        manifest: String,
+    },
+
+    /// Bundle a scene file and the files it references into a single scene package. Packages can
+    /// be loaded like scene files.
+    Pack {
+        /// Path to the scene file.
+        #[arg(long)]
+        scene: String,
+
+        /// Path to the scene package, e.g. `scene.scenepkg`.
+        #[arg(long)]
+        output: String,
+    },
+
+    /// Print the JSON Schema for scene files.
+    Schema,
+
+    /// Build a scene and dump the order of its textures and materials in the buffers indexed by
+    /// shaders as JSON, for debugging index mismatches between the renderer and shaders.
+    DumpLayouts {
+        /// Path to the scene file.
+        #[arg(long)]
+        scene: String,
+
+        /// Path to the JSON file. The layouts are printed if not set.
+        #[arg(long)]
+        output: Option<String>,
+    },
+
+    /// Check meshes for invalid normals, out of range indices, inconsistent winding and holes.
+    ValidateMeshes {
+        /// Path to a scene file whose primitives are checked. If not set, UV spheres are checked
+        /// with every pole and seam option.
+        #[arg(long)]
+        scene: Option<String>,
+    },
+
+    /// Render a scene with material sampling only and with light source sampling, and check the
+    /// estimators converge to the same image. Used to validate new sampling strategies.
+    VerifyEstimators {
+        /// Path to the scene file.
+        #[arg(long)]
+        scene: String,
+
+        /// Image width. The height is derived from the scene's aspect ratio.
+        #[arg(long, default_value_t = 320)]
+        width: u32,
+
+        /// Maximum relative difference of the mean radiance in any channel before the
+        /// verification fails.
+        #[arg(long, default_value_t = 0.02)]
+        threshold: f32,
+
+        /// Factor applied to the per-pixel difference in the difference image.
+        #[arg(long, default_value_t = 4.0)]
+        scale: f32,
+
+        /// Optional path to save the per-pixel difference image.
+        #[arg(long)]
+        output: Option<String>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -24,11 +273,166 @@ fn main() -> Result<()> {
 
     Random::seed(485_674_845_675_491);
 
+    // Ctrl+C stops the running command cleanly, e.g. between sample batches of a render, and a
+    // second Ctrl+C exits straight away.
+    let cancel = CancellationToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(CANCELLED_EXIT_CODE);
+        }
+        eprintln!("Cancelling. Press Ctrl+C again to exit immediately.");
+        handler_cancel.cancel();
+    })?;
+
+    match run(&cli, &cancel) {
+        Err(e) if e.is::<Cancelled>() => {
+            eprintln!("Cancelled");
+            std::process::exit(CANCELLED_EXIT_CODE);
+        }
+        result => result,
+    }
+}
+
+/// Runs the command given on the command line. Long-running commands stop with a `Cancelled`
+/// error when `cancel` is cancelled.
+fn run(cli: &Cli, cancel: &CancellationToken) -> Result<()> {
     match &cli.command {
         Some(Commands::GenFinalOneWeekend) => {
             generate_final_one_weekend_scene("assets/final-one-weekend.json", false)?;
             generate_final_one_weekend_scene("assets/final-one-weekend-motion-blur.json", true)?;
         }
+        Some(Commands::GenBenchmark {
+            spheres,
+            lights,
+            textures,
+            seed,
+            output,
+        }) => {
+            benchmark::generate_benchmark_scene(&benchmark::BenchmarkSettings {
+                spheres: *spheres,
+                lights: *lights,
+                textures: *textures,
+                seed: *seed,
+                output_path: output,
+            })?;
+        }
+        Some(Commands::GenBlueNoise { size, seed, output }) => {
+            blue_noise::generate_blue_noise(*size, *seed, output)?;
+        }
+        Some(Commands::GenMaterialPreview {
+            material,
+            output,
+            size,
+            frames,
+        }) => {
+            let output = output.clone().unwrap_or_else(|| {
+                Path::new(material)
+                    .with_extension("png")
+                    .to_string_lossy()
+                    .into_owned()
+            });
+            preview::generate_material_preview(
+                &preview::PreviewSettings {
+                    material_path: material,
+                    output_path: &output,
+                    size: *size,
+                    frames: *frames,
+                },
+                cancel,
+            )?;
+        }
+        Some(Commands::GenTests { output_dir }) => {
+            analytic::generate_test_scenes(output_dir)?;
+        }
+        Some(Commands::RunTests { suite, width }) => {
+            if !analytic::run_tests(suite, *width, cancel)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::History { scene, limit }) => {
+            history::print_history(scene.as_deref(), *limit)?;
+        }
+        Some(Commands::Compare {
+            scene,
+            reference,
+            threshold,
+            output,
+        }) => {
+            if !compare::compare(scene, reference, *threshold, output.as_deref(), cancel)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Render {
+            scene,
+            width,
+            output,
+            separate_layers,
+        }) => {
+            render::render(scene, *width, output, *separate_layers, cancel)?;
+        }
+        Some(Commands::RenderAnimation {
+            scene,
+            width,
+            frames,
+            shutter,
+            frames_dir,
+            fps,
+            video,
+        }) => {
+            animation::render_animation(
+                &animation::AnimationSettings {
+                    scene_path: scene,
+                    width: *width,
+                    frames: *frames,
+                    shutter: *shutter,
+                    frames_dir,
+                    fps: *fps,
+                    video_path: video.as_deref(),
+                },
+                cancel,
+            )?;
+        }
+        Some(Commands::RenderBatch { manifest }) => {
+            if !batch::render_batch(manifest, cancel)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Pack { scene, output }) => {
+            pack::pack(scene, output)?;
+        }
+        Some(Commands::Schema) => {
+            println!("{}", SceneFile::get_json_schema()?);
+        }
+        Some(Commands::DumpLayouts { scene, output }) => {
+            layouts::dump_layouts(scene, output.as_deref(), cancel)?;
+        }
+        Some(Commands::ValidateMeshes { scene }) => {
+            if !validate::validate_meshes(scene.as_deref())? {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::VerifyEstimators {
+            scene,
+            width,
+            threshold,
+            scale,
+            output,
+        }) => {
+            let passed = verify::verify_estimators(
+                &verify::VerifySettings {
+                    scene_path: scene,
+                    width: *width,
+                    threshold: *threshold,
+                    scale: *scale,
+                    output_path: output.as_deref(),
+                },
+                cancel,
+            )?;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
         None => {
             println!("Please specify a command");
         }
@@ -37,18 +441,6 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn make_sphere_touch_ground(
-    sphere_center: &[f32; 3],
-    sphere_radius: f32,
-    ground_sphere_center: &[f32; 3],
-    ground_sphere_radius: f32,
-) -> [f32; 3] {
-    let g_center = Vec3::from_slice(ground_sphere_center);
-    let dir = Vec3::from_slice(sphere_center) - g_center;
-    const FUDGE: f32 = 0.035; // Pushes the sphere into the ground a little.
-    (dir.normalize() * (ground_sphere_radius + sphere_radius - FUDGE) + g_center).to_array()
-}
-
 fn generate_final_one_weekend_scene(file_path: &str, do_motion_blur: bool) -> Result<()> {
     println!(
         "Generating Raytracing in One Weekend final scene file {file_path} {}",
@@ -59,268 +451,158 @@ fn generate_final_one_weekend_scene(file_path: &str, do_motion_blur: bool) -> Re
         }
     );
 
-    let mut primitives = vec![];
-    let mut instances = vec![];
-    let mut textures = vec![];
-    let mut materials = vec![];
-    let mut cameras = vec![];
-
-    let green_texture = Texture::Constant {
-        name: "green".to_string(),
-        rgb: [0.2, 0.3, 0.1],
-    };
-    let white_texture = Texture::Constant {
-        name: "pale-white".to_string(),
-        rgb: [0.9, 0.9, 0.9],
-    };
-    let green_and_white_checker_texture = Texture::Checker {
-        name: "green-and-white-checker".to_string(),
-        scale: 0.32,
-        even: green_texture.get_name().to_string(),
-        odd: white_texture.get_name().to_string(),
-    };
-
-    let ground_material = Material::Lambertian {
-        name: "ground".to_string(),
-        albedo: green_and_white_checker_texture.get_name().to_string(),
-    };
-
-    let ground_center = [0.0, 1000.0, 0.0];
-    let ground_radius = 1000.0;
-
-    primitives.push(Primitive::UvSphere {
-        name: "ground_sphere".to_string(),
-        center: ground_center,
-        radius: ground_radius,
-        rings: 128,
-        segments: 256,
-        material: ground_material.get_name().to_string(),
-    });
-    textures.push(green_texture);
-    textures.push(white_texture);
-    textures.push(green_and_white_checker_texture);
-    materials.push(ground_material);
-    instances.push(Instance {
-        name: "ground_sphere".to_string(),
-        transform: None,
-    });
-
-    let center_sphere_1 = Vec3::new(0.0, -1.0, 0.0);
-    let center_sphere_2 = Vec3::from_array(make_sphere_touch_ground(
-        &[-4.0, -1.0, 0.0],
-        1.0,
-        &ground_center,
-        ground_radius,
-    ));
-    let center_sphere_3 = Vec3::from_array(make_sphere_touch_ground(
-        &[4.0, -1.0, 0.0],
-        1.0,
-        &ground_center,
-        ground_radius,
-    ));
-
-    let center_spheres_radius = 1.0;
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat: f32 = Random::sample();
-
-            let radius = 0.2;
-            let mut center: [f32; 3];
-
-            loop {
-                center = [
-                    a as f32 + 0.9 * Random::sample::<f32>(),
-                    -radius,
-                    b as f32 + 0.9 * Random::sample::<f32>(),
-                ];
-                center = make_sphere_touch_ground(&center, radius, &ground_center, ground_radius);
-
-                let p_center = Vec3::from_slice(&center);
-
-                let total_radius = center_spheres_radius + radius;
-                if (p_center - center_sphere_1).length() > total_radius
-                    && (p_center - center_sphere_2).length() > total_radius
-                    && (p_center - center_sphere_3).length() > total_radius
-                {
-                    break;
-                }
-            }
-
-            let (tex, material, transform) = if choose_mat < 0.8 {
-                // diffuse
-                let name = format!("diffuse_{a}_{b}");
-                let t_albedo = Texture::Constant {
-                    name: format!("tex_albedo_{name}"),
-                    rgb: (Random::vec3() * Random::vec3()).to_array(),
-                };
-                let mat = Material::Lambertian {
-                    name: format!("mat_{name}"),
-                    albedo: t_albedo.get_name().to_string(),
-                };
-                let transform = if do_motion_blur {
-                    Some(TransformType::Animated(
-                        Transform {
-                            translate: Some([0.0, Random::sample_in_range(-0.5, 0.0), 0.0]),
-                            rotate: None,
-                            scale: None,
-                        },
-                        Transform {
-                            translate: Some([0.0, 0.0, 0.0]),
-                            rotate: None,
-                            scale: None,
-                        },
-                    ))
-                } else {
-                    None
-                };
-                (vec![t_albedo], mat, transform)
-            } else if choose_mat < 0.95 {
-                // metal
-                let name = format!("metal_{a}_{b}");
-                let t_albedo = Texture::Constant {
-                    name: format!("tex_albedo_{name}"),
-                    rgb: Random::vec3_in_range(0.5, 1.0).to_array(),
-                };
-                let t_fuzz = Texture::Constant {
-                    name: format!("tex_fuzz_{name}"),
-                    rgb: Random::vec3_in_range(0.0, 0.5).to_array(),
-                };
-                let mat = Material::Metal {
-                    name: format!("mat_metal_{a}_{b}"),
-                    albedo: t_albedo.get_name().to_string(),
-                    fuzz: t_fuzz.get_name().to_string(),
-                };
-                (vec![t_albedo, t_fuzz], mat, None)
-            } else {
-                // glass
-                let mat = Material::Dielectric {
-                    name: format!("mat_dielectric_{a}_{b}"),
-                    refraction_index: 1.5,
-                };
-                (vec![], mat, None)
-            };
-
-            let name = format!("sphere_{a}_{b}").to_string();
-            primitives.push(Primitive::UvSphere {
-                name: name.clone(),
-                center,
-                radius,
-                rings: 32,
-                segments: 64,
-                material: material.get_name().to_string(),
-            });
-            instances.push(Instance { name, transform });
-
-            textures.extend_from_slice(&tex);
-            materials.push(material);
-        }
-    }
-
-    let material1 = Material::Dielectric {
-        name: "material1".to_string(),
-        refraction_index: 1.5,
+    let render = Render {
+        camera: "default".to_string(),
+        samples_per_pixel: 4,
+        sample_batches: 25,
+        max_ray_depth: 50,
+        aspect_ratio: 16.0 / 9.0,
+        resolution: None,
+        scaling: ScalingPolicy::default(),
+        strict_textures: false,
+        layers: None,
+        tags: None,
+        film: Film::default(),
+        pixel_filter: PixelFilter::default(),
+        supersample: None,
+        shutter: None,
+        caustics: false,
+        transparent_shadows: false,
+        denoise: false,
+        texture_filter: None,
+        strip_unused: false,
+        compact_acceleration: false,
+        budget: None,
+        quality: Quality::default(),
     };
-    primitives.push(Primitive::UvSphere {
-        name: "sphere1".to_string(),
-        center: center_sphere_1.to_array(),
-        radius: center_spheres_radius,
-        rings: 64,
-        segments: 128,
-        material: material1.get_name().to_string(),
-    });
-    materials.push(material1);
-    instances.push(Instance {
-        name: "sphere1".to_string(),
-        transform: None,
-    });
 
-    let texture2 = Texture::Constant {
-        name: "texture2".to_string(),
-        rgb: [0.4, 0.2, 0.1],
-    };
-    let material2 = Material::Lambertian {
-        name: "material2".to_string(),
-        albedo: texture2.get_name().to_string(),
+    let sky = Sky::VerticalGradient {
+        factor: 0.5,
+        top: [0.5, 0.7, 1.0],
+        bottom: [1.0, 1.0, 1.0],
     };
-    primitives.push(Primitive::UvSphere {
-        name: "sphere2".to_string(),
-        center: center_sphere_2.to_array(),
-        radius: center_spheres_radius,
-        rings: 64,
-        segments: 128,
-        material: material2.get_name().to_string(),
-    });
-    textures.push(texture2);
-    materials.push(material2);
-    instances.push(Instance {
-        name: "sphere2".to_string(),
-        transform: None,
-    });
 
-    let texture3 = Texture::Constant {
-        name: "texture3".to_string(),
-        rgb: [0.7, 0.6, 0.5],
-    };
-    let texture4 = Texture::Constant {
-        name: "texture4".to_string(),
-        rgb: [0.0, 0.0, 0.0],
-    };
-    let material3 = Material::Metal {
-        name: "material3".to_string(),
-        albedo: texture3.get_name().to_string(),
-        fuzz: texture4.get_name().to_string(),
-    };
-    primitives.push(Primitive::UvSphere {
-        name: "sphere3".to_string(),
-        center: center_sphere_3.to_array(),
-        radius: center_spheres_radius,
-        rings: 64,
-        segments: 128,
-        material: material3.get_name().to_string(),
-    });
-    textures.push(texture3);
-    textures.push(texture4);
-    materials.push(material3);
-    instances.push(Instance {
-        name: "sphere3".to_string(),
-        transform: None,
-    });
+    let mut scene = SceneBuilder::new(render, sky);
 
-    cameras.push(Camera::Perspective {
+    scene.add_camera(Camera::Perspective {
         name: "default".to_string(),
         eye: [13.0, -2.0, 3.0],
         look_at: [0.0, 0.0, 0.0],
-        up: [0.0, 1.0, 0.0],
+        up: Some([0.0, 1.0, 0.0]),
+        orientation: None,
         fov_y: 20.0,
         z_near: 0.01,
         z_far: 100.0,
         focal_length: 10.0,
         aperture_size: 0.2,
+        exposure: None,
     });
 
-    let render = Render {
-        camera: cameras[0].get_name().to_string(),
-        samples_per_pixel: 4,
-        sample_batches: 25,
-        max_ray_depth: 50,
-        aspect_ratio: 16.0 / 9.0,
-    };
+    let green = scene.constant_texture([0.2, 0.3, 0.1]);
+    let white = scene.constant_texture([0.9, 0.9, 0.9]);
+    let checker = scene.checker_texture(0.32, &green, &white);
+    let ground_material = scene.lambertian(&checker);
+    let ground = scene
+        .add_sphere([0.0, 1000.0, 0.0], 1000.0)
+        .name("ground_sphere")
+        .tessellation(128, 256)
+        .material(&ground_material)
+        .build()?;
 
-    let sky = Sky::VerticalGradient {
-        factor: 0.5,
-        top: [0.5, 0.7, 1.0],
-        bottom: [1.0, 1.0, 1.0],
-    };
+    let material1 = scene.dielectric(1.5);
+    let sphere1 = scene
+        .add_sphere([0.0, -1.0, 0.0], 1.0)
+        .name("sphere1")
+        .tessellation(64, 128)
+        .material(&material1)
+        .build()?;
 
-    let scene_file = SceneFile {
-        cameras,
-        instances,
-        materials,
-        primitives,
-        textures,
-        sky,
-        render,
-    };
-    scene_file.save_json(file_path)
+    let material2 = scene.lambertian_rgb([0.4, 0.2, 0.1]);
+    let sphere2 = scene
+        .add_sphere([-4.0, -1.0, 0.0], 1.0)
+        .name("sphere2")
+        .tessellation(64, 128)
+        .material(&material2)
+        .touching_ground(&ground)
+        .build()?;
+
+    let material3 = scene.metal_rgb([0.7, 0.6, 0.5], 0.0);
+    let sphere3 = scene
+        .add_sphere([4.0, -1.0, 0.0], 1.0)
+        .name("sphere3")
+        .tessellation(64, 128)
+        .material(&material3)
+        .touching_ground(&ground)
+        .build()?;
+
+    // Scatter small spheres on the ground around the large ones. They are instances of one sphere at
+    // the origin so they share its acceleration structure. That sphere isn't rendered itself so it
+    // can use any material.
+    let radius = 0.2;
+    let small_sphere = scene
+        .add_sphere([0.0, 0.0, 0.0], radius)
+        .name("small_sphere")
+        .material(&ground_material)
+        .build_geometry()?;
+    let exclusions = [sphere1, sphere2, sphere3].map(|sphere| Exclusion {
+        center: Vec3::from(sphere.center),
+        radius: sphere.radius,
+    });
+    let centers = Scatter::jittered_grid(Vec2::splat(-11.0), Vec2::splat(11.0), [22, 22], 0.9)
+        .into_iter()
+        .map(|p| Vec3::from(ground.get_resting_center([p.x, -radius, p.y], radius)));
+
+    for (i, center) in Scatter::exclude(centers, radius, &exclusions)
+        .into_iter()
+        .enumerate()
+    {
+        let choose_mat: f32 = Random::sample();
+
+        let (material, transform) = if choose_mat < 0.8 {
+            // diffuse
+            let material = scene.lambertian_rgb((Random::vec3() * Random::vec3()).to_array());
+            let transform = if do_motion_blur {
+                Some(TransformType::Animated(
+                    Transform {
+                        translate: Some([0.0, Random::sample_in_range(-0.5, 0.0), 0.0]),
+                        rotate: None,
+                        scale: None,
+                    },
+                    Transform {
+                        translate: Some([0.0, 0.0, 0.0]),
+                        rotate: None,
+                        scale: None,
+                    },
+                ))
+            } else {
+                None
+            };
+            (material, transform)
+        } else if choose_mat < 0.95 {
+            // metal
+            let material = scene.metal_rgb(
+                Random::vec3_in_range(0.5, 1.0).to_array(),
+                Random::sample_in_range(0.0, 0.5),
+            );
+            (material, None)
+        } else {
+            // glass
+            (scene.dielectric(1.5), None)
+        };
+
+        // The centre already rests on the ground.
+        scene.add_instance_of(
+            &format!("sphere_{i}"),
+            &small_sphere,
+            &material,
+            Transform {
+                translate: Some(center.to_array()),
+                rotate: None,
+                scale: None,
+            },
+            transform,
+        );
+    }
+
+    scene.build()?.save_json(file_path)
 }
@@ -0,0 +1,9 @@
+use anyhow::Result;
+use scene_file::pack_scene;
+
+/// Packages a scene file and the files it references into a single scene package.
+pub fn pack(scene_path: &str, output_path: &str) -> Result<()> {
+    let file_count = pack_scene(scene_path, output_path)?;
+    println!("Packaged {scene_path} and {file_count} referenced files into {output_path}");
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use std::{f32::consts::TAU, fs, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use raytracer::{CancellationToken, Vk};
+use scene_file::{
+    AssetResolver, Camera, Film, FilmPreset, Material, PixelFilter, Quality, Render, ScalingPolicy,
+    SceneBuilder, Sky, Texture,
+};
+use serde::Deserialize;
+
+use crate::render::render_scene;
+
+/// Radius of the shaderball.
+const BALL_RADIUS: f32 = 1.0;
+
+/// Distance of the turntable camera from the centre of the shaderball.
+const CAMERA_DISTANCE: f32 = 5.5;
+
+/// Height of the turntable camera above the centre of the shaderball.
+const CAMERA_HEIGHT: f32 = 1.5;
+
+/// Size of the squares of the checkered floor. Their size gives a sense of scale and their edges
+/// show how reflections and refractions distort the floor.
+const FLOOR_CHECKER_SCALE: f32 = 0.5;
+
+/// Prefix of the names of the textures, materials and primitives of the preview scene so they
+/// don't clash with the names used by the material definition.
+const PREFIX: &str = "preview";
+
+/// A material to preview along with the textures it references, in the same format as in scene
+/// files.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDefinition {
+    /// The material to preview.
+    pub material: Material,
+
+    /// Textures referenced by the material. Relative image paths are relative to the material
+    /// definition.
+    #[serde(default)]
+    pub textures: Vec<Texture>,
+}
+
+/// Settings for generating a material preview.
+#[derive(Debug)]
+pub struct PreviewSettings<'a> {
+    /// Path to the material definition.
+    pub material_path: &'a str,
+
+    /// Path to the preview image. The generated scene file is saved next to it with a `-scene`
+    /// suffix and a `.json` extension.
+    pub output_path: &'a str,
+
+    /// Width and height of the preview image.
+    pub size: u32,
+
+    /// Number of images rendered while the camera turns around the shaderball. A single image is
+    /// rendered from the front and more images get a `-<n>` suffix.
+    pub frames: u32,
+}
+
+/// Wraps a material definition in a standard shaderball scene and renders it headless, for
+/// generating material library thumbnails. The shaderball rests on a checkered floor under a
+/// studio gradient sky with a large key light, and is viewed by a turntable camera. Scene files
+/// can't reference HDRI environment maps so the sky stands in for one.
+pub fn generate_material_preview(
+    settings: &PreviewSettings,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    if settings.frames == 0 {
+        return Err(anyhow!("At least one frame is required"));
+    }
+    if settings.size == 0 {
+        return Err(anyhow!("Preview size must be greater than 0"));
+    }
+
+    let material_path = settings.material_path;
+    let serialized = fs::read_to_string(material_path)
+        .with_context(|| format!("Unable to read material definition '{material_path}'"))?;
+    let mut definition: MaterialDefinition = serde_json::from_str(&serialized)
+        .with_context(|| format!("Unable to parse material definition '{material_path}'"))?;
+
+    // Image paths are resolved now since the scene file is saved next to the output rather than
+    // the material definition.
+    let material_dir = Path::new(material_path)
+        .parent()
+        .map(fs::canonicalize)
+        .transpose()?
+        .unwrap_or_default();
+    let resolver = AssetResolver::default();
+    for texture in definition.textures.iter_mut() {
+        texture.resolve_path(&resolver, &material_dir);
+    }
+
+    let scene_path = get_scene_path(settings.output_path);
+    let mut scene_file = build_preview_scene(definition, settings.frames)?.build()?;
+    scene_file.save_json(&scene_path)?;
+    println!("Saved preview scene {scene_path}");
+
+    let vk = Vk::new_headless()?;
+    for frame in 0..settings.frames {
+        let output_path = if settings.frames == 1 {
+            settings.output_path.to_string()
+        } else {
+            get_frame_output_path(settings.output_path, frame)
+        };
+        println!(
+            "Rendering {} preview {output_path} at {size} x {size}",
+            scene_file.materials[0].get_name(),
+            size = settings.size
+        );
+
+        scene_file.render.camera = get_camera_name(frame);
+        render_scene(
+            vk.clone(),
+            &scene_path,
+            &scene_file,
+            [settings.size, settings.size],
+            &output_path,
+            cancel,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the preview scene with the material on the shaderball and a camera for each frame of
+/// the turntable, evenly spaced around the shaderball starting from the front.
+fn build_preview_scene(definition: MaterialDefinition, frames: u32) -> Result<SceneBuilder> {
+    let render = Render {
+        camera: get_camera_name(0),
+        samples_per_pixel: 16,
+        sample_batches: 16,
+        max_ray_depth: 16,
+        aspect_ratio: 1.0,
+        resolution: None,
+        scaling: ScalingPolicy::default(),
+        strict_textures: true,
+        layers: None,
+        tags: None,
+        film: Film {
+            preset: FilmPreset::Agx,
+            ..Film::default()
+        },
+        pixel_filter: PixelFilter::default(),
+        supersample: None,
+        shutter: None,
+        caustics: false,
+        transparent_shadows: false,
+        denoise: false,
+        texture_filter: None,
+        strip_unused: false,
+        compact_acceleration: false,
+        budget: None,
+        quality: Quality::default(),
+    };
+
+    let sky = Sky::VerticalGradient {
+        factor: 0.5,
+        top: [0.9, 0.9, 0.9],
+        bottom: [0.2, 0.2, 0.2],
+    };
+
+    let mut scene = SceneBuilder::new(render, sky);
+
+    // The material and its textures are added first so the shaderball's material is the first
+    // one in the scene file.
+    let material = definition.material.get_name().to_string();
+    for texture in definition.textures {
+        scene.add_texture(texture);
+    }
+    scene.add_material(definition.material);
+
+    // Up is -Y like in the other generated scenes.
+    let center = [0.0, -BALL_RADIUS, 0.0];
+    for frame in 0..frames {
+        let angle = TAU * frame as f32 / frames as f32;
+        scene.add_camera(Camera::Perspective {
+            name: get_camera_name(frame),
+            eye: [
+                CAMERA_DISTANCE * angle.sin(),
+                center[1] - CAMERA_HEIGHT,
+                CAMERA_DISTANCE * angle.cos(),
+            ],
+            look_at: center,
+            up: Some([0.0, 1.0, 0.0]),
+            orientation: None,
+            fov_y: 30.0,
+            z_near: 0.01,
+            z_far: 100.0,
+            focal_length: 1.0,
+            aperture_size: 0.0,
+            exposure: None,
+        });
+    }
+
+    let dark = scene.add_texture(Texture::Constant {
+        name: format!("{PREFIX}_floor_dark"),
+        rgb: [0.2, 0.2, 0.2],
+    });
+    let light = scene.add_texture(Texture::Constant {
+        name: format!("{PREFIX}_floor_light"),
+        rgb: [0.5, 0.5, 0.5],
+    });
+    let checker = scene.add_texture(Texture::Checker {
+        name: format!("{PREFIX}_floor"),
+        scale: FLOOR_CHECKER_SCALE,
+        even: dark,
+        odd: light,
+    });
+    let floor_material = scene.add_material(Material::Lambertian {
+        name: format!("{PREFIX}_floor"),
+        albedo: checker,
+        normal_map: None,
+        clearcoat: None,
+    });
+    let floor = scene
+        .add_sphere([0.0, 1000.0, 0.0], 1000.0)
+        .name(&format!("{PREFIX}_floor"))
+        .tessellation(128, 256)
+        .material(&floor_material)
+        .build()?;
+
+    scene
+        .add_sphere(center, BALL_RADIUS)
+        .name(&format!("{PREFIX}_ball"))
+        .tessellation(128, 256)
+        .material(&material)
+        .touching_ground(&floor)
+        .build()?;
+
+    // A large light above and to the side of the front gives soft shadows and a broad highlight.
+    let key_emit = scene.add_texture(Texture::Constant {
+        name: format!("{PREFIX}_key_light"),
+        rgb: [6.0, 6.0, 6.0],
+    });
+    let key_material = scene.add_material(Material::DiffuseLight {
+        name: format!("{PREFIX}_key_light"),
+        emit: key_emit,
+        temperature_kelvin: None,
+    });
+    scene
+        .add_sphere([-4.0, -6.0, 4.0], 1.5)
+        .name(&format!("{PREFIX}_key_light"))
+        .material(&key_material)
+        .build()?;
+
+    Ok(scene)
+}
+
+/// Returns the name of the camera for a frame of the turntable.
+fn get_camera_name(frame: u32) -> String {
+    format!("turntable_{frame}")
+}
+
+/// Returns the path of the scene file saved next to the preview image.
+fn get_scene_path(output_path: &str) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    path.with_file_name(format!("{stem}-scene.json"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the output path for a frame of the turntable by adding `-<n>` to the file name.
+fn get_frame_output_path(output_path: &str, frame: u32) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem}-{frame:03}.{ext}"),
+        None => format!("{stem}-{frame:03}"),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::Result;
+use raytracer::{
+    CancellationToken, FilmResponse, RenderHooks, RenderMetadata, Scene, Vk, save_exr, save_png,
+};
+use scene_file::{Primitive, SceneFile};
+
+use crate::history::{self, HistoryEntry};
+
+/// Renders the scene deterministically and saves the result. Files with an `.exr` extension are
+/// written as multi-layer OpenEXR with AOVs. Otherwise a PNG of the beauty pass is written.
+///
+/// If `separate_layers` is set, each render layer used by the scene is rendered on its own and
+/// saved with a `-layer<n>` suffix for compositing.
+pub fn render(
+    scene_path: &str,
+    width: u32,
+    output_path: &str,
+    separate_layers: bool,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut scene_file = SceneFile::load_json(scene_path)?;
+    let height = get_height(width, &scene_file);
+
+    println!("Rendering {scene_path} at {width} x {height}");
+    let vk = Vk::new_headless()?;
+
+    if !separate_layers {
+        return render_scene(
+            vk,
+            scene_path,
+            &scene_file,
+            [width, height],
+            output_path,
+            cancel,
+        );
+    }
+
+    let layer_mask = scene_file.render.get_layer_mask();
+    let layers: BTreeSet<u32> = scene_file
+        .primitives
+        .iter()
+        .map(Primitive::get_layer)
+        .filter(|layer| layer_mask & (1 << layer) != 0)
+        .collect();
+
+    for layer in layers {
+        let layer_output_path = get_layer_output_path(output_path, layer);
+        println!("Rendering layer {layer} to {layer_output_path}");
+
+        scene_file.render.layers = Some(vec![layer]);
+        render_scene(
+            vk.clone(),
+            scene_path,
+            &scene_file,
+            [width, height],
+            &layer_output_path,
+            cancel,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Renders a scene file that was loaded from `scene_path` at the given resolution and saves the
+/// result the same way as `render`. Completed renders are added to the render history log.
+/// Cancelled renders stop with a `Cancelled` error and nothing is saved.
+pub fn render_scene(
+    vk: Arc<Vk>,
+    scene_path: &str,
+    scene_file: &SceneFile,
+    [width, height]: [u32; 2],
+    output_path: &str,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let start = Instant::now();
+    let mut scene = Scene::new_headless(
+        vk,
+        scene_file,
+        width,
+        height,
+        cancel.clone(),
+        create_progress_hooks(),
+    )?;
+    let output = scene.render_offline()?;
+
+    let metadata = RenderMetadata::new(scene_path, scene_file)?;
+
+    let is_exr = Path::new(output_path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+
+    // EXR files keep the linear rendered image while 8-bit images get the scene's film response
+    // like the presented image.
+    if is_exr {
+        save_exr(output_path, &output, Some(&metadata))?;
+    } else {
+        let film = FilmResponse::new(&scene_file.render.film);
+        let pixels = film.apply_to_pixels(&output.beauty);
+        save_png(output_path, width, height, &pixels, Some(&metadata))?;
+    }
+
+    history::record(&HistoryEntry::new(
+        &metadata,
+        [width, height],
+        start.elapsed(),
+        output_path,
+    ));
+    Ok(())
+}
+
+/// Returns hooks printing how long the scene took to build and the number of sample batches
+/// rendered so far on a single line.
+fn create_progress_hooks() -> RenderHooks {
+    RenderHooks::default()
+        .on_scene_built(|stats| println!("Built scene in {:.2?}", stats.build_time))
+        .on_batch_complete(|progress| {
+            let batch_count = progress.batch_count.unwrap_or(progress.sample_batch);
+            print!("\rRendered batch {} / {batch_count}", progress.sample_batch);
+            if progress.is_complete {
+                println!();
+            }
+            let _ = io::stdout().flush();
+        })
+}
+
+/// Returns the image height for the given width and the scene's aspect ratio.
+pub fn get_height(width: u32, scene_file: &SceneFile) -> u32 {
+    ((width as f32 / scene_file.render.aspect_ratio) as u32).max(1)
+}
+
+/// Returns the output path for a render layer by adding `-layer<n>` to the file name.
+fn get_layer_output_path(output_path: &str, layer: u32) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem}-layer{layer}.{ext}"),
+        None => format!("{stem}-layer{layer}"),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use scene_file::{Facing, Primitive, Quality, SceneFile, SpherePoles, SphereSeam, validate_mesh};
+
+/// Number of rings and segments of the UV spheres that are checked when no scene is given. This
+/// includes the smallest tessellation and ones with an odd number of rings.
+const SPHERE_TESSELLATIONS: [(u32, u32); 5] = [(2, 3), (3, 4), (7, 9), (32, 64), (128, 256)];
+
+/// Checks the meshes of every primitive in a scene file with the scene's normal orientation and
+/// winding applied, or of UV spheres and boxes with all generation options if no scene file is
+/// given, and prints the result for each. Returns true if all meshes are valid.
+pub fn validate_meshes(scene_path: Option<&str>) -> Result<bool> {
+    let (primitives, facing) = match scene_path {
+        Some(path) => {
+            let scene_file = SceneFile::load_json(path)?;
+            (scene_file.primitives, scene_file.facing)
+        }
+        None => (get_generated_primitives(), Facing::default()),
+    };
+
+    // Instances are checked with the mesh of the primitive they reference.
+    let mut failed = 0;
+    for primitive in primitives.iter() {
+        let result = primitive
+            .get_geometry(&primitives)
+            .and_then(|(geometry, _)| {
+                let (vertices, indices) =
+                    geometry.generate_oriented_mesh(&facing, Quality::Final)?;
+                validate_mesh(&vertices, &indices, geometry.is_closed())?;
+                Ok((vertices, indices))
+            });
+        match result {
+            Ok((vertices, indices)) => println!(
+                "OK   {} ({} vertices, {} triangles)",
+                primitive.get_name(),
+                vertices.len(),
+                indices.len() / 3
+            ),
+            Err(e) => {
+                println!("FAIL {}: {e}", primitive.get_name());
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} valid, {failed} invalid", primitives.len() - failed);
+
+    Ok(failed == 0)
+}
+
+/// Returns UV spheres for every combination of tessellation and generation options, and a box.
+fn get_generated_primitives() -> Vec<Primitive> {
+    let mut primitives = vec![];
+
+    for (rings, segments) in SPHERE_TESSELLATIONS {
+        for poles in [SpherePoles::Fan, SpherePoles::CollapsedQuads] {
+            for seam in [SphereSeam::Duplicated, SphereSeam::Shared] {
+                primitives.push(Primitive::UvSphere {
+                    name: format!("uv_sphere_{rings}x{segments}_{poles:?}_{seam:?}"),
+                    center: [1.0, 2.0, 3.0],
+                    radius: 1.5,
+                    rings,
+                    segments,
+                    poles,
+                    seam,
+                    material: String::new(),
+                    facing: None,
+                    acceleration: None,
+                    tags: vec![],
+                    layer: None,
+                    array: None,
+                    transform: None,
+                    velocity: None,
+                });
+            }
+        }
+    }
+
+    primitives.push(Primitive::Box {
+        name: "box".to_string(),
+        corners: [[-1.0, -2.0, -3.0], [1.0, 2.0, 3.0]],
+        material: String::new(),
+        facing: None,
+        acceleration: None,
+        tags: vec![],
+        layer: None,
+        array: None,
+        transform: None,
+        velocity: None,
+    });
+
+    primitives
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use raytracer::{CancellationToken, Estimator, RenderHooks, Scene, Vk, linear_to_srgb, save_png};
+use scene_file::SceneFile;
+
+use crate::{compare::compare_images, render::get_height};
+
+/// Settings for verifying that the estimators converge to the same image.
+#[derive(Debug)]
+pub struct VerifySettings<'a> {
+    /// Path to the scene file.
+    pub scene_path: &'a str,
+
+    /// Image width. The height is derived from the scene's aspect ratio.
+    pub width: u32,
+
+    /// Maximum relative difference allowed between the mean radiance of the two renders in any
+    /// channel before the verification fails.
+    pub threshold: f32,
+
+    /// Factor applied to the per-pixel difference so small differences are visible.
+    pub scale: f32,
+
+    /// Optional path to save the per-pixel difference image.
+    pub output_path: Option<&'a str>,
+}
+
+/// Renders the scene once with material sampling only and once with the default estimator, which
+/// samples light sources with next event estimation, and compares the two. Both estimators are unbiased so the renders only
+/// differ by noise, which averages out over the image. A systematic difference in the mean
+/// radiance means one of them is biased. Returns true if the relative difference of the means is
+/// within the threshold in every channel.
+pub fn verify_estimators(settings: &VerifySettings, cancel: &CancellationToken) -> Result<bool> {
+    let scene_file = SceneFile::load_json(settings.scene_path)?;
+    let width = settings.width;
+    let height = get_height(width, &scene_file);
+
+    let vk = Vk::new_headless()?;
+
+    let mut renders = Vec::with_capacity(2);
+    for estimator in [Estimator::Material, Estimator::default()] {
+        println!(
+            "Rendering {} at {width} x {height} with the {estimator:?} estimator",
+            settings.scene_path
+        );
+        let mut scene = Scene::new_headless(
+            vk.clone(),
+            &scene_file,
+            width,
+            height,
+            cancel.clone(),
+            RenderHooks::default(),
+        )?;
+        scene.set_estimator(estimator)?;
+        renders.push(scene.render_offline()?.beauty);
+    }
+    let (material, light) = (&renders[0], &renders[1]);
+
+    if let Some(output_path) = settings.output_path {
+        let difference: Vec<[f32; 4]> = material
+            .iter()
+            .zip(light.iter())
+            .map(|(a, b)| {
+                let [r, g, b] = [0, 1, 2].map(|i| (a[i] - b[i]).abs() * settings.scale);
+                [r, g, b, 1.0]
+            })
+            .collect();
+        save_png(output_path, width, height, &difference, None)?;
+    }
+
+    // Noise makes individual pixels differ so compare the mean radiance of each channel in linear
+    // space where it is additive.
+    let mean_material = mean_radiance(material);
+    let mean_light = mean_radiance(light);
+    let bias = [0, 1, 2].map(|i| {
+        let mean = mean_light[i].max(f64::EPSILON);
+        ((mean_material[i] - mean_light[i]) / mean) as f32
+    });
+    let passed = bias.iter().all(|b| b.abs() <= settings.threshold);
+
+    let to_srgb = |pixels: &[[f32; 4]]| -> Vec<[f32; 3]> {
+        pixels
+            .iter()
+            .map(|p| [p[0], p[1], p[2]].map(|c| linear_to_srgb(c.max(0.0)).clamp(0.0, 1.0)))
+            .collect()
+    };
+    let result = compare_images(
+        &to_srgb(material),
+        &to_srgb(light),
+        width as usize,
+        height as usize,
+    )?;
+
+    println!(
+        "Relative mean difference: R {:+.6}, G {:+.6}, B {:+.6} (threshold {})",
+        bias[0], bias[1], bias[2], settings.threshold
+    );
+    println!(
+        "RMSE: {:.6}, SSIM: {:.6} => {}",
+        result.rmse,
+        result.ssim,
+        if passed { "PASS" } else { "FAIL" }
+    );
+
+    Ok(passed)
+}
+
+/// Mean linear radiance of each RGB channel.
+fn mean_radiance(pixels: &[[f32; 4]]) -> [f64; 3] {
+    let sum = pixels.iter().fold([0.0_f64; 3], |acc, p| {
+        [0, 1, 2].map(|i| acc[i] + p[i].max(0.0) as f64)
+    });
+    sum.map(|s| s / pixels.len().max(1) as f64)
+}